@@ -46,9 +46,9 @@ export const columns: ColumnDef<Entry>[] = [
 		header: "Score",
 		accessorFn: (row) => {
 			if (row._tag === "Channel") {
-				return (row.data as ChannelEntry).total_channel;
+				return (row.data as ChannelEntry).total_as_broadcaster;
 			} else {
-				return (row.data as ChatterEntry).total;
+				return (row.data as ChatterEntry).total_as_chatter;
 			}
 		}
 	},
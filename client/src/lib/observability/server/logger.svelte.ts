@@ -113,7 +113,7 @@ export const serializeHandlers = {
 			return {
 				channel: `${entry.data.login}:${entry.data.id}`,
 				scores: scores.length == 0 ? [] : scores,
-				totalChannel: entry.data.total_channel,
+				totalChannel: entry.data.total_as_broadcaster,
 			};
 		} else if (entry._tag === "Chatter") {
 			const len = entry.data.channel_scores?.length || 0;
@@ -129,16 +129,16 @@ export const serializeHandlers = {
 			return {
 				chatter: `${entry.data.login}:${entry.data.id}`,
 				scores: scores.length == 0 ? [] : scores,
-				total: entry.data.total,
+				total: entry.data.total_as_chatter,
 			};
 		}
 	},
 
 	score: (score: Score) => {
 		if (score._tag === "Channel") {
-			return `${score.data.channel_login} -> ${score.data.score}`;
+			return `${score.data.channel.login} -> ${score.data.score}`;
 		} else if (score._tag === "Chatter") {
-			return `${score.data.chatter_login} -> ${score.data.score}`;
+			return `${score.data.chatter.login} -> ${score.data.score}`;
 		}
 	},
 
@@ -8,65 +8,55 @@ export type HelixUser = {
 	private: boolean;
 };
 
-export type Chatter = {
+// response shapes from `/api/v1` (see `server/src/api/dto/v1.rs`)
+
+export type Profile = {
 	id: string;
-	name: string;
 	login: string;
+	name: string;
 	color: string;
 	image: string;
-	total: number;
-	private: boolean;
-	created_at: string;
-	updated_at: string;
 };
 
-export type ChannelEntry = {
-	id: string;
-	name: string;
-	login: string;
-	color: string;
-	image: string;
+export type ChannelEntry = Profile & {
 	ranking: number;
-	total_chatter: number;
-	total_channel: number;
+	total_as_broadcaster: number;
+	total_as_chatter: number;
+	chatter_count: number;
 	chatter_scores?: Array<ChatterScore>;
-	total_scores: number;
+	live: boolean;
 };
 
-export type ChatterEntry = {
-	id: string;
-	name: string;
-	login: string;
-	color: string;
-	image: string;
+export type ChatterEntry = Profile & {
 	ranking: number;
-	total: number;
+	total_as_chatter: number;
+	channel_count: number;
 	channel_scores?: Array<ChannelScore>;
-	total_scores: number;
 };
 
 //
 
 export type ChannelScore = {
-	channel_id: string;
-	chatter_id: string;
+	channel: Profile;
 	score: number;
 	ranking: number;
-	channel_login: string;
-	channel_name: string;
-	channel_color: string;
-	channel_image: string;
 };
 
 export type ChatterScore = {
-	channel_id: string;
-	chatter_id: string;
+	chatter: Profile;
 	score: number;
 	ranking: number;
-	chatter_login: string;
-	chatter_name: string;
-	chatter_color: string;
-	chatter_image: string;
+};
+
+export type SearchResult = Profile & {
+	total_as_chatter: number;
+	ranking: number;
+	similarity: number;
+};
+
+export type SearchResults = {
+	results: Array<SearchResult>;
+	total_chatters: number;
 };
 
 export type ScoreWindows = {
@@ -29,8 +29,7 @@ export type UntypedEntry<T = UntypedSubEntry> = {
 };
 
 export type UntypedSubEntry = {
-	channel_id: string;
-	chatter_id: string;
+	id: string;
 	score: number;
 	ranking: number;
 	login: string;
@@ -166,28 +165,18 @@ export function intoUntypedSubEntries(
 		const typed = subEntries as ChatterScore[];
 		return typed.map((entry) => {
 			return {
-				chatter_id: entry.chatter_id,
-				channel_id: entry.channel_id,
+				...entry.chatter,
 				score: entry.score,
 				ranking: entry.ranking,
-				login: entry.chatter_login,
-				name: entry.chatter_name,
-				color: entry.chatter_color,
-				image: entry.chatter_image,
 			};
 		});
 	} else {
 		const typed = subEntries as ChannelScore[];
 		return typed.map((entry) => {
 			return {
-				chatter_id: entry.chatter_id,
-				channel_id: entry.channel_id,
+				...entry.channel,
 				score: entry.score,
 				ranking: entry.ranking,
-				login: entry.channel_login,
-				name: entry.channel_name,
-				color: entry.channel_color,
-				image: entry.channel_image,
 			};
 		});
 	}
@@ -195,7 +184,7 @@ export function intoUntypedSubEntries(
 
 export function intoParentEntry(entry: UntypedSubEntry): UntypedEntry {
 	return {
-		id: entry.channel_id,
+		id: entry.id,
 		login: entry.login,
 		name: entry.name,
 		color: entry.color,
@@ -218,9 +207,9 @@ export function intoUntypedEntry(entry: Entry): UntypedEntry {
 			color: typed.color,
 			image: typed.image,
 			ranking: typed.ranking,
-			total: typed.total_channel,
+			total: typed.total_as_broadcaster,
 			scores: subEntries,
-			totalScores: typed.total_scores,
+			totalScores: typed.chatter_count,
 		};
 	} else {
 		const typed = entry.data as ChatterEntry;
@@ -232,8 +221,8 @@ export function intoUntypedEntry(entry: Entry): UntypedEntry {
 			color: typed.color,
 			image: typed.image,
 			ranking: typed.ranking,
-			total: typed.total,
-			totalScores: typed.total_scores,
+			total: typed.total_as_chatter,
+			totalScores: typed.channel_count,
 			scores: subEntries,
 		};
 	}
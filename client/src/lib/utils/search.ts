@@ -1,13 +1,4 @@
-export type SearchResult = {
-	id: string;
-	name: string;
-	login: string;
-	color: string;
-	image: string;
-	similarity_score: number;
-	total: number;
-    ranking: number;
-};
+export type { SearchResult } from "$lib/types";
 
 export function debounce<
 	T extends (signal: AbortSignal, ...args: any[]) => any,
@@ -1,7 +1,7 @@
 import type { LayoutServerLoad } from "./$types";
 import { logger as serverLogger } from "$lib/observability/server/logger.svelte";
 import { MODE_COOKIE_NAME } from "$lib/utils/mode-cookie.svelte";
-import type { Chatter } from "$lib/types";
+import type { Profile } from "$lib/types";
 import { routeManager } from "$lib/utils/route";
 import { error } from "@sveltejs/kit";
 import {
@@ -55,7 +55,7 @@ async function fetchAnnouncement(
 }
 
 function defaultLayoutData(
-	liveBroadcasters: Chatter[],
+	liveBroadcasters: Profile[],
 	modePreference: string | null,
 	announcement: Announcement
 ) {
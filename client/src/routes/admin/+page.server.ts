@@ -114,7 +114,7 @@ export const actions = {
 			const body = await res.json();
 			logger.info({ response: res, body }, "query ok");
 
-			return { status: 200, data: body.data?.[0] };
+			return { status: 200, data: body.data?.results };
 		} catch (err) {
 			logger.error({ error: err }, "failure during action");
 			return fail(500, {
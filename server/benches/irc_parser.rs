@@ -0,0 +1,170 @@
+//! Measures the parsing hot path: raw IRC lines into `irc::proto::Message`s, messages into
+//! `IncomingMessage`s, and the keyword matcher on the result.
+//!
+//! `cargo bench --bench irc_parser`
+//!
+//! Allocations per message are counted by a wrapping global allocator and printed before the
+//! timings.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::NaiveDateTime;
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use irc::proto::Message;
+
+use pea_fan::db::models::keyword::{Keyword, KeywordId, KeywordKind, KeywordMatcher};
+use pea_fan::irc::commands::IncomingMessage;
+use pea_fan::irc::parse::{parse_incoming, parse_tags};
+
+/// Tag-heavy lines as Twitch sends them: a chat message with badges and emotes, one relayed from
+/// a shared chat session, a resub with its message, and a deleted message.
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+/// Counts the allocations made by `f`.
+fn allocations<T>(f: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    black_box(f());
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+const LINES: [&str; 4] = [
+    "@badge-info=subscriber/25;badges=broadcaster/1,subscriber/3012,partner/1;client-nonce=\
+     6b7b0cba2b6f1b2a6e1f3f0a4b6d8c21;color=#FF69B4;display-name=Example;emotes=62835:0-10,\
+     27-37/25:39-43;first-msg=0;flags=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;mod=0;\
+     returning-chatter=0;room-id=123456789;subscriber=1;tmi-sent-ts=1642696567751;turbo=0;\
+     user-id=123456789;user-type= :example!example@example.tmi.twitch.tv PRIVMSG #example \
+     :bleedPurple piss pisspiss bleedPurple Kappa",
+    "@badge-info=;badges=vip/1;color=;display-name=SomeChatter;emotes=;first-msg=0;flags=;\
+     id=0f1e2d3c-4b5a-4978-8695-a4b3c2d1e0f9;mod=0;returning-chatter=0;room-id=123456789;\
+     source-badge-info=;source-badges=moderator/1;source-id=9a8b7c6d-5e4f-4a3b-9c2d-1e0f9a8b7c6d;\
+     source-room-id=987654321;subscriber=0;tmi-sent-ts=1642696567752;turbo=0;user-id=555555555;\
+     user-type= :somechatter!somechatter@somechatter.tmi.twitch.tv PRIVMSG #example \
+     :hello chat, nothing to count in this one",
+    "@badge-info=subscriber/12;badges=subscriber/12,premium/1;color=#008000;display-name=Resubber;\
+     emotes=;flags=;id=db25007f-7a18-43eb-9379-80131e44d633;login=resubber;mod=0;msg-id=resub;\
+     msg-param-cumulative-months=12;msg-param-months=0;msg-param-multimonth-duration=0;\
+     msg-param-multimonth-tenure=0;msg-param-should-share-streak=0;msg-param-sub-plan-name=\
+     Channel\\sSubscription;msg-param-sub-plan=1000;msg-param-was-gifted=false;room-id=123456789;\
+     subscriber=1;system-msg=Resubber\\ssubscribed\\sat\\sTier\\s1.;tmi-sent-ts=1642696567753;\
+     user-id=222222222;user-type= :tmi.twitch.tv USERNOTICE #example :a year of piss",
+    "@login=spammer;room-id=;target-msg-id=abc-123-def;tmi-sent-ts=1642696567754 \
+     :tmi.twitch.tv CLEARMSG #example :buy followers",
+];
+
+fn keywords() -> Vec<Keyword> {
+    [
+        (1, "piss", KeywordKind::Text, None),
+        (2, "bleedpurple", KeywordKind::Emote, Some("62835")),
+    ]
+    .into_iter()
+    .map(|(id, word, kind, emote_id)| Keyword {
+        id: KeywordId(id),
+        word: String::from(word),
+        kind,
+        emote_id: emote_id.map(String::from),
+        created_at: NaiveDateTime::default(),
+    })
+    .collect()
+}
+
+fn messages() -> Vec<Message> {
+    LINES.iter().map(|line| line.parse().unwrap()).collect()
+}
+
+fn report_allocations(_: &mut Criterion) {
+    let messages = messages();
+    let matcher = KeywordMatcher::new(&keywords());
+    let parsed: Vec<_> = messages.iter().map(parse_incoming).collect();
+
+    for (line, (message, parsed)) in messages.iter().zip(&parsed).enumerate() {
+        let parse = allocations(|| parse_incoming(message));
+        let matching = match parsed {
+            Some(IncomingMessage::Privmsg(message)) => {
+                allocations(|| matcher.occurrences(&message.text, &message.emotes))
+            }
+            _ => 0,
+        };
+
+        println!("line {line}: {parse} allocations parsing, {matching} matching");
+    }
+}
+
+fn parsing(c: &mut Criterion) {
+    let messages = messages();
+    let mut group = c.benchmark_group("irc_parser");
+    group.throughput(Throughput::Elements(LINES.len() as u64));
+
+    group.bench_function("line_to_message", |b| {
+        b.iter(|| {
+            for line in LINES {
+                black_box(black_box(line).parse::<Message>().unwrap());
+            }
+        })
+    });
+
+    // only the chat messages; the others don't have the ids it requires
+    group.throughput(Throughput::Elements(2));
+    group.bench_function("parse_tags", |b| {
+        b.iter(|| {
+            for message in &messages[..2] {
+                black_box(parse_tags(black_box(message), "#example").unwrap());
+            }
+        })
+    });
+
+    group.throughput(Throughput::Elements(LINES.len() as u64));
+
+    group.bench_function("parse_incoming", |b| {
+        b.iter(|| {
+            for message in &messages {
+                black_box(parse_incoming(black_box(message)));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn hot_path(c: &mut Criterion) {
+    let matcher = KeywordMatcher::new(&keywords());
+    let mut group = c.benchmark_group("irc_hot_path");
+    group.throughput(Throughput::Elements(LINES.len() as u64));
+
+    group.bench_function("line_to_occurrences", |b| {
+        b.iter(|| {
+            for line in LINES {
+                let message: Message = black_box(line).parse().unwrap();
+                if let Some(IncomingMessage::Privmsg(message)) = parse_incoming(&message) {
+                    black_box(matcher.occurrences(&message.text, &message.emotes));
+                }
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, report_allocations, parsing, hot_path);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+//! Compares the keyword match modes on typical chat messages.
+//!
+//! `cargo bench --bench keyword_matcher`
+
+use chrono::NaiveDateTime;
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+
+use pea_fan::db::models::keyword::{Keyword, KeywordId, KeywordKind, KeywordMatcher};
+use pea_fan::irc::matcher::MatchMode;
+
+const MESSAGES: [&str; 4] = [
+    "hello chat",
+    "piss",
+    "PISS piss pisspiss, pissing and peeing all over the place LUL",
+    "this is a much longer message that goes on for a while without ever mentioning any of the \
+     tracked keywords, which is what most of chat looks like most of the time",
+];
+
+fn keywords(count: i32) -> Vec<Keyword> {
+    (0..count)
+        .map(|n| Keyword {
+            id: KeywordId(n),
+            word: match n {
+                0 => String::from("piss"),
+                1 => String::from("pee"),
+                n => format!("keyword{n}"),
+            },
+            kind: KeywordKind::Text,
+            emote_id: None,
+            created_at: NaiveDateTime::default(),
+        })
+        .collect()
+}
+
+fn match_modes(c: &mut Criterion) {
+    for count in [1, 10] {
+        let keywords = keywords(count);
+        let mut group = c.benchmark_group(format!("keyword_matcher/{count}_keywords"));
+
+        for mode in [MatchMode::Substring, MatchMode::Word, MatchMode::Regex] {
+            let matcher = KeywordMatcher::with_matcher(&keywords, mode.build(&keywords));
+            group.bench_with_input(
+                BenchmarkId::from_parameter(format!("{mode:?}")),
+                &matcher,
+                |b, matcher| {
+                    b.iter(|| {
+                        for message in MESSAGES {
+                            black_box(matcher.occurrences(black_box(message), &[]));
+                        }
+                    })
+                },
+            );
+        }
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, match_modes);
+criterion_main!(benches);
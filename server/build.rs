@@ -0,0 +1,20 @@
+fn main() {
+    println!("cargo::rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the gRPC service and messages from `proto/`, with a vendored `protoc` so that one
+/// doesn't need to be installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+    // SAFETY: build scripts are single-threaded
+    unsafe { std::env::set_var("PROTOC", protoc) };
+
+    tonic_prost_build::configure()
+        .compile_protos(&["proto/pea_fan/v1/pea_fan.proto"], &["proto"])
+        .expect("failed to compile protos");
+}
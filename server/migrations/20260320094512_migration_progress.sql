@@ -0,0 +1,16 @@
+CREATE TABLE migration_phase (
+    phase varchar(16) PRIMARY KEY NOT NULL,
+    processed INT8 DEFAULT 0 NOT NULL,
+    total INT8 DEFAULT 0 NOT NULL,
+    started_at timestamp DEFAULT now() NOT NULL,
+    completed_at timestamp
+);
+
+CREATE TABLE migration_progress (
+    phase varchar(16) NOT NULL,
+    key varchar NOT NULL,
+    migrated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT migration_progress_phase_key_pk PRIMARY KEY(phase, key)
+);
+
+CREATE INDEX idx_migration_progress_phase ON migration_progress USING btree (phase);
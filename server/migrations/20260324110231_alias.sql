@@ -0,0 +1,31 @@
+CREATE TABLE alias (
+    chatter_id varchar(16) NOT NULL,
+    login varchar(25) NOT NULL,
+    detected_at timestamp DEFAULT now() NOT NULL,
+    merged_at timestamp,
+    merged_score INT8 DEFAULT 0 NOT NULL,
+    CONSTRAINT alias_chatter_id_login_pk PRIMARY KEY(chatter_id, login),
+    CONSTRAINT alias_chatter_id_fk FOREIGN KEY(chatter_id) REFERENCES chatter(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_alias_login ON alias USING btree (login);
+CREATE INDEX idx_alias_pending ON alias USING btree (chatter_id) WHERE merged_at IS NULL;
+
+-- record a chatter's previous login whenever a refresh from helix changes it
+CREATE OR REPLACE FUNCTION record_chatter_alias()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO alias (chatter_id, login, detected_at)
+    VALUES (OLD.id, OLD.login, NOW())
+    ON CONFLICT (chatter_id, login)
+    DO NOTHING;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER chatter_login_change_trigger
+AFTER UPDATE OF login ON chatter
+FOR EACH ROW
+WHEN (OLD.login IS DISTINCT FROM NEW.login)
+EXECUTE FUNCTION record_chatter_alias();
@@ -0,0 +1,71 @@
+ALTER TABLE channel
+    ADD COLUMN timezone text DEFAULT 'UTC' NOT NULL;
+
+-- score events per channel, bucketed by hour of the week in the channel's local time
+CREATE TABLE channel_heatmap (
+    channel_id varchar(16) NOT NULL,
+    day_of_week INT2 NOT NULL,
+    hour INT2 NOT NULL,
+    total INT8 DEFAULT 0 NOT NULL,
+    CONSTRAINT channel_heatmap_pk PRIMARY KEY(channel_id, day_of_week, hour),
+    CONSTRAINT channel_heatmap_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT channel_heatmap_day_check CHECK (day_of_week BETWEEN 0 AND 6),
+    CONSTRAINT channel_heatmap_hour_check CHECK (hour BETWEEN 0 AND 23)
+);
+
+-- `earned_at` is stored as UTC
+CREATE OR REPLACE FUNCTION to_channel_local(ts timestamp, tz text)
+RETURNS timestamp AS $$
+    SELECT (ts AT TIME ZONE 'UTC') AT TIME ZONE tz;
+$$ LANGUAGE sql STABLE;
+
+CREATE OR REPLACE FUNCTION increment_channel_heatmap()
+RETURNS TRIGGER AS $$
+DECLARE
+    local_ts timestamp;
+BEGIN
+    SELECT to_channel_local(NEW.earned_at, timezone) INTO local_ts
+    FROM channel
+    WHERE id = NEW.channel_id;
+
+    INSERT INTO channel_heatmap (channel_id, day_of_week, hour, total)
+    VALUES (
+        NEW.channel_id,
+        EXTRACT(DOW FROM local_ts)::INT2,
+        EXTRACT(HOUR FROM local_ts)::INT2,
+        1
+    )
+    ON CONFLICT (channel_id, day_of_week, hour)
+    DO UPDATE SET
+        total = channel_heatmap.total + 1;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER score_event_heatmap_trigger
+AFTER INSERT ON score_event
+FOR EACH ROW
+EXECUTE FUNCTION increment_channel_heatmap();
+
+-- rebuild a channel's heatmap from its score events (e.g. after its timezone changes)
+CREATE OR REPLACE FUNCTION recalc_channel_heatmap(channel_id_param varchar(16))
+RETURNS void AS $$
+BEGIN
+    DELETE FROM channel_heatmap
+    WHERE channel_id = channel_id_param;
+
+    INSERT INTO channel_heatmap (channel_id, day_of_week, hour, total)
+    SELECT
+        e.channel_id,
+        EXTRACT(DOW FROM to_channel_local(e.earned_at, c.timezone))::INT2 AS day_of_week,
+        EXTRACT(HOUR FROM to_channel_local(e.earned_at, c.timezone))::INT2 AS hour,
+        COUNT(*)
+    FROM score_event e
+    JOIN channel c ON c.id = e.channel_id
+    WHERE e.channel_id = channel_id_param
+    GROUP BY e.channel_id, day_of_week, hour;
+END;
+$$ LANGUAGE plpgsql;
+
+SELECT recalc_channel_heatmap(id) FROM channel;
@@ -0,0 +1,114 @@
+CREATE TABLE keyword (
+    id SERIAL PRIMARY KEY,
+    word varchar(32) NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT keyword_word_unique UNIQUE (word),
+    CONSTRAINT keyword_word_lowercase CHECK (word = LOWER(word))
+);
+
+-- the keyword counted before keyword support was added; all existing scores belong to it
+INSERT INTO keyword (id, word) VALUES (1, 'piss');
+SELECT setval('keyword_id_seq', (SELECT MAX(id) FROM keyword));
+
+ALTER TABLE score_event
+    ADD COLUMN keyword_id INT4 DEFAULT 1 NOT NULL,
+    ADD CONSTRAINT score_event_keyword_fk
+        FOREIGN KEY(keyword_id) REFERENCES keyword(id);
+
+CREATE INDEX idx_score_event_keyword ON score_event(keyword_id);
+
+ALTER TABLE score
+    ADD COLUMN keyword_id INT4 DEFAULT 1 NOT NULL,
+    ADD CONSTRAINT score_keyword_id_keyword_id_fk
+        FOREIGN KEY(keyword_id) REFERENCES keyword(id),
+    DROP CONSTRAINT score_chatter_id_channel_id_pk,
+    ADD CONSTRAINT score_chatter_id_channel_id_keyword_id_pk
+        PRIMARY KEY(chatter_id, channel_id, keyword_id);
+
+CREATE INDEX idx_keyword_all_chatter_ranks ON score USING btree (keyword_id, score DESC, created_at ASC);
+
+-- per-channel rankings remain across all keywords
+CREATE OR REPLACE VIEW ranked_scores_view_per_channel AS
+SELECT
+    s.channel_id,
+    s.chatter_id,
+    SUM(s.score)::INT8 AS score,
+    MIN(s.created_at) AS created_at,
+    MAX(s.updated_at) AS updated_at,
+    ROW_NUMBER() OVER (
+        PARTITION BY s.channel_id
+        ORDER BY SUM(s.score) DESC, MIN(s.created_at) ASC
+    ) AS ranking
+FROM score s
+GROUP BY s.channel_id, s.chatter_id;
+
+CREATE VIEW keyword_leaderboard AS
+SELECT
+    s.keyword_id,
+    c.id,
+    c.login,
+    c.name,
+    c.color,
+    c.image,
+    SUM(s.score)::INT8 AS total,
+    ROW_NUMBER() OVER (
+        PARTITION BY s.keyword_id
+        ORDER BY SUM(s.score) DESC, MIN(s.created_at) ASC
+    ) AS ranking
+FROM score s
+JOIN chatter c ON c.id = s.chatter_id
+GROUP BY s.keyword_id, c.id, c.login, c.name, c.color, c.image;
+
+-- chatter/channel totals count events for every keyword, scores are kept per keyword
+CREATE OR REPLACE FUNCTION increment_score_totals()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE chatter
+    SET total = total + 1,
+        updated_at = NOW()
+    WHERE id = NEW.chatter_id;
+
+    UPDATE channel
+    SET channel_total = channel_total + 1,
+        updated_at = NOW()
+    WHERE id = NEW.channel_id;
+
+    INSERT INTO score (chatter_id, channel_id, keyword_id, score, updated_at)
+    VALUES (NEW.chatter_id, NEW.channel_id, NEW.keyword_id, 1, NOW())
+    ON CONFLICT (chatter_id, channel_id, keyword_id)
+    DO UPDATE SET
+        score = score.score + 1,
+        updated_at = NOW();
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP FUNCTION recalc_score(varchar(16), varchar(16));
+
+-- recalculate a specific score for a single keyword
+CREATE OR REPLACE FUNCTION recalc_score(
+    chatter_id_param varchar(16),
+    channel_id_param varchar(16),
+    keyword_id_param INT4 DEFAULT 1
+)
+RETURNS INT8 AS $$
+DECLARE
+    new_score INT8;
+BEGIN
+    SELECT COALESCE(COUNT(*), 0) INTO new_score
+    FROM score_event
+    WHERE chatter_id = chatter_id_param
+    AND channel_id = channel_id_param
+    AND keyword_id = keyword_id_param;
+
+    INSERT INTO score (chatter_id, channel_id, keyword_id, score, updated_at)
+    VALUES (chatter_id_param, channel_id_param, keyword_id_param, new_score, NOW())
+    ON CONFLICT (chatter_id, channel_id, keyword_id)
+    DO UPDATE SET
+        score = new_score,
+        updated_at = NOW();
+
+    RETURN new_score;
+END;
+$$ LANGUAGE plpgsql;
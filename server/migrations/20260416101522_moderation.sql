@@ -0,0 +1,49 @@
+-- the IRC message each event was counted from, so it can be found again if the message is deleted
+ALTER TABLE score_event
+    ADD COLUMN msg_id varchar(36),
+    ADD COLUMN flagged_at timestamp;
+
+CREATE INDEX idx_score_event_msg_id ON score_event(msg_id);
+CREATE INDEX idx_score_event_flagged ON score_event(flagged_at) WHERE flagged_at IS NOT NULL;
+
+-- reverses `increment_score_totals` and `increment_channel_heatmap` when an event is rolled back
+CREATE OR REPLACE FUNCTION decrement_score_totals()
+RETURNS TRIGGER AS $$
+DECLARE
+    local_ts timestamp;
+BEGIN
+    UPDATE chatter
+    SET total = GREATEST(total - 1, 0),
+        updated_at = NOW()
+    WHERE id = OLD.chatter_id;
+
+    UPDATE channel
+    SET channel_total = GREATEST(channel_total - 1, 0),
+        updated_at = NOW()
+    WHERE id = OLD.channel_id;
+
+    UPDATE score
+    SET score = GREATEST(score - 1, 0),
+        updated_at = NOW()
+    WHERE chatter_id = OLD.chatter_id
+    AND channel_id = OLD.channel_id
+    AND keyword_id = OLD.keyword_id;
+
+    SELECT to_channel_local(OLD.earned_at, timezone) INTO local_ts
+    FROM channel
+    WHERE id = OLD.channel_id;
+
+    UPDATE channel_heatmap
+    SET total = GREATEST(total - 1, 0)
+    WHERE channel_id = OLD.channel_id
+    AND day_of_week = EXTRACT(DOW FROM local_ts)::INT2
+    AND hour = EXTRACT(HOUR FROM local_ts)::INT2;
+
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER score_event_decrement_trigger
+AFTER DELETE ON score_event
+FOR EACH ROW
+EXECUTE FUNCTION decrement_score_totals();
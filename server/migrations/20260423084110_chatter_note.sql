@@ -0,0 +1,30 @@
+-- private moderator notes on a chatter, scoped to a channel
+CREATE TABLE chatter_note (
+    channel_id varchar(16) NOT NULL,
+    chatter_id varchar(16) NOT NULL,
+    note text DEFAULT '' NOT NULL,
+    flags text[] DEFAULT '{}' NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    updated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT chatter_note_pk PRIMARY KEY(channel_id, chatter_id),
+    CONSTRAINT chatter_note_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT chatter_note_chatter_fk FOREIGN KEY(chatter_id) REFERENCES chatter(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_chatter_note_chatter ON chatter_note USING btree (chatter_id);
+
+-- every edit to a note; rows are kept after the note itself is deleted
+CREATE TABLE chatter_note_audit (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    chatter_id varchar(16) NOT NULL,
+    action varchar(8) NOT NULL,
+    note text,
+    flags text[],
+    author varchar(64) NOT NULL,
+    session_id INT4,
+    changed_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT chatter_note_audit_action_check CHECK (action IN ('create', 'update', 'delete'))
+);
+
+CREATE INDEX idx_chatter_note_audit_note ON chatter_note_audit USING btree (channel_id, chatter_id, changed_at DESC);
@@ -0,0 +1,14 @@
+-- live state of each tracked channel, refreshed periodically from the helix streams endpoint
+CREATE TABLE stream_status (
+    channel_id varchar(16) NOT NULL,
+    is_live boolean DEFAULT false NOT NULL,
+    viewer_count INT8 DEFAULT 0 NOT NULL,
+    game text DEFAULT '' NOT NULL,
+    title text DEFAULT '' NOT NULL,
+    started_at timestamp,
+    updated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT stream_status_pk PRIMARY KEY(channel_id),
+    CONSTRAINT stream_status_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_stream_status_live ON stream_status USING btree (channel_id) WHERE is_live;
@@ -0,0 +1,14 @@
+-- per-subscription webhook secrets; keyed by channel and type rather than the eventsub subscription
+-- id, as twitch sends the (signed) verification challenge before we're guaranteed to know the id
+CREATE TABLE eventsub_subscription (
+    channel_id varchar(16) NOT NULL,
+    subscription_type text NOT NULL,
+    subscription_id text,
+    secret text NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    updated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT eventsub_subscription_pk PRIMARY KEY(channel_id, subscription_type),
+    CONSTRAINT eventsub_subscription_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_eventsub_subscription_id ON eventsub_subscription USING btree (subscription_id);
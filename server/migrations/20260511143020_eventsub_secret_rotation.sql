@@ -0,0 +1,4 @@
+-- the secret replaced by the last rotation; accepted until messages signed with it would be stale
+ALTER TABLE eventsub_subscription
+    ADD COLUMN previous_secret text,
+    ADD COLUMN rotated_at timestamp;
@@ -0,0 +1,5 @@
+-- `enabled` until twitch revokes the subscription, then the revocation reason (e.g.
+-- `authorization_revoked`); reset when the subscription is re-created
+ALTER TABLE eventsub_subscription
+    ADD COLUMN status text DEFAULT 'enabled' NOT NULL,
+    ADD COLUMN revoked_at timestamp;
@@ -0,0 +1,3 @@
+-- display names are searched case-insensitively alongside logins
+CREATE INDEX IF NOT EXISTS chatter_name_trgm_idx
+ON chatter USING gist (lower(name) gist_trgm_ops);
@@ -0,0 +1,17 @@
+-- keyword matches dropped by the anti-spam score limits; kept apart from `score_event` so they
+-- never reach the totals, but can still be reviewed
+CREATE TABLE suppressed_score_event (
+    id SERIAL PRIMARY KEY,
+    chatter_id varchar(16) NOT NULL,
+    channel_id varchar(16) NOT NULL,
+    keyword_id INT4 NOT NULL,
+    msg_id varchar(36),
+    reason varchar(16) NOT NULL,
+    suppressed_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT suppressed_score_event_reason_check CHECK (reason IN ('rate_limit', 'per_message')),
+    CONSTRAINT suppressed_score_event_chatter_fk FOREIGN KEY(chatter_id) REFERENCES chatter(id) ON DELETE CASCADE,
+    CONSTRAINT suppressed_score_event_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT suppressed_score_event_keyword_fk FOREIGN KEY(keyword_id) REFERENCES keyword(id)
+);
+
+CREATE INDEX idx_suppressed_score_event_channel ON suppressed_score_event USING btree (channel_id, chatter_id, suppressed_at DESC);
@@ -0,0 +1,7 @@
+-- whether a message earns one increment per keyword (`message`) or one per occurrence of each
+-- keyword (`occurrence`), up to `max_occurrences` per keyword per message
+ALTER TABLE channel
+    ADD COLUMN count_mode varchar(16) DEFAULT 'message' NOT NULL,
+    ADD COLUMN max_occurrences INT2 DEFAULT 5 NOT NULL,
+    ADD CONSTRAINT channel_count_mode_check CHECK (count_mode IN ('message', 'occurrence')),
+    ADD CONSTRAINT channel_max_occurrences_check CHECK (max_occurrences BETWEEN 1 AND 50);
@@ -0,0 +1,9 @@
+-- emote keywords count twitch emotes (by id, or by name when no id is set) rather than the
+-- literal text, so the same word can be tracked once as text and once as an emote
+ALTER TABLE keyword
+    ADD COLUMN kind varchar(8) DEFAULT 'text' NOT NULL,
+    ADD COLUMN emote_id varchar(64),
+    ADD CONSTRAINT keyword_kind_check CHECK (kind IN ('text', 'emote')),
+    ADD CONSTRAINT keyword_emote_id_check CHECK (kind = 'emote' OR emote_id IS NULL),
+    DROP CONSTRAINT keyword_word_unique,
+    ADD CONSTRAINT keyword_word_kind_unique UNIQUE (word, kind);
@@ -0,0 +1,22 @@
+-- per-channel scores earned within each day/week/month, captured periodically from score_event
+-- so that period leaderboards don't have to aggregate raw events on every request. rows for
+-- past periods are kept as a record of each period's final standings
+CREATE TABLE leaderboard_snapshot (
+    period varchar(8) NOT NULL,
+    period_start timestamp NOT NULL,
+    channel_id varchar(16) NOT NULL,
+    chatter_id varchar(16) NOT NULL,
+    score INT8 NOT NULL,
+    captured_at timestamp DEFAULT now() NOT NULL,
+
+    CONSTRAINT leaderboard_snapshot_pk
+        PRIMARY KEY(period, period_start, channel_id, chatter_id),
+    CONSTRAINT leaderboard_snapshot_period_check CHECK (period IN ('day', 'week', 'month')),
+    CONSTRAINT leaderboard_snapshot_chatter_fk
+        FOREIGN KEY(chatter_id) REFERENCES chatter(id),
+    CONSTRAINT leaderboard_snapshot_channel_fk
+        FOREIGN KEY(channel_id) REFERENCES channel(id)
+);
+
+CREATE INDEX idx_leaderboard_snapshot_chatter
+    ON leaderboard_snapshot(period, period_start, chatter_id);
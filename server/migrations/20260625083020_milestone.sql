@@ -0,0 +1,17 @@
+-- thresholds that are announced in chat when crossed. a NULL channel applies the milestone to
+-- every channel; repeating milestones are reached at every multiple of their threshold
+CREATE TABLE milestone (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16),
+    kind varchar(16) NOT NULL,
+    threshold INT8 NOT NULL,
+    repeating boolean DEFAULT false NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+
+    CONSTRAINT milestone_channel_fk
+        FOREIGN KEY(channel_id) REFERENCES channel(id),
+    CONSTRAINT milestone_kind_check CHECK (kind IN ('channel_total', 'chatter_score')),
+    CONSTRAINT milestone_threshold_check CHECK (threshold > 0)
+);
+
+CREATE INDEX idx_milestone_channel ON milestone(channel_id);
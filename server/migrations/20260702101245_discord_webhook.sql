@@ -0,0 +1,18 @@
+-- discord webhooks posted to when notable events happen in a channel. each channel has at most
+-- one webhook per event type; a NULL template uses the built-in default for that event type
+CREATE TABLE discord_webhook (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    event varchar(24) NOT NULL,
+    url TEXT NOT NULL,
+    template TEXT,
+    enabled boolean DEFAULT true NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    updated_at timestamp DEFAULT now() NOT NULL,
+
+    CONSTRAINT discord_webhook_channel_fk
+        FOREIGN KEY(channel_id) REFERENCES channel(id),
+    CONSTRAINT discord_webhook_event_check
+        CHECK (event IN ('stream_online', 'milestone_reached', 'leaderboard_snapshot')),
+    CONSTRAINT discord_webhook_channel_event_key UNIQUE (channel_id, event)
+);
@@ -0,0 +1,13 @@
+-- administrative actions taken through the admin api, with the session that took them
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    session_id INT4,
+    action varchar(32) NOT NULL,
+    target varchar(64),
+    before jsonb,
+    after jsonb,
+    created_at timestamp DEFAULT now() NOT NULL
+);
+
+CREATE INDEX idx_audit_log_created_at ON audit_log USING btree (created_at DESC);
+CREATE INDEX idx_audit_log_action ON audit_log USING btree (action, created_at DESC);
@@ -0,0 +1,13 @@
+-- issued api tokens; the tokens themselves are signed rather than stored, so rows only track
+-- what was issued and whether it has been revoked
+CREATE TABLE api_token (
+    id varchar(32) PRIMARY KEY,
+    label varchar(64) NOT NULL,
+    scopes text[] NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    expires_at timestamp,
+    revoked_at timestamp
+);
+
+-- actions taken with an api token rather than a session
+ALTER TABLE audit_log ADD COLUMN token_id varchar(32);
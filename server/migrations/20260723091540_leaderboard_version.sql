@@ -0,0 +1,20 @@
+-- bumped whenever a channel's scores change, so leaderboard responses can be revalidated cheaply
+ALTER TABLE channel ADD COLUMN version INT8 DEFAULT 0 NOT NULL;
+
+CREATE OR REPLACE FUNCTION bump_channel_version()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.version = OLD.version + 1;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- every write to a channel's scores updates its total and/or `updated_at`
+CREATE TRIGGER bump_channel_version
+BEFORE UPDATE ON channel
+FOR EACH ROW
+WHEN (
+    OLD.channel_total IS DISTINCT FROM NEW.channel_total
+    OR OLD.updated_at IS DISTINCT FROM NEW.updated_at
+)
+EXECUTE FUNCTION bump_channel_version();
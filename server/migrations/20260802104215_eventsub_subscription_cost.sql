@@ -0,0 +1,3 @@
+-- the `cost` twitch reported when the subscription was created; counts against the client's
+-- `max_total_cost` until the subscription is deleted or revoked
+ALTER TABLE eventsub_subscription ADD COLUMN cost INT4 DEFAULT 0 NOT NULL;
@@ -0,0 +1,2 @@
+-- paused channels stay joined and subscribed, but their messages aren't counted
+ALTER TABLE channel ADD COLUMN paused boolean DEFAULT false NOT NULL;
@@ -0,0 +1,2 @@
+-- actions taken from chat (by a broadcaster or moderator) rather than through the admin api
+ALTER TABLE audit_log ADD COLUMN chatter_id varchar(16);
@@ -0,0 +1,117 @@
+-- soft-deleted chatters keep their rows until they're purged, but are left out of leaderboards,
+-- ranks, search and exports in the meantime
+ALTER TABLE chatter ADD COLUMN deleted_at timestamp;
+
+CREATE INDEX idx_chatter_deleted ON chatter USING btree (id) WHERE deleted_at IS NOT NULL;
+
+-- data deletion requests; a request is pending until it's confirmed with its token, then the
+-- chatter is soft-deleted and purged once `purge_after` has passed. rows go with the chatter
+CREATE TABLE chatter_deletion (
+    chatter_id varchar(16) PRIMARY KEY NOT NULL,
+    confirmation_hash varchar(64) NOT NULL,
+    confirm_by timestamp NOT NULL,
+    requested_at timestamp DEFAULT now() NOT NULL,
+    confirmed_at timestamp,
+    purge_after timestamp,
+    CONSTRAINT chatter_deletion_chatter_fk FOREIGN KEY(chatter_id) REFERENCES chatter(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_chatter_deletion_due ON chatter_deletion USING btree (purge_after) WHERE purge_after IS NOT NULL;
+
+CREATE OR REPLACE VIEW ranked_scores_view_chatters AS
+SELECT
+    id,
+    login,
+    name,
+    color,
+    image,
+    total,
+    private,
+    created_at,
+    updated_at,
+    ROW_NUMBER() OVER (
+        ORDER BY total DESC, updated_at ASC
+    ) AS ranking
+FROM chatter
+WHERE deleted_at IS NULL;
+
+CREATE OR REPLACE VIEW chatter_leaderboard AS
+SELECT
+    c.id,
+    c.login,
+    c.name,
+    c.color,
+    c.image,
+    c.total,
+    c.private,
+    c.created_at,
+    c.updated_at,
+    ROW_NUMBER() OVER (
+        ORDER BY
+            c.total DESC,
+            c.created_at ASC
+    ) AS ranking
+FROM chatter c
+WHERE c.deleted_at IS NULL;
+
+CREATE OR REPLACE VIEW ranked_scores_view_per_channel AS
+SELECT
+    s.channel_id,
+    s.chatter_id,
+    SUM(s.score)::INT8 AS score,
+    MIN(s.created_at) AS created_at,
+    MAX(s.updated_at) AS updated_at,
+    ROW_NUMBER() OVER (
+        PARTITION BY s.channel_id
+        ORDER BY SUM(s.score) DESC, MIN(s.created_at) ASC
+    ) AS ranking
+FROM score s
+WHERE NOT EXISTS (
+    SELECT 1 FROM chatter c
+    WHERE c.id = s.chatter_id AND c.deleted_at IS NOT NULL
+)
+GROUP BY s.channel_id, s.chatter_id;
+
+CREATE OR REPLACE VIEW keyword_leaderboard AS
+SELECT
+    s.keyword_id,
+    c.id,
+    c.login,
+    c.name,
+    c.color,
+    c.image,
+    SUM(s.score)::INT8 AS total,
+    ROW_NUMBER() OVER (
+        PARTITION BY s.keyword_id
+        ORDER BY SUM(s.score) DESC, MIN(s.created_at) ASC
+    ) AS ranking
+FROM score s
+JOIN chatter c ON c.id = s.chatter_id
+WHERE c.deleted_at IS NULL
+GROUP BY s.keyword_id, c.id, c.login, c.name, c.color, c.image;
+
+CREATE OR REPLACE FUNCTION get_chatter_rank(chatter_id_param varchar(16))
+RETURNS INT8 AS $$
+DECLARE
+    chatter_total INT8;
+    chatter_created timestamp;
+    rank_result INT8;
+BEGIN
+    SELECT total, created_at INTO chatter_total, chatter_created
+    FROM chatter
+    WHERE id = chatter_id_param
+    AND deleted_at IS NULL;
+
+    IF NOT FOUND THEN
+        RETURN NULL;
+    END IF;
+
+    SELECT COUNT(*) + 1 INTO rank_result
+    FROM chatter
+    WHERE deleted_at IS NULL
+    AND (total > chatter_total
+        OR (total = chatter_total AND created_at < chatter_created));
+
+    RETURN rank_result;
+END;
+$$ LANGUAGE plpgsql;
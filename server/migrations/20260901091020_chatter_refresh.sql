@@ -0,0 +1,5 @@
+-- when the row was last checked against Helix by the refresh job, whether or not Helix still had
+-- the user; `updated_at` only moves when the data does
+ALTER TABLE chatter ADD COLUMN refreshed_at timestamp;
+
+CREATE INDEX idx_chatter_refresh ON chatter USING btree ((GREATEST(refreshed_at, updated_at)));
@@ -0,0 +1,3 @@
+-- random path segment appended to `CALLBACK_URL` that the subscription's messages are delivered
+-- to; subscriptions created before these were introduced have none
+ALTER TABLE eventsub_subscription ADD COLUMN callback_path text;
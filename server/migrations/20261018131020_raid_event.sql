@@ -0,0 +1,13 @@
+-- raids into tracked channels, as delivered by the `channel.raid` EventSub topic. the raiding
+-- channel isn't necessarily tracked, so its login is kept alongside its id
+CREATE TABLE raid_event (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    from_broadcaster_id varchar(16) NOT NULL,
+    from_broadcaster_login text NOT NULL,
+    viewers INT8 NOT NULL,
+    raided_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT raid_event_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_raid_event_channel_raided_at ON raid_event(channel_id, raided_at);
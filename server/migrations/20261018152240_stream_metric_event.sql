@@ -0,0 +1,16 @@
+-- stream events counted alongside keyword scores, delivered by EventSub. each metric is its own
+-- family rather than a keyword, as they aren't earned by chatters and so stay out of the scores
+-- and channel totals. only the channel is recorded, not who subscribed or cheered
+CREATE TABLE stream_metric_event (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    metric varchar(16) NOT NULL,
+    -- 1 for each subscription, or the bits cheered
+    amount INT8 DEFAULT 1 NOT NULL,
+    occurred_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT stream_metric_event_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT stream_metric_event_metric_check CHECK (metric IN ('subscription', 'cheer')),
+    CONSTRAINT stream_metric_event_amount_check CHECK (amount > 0)
+);
+
+CREATE INDEX idx_stream_metric_event_channel ON stream_metric_event(channel_id, metric, occurred_at);
@@ -0,0 +1,26 @@
+-- how the bot answers chat commands in a channel: in chat (`reply`), by whisper to the chatter who
+-- asked (`whisper`), or not at all (`silent`). `enabled` is kept for existing readers, and is now
+-- derived from the mode.
+ALTER TABLE reply
+    ADD COLUMN mode varchar(16) DEFAULT 'silent' NOT NULL,
+    ADD CONSTRAINT reply_mode_check CHECK (mode IN ('reply', 'whisper', 'silent'));
+
+UPDATE reply SET mode = 'reply' WHERE enabled;
+
+DROP VIEW reply_configuration;
+
+ALTER TABLE reply
+    DROP COLUMN enabled,
+    ADD COLUMN enabled BOOLEAN GENERATED ALWAYS AS (mode <> 'silent') STORED;
+
+CREATE VIEW reply_configuration AS
+SELECT
+    r.id,
+    r.enabled,
+    r.mode,
+    c.login,
+    c.name,
+    c.color,
+    c.image
+FROM reply r
+JOIN chatter c ON r.id = c.id;
@@ -0,0 +1,38 @@
+-- per-channel behaviour that used to be hardcoded. channels without a row use the column defaults.
+-- a channel's reply mode stays in `reply`, but is read as part of its settings, so changes to
+-- either table are announced on `channel_settings` for running instances to drop their cached copy
+CREATE TABLE channel_settings (
+    channel_id varchar(16) PRIMARY KEY,
+    -- keywords that aren't counted in the channel
+    muted_keywords INT4[] DEFAULT '{}' NOT NULL,
+    -- seconds a chatter waits between chat commands; 0 turns the cooldown off
+    command_cooldown_secs INT4 DEFAULT 0 NOT NULL,
+    milestones boolean DEFAULT true NOT NULL,
+    raid_thanks boolean DEFAULT true NOT NULL,
+    updated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT channel_settings_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT channel_settings_cooldown_check CHECK (command_cooldown_secs BETWEEN 0 AND 3600)
+);
+
+CREATE OR REPLACE FUNCTION notify_channel_settings()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_TABLE_NAME = 'reply' THEN
+        PERFORM pg_notify('channel_settings', COALESCE(NEW.id, OLD.id));
+    ELSE
+        PERFORM pg_notify('channel_settings', COALESCE(NEW.channel_id, OLD.channel_id));
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER channel_settings_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE ON channel_settings
+FOR EACH ROW
+EXECUTE FUNCTION notify_channel_settings();
+
+CREATE TRIGGER reply_settings_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE ON reply
+FOR EACH ROW
+EXECUTE FUNCTION notify_channel_settings();
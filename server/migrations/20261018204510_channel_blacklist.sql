@@ -0,0 +1,17 @@
+-- chatters a broadcaster doesn't want counted in their channel, on top of the global blacklist.
+-- chatters don't have to have a row in `chatter` to be blacklisted, so the login they were added
+-- with is kept for display. the blacklist is read as part of the channel's settings, so changes
+-- are announced on `channel_settings` too
+CREATE TABLE channel_blacklist (
+    channel_id varchar(16) NOT NULL,
+    chatter_id varchar(16) NOT NULL,
+    login varchar(32) NOT NULL,
+    created_at timestamp DEFAULT now() NOT NULL,
+    PRIMARY KEY (channel_id, chatter_id),
+    CONSTRAINT channel_blacklist_channel_fk FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE
+);
+
+CREATE TRIGGER channel_blacklist_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE ON channel_blacklist
+FOR EACH ROW
+EXECUTE FUNCTION notify_channel_settings();
@@ -0,0 +1,10 @@
+-- chat messages that have been counted, so a message delivered more than once (e.g. replayed
+-- after a reconnect, or received over both IRC and EventSub) is only counted once. rows are only
+-- needed for as long as a message could be redelivered, and are purged after that
+CREATE TABLE processed_message (
+    msg_id varchar(36) PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    processed_at timestamp DEFAULT now() NOT NULL
+);
+
+CREATE INDEX idx_processed_message_processed_at ON processed_message(processed_at);
@@ -0,0 +1,30 @@
+-- a channel's daily digest is compiled once its `digest_hour` (in the channel's timezone) has
+-- passed each day; NULL turns digests off. digests are announced in chat while the channel is live
+-- if `digest_in_chat` is set, and posted to the channel's `daily_digest` discord webhook if it has one
+ALTER TABLE channel_settings
+    ADD COLUMN digest_hour INT4,
+    ADD COLUMN digest_in_chat boolean DEFAULT false NOT NULL,
+    ADD CONSTRAINT channel_settings_digest_hour_check CHECK (digest_hour BETWEEN 0 AND 23);
+
+-- compiled digests, kept for the API. a channel has at most one digest per (local) day, and only
+-- the instance whose insert succeeds delivers it
+CREATE TABLE digest (
+    id SERIAL PRIMARY KEY,
+    channel_id varchar(16) NOT NULL,
+    digest_on date NOT NULL,
+    period_start timestamp NOT NULL,
+    period_end timestamp NOT NULL,
+    total INT8 NOT NULL,
+    top_chatters jsonb NOT NULL,
+    biggest_gainer jsonb,
+    created_at timestamp DEFAULT now() NOT NULL,
+
+    CONSTRAINT digest_channel_fk
+        FOREIGN KEY(channel_id) REFERENCES channel(id) ON DELETE CASCADE,
+    CONSTRAINT digest_channel_day_key UNIQUE (channel_id, digest_on)
+);
+
+ALTER TABLE discord_webhook
+    DROP CONSTRAINT discord_webhook_event_check,
+    ADD CONSTRAINT discord_webhook_event_check
+        CHECK (event IN ('stream_online', 'milestone_reached', 'leaderboard_snapshot', 'daily_digest'));
@@ -0,0 +1,129 @@
+-- score events older than the retention window are moved out to archive files a day at a time.
+-- each archived day is recorded here along with the object it was written to, so that history
+-- can still be read back (and late events for the day merged into its archive)
+CREATE TABLE score_event_archive (
+    day date PRIMARY KEY,
+    object_key varchar NOT NULL,
+    events INT8 NOT NULL,
+    archived_at timestamp DEFAULT now() NOT NULL
+);
+
+-- archived events compacted into hourly counts, which stay counted in totals and heatmaps
+CREATE TABLE score_event_rollup (
+    channel_id varchar(16) NOT NULL,
+    chatter_id varchar(16) NOT NULL,
+    hour timestamp NOT NULL,
+    total INT8 NOT NULL,
+
+    CONSTRAINT score_event_rollup_pk PRIMARY KEY(channel_id, chatter_id, hour),
+    CONSTRAINT score_event_rollup_channel_fk
+        FOREIGN KEY(channel_id) REFERENCES channel(id),
+    CONSTRAINT score_event_rollup_chatter_fk
+        FOREIGN KEY(chatter_id) REFERENCES chatter(id)
+);
+
+CREATE INDEX idx_score_event_rollup_chatter ON score_event_rollup(chatter_id);
+
+-- archiving deletes events with `pea_fan.archiving` set for the transaction; their totals stay as
+-- they are, as the events are still counted through their rollup
+CREATE OR REPLACE FUNCTION decrement_score_totals()
+RETURNS TRIGGER AS $$
+DECLARE
+    local_ts timestamp;
+BEGIN
+    IF current_setting('pea_fan.archiving', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+
+    UPDATE chatter
+    SET total = GREATEST(total - 1, 0),
+        updated_at = NOW()
+    WHERE id = OLD.chatter_id;
+
+    UPDATE channel
+    SET channel_total = GREATEST(channel_total - 1, 0),
+        updated_at = NOW()
+    WHERE id = OLD.channel_id;
+
+    UPDATE score
+    SET score = GREATEST(score - 1, 0),
+        updated_at = NOW()
+    WHERE chatter_id = OLD.chatter_id
+    AND channel_id = OLD.channel_id
+    AND keyword_id = OLD.keyword_id;
+
+    SELECT to_channel_local(OLD.earned_at, timezone) INTO local_ts
+    FROM channel
+    WHERE id = OLD.channel_id;
+
+    UPDATE channel_heatmap
+    SET total = GREATEST(total - 1, 0)
+    WHERE channel_id = OLD.channel_id
+    AND day_of_week = EXTRACT(DOW FROM local_ts)::INT2
+    AND hour = EXTRACT(HOUR FROM local_ts)::INT2;
+
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+-- `decrement_score_totals` for a rollup's archived events (e.g. when a chatter is purged)
+CREATE OR REPLACE FUNCTION decrement_rollup_totals()
+RETURNS TRIGGER AS $$
+DECLARE
+    local_ts timestamp;
+BEGIN
+    UPDATE chatter
+    SET total = GREATEST(total - OLD.total, 0),
+        updated_at = NOW()
+    WHERE id = OLD.chatter_id;
+
+    UPDATE channel
+    SET channel_total = GREATEST(channel_total - OLD.total, 0),
+        updated_at = NOW()
+    WHERE id = OLD.channel_id;
+
+    SELECT to_channel_local(OLD.hour, timezone) INTO local_ts
+    FROM channel
+    WHERE id = OLD.channel_id;
+
+    UPDATE channel_heatmap
+    SET total = GREATEST(total - OLD.total, 0)
+    WHERE channel_id = OLD.channel_id
+    AND day_of_week = EXTRACT(DOW FROM local_ts)::INT2
+    AND hour = EXTRACT(HOUR FROM local_ts)::INT2;
+
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER score_event_rollup_decrement_trigger
+AFTER DELETE ON score_event_rollup
+FOR EACH ROW
+EXECUTE FUNCTION decrement_rollup_totals();
+
+-- rebuild a channel's heatmap from its score events and the rollups of its archived events
+CREATE OR REPLACE FUNCTION recalc_channel_heatmap(channel_id_param varchar(16))
+RETURNS void AS $$
+BEGIN
+    DELETE FROM channel_heatmap
+    WHERE channel_id = channel_id_param;
+
+    INSERT INTO channel_heatmap (channel_id, day_of_week, hour, total)
+    SELECT
+        e.channel_id,
+        EXTRACT(DOW FROM to_channel_local(e.earned_at, c.timezone))::INT2 AS day_of_week,
+        EXTRACT(HOUR FROM to_channel_local(e.earned_at, c.timezone))::INT2 AS hour,
+        SUM(e.total)
+    FROM (
+        SELECT channel_id, earned_at, 1 AS total
+        FROM score_event
+        WHERE channel_id = channel_id_param
+        UNION ALL
+        SELECT channel_id, hour, total
+        FROM score_event_rollup
+        WHERE channel_id = channel_id_param
+    ) e
+    JOIN channel c ON c.id = e.channel_id
+    GROUP BY e.channel_id, day_of_week, hour;
+END;
+$$ LANGUAGE plpgsql;
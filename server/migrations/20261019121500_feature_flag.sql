@@ -0,0 +1,26 @@
+-- runtime switches for behaviour that's rolled out gradually. flags without a row use their
+-- built-in defaults. changes are announced on `feature_flag` for running instances to reload
+CREATE TABLE feature_flag (
+    name varchar PRIMARY KEY,
+    enabled boolean DEFAULT false NOT NULL,
+    -- percentage of channels the flag is on for while enabled, picked by a stable hash of the
+    -- flag's name and the channel's id
+    rollout_percent INT2 DEFAULT 100 NOT NULL,
+    -- channels the flag is on for while enabled, regardless of the rollout percentage
+    channels varchar(16)[] DEFAULT '{}' NOT NULL,
+    updated_at timestamp DEFAULT now() NOT NULL,
+    CONSTRAINT feature_flag_rollout_check CHECK (rollout_percent BETWEEN 0 AND 100)
+);
+
+CREATE OR REPLACE FUNCTION notify_feature_flag()
+RETURNS TRIGGER AS $$
+BEGIN
+    PERFORM pg_notify('feature_flag', COALESCE(NEW.name, OLD.name));
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER feature_flag_notify_trigger
+AFTER INSERT OR UPDATE OR DELETE ON feature_flag
+FOR EACH ROW
+EXECUTE FUNCTION notify_feature_flag();
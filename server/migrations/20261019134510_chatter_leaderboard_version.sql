@@ -0,0 +1,26 @@
+-- leaderboards show chatters' names, colors and avatars (and leave out deleted chatters), so a
+-- change to any of them bumps the version of every channel the chatter has scores in, as well as
+-- their own channel if they have one
+CREATE OR REPLACE FUNCTION bump_chatter_channel_versions()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE channel
+    SET version = version + 1
+    WHERE id = NEW.id
+    OR id IN (SELECT DISTINCT channel_id FROM score WHERE chatter_id = NEW.id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER bump_chatter_channel_versions
+AFTER UPDATE ON chatter
+FOR EACH ROW
+WHEN (
+    OLD.login IS DISTINCT FROM NEW.login
+    OR OLD.name IS DISTINCT FROM NEW.name
+    OR OLD.color IS DISTINCT FROM NEW.color
+    OR OLD.image IS DISTINCT FROM NEW.image
+    OR OLD.private IS DISTINCT FROM NEW.private
+    OR OLD.deleted_at IS DISTINCT FROM NEW.deleted_at
+)
+EXECUTE FUNCTION bump_chatter_channel_versions();
@@ -0,0 +1,57 @@
+-- snapshot restores insert score events with `pea_fan.restoring` set for the transaction; the
+-- totals and scores they'd add are restored from their own rows, and heatmaps are rebuilt after
+CREATE OR REPLACE FUNCTION increment_score_totals()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('pea_fan.restoring', true) = 'on' THEN
+        RETURN NEW;
+    END IF;
+
+    UPDATE chatter
+    SET total = total + 1,
+        updated_at = NOW()
+    WHERE id = NEW.chatter_id;
+
+    UPDATE channel
+    SET channel_total = channel_total + 1,
+        updated_at = NOW()
+    WHERE id = NEW.channel_id;
+
+    INSERT INTO score (chatter_id, channel_id, keyword_id, score, updated_at)
+    VALUES (NEW.chatter_id, NEW.channel_id, NEW.keyword_id, 1, NOW())
+    ON CONFLICT (chatter_id, channel_id, keyword_id)
+    DO UPDATE SET
+        score = score.score + 1,
+        updated_at = NOW();
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION increment_channel_heatmap()
+RETURNS TRIGGER AS $$
+DECLARE
+    local_ts timestamp;
+BEGIN
+    IF current_setting('pea_fan.restoring', true) = 'on' THEN
+        RETURN NEW;
+    END IF;
+
+    SELECT to_channel_local(NEW.earned_at, timezone) INTO local_ts
+    FROM channel
+    WHERE id = NEW.channel_id;
+
+    INSERT INTO channel_heatmap (channel_id, day_of_week, hour, total)
+    VALUES (
+        NEW.channel_id,
+        EXTRACT(DOW FROM local_ts)::INT2,
+        EXTRACT(HOUR FROM local_ts)::INT2,
+        1
+    )
+    ON CONFLICT (channel_id, day_of_week, hour)
+    DO UPDATE SET
+        total = channel_heatmap.total + 1;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
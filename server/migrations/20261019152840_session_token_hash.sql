@@ -0,0 +1,8 @@
+-- sessions are looked up by a hash of their token rather than compared against every stored token,
+-- and the tokens themselves are no longer stored
+ALTER TABLE session RENAME COLUMN token TO token_hash;
+
+UPDATE session
+SET token_hash = encode(sha256(convert_to(token_hash, 'UTF8')), 'hex');
+
+CREATE UNIQUE INDEX idx_session_token_hash ON session USING btree (token_hash);
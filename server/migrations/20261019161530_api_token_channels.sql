@@ -0,0 +1,3 @@
+-- the channels an `increment` token may push increments to; the same list is signed into the
+-- token's claims, and is only kept here so that tokens can be listed
+ALTER TABLE api_token ADD COLUMN channels text[] DEFAULT '{}' NOT NULL;
@@ -0,0 +1,13 @@
+-- leaderboards' `Last-Modified` comes from the channel's `updated_at`, so a chatter change has to
+-- move it along with the version; otherwise `If-Modified-Since` revalidations still get a 304
+CREATE OR REPLACE FUNCTION bump_chatter_channel_versions()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE channel
+    SET version = version + 1,
+        updated_at = now()
+    WHERE id = NEW.id
+    OR id IN (SELECT DISTINCT channel_id FROM score WHERE chatter_id = NEW.id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
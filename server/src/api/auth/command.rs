@@ -0,0 +1,253 @@
+//! The `token` subcommand, for managing API tokens from the server host.
+//!
+//! Minted tokens are written to stdout only (never logged), so they can be piped straight into a
+//! secret store.
+
+use chrono::TimeDelta;
+use sqlx::{Pool, Postgres};
+
+use crate::api::auth::{self, AuthError, AuthResult, Scope};
+use crate::db::prelude::{ApiTokenRepository, ChannelId};
+
+const USAGE: &str = "usage: token mint <LABEL> --scopes read,admin,export,increment [--channels <ID,...>] [--ttl-days <DAYS>] | token list | token revoke <ID>";
+
+const MAX_LABEL_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenCommand {
+    Mint {
+        label: String,
+        scopes: Vec<Scope>,
+        /// Required for `increment` tokens
+        channels: Vec<ChannelId>,
+        ttl_days: Option<i64>,
+    },
+    List,
+    Revoke {
+        id: String,
+    },
+}
+
+impl TokenCommand {
+    /// `None` if the arguments aren't for this subcommand.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<AuthResult<Self>> {
+        // skip the binary name
+        _ = args.next();
+        if args.next().as_deref() != Some("token") {
+            return None;
+        }
+
+        Some(Self::parse(args))
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> AuthResult<Self> {
+        match args.next().as_deref() {
+            Some("mint") => {
+                let label = args
+                    .next()
+                    .filter(|arg| !arg.starts_with("--"))
+                    .filter(|label| !label.trim().is_empty() && label.len() <= MAX_LABEL_LEN)
+                    .ok_or(usage())?;
+
+                let mut scopes = None;
+                let mut channels = Vec::new();
+                let mut ttl_days = None;
+                while let Some(flag) = args.next() {
+                    match (flag.as_str(), args.next()) {
+                        ("--scopes", Some(value)) => {
+                            let mut parsed = Vec::new();
+                            for scope in value.split(',').filter(|s| !s.trim().is_empty()) {
+                                let scope: Scope = scope.parse()?;
+                                if !parsed.contains(&scope) {
+                                    parsed.push(scope);
+                                }
+                            }
+                            scopes = Some(parsed);
+                        }
+                        ("--channels", Some(value)) => {
+                            for id in value.split(',').filter(|s| !s.trim().is_empty()) {
+                                let id = ChannelId::try_from(id.trim()).map_err(|_| usage())?;
+                                if !channels.contains(&id) {
+                                    channels.push(id);
+                                }
+                            }
+                        }
+                        ("--ttl-days", Some(value)) => {
+                            let days = value.parse::<i64>().ok().filter(|d| *d > 0);
+                            ttl_days = Some(days.ok_or(usage())?);
+                        }
+                        _ => return Err(usage()),
+                    }
+                }
+
+                let scopes = scopes.ok_or(usage())?;
+                if scopes.is_empty() {
+                    return Err(AuthError::NoScopes);
+                }
+
+                Ok(Self::Mint {
+                    label: label.trim().to_string(),
+                    scopes,
+                    channels,
+                    ttl_days,
+                })
+            }
+            Some("list") => Ok(Self::List),
+            Some("revoke") => {
+                let id = args.next().ok_or(usage())?;
+                Ok(Self::Revoke { id })
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+pub async fn run(pool: &'static Pool<Postgres>, command: TokenCommand) -> AuthResult<()> {
+    match command {
+        TokenCommand::Mint {
+            label,
+            scopes,
+            channels,
+            ttl_days,
+        } => {
+            let (token, stored) = auth::mint(
+                pool,
+                &label,
+                &scopes,
+                &channels,
+                ttl_days.map(TimeDelta::days),
+            )
+            .await?;
+
+            eprintln!(
+                "minted token {} for '{}' (expires: {}) - it won't be shown again",
+                stored.id,
+                stored.label,
+                stored
+                    .expires_at
+                    .map_or("never".to_string(), |at| at.to_string())
+            );
+            println!("{token}");
+        }
+        TokenCommand::List => {
+            for token in ApiTokenRepository::new(pool).get_all().await? {
+                let state = match (token.revoked_at, token.expires_at) {
+                    (Some(at), _) => format!("revoked {at}"),
+                    (None, Some(at)) => format!("expires {at}"),
+                    (None, None) => "never expires".to_string(),
+                };
+
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    token.id,
+                    token.label,
+                    token.scopes.join(","),
+                    token.channels.join(","),
+                    state
+                );
+            }
+        }
+        TokenCommand::Revoke { id } => match ApiTokenRepository::new(pool).revoke(&id).await? {
+            Some(token) => eprintln!("revoked token {} for '{}'", token.id, token.label),
+            None => eprintln!("no active token with id {id}"),
+        },
+    }
+
+    Ok(())
+}
+
+fn usage() -> AuthError {
+    AuthError::Usage(USAGE.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(args: &[&str]) -> Option<AuthResult<TokenCommand>> {
+        TokenCommand::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn token_command_from_args() {
+        assert!(command(&["server", "snapshot", "create"]).is_none());
+        assert!(matches!(command(&["server", "token"]), Some(Err(_))));
+        assert!(matches!(
+            command(&["server", "token", "mint", "grafana"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            command(&[
+                "server",
+                "token",
+                "mint",
+                "grafana",
+                "--scopes",
+                "read,write"
+            ]),
+            Some(Err(AuthError::UnknownScope(_)))
+        ));
+
+        let mint = command(&[
+            "server",
+            "token",
+            "mint",
+            "grafana",
+            "--scopes",
+            "read,export,read",
+            "--ttl-days",
+            "30",
+        ]);
+        assert_eq!(
+            mint.unwrap().unwrap(),
+            TokenCommand::Mint {
+                label: "grafana".to_string(),
+                scopes: vec![Scope::Read, Scope::Export],
+                channels: Vec::new(),
+                ttl_days: Some(30),
+            }
+        );
+
+        let mint = command(&[
+            "server",
+            "token",
+            "mint",
+            "stream-deck",
+            "--scopes",
+            "increment",
+            "--channels",
+            "103033809,103033809",
+        ]);
+        assert_eq!(
+            mint.unwrap().unwrap(),
+            TokenCommand::Mint {
+                label: "stream-deck".to_string(),
+                scopes: vec![Scope::Increment],
+                channels: vec![ChannelId(String::from("103033809"))],
+                ttl_days: None,
+            }
+        );
+        assert!(matches!(
+            command(&[
+                "server",
+                "token",
+                "mint",
+                "stream-deck",
+                "--scopes",
+                "increment",
+                "--channels",
+                "plss"
+            ]),
+            Some(Err(AuthError::Usage(_)))
+        ));
+
+        assert_eq!(
+            command(&["server", "token", "revoke", "abc"])
+                .unwrap()
+                .unwrap(),
+            TokenCommand::Revoke {
+                id: "abc".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,593 @@
+//! Signed API tokens.
+//!
+//! Tokens are `pft_{claims}.{signature}`, where `claims` is the base64url-encoded JSON of a
+//! `TokenClaims` and `signature` is its HMAC-SHA256 under `API_TOKEN_KEY`. Tokens aren't stored -
+//! only their ids and claims are, so that they can be listed and revoked. A token is accepted
+//! while its signature is valid, it hasn't expired and its id hasn't been revoked.
+//!
+//! Admin routes accept either a session or a token (`Authorization: Bearer pft_...`); reads need
+//! the `read` scope and writes the `admin` scope. Other routes require a scope with the
+//! `Authorized` extractor.
+
+pub mod command;
+pub mod session;
+pub mod twitch;
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+
+use axum::extract::FromRequestParts;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{TimeDelta, Utc};
+use http::header::AUTHORIZATION;
+use http::request::Parts;
+use http::{HeaderMap, StatusCode};
+use ring::hmac::{self, Key};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+use crate::api::error::ApiError;
+use crate::api::server::AppState;
+use crate::db::models::Session;
+use crate::db::models::audit::AuditActor;
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::token::ApiToken;
+use crate::db::prelude::ApiTokenRepository;
+use crate::util::env::{EnvErr, Var};
+use crate::var;
+
+const TOKEN_PREFIX: &str = "pft_";
+const MIN_KEY_LEN: usize = 32;
+
+static SIGNING_KEY: LazyLock<OnceCell<Option<Key>>> = LazyLock::new(OnceCell::new);
+async fn signing_key() -> AuthResult<&'static Key> {
+    SIGNING_KEY
+        .get_or_try_init(|| async {
+            let hex_key = var!(Var::ApiTokenKey).await?.trim();
+            if hex_key.is_empty() {
+                tracing::warn!("API_TOKEN_KEY is unset - api tokens are disabled");
+                return Ok(None);
+            }
+
+            parse_key(hex_key).map(Some)
+        })
+        .await?
+        .as_ref()
+        .ok_or(AuthError::Disabled)
+}
+
+fn parse_key(hex_key: &str) -> AuthResult<Key> {
+    let bytes = hex::decode(hex_key).map_err(|_| AuthError::InvalidKey)?;
+    if bytes.len() < MIN_KEY_LEN {
+        return Err(AuthError::InvalidKey);
+    }
+
+    Ok(Key::new(hmac::HMAC_SHA256, &bytes))
+}
+
+/// What a token is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Read-only admin routes
+    Read,
+    /// Every admin route, and anything any other scope allows
+    Admin,
+    /// Leaderboard exports
+    Export,
+    /// Pushing increments to a channel from an external tool
+    Increment,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Admin => "admin",
+            Self::Export => "export",
+            Self::Increment => "increment",
+        }
+    }
+
+    /// Whether holding this scope allows access to something requiring `required`.
+    pub fn grants(&self, required: Scope) -> bool {
+        *self == Self::Admin || *self == required
+    }
+}
+
+impl FromStr for Scope {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "read" => Ok(Self::Read),
+            "admin" => Ok(Self::Admin),
+            "export" => Ok(Self::Export),
+            "increment" => Ok(Self::Increment),
+            _ => Err(AuthError::UnknownScope(s.to_string())),
+        }
+    }
+}
+
+/// The signed contents of a token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub id: String,
+    pub label: String,
+    pub scopes: Vec<Scope>,
+    /// The channels an `increment` token may push to. Tokens minted before channels were recorded
+    /// have none, and can't push to any channel.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<ChannelId>,
+    /// Unix timestamps
+    pub issued_at: i64,
+    pub expires_at: Option<i64>,
+}
+
+impl TokenClaims {
+    pub fn allows(&self, required: Scope) -> bool {
+        self.scopes.iter().any(|scope| scope.grants(required))
+    }
+
+    /// Whether the token may push increments to `channel_id`; `admin` tokens may push to any
+    /// channel.
+    pub fn allows_channel(&self, channel_id: &ChannelId) -> bool {
+        self.allows(Scope::Admin) || self.channels.contains(channel_id)
+    }
+}
+
+/// Signs `claims` into a token.
+pub fn sign_with(key: &Key, claims: &TokenClaims) -> AuthResult<String> {
+    sign_payload(key, TOKEN_PREFIX, claims)
+}
+
+/// Checks a token's signature and expiry (against `now`, as a unix timestamp), returning its
+/// claims. Revocation isn't checked.
+pub fn verify_with(key: &Key, token: &str, now: i64) -> AuthResult<TokenClaims> {
+    let claims: TokenClaims = verify_payload(key, TOKEN_PREFIX, token)?;
+
+    if claims
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(AuthError::Expired);
+    }
+
+    Ok(claims)
+}
+
+/// Serializes and signs `payload` as `{prefix}{payload}.{signature}`.
+fn sign_payload<T: Serialize>(key: &Key, prefix: &str, payload: &T) -> AuthResult<String> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?);
+    let signature = URL_SAFE_NO_PAD.encode(hmac::sign(key, payload.as_bytes()));
+
+    Ok(format!("{prefix}{payload}.{signature}"))
+}
+
+/// Checks the signature of something signed by `sign_payload`, returning its payload.
+fn verify_payload<T: for<'de> Deserialize<'de>>(
+    key: &Key,
+    prefix: &str,
+    token: &str,
+) -> AuthResult<T> {
+    let (payload, signature) = token
+        .strip_prefix(prefix)
+        .and_then(|token| token.split_once('.'))
+        .ok_or(AuthError::Malformed)?;
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| AuthError::Malformed)?;
+    hmac::verify(key, payload.as_bytes(), &signature).map_err(|_| AuthError::BadSignature)?;
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload)
+        .map_err(|_| AuthError::Malformed)?;
+    serde_json::from_slice(&payload).map_err(|_| AuthError::Malformed)
+}
+
+/// Issues a token, recording its claims so that it can be revoked. The token itself is only
+/// returned here and can't be recovered later.
+pub async fn mint(
+    pool: &'static Pool<Postgres>,
+    label: &str,
+    scopes: &[Scope],
+    channels: &[ChannelId],
+    ttl: Option<TimeDelta>,
+) -> AuthResult<(String, ApiToken)> {
+    let key = signing_key().await?;
+    if scopes.is_empty() {
+        return Err(AuthError::NoScopes);
+    }
+
+    // otherwise an increment token could push to every channel
+    if scopes.contains(&Scope::Increment) && !scopes.contains(&Scope::Admin) && channels.is_empty()
+    {
+        return Err(AuthError::NoChannels);
+    }
+
+    let mut id = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| AuthError::Unspecified)?;
+
+    let now = Utc::now();
+    let expires_at = ttl.map(|ttl| now + ttl);
+    let claims = TokenClaims {
+        id: hex::encode(id),
+        label: label.to_string(),
+        scopes: scopes.to_vec(),
+        channels: channels.to_vec(),
+        issued_at: now.timestamp(),
+        expires_at: expires_at.map(|at| at.timestamp()),
+    };
+
+    let token = sign_with(key, &claims)?;
+    let scopes: Vec<String> = scopes.iter().map(|s| s.as_str().to_string()).collect();
+    let channels: Vec<String> = channels.iter().map(|id| id.0.clone()).collect();
+    let stored = ApiTokenRepository::new(pool)
+        .insert(
+            &claims.id,
+            label,
+            &scopes,
+            &channels,
+            expires_at.map(|at| at.naive_utc()),
+        )
+        .await?;
+
+    tracing::info!(
+        id = claims.id,
+        label,
+        ?scopes,
+        ?channels,
+        "minted api token"
+    );
+    Ok((token, stored))
+}
+
+/// Checks a token's signature and expiry with the configured key, without checking revocation.
+pub async fn verify(token: &str) -> AuthResult<TokenClaims> {
+    verify_with(signing_key().await?, token, Utc::now().timestamp())
+}
+
+/// Validates a token and checks that it allows `required`.
+pub async fn authenticate(
+    pool: &'static Pool<Postgres>,
+    token: &str,
+    required: Scope,
+) -> AuthResult<TokenClaims> {
+    let claims = verify(token).await?;
+
+    if !ApiTokenRepository::new(pool).is_active(&claims.id).await? {
+        tracing::warn!(id = claims.id, "revoked api token used");
+        return Err(AuthError::Revoked);
+    }
+
+    if !claims.allows(required) {
+        tracing::warn!(
+            id = claims.id,
+            required = required.as_str(),
+            "api token lacks scope"
+        );
+        return Err(AuthError::MissingScope(required));
+    }
+
+    Ok(claims)
+}
+
+/// The token in an `Authorization: Bearer` header, if there is one.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Who an admin request (or a privileged chat command) was made by.
+#[derive(Debug, Clone)]
+pub enum Actor {
+    Session(Session),
+    Token(TokenClaims),
+    /// A broadcaster or moderator, through a chat command
+    Chat(ChatterId),
+    /// A broadcaster onboarding or managing their own channel, through Twitch OAuth or a
+    /// dashboard session
+    Broadcaster(ChatterId),
+}
+
+impl Actor {
+    pub fn session_id(&self) -> Option<i32> {
+        match self {
+            Self::Session(session) => Some(session.id),
+            _ => None,
+        }
+    }
+
+    pub fn token_id(&self) -> Option<&str> {
+        match self {
+            Self::Token(claims) => Some(&claims.id),
+            _ => None,
+        }
+    }
+
+    pub fn chatter_id(&self) -> Option<&ChatterId> {
+        match self {
+            Self::Chat(chatter_id) | Self::Broadcaster(chatter_id) => Some(chatter_id),
+            _ => None,
+        }
+    }
+
+    /// Whether the actor can make admin changes, rather than only read (as a token with just the
+    /// `read` scope can).
+    pub fn is_admin(&self) -> bool {
+        match self {
+            Self::Session(_) => true,
+            Self::Token(claims) => claims.allows(Scope::Admin),
+            Self::Chat(_) | Self::Broadcaster(_) => false,
+        }
+    }
+
+    pub fn audit_actor(&self) -> AuditActor<'_> {
+        AuditActor {
+            session_id: self.session_id(),
+            token_id: self.token_id(),
+            chatter_id: self.chatter_id(),
+        }
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Session(session) => write!(f, "session:{}", session.id),
+            Self::Token(claims) => write!(f, "token:{}", claims.id),
+            Self::Chat(chatter_id) => write!(f, "chat:{chatter_id}"),
+            Self::Broadcaster(chatter_id) => write!(f, "oauth:{chatter_id}"),
+        }
+    }
+}
+
+/// A scope required by `Authorized`.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+#[derive(Debug)]
+pub struct ReadScope;
+impl RequiredScope for ReadScope {
+    const SCOPE: Scope = Scope::Read;
+}
+
+#[derive(Debug)]
+pub struct AdminScope;
+impl RequiredScope for AdminScope {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+#[derive(Debug)]
+pub struct ExportScope;
+impl RequiredScope for ExportScope {
+    const SCOPE: Scope = Scope::Export;
+}
+
+#[derive(Debug)]
+pub struct IncrementScope;
+impl RequiredScope for IncrementScope {
+    const SCOPE: Scope = Scope::Increment;
+}
+
+/// Rejects requests without a bearer token allowing `S`.
+#[derive(Debug)]
+pub struct Authorized<S> {
+    pub claims: TokenClaims,
+    _scope: PhantomData<S>,
+}
+
+impl<S: RequiredScope + Send + Sync> FromRequestParts<Arc<AppState>> for Authorized<S> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers).ok_or(AuthError::MissingToken)?;
+        let claims = authenticate(state.database_pool, token, S::SCOPE).await?;
+
+        Ok(Self {
+            claims,
+            _scope: PhantomData,
+        })
+    }
+}
+
+pub type AuthResult<T> = core::result::Result<T, AuthError>;
+
+#[derive(Debug, Error)]
+pub enum AuthError {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error(transparent)]
+    EnvError(#[from] EnvErr),
+
+    #[error("api tokens are disabled")]
+    Disabled,
+
+    #[error("API_TOKEN_KEY must be a hex-encoded key of at least 256 bits")]
+    InvalidKey,
+
+    #[error("missing api token")]
+    MissingToken,
+
+    #[error("malformed api token")]
+    Malformed,
+
+    #[error("invalid api token signature")]
+    BadSignature,
+
+    #[error("api token has expired")]
+    Expired,
+
+    #[error("api token has been revoked")]
+    Revoked,
+
+    #[error("api token lacks the '{}' scope", .0.as_str())]
+    MissingScope(Scope),
+
+    #[error("unknown scope '{0}'")]
+    UnknownScope(String),
+
+    #[error("tokens need at least one scope")]
+    NoScopes,
+
+    #[error("increment tokens need at least one channel")]
+    NoChannels,
+
+    #[error("api token can't push to this channel")]
+    ChannelNotAllowed,
+
+    #[error("twitch onboarding is disabled")]
+    OAuthDisabled,
+
+    #[error("unknown or expired authorization state")]
+    OAuthState,
+
+    #[error("authorization was denied: {0}")]
+    OAuthDenied(String),
+
+    #[error("twitch rejected the authorization code ({0})")]
+    OAuthExchange(StatusCode),
+
+    #[error("missing dashboard session")]
+    MissingSession,
+
+    #[error("dashboard session is for a channel that isn't tracked")]
+    NotOwner,
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error("ring::error::Unspecified error occurred")]
+    Unspecified,
+}
+
+impl AuthError {
+    /// The status a request is rejected with.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::MissingToken
+            | Self::Malformed
+            | Self::BadSignature
+            | Self::Expired
+            | Self::Revoked
+            | Self::Disabled
+            | Self::MissingSession => StatusCode::UNAUTHORIZED,
+            Self::MissingScope(_) | Self::NotOwner | Self::ChannelNotAllowed => {
+                StatusCode::FORBIDDEN
+            }
+            Self::UnknownScope(_)
+            | Self::NoScopes
+            | Self::NoChannels
+            | Self::OAuthState
+            | Self::OAuthDenied(_)
+            | Self::OAuthExchange(_) => StatusCode::BAD_REQUEST,
+            Self::OAuthDisabled => StatusCode::NOT_FOUND,
+            Self::Redis(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn claims(scopes: &[Scope], expires_at: Option<i64>) -> TokenClaims {
+        TokenClaims {
+            id: "00112233445566778899aabbccddeeff".to_string(),
+            label: "grafana".to_string(),
+            scopes: scopes.to_vec(),
+            channels: Vec::new(),
+            issued_at: 1_700_000_000,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn tokens_verify_only_with_their_key() {
+        let key = parse_key(&"ab".repeat(MIN_KEY_LEN)).unwrap();
+        let other = parse_key(&"cd".repeat(MIN_KEY_LEN)).unwrap();
+        let claims = claims(&[Scope::Read], Some(1_700_000_100));
+
+        let token = sign_with(&key, &claims).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(verify_with(&key, &token, 1_700_000_050).unwrap(), claims);
+
+        assert!(matches!(
+            verify_with(&other, &token, 1_700_000_050),
+            Err(AuthError::BadSignature)
+        ));
+        assert!(matches!(
+            verify_with(&key, &token, 1_700_000_100),
+            Err(AuthError::Expired)
+        ));
+
+        // swapping in different claims invalidates the signature
+        let (_, signature) = token.split_once('.').unwrap();
+        let elevated = sign_with(&other, &self::claims(&[Scope::Admin], None)).unwrap();
+        let (payload, _) = elevated.split_once('.').unwrap();
+        assert!(matches!(
+            verify_with(&key, &format!("{payload}.{signature}"), 0),
+            Err(AuthError::BadSignature)
+        ));
+
+        assert!(parse_key(&"ab".repeat(MIN_KEY_LEN - 1)).is_err());
+    }
+
+    #[test]
+    fn admin_scope_grants_every_scope() {
+        let admin = claims(&[Scope::Admin], None);
+        let read = claims(&[Scope::Read], None);
+
+        assert!(admin.allows(Scope::Export));
+        assert!(admin.allows(Scope::Increment));
+        assert!(read.allows(Scope::Read));
+        assert!(!read.allows(Scope::Export));
+        assert!(!read.allows(Scope::Admin));
+    }
+
+    #[test]
+    fn increment_tokens_only_push_to_their_channels() {
+        let (own, other) = (
+            ChannelId(String::from("103033809")),
+            ChannelId(String::from("123456789")),
+        );
+        let increment = TokenClaims {
+            channels: vec![own.clone()],
+            ..claims(&[Scope::Increment], None)
+        };
+
+        assert!(increment.allows_channel(&own));
+        assert!(!increment.allows_channel(&other));
+        assert!(!claims(&[Scope::Increment], None).allows_channel(&own));
+        assert!(claims(&[Scope::Admin], None).allows_channel(&other));
+    }
+}
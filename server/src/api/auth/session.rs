@@ -0,0 +1,220 @@
+//! Dashboard sessions, for broadcasters who've logged in through Twitch (see `auth::twitch`).
+//!
+//! A session is a cookie holding the broadcaster's channel, signed the same way as api tokens but
+//! with a key derived from `API_TOKEN_KEY`, so that neither can be passed off as the other. Each
+//! session is also recorded in Redis for `SESSION_TTL`, and is only accepted while that record
+//! exists: logging out deletes it, and an admin can revoke every session for a channel. The cookie
+//! is scoped to the dashboard routes and is `SameSite=Lax`, so other sites can't make changes with
+//! it.
+
+use chrono::{TimeDelta, Utc};
+use http::HeaderMap;
+use http::header::COOKIE;
+use redis::AsyncCommands;
+use ring::hmac::{self, Key};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::api::auth::{AuthError, AuthResult, sign_payload, signing_key, verify_payload};
+use crate::db::models::channel::ChannelId;
+
+pub const SESSION_COOKIE: &str = "pf_session";
+pub const SESSION_TTL: TimeDelta = TimeDelta::days(7);
+const SESSION_PREFIX: &str = "pfs_";
+const SESSION_PATH: &str = "/api/v1/me";
+const SESSION_KEY_CONTEXT: &[u8] = b"pea-fan broadcaster session";
+const SESSION_ID_LEN: usize = 16;
+/// `{id}` -> the session's channel
+const SESSION_RECORD_PREFIX: &str = "dashboard:session:";
+/// `{channel_id}` -> the ids of the channel's sessions
+const CHANNEL_SESSIONS_PREFIX: &str = "dashboard:sessions:";
+
+static SESSION_KEY: OnceCell<Key> = OnceCell::const_new();
+async fn session_key() -> AuthResult<&'static Key> {
+    SESSION_KEY
+        .get_or_try_init(|| async { Ok(derive_key(signing_key().await?)) })
+        .await
+}
+
+fn derive_key(key: &Key) -> Key {
+    Key::new(
+        hmac::HMAC_SHA256,
+        hmac::sign(key, SESSION_KEY_CONTEXT).as_ref(),
+    )
+}
+
+/// The signed contents of a session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionClaims {
+    /// Identifies the session's record in Redis
+    pub id: String,
+    pub channel_id: ChannelId,
+    pub login: String,
+    /// Unix timestamps
+    pub issued_at: i64,
+    pub expires_at: i64,
+}
+
+/// Checks a session's signature and expiry (against `now`, as a unix timestamp), returning its
+/// claims.
+pub fn verify_with(key: &Key, session: &str, now: i64) -> AuthResult<SessionClaims> {
+    let claims: SessionClaims = verify_payload(key, SESSION_PREFIX, session)?;
+    if claims.expires_at <= now {
+        return Err(AuthError::Expired);
+    }
+
+    Ok(claims)
+}
+
+/// Starts a session for a channel's broadcaster, returning the `Set-Cookie` value for it.
+pub async fn issue<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    channel_id: &ChannelId,
+    login: &str,
+) -> AuthResult<String> {
+    let mut bytes = [0u8; SESSION_ID_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| AuthError::Unspecified)?;
+
+    let now = Utc::now();
+    let claims = SessionClaims {
+        id: hex::encode(bytes),
+        channel_id: channel_id.clone(),
+        login: login.to_string(),
+        issued_at: now.timestamp(),
+        expires_at: (now + SESSION_TTL).timestamp(),
+    };
+
+    let session = sign_payload(session_key().await?, SESSION_PREFIX, &claims)?;
+
+    let ttl = SESSION_TTL.num_seconds();
+    let channel_sessions = format!("{CHANNEL_SESSIONS_PREFIX}{channel_id}");
+    let _: () = redis::pipe()
+        .atomic()
+        .set_ex(
+            format!("{SESSION_RECORD_PREFIX}{}", claims.id),
+            &channel_id.0,
+            ttl as u64,
+        )
+        .sadd(&channel_sessions, &claims.id)
+        .expire(&channel_sessions, ttl)
+        .query_async(redis_pool)
+        .await?;
+
+    Ok(format!(
+        "{SESSION_COOKIE}={session}; Path={SESSION_PATH}; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        SESSION_TTL.num_seconds()
+    ))
+}
+
+/// Checks a session with the configured key, and that it hasn't been ended or revoked.
+pub async fn verify<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    session: &str,
+) -> AuthResult<SessionClaims> {
+    let claims = verify_with(session_key().await?, session, Utc::now().timestamp())?;
+
+    let recorded: bool = redis_pool
+        .exists(format!("{SESSION_RECORD_PREFIX}{}", claims.id))
+        .await?;
+    if !recorded {
+        tracing::warn!(channel = %claims.channel_id, "dashboard session was revoked");
+        return Err(AuthError::Revoked);
+    }
+
+    Ok(claims)
+}
+
+/// Ends a single session.
+pub async fn revoke<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    claims: &SessionClaims,
+) -> AuthResult<()> {
+    let _: () = redis::pipe()
+        .atomic()
+        .del(format!("{SESSION_RECORD_PREFIX}{}", claims.id))
+        .srem(
+            format!("{CHANNEL_SESSIONS_PREFIX}{}", claims.channel_id),
+            &claims.id,
+        )
+        .query_async(redis_pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Ends every session for a channel, returning how many were still active.
+pub async fn revoke_all<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    channel_id: &ChannelId,
+) -> AuthResult<usize> {
+    let channel_sessions = format!("{CHANNEL_SESSIONS_PREFIX}{channel_id}");
+    let ids: Vec<String> = redis_pool.smembers(&channel_sessions).await?;
+
+    let mut pipeline = redis::pipe();
+    pipeline.atomic().del(&channel_sessions);
+    for id in &ids {
+        pipeline.del(format!("{SESSION_RECORD_PREFIX}{id}"));
+    }
+
+    // the set can hold sessions that have since expired, so only the deleted records are counted
+    let deleted: Vec<usize> = pipeline.query_async(redis_pool).await?;
+    Ok(deleted.iter().skip(1).sum())
+}
+
+/// The `Set-Cookie` value that ends a session.
+pub fn clear() -> String {
+    format!("{SESSION_COOKIE}=; Path={SESSION_PATH}; Max-Age=0; HttpOnly; Secure; SameSite=Lax")
+}
+
+/// The session in a request's cookies, if there is one.
+pub fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find_map(|(name, value)| (name == SESSION_COOKIE).then_some(value))
+}
+
+#[cfg(test)]
+mod test {
+    use http::HeaderValue;
+
+    use super::*;
+    use crate::api::auth::parse_key;
+
+    #[test]
+    fn sessions_verify_only_with_the_derived_key() {
+        let key = parse_key(&"ab".repeat(32)).unwrap();
+        let derived = derive_key(&key);
+        let claims = SessionClaims {
+            id: String::from("00112233445566778899aabbccddeeff"),
+            channel_id: ChannelId(String::from("123")),
+            login: String::from("broadcaster"),
+            issued_at: 1_000,
+            expires_at: 2_000,
+        };
+
+        let session = sign_payload(&derived, SESSION_PREFIX, &claims).unwrap();
+        assert_eq!(verify_with(&derived, &session, 1_500).unwrap(), claims);
+        assert!(matches!(
+            verify_with(&key, &session, 1_500),
+            Err(AuthError::BadSignature)
+        ));
+        assert!(matches!(
+            verify_with(&derived, &session, 2_000),
+            Err(AuthError::Expired)
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            COOKIE,
+            HeaderValue::from_str(&format!("theme=dark; {SESSION_COOKIE}={session}")).unwrap(),
+        );
+        assert_eq!(session_cookie(&headers), Some(session.as_str()));
+    }
+}
@@ -0,0 +1,179 @@
+//! Twitch OAuth (the authorization code flow), for broadcasters onboarding their own channels.
+//!
+//! A broadcaster is sent to Twitch's authorization page with a random `state`, which is kept in
+//! Redis for `STATE_TTL` and can only be used once. Twitch sends them back to `OAUTH_REDIRECT_URI`
+//! with a code, which is exchanged for a user token to find out who they are. The token is only
+//! used for that - the authorization itself is what lets the client subscribe to the channel's
+//! EventSub topics with its app token, so the token isn't stored.
+
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use reqwest::Url;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Deserialize;
+
+use crate::api::auth::{AuthError, AuthResult};
+use crate::api::webhook::metric::stream_metrics_enabled;
+use crate::util::env::Var;
+use crate::util::http_client;
+use crate::var;
+
+const AUTHORIZE_URL: &str = "https://id.twitch.tv/oauth2/authorize";
+const TOKEN_URL: &str = "https://id.twitch.tv/oauth2/token";
+const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+const OAUTH_SERVICE: &str = "twitch_oauth";
+
+const STATE_PREFIX: &str = "oauth:state:";
+const STATE_TTL: Duration = Duration::from_secs(60 * 10);
+const STATE_LEN: usize = 32;
+
+/// Lets the bot chat in the channel without being a moderator.
+const CHANNEL_SCOPES: [&str; 1] = ["channel:bot"];
+/// Lets the client read the subscriptions and cheers counted as stream metrics.
+const METRIC_SCOPES: [&str; 2] = ["channel:read:subscriptions", "bits:read"];
+
+/// The scopes broadcasters are asked for; the metric scopes are only asked for when
+/// `EVENTSUB_STREAM_METRICS` is set.
+pub async fn scopes() -> Vec<&'static str> {
+    let mut scopes = CHANNEL_SCOPES.to_vec();
+    if stream_metrics_enabled().await {
+        scopes.extend(METRIC_SCOPES);
+    }
+
+    scopes
+}
+
+/// The client's OAuth configuration; onboarding is disabled unless all of it is set.
+#[derive(Debug, Clone)]
+pub struct OAuthConfig {
+    pub client_id: &'static str,
+    pub client_secret: &'static str,
+    pub redirect_uri: &'static str,
+}
+
+impl OAuthConfig {
+    pub async fn from_env() -> AuthResult<Self> {
+        let client_secret = var!(Var::ClientSecret).await?.trim();
+        let redirect_uri = var!(Var::OAuthRedirectUri).await?.trim();
+        if client_secret.is_empty() || redirect_uri.is_empty() {
+            return Err(AuthError::OAuthDisabled);
+        }
+
+        Ok(Self {
+            client_id: var!(Var::ClientId).await?,
+            client_secret,
+            redirect_uri,
+        })
+    }
+
+    /// The Twitch page that asks the broadcaster to authorize the client.
+    pub fn authorize_url(&self, state: &str, scopes: &[&str]) -> String {
+        Url::parse_with_params(
+            AUTHORIZE_URL,
+            [
+                ("response_type", "code"),
+                ("client_id", self.client_id),
+                ("redirect_uri", self.redirect_uri),
+                ("scope", &scopes.join(" ")),
+                ("state", state),
+            ],
+        )
+        .expect("authorize url is valid")
+        .into()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// The user a token belongs to, from Twitch's validation endpoint.
+#[derive(Debug, Deserialize)]
+pub struct ValidatedToken {
+    pub user_id: String,
+    pub login: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// Creates a single-use `state` for an authorization request.
+pub async fn issue_state<R: AsyncCommands + Sync>(redis_pool: &mut R) -> AuthResult<String> {
+    let mut bytes = [0u8; STATE_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| AuthError::Unspecified)?;
+
+    let state = hex::encode(bytes);
+    let _: () = redis_pool
+        .set_ex(format!("{STATE_PREFIX}{state}"), 1, STATE_TTL.as_secs())
+        .await?;
+
+    Ok(state)
+}
+
+/// Checks that `state` was issued by `issue_state` and hasn't expired, using it up.
+pub async fn consume_state<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    state: &str,
+) -> AuthResult<()> {
+    let issued: Option<i64> = redis_pool.get_del(format!("{STATE_PREFIX}{state}")).await?;
+    issued.map(|_| ()).ok_or(AuthError::OAuthState)
+}
+
+/// Exchanges an authorization code for the authorizing user, returning who they are.
+pub async fn authorize(config: &OAuthConfig, code: &str) -> AuthResult<ValidatedToken> {
+    let client = http_client::client().await;
+
+    let request = client.post(TOKEN_URL).form(&[
+        ("client_id", config.client_id),
+        ("client_secret", config.client_secret),
+        ("code", code),
+        ("grant_type", "authorization_code"),
+        ("redirect_uri", config.redirect_uri),
+    ]);
+    let response = http_client::send(OAUTH_SERVICE, request).await?;
+    if !response.status().is_success() {
+        return Err(AuthError::OAuthExchange(response.status()));
+    }
+    let token = response.json::<TokenResponse>().await?;
+
+    let request = client
+        .get(VALIDATE_URL)
+        .header("Authorization", format!("OAuth {}", token.access_token));
+    let response = http_client::send(OAUTH_SERVICE, request).await?;
+    if !response.status().is_success() {
+        return Err(AuthError::OAuthExchange(response.status()));
+    }
+
+    Ok(response.json::<ValidatedToken>().await?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn authorize_urls_are_encoded() {
+        let config = OAuthConfig {
+            client_id: "abc",
+            client_secret: "secret",
+            redirect_uri: "https://example.com/api/v1/auth/twitch/callback",
+        };
+
+        let url = Url::parse(&config.authorize_url("f00", &METRIC_SCOPES)).unwrap();
+        let params: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+        assert!(params.contains(&(
+            "scope".into(),
+            "channel:read:subscriptions bits:read".into()
+        )));
+        assert!(params.contains(&("state".into(), "f00".into())));
+        assert!(params.contains(&(
+            "redirect_uri".into(),
+            "https://example.com/api/v1/auth/twitch/callback".into()
+        )));
+        assert!(!url.as_str().contains("secret"));
+    }
+}
@@ -0,0 +1,116 @@
+//! Conditional requests for leaderboards.
+//!
+//! Leaderboard responses carry an `ETag` and `Last-Modified` built from a `LeaderboardVersion`,
+//! and requests that already have the current version (per `If-None-Match`, or `If-Modified-Since`
+//! without it) get an empty `304` without the leaderboard being queried. `Cache-Control: no-cache`
+//! makes clients revalidate rather than guess how long a cached leaderboard stays fresh.
+
+use std::future::Future;
+
+use axum::response::{IntoResponse, Response};
+use chrono::{NaiveDateTime, SubsecRound};
+use http::header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use serde::Serialize;
+
+use crate::api::error::ApiError;
+use crate::api::server::ApiResult;
+use crate::db::models::leaderboard::LeaderboardVersion;
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Responds with `304 Not Modified` if the request's validators match `version`, otherwise with
+/// `response` (which isn't polled at all in the former case).
+pub async fn respond<T: Serialize>(
+    headers: &HeaderMap,
+    version: LeaderboardVersion,
+    response: impl Future<Output = ApiResult<T>>,
+) -> Result<Response, ApiError> {
+    // nothing to version, so no validators to send
+    let Some(tag) = version.tag else {
+        return Ok(response.await?.into_response());
+    };
+
+    let etag = format!("W/\"{tag}\"");
+    let modified = version.modified.map(|at| at.trunc_subsecs(0));
+
+    let mut response = if is_fresh(headers, &etag, modified) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        response.await?.into_response()
+    };
+
+    let response_headers = response.headers_mut();
+    response_headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        response_headers.insert(ETAG, etag);
+    }
+    if let Some(modified) = modified
+        && let Ok(modified) = HeaderValue::from_str(&modified.format(HTTP_DATE_FORMAT).to_string())
+    {
+        response_headers.insert(LAST_MODIFIED, modified);
+    }
+
+    Ok(response)
+}
+
+/// `If-Modified-Since` is only considered without `If-None-Match`, which takes precedence.
+fn is_fresh(headers: &HeaderMap, etag: &str, modified: Option<NaiveDateTime>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|tags| {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || opaque_tag(tag) == opaque_tag(etag))
+        });
+    }
+
+    let since = headers
+        .get(IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| NaiveDateTime::parse_from_str(v.trim(), HTTP_DATE_FORMAT).ok());
+
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Leaderboards are compared weakly, so `W/"x"` matches `"x"`.
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fresh_requests_match_the_current_version() {
+        let etag = "W/\"abc\"";
+        let modified =
+            NaiveDateTime::parse_from_str("Sat, 17 Oct 2026 23:48:47 GMT", HTTP_DATE_FORMAT).ok();
+
+        let mut headers = HeaderMap::new();
+        assert!(!is_fresh(&headers, etag, modified));
+
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("\"xyz\", \"abc\""));
+        assert!(is_fresh(&headers, etag, modified));
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_static("W/\"xyz\""));
+        assert!(!is_fresh(&headers, etag, modified));
+
+        // a mismatched etag isn't overridden by the date
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sun, 18 Oct 2026 00:00:00 GMT"),
+        );
+        assert!(!is_fresh(&headers, etag, modified));
+
+        headers.remove(IF_NONE_MATCH);
+        assert!(is_fresh(&headers, etag, modified));
+        headers.insert(
+            IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Sat, 17 Oct 2026 23:48:46 GMT"),
+        );
+        assert!(!is_fresh(&headers, etag, modified));
+    }
+}
@@ -0,0 +1,6 @@
+//! Public API response types, versioned alongside the `/api/{version}` route prefix.
+//!
+//! Public handlers map internal models into these types instead of serializing the models
+//! directly, so that database and model refactors don't change the shape of API responses.
+
+pub mod v1;
@@ -0,0 +1,420 @@
+//! Response types for `/api/v1`.
+//!
+//! Field names here are part of the v1 contract and are consistent across responses:
+//!
+//! - `total_as_chatter`: scores earned by a user while chatting (across all channels)
+//! - `total_as_broadcaster`: scores earned by chatters in a user's channel
+//! - `score`/`ranking`: a single score and its rank within the containing leaderboard
+//!
+//! Renaming or removing a field is a breaking change and belongs in a new API version; all
+//! mapping from internal models happens in this module.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelLeaderboardEntry, ChannelReplies, ChannelScoreSummary};
+use crate::db::models::chatter::{
+    Chatter, ChatterLeaderboardEntry, ChatterScoreSummary, ChatterSearchResult,
+};
+use crate::db::models::keyword::{Keyword, KeywordKind, KeywordLeaderboardEntry};
+use crate::db::models::metric::ChannelMetrics;
+use crate::db::models::profile::ChatterProfile;
+use crate::db::models::search::UserSearchMatch;
+use crate::db::models::settings::ChannelSettings;
+
+/// Public profile information for a chatter or broadcaster.
+#[derive(Debug, Clone, Serialize)]
+pub struct Profile {
+    pub id: String,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_items: i64,
+    pub total_pages: i64,
+}
+
+impl<M, T: From<M>> From<PaginatedResponse<M>> for Page<T> {
+    fn from(value: PaginatedResponse<M>) -> Self {
+        Self {
+            items: value.items.into_iter().map(T::from).collect(),
+            page: value.page,
+            page_size: value.page_size,
+            total_items: value.total_items,
+            total_pages: value.total_pages,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatterEntry {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    /// Number of channels the chatter has a score in
+    pub channel_count: i64,
+    pub channel_scores: Vec<ChannelScore>,
+}
+
+/// A chatter's profile page: their `ChatterEntry`, plus when they were first and last counted and
+/// their daily scores over recent days.
+#[derive(Debug, Serialize)]
+pub struct ChatterProfileEntry {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    /// Number of channels the chatter has a score in
+    pub channel_count: i64,
+    pub channel_scores: Vec<ChannelScore>,
+    pub first_seen: Option<NaiveDateTime>,
+    pub last_seen: Option<NaiveDateTime>,
+    /// Oldest first, including days without any scores
+    pub activity: Vec<ActivityDay>,
+}
+
+/// Scores earned across all channels in a single (UTC) day.
+#[derive(Debug, Serialize)]
+pub struct ActivityDay {
+    pub day: NaiveDate,
+    pub score: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelEntry {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub total_as_broadcaster: i64,
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    /// Number of chatters with a score in the channel
+    pub chatter_count: i64,
+    pub chatter_scores: Vec<ChatterScore>,
+    /// Whether the channel was live as of the last stream status refresh
+    pub live: bool,
+}
+
+/// A chatter's score in a single channel.
+#[derive(Debug, Serialize)]
+pub struct ChannelScore {
+    pub channel: Profile,
+    pub score: i64,
+    pub ranking: i64,
+}
+
+/// A broadcaster's own channel, as shown on their dashboard.
+#[derive(Debug, Serialize)]
+pub struct Dashboard {
+    pub channel: ChannelEntry,
+    /// Whether counting is paused in the channel
+    pub paused: bool,
+    /// All-time subscription and cheer totals
+    pub metrics: ChannelMetrics,
+    pub settings: ChannelSettings,
+}
+
+/// A single chatter's score in a channel.
+#[derive(Debug, Serialize)]
+pub struct ChatterScore {
+    pub chatter: Profile,
+    pub score: i64,
+    pub ranking: i64,
+}
+
+/// A tracked keyword; emote keywords may match by `emote_id` instead of their name.
+#[derive(Debug, Serialize)]
+pub struct KeywordSummary {
+    pub word: String,
+    pub kind: KeywordKind,
+    pub emote_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeywordEntry {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub score: i64,
+    pub ranking: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    pub similarity: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub results: Vec<SearchResult>,
+    pub total_chatters: i64,
+}
+
+/// A chatter or broadcaster matched by `/search`.
+#[derive(Debug, Serialize)]
+pub struct UserSearchResult {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub total_as_chatter: i64,
+    /// Only present if the user is a tracked channel
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_as_broadcaster: Option<i64>,
+    pub similarity: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BotChannel {
+    #[serde(flatten)]
+    pub profile: Profile,
+    pub replies_enabled: bool,
+}
+
+impl From<Chatter> for Profile {
+    fn from(value: Chatter) -> Self {
+        Self {
+            id: value.id.0,
+            login: value.login,
+            name: value.name,
+            color: value.color,
+            image: value.image,
+        }
+    }
+}
+
+impl From<ChatterLeaderboardEntry> for ChatterEntry {
+    fn from(value: ChatterLeaderboardEntry) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id.0,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            total_as_chatter: value.total,
+            ranking: value.ranking,
+            channel_count: value.total_scores,
+            channel_scores: value
+                .channel_scores
+                .into_iter()
+                .map(ChannelScore::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<ChatterProfile> for ChatterProfileEntry {
+    fn from(value: ChatterProfile) -> Self {
+        let chatter = value.chatter;
+        Self {
+            profile: Profile {
+                id: chatter.id.0,
+                login: chatter.login,
+                name: chatter.name,
+                color: chatter.color,
+                image: chatter.image,
+            },
+            total_as_chatter: chatter.total,
+            ranking: chatter.ranking,
+            channel_count: value.channel_scores.len() as i64,
+            channel_scores: value
+                .channel_scores
+                .into_iter()
+                .map(ChannelScore::from)
+                .collect(),
+            first_seen: chatter.first_seen,
+            last_seen: chatter.last_seen,
+            activity: value
+                .activity
+                .into_iter()
+                .map(|day| ActivityDay {
+                    day: day.day,
+                    score: day.total,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ChannelLeaderboardEntry> for ChannelEntry {
+    fn from(value: ChannelLeaderboardEntry) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id.0,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            total_as_broadcaster: value.total_channel,
+            total_as_chatter: value.total_chatter,
+            ranking: value.ranking,
+            chatter_count: value.total_scores,
+            chatter_scores: value
+                .chatter_scores
+                .into_iter()
+                .map(ChatterScore::from)
+                .collect(),
+            live: false,
+        }
+    }
+}
+
+impl From<ChannelScoreSummary> for ChannelScore {
+    fn from(value: ChannelScoreSummary) -> Self {
+        Self {
+            channel: Profile {
+                id: value.channel_id.0,
+                login: value.channel_login,
+                name: value.channel_name,
+                color: value.channel_color,
+                image: value.channel_image,
+            },
+            score: value.score,
+            ranking: value.ranking,
+        }
+    }
+}
+
+impl From<ChatterScoreSummary> for ChatterScore {
+    fn from(value: ChatterScoreSummary) -> Self {
+        Self {
+            chatter: Profile {
+                id: value.chatter_id.0,
+                login: value.chatter_login,
+                name: value.chatter_name,
+                color: value.chatter_color,
+                image: value.chatter_image,
+            },
+            score: value.score,
+            ranking: value.ranking,
+        }
+    }
+}
+
+impl From<Keyword> for KeywordSummary {
+    fn from(value: Keyword) -> Self {
+        Self {
+            word: value.word,
+            kind: value.kind,
+            emote_id: value.emote_id,
+        }
+    }
+}
+
+impl From<KeywordLeaderboardEntry> for KeywordEntry {
+    fn from(value: KeywordLeaderboardEntry) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id.0,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            score: value.total,
+            ranking: value.ranking,
+        }
+    }
+}
+
+impl From<UserSearchMatch> for UserSearchResult {
+    fn from(value: UserSearchMatch) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id.0,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            total_as_chatter: value.total,
+            total_as_broadcaster: value.channel_total,
+            similarity: value.similarity,
+        }
+    }
+}
+
+impl From<ChatterSearchResult> for SearchResult {
+    fn from(value: ChatterSearchResult) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            total_as_chatter: value.total,
+            ranking: value.ranking,
+            similarity: value.similarity_score,
+        }
+    }
+}
+
+impl From<ChannelReplies> for BotChannel {
+    fn from(value: ChannelReplies) -> Self {
+        Self {
+            profile: Profile {
+                id: value.id.0,
+                login: value.login,
+                name: value.name,
+                color: value.color,
+                image: value.image,
+            },
+            replies_enabled: value.enabled,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::prelude::{ChannelId, ChatterId};
+
+    #[test]
+    fn channel_entry_field_names_are_stable() {
+        let entry = ChannelEntry::from(ChannelLeaderboardEntry {
+            id: ChannelId(String::from("1")),
+            name: String::from("Pea"),
+            login: String::from("pea"),
+            color: String::from("#000000"),
+            image: String::new(),
+            total_chatter: 2,
+            total_channel: 10,
+            ranking: 1,
+            chatter_scores: vec![ChatterScoreSummary {
+                channel_id: ChannelId(String::from("1")),
+                chatter_id: ChatterId(String::from("2")),
+                chatter_login: String::from("chatter"),
+                chatter_name: String::from("Chatter"),
+                chatter_color: String::from("#FFFFFF"),
+                chatter_image: String::new(),
+                score: 10,
+                ranking: 1,
+            }],
+            total_scores: 1,
+        });
+
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["id"], "1");
+        assert_eq!(json["login"], "pea");
+        assert_eq!(json["total_as_broadcaster"], 10);
+        assert_eq!(json["total_as_chatter"], 2);
+        assert_eq!(json["chatter_count"], 1);
+        assert_eq!(json["chatter_scores"][0]["chatter"]["login"], "chatter");
+        assert_eq!(json["chatter_scores"][0]["score"], 10);
+        assert_eq!(json["live"], false);
+    }
+}
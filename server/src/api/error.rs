@@ -0,0 +1,322 @@
+//! The error type returned by API and webhook handlers.
+//!
+//! Errors are rendered as `{ code, message, request_id }`, where `code` is a stable identifier
+//! clients can match on and `message` is safe to show to users - the underlying error is only
+//! logged, never returned.
+
+use std::sync::Arc;
+
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::oneshot::{self, Sender};
+use tokio::task::JoinError;
+
+use crate::api::auth::AuthError;
+use crate::api::middleware::access_log;
+use crate::api::webhook::WebhookError;
+use crate::db::PgError;
+use crate::db::redis::redis_pool::RedisErr;
+use crate::db::store::StoreError;
+use crate::irc::ConnectionClientError;
+use crate::util::archive::ArchiveError;
+use crate::util::avatar::AvatarError;
+use crate::util::channel::ChannelError;
+use crate::util::helix::HelixErr;
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error(transparent)]
+    JoinError(#[from] JoinError),
+
+    #[error(transparent)]
+    IrcClientError(#[from] ConnectionClientError),
+
+    #[error(transparent)]
+    QueryError(#[from] PgError),
+
+    #[error(transparent)]
+    ChannelFetch(#[from] ChannelError),
+
+    #[error("{0}")]
+    GenericStatusCode(StatusCode),
+
+    #[error("bad request: {0}")]
+    BadRequest(String),
+
+    #[allow(dead_code)]
+    #[error("TOTP validation failure: {0}")]
+    ValidationError(String),
+
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    HelixError(#[from] HelixErr),
+
+    #[error(transparent)]
+    WebhookError(#[from] WebhookError),
+
+    #[error(transparent)]
+    AuthError(#[from] AuthError),
+
+    #[error(transparent)]
+    SignalError(#[from] mpsc::error::SendError<()>),
+
+    #[error(transparent)]
+    RedisError(#[from] RedisErr),
+
+    #[error(transparent)]
+    SqlxError(#[from] sqlx::error::Error),
+
+    #[error(transparent)]
+    ArchiveError(#[from] ArchiveError),
+
+    #[cfg(feature = "profiling")]
+    #[error(transparent)]
+    ProfilingError(#[from] crate::util::profiling::ProfilingError),
+
+    #[error("invalid login or id '{0}'")]
+    InvalidUser(String),
+
+    #[error(transparent)]
+    TryRecvError(#[from] oneshot::error::TryRecvError),
+
+    #[error(transparent)]
+    ChannelRecvError(#[from] oneshot::error::RecvError),
+
+    #[error(transparent)]
+    ChannelSendError(#[from] SendError<(String, Sender<Vec<String>>)>),
+}
+
+impl From<StoreError> for ApiError {
+    fn from(value: StoreError) -> Self {
+        match value {
+            StoreError::Sqlx(e) => Self::SqlxError(e),
+            StoreError::Redis(e) => Self::RedisError(e),
+            StoreError::Unavailable => Self::GenericStatusCode(StatusCode::SERVICE_UNAVAILABLE),
+        }
+    }
+}
+
+impl ApiError {
+    /// The Helix error this error wraps, if any.
+    fn helix(&self) -> Option<&HelixErr> {
+        match self {
+            Self::HelixError(e)
+            | Self::WebhookError(WebhookError::HelixError(e))
+            | Self::QueryError(PgError::HelixError(e))
+            | Self::ChannelFetch(
+                ChannelError::Helix(e) | ChannelError::Pg(PgError::HelixError(e)),
+            ) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// The database error this error wraps, if any.
+    fn sqlx(&self) -> Option<&sqlx::Error> {
+        match self {
+            Self::SqlxError(e)
+            | Self::QueryError(PgError::SqlxError(e))
+            | Self::WebhookError(WebhookError::SqlxError(e))
+            | Self::WebhookError(WebhookError::QueryError(PgError::SqlxError(e)))
+            | Self::AuthError(AuthError::SqlxError(e))
+            | Self::ArchiveError(ArchiveError::Sqlx(e))
+            | Self::ChannelFetch(
+                ChannelError::SqlxError(e) | ChannelError::Pg(PgError::SqlxError(e)),
+            ) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn is_irc(&self) -> bool {
+        matches!(
+            self,
+            Self::IrcClientError(_)
+                | Self::SignalError(_)
+                | Self::WebhookError(WebhookError::IrcError(_))
+        )
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        if let Some(e) = self.helix() {
+            return e.status_code();
+        }
+
+        if let Some(e) = self.sqlx() {
+            return match e {
+                sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+                sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => {
+                    StatusCode::SERVICE_UNAVAILABLE
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+        }
+
+        match self {
+            Self::InvalidUser(_) => StatusCode::NOT_FOUND,
+            Self::BadRequest(_)
+            | Self::WebhookError(
+                WebhookError::MessageTypeParseError(_) | WebhookError::UnknownSubscriptionType(_),
+            ) => StatusCode::BAD_REQUEST,
+            Self::ValidationError(_) => StatusCode::UNAUTHORIZED,
+            Self::AuthError(e) => e.status_code(),
+            Self::GenericStatusCode(s) => *s,
+            Self::RedisError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            e if e.is_irc() => StatusCode::SERVICE_UNAVAILABLE,
+            Self::WebhookError(WebhookError::BudgetExhausted { .. }) => StatusCode::CONFLICT,
+            #[cfg(feature = "profiling")]
+            Self::ProfilingError(crate::util::profiling::ProfilingError::InProgress) => {
+                StatusCode::CONFLICT
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// A stable, machine-readable identifier for the kind of error.
+    pub fn code(&self) -> &'static str {
+        match self.status_code() {
+            StatusCode::BAD_REQUEST => return "bad_request",
+            StatusCode::UNAUTHORIZED => return "unauthorized",
+            StatusCode::FORBIDDEN => return "forbidden",
+            StatusCode::NOT_FOUND => return "not_found",
+            StatusCode::CONFLICT => return "conflict",
+            StatusCode::TOO_MANY_REQUESTS => return "rate_limited",
+            _ => (),
+        }
+
+        if self.helix().is_some() {
+            "twitch_error"
+        } else if self.sqlx().is_some() || matches!(self, Self::QueryError(_)) {
+            "database_error"
+        } else if matches!(self, Self::RedisError(_)) {
+            "cache_error"
+        } else if self.is_irc() {
+            "irc_error"
+        } else {
+            "internal_error"
+        }
+    }
+
+    pub(crate) fn client_message(&self) -> String {
+        if let Some(e) = self.helix() {
+            return e.client_message();
+        }
+
+        match self {
+            Self::InvalidUser(id) => format!("unknown user '{id}'"),
+            Self::BadRequest(message) => message.clone(),
+            Self::WebhookError(
+                e @ (WebhookError::MessageTypeParseError(_)
+                | WebhookError::UnknownSubscriptionType(_)
+                | WebhookError::BudgetExhausted { .. }),
+            ) => e.to_string(),
+            Self::AuthError(e) if e.status_code().is_client_error() => e.to_string(),
+            Self::GenericStatusCode(s) => s
+                .canonical_reason()
+                .unwrap_or("unknown error")
+                .to_lowercase(),
+            #[cfg(feature = "profiling")]
+            Self::ProfilingError(e @ crate::util::profiling::ProfilingError::InProgress) => {
+                e.to_string()
+            }
+            e => match (e.code(), e.status_code()) {
+                ("not_found", _) => "not found".into(),
+                ("database_error", StatusCode::SERVICE_UNAVAILABLE) => {
+                    "database unavailable".into()
+                }
+                ("cache_error", _) => "cache unavailable".into(),
+                ("irc_error", _) => "irc connection unavailable".into(),
+                _ => "internal server error".into(),
+            },
+        }
+    }
+}
+
+impl From<AvatarError> for ApiError {
+    fn from(value: AvatarError) -> Self {
+        match value {
+            AvatarError::Sqlx(e) => Self::SqlxError(e),
+            AvatarError::Helix(e) => Self::HelixError(e),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+    request_id: Option<String>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.client_message(),
+            request_id: access_log::request_id(),
+        };
+
+        let mut response = (status, Json(body)).into_response();
+
+        // logged with the request's span by the trace layer
+        response.extensions_mut().insert(Arc::new(self));
+        response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn errors_map_to_consistent_statuses_and_codes() {
+        let cases = [
+            (
+                ApiError::InvalidUser("foo".into()),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                ApiError::SqlxError(sqlx::Error::RowNotFound),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                ApiError::QueryError(PgError::SqlxError(sqlx::Error::PoolTimedOut)),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "database_error",
+            ),
+            (
+                ApiError::HelixError(HelixErr::Unavailable),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "twitch_error",
+            ),
+            (
+                ApiError::GenericStatusCode(StatusCode::BAD_REQUEST),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+            (
+                ApiError::SerdeJsonError(serde_json::from_str::<u8>("").unwrap_err()),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+            ),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!(error.status_code(), status, "{error:?}");
+            assert_eq!(error.code(), code, "{error:?}");
+        }
+
+        assert_eq!(
+            ApiError::GenericStatusCode(StatusCode::NOT_FOUND).client_message(),
+            "not found"
+        );
+    }
+}
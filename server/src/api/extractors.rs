@@ -1,5 +1,14 @@
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 
+use crate::db::models::channel::{CountMode, ReplyMode};
+use crate::db::models::integration::IntegrationEvent;
+use crate::db::models::keyword::KeywordKind;
+use crate::db::models::leaderboard::Period;
+use crate::db::models::milestone::MilestoneKind;
+use crate::db::models::settings::ChannelSettings;
+use crate::util::export::ExportFormat;
+
 /// for `update_chatter_in_cache`
 #[derive(Debug, Deserialize)]
 pub struct AliasUpdateRequest {
@@ -13,6 +22,162 @@ pub struct UserIdRequest {
     pub id: String,
 }
 
+/// for `update_channel_timezone`; `timezone` is an IANA name (e.g. `Australia/Sydney`)
+#[derive(Debug, Deserialize)]
+pub struct ChannelTimezoneRequest {
+    pub id: String,
+    pub timezone: String,
+}
+
+/// for `update_channel_count_mode`; `max_occurrences` is left as-is if unset
+#[derive(Debug, Deserialize)]
+pub struct ChannelCountModeRequest {
+    pub id: String,
+    pub mode: CountMode,
+    pub max_occurrences: Option<i16>,
+}
+
+/// for `update_channel_reply_mode`
+#[derive(Debug, Deserialize)]
+pub struct ChannelReplyModeRequest {
+    pub id: String,
+    pub mode: ReplyMode,
+}
+
+/// for `update_channel_settings`; replaces every setting
+#[derive(Debug, Deserialize)]
+pub struct ChannelSettingsRequest {
+    pub id: String,
+    #[serde(flatten)]
+    pub settings: ChannelSettings,
+}
+
+/// for `update_channel_paused`
+#[derive(Debug, Deserialize)]
+pub struct ChannelPauseRequest {
+    pub id: String,
+    pub paused: bool,
+}
+
+/// for `dashboard::update_paused`, which always acts on the session's channel
+#[derive(Debug, Deserialize)]
+pub struct PauseRequest {
+    pub paused: bool,
+}
+
+/// for `dashboard::blacklist_chatter`
+#[derive(Debug, Deserialize)]
+pub struct BlacklistRequest {
+    pub login: String,
+}
+
+/// for `adjust_score`; exactly one of `delta` and `value` must be set, and `keyword` defaults to
+/// the first tracked keyword
+#[derive(Debug, Deserialize)]
+pub struct ScoreAdjustRequest {
+    pub chatter_id: String,
+    pub channel_id: String,
+    #[serde(default)]
+    pub keyword: Option<String>,
+    #[serde(default)]
+    pub delta: Option<i64>,
+    #[serde(default)]
+    pub value: Option<i64>,
+    pub reason: String,
+}
+
+/// for `score_history`; `to` defaults to today, and scores can be limited to a channel and/or
+/// chatter
+#[derive(Debug, Deserialize)]
+pub struct ScoreHistoryQuery {
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    #[serde(default)]
+    pub chatter_id: Option<String>,
+    pub from: NaiveDate,
+    #[serde(default)]
+    pub to: Option<NaiveDate>,
+}
+
+/// for `update_feature_flag`; replaces the flag's state. Enabled flags are on for every channel
+/// unless `rollout_percent` is set
+#[derive(Debug, Deserialize)]
+pub struct FeatureFlagRequest {
+    pub enabled: bool,
+    #[serde(default = "default_rollout_percent")]
+    pub rollout_percent: i16,
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+fn default_rollout_percent() -> i16 {
+    100
+}
+
+/// for `create_milestone`; milestones without a `channel_id` apply to every channel
+#[derive(Debug, Deserialize)]
+pub struct MilestoneRequest {
+    #[serde(default)]
+    pub channel_id: Option<String>,
+    pub kind: MilestoneKind,
+    pub threshold: i64,
+    #[serde(default)]
+    pub repeating: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// for `create_keyword` and `update_keyword`; `emote_id` is only accepted for emote keywords, which
+/// are matched by name when it's unset
+#[derive(Debug, Deserialize)]
+pub struct KeywordRequest {
+    pub word: String,
+    #[serde(default)]
+    pub kind: KeywordKind,
+    #[serde(default)]
+    pub emote_id: Option<String>,
+}
+
+/// for `update_discord_webhook`; webhooks are enabled unless `enabled` is set
+#[derive(Debug, Deserialize)]
+pub struct DiscordWebhookRequest {
+    pub channel_id: String,
+    pub event: IntegrationEvent,
+    pub url: String,
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// for `update_chatter_note`
+#[derive(Debug, Deserialize)]
+pub struct ChatterNoteRequest {
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+/// for `delete_chatter`; without `confirm`, a confirmation token is issued instead of deleting
+/// anything. `export` returns the chatter's data along with the confirmed deletion
+#[derive(Debug, Deserialize)]
+pub struct ChatterDeletionQuery {
+    #[serde(default)]
+    pub confirm: Option<String>,
+    #[serde(default)]
+    pub export: bool,
+}
+
+/// for keyword lookups; text keywords unless `kind=emote`
+#[derive(Debug, Deserialize)]
+pub struct KeywordKindQuery {
+    #[serde(default)]
+    pub kind: KeywordKind,
+}
+
 /// for anything that requires chatter/channel login input
 #[derive(Debug, Deserialize)]
 pub struct UserLoginRequest {
@@ -25,6 +190,79 @@ pub struct UserRequest {
     pub user: String,
 }
 
+/// for `search`; `q` is a login or display name, or the start of one
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+/// for `chatter::compare`; `users` is two comma-separated logins, compared on the `channel`
+/// (a broadcaster's login) if one is given
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    pub users: String,
+    #[serde(default)]
+    pub channel: Option<String>,
+}
+
+/// for `onboarding::twitch_callback`; Twitch sets either `code` or `error`
+#[derive(Debug, Deserialize)]
+pub struct OAuthCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// for `channel::export`
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// for `channel::timeline`; the number of hours back from the current hour, capped to a week
+#[derive(Debug, Deserialize)]
+pub struct TimelineQuery {
+    #[serde(default = "default_timeline_hours")]
+    pub hours: u32,
+}
+
+fn default_timeline_hours() -> u32 {
+    24
+}
+
+/// for `channel::increment`; `chatter` is a login, defaulting to the broadcaster, and `keyword`
+/// defaults to the first tracked keyword
+#[derive(Debug, Deserialize)]
+pub struct IncrementRequest {
+    pub chatter: Option<String>,
+    pub keyword: String,
+    #[serde(default = "default_increment_count")]
+    pub count: u32,
+}
+
+fn default_increment_count() -> u32 {
+    1
+}
+
+/// for `channel::digests`; the number of most recent digests, capped to a month
+#[derive(Debug, Deserialize)]
+pub struct DigestQuery {
+    #[serde(default = "default_digest_days")]
+    pub days: u32,
+}
+
+fn default_digest_days() -> u32 {
+    7
+}
+
+/// for `overlay::overlay`; an earlier response's `ETag` to wait for a change from
+#[derive(Debug, Deserialize)]
+pub struct OverlayQuery {
+    pub since: Option<String>,
+}
+
 /// for `totp_compare`
 #[derive(Debug, Deserialize)]
 pub struct TOTPRequest {
@@ -38,6 +276,13 @@ pub enum ScoreVariant {
     Chatter,
 }
 
+/// for leaderboards; all-time unless `period` is set
+#[derive(Debug, Deserialize)]
+pub struct PeriodQuery {
+    #[serde(default)]
+    pub period: Period,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ScoreWindowQuery {
     pub variant: ScoreVariant,
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use sqlx::{Pool, Postgres};
+
+use crate::api::graphql::field_error;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardRow, ChannelScoreSummary};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardRow, ChatterScoreSummary};
+use crate::db::models::stream::StreamStatus;
+use crate::db::prelude::{LeaderboardRepository, StreamStatusRepository};
+use crate::db::repositories::leaderboard::ScorePagination;
+
+/// Batches the lookups made while resolving a single query; created per request, so nothing is
+/// cached between requests.
+pub type Loaders = DataLoader<DbLoader>;
+
+pub fn loaders(pool: &'static Pool<Postgres>) -> Loaders {
+    DataLoader::new(DbLoader { pool }, tokio::spawn)
+}
+
+pub struct DbLoader {
+    pool: &'static Pool<Postgres>,
+}
+
+/// A channel's stream status.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamOf(pub ChannelId);
+
+/// Every score a chatter has, one per channel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelScoresOf(pub ChatterId);
+
+/// A page of the chatters scoring in a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChatterScoresOf {
+    pub channel_id: ChannelId,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl Loader<ChatterId> for DbLoader {
+    type Value = ChatterLeaderboardRow;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ChatterId],
+    ) -> Result<HashMap<ChatterId, Self::Value>, Self::Error> {
+        let rows = LeaderboardRepository::new(self.pool)
+            .get_chatter_rows(keys)
+            .await
+            .map_err(field_error)?;
+
+        Ok(rows.into_iter().map(|row| (row.id.clone(), row)).collect())
+    }
+}
+
+impl Loader<ChannelId> for DbLoader {
+    type Value = ChannelLeaderboardRow;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ChannelId],
+    ) -> Result<HashMap<ChannelId, Self::Value>, Self::Error> {
+        let rows = LeaderboardRepository::new(self.pool)
+            .get_channel_rows(keys)
+            .await
+            .map_err(field_error)?;
+
+        Ok(rows.into_iter().map(|row| (row.id.clone(), row)).collect())
+    }
+}
+
+impl Loader<StreamOf> for DbLoader {
+    type Value = StreamStatus;
+    type Error = async_graphql::Error;
+
+    async fn load(&self, keys: &[StreamOf]) -> Result<HashMap<StreamOf, Self::Value>, Self::Error> {
+        let ids: Vec<ChannelId> = keys.iter().map(|key| key.0.clone()).collect();
+        let statuses = StreamStatusRepository::new(self.pool)
+            .get_by_channels(&ids)
+            .await
+            .map_err(field_error)?;
+
+        Ok(statuses
+            .into_iter()
+            .map(|status| (StreamOf(status.channel_id.clone()), status))
+            .collect())
+    }
+}
+
+impl Loader<ChannelScoresOf> for DbLoader {
+    type Value = Vec<ChannelScoreSummary>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ChannelScoresOf],
+    ) -> Result<HashMap<ChannelScoresOf, Self::Value>, Self::Error> {
+        let ids: Vec<ChatterId> = keys.iter().map(|key| key.0.clone()).collect();
+        let scores = LeaderboardRepository::new(self.pool)
+            .get_channel_scores_batch(&ids)
+            .await
+            .map_err(field_error)?;
+
+        // chatters without any scores still get an (empty) entry
+        let mut loaded: HashMap<ChannelScoresOf, Self::Value> =
+            keys.iter().map(|key| (key.clone(), Vec::new())).collect();
+        for score in scores {
+            if let Some(scores) = loaded.get_mut(&ChannelScoresOf(score.chatter_id.clone())) {
+                scores.push(score);
+            }
+        }
+
+        Ok(loaded)
+    }
+}
+
+impl Loader<ChatterScoresOf> for DbLoader {
+    type Value = Vec<ChatterScoreSummary>;
+    type Error = async_graphql::Error;
+
+    async fn load(
+        &self,
+        keys: &[ChatterScoresOf],
+    ) -> Result<HashMap<ChatterScoresOf, Self::Value>, Self::Error> {
+        // pagination applies to every channel in a batch, so channels are batched per page
+        let mut pages: HashMap<(i64, i64), Vec<ChannelId>> = HashMap::new();
+        for key in keys {
+            pages
+                .entry((key.limit, key.offset))
+                .or_default()
+                .push(key.channel_id.clone());
+        }
+
+        let repository = LeaderboardRepository::new(self.pool);
+        let mut loaded: HashMap<ChatterScoresOf, Self::Value> =
+            keys.iter().map(|key| (key.clone(), Vec::new())).collect();
+        for ((limit, offset), ids) in pages {
+            let scores = repository
+                .get_chatter_scores_batch(&ids, &ScorePagination::new(limit, offset))
+                .await
+                .map_err(field_error)?;
+
+            for score in scores {
+                let key = ChatterScoresOf {
+                    channel_id: score.channel_id.clone(),
+                    limit,
+                    offset,
+                };
+                if let Some(scores) = loaded.get_mut(&key) {
+                    scores.push(score);
+                }
+            }
+        }
+
+        Ok(loaded)
+    }
+}
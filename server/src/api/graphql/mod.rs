@@ -0,0 +1,224 @@
+//! The GraphQL endpoint at `/api/graphql`, for clients that would rather choose the shape of the
+//! data they get back than combine several `/api/v1` responses.
+//!
+//! Nested fields (a chatter's channels, a channel's chatters and stream) are resolved through
+//! per-request dataloaders, so a page of entries costs one query per nested field rather than one
+//! per entry. Queries are limited in depth and complexity, where each list counts once per item
+//! it's allowed to return, so they can't fan out indefinitely.
+
+pub mod loader;
+pub mod types;
+
+use std::sync::Arc;
+
+use async_graphql::{Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object, Schema};
+use axum::extract::State;
+use axum::{Extension, Json};
+use tracing::instrument;
+
+use crate::api::error::ApiError;
+use crate::api::graphql::loader::{Loaders, loaders};
+use crate::api::graphql::types::{Channel, Chatter, Page, PeriodArg};
+use crate::api::server::AppState;
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::leaderboard::Period;
+use crate::db::prelude::{ChatterRepository, PeriodRepository, Repository};
+use crate::db::repositories::leaderboard::ScorePagination;
+
+pub type PeaSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const MAX_DEPTH: usize = 10;
+const MAX_COMPLEXITY: usize = 2_500;
+
+pub fn schema() -> PeaSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Executes a GraphQL query over chatters, channels, their scores and stream status
+///
+/// # Methods
+///
+/// * POST
+///
+///     ```http
+///     /api/graphql
+///     ```
+///
+///     Body:
+///
+///     - `query`:          the GraphQL document to execute.
+///     - `operationName`:  the operation to run, if `query` has more than one.
+///     - `variables`:      values for the operation's variables.
+///
+///     Always responds with `200`; errors (including those from rejected queries) are reported in
+///     the response's `errors`, each with the `status` the equivalent REST request would have had.
+#[instrument(skip_all)]
+pub async fn graphql(
+    State(state): State<Arc<AppState>>,
+    Extension(schema): Extension<PeaSchema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = request.data(loaders(state.replicas.reader())).data(state);
+
+    Json(schema.execute(request).await)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A single chatter by ID or login, with the ID taking precedence if both are given.
+    async fn chatter(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<String>,
+        login: Option<String>,
+    ) -> async_graphql::Result<Option<Chatter>> {
+        let Some(id) = user_id(ctx, id, login).await? else {
+            return Ok(None);
+        };
+
+        let row = ctx.data::<Loaders>()?.load_one(ChatterId(id)).await?;
+        Ok(row.map(Chatter::from))
+    }
+
+    /// A single channel by ID or login, with the ID taking precedence if both are given.
+    async fn channel(
+        &self,
+        ctx: &Context<'_>,
+        id: Option<String>,
+        login: Option<String>,
+    ) -> async_graphql::Result<Option<Channel>> {
+        let Some(id) = user_id(ctx, id, login).await? else {
+            return Ok(None);
+        };
+
+        let row = ctx.data::<Loaders>()?.load_one(ChannelId(id)).await?;
+        Ok(row.map(Channel::from))
+    }
+
+    /// The chatter leaderboard.
+    #[graphql(complexity = "limit as usize * child_complexity")]
+    async fn chatters(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] period: PeriodArg,
+        #[graphql(default = 25, validator(minimum = 1, maximum = 100))] limit: i64,
+        #[graphql(default, validator(minimum = 0))] page: i64,
+    ) -> async_graphql::Result<Page<Chatter>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let offset = page * limit;
+
+        let segment = match Period::from(period) {
+            Period::All => state.scores.get_chatter_leaderboard(limit, offset).await,
+            period => PeriodRepository::new(state.replicas.reader())
+                .get_chatter_leaderboard(period, limit, offset)
+                .await
+                .map_err(Into::into),
+        }
+        .map_err(field_error)?;
+
+        Ok(segment.into())
+    }
+
+    /// The channel leaderboard.
+    #[graphql(complexity = "limit as usize * child_complexity")]
+    async fn channels(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] period: PeriodArg,
+        #[graphql(default = 25, validator(minimum = 1, maximum = 100))] limit: i64,
+        #[graphql(default, validator(minimum = 0))] page: i64,
+    ) -> async_graphql::Result<Page<Channel>> {
+        let state = ctx.data::<Arc<AppState>>()?;
+        let offset = page * limit;
+
+        // chatter scores are resolved separately, so none are fetched with the page
+        let segment = match Period::from(period) {
+            Period::All => {
+                state
+                    .scores
+                    .get_channel_leaderboard(limit, offset, &ScorePagination::new(0, 0))
+                    .await
+            }
+            period => PeriodRepository::new(state.replicas.reader())
+                .get_channel_leaderboard(period, limit, offset)
+                .await
+                .map_err(Into::into),
+        }
+        .map_err(field_error)?;
+
+        Ok(segment.into())
+    }
+}
+
+/// Resolves a user's ID from either an ID or a login; unknown logins resolve to `None`.
+async fn user_id(
+    ctx: &Context<'_>,
+    id: Option<String>,
+    login: Option<String>,
+) -> async_graphql::Result<Option<String>> {
+    match (id, login) {
+        (Some(id), _) => Ok(Some(id)),
+        (None, Some(login)) => {
+            let state = ctx.data::<Arc<AppState>>()?;
+            match ChatterRepository::new(state.replicas.reader())
+                .get_by_login(&login.to_lowercase())
+                .await
+            {
+                Ok(chatter) => Ok(Some(chatter.id.0)),
+                Err(sqlx::Error::RowNotFound) => Ok(None),
+                Err(e) => Err(field_error(e)),
+            }
+        }
+        (None, None) => Err(field_error(ApiError::GenericStatusCode(
+            http::StatusCode::BAD_REQUEST,
+        ))),
+    }
+}
+
+/// Reports an error as a field error with the message and status a REST response would have had;
+/// as with REST responses, the underlying error is only logged.
+pub(crate) fn field_error(e: impl Into<ApiError>) -> async_graphql::Error {
+    let e = e.into();
+    let status = e.status_code();
+    if status.is_server_error() {
+        tracing::error!(error = ?e, "graphql field failed");
+    }
+
+    async_graphql::Error::new(e.client_message())
+        .extend_with(|_, extensions| extensions.set("status", status.as_u16()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn field_errors_match_rest_responses() {
+        let e = field_error(ApiError::InvalidUser("foo".to_string()));
+        assert_eq!(e.message, "unknown user 'foo'");
+        assert_eq!(
+            e.extensions.and_then(|ext| ext.get("status").cloned()),
+            Some(async_graphql::Value::from(404))
+        );
+
+        let e = field_error(sqlx::Error::PoolClosed);
+        assert_eq!(e.message, "database unavailable");
+    }
+
+    #[tokio::test]
+    async fn oversized_queries_are_rejected() {
+        let schema = schema();
+        let response = schema
+            .execute("{ chatters(limit: 100) { items { channelScores(limit: 100) { score } } } }")
+            .await;
+
+        assert!(!response.errors.is_empty());
+        assert!(response.errors[0].message.contains("complex"));
+    }
+}
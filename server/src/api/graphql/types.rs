@@ -0,0 +1,280 @@
+use async_graphql::{ComplexObject, Context, Enum, OutputType, Result, SimpleObject};
+use chrono::NaiveDateTime;
+
+use crate::api::graphql::loader::{ChannelScoresOf, ChatterScoresOf, Loaders, StreamOf};
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::ChannelScoreSummary;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry, ChannelLeaderboardRow};
+use crate::db::models::chatter::ChatterScoreSummary;
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry, ChatterLeaderboardRow};
+use crate::db::models::stream::StreamStatus;
+
+/// The span a leaderboard's scores are counted over.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Enum)]
+#[graphql(name = "Period", remote = "crate::db::models::leaderboard::Period")]
+pub enum PeriodArg {
+    Day,
+    Week,
+    Month,
+    /// The running totals, rather than a snapshot
+    #[default]
+    All,
+}
+
+#[derive(Debug, SimpleObject)]
+#[graphql(concrete(name = "ChatterPage", params(Chatter)))]
+#[graphql(concrete(name = "ChannelPage", params(Channel)))]
+pub struct Page<T: OutputType> {
+    pub items: Vec<T>,
+    pub page: i64,
+    pub page_size: i64,
+    pub total_items: i64,
+    pub total_pages: i64,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Chatter {
+    pub id: String,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    /// Scores earned while chatting, across all channels
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    /// Number of channels the chatter has a score in
+    pub channel_count: i64,
+}
+
+#[ComplexObject]
+impl Chatter {
+    /// The chatter's score in each channel, highest first.
+    #[graphql(complexity = "limit as usize * child_complexity")]
+    async fn channel_scores(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 25, validator(minimum = 1, maximum = 100))] limit: i64,
+        #[graphql(default, validator(minimum = 0))] page: i64,
+    ) -> Result<Vec<ChannelScore>> {
+        let scores = ctx
+            .data::<Loaders>()?
+            .load_one(ChannelScoresOf(ChatterId(self.id.clone())))
+            .await?
+            .unwrap_or_default();
+
+        Ok(scores
+            .into_iter()
+            .skip((page * limit) as usize)
+            .take(limit as usize)
+            .map(ChannelScore::from)
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct Channel {
+    pub id: String,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    /// Scores earned by chatters in the channel
+    pub total_as_broadcaster: i64,
+    /// Scores the broadcaster earned while chatting, across all channels
+    pub total_as_chatter: i64,
+    pub ranking: i64,
+    /// Number of chatters with a score in the channel
+    pub chatter_count: i64,
+}
+
+#[ComplexObject]
+impl Channel {
+    /// Whether the channel was live as of the last stream status refresh.
+    async fn live(&self, ctx: &Context<'_>) -> Result<bool> {
+        let status = stream_status(ctx, &self.id).await?;
+        Ok(status.is_some_and(|status| status.is_live))
+    }
+
+    /// The channel's stream as of the last stream status refresh, if it's been refreshed at all.
+    async fn stream(&self, ctx: &Context<'_>) -> Result<Option<Stream>> {
+        Ok(stream_status(ctx, &self.id).await?.map(Stream::from))
+    }
+
+    /// The chatters scoring in the channel, highest first.
+    #[graphql(complexity = "limit as usize * child_complexity")]
+    async fn chatter_scores(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 25, validator(minimum = 1, maximum = 100))] limit: i64,
+        #[graphql(default, validator(minimum = 0))] page: i64,
+    ) -> Result<Vec<ChatterScore>> {
+        let key = ChatterScoresOf {
+            channel_id: ChannelId(self.id.clone()),
+            limit,
+            offset: page * limit,
+        };
+        let scores = ctx.data::<Loaders>()?.load_one(key).await?;
+
+        Ok(scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(ChatterScore::from)
+            .collect())
+    }
+}
+
+/// A channel's live state as of the last refresh from Helix.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct Stream {
+    pub is_live: bool,
+    /// Zero while offline
+    pub viewer_count: i64,
+    /// Empty while offline
+    pub game: String,
+    pub title: String,
+    pub started_at: Option<NaiveDateTime>,
+    pub updated_at: NaiveDateTime,
+    /// Counting is paused for the channel, whether or not it's live
+    pub paused: bool,
+}
+
+/// A chatter's score in a single channel.
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct ChannelScore {
+    #[graphql(skip)]
+    pub channel_id: ChannelId,
+    pub score: i64,
+    pub ranking: i64,
+}
+
+#[ComplexObject]
+impl ChannelScore {
+    async fn channel(&self, ctx: &Context<'_>) -> Result<Option<Channel>> {
+        let row = ctx
+            .data::<Loaders>()?
+            .load_one(self.channel_id.clone())
+            .await?;
+
+        Ok(row.map(Channel::from))
+    }
+}
+
+/// A single chatter's score in a channel.
+#[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
+pub struct ChatterScore {
+    #[graphql(skip)]
+    pub chatter_id: ChatterId,
+    pub score: i64,
+    pub ranking: i64,
+}
+
+#[ComplexObject]
+impl ChatterScore {
+    async fn chatter(&self, ctx: &Context<'_>) -> Result<Option<Chatter>> {
+        let row = ctx
+            .data::<Loaders>()?
+            .load_one(self.chatter_id.clone())
+            .await?;
+
+        Ok(row.map(Chatter::from))
+    }
+}
+
+async fn stream_status(ctx: &Context<'_>, channel_id: &str) -> Result<Option<StreamStatus>> {
+    ctx.data::<Loaders>()?
+        .load_one(StreamOf(ChannelId(channel_id.to_string())))
+        .await
+}
+
+impl<M, T: OutputType + From<M>> From<PaginatedResponse<M>> for Page<T> {
+    fn from(value: PaginatedResponse<M>) -> Self {
+        Self {
+            items: value.items.into_iter().map(T::from).collect(),
+            page: value.page,
+            page_size: value.page_size,
+            total_items: value.total_items,
+            total_pages: value.total_pages,
+        }
+    }
+}
+
+impl From<ChatterLeaderboardEntry> for Chatter {
+    fn from(value: ChatterLeaderboardEntry) -> Self {
+        Self {
+            id: value.id.0,
+            login: value.login,
+            name: value.name,
+            color: value.color,
+            image: value.image,
+            total_as_chatter: value.total,
+            ranking: value.ranking,
+            channel_count: value.total_scores,
+        }
+    }
+}
+
+impl From<ChatterLeaderboardRow> for Chatter {
+    fn from(value: ChatterLeaderboardRow) -> Self {
+        value.into_leaderboard_entry(Vec::new()).into()
+    }
+}
+
+impl From<ChannelLeaderboardEntry> for Channel {
+    fn from(value: ChannelLeaderboardEntry) -> Self {
+        Self {
+            id: value.id.0,
+            login: value.login,
+            name: value.name,
+            color: value.color,
+            image: value.image,
+            total_as_broadcaster: value.total_channel,
+            total_as_chatter: value.total_chatter,
+            ranking: value.ranking,
+            chatter_count: value.total_scores,
+        }
+    }
+}
+
+impl From<ChannelLeaderboardRow> for Channel {
+    fn from(value: ChannelLeaderboardRow) -> Self {
+        value.into_leaderboard_entry(Vec::new()).into()
+    }
+}
+
+impl From<StreamStatus> for Stream {
+    fn from(value: StreamStatus) -> Self {
+        Self {
+            is_live: value.is_live,
+            viewer_count: value.viewer_count,
+            game: value.game,
+            title: value.title,
+            started_at: value.started_at,
+            updated_at: value.updated_at,
+            paused: value.paused,
+        }
+    }
+}
+
+impl From<ChannelScoreSummary> for ChannelScore {
+    fn from(value: ChannelScoreSummary) -> Self {
+        Self {
+            channel_id: value.channel_id,
+            score: value.score,
+            ranking: value.ranking,
+        }
+    }
+}
+
+impl From<ChatterScoreSummary> for ChatterScore {
+    fn from(value: ChatterScoreSummary) -> Self {
+        Self {
+            chatter_id: value.chatter_id,
+            score: value.score,
+            ranking: value.ranking,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::{Extension, Json};
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::AliasUpdateRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::alias::Alias;
+use crate::db::models::audit::AuditAction;
+use crate::db::prelude::{AliasRepository, Chatter, ChatterRepository, Repository};
+use crate::util::alias::MergeResult;
+use crate::util::helix::Helix;
+
+/// GET
+///
+/// Aliases that have been detected but not yet merged.
+#[instrument(skip(state))]
+pub async fn pending_aliases(State(state): State<Arc<AppState>>) -> ApiResult<Vec<Alias>> {
+    let pending = AliasRepository::new(state.database_pool)
+        .get_pending(None)
+        .await?;
+
+    Ok(ApiResponse::ok(pending))
+}
+
+/// POST
+///
+/// Merges all pending aliases.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn merge_aliases(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<Vec<MergeResult>> {
+    let merged = spawn_protected(async move {
+        let merged = state.scores.merge_aliases(None).await?;
+
+        Audit::new(AuditAction::AliasesMerged)
+            .after(&merged)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(merged)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(merged))
+}
+
+/// PUT
+///
+/// Manually records historic logins for a chatter (e.g. renames that happened before detection
+/// was in place) and merges them.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn repair_aliases(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<AliasUpdateRequest>,
+) -> ApiResult<Vec<MergeResult>> {
+    let merged = spawn_protected(async move {
+        let chatter_repo = ChatterRepository::new(state.database_pool);
+        let current = payload.current.to_lowercase();
+
+        let chatter = match chatter_repo.get_by_login(&current).await {
+            Ok(chatter) => chatter,
+            Err(sqlx::Error::RowNotFound) => {
+                let helix_user = Helix::fetch_users_by_login(vec![current.clone()])
+                    .await?
+                    .into_iter()
+                    .next()
+                    .ok_or(ApiError::InvalidUser(current))?;
+
+                let chatter = Chatter::from(helix_user);
+                chatter_repo.insert(&chatter).await?;
+                chatter
+            }
+            Err(e) => return Err(ApiError::from(e)),
+        };
+
+        let alias_repo = AliasRepository::new(state.database_pool);
+        for login in &payload.historic {
+            alias_repo.insert(&chatter.id, login).await?;
+        }
+
+        let merged = state
+            .scores
+            .merge_aliases(Some(std::slice::from_ref(&chatter.id)))
+            .await?;
+
+        Audit::new(AuditAction::AliasesRepaired)
+            .target(&chatter.id)
+            .before(&payload.historic)
+            .after(&merged)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(merged)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(merged))
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::{AuditAction, AuditEntry, AuditFilter};
+use crate::db::models::{PaginatedResponse, Pagination};
+use crate::db::prelude::AuditRepository;
+
+const MAX_AUDIT_LIMIT: i64 = 100;
+
+/// An admin action to be written to the audit log.
+#[derive(Debug)]
+pub(crate) struct Audit {
+    action: AuditAction,
+    target: Option<String>,
+    before: Option<Value>,
+    after: Option<Value>,
+}
+
+impl Audit {
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            action,
+            target: None,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn target(mut self, target: impl ToString) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    pub fn before(mut self, before: &impl Serialize) -> Self {
+        self.before = serde_json::to_value(before).ok();
+        self
+    }
+
+    pub fn after(mut self, after: &impl Serialize) -> Self {
+        self.after = serde_json::to_value(after).ok();
+        self
+    }
+
+    /// The action has already been taken by the time it's recorded, so a failure to record it is
+    /// logged rather than returned.
+    pub async fn record(self, pool: &'static Pool<Postgres>, actor: &Actor) {
+        let result = AuditRepository::new(pool)
+            .record(
+                actor.audit_actor(),
+                self.action,
+                self.target.as_deref(),
+                self.before.as_ref(),
+                self.after.as_ref(),
+            )
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!(error = ?e, action = self.action.as_str(), "failed to record audit entry");
+        }
+    }
+}
+
+/// GET
+///
+/// Recorded admin actions, newest first. Filtered by any of `action`, `session_id`, `token_id`,
+/// `target`, `since` and `until` (as `YYYY-MM-DDTHH:MM:SS`, UTC).
+///
+/// Parameters:
+///     - `limit`:          number of items on the retrieved page. valid range is `1 <= limit <= 100`
+///     - `page`:           retrieve items starting with `limit * page`
+#[instrument(skip(state))]
+pub async fn audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<AuditFilter>,
+    Query(param): Query<Pagination>,
+) -> ApiResult<PaginatedResponse<AuditEntry>> {
+    let limit = param.limit.clamp(1, MAX_AUDIT_LIMIT);
+    let offset = param.page.max(0) * limit;
+
+    let entries = AuditRepository::new(state.database_pool)
+        .get_page(&filter, limit, offset)
+        .await?;
+
+    Ok(ApiResponse::ok(entries))
+}
@@ -1,25 +1,41 @@
 use std::sync::Arc;
 
-use axum::Json;
-use axum::extract::{Query, State};
+use axum::extract::{Path, Query, State};
+use axum::{Extension, Json};
 use chrono::Utc;
 use http::StatusCode;
 use tracing::instrument;
 
+use crate::api::auth::{Actor, session};
+use crate::api::error::ApiError;
+use crate::api::extractors::{
+    ChannelCountModeRequest, ChannelPauseRequest, ChannelReplyModeRequest, ChannelSettingsRequest,
+    ChannelTimezoneRequest,
+};
 use crate::api::extractors::{UserIdRequest, UserRequest};
+use crate::api::handlers::admin::audit::Audit;
 use crate::api::handlers::spawn_protected;
-use crate::api::server::{ApiResponse, ApiResult, AppState, RouteError};
-use crate::api::webhook::StreamGenericRequestType;
-use crate::db::models::channel::ChannelReplies;
-use crate::db::prelude::{Channel, ChannelId, ChannelRepository};
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::api::webhook::dispatch::{CHANNEL_TOPICS, METRIC_TOPICS, subscribe};
+use crate::api::webhook::metric::stream_metrics_enabled;
+use crate::db::models::audit::AuditAction;
+use crate::db::models::channel::{ChannelCountConfig, ChannelReplies};
+use crate::db::models::settings::ChannelSettings;
+use crate::db::prelude::{Channel, ChannelId, ChannelRepository, HeatmapRepository};
 use crate::db::prelude::{Chatter, ChatterId, ChatterRepository, Repository};
+use crate::db::prelude::{KeywordRepository, SettingsRepository};
 use crate::db::{self, redis};
-use crate::util::helix::Helix;
+use crate::util::helix::{Helix, HelixUser};
+use crate::util::settings::settings;
+use crate::util::shard::sharding;
 use crate::util::{self, is_user_id};
 
 /// PUT
-#[instrument(skip(state))]
-pub async fn update_channel_data(State(state): State<Arc<AppState>>) -> ApiResult<()> {
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_data(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<()> {
     let _guard = state.channel_ids.read().await;
     let channel_ids = _guard.clone();
 
@@ -43,9 +59,13 @@ pub async fn update_channel_data(State(state): State<Arc<AppState>>) -> ApiResul
         // .map(|id| ChatterId(id))
         // .collect::<Vec<_>>();
 
-        util::channel::update_stored_channels(&mut channel_ids, true)
-            .await
-            .map_err(RouteError::from)
+        util::channel::update_stored_channels(&mut channel_ids, true).await?;
+
+        Audit::new(AuditAction::ChannelDataRefreshed)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
     })
     .await?;
 
@@ -56,6 +76,7 @@ pub async fn update_channel_data(State(state): State<Arc<AppState>>) -> ApiResul
 // #[instrument(skip(state))]
 pub async fn new_channel(
     State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
     Json(payload): Json<UserRequest>,
 ) -> ApiResult<String> {
     spawn_protected(async move {
@@ -66,39 +87,58 @@ pub async fn new_channel(
         }
         .into_iter()
         .nth(0)
-        .ok_or(RouteError::GenericStatusCode(
+        .ok_or(ApiError::GenericStatusCode(
             StatusCode::INTERNAL_SERVER_ERROR,
         ))?;
 
-        let chatter = Chatter::from(helix_user);
-        ChatterRepository::new(state.database_pool)
-            .insert(&chatter)
-            .await?;
+        let chatter = add_channel(&state, helix_user, &actor).await?;
+        Ok(ApiResponse::ok(chatter.login))
+    })
+    .await
+}
 
-        let now = Utc::now().naive_utc();
-        let channel = Channel {
-            id: ChannelId(chatter.id.0.clone()),
-            channel_total: 0,
-            created_at: now,
-            updated_at: now,
-        };
+/// Starts tracking a broadcaster's channel: stores it, then (if it's in this instance's shard)
+/// joins its chat and subscribes to its EventSub topics.
+///
+/// Shared by `new_channel` and self-serve onboarding (see `handlers::onboarding`).
+pub(crate) async fn add_channel(
+    state: &AppState,
+    helix_user: HelixUser,
+    actor: &Actor,
+) -> Result<Chatter, ApiError> {
+    let chatter = Chatter::from(helix_user);
+    ChatterRepository::new(state.database_pool)
+        .insert(&chatter)
+        .await?;
+
+    let now = Utc::now().naive_utc();
+    let channel = Channel {
+        id: ChannelId(chatter.id.0.clone()),
+        channel_total: 0,
+        created_at: now,
+        updated_at: now,
+    };
 
-        let chan_repo = ChannelRepository::new(state.database_pool);
-        chan_repo.insert(&channel).await?;
-        chan_repo.new_channel_config(&channel.id).await?;
+    let chan_repo = ChannelRepository::new(state.database_pool);
+    chan_repo.insert(&channel).await?;
+    chan_repo.new_channel_config(&channel.id).await?;
 
-        tracing::debug!("acquiring write locks");
-        let mut _ids = state.channel_ids.write().await;
-        let mut _logins = state.channels.write().await;
+    tracing::debug!("acquiring write locks");
+    let mut _ids = state.channel_ids.write().await;
+    let mut _logins = state.channels.write().await;
 
-        tracing::debug!("acquired - writing new data");
-        _ids.push(chatter.id.0.clone());
-        _logins.push(chatter.login.clone());
+    tracing::debug!("acquired - writing new data");
+    _ids.push(chatter.id.0.clone());
+    _logins.push(chatter.login.clone());
 
-        tracing::debug!("dropping locks");
-        drop(_logins);
-        drop(_ids);
+    tracing::debug!("dropping locks");
+    drop(_logins);
+    drop(_ids);
 
+    // channels in another shard are joined (and subscribed to) by that shard's instances, once
+    // they've restarted and picked the channel up
+    let shard = sharding();
+    if shard.owns(&chatter.id.0) {
         let res = state
             .irc_connection
             // we prefer to clone in the non-blocking task than in the connection handler i imagine
@@ -118,14 +158,40 @@ pub async fn new_channel(
             }
         }
 
-        Helix::create_subscription(channel_id.clone(), StreamGenericRequestType::Online).await?;
-        Helix::create_subscription(channel_id, StreamGenericRequestType::Offline).await?;
+        for notif_type in CHANNEL_TOPICS {
+            subscribe(state.database_pool, channel_id.clone(), notif_type).await?;
+        }
 
-        tracing::info!("channel addition pipeline completed");
+        if stream_metrics_enabled().await {
+            for notif_type in METRIC_TOPICS {
+                // the broadcaster may not have authorized the client, which isn't worth
+                // failing the addition over
+                if let Err(e) = subscribe(state.database_pool, channel_id.clone(), notif_type).await
+                {
+                    tracing::warn!(
+                        error = ?e,
+                        subscription_type = notif_type.as_str(),
+                        "failed to subscribe to stream metrics"
+                    );
+                }
+            }
+        }
+    } else {
+        tracing::info!(
+            login = chatter.login,
+            shard = shard.shard_of(&chatter.id.0),
+            "channel belongs to another shard - not joining"
+        );
+    }
 
-        Ok(ApiResponse::ok(chatter.login))
-    })
-    .await
+    tracing::info!("channel addition pipeline completed");
+    Audit::new(AuditAction::ChannelAdded)
+        .target(&chatter.id)
+        .after(&chatter)
+        .record(state.database_pool, actor)
+        .await;
+
+    Ok(chatter)
 }
 
 /// GET
@@ -146,19 +212,27 @@ pub async fn get_reply_config(
 }
 
 /// PUT
-#[instrument(skip(state))]
+#[instrument(skip(state, actor), fields(actor = %actor))]
 pub async fn update_channel_config(
     State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
     Json(payload): Json<UserIdRequest>,
 ) -> ApiResult<()> {
     spawn_protected(async move {
         let channel_repo = ChannelRepository::new(state.database_pool);
         let id = ChannelId(payload.id);
 
-        channel_repo
-            .update_channel_config(&id)
-            .await
-            .map_err(RouteError::from)
+        let previous = channel_repo.get_reply_config(&id.0).await.ok();
+        channel_repo.update_channel_config(&id).await?;
+
+        Audit::new(AuditAction::ChannelRepliesUpdated)
+            .target(&id)
+            .before(&previous)
+            .after(&channel_repo.get_reply_config(&id.0).await.ok())
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
     })
     .await?;
 
@@ -166,9 +240,301 @@ pub async fn update_channel_config(
 }
 
 /// PUT
+///
+/// Sets a channel's timezone, rebuilding its heatmap buckets in the new timezone.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_timezone(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelTimezoneRequest>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let id = ChannelId::try_from(payload.id.as_str())
+            .map_err(|_| ApiError::InvalidUser(payload.id.clone()))?;
+
+        let heatmap_repo = HeatmapRepository::new(state.database_pool);
+        let Some(previous) = heatmap_repo.get_timezone(&id).await? else {
+            return Err(ApiError::InvalidUser(payload.id));
+        };
+
+        if !heatmap_repo.set_timezone(&id, &payload.timezone).await? {
+            tracing::warn!(timezone = payload.timezone, "unknown timezone");
+            return Err(ApiError::GenericStatusCode(StatusCode::BAD_REQUEST));
+        }
+
+        Audit::new(AuditAction::ChannelTimezoneUpdated)
+            .target(&id)
+            .before(&previous)
+            .after(&payload.timezone)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// GET
+///
+/// Retrieves how a channel counts keywords.
+#[instrument(skip(state))]
+pub async fn get_channel_count_mode(
+    State(state): State<Arc<AppState>>,
+    Query(param): Query<UserIdRequest>,
+) -> ApiResult<ChannelCountConfig> {
+    let config = ChannelRepository::new(state.database_pool)
+        .get_count_config(&ChannelId(param.id.clone()))
+        .await?
+        .ok_or(ApiError::InvalidUser(param.id))?;
+
+    Ok(ApiResponse::ok(config))
+}
+
+/// PUT
+///
+/// Sets whether a channel counts keywords once per message or once per occurrence.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_count_mode(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelCountModeRequest>,
+) -> ApiResult<()> {
+    let repo = ChannelRepository::new(state.database_pool);
+    let id = ChannelId::try_from(payload.id.as_str())
+        .map_err(|_| ApiError::InvalidUser(payload.id.clone()))?;
+
+    let current = repo
+        .get_count_config(&id)
+        .await?
+        .ok_or(ApiError::InvalidUser(payload.id.clone()))?;
+
+    let max_occurrences = payload.max_occurrences.unwrap_or(current.max_occurrences);
+    if !ChannelCountConfig::MAX_OCCURRENCES_RANGE.contains(&max_occurrences) {
+        return Err(ApiError::BadRequest(format!(
+            "max_occurrences must be within {:?}",
+            ChannelCountConfig::MAX_OCCURRENCES_RANGE
+        )));
+    }
+
+    let config = ChannelCountConfig {
+        count_mode: payload.mode,
+        max_occurrences,
+    };
+    if !repo.set_count_config(&id, &config).await? {
+        return Err(ApiError::InvalidUser(payload.id));
+    }
+
+    tracing::info!(channel = %id, ?config, "updated channel count mode");
+    Audit::new(AuditAction::ChannelCountModeUpdated)
+        .target(&id)
+        .before(&current)
+        .after(&config)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// GET
+///
+/// Retrieves whether counting is paused for a channel.
 #[instrument(skip(state))]
+pub async fn get_channel_paused(
+    State(state): State<Arc<AppState>>,
+    Query(param): Query<UserIdRequest>,
+) -> ApiResult<bool> {
+    let id = ChannelId(param.id.clone());
+    let repo = ChannelRepository::new(state.database_pool);
+    if repo.get_count_config(&id).await?.is_none() {
+        return Err(ApiError::InvalidUser(param.id));
+    }
+
+    Ok(ApiResponse::ok(repo.is_paused(&id).await?))
+}
+
+/// PUT
+///
+/// Pauses or resumes counting for a channel. The channel stays joined and subscribed while
+/// paused, so counting picks up again as soon as it's resumed.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_paused(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelPauseRequest>,
+) -> ApiResult<()> {
+    let id = ChannelId::try_from(payload.id.as_str())
+        .map_err(|_| ApiError::InvalidUser(payload.id.clone()))?;
+
+    set_channel_paused(&state, &id, payload.paused, &actor).await?;
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// Pauses or resumes counting for a tracked channel, recording who did it.
+pub(crate) async fn set_channel_paused(
+    state: &AppState,
+    id: &ChannelId,
+    paused: bool,
+    actor: &Actor,
+) -> Result<(), ApiError> {
+    let repo = ChannelRepository::new(state.database_pool);
+
+    let previous = repo.is_paused(id).await?;
+    if !repo.set_paused(id, paused).await? {
+        return Err(ApiError::InvalidUser(id.to_string()));
+    }
+
+    tracing::info!(channel = %id, paused, "updated channel pause");
+    Audit::new(AuditAction::ChannelPauseUpdated)
+        .target(id)
+        .before(&previous)
+        .after(&paused)
+        .record(state.database_pool, actor)
+        .await;
+
+    Ok(())
+}
+
+/// DELETE
+///
+/// Ends every dashboard session for a channel, e.g. after its broadcaster's account was
+/// compromised. Returns how many sessions were still active.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn revoke_dashboard_sessions(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(channel_id): Path<String>,
+) -> ApiResult<usize> {
+    let id = ChannelId::try_from(channel_id.as_str())
+        .map_err(|_| ApiError::InvalidUser(channel_id.clone()))?;
+
+    let revoked = session::revoke_all(&mut state.redis_pool.clone(), &id).await?;
+
+    tracing::info!(channel = %id, revoked, "revoked dashboard sessions");
+    Audit::new(AuditAction::DashboardSessionsRevoked)
+        .target(&id)
+        .after(&revoked)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(revoked))
+}
+
+/// PUT
+///
+/// Sets how the bot answers chat commands in a channel: in chat, by whisper, or not at all.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_reply_mode(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelReplyModeRequest>,
+) -> ApiResult<()> {
+    let repo = ChannelRepository::new(state.database_pool);
+    let id = ChannelId(payload.id.clone());
+
+    let previous = repo.get_reply_config(&id.0).await.ok();
+    if !repo.set_reply_mode(&id, payload.mode).await? {
+        return Err(ApiError::InvalidUser(payload.id));
+    }
+
+    tracing::info!(channel = %id, mode = payload.mode.as_str(), "updated channel reply mode");
+    Audit::new(AuditAction::ChannelRepliesUpdated)
+        .target(&id)
+        .before(&previous)
+        .after(&repo.get_reply_config(&id.0).await.ok())
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// GET
+///
+/// Retrieves a channel's settings.
+#[instrument(skip(state))]
+pub async fn get_channel_settings(
+    State(state): State<Arc<AppState>>,
+    Query(param): Query<UserIdRequest>,
+) -> ApiResult<ChannelSettings> {
+    let settings = SettingsRepository::new(state.database_pool)
+        .get(&ChannelId(param.id.clone()))
+        .await?
+        .ok_or(ApiError::InvalidUser(param.id))?;
+
+    Ok(ApiResponse::ok(settings))
+}
+
+/// PUT
+///
+/// Replaces a channel's settings. Running IRC handlers pick the new settings up on the next
+/// message, without a restart.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_channel_settings(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelSettingsRequest>,
+) -> ApiResult<()> {
+    let id = ChannelId::try_from(payload.id.as_str())
+        .map_err(|_| ApiError::InvalidUser(payload.id.clone()))?;
+
+    replace_channel_settings(&state, &id, payload.settings, &actor).await?;
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// Validates and stores a tracked channel's new settings, recording who changed them.
+pub(crate) async fn replace_channel_settings(
+    state: &AppState,
+    id: &ChannelId,
+    mut updated: ChannelSettings,
+    actor: &Actor,
+) -> Result<(), ApiError> {
+    let repo = SettingsRepository::new(state.database_pool);
+    updated.validate().map_err(ApiError::BadRequest)?;
+
+    let keywords = KeywordRepository::new(state.database_pool)
+        .get_all()
+        .await?;
+    if let Some(unknown) = updated
+        .muted_keywords
+        .iter()
+        .find(|id| !keywords.iter().any(|keyword| keyword.id == **id))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "unknown keyword id {unknown}"
+        )));
+    }
+    updated.muted_keywords.sort_unstable_by_key(|id| id.0);
+    updated.muted_keywords.dedup();
+
+    let previous = repo
+        .get(id)
+        .await?
+        .ok_or(ApiError::InvalidUser(id.to_string()))?;
+    updated.blacklist = previous.blacklist.clone();
+    if !repo.set(id, &updated).await? {
+        return Err(ApiError::InvalidUser(id.to_string()));
+    }
+
+    // the listener drops it too, but not before this instance's next message
+    settings().invalidate(id);
+
+    tracing::info!(channel = %id, settings = ?updated, "updated channel settings");
+    Audit::new(AuditAction::ChannelSettingsUpdated)
+        .target(id)
+        .before(&previous)
+        .after(&updated)
+        .record(state.database_pool, actor)
+        .await;
+
+    Ok(())
+}
+
+/// PUT
+#[instrument(skip(state, actor), fields(actor = %actor))]
 pub async fn refresh_channel_state(
     State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
     Json(payload): Json<UserIdRequest>,
 ) -> ApiResult<()> {
     if payload.id == "all" {
@@ -181,9 +547,18 @@ pub async fn refresh_channel_state(
         let id = payload.id.clone();
         let live = !Helix::get_streams(&[id]).await?.is_empty();
 
-        db::redis::set_stream_state(&mut state.redis_pool.clone(), &ChannelId(payload.id), live)
-            .await?;
+        db::redis::set_stream_state(
+            &mut state.redis_pool.clone(),
+            &ChannelId(payload.id.clone()),
+            live,
+        )
+        .await?;
     }
 
+    Audit::new(AuditAction::StreamStateRefreshed)
+        .target(&payload.id)
+        .record(state.database_pool, &actor)
+        .await;
+
     Ok(ApiResponse::<()>::empty())
 }
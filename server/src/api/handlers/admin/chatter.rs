@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use axum::Extension;
+use axum::extract::{Path, Query, State};
+use chrono::Utc;
+use http::StatusCode;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::ChatterDeletionQuery;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::deletion::ChatterDeletion;
+use crate::db::prelude::{ChannelId, ChannelRepository, ChatterId, ChatterRepository};
+use crate::db::prelude::{DeletionRepository, Repository};
+use crate::util::{self, deletion};
+
+#[derive(Debug, Serialize)]
+pub struct ChatterDeletionResponse {
+    #[serde(flatten)]
+    pub deletion: ChatterDeletion,
+    /// Only issued when the deletion is requested; it isn't shown again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation_token: Option<String>,
+    /// Everything stored about the chatter, if it was asked for when confirming
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub export: Option<Value>,
+}
+
+/// DELETE
+///
+/// Deletes a chatter's data. Without `?confirm=`, nothing is deleted: a confirmation token is
+/// issued, which is valid for 15 minutes. Confirming with it soft-deletes the chatter, and their
+/// data is purged `CHATTER_PURGE_DELAY_HOURS` later. With `?export=true`, the confirmed deletion
+/// includes everything that was stored about the chatter.
+///
+/// Channels can't be deleted this way while they're tracked.
+#[instrument(skip(state, actor, query), fields(actor = %actor))]
+pub async fn delete_chatter(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+    Query(query): Query<ChatterDeletionQuery>,
+) -> ApiResult<ChatterDeletionResponse> {
+    let pool = state.database_pool;
+    let chatter_id =
+        ChatterId::try_from(id.as_str()).map_err(|_| ApiError::InvalidUser(id.clone()))?;
+
+    if !ChatterRepository::new(pool).exists(&chatter_id).await? {
+        return Err(ApiError::InvalidUser(id));
+    }
+
+    if ChannelRepository::new(pool)
+        .exists(&ChannelId::from(chatter_id.clone()))
+        .await?
+    {
+        return Err(ApiError::BadRequest(
+            "tracked channels can't be deleted".into(),
+        ));
+    }
+
+    let repo = DeletionRepository::new(pool);
+    let existing = repo.get(&chatter_id).await?;
+
+    // confirming twice is harmless
+    if let Some(deletion) = existing.as_ref().filter(|d| d.is_confirmed()) {
+        return Ok(ApiResponse::ok(ChatterDeletionResponse {
+            deletion: deletion.clone(),
+            confirmation_token: None,
+            export: None,
+        }));
+    }
+
+    let Some(token) = query.confirm else {
+        let (token, hash) = deletion::confirmation_token();
+        let confirm_by = Utc::now().naive_utc() + deletion::CONFIRMATION_TTL;
+        let deletion = repo
+            .request(&chatter_id, &hash, confirm_by)
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::CONFLICT))?;
+
+        tracing::info!(%chatter_id, "chatter deletion requested");
+        return Ok(ApiResponse::ok(ChatterDeletionResponse {
+            deletion,
+            confirmation_token: Some(token),
+            export: None,
+        }));
+    };
+
+    let confirmed = existing.as_ref().is_some_and(|d| {
+        d.can_confirm()
+            && util::constant_time_cmp(&d.confirmation_hash, &deletion::hash_token(&token))
+    });
+    if !confirmed {
+        return Err(ApiError::BadRequest(
+            "invalid or expired confirmation token".into(),
+        ));
+    }
+
+    // read before the soft delete, so that it matches what's deleted
+    let export = match query.export {
+        true => Some(repo.export(&chatter_id).await?),
+        false => None,
+    };
+
+    let purge_after = Utc::now().naive_utc() + deletion::purge_delay().await;
+    let deletion = repo
+        .confirm(&chatter_id, purge_after)
+        .await?
+        .ok_or(ApiError::GenericStatusCode(StatusCode::CONFLICT))?;
+
+    tracing::info!(%chatter_id, %purge_after, "chatter deleted");
+    Audit::new(AuditAction::ChatterDeleted)
+        .target(&chatter_id)
+        .after(&deletion)
+        .record(pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(ChatterDeletionResponse {
+        deletion,
+        confirmation_token: None,
+        export,
+    }))
+}
@@ -0,0 +1,45 @@
+use axum::extract::Query;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::api::server::{ApiResponse, ApiResult};
+use crate::util::profiling::{self, ProfileArtifacts, RuntimeSnapshot};
+
+#[inline]
+const fn default_profile_secs() -> u64 {
+    10
+}
+
+#[inline]
+const fn default_profile_frequency() -> i32 {
+    99
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProfileQuery {
+    #[serde(default = "default_profile_secs")]
+    pub seconds: u64,
+    #[serde(default = "default_profile_frequency")]
+    pub frequency: i32,
+}
+
+/// POST
+///
+/// Captures a CPU profile for `?seconds=` (max 60) at `?frequency=` Hz, returning the paths of
+/// the flamegraph and pprof artifacts written on the server.
+#[instrument]
+pub async fn cpu_profile(Query(query): Query<ProfileQuery>) -> ApiResult<ProfileArtifacts> {
+    let artifacts = profiling::capture_cpu_profile(query.seconds, query.frequency).await?;
+
+    Ok(ApiResponse::ok(artifacts))
+}
+
+/// POST
+///
+/// Writes a snapshot of the tokio runtime's task/worker metrics, returning its path.
+#[instrument]
+pub async fn runtime_snapshot() -> ApiResult<RuntimeSnapshot> {
+    let snapshot = profiling::capture_runtime_snapshot().await?;
+
+    Ok(ApiResponse::ok(snapshot))
+}
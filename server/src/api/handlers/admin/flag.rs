@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::FeatureFlagRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::flag::FeatureFlag;
+use crate::db::prelude::{ChannelId, FeatureFlagRepository};
+use crate::util::flags::{Flag, FlagState, flags};
+
+/// GET
+///
+/// Every feature flag's current state, including flags still on their defaults.
+#[instrument]
+pub async fn feature_flags() -> ApiResult<Vec<FlagState>> {
+    Ok(ApiResponse::ok(flags().all()))
+}
+
+/// PUT
+///
+/// Replaces a feature flag's state. Every instance picks the change up within moments, without a
+/// restart. Channels turned on for `eventsub_chat` are only subscribed to their chat once the
+/// hooks are next reset.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_feature_flag(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(name): Path<String>,
+    Json(payload): Json<FeatureFlagRequest>,
+) -> ApiResult<FlagState> {
+    let Ok(flag) = name.parse::<Flag>() else {
+        return Err(ApiError::GenericStatusCode(StatusCode::NOT_FOUND));
+    };
+    if !FeatureFlag::ROLLOUT_RANGE.contains(&payload.rollout_percent) {
+        return Err(ApiError::BadRequest(format!(
+            "rollout_percent must be between {} and {}",
+            FeatureFlag::ROLLOUT_RANGE.start(),
+            FeatureFlag::ROLLOUT_RANGE.end()
+        )));
+    }
+
+    let mut channels = Vec::with_capacity(payload.channels.len());
+    for id in &payload.channels {
+        match ChannelId::try_from(id.as_str()) {
+            Ok(id) => channels.push(id),
+            Err(_) => return Err(ApiError::InvalidUser(id.clone())),
+        }
+    }
+    channels.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    channels.dedup();
+
+    let previous = flags().get(flag);
+    FeatureFlagRepository::new(state.database_pool)
+        .set(
+            flag.as_str(),
+            payload.enabled,
+            payload.rollout_percent,
+            &channels,
+        )
+        .await?;
+    let updated = reload(&state, flag).await;
+
+    tracing::warn!(%flag, state = ?updated, "feature flag updated");
+    Audit::new(AuditAction::FeatureFlagUpdated)
+        .target(flag)
+        .before(&previous)
+        .after(&updated)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(updated))
+}
+
+/// DELETE
+///
+/// Returns a feature flag to its default state.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn reset_feature_flag(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(name): Path<String>,
+) -> ApiResult<FlagState> {
+    let Ok(flag) = name.parse::<Flag>() else {
+        return Err(ApiError::GenericStatusCode(StatusCode::NOT_FOUND));
+    };
+
+    let previous = flags().get(flag);
+    if !FeatureFlagRepository::new(state.database_pool)
+        .delete(flag.as_str())
+        .await?
+    {
+        return Ok(ApiResponse::ok(previous));
+    }
+    let updated = reload(&state, flag).await;
+
+    tracing::warn!(%flag, state = ?updated, "feature flag reset");
+    Audit::new(AuditAction::FeatureFlagReset)
+        .target(flag)
+        .before(&previous)
+        .after(&updated)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(updated))
+}
+
+/// The listener reloads the flags too, but not necessarily before this instance's next check.
+async fn reload(state: &AppState, flag: Flag) -> FlagState {
+    if let Err(e) = flags().reload(state.database_pool).await {
+        tracing::error!(error = ?e, "failed to reload feature flags");
+    }
+
+    flags().get(flag)
+}
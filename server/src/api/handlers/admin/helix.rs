@@ -1,13 +1,21 @@
 use std::sync::Arc;
 
+use axum::Extension;
 use axum::extract::{Path, State};
 use tracing::instrument;
 
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
 use crate::api::handlers::spawn_protected;
 use crate::api::server::stream_online_hook_handler;
-use crate::api::server::{ApiResponse, ApiResult, AppState, RouteError};
+use crate::api::server::{ApiResponse, ApiResult, AppState};
 use crate::api::webhook::SubscriptionGenericData;
+use crate::api::webhook::dispatch::{self, RotationReport, SubscriptionBudget};
+use crate::db::models::audit::AuditAction;
+use crate::db::prelude::SubscriptionRepository;
 use crate::util::helix::{Helix, HelixUser};
+use crate::util::shard::sharding;
 
 /// GET
 #[instrument]
@@ -28,29 +36,46 @@ pub async fn user_by_id(Path(id): Path<String>) -> ApiResult<Vec<HelixUser>> {
 }
 
 /// DELETE
-#[instrument(skip(state))]
-pub async fn delete_hooks(State(state): State<Arc<AppState>>) -> ApiResult<usize> {
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn delete_hooks(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<usize> {
     let result = spawn_protected(async move {
         crate::db::redis::clear_stream_states(&mut state.redis_pool.clone())
             .await
-            .map_err(RouteError::from)?;
+            .map_err(ApiError::from)?;
 
         tracing::info!("removed all channel states from redis cache");
 
-        let active_hooks = Helix::get_active_subscription_ids().await?;
+        // only this shard's subscriptions; other shards' are managed (and deleted) by their own
+        // instances
+        let sharding = sharding();
+        let active_hooks: Vec<String> = Helix::get_active_subscriptions()
+            .await?
+            .into_iter()
+            .filter(|hook| sharding.owns(&hook.condition.broadcaster_user_id))
+            .map(|hook| hook.id)
+            .collect();
         tracing::debug!(?active_hooks, "active_hooks");
 
-        if !active_hooks.is_empty() {
+        let hooks_count = if !active_hooks.is_empty() {
             tracing::debug!("active_hooks populated - deleting...");
             Helix::delete_subscriptions(&active_hooks)
                 .await
-                .map_err(RouteError::from)?;
+                .map_err(ApiError::from)?;
 
-            let hooks_count = active_hooks.len();
-            Ok(hooks_count)
+            active_hooks.len()
         } else {
-            Ok(0usize)
-        }
+            0usize
+        };
+
+        Audit::new(AuditAction::HooksDeleted)
+            .before(&active_hooks)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(hooks_count)
     })
     .await?;
 
@@ -58,17 +83,63 @@ pub async fn delete_hooks(State(state): State<Arc<AppState>>) -> ApiResult<usize
 }
 
 /// PUT
-#[instrument(skip(state))]
-pub async fn reset_hooks(State(state): State<Arc<AppState>>) -> ApiResult<()> {
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn reset_hooks(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<()> {
     spawn_protected(async move {
         let ids = state.channel_ids.read().await.clone();
-        stream_online_hook_handler(&ids, state.redis_pool.clone()).await
+        stream_online_hook_handler(&ids, state.redis_pool.clone()).await?;
+
+        Audit::new(AuditAction::HooksReset)
+            .after(&ids)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
     })
     .await?;
 
     Ok(ApiResponse::<()>::empty())
 }
 
+/// POST
+///
+/// Rotates every webhook subscription's secret and re-subscribes with the new secrets. Messages
+/// signed with a previous secret are accepted until they'd be rejected as stale.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn rotate_hook_secrets(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<RotationReport> {
+    let report = spawn_protected(async move {
+        let report = dispatch::rotate_secrets(state.database_pool).await?;
+
+        // the secrets themselves are never recorded
+        Audit::new(AuditAction::HookSecretsRotated)
+            .after(&report)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(report)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(report))
+}
+
+/// GET
+///
+/// How much of the EventSub subscription cost budget the stored subscriptions use.
+#[instrument(skip(state))]
+pub async fn hook_budget(State(state): State<Arc<AppState>>) -> ApiResult<SubscriptionBudget> {
+    let repo = SubscriptionRepository::new(state.database_pool);
+    let budget = SubscriptionBudget::load(&repo).await?;
+
+    Ok(ApiResponse::ok(budget))
+}
+
 /// GET
 #[instrument(skip(state))]
 pub async fn active_hooks(
@@ -79,7 +150,7 @@ pub async fn active_hooks(
 
         // let active_data: HelixDataGeneric<SubscriptionGenericData> =
         //     serde_json::from_value(Helix::get_active_subscriptions_raw().await)
-        //         .map_err(RouteError::from)?;
+        //         .map_err(ApiError::from)?;
 
         let mut channel_data = Vec::new();
         let mut tx = state.database_pool.begin().await?;
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::DiscordWebhookRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::integration::DiscordWebhook;
+use crate::db::prelude::{ChannelId, ChannelRepository, DiscordWebhookRepository, Repository};
+use crate::integrations::discord;
+
+/// GET
+///
+/// Every configured Discord webhook, by channel. Webhook URLs carry the token used to post to them,
+/// so they're redacted for read-only tokens.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn discord_webhooks(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<Vec<DiscordWebhook>> {
+    let mut webhooks = DiscordWebhookRepository::new(state.database_pool)
+        .get_all()
+        .await?;
+
+    if !actor.is_admin() {
+        webhooks = webhooks.iter().map(DiscordWebhook::redacted).collect();
+    }
+
+    Ok(ApiResponse::ok(webhooks))
+}
+
+/// PUT
+///
+/// Sets a channel's Discord webhook for an event, replacing any existing one. An unset `template`
+/// uses the event's default.
+#[instrument(
+    skip(state, actor, payload),
+    fields(actor = %actor, channel_id = payload.channel_id, event = payload.event.as_str())
+)]
+pub async fn update_discord_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<DiscordWebhookRequest>,
+) -> ApiResult<DiscordWebhook> {
+    let webhook = spawn_protected(async move {
+        if !discord::is_webhook_url(&payload.url) {
+            return Err(ApiError::BadRequest(
+                "url must be a discord webhook url".to_string(),
+            ));
+        }
+
+        let channel_id = ChannelId::try_from(payload.channel_id.as_str())
+            .map_err(|_| ApiError::InvalidUser(payload.channel_id.clone()))?;
+
+        if ChannelRepository::new(state.database_pool)
+            .get_by_id(&channel_id)
+            .await?
+            .is_none()
+        {
+            return Err(ApiError::InvalidUser(payload.channel_id));
+        }
+
+        let repo = DiscordWebhookRepository::new(state.database_pool);
+        let previous = repo.get(&channel_id, payload.event).await?;
+
+        let template = payload.template.filter(|t| !t.trim().is_empty());
+        let webhook = repo
+            .upsert(
+                &channel_id,
+                payload.event,
+                &payload.url,
+                template.as_deref(),
+                payload.enabled,
+            )
+            .await?;
+
+        tracing::info!(id = webhook.id, "discord webhook updated");
+        Audit::new(AuditAction::DiscordWebhookUpdated)
+            .target(&channel_id)
+            .before(&previous.map(|w| w.redacted()))
+            .after(&webhook.redacted())
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(webhook)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(webhook))
+}
+
+/// DELETE
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn delete_discord_webhook(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<i32>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let webhook = DiscordWebhookRepository::new(state.database_pool)
+            .delete(id)
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+        tracing::info!(id, "discord webhook deleted");
+        Audit::new(AuditAction::DiscordWebhookDeleted)
+            .target(&webhook.channel_id)
+            .before(&webhook.redacted())
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::KeywordRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::keyword::{Keyword, KeywordKind};
+use crate::db::prelude::{KeywordId, KeywordRepository};
+
+/// Matches the `keyword.word` column
+const MAX_WORD_LEN: usize = 32;
+/// Matches the `keyword.emote_id` column
+const MAX_EMOTE_ID_LEN: usize = 64;
+
+/// POST
+///
+/// Tracks a new text or emote keyword. Keywords are loaded when the IRC connection starts, so a new
+/// keyword is only counted after a restart.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn create_keyword(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<KeywordRequest>,
+) -> ApiResult<Keyword> {
+    let keyword = spawn_protected(async move {
+        let (word, kind, emote_id) = validate(payload).map_err(ApiError::BadRequest)?;
+        let keyword = KeywordRepository::new(state.database_pool)
+            .insert(&word, kind, emote_id.as_deref())
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::CONFLICT))?;
+
+        tracing::info!(?keyword, "keyword created");
+        Audit::new(AuditAction::KeywordCreated)
+            .target(keyword.id)
+            .after(&keyword)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(keyword)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(keyword))
+}
+
+/// PUT
+///
+/// Replaces a keyword's word, kind and emote id; its scores are kept. Like new keywords, the change
+/// is only matched after a restart.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_keyword(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<i32>,
+    Json(payload): Json<KeywordRequest>,
+) -> ApiResult<Keyword> {
+    let keyword = spawn_protected(async move {
+        let id = KeywordId(id);
+        let (word, kind, emote_id) = validate(payload).map_err(ApiError::BadRequest)?;
+        let repo = KeywordRepository::new(state.database_pool);
+
+        let before = repo
+            .get_by_id(id)
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+        if repo
+            .get_by_word(&word, kind)
+            .await?
+            .is_some_and(|existing| existing.id != id)
+        {
+            return Err(ApiError::GenericStatusCode(StatusCode::CONFLICT));
+        }
+
+        let keyword = repo
+            .update(id, &word, kind, emote_id.as_deref())
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+        tracing::info!(?keyword, "keyword updated");
+        Audit::new(AuditAction::KeywordUpdated)
+            .target(keyword.id)
+            .before(&before)
+            .after(&keyword)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(keyword)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(keyword))
+}
+
+/// Normalizes a keyword request into its lowercase word, kind and emote id.
+fn validate(payload: KeywordRequest) -> Result<(String, KeywordKind, Option<String>), String> {
+    let word = payload.word.trim().to_lowercase();
+    if word.is_empty() || word.chars().count() > MAX_WORD_LEN {
+        return Err(format!(
+            "word must be between 1 and {MAX_WORD_LEN} characters"
+        ));
+    }
+
+    let emote_id = payload
+        .emote_id
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty());
+
+    match (&payload.kind, &emote_id) {
+        (KeywordKind::Text, Some(_)) => {
+            return Err("emote_id is only accepted for emote keywords".into());
+        }
+        (KeywordKind::Emote, Some(id)) if id.len() > MAX_EMOTE_ID_LEN => {
+            return Err(format!(
+                "emote_id must be at most {MAX_EMOTE_ID_LEN} characters"
+            ));
+        }
+        _ => (),
+    }
+
+    Ok((word, payload.kind, emote_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn request(word: &str, kind: KeywordKind, emote_id: Option<&str>) -> KeywordRequest {
+        KeywordRequest {
+            word: word.to_string(),
+            kind,
+            emote_id: emote_id.map(String::from),
+        }
+    }
+
+    #[test]
+    fn emote_ids_are_only_accepted_for_emote_keywords() {
+        let (word, kind, emote_id) = validate(request(
+            " PissEmote ",
+            KeywordKind::Emote,
+            Some("emotesv2_1"),
+        ))
+        .unwrap();
+        assert_eq!(word, "pissemote");
+        assert_eq!(kind, KeywordKind::Emote);
+        assert_eq!(emote_id.as_deref(), Some("emotesv2_1"));
+
+        assert!(validate(request("piss", KeywordKind::Text, Some("emotesv2_1"))).is_err());
+        assert!(validate(request("piss", KeywordKind::Text, Some(" "))).is_ok());
+        assert!(validate(request(" ", KeywordKind::Text, None)).is_err());
+        assert!(validate(request(&"p".repeat(33), KeywordKind::Text, None)).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::{Extension, Json};
+use http::StatusCode;
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::util::telemetry::{LogFilterChange, LogFilterError, log_filter, set_log_filter};
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelUpdate {
+    /// Directives in `EnvFilter` syntax, e.g. `info,pea_fan::irc::connection=debug`; omit it to
+    /// restore the filter the server started with
+    pub filter: Option<String>,
+}
+
+/// GET
+///
+/// The tracing filter currently in effect.
+#[instrument]
+pub async fn log_level() -> ApiResult<String> {
+    let filter =
+        log_filter().ok_or(ApiError::GenericStatusCode(StatusCode::SERVICE_UNAVAILABLE))?;
+    Ok(ApiResponse::ok(filter))
+}
+
+/// PUT
+///
+/// Replaces the tracing filter without a restart, e.g. to turn up a single module's logging
+/// while debugging an incident. The change isn't persisted, so a restart restores `LOG_FILTER`.
+/// Responds with the previous and new filters.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn update_log_level(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<LogLevelUpdate>,
+) -> ApiResult<LogFilterChange> {
+    let change = set_log_filter(payload.filter.as_deref()).map_err(|e| match e {
+        LogFilterError::Invalid(e) => ApiError::BadRequest(e.to_string()),
+        e => {
+            tracing::error!(error = ?e, "failed to replace log filter");
+            ApiError::GenericStatusCode(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    })?;
+
+    tracing::warn!(
+        previous = change.previous,
+        current = change.current,
+        "log filter replaced"
+    );
+
+    Audit::new(AuditAction::LogFilterUpdated)
+        .before(&change.previous)
+        .after(&change.current)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(change))
+}
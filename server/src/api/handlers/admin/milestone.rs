@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::MilestoneRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::milestone::Milestone;
+use crate::db::prelude::{ChannelId, ChannelRepository, MilestoneRepository, Repository};
+use crate::util::milestones;
+
+/// GET
+///
+/// Every configured milestone, global milestones first.
+#[instrument(skip(state))]
+pub async fn milestones(State(state): State<Arc<AppState>>) -> ApiResult<Vec<Milestone>> {
+    let milestones = MilestoneRepository::new(state.database_pool)
+        .get_all()
+        .await?;
+
+    Ok(ApiResponse::ok(milestones))
+}
+
+/// POST
+///
+/// Adds a milestone to a single channel, or to every channel if `channel_id` is unset.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn create_milestone(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<MilestoneRequest>,
+) -> ApiResult<Milestone> {
+    let milestone = spawn_protected(async move {
+        if payload.threshold <= 0 {
+            return Err(ApiError::BadRequest(
+                "threshold must be greater than 0".to_string(),
+            ));
+        }
+
+        let channel_id = match payload.channel_id {
+            Some(id) => {
+                let channel_id = ChannelId::try_from(id.as_str())
+                    .map_err(|_| ApiError::InvalidUser(id.clone()))?;
+
+                if ChannelRepository::new(state.database_pool)
+                    .get_by_id(&channel_id)
+                    .await?
+                    .is_none()
+                {
+                    return Err(ApiError::InvalidUser(id));
+                }
+
+                Some(channel_id)
+            }
+            None => None,
+        };
+
+        let milestone = MilestoneRepository::new(state.database_pool)
+            .insert(
+                channel_id.as_ref(),
+                payload.kind,
+                payload.threshold,
+                payload.repeating,
+            )
+            .await?;
+
+        milestones::milestones().invalidate(milestone.channel_id.as_ref());
+        tracing::info!(?milestone, "milestone created");
+        let mut audit = Audit::new(AuditAction::MilestoneCreated).after(&milestone);
+        if let Some(channel_id) = &milestone.channel_id {
+            audit = audit.target(channel_id);
+        }
+        audit.record(state.database_pool, &actor).await;
+
+        Ok(milestone)
+    })
+    .await?;
+
+    Ok(ApiResponse::ok(milestone))
+}
+
+/// DELETE
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn delete_milestone(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<i32>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let milestone = MilestoneRepository::new(state.database_pool)
+            .delete(id)
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+        milestones::milestones().invalidate(milestone.channel_id.as_ref());
+        tracing::info!(id, "milestone deleted");
+        let mut audit = Audit::new(AuditAction::MilestoneDeleted).before(&milestone);
+        if let Some(channel_id) = &milestone.channel_id {
+            audit = audit.target(channel_id);
+        }
+        audit.record(state.database_pool, &actor).await;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
@@ -1,30 +1,48 @@
+pub mod alias;
+pub mod audit;
 pub mod channel;
+pub mod chatter;
+#[cfg(feature = "profiling")]
+pub mod debug;
 
+pub mod flag;
 pub mod helix;
+pub mod integration;
+pub mod keyword;
+pub mod logging;
+pub mod milestone;
+pub mod note;
+pub mod pool;
+pub mod score;
+pub mod status;
+pub mod token;
 
 use std::sync::Arc;
 
-use axum::Json;
 use axum::extract::State;
+use axum::{Extension, Json};
 use http::StatusCode;
 use sqlx::{Pool, Postgres};
 use tracing::instrument;
 
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
 use crate::api::extractors::{TOTPRequest, TOTPResponse};
+use crate::api::handlers::admin::audit::Audit;
 use crate::api::middleware::verify_internal::SessionToken;
-use crate::api::server::{ApiResponse, ApiResult, AppState, RouteError};
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
 
 /// Create a new admin session token and store it in the database. Return the token to the caller
-async fn create_session(database_pool: &'static Pool<Postgres>) -> Result<String, RouteError> {
+async fn create_session(database_pool: &'static Pool<Postgres>) -> Result<String, ApiError> {
     let session = SessionToken::new_token();
     SessionToken::store_token(database_pool, &session)
         .await
-        .map_err(RouteError::from)?;
+        .map_err(ApiError::from)?;
 
     Ok(session)
 }
 
-
 /// This handler should be used from a middleware-protected route; by returning a response, the
 /// given token matches some session token where the token is not yet expired
 #[instrument]
@@ -32,6 +50,33 @@ pub async fn validate_session() -> ApiResult<&'static str> {
     Ok(ApiResponse::ok("ok"))
 }
 
+/// DELETE
+///
+/// Ends the admin session the request was made with.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn end_session(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<()> {
+    let Some(session_id) = actor.session_id() else {
+        return Err(ApiError::BadRequest(
+            "only session logins can be ended".into(),
+        ));
+    };
+
+    SessionToken::delete(state.database_pool, session_id)
+        .await
+        .map_err(ApiError::from)?;
+
+    tracing::info!(session_id, "admin session ended");
+    Audit::new(AuditAction::AdminSessionEnded)
+        .target(session_id.to_string())
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
 /// POST
 #[instrument(skip(state))]
 pub async fn new_session(
@@ -42,7 +87,7 @@ pub async fn new_session(
     let mut guard = state.totp_handler.lock().await;
     let is_valid = guard.totp_cmp(&payload.token).map_err(|e| {
         tracing::error!(error = ?e, "unknown error during TOTP validation");
-        RouteError::GenericStatusCode(StatusCode::INTERNAL_SERVER_ERROR)
+        ApiError::GenericStatusCode(StatusCode::INTERNAL_SERVER_ERROR)
     })?;
 
     if is_valid {
@@ -54,8 +99,11 @@ pub async fn new_session(
 }
 
 /// PUT
-#[instrument(skip(state))]
-pub async fn reset_irc(State(state): State<Arc<AppState>>) -> ApiResult<()> {
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn reset_irc(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<()> {
     let supervisor = &state.irc_connection;
 
     supervisor
@@ -63,7 +111,11 @@ pub async fn reset_irc(State(state): State<Arc<AppState>>) -> ApiResult<()> {
         .reset_tx
         .send(())
         .await
-        .map_err(RouteError::from)?;
+        .map_err(ApiError::from)?;
+
+    Audit::new(AuditAction::IrcReset)
+        .record(state.database_pool, &actor)
+        .await;
 
     Ok(ApiResponse::<()>::empty())
 }
@@ -0,0 +1,208 @@
+// `ApiError` is large, but these helpers are only called once per request
+#![allow(clippy::result_large_err)]
+
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::{Extension, Json};
+use http::StatusCode;
+use serde_json::json;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::ChatterNoteRequest;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::note::{ChatterNote, ChatterNoteHistory};
+use crate::db::prelude::{ChannelId, ChannelRepository, ChatterId, ChatterRepository};
+use crate::db::prelude::{NoteRepository, Repository};
+
+const MAX_FLAG_LEN: usize = 64;
+const MAX_FLAGS: usize = 16;
+
+fn parse_ids(channel_id: &str, chatter_id: &str) -> Result<(ChannelId, ChatterId), ApiError> {
+    let channel_id = ChannelId::try_from(channel_id)
+        .map_err(|_| ApiError::InvalidUser(channel_id.to_string()))?;
+    let chatter_id = ChatterId::try_from(chatter_id)
+        .map_err(|_| ApiError::InvalidUser(chatter_id.to_string()))?;
+
+    Ok((channel_id, chatter_id))
+}
+
+/// Trims and deduplicates flags, rejecting any that are too long.
+fn normalize_flags(flags: &[String]) -> Result<Vec<String>, ApiError> {
+    let mut normalized: Vec<String> = Vec::with_capacity(flags.len());
+    for flag in flags.iter().map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        if flag.len() > MAX_FLAG_LEN {
+            tracing::warn!(flag, "note flag too long");
+            return Err(ApiError::GenericStatusCode(StatusCode::BAD_REQUEST));
+        }
+
+        if !normalized.iter().any(|f| f.eq_ignore_ascii_case(flag)) {
+            normalized.push(flag.to_string());
+        }
+    }
+
+    if normalized.len() > MAX_FLAGS {
+        tracing::warn!(count = normalized.len(), "too many note flags");
+        return Err(ApiError::GenericStatusCode(StatusCode::BAD_REQUEST));
+    }
+
+    Ok(normalized)
+}
+
+/// GET
+///
+/// All notes attached to chatters in a channel, most recently edited first.
+#[instrument(skip(state))]
+pub async fn channel_notes(
+    State(state): State<Arc<AppState>>,
+    Path(channel_id): Path<String>,
+) -> ApiResult<Vec<ChatterNote>> {
+    let id = ChannelId::try_from(channel_id.as_str())
+        .map_err(|_| ApiError::InvalidUser(channel_id.clone()))?;
+
+    let notes = NoteRepository::new(state.database_pool)
+        .get_for_channel(&id)
+        .await?;
+
+    Ok(ApiResponse::ok(notes))
+}
+
+/// GET
+///
+/// A chatter's note in a channel (if any) along with its edit history.
+#[instrument(skip(state))]
+pub async fn chatter_note(
+    State(state): State<Arc<AppState>>,
+    Path((channel_id, chatter_id)): Path<(String, String)>,
+) -> ApiResult<ChatterNoteHistory> {
+    let (channel_id, chatter_id) = parse_ids(&channel_id, &chatter_id)?;
+
+    let repo = NoteRepository::new(state.database_pool);
+    let note = repo.get(&channel_id, &chatter_id).await?;
+    let history = repo.get_history(&channel_id, &chatter_id).await?;
+
+    Ok(ApiResponse::ok(ChatterNoteHistory { note, history }))
+}
+
+/// PUT
+///
+/// Creates or replaces a chatter's note in a channel. The edit is attributed to the
+/// authenticated actor.
+#[instrument(skip(state, actor, payload), fields(actor = %actor))]
+pub async fn update_chatter_note(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path((channel_id, chatter_id)): Path<(String, String)>,
+    Json(payload): Json<ChatterNoteRequest>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let (channel_id, chatter_id) = parse_ids(&channel_id, &chatter_id)?;
+        let author = actor.to_string();
+        let flags = normalize_flags(&payload.flags)?;
+
+        let pool = state.database_pool;
+        if ChannelRepository::new(pool)
+            .get_by_id(&channel_id)
+            .await?
+            .is_none()
+        {
+            return Err(ApiError::InvalidUser(channel_id.0));
+        }
+
+        if ChatterRepository::new(pool)
+            .get_by_id(&chatter_id)
+            .await?
+            .is_none()
+        {
+            return Err(ApiError::InvalidUser(chatter_id.0));
+        }
+
+        let repo = NoteRepository::new(pool);
+        let previous = repo.get(&channel_id, &chatter_id).await?;
+        repo.upsert(
+            &channel_id,
+            &chatter_id,
+            payload.note.trim(),
+            &flags,
+            &author,
+            actor.session_id(),
+        )
+        .await?;
+
+        tracing::info!(%channel_id, %chatter_id, ?flags, "chatter note updated");
+        Audit::new(AuditAction::NoteUpdated)
+            .target(format!("{channel_id}/{chatter_id}"))
+            .before(&previous)
+            .after(&json!({ "note": payload.note.trim(), "flags": flags }))
+            .record(pool, &actor)
+            .await;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// DELETE
+///
+/// Removes a chatter's note from a channel; the note remains in its edit history.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn delete_chatter_note(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path((channel_id, chatter_id)): Path<(String, String)>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let (channel_id, chatter_id) = parse_ids(&channel_id, &chatter_id)?;
+        let author = actor.to_string();
+
+        let repo = NoteRepository::new(state.database_pool);
+        let previous = repo.get(&channel_id, &chatter_id).await?;
+        let deleted = repo
+            .delete(&channel_id, &chatter_id, &author, actor.session_id())
+            .await?;
+
+        if !deleted {
+            return Err(ApiError::GenericStatusCode(StatusCode::NOT_FOUND));
+        }
+
+        tracing::info!(%channel_id, %chatter_id, "chatter note deleted");
+        Audit::new(AuditAction::NoteDeleted)
+            .target(format!("{channel_id}/{chatter_id}"))
+            .before(&previous)
+            .record(state.database_pool, &actor)
+            .await;
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flags_are_trimmed_and_deduplicated() {
+        let flags = [
+            "counts contested ",
+            "",
+            "Counts Contested",
+            "alt account of x",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            normalize_flags(&flags).unwrap(),
+            ["counts contested", "alt account of x"]
+        );
+        assert!(normalize_flags(&["x".repeat(MAX_FLAG_LEN + 1)]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::Extension;
+use axum::extract::{Path, Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures::{Stream, StreamExt};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::prelude::{ChannelRepository, Repository};
+use crate::irc::bridge::PoolStats;
+use crate::irc::tap::{MAX_TAP_DURATION, TapItem, TapQuery};
+use crate::util::shard::{ShardReport, sharding};
+
+/// GET
+///
+/// Channel membership, the join queue and keepalive latency of each IRC connection, along with
+/// the shared join limit. When channels are shared with other instances, also lists the live instances and which
+/// of them holds each channel.
+#[instrument(skip(state))]
+pub async fn pool_stats(State(state): State<Arc<AppState>>) -> ApiResult<PoolStats> {
+    Ok(ApiResponse::ok(state.irc_connection.stats().await?))
+}
+
+/// POST
+///
+/// Queues joins for any missing channels now rather than at the next periodic check. Responds
+/// with the number of channels queued.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn rebalance(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+) -> ApiResult<usize> {
+    let queued = state.irc_connection.resync().await?;
+
+    Audit::new(AuditAction::IrcRebalanced)
+        .after(&queued)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::ok(queued))
+}
+
+/// POST
+///
+/// Drops and re-establishes a single IRC connection; connection IDs are listed by `pool_stats`.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn reconnect(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<usize>,
+) -> ApiResult<()> {
+    // there's a single connection for now
+    if id != 0 {
+        return Err(ApiError::GenericStatusCode(StatusCode::NOT_FOUND));
+    }
+
+    state
+        .irc_connection
+        .connection
+        .reset_tx
+        .send(())
+        .await
+        .map_err(ApiError::from)?;
+
+    Audit::new(AuditAction::IrcReconnected)
+        .target(id)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// GET
+///
+/// The shard each tracked channel is assigned to, and how unevenly they're spread; see
+/// `util::shard`. Without sharding, every channel is in shard `0`.
+#[instrument(skip(state))]
+pub async fn shards(State(state): State<Arc<AppState>>) -> ApiResult<ShardReport> {
+    let channel_ids = ChannelRepository::new(state.database_pool)
+        .get_all_channel_ids()
+        .await?;
+
+    Ok(ApiResponse::ok(sharding().report(channel_ids)))
+}
+
+/// GET
+///
+/// Streams raw messages received for a channel as server-sent events, each with the message's
+/// parsed form (if any). `lagged` events report messages skipped because the client fell behind.
+/// The stream is closed after `MAX_TAP_DURATION`.
+#[instrument(skip(state))]
+pub async fn irc_tap(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TapQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    tracing::info!(channel = query.channel, "opened irc tap");
+
+    let events = state
+        .irc_connection
+        .tap
+        .subscribe(&query.channel, MAX_TAP_DURATION)
+        .map(|item| {
+            let event = match item {
+                TapItem::Event(event) => Event::default()
+                    .event("message")
+                    .json_data(&*event)
+                    .unwrap_or_else(|_| Event::default().event("error")),
+                TapItem::Lagged(skipped) => {
+                    Event::default().event("lagged").data(skipped.to_string())
+                }
+            };
+
+            Ok(event)
+        });
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
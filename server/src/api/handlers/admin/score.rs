@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::{Extension, Json};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::extractors::{ScoreAdjustRequest, ScoreHistoryQuery};
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::keyword::{KeywordId, KeywordKind};
+use crate::db::models::leaderboard::{AdjustedScore, ScoreAdjustment};
+use crate::db::models::profile::DailyScore;
+use crate::db::prelude::{ChannelId, ChannelRepository, ChatterId, ChatterRepository};
+use crate::db::prelude::{KeywordRepository, LeaderboardRepository, Repository};
+use crate::util::archive::ScoreHistory;
+
+/// The longest range of days `score_history` returns at once.
+const MAX_HISTORY_DAYS: i64 = 366;
+
+/// A validated score correction, from either the admin API or the `!adjust` chat command.
+#[derive(Debug)]
+pub(crate) struct ScoreCorrection {
+    pub chatter_id: ChatterId,
+    pub channel_id: ChannelId,
+    /// The first tracked keyword if unset
+    pub keyword: Option<String>,
+    pub adjustment: ScoreAdjustment,
+    pub reason: String,
+}
+
+/// What's recorded in the audit log for each correction.
+#[derive(Debug, Serialize)]
+struct CorrectionAudit<'a> {
+    chatter_id: &'a ChatterId,
+    keyword_id: KeywordId,
+    adjustment: ScoreAdjustment,
+    score: i64,
+    reason: &'a str,
+}
+
+/// Applies a correction and records it in the audit log.
+#[instrument(skip(pool, actor), fields(actor = %actor))]
+pub(crate) async fn apply_correction(
+    pool: &'static Pool<Postgres>,
+    actor: &Actor,
+    correction: ScoreCorrection,
+) -> Result<AdjustedScore, ApiError> {
+    if correction.reason.trim().is_empty() {
+        return Err(ApiError::BadRequest("a reason is required".into()));
+    }
+
+    if !ChatterRepository::new(pool)
+        .exists(&correction.chatter_id)
+        .await?
+    {
+        return Err(ApiError::InvalidUser(correction.chatter_id.0));
+    }
+
+    if !ChannelRepository::new(pool)
+        .exists(&correction.channel_id)
+        .await?
+    {
+        return Err(ApiError::InvalidUser(correction.channel_id.0));
+    }
+
+    let keyword_repo = KeywordRepository::new(pool);
+    let keyword = match &correction.keyword {
+        Some(word) => keyword_repo.get_by_word(word, KeywordKind::Text).await?,
+        None => keyword_repo.get_all().await?.into_iter().next(),
+    }
+    .ok_or_else(|| ApiError::BadRequest("unknown keyword".into()))?;
+
+    let adjusted = LeaderboardRepository::new(pool)
+        .adjust_score(
+            &correction.chatter_id,
+            &correction.channel_id,
+            &keyword.id,
+            correction.adjustment,
+        )
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("scores can't be adjusted below zero".into()))?;
+
+    tracing::info!(?adjusted, reason = correction.reason, "adjusted score");
+    Audit::new(AuditAction::ScoreAdjusted)
+        .target(&correction.channel_id)
+        .before(&adjusted.before)
+        .after(&CorrectionAudit {
+            chatter_id: &correction.chatter_id,
+            keyword_id: keyword.id,
+            adjustment: correction.adjustment,
+            score: adjusted.after,
+            reason: &correction.reason,
+        })
+        .record(pool, actor)
+        .await;
+
+    Ok(adjusted)
+}
+
+/// POST
+///
+/// Corrects a chatter's score in a channel, either by `delta` or to an absolute `value`, and
+/// recalculates the chatter's and channel's totals. Every correction needs a `reason`, which is
+/// recorded in the audit log.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn adjust_score(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ScoreAdjustRequest>,
+) -> ApiResult<AdjustedScore> {
+    let adjustment = match (payload.delta, payload.value) {
+        (Some(delta), None) => ScoreAdjustment::By(delta),
+        (None, Some(value)) => ScoreAdjustment::To(value),
+        _ => {
+            return Err(ApiError::BadRequest(
+                "exactly one of delta or value is required".into(),
+            ));
+        }
+    };
+
+    let chatter_id = ChatterId::try_from(payload.chatter_id.as_str())
+        .map_err(|_| ApiError::InvalidUser(payload.chatter_id.clone()))?;
+    let channel_id = ChannelId::try_from(payload.channel_id.as_str())
+        .map_err(|_| ApiError::InvalidUser(payload.channel_id.clone()))?;
+
+    let correction = ScoreCorrection {
+        chatter_id,
+        channel_id,
+        keyword: payload.keyword,
+        adjustment,
+        reason: payload.reason,
+    };
+    let adjusted = apply_correction(state.database_pool, &actor, correction).await?;
+
+    Ok(ApiResponse::ok(adjusted))
+}
+
+/// GET
+///
+/// Scores counted on each (UTC) day from `from` to `to` (inclusive, and at most a year apart),
+/// optionally limited to a `channel_id` and/or `chatter_id`. Days whose score events have been
+/// archived are read back from their archives, so history reaches back past the retention window.
+#[instrument(skip(state))]
+pub async fn score_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ScoreHistoryQuery>,
+) -> ApiResult<Vec<DailyScore>> {
+    let to = query.to.unwrap_or_else(|| chrono::Utc::now().date_naive());
+    if to < query.from || (to - query.from).num_days() >= MAX_HISTORY_DAYS {
+        return Err(ApiError::BadRequest(format!(
+            "from must be before to, and at most {MAX_HISTORY_DAYS} days earlier"
+        )));
+    }
+
+    let channel_id = match &query.channel_id {
+        Some(id) => {
+            Some(ChannelId::try_from(id.as_str()).map_err(|_| ApiError::InvalidUser(id.clone()))?)
+        }
+        None => None,
+    };
+    let chatter_id = match &query.chatter_id {
+        Some(id) => {
+            Some(ChatterId::try_from(id.as_str()).map_err(|_| ApiError::InvalidUser(id.clone()))?)
+        }
+        None => None,
+    };
+
+    let history = ScoreHistory::new(state.database_pool)
+        .daily(channel_id.as_ref(), chatter_id.as_ref(), query.from, to)
+        .await?;
+
+    Ok(ApiResponse::ok(history))
+}
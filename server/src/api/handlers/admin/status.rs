@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use http::StatusCode;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::api::error::ApiError;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::stats::TableStats;
+use crate::db::prelude::StatsRepository;
+use crate::db::redis::sync::{self, DriftReport};
+use crate::db::replica::ReplicaStatus;
+use crate::util::availability::{self, AvailabilityStatus};
+use crate::util::trace_buffer::{TraceEvent, TraceFilter, TraceQuery, trace_buffer};
+
+/// GET
+///
+/// Health, replication lag, and connection counts for each configured read replica; an empty
+/// list means reads are served by the primary.
+#[instrument(skip(state))]
+pub async fn replicas(State(state): State<Arc<AppState>>) -> ApiResult<Vec<ReplicaStatus>> {
+    Ok(ApiResponse::ok(state.replicas.status()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DbStats {
+    /// Open connections to the primary
+    pub pool_size: u32,
+    pub pool_idle: usize,
+    pub tables: Vec<TableStats>,
+}
+
+/// GET
+///
+/// Primary connection pool usage, plus the row count and most recent write for each table.
+#[instrument(skip(state))]
+pub async fn db_stats(State(state): State<Arc<AppState>>) -> ApiResult<DbStats> {
+    let tables = StatsRepository::new(state.database_pool)
+        .get_table_stats()
+        .await?;
+
+    Ok(ApiResponse::ok(DbStats {
+        pool_size: state.database_pool.size(),
+        pool_idle: state.database_pool.num_idle(),
+        tables,
+    }))
+}
+
+/// GET
+///
+/// Runs an on-demand comparison of chatter/channel totals between Postgres and the legacy Redis
+/// keys.
+#[instrument(skip(state))]
+pub async fn score_drift(State(state): State<Arc<AppState>>) -> ApiResult<DriftReport> {
+    let mut conn = state.redis_pool.clone();
+    let report = sync::reconcile(&mut conn, state.database_pool).await?;
+
+    Ok(ApiResponse::ok(report))
+}
+
+/// GET
+///
+/// Current Twitch availability state, including consecutive IRC/Helix failure counts.
+#[instrument]
+pub async fn availability() -> ApiResult<AvailabilityStatus> {
+    Ok(ApiResponse::ok(availability::availability().status()))
+}
+
+/// GET
+///
+/// Most recent buffered tracing events (newest first), optionally filtered by module, channel,
+/// and/or level - see `TraceFilter` for the filter syntax.
+#[instrument]
+pub async fn trace_events(Query(query): Query<TraceQuery>) -> ApiResult<Vec<TraceEvent>> {
+    let filter = match query.filter.as_deref() {
+        Some(filter) => TraceFilter::parse(filter).map_err(|e| {
+            tracing::warn!(error = e, "invalid trace filter");
+            ApiError::GenericStatusCode(StatusCode::BAD_REQUEST)
+        })?,
+        None => TraceFilter::default(),
+    };
+
+    Ok(ApiResponse::ok(trace_buffer().query(&filter, query.limit)))
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use axum::Extension;
+use axum::extract::{Path, State};
+use http::StatusCode;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::token::ApiToken;
+use crate::db::prelude::ApiTokenRepository;
+
+/// GET
+///
+/// Every issued API token, newest first. Tokens are minted with the `token mint` subcommand.
+#[instrument(skip(state))]
+pub async fn api_tokens(State(state): State<Arc<AppState>>) -> ApiResult<Vec<ApiToken>> {
+    let tokens = ApiTokenRepository::new(state.database_pool)
+        .get_all()
+        .await?;
+
+    Ok(ApiResponse::ok(tokens))
+}
+
+/// DELETE
+///
+/// Revokes a token; it's rejected from then on.
+#[instrument(skip(state, actor), fields(actor = %actor))]
+pub async fn revoke_api_token(
+    State(state): State<Arc<AppState>>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+) -> ApiResult<()> {
+    spawn_protected(async move {
+        let token = ApiTokenRepository::new(state.database_pool)
+            .revoke(&id)
+            .await?
+            .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+        tracing::info!(id, label = token.label, "api token revoked");
+        Audit::new(AuditAction::ApiTokenRevoked)
+            .target(&token.id)
+            .after(&token)
+            .record(state.database_pool, &actor)
+            .await;
+
+        Ok(())
+    })
+    .await?;
+
+    Ok(ApiResponse::<()>::empty())
+}
@@ -3,20 +3,54 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use axum::Json;
+use axum::body::Body;
 use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Response};
+use chrono::{Timelike, Utc};
+use futures::TryStreamExt;
+use http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use http::{HeaderMap, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
 use serde::Serialize;
 use tracing::instrument;
 
-use crate::api::extractors::{ScoreVariant, ScoreWindowQuery};
-use crate::api::server::{ApiResponse, ApiResult, AppState, RouteError};
-use crate::db::models::channel::ChannelReplies;
-use crate::db::models::leaderboard::TimeWindow;
-use crate::db::models::{PaginatedResponse, Pagination};
-use crate::db::prelude::LeaderboardRepository;
-use crate::db::prelude::Repository;
-use crate::db::prelude::{ChannelLeaderboardEntry, Chatter};
-use crate::db::prelude::{ChatterId, ChatterRepository};
+use crate::api::auth::{AuthError, Authorized, ExportScope, IncrementScope};
+use crate::api::conditional;
+use crate::api::dto::v1::{BotChannel, ChannelEntry, Page, Profile};
+use crate::api::error::ApiError;
+use crate::api::extractors::{DigestQuery, IncrementRequest, TimelineQuery};
+use crate::api::extractors::{ExportQuery, PeriodQuery, ScoreVariant, ScoreWindowQuery};
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::Pagination;
+use crate::db::models::channel::{ChannelId, ChannelReplies};
+use crate::db::models::digest::Digest;
+use crate::db::models::heatmap::ChannelHeatmap;
+use crate::db::models::keyword::KeywordKind;
+use crate::db::models::leaderboard::{Period, TimeWindow};
+use crate::db::models::metric::ChannelMetrics;
+use crate::db::models::raid::ScoreTimeline;
+use crate::db::models::rank::ChannelRank;
+use crate::db::models::stream::StreamStatus;
+use crate::db::prelude::{ChannelRepository, Repository};
+use crate::db::prelude::{ChatterId, ChatterRepository, HeatmapRepository, StreamStatusRepository};
+use crate::db::prelude::{DigestRepository, KeywordRepository, MetricRepository};
+use crate::db::prelude::{LeaderboardRepository, PeriodRepository, RaidRepository, RankRepository};
 use crate::db::repositories::leaderboard::ScorePagination;
+use crate::irc::message::ChatMessage;
+use crate::irc::permission::PermissionLevel;
+use crate::irc::room_state::room_states;
+use crate::irc::worker::CountOutcome;
+use crate::util::export;
+
+/// The longest timeline that can be requested, in hours.
+const MAX_TIMELINE_HOURS: u32 = 24 * 7;
+/// The most digests that can be requested at once.
+const MAX_DIGEST_DAYS: u32 = 31;
+/// The occurrences a single pushed increment can count.
+const INCREMENT_COUNT_RANGE: std::ops::RangeInclusive<u32> = 1..=10;
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
 
 #[derive(Debug, Serialize)]
 pub struct WindowedScores {
@@ -93,11 +127,12 @@ pub async fn channel_name_list(State(state): State<Arc<AppState>>) -> ApiResult<
 /// * GET
 ///
 ///     ```http
-///     /api/v1/channels/leaderboard?limit=[LIMIT]&page=[PAGE]&score_page=0&score_limit=0
+///     /api/v1/channels/leaderboard?limit=[LIMIT]&page=[PAGE]&score_page=0&score_limit=0&period=[PERIOD]
 ///     ```
 ///
 ///     Params:
 ///
+///     - `period`:         `day`, `week` or `month` to rank by scores earned in the current period (without per-chatter scores), or `all` (default).
 ///     - `limit`:          number of items on the retrieved page. valid range is `0 <= limit <= MAX_U64`
 ///     - `page`:           retrieve items starting with `limit * page`. valid range is `0 <= page <= BROADCASTER_COUNT`
 ///     - `score_page`:     should be set to 0; consumed downstream by SQL queries but not relevant for this function.
@@ -105,23 +140,50 @@ pub async fn channel_name_list(State(state): State<Arc<AppState>>) -> ApiResult<
 #[instrument(skip(state))]
 pub async fn channel_leaderboard(
     Query(param): Query<Pagination>,
+    Query(period): Query<PeriodQuery>,
     State(state): State<Arc<AppState>>,
-) -> ApiResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     let limit = param.limit;
     let offset = param.page * limit;
     let score_limit = param.score_limit;
     let score_offset = param.score_page * score_limit;
 
-    let lb_repo = LeaderboardRepository::new(state.database_pool);
-    let segment = lb_repo
-        .get_channel_leaderboard(
-            limit,
-            offset,
-            &ScorePagination::new(score_limit, score_offset),
-        )
+    let version = LeaderboardRepository::new(state.replicas.reader())
+        .get_version(None, period.period)
         .await?;
 
-    Ok(ApiResponse::ok(segment))
+    conditional::respond(&headers, version, async {
+        let segment = match period.period {
+            Period::All => {
+                state
+                    .scores
+                    .get_channel_leaderboard(
+                        limit,
+                        offset,
+                        &ScorePagination::new(score_limit, score_offset),
+                    )
+                    .await?
+            }
+            period => {
+                PeriodRepository::new(state.replicas.reader())
+                    .get_channel_leaderboard(period, limit, offset)
+                    .await?
+            }
+        };
+
+        let live = StreamStatusRepository::new(state.replicas.reader())
+            .get_live_channel_ids()
+            .await?;
+
+        let mut page: Page<ChannelEntry> = segment.into();
+        for entry in page.items.iter_mut() {
+            entry.live = live.contains(&ChannelId(entry.profile.id.clone()));
+        }
+
+        Ok(ApiResponse::ok(page))
+    })
+    .await
 }
 
 /// Retrieve a channel via `login` along with their associated per-channel leaderboard.
@@ -145,22 +207,28 @@ pub async fn by_login(
     State(state): State<Arc<AppState>>,
     Path(login): Path<String>,
     Query(param): Query<Pagination>,
-) -> ApiResult<ChannelLeaderboardEntry> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     let (ch_repo, lb_repo) = (
-        ChatterRepository::new(state.database_pool),
-        LeaderboardRepository::new(state.database_pool),
+        ChatterRepository::new(state.replicas.reader()),
+        LeaderboardRepository::new(state.replicas.reader()),
     );
 
-    let channel = ch_repo.get_by_login(&login).await?;
-    let ch = lb_repo
-        .get_single_channel_leaderboard(
-            channel.id.into(),
-            ScorePagination::new(param.score_limit, param.score_page * param.score_limit),
-        )
-        .await?
-        .ok_or(RouteError::InvalidUser(login))?;
+    let channel_id: ChannelId = ch_repo.get_by_login(&login).await?.id.into();
+    let version = lb_repo.get_version(Some(&channel_id), Period::All).await?;
+
+    conditional::respond(&headers, version, async {
+        let ch = lb_repo
+            .get_single_channel_leaderboard(
+                channel_id,
+                ScorePagination::new(param.score_limit, param.score_page * param.score_limit),
+            )
+            .await?
+            .ok_or(ApiError::InvalidUser(login))?;
 
-    Ok(ApiResponse::ok(ch))
+        Ok(ApiResponse::ok(with_live_state(&state, ch.into()).await?))
+    })
+    .await
 }
 
 /// Retrieve a channel via its `id`, along with their associated per-channel leaderboard.
@@ -187,16 +255,36 @@ pub async fn by_id(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
     Query(param): Query<Pagination>,
-) -> ApiResult<ChannelLeaderboardEntry> {
-    let ch = LeaderboardRepository::new(state.database_pool)
-        .get_single_channel_leaderboard(
-            id.clone().into(),
-            ScorePagination::new(param.score_limit, param.score_page * param.score_limit),
-        )
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let lb_repo = LeaderboardRepository::new(state.replicas.reader());
+    let channel_id = ChannelId(id.clone());
+    let version = lb_repo.get_version(Some(&channel_id), Period::All).await?;
+
+    conditional::respond(&headers, version, async {
+        let ch = lb_repo
+            .get_single_channel_leaderboard(
+                channel_id,
+                ScorePagination::new(param.score_limit, param.score_page * param.score_limit),
+            )
+            .await?
+            .ok_or(ApiError::InvalidUser(id))?;
+
+        Ok(ApiResponse::ok(with_live_state(&state, ch.into()).await?))
+    })
+    .await
+}
+
+pub(crate) async fn with_live_state(
+    state: &AppState,
+    mut entry: ChannelEntry,
+) -> Result<ChannelEntry, ApiError> {
+    entry.live = StreamStatusRepository::new(state.replicas.reader())
+        .get_by_channel(&ChannelId(entry.profile.id.clone()))
         .await?
-        .ok_or(RouteError::InvalidUser(id))?;
+        .is_some_and(|status| status.is_live);
 
-    Ok(ApiResponse::ok(ch))
+    Ok(entry)
 }
 
 /// Retrieves a list of those broadcasters where bot responses are enabled.
@@ -209,17 +297,19 @@ pub async fn by_id(
 ///     /api/v1/channels/bot-enabled
 ///     ```
 #[instrument(skip(state))]
-pub async fn bot_enabled(State(state): State<Arc<AppState>>) -> ApiResult<Vec<ChannelReplies>> {
+pub async fn bot_enabled(State(state): State<Arc<AppState>>) -> ApiResult<Vec<BotChannel>> {
     let enabled_channels = sqlx::query_as::<_, ChannelReplies>(
         r#"
         SELECT * FROM reply_configuration 
         WHERE enabled = TRUE
         "#,
     )
-    .fetch_all(state.database_pool)
+    .fetch_all(state.replicas.reader())
     .await?;
 
-    Ok(ApiResponse::ok(enabled_channels))
+    Ok(ApiResponse::ok(
+        enabled_channels.into_iter().map(BotChannel::from).collect(),
+    ))
 }
 
 /// Retrieves a list of the current live channels
@@ -233,13 +323,13 @@ pub async fn bot_enabled(State(state): State<Arc<AppState>>) -> ApiResult<Vec<Ch
 ///     ```
 #[instrument(skip(state))]
 #[axum::debug_handler]
-pub async fn live_channels(State(state): State<Arc<AppState>>) -> ApiResult<Vec<Chatter>> {
+pub async fn live_channels(State(state): State<Arc<AppState>>) -> ApiResult<Vec<Profile>> {
     let cached_live_ids = crate::db::redis::get_all_live(&mut state.redis_pool.clone())
         .await
         .unwrap_or_default();
 
     if let Some(live_ids) = cached_live_ids {
-        let broadcasters = ChatterRepository::new(state.database_pool)
+        let broadcasters = ChatterRepository::new(state.replicas.reader())
             .get_many_by_id(
                 &live_ids
                     .into_iter()
@@ -248,7 +338,9 @@ pub async fn live_channels(State(state): State<Arc<AppState>>) -> ApiResult<Vec<
             )
             .await?;
 
-        return Ok(ApiResponse::ok(broadcasters));
+        return Ok(ApiResponse::ok(
+            broadcasters.into_iter().map(Profile::from).collect(),
+        ));
     };
 
     Ok(ApiResponse::ok(Vec::new()))
@@ -261,7 +353,7 @@ pub async fn live_channels(State(state): State<Arc<AppState>>) -> ApiResult<Vec<
 /// Response data is a HashMap where the `key` is the channel status (`missing`/`joined`/`all`)
 /// to the channel name in IRC format (`#channel_name`), e.g.:
 ///
-/// ```
+/// ```json
 /// {
 ///     "joined": [
 ///         "#sleepiebug",
@@ -342,7 +434,7 @@ pub async fn channel_score_windows(
     Path(id): Path<String>,
     Query(window): Query<ScoreWindowQuery>,
 ) -> ApiResult<WindowedScores> {
-    let pool = state.database_pool;
+    let pool = state.replicas.reader();
     let variant_str = match window.variant {
         ScoreVariant::Channel => "channel",
         ScoreVariant::Chatter => "chatter",
@@ -379,3 +471,476 @@ pub async fn channel_score_windows(
 
     Ok(ApiResponse::ok(windows))
 }
+
+/// Retrieve a channel's score counts by hour of the week, in the channel's local timezone.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/heatmap
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+#[instrument(skip(state))]
+pub async fn heatmap(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+) -> ApiResult<ChannelHeatmap> {
+    let pool = state.replicas.reader();
+    let channel = match ChatterRepository::new(pool)
+        .get_by_login(&login.to_lowercase())
+        .await
+    {
+        Ok(ch) => ch,
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::InvalidUser(login)),
+        Err(e) => return Err(ApiError::from(e)),
+    };
+
+    let channel_id = ChannelId::from(channel.id);
+    let heatmap_repo = HeatmapRepository::new(pool);
+    let timezone = heatmap_repo
+        .get_timezone(&channel_id)
+        .await?
+        .ok_or(ApiError::InvalidUser(login))?;
+
+    let buckets = heatmap_repo.get_buckets(&channel_id).await?;
+
+    Ok(ApiResponse::ok(ChannelHeatmap::from_buckets(
+        channel_id, timezone, &buckets,
+    )))
+}
+
+/// Retrieve a channel's live state, viewer count and game as of the last refresh from Helix, along
+/// with its chat modes if this instance has joined it.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/status
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+#[instrument(skip(state))]
+pub async fn stream_status(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+) -> ApiResult<StreamStatus> {
+    let pool = state.replicas.reader();
+    let channel = match ChatterRepository::new(pool)
+        .get_by_login(&login.to_lowercase())
+        .await
+    {
+        Ok(ch) => ch,
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::InvalidUser(login)),
+        Err(e) => return Err(ApiError::from(e)),
+    };
+
+    let mut status = StreamStatusRepository::new(pool)
+        .get_by_channel(&ChannelId::from(channel.id))
+        .await?
+        .ok_or(ApiError::InvalidUser(login))?;
+    status.room_state = room_states().get(&channel.login);
+
+    Ok(ApiResponse::ok(status))
+}
+
+/// Retrieve a chatter's rank on a channel's leaderboard.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/rank/{USER}
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///     - {USER}:   the login of a chatter; responds with a 404 if they haven't scored in the channel.
+#[instrument(skip(state))]
+pub async fn rank(
+    State(state): State<Arc<AppState>>,
+    Path((login, user)): Path<(String, String)>,
+) -> ApiResult<ChannelRank> {
+    let pool = state.replicas.reader();
+    let repo = ChatterRepository::new(pool);
+    let channel_id = ChannelId::from(resolve_login(&repo, login).await?);
+    let chatter_id = resolve_login(&repo, user.clone()).await?;
+
+    let rank = RankRepository::new(pool)
+        .get_channel_rank(&channel_id, &chatter_id)
+        .await?
+        .ok_or(ApiError::InvalidUser(user))?;
+
+    Ok(ApiResponse::ok(rank))
+}
+
+/// Export a channel's full leaderboard, streamed as a chunked response. Requires an API token
+/// with the `export` scope.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/export?format=[csv|json]
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///
+///     Params:
+///
+///     - `format`: `csv` (default) or `json`.
+#[instrument(skip(state))]
+pub async fn export(
+    State(state): State<Arc<AppState>>,
+    _token: Authorized<ExportScope>,
+    Path(login): Path<String>,
+    Query(param): Query<ExportQuery>,
+) -> Result<Response, ApiError> {
+    let pool = state.replicas.reader();
+    let channel_id =
+        ChannelId::from(resolve_login(&ChatterRepository::new(pool), login.clone()).await?);
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::InvalidUser(login));
+    }
+
+    // headers have already been sent by the time a batch fails, so the body is just truncated
+    let chunks = export::channel_leaderboard(pool, channel_id, param.format)
+        .inspect_err(|e| tracing::error!(error = ?e, "leaderboard export failed"));
+
+    let disposition = format!(
+        "attachment; filename=\"{}.{}\"",
+        login.to_lowercase(),
+        param.format.extension()
+    );
+
+    Ok((
+        [
+            (CONTENT_TYPE, param.format.content_type().to_string()),
+            (CONTENT_DISPOSITION, disposition),
+        ],
+        Body::from_stream(chunks),
+    )
+        .into_response())
+}
+
+/// Count increments pushed by an external tool (e.g. a stream deck button) as though the chatter
+/// had mentioned the keyword `count` times in one chat message, through the same pause, blacklist,
+/// live state, count mode, muted keyword and score limit checks. Requires an API token with the
+/// `increment` scope that was minted for the channel (or one with the `admin` scope).
+///
+/// Requests with the same `Idempotency-Key` (per token) are only counted once, for as long as
+/// chat message ids are remembered (see `irc::dedupe`); repeats report the increment as skipped.
+///
+/// # Methods
+///
+/// * POST
+///
+///     ```http
+///     /api/v1/channels/{LOGIN}/increment
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///
+///     Body:
+///
+///     - `chatter`:    login of the chatter to count, defaulting to the broadcaster.
+///     - `keyword`:    the text keyword to count.
+///     - `count`:      occurrences of the keyword. valid range is `1 <= count <= 10`, defaulting
+///                     to 1.
+#[instrument(skip(state, token, headers), fields(token = token.claims.id))]
+pub async fn increment(
+    State(state): State<Arc<AppState>>,
+    token: Authorized<IncrementScope>,
+    Path(login): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<IncrementRequest>,
+) -> ApiResult<CountOutcome> {
+    let pool = state.database_pool;
+    let counter = state
+        .irc_connection
+        .counter
+        .clone()
+        .ok_or(ApiError::GenericStatusCode(StatusCode::SERVICE_UNAVAILABLE))?;
+
+    if !INCREMENT_COUNT_RANGE.contains(&payload.count) {
+        return Err(ApiError::BadRequest(format!(
+            "count must be within {INCREMENT_COUNT_RANGE:?}"
+        )));
+    }
+    let message_id = match headers.get(IDEMPOTENCY_KEY) {
+        Some(key) => {
+            let key = key
+                .to_str()
+                .ok()
+                .map(str::trim)
+                .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+                .ok_or_else(|| ApiError::BadRequest("invalid idempotency key".into()))?;
+            idempotent_message_id(&token.claims.id, key)
+        }
+        None => random_message_id(),
+    };
+
+    let repo = ChatterRepository::new(pool);
+    let channel = match repo.get_by_login(&login.to_lowercase()).await {
+        Ok(channel) => channel,
+        Err(sqlx::Error::RowNotFound) => return Err(ApiError::InvalidUser(login)),
+        Err(e) => return Err(e.into()),
+    };
+    let channel_id = ChannelId::from(channel.id.clone());
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::InvalidUser(login));
+    }
+
+    if !token.claims.allows_channel(&channel_id) {
+        tracing::warn!(channel = %channel_id, "api token used for another channel");
+        return Err(AuthError::ChannelNotAllowed.into());
+    }
+
+    let chatter = match payload.chatter {
+        Some(chatter) => match repo
+            .get_by_login(&chatter.trim_start_matches('@').to_lowercase())
+            .await
+        {
+            Ok(chatter) => chatter,
+            Err(sqlx::Error::RowNotFound) => return Err(ApiError::InvalidUser(chatter)),
+            Err(e) => return Err(e.into()),
+        },
+        None => channel.clone(),
+    };
+
+    let keyword = KeywordRepository::new(pool)
+        .get_by_word(&payload.keyword, KeywordKind::Text)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("unknown keyword".into()))?;
+
+    let message = ChatMessage {
+        channel_id,
+        channel_login: channel.login,
+        source_channel_id: None,
+        user_id: chatter.id,
+        user_login: chatter.login,
+        display_name: chatter.name,
+        color: chatter.color,
+        text: String::new(),
+        emotes: Vec::new(),
+        message_id,
+        sent_at: Utc::now().naive_utc(),
+        badges: Vec::new(),
+        permission: PermissionLevel::default(),
+    };
+
+    let outcome = counter
+        .count(&message, &[(keyword.id, payload.count as usize)])
+        .await?;
+    tracing::info!(
+        channel = message.channel_login,
+        chatter = message.user_login,
+        counted = outcome.counted.len(),
+        skipped = ?outcome.skipped,
+        "counted pushed increment"
+    );
+
+    Ok(ApiResponse::ok(outcome))
+}
+
+/// Message ids are at most 36 characters, so keys are hashed (along with the token's id, so that
+/// tokens can't collide) into one.
+fn idempotent_message_id(token_id: &str, key: &str) -> String {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA256,
+        format!("{token_id}:{key}").as_bytes(),
+    );
+
+    format!("api-{}", &hex::encode(digest.as_ref())[..32])
+}
+
+fn random_message_id() -> String {
+    let mut bytes = [0u8; 16];
+    _ = SystemRandom::new().fill(&mut bytes);
+
+    format!("api-{}", hex::encode(bytes))
+}
+
+/// Retrieve a channel's score counts by hour, along with the raids it received over the same hours.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/timeline?hours=[HOURS]
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///
+///     Params:
+///
+///     - `hours`:  number of hours to include, up to and including the current hour. valid range
+///                 is `1 <= hours <= 168`, defaulting to 24.
+#[instrument(skip(state))]
+pub async fn timeline(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+    Query(param): Query<TimelineQuery>,
+) -> ApiResult<ScoreTimeline> {
+    let pool = state.replicas.reader();
+    let channel_id =
+        ChannelId::from(resolve_login(&ChatterRepository::new(pool), login.clone()).await?);
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::InvalidUser(login));
+    }
+
+    let hours = i64::from(param.hours.clamp(1, MAX_TIMELINE_HOURS));
+    let now = Utc::now().naive_utc();
+    let current_hour = now.date().and_hms_opt(now.hour(), 0, 0).unwrap_or(now);
+    let start = current_hour - chrono::Duration::hours(hours - 1);
+
+    let hourly = HeatmapRepository::new(pool)
+        .get_hourly(&channel_id, start)
+        .await?;
+    let raids = RaidRepository::new(pool)
+        .get_since(&channel_id, start)
+        .await?;
+
+    Ok(ApiResponse::ok(ScoreTimeline::from_hours(
+        channel_id, start, hours, &hourly, raids,
+    )))
+}
+
+/// Retrieve a channel's most recent daily digests, newest first. Digests are only compiled for
+/// channels with a `digest_hour` set.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/digests?days=[DAYS]
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///
+///     Params:
+///
+///     - `days`:   number of digests to include. valid range is `1 <= days <= 31`, defaulting
+///                 to 7.
+#[instrument(skip(state))]
+pub async fn digests(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+    Query(param): Query<DigestQuery>,
+) -> ApiResult<Vec<Digest>> {
+    let pool = state.replicas.reader();
+    let channel_id =
+        ChannelId::from(resolve_login(&ChatterRepository::new(pool), login.clone()).await?);
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::InvalidUser(login));
+    }
+
+    let days = i64::from(param.days.clamp(1, MAX_DIGEST_DAYS));
+    let digests = DigestRepository::new(pool)
+        .get_for_channel(&channel_id, days)
+        .await?;
+
+    Ok(ApiResponse::ok(digests))
+}
+
+/// Retrieve a channel's subscription and cheer totals, counted from EventSub separately to its
+/// keyword scores.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/channels/by-login/{LOGIN}/metrics?period=[PERIOD]
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+///
+///     Params:
+///
+///     - `period`: `day`, `week` or `month` for totals in the current period, or `all` (default).
+#[instrument(skip(state))]
+pub async fn metrics(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+    Query(param): Query<PeriodQuery>,
+) -> ApiResult<ChannelMetrics> {
+    let pool = state.replicas.reader();
+    let channel_id =
+        ChannelId::from(resolve_login(&ChatterRepository::new(pool), login.clone()).await?);
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::InvalidUser(login));
+    }
+
+    let totals = MetricRepository::new(pool)
+        .get_totals(&channel_id, param.period)
+        .await?;
+
+    Ok(ApiResponse::ok(ChannelMetrics::from_totals(
+        channel_id,
+        param.period,
+        &totals,
+    )))
+}
+
+async fn resolve_login(repo: &ChatterRepository, login: String) -> Result<ChatterId, ApiError> {
+    match repo.get_by_login(&login.to_lowercase()).await {
+        Ok(chatter) => Ok(chatter.id),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::InvalidUser(login)),
+        Err(e) => Err(ApiError::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pushed_message_ids_fit_the_msg_id_column() {
+        let (token, other) = ("0011223344556677", "8899aabbccddeeff");
+        let key = "k".repeat(MAX_IDEMPOTENCY_KEY_LEN);
+
+        let id = idempotent_message_id(token, &key);
+        assert_eq!(id.len(), 36);
+        assert_eq!(random_message_id().len(), 36);
+
+        // the same key is only the same message for the same token
+        assert_eq!(id, idempotent_message_id(token, &key));
+        assert_ne!(id, idempotent_message_id(other, &key));
+    }
+}
@@ -3,14 +3,34 @@
 use std::sync::Arc;
 
 use axum::extract::{Path, Query, State};
+use axum::response::{IntoResponse, Redirect, Response};
+use chrono::Utc;
+use http::header::CACHE_CONTROL;
+use http::{HeaderMap, HeaderValue, StatusCode};
 use tracing::instrument;
 
-use crate::api::server::{ApiResponse, ApiResult, AppState, RouteError};
+use crate::api::conditional;
+use crate::api::dto::v1::{
+    ChatterEntry, ChatterProfileEntry, Page, Profile, SearchResult, SearchResults,
+};
+use crate::api::error::ApiError;
+use crate::api::extractors::{CompareQuery, PeriodQuery};
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::Pagination;
 use crate::db::models::chatter::ChatterSearchResult;
-use crate::db::models::{PaginatedResponse, Pagination};
-use crate::db::prelude::{ChatterId, Repository};
-use crate::db::prelude::{ChatterLeaderboardEntry, ChatterRepository, LeaderboardRepository};
-use crate::util::is_user_id;
+use crate::db::models::leaderboard::Period;
+use crate::db::models::profile::ChatterProfile;
+use crate::db::models::rank::Versus;
+use crate::db::prelude::{ChannelId, ProfileRepository, RankRepository};
+use crate::db::prelude::{Chatter, ChatterId, Repository};
+use crate::db::prelude::{ChatterRepository, LeaderboardRepository, PeriodRepository};
+use crate::db::store::RankTarget;
+use crate::util::{avatar, is_user_id};
+
+/// Days of activity included in a chatter's profile, up to and including today
+const PROFILE_ACTIVITY_DAYS: u64 = 30;
+/// Avatar redirects are cached briefly so that a rotated image is picked up again soon after
+const AVATAR_CACHE_CONTROL: &str = "public, max-age=300";
 
 /// Query the database for a chatter given their login or ID.
 ///
@@ -31,8 +51,8 @@ use crate::util::is_user_id;
 pub async fn search(
     State(state): State<Arc<AppState>>,
     Path(query): Path<String>,
-) -> ApiResult<(Vec<ChatterSearchResult>, i64)> {
-    let chatter_repo = ChatterRepository::new(state.database_pool);
+) -> ApiResult<SearchResults> {
+    let chatter_repo = ChatterRepository::new(state.replicas.reader());
 
     // I don't think Twitch lets you have a number-only login (?)
     let result = if is_user_id(&query) {
@@ -40,8 +60,9 @@ pub async fn search(
         let chatter_id = ChatterId::from(query.clone());
 
         if let Some(chatter) = chatter_repo.get_by_id(&chatter_id).await?
-            && let Some(ranking) = LeaderboardRepository::new(state.database_pool)
-                .get_chatter_rank(&chatter_id)
+            && let Some(ranking) = state
+                .scores
+                .get_rank(RankTarget::Chatter(&chatter_id))
                 .await?
         {
             vec![ChatterSearchResult {
@@ -63,16 +84,15 @@ pub async fn search(
         chatter_repo.search_by_login(&query).await?
     };
 
-    let total: i64 = sqlx::query_scalar!(
-        r#"
-        SELECT COUNT(*) FROM chatter
-        "#,
-    )
-    .fetch_one(state.database_pool)
-    .await?
-    .unwrap_or(0);
+    let total: i64 =
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM chatter WHERE deleted_at IS NULL")
+            .fetch_one(state.replicas.reader())
+            .await?;
 
-    Ok(ApiResponse::ok((result, total)))
+    Ok(ApiResponse::ok(SearchResults {
+        results: result.into_iter().map(SearchResult::from).collect(),
+        total_chatters: total,
+    }))
 }
 
 /// Retrieves the global chatter leaderboard
@@ -82,7 +102,7 @@ pub async fn search(
 /// * GET
 ///
 ///     ```http
-///     /api/v1/chatter/leaderboard?score_page=[SCORE_PAGE]&score_limit=[SCORE_LIMIT]&limit=0&page=0
+///     /api/v1/chatter/leaderboard?score_page=[SCORE_PAGE]&score_limit=[SCORE_LIMIT]&limit=0&page=0&period=[PERIOD]
 ///     ```
 ///
 ///     Path:
@@ -90,6 +110,7 @@ pub async fn search(
 ///
 ///     Params:
 ///
+///     - `period`:         `day`, `week` or `month` to rank by scores earned in the current period (without per-channel scores), or `all` (default).
 ///     - `limit`:          number of items on the retrieved page. valid range is `0 <= limit <= MAX_U64`
 ///     - `page`:           retrieve items starting with `limit * page`. valid range is `0 <= page <= MAX_U64`
 ///     - `score_page`:     should be set to 0; consumed downstream by SQL queries but not relevant for this function.
@@ -97,15 +118,30 @@ pub async fn search(
 #[instrument(skip(state))]
 pub async fn chatter_leaderboard(
     Query(param): Query<Pagination>,
+    Query(period): Query<PeriodQuery>,
     State(state): State<Arc<AppState>>,
-) -> ApiResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     let limit = param.limit;
     let offset = param.page * limit;
 
-    let lb_repo = LeaderboardRepository::new(state.database_pool);
-    let segment = lb_repo.get_chatter_leaderboard(limit, offset).await?;
+    let version = LeaderboardRepository::new(state.replicas.reader())
+        .get_version(None, period.period)
+        .await?;
+
+    conditional::respond(&headers, version, async {
+        let segment = match period.period {
+            Period::All => state.scores.get_chatter_leaderboard(limit, offset).await?,
+            period => {
+                PeriodRepository::new(state.replicas.reader())
+                    .get_chatter_leaderboard(period, limit, offset)
+                    .await?
+            }
+        };
 
-    Ok(ApiResponse::ok(segment))
+        Ok(ApiResponse::ok(Page::<ChatterEntry>::from(segment)))
+    })
+    .await
 }
 
 /// Retrieve a chatter via `login`, along with the associated per-channel leaderboard.
@@ -124,19 +160,107 @@ pub async fn chatter_leaderboard(
 pub async fn by_login(
     State(state): State<Arc<AppState>>,
     Path(login): Path<String>,
-) -> ApiResult<ChatterLeaderboardEntry> {
+) -> ApiResult<ChatterEntry> {
     let (ch_repo, lb_repo) = (
-        ChatterRepository::new(state.database_pool),
-        LeaderboardRepository::new(state.database_pool),
+        ChatterRepository::new(state.replicas.reader()),
+        LeaderboardRepository::new(state.replicas.reader()),
     );
 
     let chatter = ch_repo.get_by_login(&login).await?;
     let ch = lb_repo
         .get_single_chatter_leaderboard(chatter.id.clone())
         .await?
-        .ok_or(RouteError::InvalidUser(chatter.id.0))?;
+        .ok_or(ApiError::InvalidUser(chatter.id.0))?;
+
+    Ok(ApiResponse::ok(ch.into()))
+}
+
+/// Retrieve a chatter's profile via `login`: their global total and rank, their score and rank in
+/// each channel, when they were first and last counted, and their daily scores over the last
+/// `PROFILE_ACTIVITY_DAYS` days.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/chatter/by-login/{login}/profile
+///     ```
+///
+///     Path:
+///     - {login}:             the login of a chatter.
+#[instrument(skip(state))]
+pub async fn profile_by_login(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+) -> ApiResult<ChatterProfileEntry> {
+    let pool = state.replicas.reader();
+    let chatter = ProfileRepository::new(pool)
+        .get_by_login(&login.to_lowercase())
+        .await?
+        .ok_or(ApiError::InvalidUser(login))?;
+
+    let channel_scores = LeaderboardRepository::new(pool)
+        .get_channel_scores_batch(std::slice::from_ref(&chatter.id))
+        .await?;
+
+    let today = Utc::now().date_naive();
+    let start = today - chrono::Days::new(PROFILE_ACTIVITY_DAYS - 1);
+    let daily = ProfileRepository::new(pool)
+        .get_daily(&chatter.id, start)
+        .await?;
+
+    let profile = ChatterProfile::new(
+        chatter,
+        channel_scores,
+        start,
+        PROFILE_ACTIVITY_DAYS,
+        &daily,
+    );
+
+    Ok(ApiResponse::ok(profile.into()))
+}
+
+/// Compare two chatters head to head: their score and rank on a channel (if given) and on the
+/// global leaderboard.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/compare?users=[LOGIN],[LOGIN]&channel=[CHANNEL]
+///     ```
+///
+///     Params:
+///
+///     - `users`:          the logins of two different chatters, separated by a comma.
+///     - `channel`:        the login of a broadcaster; optional.
+#[instrument(skip(state))]
+pub async fn compare(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CompareQuery>,
+) -> ApiResult<Versus> {
+    let logins = parse_contenders(&query.users).map_err(ApiError::BadRequest)?;
+    let pool = state.replicas.reader();
+    let repo = ChatterRepository::new(pool);
 
-    Ok(ApiResponse::ok(ch))
+    let channel_id = match query.channel {
+        Some(login) => Some(ChannelId::from(chatter_by_login(&repo, login).await?.id)),
+        None => None,
+    };
+
+    let [a, b] = logins;
+    let chatters = [
+        chatter_by_login(&repo, a).await?,
+        chatter_by_login(&repo, b).await?,
+    ];
+
+    let versus = RankRepository::new(pool)
+        .get_versus(channel_id.as_ref(), chatters)
+        .await?;
+
+    Ok(ApiResponse::ok(versus))
 }
 
 /// Retrieve a chatter via `id`, along with the associated per-channel leaderboard.
@@ -155,11 +279,116 @@ pub async fn by_login(
 pub async fn by_id(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-) -> ApiResult<ChatterLeaderboardEntry> {
-    let ch = LeaderboardRepository::new(state.database_pool)
+) -> ApiResult<ChatterEntry> {
+    let ch = LeaderboardRepository::new(state.replicas.reader())
         .get_single_chatter_leaderboard(id.clone().into())
         .await?
-        .ok_or(RouteError::InvalidUser(id))?;
+        .ok_or(ApiError::InvalidUser(id))?;
+
+    Ok(ApiResponse::ok(ch.into()))
+}
+
+/// Redirect to a chatter's current profile image, refreshing it from Helix if the stored image
+/// URL has gone stale.
+///
+/// Intended to be used directly as an `<img>` source in place of the stored CDN URL.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/chatter/by-id/{id}/avatar
+///     ```
+///
+///     Path:
+///     - {id}:             the id of a chatter.
+#[instrument(skip(state))]
+pub async fn avatar(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let chatter = fresh_chatter_by_id(&state, id).await?;
+    if chatter.image.is_empty() {
+        return Err(ApiError::GenericStatusCode(StatusCode::NOT_FOUND));
+    }
+
+    let mut response = Redirect::temporary(&chatter.image).into_response();
+    response.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static(AVATAR_CACHE_CONTROL),
+    );
+
+    Ok(response)
+}
+
+/// Retrieve a chatter's profile (including their image and color), refreshing it from Helix if
+/// the stored image URL has gone stale.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/chatter/by-id/{id}/profile
+///     ```
+///
+///     Path:
+///     - {id}:             the id of a chatter.
+#[instrument(skip(state))]
+pub async fn profile(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> ApiResult<Profile> {
+    let chatter = fresh_chatter_by_id(&state, id).await?;
+    Ok(ApiResponse::ok(chatter.into()))
+}
+
+async fn chatter_by_login(repo: &ChatterRepository, login: String) -> Result<Chatter, ApiError> {
+    match repo.get_by_login(&login.to_lowercase()).await {
+        Ok(chatter) => Ok(chatter),
+        Err(sqlx::Error::RowNotFound) => Err(ApiError::InvalidUser(login)),
+        Err(e) => Err(ApiError::from(e)),
+    }
+}
+
+/// Splits `users` into exactly two different logins, ignoring any leading `@`.
+fn parse_contenders(users: &str) -> Result<[String; 2], String> {
+    let logins: Vec<String> = users
+        .split(',')
+        .map(|login| login.trim().trim_start_matches('@').to_lowercase())
+        .filter(|login| !login.is_empty())
+        .collect();
+
+    match <[String; 2]>::try_from(logins) {
+        Ok([a, b]) if a != b => Ok([a, b]),
+        Ok(_) => Err("can't compare a chatter with themselves".into()),
+        Err(_) => Err("`users` must be two comma-separated logins".into()),
+    }
+}
+
+async fn fresh_chatter_by_id(state: &AppState, id: String) -> Result<Chatter, ApiError> {
+    let chatter_id =
+        ChatterId::try_from(id.as_str()).map_err(|_| ApiError::InvalidUser(id.clone()))?;
+
+    // uses the primary, as a stale image is written back to the chatter's row
+    avatar::fresh_chatter(state.database_pool, &chatter_id)
+        .await?
+        .ok_or(ApiError::InvalidUser(id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    Ok(ApiResponse::ok(ch))
+    #[test]
+    fn contenders_are_two_different_logins() {
+        assert_eq!(
+            parse_contenders(" @PlsUwu,chatter ").unwrap(),
+            ["plsuwu".to_string(), "chatter".to_string()]
+        );
+        assert!(parse_contenders("plsuwu").is_err());
+        assert!(parse_contenders("a,b,c").is_err());
+        assert!(parse_contenders("plsuwu,@PLSUWU").is_err());
+    }
 }
@@ -0,0 +1,263 @@
+//! Route handlers for a broadcaster's dashboard, where they can manage their own channel once
+//! they've logged in through Twitch (see `auth::session`).
+//!
+//! Every route here is behind `verify_broadcaster_session`, and only ever acts on the session's
+//! channel, so there's no channel id to pass (or to get wrong).
+
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::response::{AppendHeaders, IntoResponse, Response};
+use axum::{Extension, Json};
+use http::header::SET_COOKIE;
+use tracing::instrument;
+
+use crate::api::auth::Actor;
+use crate::api::auth::session::{self, SessionClaims};
+use crate::api::dto::v1::Dashboard;
+use crate::api::error::ApiError;
+use crate::api::extractors::{BlacklistRequest, PauseRequest};
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::admin::channel::{replace_channel_settings, set_channel_paused};
+use crate::api::handlers::channel::with_live_state;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::Pagination;
+use crate::db::models::audit::AuditAction;
+use crate::db::models::leaderboard::Period;
+use crate::db::models::metric::ChannelMetrics;
+use crate::db::models::settings::{BlacklistedChatter, ChannelSettings};
+use crate::db::prelude::{ChannelRepository, ChatterId, ChatterRepository, Repository};
+use crate::db::prelude::{LeaderboardRepository, MetricRepository, SettingsRepository};
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::util::helix::Helix;
+use crate::util::settings::settings;
+
+/// Retrieve the session's channel along with its leaderboard, whether counting is paused, its
+/// stream metrics and its settings.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/me?score_page=[SCORE_PAGE]&score_limit=[SCORE_LIMIT]
+///     ```
+///
+///     Params:
+///
+///     - `score_limit`:    number of chatters on the retrieved page of the channel's leaderboard.
+///     - `score_page`:     retrieve chatters starting with `score_limit * score_page`.
+#[instrument(skip(state, claims), fields(channel = %claims.channel_id))]
+pub async fn dashboard(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+    Query(param): Query<Pagination>,
+) -> ApiResult<Dashboard> {
+    let channel_id = claims.channel_id;
+
+    let channel = LeaderboardRepository::new(state.database_pool)
+        .get_single_channel_leaderboard(
+            channel_id.clone(),
+            ScorePagination::new(param.score_limit, param.score_page * param.score_limit),
+        )
+        .await?
+        .ok_or(ApiError::InvalidUser(claims.login))?;
+
+    let totals = MetricRepository::new(state.database_pool)
+        .get_totals(&channel_id, Period::All)
+        .await?;
+
+    Ok(ApiResponse::ok(Dashboard {
+        channel: with_live_state(&state, channel.into()).await?,
+        paused: ChannelRepository::new(state.database_pool)
+            .is_paused(&channel_id)
+            .await?,
+        metrics: ChannelMetrics::from_totals(channel_id.clone(), Period::All, &totals),
+        settings: settings().get(state.database_pool, &channel_id).await?,
+    }))
+}
+
+/// Replace the session's channel's settings. The blacklist is managed through `/me/blacklist`
+/// rather than here.
+///
+/// # Methods
+///
+/// * PUT
+///
+///     ```http
+///     /api/v1/me/settings
+///     ```
+///
+///     Body: a `ChannelSettings`, as returned by `/me`.
+#[instrument(skip(state, claims, actor), fields(channel = %claims.channel_id))]
+pub async fn update_settings(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<ChannelSettings>,
+) -> ApiResult<()> {
+    replace_channel_settings(&state, &claims.channel_id, payload, &actor).await?;
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// Pause or resume counting in the session's channel.
+///
+/// # Methods
+///
+/// * PUT
+///
+///     ```http
+///     /api/v1/me/paused
+///     ```
+///
+///     Body: `{ "paused": true }`
+#[instrument(skip(state, claims, actor), fields(channel = %claims.channel_id))]
+pub async fn update_paused(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<PauseRequest>,
+) -> ApiResult<()> {
+    set_channel_paused(&state, &claims.channel_id, payload.paused, &actor).await?;
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// Retrieve the chatters that aren't counted in the session's channel, newest first.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/me/blacklist
+///     ```
+#[instrument(skip(state, claims), fields(channel = %claims.channel_id))]
+pub async fn blacklist(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+) -> ApiResult<Vec<BlacklistedChatter>> {
+    let blacklist = SettingsRepository::new(state.database_pool)
+        .get_blacklist(&claims.channel_id)
+        .await?;
+
+    Ok(ApiResponse::ok(blacklist))
+}
+
+/// Stop a chatter from being counted in the session's channel. Scores they've already earned
+/// there are kept.
+///
+/// # Methods
+///
+/// * POST
+///
+///     ```http
+///     /api/v1/me/blacklist
+///     ```
+///
+///     Body: `{ "login": "[LOGIN]" }`; chatters that haven't chatted in any tracked channel yet
+///     are looked up through Twitch.
+#[instrument(skip(state, claims, actor), fields(channel = %claims.channel_id))]
+pub async fn blacklist_chatter(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+    Extension(actor): Extension<Actor>,
+    Json(payload): Json<BlacklistRequest>,
+) -> ApiResult<()> {
+    let login = payload.login.trim().trim_start_matches('@').to_lowercase();
+    let (chatter_id, login) = match ChatterRepository::new(state.database_pool)
+        .get_by_login(&login)
+        .await
+    {
+        Ok(chatter) => (chatter.id, chatter.login),
+        Err(sqlx::Error::RowNotFound) => {
+            let user = Helix::fetch_users_by_login(vec![login.clone()])
+                .await?
+                .into_iter()
+                .next()
+                .ok_or(ApiError::InvalidUser(login))?;
+            (ChatterId(user.id), user.login)
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let added = SettingsRepository::new(state.database_pool)
+        .add_to_blacklist(&claims.channel_id, &chatter_id, &login)
+        .await?;
+    if !added {
+        return Ok(ApiResponse::<()>::empty());
+    }
+
+    // the listener drops it too, but not before this instance's next message
+    settings().invalidate(&claims.channel_id);
+
+    tracing::info!(channel = %claims.channel_id, chatter = %chatter_id, "blacklisted chatter");
+    Audit::new(AuditAction::ChatterBlacklisted)
+        .target(&claims.channel_id)
+        .after(&chatter_id)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// Count a blacklisted chatter in the session's channel again.
+///
+/// # Methods
+///
+/// * DELETE
+///
+///     ```http
+///     /api/v1/me/blacklist/{ID}
+///     ```
+///
+///     Path:
+///     - {ID}:     the chatter's id.
+#[instrument(skip(state, claims, actor), fields(channel = %claims.channel_id))]
+pub async fn unblacklist_chatter(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<String>,
+) -> ApiResult<()> {
+    let chatter_id = ChatterId(id.clone());
+    if !SettingsRepository::new(state.database_pool)
+        .remove_from_blacklist(&claims.channel_id, &chatter_id)
+        .await?
+    {
+        return Err(ApiError::InvalidUser(id));
+    }
+
+    settings().invalidate(&claims.channel_id);
+
+    tracing::info!(channel = %claims.channel_id, chatter = %chatter_id, "unblacklisted chatter");
+    Audit::new(AuditAction::ChatterUnblacklisted)
+        .target(&claims.channel_id)
+        .before(&chatter_id)
+        .record(state.database_pool, &actor)
+        .await;
+
+    Ok(ApiResponse::<()>::empty())
+}
+
+/// End the session; it's rejected from then on, even if the cookie is kept.
+///
+/// # Methods
+///
+/// * POST
+///
+///     ```http
+///     /api/v1/me/logout
+///     ```
+#[instrument(skip(state, claims), fields(channel = %claims.channel_id))]
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    Extension(claims): Extension<SessionClaims>,
+) -> Result<Response, ApiError> {
+    session::revoke(&mut state.redis_pool.clone(), &claims).await?;
+
+    Ok((
+        AppendHeaders([(SET_COOKIE, session::clear())]),
+        ApiResponse::<()>::empty(),
+    )
+        .into_response())
+}
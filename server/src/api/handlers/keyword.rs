@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::response::Response;
+use http::{HeaderMap, StatusCode};
+use tracing::instrument;
+
+use crate::api::conditional;
+use crate::api::dto::v1::{KeywordEntry, KeywordSummary, Page};
+use crate::api::error::ApiError;
+use crate::api::extractors::KeywordKindQuery;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::Pagination;
+use crate::db::models::leaderboard::Period;
+use crate::db::prelude::{KeywordRepository, LeaderboardRepository};
+
+/// Retrieves every tracked keyword, text and emote.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/keywords
+///     ```
+#[instrument(skip(state))]
+pub async fn keywords(State(state): State<Arc<AppState>>) -> ApiResult<Vec<KeywordSummary>> {
+    let keywords = KeywordRepository::new(state.replicas.reader())
+        .get_all()
+        .await?;
+
+    Ok(ApiResponse::ok(
+        keywords.into_iter().map(KeywordSummary::from).collect(),
+    ))
+}
+
+/// Retrieves the chatter leaderboard for a single keyword, across all channels.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/keywords/{KEYWORD}/leaderboard?kind=[KIND]&limit=[LIMIT]&page=[PAGE]
+///     ```
+///
+///     Path:
+///     - {KEYWORD}:        the tracked keyword (case-insensitive).
+///
+///     Params:
+///
+///     - `kind`:           `text` (default) or `emote`; a word can be tracked as both.
+///     - `limit`:          number of items on the retrieved page. valid range is `0 <= limit <= MAX_U64`
+///     - `page`:           retrieve items starting with `limit * page`. valid range is `0 <= page <= MAX_U64`
+#[instrument(skip(state))]
+pub async fn keyword_leaderboard(
+    State(state): State<Arc<AppState>>,
+    Path(keyword): Path<String>,
+    Query(kind): Query<KeywordKindQuery>,
+    Query(param): Query<Pagination>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let repo = KeywordRepository::new(state.replicas.reader());
+    let keyword = repo
+        .get_by_word(&keyword, kind.kind)
+        .await?
+        .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+    let version = LeaderboardRepository::new(state.replicas.reader())
+        .get_version(None, Period::All)
+        .await?;
+
+    conditional::respond(&headers, version, async {
+        let leaderboard = repo
+            .get_leaderboard(&keyword.id, param.limit, param.page * param.limit)
+            .await?;
+
+        Ok(ApiResponse::ok(Page::<KeywordEntry>::from(leaderboard)))
+    })
+    .await
+}
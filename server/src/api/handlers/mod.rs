@@ -1,18 +1,26 @@
-use crate::api::server::RouteError;
+use crate::api::error::ApiError;
+use crate::api::middleware::access_log;
 
 pub mod admin;
 // pub mod admin_old;
 pub mod channel;
 pub mod chatter;
+pub mod dashboard;
+pub mod keyword;
+pub mod onboarding;
+pub mod overlay;
+pub mod search;
 
-/// Wraps a Tokio task with the `RouteError::JoinError` return type.
+/// Wraps a Tokio task with the `ApiError::JoinError` return type.
 ///
 /// Intended for use in handler tasks that should always execute to completion (regardless of
-/// whether the client remains connected).
-pub async fn spawn_protected<F, T>(f: F) -> Result<T, RouteError>
+/// whether the client remains connected). The task keeps the request's span and ID.
+pub async fn spawn_protected<F, T>(f: F) -> Result<T, ApiError>
 where
-    F: Future<Output = Result<T, RouteError>> + Send + 'static,
+    F: Future<Output = Result<T, ApiError>> + Send + 'static,
     T: Send + 'static,
 {
-    tokio::spawn(f).await.map_err(RouteError::JoinError)?
+    tokio::spawn(access_log::propagate(f))
+        .await
+        .map_err(ApiError::JoinError)?
 }
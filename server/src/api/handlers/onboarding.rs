@@ -0,0 +1,127 @@
+//! Route handlers for self-serve onboarding, where broadcasters authorize the client through
+//! Twitch (see `auth::twitch`) and their channel is then tracked as if it had been added through
+//! the admin API.
+
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::response::{AppendHeaders, IntoResponse, Redirect, Response};
+use http::StatusCode;
+use http::header::SET_COOKIE;
+use reqwest::Url;
+use tracing::instrument;
+
+use crate::api::auth::session;
+use crate::api::auth::twitch::{self, OAuthConfig};
+use crate::api::auth::{Actor, AuthError};
+use crate::api::error::ApiError;
+use crate::api::extractors::OAuthCallbackQuery;
+use crate::api::handlers::admin::channel::add_channel;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::{ApiResponse, AppState};
+use crate::db::prelude::{ChannelId, ChannelRepository, ChatterId, Repository};
+use crate::util::env::Var;
+use crate::util::helix::Helix;
+use crate::var;
+
+/// Redirect a broadcaster to Twitch to authorize the client. Responds with a 404 unless
+/// `CLIENT_SECRET` and `OAUTH_REDIRECT_URI` are set.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/auth/twitch/login
+///     ```
+#[instrument(skip(state))]
+pub async fn twitch_login(State(state): State<Arc<AppState>>) -> Result<Response, ApiError> {
+    let config = OAuthConfig::from_env().await?;
+    let oauth_state = twitch::issue_state(&mut state.redis_pool.clone()).await?;
+    let url = config.authorize_url(&oauth_state, &twitch::scopes().await);
+
+    Ok(Redirect::to(&url).into_response())
+}
+
+/// Where Twitch sends a broadcaster back to once they've authorized the client (or declined to).
+/// Their channel is tracked - stored, joined and subscribed to - unless it already is, and they're
+/// given a dashboard session (see `auth::session`) if api tokens are enabled.
+///
+/// Redirects to `OAUTH_SUCCESS_REDIRECT` with the channel's login appended if it's set, and
+/// otherwise responds with the login.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/auth/twitch/callback?code=[CODE]&state=[STATE]
+///     ```
+///
+///     Params:
+///
+///     - `code`:           the authorization code.
+///     - `state`:          the state issued by `/auth/twitch/login`; each can only be used once.
+///     - `error`:          set by Twitch instead of `code` if the broadcaster declined.
+#[instrument(skip(state, query))]
+pub async fn twitch_callback(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<OAuthCallbackQuery>,
+) -> Result<Response, ApiError> {
+    let config = OAuthConfig::from_env().await?;
+    let oauth_state = query.state.as_deref().ok_or(AuthError::OAuthState)?;
+    twitch::consume_state(&mut state.redis_pool.clone(), oauth_state).await?;
+
+    if let Some(error) = query.error {
+        return Err(AuthError::OAuthDenied(query.error_description.unwrap_or(error)).into());
+    }
+    let code = query
+        .code
+        .ok_or_else(|| ApiError::BadRequest("missing authorization code".into()))?;
+
+    let user = twitch::authorize(&config, &code).await?;
+    tracing::info!(login = user.login, scopes = ?user.scopes, "broadcaster authorized client");
+
+    let channel_id = ChannelId(user.user_id.clone());
+    let mut redis_pool = state.redis_pool.clone();
+    let login = if ChannelRepository::new(state.database_pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_some()
+    {
+        tracing::info!(login = user.login, "channel is already tracked");
+        user.login
+    } else {
+        let helix_user = Helix::fetch_users_by_id(&mut [user.user_id.clone()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or(ApiError::InvalidUser(user.user_id.clone()))?;
+
+        let actor = Actor::Broadcaster(ChatterId(user.user_id));
+        spawn_protected(async move { add_channel(&state, helix_user, &actor).await })
+            .await?
+            .login
+    };
+
+    let cookie = match session::issue(&mut redis_pool, &channel_id, &login).await {
+        Ok(cookie) => Some(cookie),
+        Err(AuthError::Disabled) => None,
+        Err(e) => return Err(e.into()),
+    };
+    let cookie = AppendHeaders(cookie.map(|cookie| (SET_COOKIE, cookie)));
+
+    let success_redirect = var!(Var::OAuthSuccessRedirect)
+        .await
+        .map_err(AuthError::from)?
+        .trim();
+    if success_redirect.is_empty() {
+        return Ok((cookie, ApiResponse::ok(login)).into_response());
+    }
+
+    let url = Url::parse_with_params(success_redirect, [("login", &login)]).map_err(|e| {
+        tracing::error!(error = ?e, "invalid OAUTH_SUCCESS_REDIRECT");
+        ApiError::GenericStatusCode(StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    Ok((cookie, Redirect::to(url.as_str())).into_response())
+}
@@ -0,0 +1,154 @@
+//! Data for browser-source overlays: a channel's total, the chatter most recently counted there
+//! and its top chatters.
+//!
+//! Every request is served from the channel's shared snapshot (see `util::overlay`), so overlays
+//! can poll as often as they like without reaching the database. Overlays can also long-poll for
+//! the next change, or hold a server-sent event stream open instead.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures::Stream;
+use http::header::CACHE_CONTROL;
+use http::{HeaderMap, HeaderValue};
+use tracing::instrument;
+
+use crate::api::conditional;
+use crate::api::error::ApiError;
+use crate::api::extractors::OverlayQuery;
+use crate::api::server::{ApiResponse, AppState};
+use crate::db::models::leaderboard::LeaderboardVersion;
+use crate::db::models::overlay::Overlay;
+use crate::util::overlay::overlays;
+
+/// How long a long-poll waits for the overlay to change before responding with it anyway.
+const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(25);
+/// Lets shared caches (e.g. a CDN) serve a snapshot for as long as it's kept here.
+const CACHE_CONTROL_OVERLAY: &str = "public, max-age=2";
+
+/// Retrieve a channel's overlay.
+///
+/// Responses carry an `ETag`, and can be cached for a couple of seconds. With `since` set to an
+/// earlier response's `ETag`, the request is held until the overlay changes (or for up to 25
+/// seconds, after which the unchanged overlay is returned).
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/overlay/{LOGIN}?since=[ETAG]
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+#[instrument(skip(state, headers))]
+pub async fn overlay(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+    Query(query): Query<OverlayQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let pool = state.replicas.reader();
+    let channel = login.to_lowercase();
+
+    let overlay = match query.since {
+        Some(since) => {
+            let Some(mut rx) = overlays().watch(pool, &channel).await? else {
+                return Err(ApiError::InvalidUser(login));
+            };
+
+            // a timeout just responds with the overlay as it stands
+            _ = tokio::time::timeout(LONG_POLL_TIMEOUT, async {
+                while rx.changed().await.is_ok() {
+                    if rx
+                        .borrow_and_update()
+                        .as_ref()
+                        .is_some_and(|overlay| !matches_tag(&since, overlay))
+                    {
+                        break;
+                    }
+                }
+            })
+            .await;
+
+            rx.borrow().clone()
+        }
+        None => overlays().get(pool, &channel).await?,
+    }
+    .ok_or(ApiError::InvalidUser(login))?;
+
+    let version = LeaderboardVersion {
+        tag: Some(overlay.tag()),
+        modified: overlay
+            .last_incrementer
+            .as_ref()
+            .map(|last| last.counted_at),
+    };
+
+    let mut response = conditional::respond(&headers, version, async {
+        Ok(ApiResponse::ok(overlay.as_ref()))
+    })
+    .await?;
+    // in place of `no-cache`, as revalidating with every poll is what this is saving overlays from
+    response.headers_mut().insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL_OVERLAY),
+    );
+
+    Ok(response.into_response())
+}
+
+/// Streams a channel's overlay as server-sent `overlay` events: the overlay as it stands once the
+/// stream is opened, then each time it changes (at most once a second).
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/overlay/{LOGIN}/events
+///     ```
+///
+///     Path:
+///     - {LOGIN}:  the login of a broadcaster.
+#[instrument(skip(state))]
+pub async fn events(
+    State(state): State<Arc<AppState>>,
+    Path(login): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let Some(rx) = overlays()
+        .watch(state.replicas.reader(), &login.to_lowercase())
+        .await?
+    else {
+        return Err(ApiError::InvalidUser(login));
+    };
+
+    // ends if the channel is removed, as its overlay stops being kept
+    let events = futures::stream::unfold(rx, |mut rx| async move {
+        loop {
+            rx.changed().await.ok()?;
+            let Some(overlay) = rx.borrow_and_update().clone() else {
+                continue;
+            };
+
+            let event = Event::default()
+                .event("overlay")
+                .id(overlay.tag())
+                .json_data(overlay.as_ref())
+                .unwrap_or_else(|_| Event::default().event("error"));
+            return Some((Ok(event), rx));
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Whether `since` (an `ETag`, weak or not, with or without its quotes) is the overlay's tag.
+fn matches_tag(since: &str, overlay: &Overlay) -> bool {
+    since.trim_start_matches("W/").trim_matches('"') == overlay.tag()
+}
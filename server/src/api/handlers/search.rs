@@ -0,0 +1,78 @@
+//! Route handlers for searching across chatters and channels
+
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use tracing::instrument;
+
+use crate::api::dto::v1::{Page, UserSearchResult};
+use crate::api::error::ApiError;
+use crate::api::extractors::SearchQuery;
+use crate::api::server::{ApiResponse, ApiResult, AppState};
+use crate::db::models::Pagination;
+use crate::db::prelude::SearchRepository;
+
+/// Twitch logins and display names are at most 25 characters
+const MAX_QUERY_LEN: usize = 25;
+const MAX_SEARCH_LIMIT: i64 = 50;
+
+/// Search chatters and channels by login or display name, for autocompletion.
+///
+/// Matches on a prefix of either name, or fuzzily via Postgres trigram similarity. An exact login
+/// match is ranked first, then prefix matches, then the closest fuzzy matches.
+///
+/// # Methods
+///
+/// * GET
+///
+///     ```http
+///     /api/v1/search?q=[QUERY]&limit=[LIMIT]&page=[PAGE]
+///     ```
+///
+///     Params:
+///
+///     - `q`:              a login or display name (case-insensitive), or the start of one. at most 25 characters.
+///     - `limit`:          number of items on the retrieved page. valid range is `1 <= limit <= 50`
+///     - `page`:           retrieve items starting with `limit * page`. valid range is `0 <= page <= MAX_U64`
+#[instrument(skip(state))]
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(search): Query<SearchQuery>,
+    Query(param): Query<Pagination>,
+) -> ApiResult<Page<UserSearchResult>> {
+    let query = normalize_query(&search.q).map_err(ApiError::BadRequest)?;
+    let limit = param.limit.clamp(1, MAX_SEARCH_LIMIT);
+    let offset = param.page.max(0) * limit;
+
+    let matches = SearchRepository::new(state.replicas.reader())
+        .search_users(&query, limit, offset)
+        .await?;
+
+    Ok(ApiResponse::ok(matches.into()))
+}
+
+fn normalize_query(query: &str) -> Result<String, String> {
+    let query = query.trim().trim_start_matches('@').to_lowercase();
+    if query.is_empty() {
+        return Err("search query is empty".into());
+    }
+    if query.chars().count() > MAX_QUERY_LEN {
+        return Err(format!(
+            "search query is longer than {MAX_QUERY_LEN} characters"
+        ));
+    }
+
+    Ok(query)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn queries_are_normalized() {
+        assert_eq!(normalize_query("  @PlsUwu ").unwrap(), "plsuwu");
+        assert!(normalize_query("   ").is_err());
+        assert!(normalize_query(&"a".repeat(MAX_QUERY_LEN + 1)).is_err());
+    }
+}
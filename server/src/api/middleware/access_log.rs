@@ -0,0 +1,168 @@
+//! Logs a structured event for every request, and tags each request with an ID that's returned in
+//! the `x-request-id` header and in error bodies, so a failed request can be matched with its logs.
+//!
+//! The ID (and for webhook deliveries, Twitch's message ID, which stays the same across retries)
+//! is recorded on the `http_request` span, so it's attached to every span created while handling
+//! the request. An ID sent by the client (or a proxy in front of us) is reused if it looks sane.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::body::HttpBody;
+use axum::extract::{MatchedPath, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use http::header::CONTENT_LENGTH;
+use http::{HeaderName, HeaderValue};
+use ring::rand::{SecureRandom, SystemRandom};
+use tracing::Instrument;
+
+use crate::api::error::ApiError;
+use crate::api::middleware::verify_external::{TWITCH_MESSAGE_ID, TWITCH_MESSAGE_RETRY};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static REQUEST_ID: Option<String>;
+}
+
+/// The ID of the request currently being handled, if any.
+pub fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok().flatten()
+}
+
+/// Carries the current request's ID and span into a future that will run on another task.
+pub fn propagate<F: Future>(f: F) -> impl Future<Output = F::Output> {
+    REQUEST_ID.scope(request_id(), f.in_current_span())
+}
+
+pub async fn access_log(req: Request, next: Next) -> Response {
+    let started = Instant::now();
+
+    let headers = req.headers();
+    let id = headers
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(generate);
+
+    let span = tracing::Span::current();
+    span.record("request_id", id.as_str());
+    if let Some(message_id) = headers.get(TWITCH_MESSAGE_ID).and_then(|v| v.to_str().ok()) {
+        span.record("twitch_message_id", message_id);
+    }
+    if let Some(retry) = headers
+        .get(TWITCH_MESSAGE_RETRY)
+        .and_then(|v| v.to_str().ok())
+    {
+        span.record("twitch_message_retry", retry);
+    }
+
+    let method = req.method().clone();
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched| matched.as_str().to_string())
+        .unwrap_or_else(|| req.uri().path().to_string());
+    let request_bytes = content_length(headers);
+
+    let mut response = REQUEST_ID.scope(Some(id.clone()), next.run(req)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    let status = response.status();
+    let latency_ms = started.elapsed().as_millis();
+    let response_bytes = content_length(response.headers()).or(response.body().size_hint().exact());
+
+    if let Some(err) = response.extensions().get::<Arc<ApiError>>() {
+        tracing::error!(
+            %method,
+            path,
+            %status,
+            latency_ms,
+            request_bytes,
+            response_bytes,
+            error = ?err,
+            "processing_failure",
+        );
+    } else if status.is_server_error() || status.is_client_error() {
+        tracing::warn!(
+            %method,
+            path,
+            %status,
+            latency_ms,
+            request_bytes,
+            response_bytes,
+            "request_result_non2xx"
+        );
+    } else {
+        tracing::info!(
+            %method,
+            path,
+            %status,
+            latency_ms,
+            request_bytes,
+            response_bytes,
+            "request_result_2xx"
+        );
+    }
+
+    response
+}
+
+fn content_length(headers: &http::HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn generate() -> String {
+    let mut bytes = [0u8; 8];
+    // falls back to an all-zero ID rather than failing the request
+    _ = SystemRandom::new().fill(&mut bytes);
+
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_ids_are_only_reused_if_sane() {
+        assert!(is_valid("3f2a9c1e-04b7-4e0d-9a7c-2f8b6d5e1a90"));
+        assert!(!is_valid(""));
+        assert!(!is_valid("has spaces"));
+        assert!(!is_valid(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+
+        assert_eq!(generate().len(), 16);
+    }
+
+    #[tokio::test]
+    async fn request_ids_follow_spawned_tasks() {
+        let id = REQUEST_ID
+            .scope(Some("abc".into()), async {
+                tokio::spawn(propagate(async { request_id() }))
+                    .await
+                    .unwrap()
+            })
+            .await;
+
+        assert_eq!(id.as_deref(), Some("abc"));
+        assert_eq!(request_id(), None);
+    }
+}
@@ -0,0 +1,47 @@
+//! Turns away requests that arrive while the server is draining (see `util::shutdown`).
+//!
+//! The listener stops accepting connections as soon as a drain starts, but clients can still send
+//! requests on connections that were already open. These are answered with a 503 and
+//! `Connection: close`, so that they're retried against another instance - Twitch redelivers
+//! EventSub notifications that weren't acknowledged.
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::header::{CONNECTION, RETRY_AFTER};
+use http::{HeaderValue, StatusCode};
+
+use crate::api::error::ApiError;
+use crate::util::shutdown::shutdown;
+
+const RETRY_AFTER_SECS: u32 = 5;
+
+pub async fn reject_while_draining(req: Request, next: Next) -> Response {
+    if !shutdown().is_draining() {
+        return next.run(req).await;
+    }
+
+    draining_response()
+}
+
+fn draining_response() -> Response {
+    let mut response = ApiError::GenericStatusCode(StatusCode::SERVICE_UNAVAILABLE).into_response();
+    let headers = response.headers_mut();
+    headers.insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+    headers.insert(CONNECTION, HeaderValue::from_static("close"));
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn draining_responses_close_the_connection() {
+        let response = draining_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "5");
+        assert_eq!(response.headers()[CONNECTION], "close");
+    }
+}
@@ -1,9 +1,16 @@
+pub mod access_log;
+pub mod drain;
+pub mod rate_limit;
+pub mod verify_broadcaster;
 pub mod verify_external;
 pub mod verify_internal;
 
+use std::time::Duration;
+
 use axum::extract::Request;
 use axum::middleware::Next;
 use axum::response::Response;
+use http::header::{ETAG, LAST_MODIFIED};
 use http::request::Parts as ReqParts;
 use http::{HeaderValue, Method};
 use thiserror::Error;
@@ -13,6 +20,9 @@ use tracing::instrument;
 use crate::util::env::{EnvErr, Var};
 use crate::var;
 
+/// How long browsers can reuse an overlay preflight response for
+const OVERLAY_PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(86400);
+
 pub type MiddlewareResult<T> = core::result::Result<T, MiddlewareErr>;
 
 #[derive(Debug, Error)]
@@ -24,9 +34,25 @@ pub enum MiddlewareErr {
     UnspecifiedRingErr,
 }
 
+/// CORS for the API, allowing origins per `CORS_ALLOW_ORIGINS`.
 pub async fn cors_layer() -> CorsLayer {
     let cors_allowed = var!(Var::CorsAllowOrigins).await.unwrap_or("*");
+    cors(cors_allowed).allow_methods([Method::GET, Method::POST])
+}
+
+/// CORS for overlays, allowing origins per `OVERLAY_CORS_ALLOW_ORIGINS`. Overlays are loaded from
+/// wherever a streamer hosts them (or from a `file://` browser source), so every origin is allowed
+/// unless that's set.
+pub async fn overlay_cors_layer() -> CorsLayer {
+    let cors_allowed = var!(Var::OverlayCorsAllowOrigins).await.unwrap_or("*");
+    cors(cors_allowed)
+        .allow_methods([Method::GET])
+        .expose_headers([ETAG, LAST_MODIFIED])
+        .max_age(OVERLAY_PREFLIGHT_MAX_AGE)
+}
 
+/// `*` allows every origin; anything else allows origins ending with it.
+fn cors(cors_allowed: &'static str) -> CorsLayer {
     let allowed = if cors_allowed == "*" {
         AllowOrigin::any()
     } else {
@@ -35,7 +61,5 @@ pub async fn cors_layer() -> CorsLayer {
         })
     };
 
-    CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_origin(allowed)
+    CorsLayer::new().allow_origin(allowed)
 }
@@ -0,0 +1,482 @@
+//! Token bucket rate limiting for the public API.
+//!
+//! Each client gets a bucket holding a minute's worth of requests, refilled continuously. Clients
+//! are identified by their API token if they send a valid one (with `RATE_LIMIT_TOKEN_PER_MINUTE`)
+//! and by IP otherwise (with `RATE_LIMIT_IP_PER_MINUTE`); the IP is taken from `cf-connecting-ip`
+//! when the request came through Cloudflare (or through a proxy trusted with
+//! `RATE_LIMIT_TRUST_PROXY`), or the peer address.
+//!
+//! Buckets are kept in memory by default. With `RATE_LIMIT_STORE=redis` they're kept in Redis so
+//! that instances share them; if Redis can't be reached, requests are let through rather than
+//! rejected.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, HeaderValue, StatusCode};
+use redis::Script;
+use redis::aio::ConnectionManager;
+
+use crate::api::auth;
+use crate::api::error::ApiError;
+use crate::util::env::Var;
+use crate::var;
+
+const CF_CONNECTING_IP: &str = "cf-connecting-ip";
+/// Cloudflare's published edge ranges (https://www.cloudflare.com/ips/); `cf-connecting-ip` can be
+/// set by anyone, so it's only believed from these.
+const CLOUDFLARE_V4: &[([u8; 4], u32)] = &[
+    ([173, 245, 48, 0], 20),
+    ([103, 21, 244, 0], 22),
+    ([103, 22, 200, 0], 22),
+    ([103, 31, 4, 0], 22),
+    ([141, 101, 64, 0], 18),
+    ([108, 162, 192, 0], 18),
+    ([190, 93, 240, 0], 20),
+    ([188, 114, 96, 0], 20),
+    ([197, 234, 240, 0], 22),
+    ([198, 41, 128, 0], 17),
+    ([162, 158, 0, 0], 15),
+    ([104, 16, 0, 0], 13),
+    ([104, 24, 0, 0], 14),
+    ([172, 64, 0, 0], 13),
+    ([131, 0, 72, 0], 22),
+];
+const CLOUDFLARE_V6: &[([u16; 8], u32)] = &[
+    ([0x2400, 0xcb00, 0, 0, 0, 0, 0, 0], 32),
+    ([0x2606, 0x4700, 0, 0, 0, 0, 0, 0], 32),
+    ([0x2803, 0xf800, 0, 0, 0, 0, 0, 0], 32),
+    ([0x2405, 0xb500, 0, 0, 0, 0, 0, 0], 32),
+    ([0x2405, 0x8100, 0, 0, 0, 0, 0, 0], 32),
+    ([0x2a06, 0x98c0, 0, 0, 0, 0, 0, 0], 29),
+    ([0x2c0f, 0xf248, 0, 0, 0, 0, 0, 0], 32),
+];
+const REDIS_KEY_PREFIX: &str = "ratelimit:";
+/// How often idle in-memory buckets are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Takes a token from the bucket at `KEYS[1]`, refilling it by the time elapsed (per Redis' clock,
+/// so that every instance agrees). Returns `{allowed, retry_after_ms}`.
+const TAKE_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local per_ms = tonumber(ARGV[2])
+
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+
+local state = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(state[1]) or capacity
+local ts = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * per_ms)
+
+local allowed = 0
+local retry_after = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+    allowed = 1
+else
+    retry_after = math.ceil((1 - tokens) / per_ms)
+end
+
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / per_ms))
+return { allowed, retry_after }
+"#;
+
+/// A bucket's size and refill rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub capacity: f64,
+    /// Tokens added per second
+    pub refill: f64,
+}
+
+impl Quota {
+    /// `None` (no limit) for `0`, or if the value isn't a number.
+    pub fn per_minute(val: &str) -> Option<Self> {
+        match val.trim().parse::<u32>() {
+            Ok(0) => None,
+            Ok(per_minute) => Some(Self {
+                capacity: per_minute as f64,
+                refill: per_minute as f64 / 60.0,
+            }),
+            Err(_) => {
+                tracing::warn!(val, "invalid rate limit - ignoring");
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Ip(IpAddr),
+    Token(String),
+}
+
+impl ClientKey {
+    fn redis_key(&self) -> String {
+        match self {
+            Self::Ip(ip) => format!("{REDIS_KEY_PREFIX}ip:{ip}"),
+            Self::Token(id) => format!("{REDIS_KEY_PREFIX}token:{id}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// The quota the bucket was last taken from, which it's swept by
+    quota: Quota,
+}
+
+impl Bucket {
+    fn take(&mut self, quota: Quota, now: Instant) -> Decision {
+        self.quota = quota;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.refill).min(quota.capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Decision::Allowed
+        } else {
+            Decision::Limited {
+                retry_after: Duration::from_secs_f64((1.0 - self.tokens) / quota.refill),
+            }
+        }
+    }
+
+    fn is_full(&self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * self.quota.refill >= self.quota.capacity
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryBuckets {
+    buckets: HashMap<ClientKey, Bucket>,
+    swept: Option<Instant>,
+}
+
+impl MemoryBuckets {
+    fn take(&mut self, key: ClientKey, quota: Quota, now: Instant) -> Decision {
+        // full buckets are indistinguishable from new ones, so they can be dropped
+        if self
+            .swept
+            .is_none_or(|swept| now.saturating_duration_since(swept) >= SWEEP_INTERVAL)
+        {
+            self.buckets.retain(|_, bucket| !bucket.is_full(now));
+            self.swept = Some(now);
+        }
+
+        self.buckets
+            .entry(key)
+            .or_insert(Bucket {
+                tokens: quota.capacity,
+                updated: now,
+                quota,
+            })
+            .take(quota, now)
+    }
+}
+
+enum BucketStore {
+    Memory(Mutex<MemoryBuckets>),
+    Redis(ConnectionManager, Script),
+}
+
+pub struct RateLimiter {
+    ip_quota: Option<Quota>,
+    token_quota: Option<Quota>,
+    /// Whether `cf-connecting-ip` is believed from any peer, not just Cloudflare's
+    trust_proxy: bool,
+    store: BucketStore,
+}
+
+impl RateLimiter {
+    pub fn memory(ip_quota: Option<Quota>, token_quota: Option<Quota>) -> Self {
+        Self {
+            ip_quota,
+            token_quota,
+            trust_proxy: false,
+            store: BucketStore::Memory(Mutex::default()),
+        }
+    }
+
+    pub fn redis(
+        ip_quota: Option<Quota>,
+        token_quota: Option<Quota>,
+        redis_pool: ConnectionManager,
+    ) -> Self {
+        Self {
+            ip_quota,
+            token_quota,
+            trust_proxy: false,
+            store: BucketStore::Redis(redis_pool, Script::new(TAKE_SCRIPT)),
+        }
+    }
+
+    /// Believes `cf-connecting-ip` from any peer, for when we're only reachable through a proxy
+    /// (e.g. a tunnel) that sets it.
+    pub fn trusting_proxy(mut self, trust_proxy: bool) -> Self {
+        self.trust_proxy = trust_proxy;
+        self
+    }
+
+    fn quota(&self, key: &ClientKey) -> Option<Quota> {
+        match key {
+            ClientKey::Ip(_) => self.ip_quota,
+            ClientKey::Token(_) => self.token_quota,
+        }
+    }
+
+    pub async fn take(&self, key: ClientKey) -> Decision {
+        let Some(quota) = self.quota(&key) else {
+            return Decision::Allowed;
+        };
+
+        match &self.store {
+            BucketStore::Memory(buckets) => {
+                buckets.lock().unwrap().take(key, quota, Instant::now())
+            }
+            BucketStore::Redis(redis_pool, script) => {
+                let result: redis::RedisResult<(u8, u64)> = script
+                    .key(key.redis_key())
+                    .arg(quota.capacity)
+                    .arg(quota.refill / 1000.0)
+                    .invoke_async(&mut redis_pool.clone())
+                    .await;
+
+                match result {
+                    Ok((0, retry_after_ms)) => Decision::Limited {
+                        retry_after: Duration::from_millis(retry_after_ms),
+                    },
+                    Ok(_) => Decision::Allowed,
+                    Err(e) => {
+                        tracing::warn!(error = ?e, "rate limit check failed - allowing request");
+                        Decision::Allowed
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds the limiter from `RATE_LIMIT_*`.
+pub async fn rate_limiter(redis_pool: ConnectionManager) -> Arc<RateLimiter> {
+    let ip_quota = Quota::per_minute(var!(Var::RateLimitIpPerMinute).await.unwrap_or_default());
+    let token_quota =
+        Quota::per_minute(var!(Var::RateLimitTokenPerMinute).await.unwrap_or_default());
+    let store = var!(Var::RateLimitStore).await.unwrap_or_default().trim();
+    let trust_proxy = var!(Var::RateLimitTrustProxy)
+        .await
+        .is_ok_and(|val| matches!(val.trim().to_lowercase().as_str(), "true" | "1"));
+
+    tracing::info!(
+        ?ip_quota,
+        ?token_quota,
+        store,
+        trust_proxy,
+        "configured public api rate limits"
+    );
+    let limiter = match store {
+        "redis" => RateLimiter::redis(ip_quota, token_quota, redis_pool),
+        "memory" | "" => RateLimiter::memory(ip_quota, token_quota),
+        _ => {
+            tracing::warn!(
+                store,
+                "unknown rate limit store - keeping buckets in memory"
+            );
+            RateLimiter::memory(ip_quota, token_quota)
+        }
+    };
+
+    Arc::new(limiter.trusting_proxy(trust_proxy))
+}
+
+/// The client's IP, preferring the address Cloudflare saw over the peer address when the peer is
+/// Cloudflare (or a trusted proxy).
+fn client_ip(headers: &HeaderMap, peer: Option<SocketAddr>, trust_proxy: bool) -> Option<IpAddr> {
+    let peer = peer.map(|addr| addr.ip().to_canonical());
+    if !trust_proxy && !peer.is_some_and(is_cloudflare) {
+        return peer;
+    }
+
+    headers
+        .get(CF_CONNECTING_IP)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|ip| ip.trim().parse().ok())
+        .or(peer)
+}
+
+fn is_cloudflare(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => CLOUDFLARE_V4.iter().any(|&(net, len)| {
+            let mask = u32::MAX << (32 - len);
+            u32::from(ip) & mask == u32::from_be_bytes(net) & mask
+        }),
+        IpAddr::V6(ip) => CLOUDFLARE_V6.iter().any(|&(net, len)| {
+            let mask = u128::MAX << (128 - len);
+            u128::from(ip) & mask == u128::from(std::net::Ipv6Addr::from(net)) & mask
+        }),
+    }
+}
+
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // only valid tokens get their own budget, so that made-up tokens can't dodge the IP limit
+    let token = match auth::bearer_token(req.headers()) {
+        Some(token) => auth::verify(token).await.ok(),
+        None => None,
+    };
+
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0);
+    let key = match (token, client_ip(req.headers(), peer, limiter.trust_proxy)) {
+        (Some(claims), _) => ClientKey::Token(claims.id),
+        (None, Some(ip)) => ClientKey::Ip(ip),
+        (None, None) => {
+            tracing::warn!("no client address - skipping rate limit");
+            return next.run(req).await;
+        }
+    };
+
+    match limiter.take(key.clone()).await {
+        Decision::Allowed => next.run(req).await,
+        Decision::Limited { retry_after } => {
+            tracing::info!(?key, ?retry_after, "rate limited");
+            too_many_requests(retry_after)
+        }
+    }
+}
+
+fn too_many_requests(retry_after: Duration) -> Response {
+    let mut response = ApiError::GenericStatusCode(StatusCode::TOO_MANY_REQUESTS).into_response();
+
+    // whole seconds, rounded up so that retrying right on time succeeds
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(secs.max(1)));
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let quota = Quota::per_minute("60").unwrap();
+        let mut buckets = MemoryBuckets::default();
+        let key = ClientKey::Ip(IpAddr::from([127, 0, 0, 1]));
+        let start = Instant::now();
+
+        for _ in 0..60 {
+            assert_eq!(buckets.take(key.clone(), quota, start), Decision::Allowed);
+        }
+
+        let Decision::Limited { retry_after } = buckets.take(key.clone(), quota, start) else {
+            panic!("bucket should be empty");
+        };
+        assert_eq!(retry_after, Duration::from_secs(1));
+
+        // other clients have their own buckets
+        let other = ClientKey::Token("abc".to_string());
+        assert_eq!(buckets.take(other, quota, start), Decision::Allowed);
+
+        let later = start + Duration::from_secs(1);
+        assert_eq!(buckets.take(key.clone(), quota, later), Decision::Allowed);
+        assert!(matches!(
+            buckets.take(key, quota, later),
+            Decision::Limited { .. }
+        ));
+
+        assert_eq!(Quota::per_minute("0"), None);
+        assert_eq!(Quota::per_minute("lots"), None);
+    }
+
+    #[test]
+    fn limited_responses_say_when_to_retry() {
+        let response = too_many_requests(Duration::from_millis(1500));
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "2");
+    }
+
+    #[test]
+    fn idle_buckets_are_swept_by_their_own_quota() {
+        let ip_quota = Quota::per_minute("60").unwrap();
+        let token_quota = Quota::per_minute("600").unwrap();
+        let mut buckets = MemoryBuckets::default();
+        let ip = ClientKey::Ip(IpAddr::from([127, 0, 0, 1]));
+        let token = ClientKey::Token("abc".to_string());
+        let start = Instant::now();
+
+        buckets.take(ip.clone(), ip_quota, start);
+        for _ in 0..500 {
+            buckets.take(token.clone(), token_quota, start + Duration::from_secs(59));
+        }
+
+        // the token bucket holds more than an ip bucket's worth, but isn't full
+        buckets.take(ip.clone(), ip_quota, start + SWEEP_INTERVAL);
+        assert!(buckets.buckets.contains_key(&token));
+        assert_eq!(buckets.buckets.len(), 2);
+
+        buckets.take(ip, ip_quota, start + SWEEP_INTERVAL * 3);
+        assert!(!buckets.buckets.contains_key(&token));
+    }
+
+    #[test]
+    fn cloudflare_address_is_preferred() {
+        let peer = Some(SocketAddr::from(([10, 0, 0, 1], 443)));
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            client_ip(&headers, peer, false),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+
+        headers.insert(CF_CONNECTING_IP, HeaderValue::from_static("203.0.113.7"));
+        // anyone can send the header, so it's ignored unless it came through cloudflare...
+        assert_eq!(
+            client_ip(&headers, peer, false),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+        let cloudflare = Some(SocketAddr::from(([172, 70, 1, 1], 443)));
+        assert_eq!(
+            client_ip(&headers, cloudflare, false),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        let cloudflare = Some("[2606:4700::1]:443".parse().unwrap());
+        assert_eq!(
+            client_ip(&headers, cloudflare, false),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+
+        // ...or a trusted proxy
+        assert_eq!(
+            client_ip(&headers, peer, true),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::api::auth::{Actor, AuthError, session};
+use crate::api::error::ApiError;
+use crate::api::server::AppState;
+use crate::db::prelude::{ChannelRepository, ChatterId, Repository};
+
+/// Admits requests with a valid dashboard session for a channel that's still tracked. Handlers
+/// only act on the session's own channel, via `Extension<SessionClaims>`, and can attribute changes
+/// to the broadcaster via `Extension<Actor>`.
+pub async fn verify_broadcaster_session(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let cookie = session::session_cookie(req.headers()).ok_or(AuthError::MissingSession)?;
+    let claims = session::verify(&mut state.redis_pool.clone(), cookie).await?;
+
+    if ChannelRepository::new(state.database_pool)
+        .get_by_id(&claims.channel_id)
+        .await?
+        .is_none()
+    {
+        tracing::warn!(channel = %claims.channel_id, "dashboard session for untracked channel");
+        return Err(AuthError::NotOwner.into());
+    }
+
+    tracing::debug!(channel = %claims.channel_id, "validated dashboard session");
+    let actor = Actor::Broadcaster(ChatterId(claims.channel_id.0.clone()));
+    req.extensions_mut().insert(actor);
+    req.extensions_mut().insert(claims);
+
+    Ok(next.run(req).await)
+}
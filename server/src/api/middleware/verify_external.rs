@@ -1,55 +1,28 @@
-use core::fmt;
-use std::sync::LazyLock;
+use std::sync::Arc;
+use std::time::Duration;
 
 use axum::body::{Body, Bytes};
-use axum::extract::{FromRequest, Request};
+use axum::extract::{FromRequest, Path, Request, State};
 use axum::middleware::Next;
-use axum::response::Response;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::StreamExt;
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
 use http::{HeaderMap, StatusCode};
-use ring::digest;
 use ring::hmac::{self, Key};
-use ring::rand::SecureRandom;
-use tokio::sync::OnceCell;
+use serde::Deserialize;
 
-use super::{MiddlewareErr, MiddlewareResult};
-use crate::util::constant_time_cmp;
+use crate::api::webhook::BroadcasterUserId;
+use crate::api::webhook::secret_store::SecretStore;
+use crate::api::webhook::{callback, secret};
+use crate::db::prelude::ChannelId;
+use crate::db::redis::redis_pool::redis_pool;
 
-static KEY: LazyLock<OnceCell<Hmac>> = LazyLock::new(OnceCell::new);
-async fn get_hmac_struct() -> MiddlewareResult<&'static Hmac> {
-    KEY.get_or_try_init(|| async { Hmac::new() }).await
-}
-
-pub async fn get_hmac_key() -> MiddlewareResult<String> {
-    Ok(get_hmac_struct().await?.hex.clone())
-}
-
-#[allow(dead_code)]
-pub struct Hmac {
-    pub key: Key,
-    pub digest: [u8; digest::SHA256_OUTPUT_LEN],
-    pub hex: String,
-}
-
-impl Hmac {
-    fn new() -> MiddlewareResult<Self> {
-        let rng = ring::rand::SystemRandom::new();
-
-        let mut digest = [0u8; digest::SHA256_OUTPUT_LEN];
-        rng.fill(&mut digest)
-            .map_err(|_| MiddlewareErr::UnspecifiedRingErr)?;
-
-        let hex = hex::encode(digest);
-        let key = Key::new(hmac::HMAC_SHA256, hex.as_bytes());
-
-        Ok(Self { key, digest, hex })
-    }
-}
-
-impl fmt::Display for Hmac {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        write!(f, "{}", self.hex)
-    }
-}
+/// Messages with an older timestamp are rejected outright; message ids are remembered for as long
+/// so that a replayed message is rejected by one check or the other.
+const MAX_MESSAGE_AGE: Duration = Duration::from_secs(60 * 10);
+/// EventSub notifications are a few KiB at most, so anything much bigger isn't from Twitch.
+const MAX_BODY_BYTES: usize = 256 * 1024;
 
 #[derive(Clone)]
 pub struct VerifiedBody(pub Bytes);
@@ -68,55 +41,295 @@ impl VerifiedBody {
     }
 }
 
-pub async fn verify_external_ident(mut req: Request, next: Next) -> Result<Response, StatusCode> {
+/// `callback_path` is the path segment the message was delivered to, if any; see
+/// `api::webhook::callback`. Messages are verified against the secrets in `secrets`.
+pub async fn verify_external_ident(
+    State(secrets): State<Arc<dyn SecretStore>>,
+    callback_path: Option<Path<String>>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
     let headers = req.headers().clone();
-    let body = match extract_body(&mut req).await {
-        Ok(bytes) => bytes,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
-    };
+    if !is_json(&headers) {
+        tracing::warn!("rejecting webhook message that isn't json");
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
 
-    if let Err(status) = verify_signature(&headers, &body).await {
+    let body = std::mem::replace(req.body_mut(), Body::empty());
+    let body = read_body(body, content_length(&headers)?, MAX_BODY_BYTES)
+        .await
+        .inspect_err(|status| tracing::warn!(%status, "rejecting webhook message body"))?;
+
+    let callback_path = callback_path.as_ref().map(|Path(path)| path.as_str());
+    if let Err(status) = verify_signature(secrets.as_ref(), &headers, &body, callback_path).await {
         tracing::error!(%status, "unable to verify external webhook signature");
         return Err(status);
     }
 
+    let (id, _, _) = get_message_parts(&headers)?;
+    if !first_delivery(id).await {
+        // twitch retries anything other than a 2xx, so a replay is acknowledged but not handled
+        tracing::warn!(message_id = id, "dropping replayed webhook message");
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
     req.extensions_mut().insert(VerifiedBody(body));
-    Ok(next.run(req).await)
+    let res = next.run(req).await;
+    if !res.status().is_success() {
+        // twitch retries the message, which has to be handled when it does
+        forget_delivery(id).await;
+    }
+
+    Ok(res)
 }
 
-async fn extract_body(request: &mut Request) -> Result<Bytes, ()> {
-    let body = std::mem::replace(request.body_mut(), Body::empty());
-    axum::body::to_bytes(body, usize::MAX).await.map_err(|_| ())
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+}
+
+fn content_length(headers: &HeaderMap) -> Result<Option<usize>, StatusCode> {
+    headers
+        .get(CONTENT_LENGTH)
+        .map(|v| {
+            v.to_str()
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .ok_or(StatusCode::BAD_REQUEST)
+        })
+        .transpose()
+}
+
+/// Reads a message body a chunk at a time, stopping as soon as it's longer than `limit` (or than
+/// its declared length allows) rather than buffering it all first. Bodies shorter than their
+/// declared length were truncated in transit and are rejected too.
+///
+/// The whole body is still kept, as the secret it's signed with depends on the subscription it
+/// names.
+async fn read_body(
+    body: Body,
+    expected_len: Option<usize>,
+    limit: usize,
+) -> Result<Bytes, StatusCode> {
+    if expected_len.is_some_and(|len| len > limit) {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let mut buf = Vec::with_capacity(expected_len.unwrap_or_default());
+    let mut chunks = body.into_data_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if buf.len() + chunk.len() > expected_len.unwrap_or(limit) {
+            return Err(match expected_len {
+                Some(_) => StatusCode::BAD_REQUEST,
+                None => StatusCode::PAYLOAD_TOO_LARGE,
+            });
+        }
+
+        buf.extend_from_slice(&chunk);
+    }
+
+    if expected_len.is_some_and(|len| len != buf.len()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(Bytes::from(buf))
 }
 
-async fn verify_signature(headers: &HeaderMap, body: &Bytes) -> Result<(), StatusCode> {
+async fn verify_signature(
+    store: &dyn SecretStore,
+    headers: &HeaderMap,
+    body: &Bytes,
+    callback_path: Option<&str>,
+) -> Result<(), StatusCode> {
     let (id, timestamp, extern_signature) = get_message_parts(headers)?;
-    let rebuilt_message = rebuild_message(id, timestamp, body);
+    if !is_fresh(timestamp, Utc::now()) {
+        tracing::warn!(
+            message_id = id,
+            timestamp,
+            "rejecting stale webhook message"
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
 
-    let expected_signature = {
-        let key = &get_hmac_struct()
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-            .key;
+    let signature = extern_signature
+        .strip_prefix(HMAC_PREFIX)
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or(StatusCode::FORBIDDEN)?;
 
-        let signed = hmac::sign(key, &rebuilt_message);
-        format!("{}{}", HMAC_PREFIX, hex::encode(signed))
-    };
+    let secrets = subscription_secrets(store, body, callback_path).await?;
+    let message = message(id, timestamp, body);
+    let verified = secrets
+        .iter()
+        .any(|secret| verifies(secret, &message, &signature));
 
-    if constant_time_cmp(extern_signature, &expected_signature) {
+    if verified {
         return Ok(());
     }
 
     Err(StatusCode::FORBIDDEN)
 }
 
-fn rebuild_message(id: &str, ts: &str, body: &Bytes) -> Vec<u8> {
-    let mut m = Vec::new();
-    m.extend_from_slice(id.as_bytes());
-    m.extend_from_slice(ts.as_bytes());
-    m.extend_from_slice(body);
+#[derive(Deserialize)]
+struct SignedPayload {
+    subscription: SignedSubscription,
+}
+
+#[derive(Deserialize)]
+struct SignedSubscription {
+    r#type: String,
+    condition: BroadcasterUserId,
+}
+
+/// Looks up the secrets for the subscription a message claims to be for: the current secret, and
+/// the previous one if it was rotated recently enough that a message signed with it isn't stale.
+/// The claim is only trusted once the signature has been checked against one of them.
+///
+/// Messages delivered to any path other than the subscription's own are rejected as not found.
+async fn subscription_secrets(
+    store: &dyn SecretStore,
+    body: &Bytes,
+    callback_path: Option<&str>,
+) -> Result<Vec<String>, StatusCode> {
+    let payload: SignedPayload =
+        serde_json::from_slice(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let channel_id = ChannelId(payload.subscription.condition.broadcaster_user_id);
+
+    let subscription = store
+        .get(&channel_id, &payload.subscription.r#type)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = ?e, "failed to look up webhook subscription secret");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            tracing::warn!(
+                channel_id = %channel_id,
+                subscription_type = payload.subscription.r#type,
+                "no secret stored for webhook subscription"
+            );
+            StatusCode::FORBIDDEN
+        })?;
+
+    if !callback::accepts(subscription.callback_path.as_deref(), callback_path) {
+        tracing::warn!(
+            channel_id = %channel_id,
+            subscription_type = payload.subscription.r#type,
+            "webhook message delivered to the wrong callback path"
+        );
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut secrets = vec![secret::open(&subscription.secret).await.map_err(|e| {
+        tracing::error!(error = ?e, "failed to open webhook subscription secret");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?];
+
+    if let Some(previous) = &subscription.previous_secret
+        && accepts_previous(subscription.rotated_at, Utc::now().naive_utc())
+    {
+        match secret::open(previous).await {
+            Ok(previous) => secrets.push(previous),
+            Err(e) => tracing::warn!(error = ?e, "failed to open previous webhook secret"),
+        }
+    }
+
+    Ok(secrets)
+}
+
+fn accepts_previous(rotated_at: Option<NaiveDateTime>, now: NaiveDateTime) -> bool {
+    rotated_at.is_some_and(|at| {
+        now.signed_duration_since(at)
+            .to_std()
+            .is_ok_and(|since| since <= MAX_MESSAGE_AGE)
+    })
+}
+
+/// The signed message: the message id, timestamp and body, concatenated.
+fn message(id: &str, timestamp: &str, body: &Bytes) -> Vec<u8> {
+    [id.as_bytes(), timestamp.as_bytes(), body].concat()
+}
+
+/// Whether `signature` is the message's HMAC under `secret`; ring compares the tags in constant
+/// time.
+fn verifies(secret: &str, message: &[u8], signature: &[u8]) -> bool {
+    let key = Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::verify(&key, message, signature).is_ok()
+}
 
-    m
+/// The message's signature as Twitch sends it.
+#[cfg(test)]
+fn sign(secret: &str, id: &str, timestamp: &str, body: &Bytes) -> String {
+    let key = Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    format!(
+        "{}{}",
+        HMAC_PREFIX,
+        hex::encode(hmac::sign(&key, &message(id, timestamp, body)))
+    )
+}
+
+fn is_fresh(timestamp: &str, now: DateTime<Utc>) -> bool {
+    let Ok(sent) = DateTime::parse_from_rfc3339(timestamp) else {
+        return false;
+    };
+
+    let age = now.signed_duration_since(sent).abs();
+    age.to_std().is_ok_and(|age| age <= MAX_MESSAGE_AGE)
+}
+
+/// Records a message id as seen, returning false if it had already been recorded. The id is
+/// forgotten again if the message isn't handled (see `forget_delivery`), so that Twitch's retry is.
+///
+/// Fails open if Redis is unreachable; the timestamp check still bounds how long a message can be
+/// replayed for.
+async fn first_delivery(id: &str) -> bool {
+    let mut redis_pool = match redis_pool().await {
+        Ok(pool) => pool.clone(),
+        Err(e) => {
+            tracing::warn!(error = ?e, "unable to record webhook message id");
+            return true;
+        }
+    };
+
+    let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(format!("eventsub:message:{id}"))
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(MAX_MESSAGE_AGE.as_secs())
+        .query_async(&mut redis_pool)
+        .await;
+
+    match result {
+        Ok(set) => set.is_some(),
+        Err(e) => {
+            tracing::warn!(error = ?e, "unable to record webhook message id");
+            true
+        }
+    }
+}
+
+/// Forgets a message id recorded by `first_delivery`, for a message that wasn't handled.
+async fn forget_delivery(id: &str) {
+    let mut redis_pool = match redis_pool().await {
+        Ok(pool) => pool.clone(),
+        Err(e) => {
+            tracing::warn!(error = ?e, message_id = id, "unable to forget webhook message id");
+            return;
+        }
+    };
+
+    let result: redis::RedisResult<()> = redis::cmd("DEL")
+        .arg(format!("eventsub:message:{id}"))
+        .query_async(&mut redis_pool)
+        .await;
+
+    if let Err(e) = result {
+        tracing::warn!(error = ?e, message_id = id, "unable to forget webhook message id");
+    }
 }
 
 type MessageParts<'a> = (&'a str, &'a str, &'a str);
@@ -157,3 +370,175 @@ pub const TWITCH_MESSAGE_ID: &str = "Twitch-Eventsub-Message-Id";
 pub const TWITCH_MESSAGE_TIMESTAMP: &str = "Twitch-Eventsub-Message-Timestamp";
 pub const TWITCH_MESSAGE_SIGNATURE: &str = "Twitch-Eventsub-Message-Signature";
 pub const TWITCH_MESSAGE_TYPE_HEADER: &str = "Twitch-Eventsub-Message-Type";
+pub const TWITCH_MESSAGE_RETRY: &str = "Twitch-Eventsub-Message-Retry";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_older_than_ten_minutes_are_stale() {
+        let now = DateTime::parse_from_rfc3339("2026-05-07T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert!(is_fresh("2026-05-07T09:55:00.123456789Z", now));
+        assert!(is_fresh("2026-05-07T09:50:00Z", now));
+        assert!(!is_fresh("2026-05-07T09:49:59Z", now));
+        assert!(!is_fresh("2026-05-07T10:10:01Z", now));
+        assert!(!is_fresh("not a timestamp", now));
+    }
+
+    #[test]
+    fn signatures_depend_on_the_subscription_secret() {
+        let body = Bytes::from_static(br#"{"subscription":{}}"#);
+        let signature = sign("secret", "id", "2026-05-07T10:00:00Z", &body);
+
+        assert!(signature.starts_with(HMAC_PREFIX));
+        assert_eq!(
+            signature,
+            sign("secret", "id", "2026-05-07T10:00:00Z", &body)
+        );
+        assert_ne!(
+            signature,
+            sign("other", "id", "2026-05-07T10:00:00Z", &body)
+        );
+    }
+
+    #[test]
+    fn simulated_messages_are_signed_like_twitch() {
+        use crate::api::webhook::simulator::EventSubSimulator;
+
+        let sim = EventSubSimulator::with_secret("http://localhost/callback", "secret");
+        let message = sim.stream_online("123456789", "someone");
+        let headers = message.headers(sim.secret());
+        let body = Bytes::from(message.body_bytes());
+
+        let (id, timestamp, signature) = get_message_parts(&headers).unwrap();
+        assert!(is_fresh(timestamp, Utc::now()));
+        assert_eq!(signature, sign("secret", id, timestamp, &body));
+
+        let forged = message.headers("other");
+        let (_, _, signature) = get_message_parts(&forged).unwrap();
+        assert_ne!(signature, sign("secret", id, timestamp, &body));
+    }
+
+    #[test]
+    fn signatures_match_the_concatenated_message() {
+        let body = Bytes::from_static(br#"{"subscription":{}}"#);
+        let key = Key::new(hmac::HMAC_SHA256, b"secret");
+        let message = [b"id".as_slice(), b"2026-05-07T10:00:00Z", &body].concat();
+
+        assert_eq!(
+            sign("secret", "id", "2026-05-07T10:00:00Z", &body),
+            format!("{HMAC_PREFIX}{}", hex::encode(hmac::sign(&key, &message)))
+        );
+    }
+
+    #[test]
+    fn signatures_are_verified_against_each_secret() {
+        let body = Bytes::from_static(br#"{"subscription":{}}"#);
+        let signature = sign("secret", "id", "2026-05-07T10:00:00Z", &body);
+        let signature = hex::decode(signature.strip_prefix(HMAC_PREFIX).unwrap()).unwrap();
+        let message = message("id", "2026-05-07T10:00:00Z", &body);
+
+        assert!(verifies("secret", &message, &signature));
+        assert!(!verifies("other", &message, &signature));
+        assert!(!verifies("secret", &message, &signature[1..]));
+    }
+
+    #[test]
+    fn only_json_bodies_are_accepted() {
+        let with_type = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CONTENT_TYPE, value.parse().unwrap());
+            headers
+        };
+
+        assert!(is_json(&with_type("application/json")));
+        assert!(is_json(&with_type("Application/JSON; charset=utf-8")));
+        assert!(!is_json(&with_type("text/plain")));
+        assert!(!is_json(&HeaderMap::new()));
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let body = || Body::from(vec![b'a'; 64]);
+
+        assert_eq!(
+            read_body(body(), Some(64), 32).await,
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        assert_eq!(
+            read_body(body(), None, 32).await,
+            Err(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        // longer than declared
+        assert_eq!(
+            read_body(body(), Some(16), 32).await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(read_body(body(), None, 64).await.unwrap().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn truncated_bodies_are_rejected() {
+        let body = Bytes::from_static(br#"{"subscription":"#);
+
+        assert_eq!(
+            read_body(Body::from(body.clone()), Some(body.len() + 8), 1024).await,
+            Err(StatusCode::BAD_REQUEST)
+        );
+        assert_eq!(
+            read_body(Body::from(body.clone()), Some(body.len()), 1024).await,
+            Ok(body)
+        );
+    }
+
+    #[tokio::test]
+    async fn messages_are_verified_against_the_stored_secrets() {
+        use crate::api::webhook::secret_store::{MemorySecretStore, StoredSecrets};
+        use crate::api::webhook::simulator::EventSubSimulator;
+
+        let sim = EventSubSimulator::with_secret("http://localhost/callback", "new");
+        let message = sim.stream_online("123456789", "someone");
+        let body = Bytes::from(message.body_bytes());
+        let store = MemorySecretStore::default();
+
+        let verify = |secret: &str, path: Option<&'static str>| {
+            let headers = message.headers(secret);
+            let (store, body) = (&store, body.clone());
+            async move { verify_signature(store, &headers, &body, path).await }
+        };
+
+        assert_eq!(verify("new", None).await, Err(StatusCode::FORBIDDEN));
+
+        store.insert(
+            ChannelId(String::from("123456789")),
+            "stream.online",
+            StoredSecrets {
+                secret: String::from("new"),
+                previous_secret: Some(String::from("old")),
+                rotated_at: Some(Utc::now().naive_utc()),
+                callback_path: None,
+            },
+        );
+        assert_eq!(verify("new", None).await, Ok(()));
+        assert_eq!(verify("old", None).await, Ok(()));
+        assert_eq!(verify("forged", None).await, Err(StatusCode::FORBIDDEN));
+        assert_eq!(verify("new", Some("abc")).await, Err(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn previous_secret_is_only_accepted_shortly_after_rotation() {
+        let rotated_at = DateTime::parse_from_rfc3339("2026-05-07T10:00:00Z")
+            .unwrap()
+            .naive_utc();
+        let after = |secs| rotated_at + chrono::Duration::seconds(secs);
+
+        assert!(accepts_previous(Some(rotated_at), after(60)));
+        assert!(accepts_previous(Some(rotated_at), after(600)));
+        assert!(!accepts_previous(Some(rotated_at), after(601)));
+        assert!(!accepts_previous(None, after(60)));
+    }
+}
@@ -3,12 +3,13 @@ use std::sync::Arc;
 use axum::extract::{Request, State};
 use axum::middleware::Next;
 use axum::response::Response;
-use http::StatusCode;
 use http::header::AUTHORIZATION;
+use http::{Method, StatusCode};
 use ring::digest;
 use ring::rand::SecureRandom;
-use sqlx::{Error, PgPool};
+use sqlx::PgPool;
 
+use crate::api::auth::{self, Actor, Scope};
 use crate::api::server::AppState;
 use crate::db::models::Session;
 use crate::db::{PgError, PgResult};
@@ -32,13 +33,35 @@ use crate::db::{PgError, PgResult};
 //     }
 // }
 
-pub async fn verify_session_ident(
+/// Admits requests with either a valid session token or an API token with the `read` scope (for
+/// GET requests) or the `admin` scope. Handlers can attribute changes to whoever made the request
+/// via `Extension<Actor>`.
+pub async fn verify_admin_ident(
     State(state): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
     let db = state.database_pool;
 
+    if let Some(token) = auth::bearer_token(req.headers()) {
+        let required = match *req.method() {
+            Method::GET | Method::HEAD => Scope::Read,
+            _ => Scope::Admin,
+        };
+
+        let claims = auth::authenticate(db, token, required)
+            .await
+            .map_err(|e| e.status_code())?;
+
+        tracing::info!(
+            token_id = claims.id,
+            label = claims.label,
+            "validated api token"
+        );
+        req.extensions_mut().insert(Actor::Token(claims));
+        return Ok(next.run(req).await);
+    }
+
     let headers = req.headers().clone();
     let ident = headers
         .get(AUTHORIZATION)
@@ -55,12 +78,8 @@ pub async fn verify_session_ident(
 
     tracing::info!(token = ?is_valid, "validated token");
 
-    // i dont think its possible to be a `String::Default()` here??
-    if is_valid.token != String::default() {
-        Ok(next.run(req).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
-    }
+    req.extensions_mut().insert(Actor::Session(is_valid));
+    Ok(next.run(req).await)
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -87,56 +106,71 @@ impl SessionToken {
         hex
     }
 
+    /// The hex-encoded SHA-256 of `token`, which is all that's stored of a session token.
+    pub fn hash_token(token: &str) -> String {
+        hex::encode(digest::digest(&digest::SHA256, token.as_bytes()))
+    }
+
     pub async fn store_token(db: &'static PgPool, token: &str) -> PgResult<()> {
         let ts_now = chrono::Utc::now();
         let ts_exp = ts_now.checked_add_days(chrono::Days::new(14)).unwrap();
 
-        sqlx::query!(
+        sqlx::query(
             r#"
             INSERT INTO session (
-                token,
+                token_hash,
                 created_at,
                 expires_at
             )
             VALUES ($1, $2, $3)
             "#,
-            token,
-            ts_now.naive_utc(),
-            ts_exp.naive_utc()
         )
+        .bind(Self::hash_token(token))
+        .bind(ts_now.naive_utc())
+        .bind(ts_exp.naive_utc())
         .execute(db)
         .await?;
 
         Ok(())
     }
 
+    /// Ends a session; its token is rejected from then on.
+    pub async fn delete(db: &'static PgPool, id: i32) -> PgResult<()> {
+        sqlx::query("DELETE FROM session WHERE id = $1")
+            .bind(id)
+            .execute(db)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Finds the unexpired session for `token` by the hash of the token. Matching on the hash
+    /// (rather than the token) means the lookup can use an index without its timing revealing
+    /// anything about a stored token.
     pub async fn get_matching_token(
         db: &'static PgPool,
         token: &str,
     ) -> Result<Option<Session>, SessionError> {
-        match sqlx::query_as::<_, Session>(
+        let matching = sqlx::query_as::<_, Session>(
             r#"
-            SELECT * FROM session 
-            WHERE token = $1 
+            SELECT * FROM session
+            WHERE token_hash = $1
             AND expires_at > NOW()
             "#,
         )
-        .bind(token)
-        .fetch_one(db)
+        .bind(Self::hash_token(token))
+        .fetch_optional(db)
         .await
-        {
-            Ok(val) => Ok(Some(val)),
-            Err(e) => match e {
-                Error::RowNotFound => {
-                    tracing::warn!(error = ?e, "token invalid");
-                    return Ok(None);
-                }
-                _ => {
-                    tracing::error!(error = ?e, "failed to retrieve session from database");
-                    Err(SessionError::DatabaseError(PgError::SqlxError(e)))
-                }
-            },
+        .map_err(|e| {
+            tracing::error!(error = ?e, "failed to retrieve session from database");
+            SessionError::DatabaseError(PgError::SqlxError(e))
+        })?;
+
+        if matching.is_none() {
+            tracing::warn!("token invalid");
         }
+
+        Ok(matching)
     }
 
     pub async fn cmp_token(db: &'static PgPool, ident: &str) -> Result<Session, SessionError> {
@@ -1,6 +1,13 @@
+pub mod auth;
+pub mod conditional;
+pub mod dto;
+pub mod error;
 pub mod extractors;
+pub mod graphql;
 // pub mod handler;
 pub mod handlers;
 pub mod middleware;
 pub mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod webhook;
@@ -2,41 +2,59 @@ use std::net::SocketAddr;
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
 
-use axum::extract::{MatchedPath, Request};
+use axum::extract::connect_info::IntoMakeServiceWithConnectInfo;
+use axum::extract::{MatchedPath, Request, State};
 use axum::middleware;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{delete, get, post, put};
-use axum::{Json, Router};
+#[cfg(feature = "tls")]
+use axum::serve::ListenerExt;
+use axum::{Extension, Json, Router};
 use axum_prometheus::PrometheusMetricLayer;
+use futures::FutureExt;
+use futures::future::BoxFuture;
 use http::StatusCode;
 use redis::AsyncCommands;
 use redis::aio::ConnectionManager;
 use serde::Serialize;
 use sqlx::{PgPool, Pool, Postgres};
-use thiserror::Error;
-use tokio::sync::mpsc::error::SendError;
+use tokio::net::TcpListener;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::oneshot::{self, Sender};
-use tokio::sync::{Mutex, RwLock, mpsc};
-use tokio::task::{JoinError, JoinHandle};
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
 use tower_http::trace::TraceLayer;
 use tracing::instrument;
 
-use crate::api::middleware::cors_layer;
-use crate::api::middleware::verify_external::{get_hmac_key, verify_external_ident};
-use crate::api::middleware::verify_internal::verify_session_ident;
+use crate::api::error::ApiError;
+use crate::api::graphql;
+use crate::api::middleware::access_log::access_log;
+use crate::api::middleware::drain::reject_while_draining;
+use crate::api::middleware::rate_limit::{rate_limit, rate_limiter};
+use crate::api::middleware::verify_broadcaster::verify_broadcaster_session;
+use crate::api::middleware::verify_external::verify_external_ident;
+use crate::api::middleware::verify_internal::verify_admin_ident;
+use crate::api::middleware::{cors_layer, overlay_cors_layer};
+#[cfg(feature = "tls")]
+use crate::api::tls;
+use crate::api::webhook::secret_store::{PostgresSecretStore, SecretStore};
 use crate::api::webhook::webhook_handler;
 use crate::api::{handlers::*, webhook};
+use crate::db::migrate;
 use crate::db::prelude::*;
-use crate::db::redis::redis_pool::RedisErr;
-use crate::irc::{ConnectionClientError, IrcHandle};
-use crate::util::channel::ChannelError;
+use crate::db::redis::redis_pool::redis_healthy;
+use crate::db::replica::ReplicaSet;
+use crate::db::store::guarded::spawn_dead_letter_replay;
+use crate::db::store::{ScoreStore, score_store};
+use crate::irc::IrcHandle;
+use crate::util::availability::availability;
+use crate::util::breaker::{BreakerState, BreakerStatus, helix_breaker, postgres_breaker};
 use crate::util::env::Var;
-use crate::util::helix::HelixErr;
+use crate::util::shard;
+use crate::util::shutdown::{self, shutdown};
 use crate::util::totp::TOTPHandler;
 use crate::{util, var};
 
-pub type ApiResult<T> = Result<Json<ApiResponse<T>>, RouteError>;
+pub type ApiResult<T> = Result<Json<ApiResponse<T>>, ApiError>;
 
 #[derive(Serialize)]
 pub struct ApiResponse<T: Serialize> {
@@ -70,6 +88,9 @@ impl<T: Serialize> IntoResponse for ApiResponse<T> {
 #[derive(Clone, Debug)]
 pub struct AppState {
     pub database_pool: &'static PgPool,
+    pub replicas: &'static ReplicaSet,
+    /// Where scores are counted and ranked; see `db::store`
+    pub scores: Arc<dyn ScoreStore>,
     pub redis_pool: ConnectionManager,
     pub irc_connection: IrcHandle,
     pub channels: Arc<RwLock<Vec<String>>>,
@@ -81,7 +102,7 @@ pub struct AppState {
 pub async fn stream_online_hook_handler<R: AsyncCommands + Sync>(
     channel_ids: &[String],
     mut redis_pool: R,
-) -> Result<(), RouteError> {
+) -> Result<(), ApiError> {
     match webhook::dispatch::reset_hooks(channel_ids).await {
         Ok(_) => tracing::debug!("webhook subs reset"),
         Err(e) => tracing::error!(error = ?e, "reset webhook subs failure"),
@@ -95,10 +116,18 @@ pub async fn stream_online_hook_handler<R: AsyncCommands + Sync>(
     Ok(())
 }
 
+#[derive(Debug)]
+pub struct TrackedChannels {
+    pub ids: Vec<String>,
+    pub logins: Vec<String>,
+    /// Logins of the channels in this instance's shard, which are the only ones it joins
+    pub owned_logins: Vec<String>,
+}
+
 #[instrument(skip(database_pool))]
 pub async fn initialize_channels(
     database_pool: &'static Pool<Postgres>,
-) -> Result<(Vec<String>, Vec<String>), RouteError> {
+) -> Result<TrackedChannels, ApiError> {
     let channel_ids = ChannelRepository::new(database_pool)
         .get_all_channel_ids()
         .await
@@ -109,14 +138,32 @@ pub async fn initialize_channels(
         .map(|ch| ChatterId::from(ch.to_owned()))
         .collect::<Vec<ChatterId>>();
 
-    let channel_logins: Vec<String> = util::channel::update_stored_channels(&as_chatter_ids, true)
+    let channels = util::channel::update_stored_channels(&as_chatter_ids, true)
         .await
-        .unwrap()
-        .into_keys()
+        .unwrap();
+
+    let sharding = shard::sharding();
+    let owned_logins: Vec<String> = channels
+        .iter()
+        .filter(|(_, chatter)| sharding.owns(&chatter.id.0))
+        .map(|(login, _)| login.clone())
         .collect();
+    let channel_logins: Vec<String> = channels.into_keys().collect();
 
     tracing::info!(?channel_logins, "using this channel list");
-    Ok((channel_ids, channel_logins))
+    if sharding.is_sharded() {
+        tracing::info!(
+            ?owned_logins,
+            shard = sharding.index,
+            "joining this shard's channels"
+        );
+    }
+
+    Ok(TrackedChannels {
+        ids: channel_ids,
+        logins: channel_logins,
+        owned_logins,
+    })
 }
 
 fn public_channel_routes() -> Router<Arc<AppState>> {
@@ -128,14 +175,64 @@ fn public_channel_routes() -> Router<Arc<AppState>> {
         .route("/bot-state", get(channel::bot_enabled))
         .route("/by-id/{id}", get(channel::by_id))
         .route("/by-login/{login}", get(channel::by_login))
+        .route("/by-login/{login}/heatmap", get(channel::heatmap))
+        .route("/by-login/{login}/status", get(channel::stream_status))
+        .route("/by-login/{login}/timeline", get(channel::timeline))
+        .route("/by-login/{login}/digests", get(channel::digests))
+        .route("/by-login/{login}/metrics", get(channel::metrics))
+        .route("/by-login/{login}/rank/{user}", get(channel::rank))
+        .route("/by-login/{login}/export", get(channel::export))
         .route("/windowed/{id}", get(channel::channel_score_windows))
+        .route("/{login}/increment", post(channel::increment))
 }
 
 fn public_chatter_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/leaderboard", get(chatter::chatter_leaderboard))
         .route("/by-login/{login}", get(chatter::by_login))
+        .route("/by-login/{login}/profile", get(chatter::profile_by_login))
         .route("/by-id/{id}", get(chatter::by_id))
+        .route("/by-id/{id}/avatar", get(chatter::avatar))
+        .route("/by-id/{id}/profile", get(chatter::profile))
+}
+
+fn public_keyword_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(keyword::keywords))
+        .route("/{keyword}/leaderboard", get(keyword::keyword_leaderboard))
+}
+
+/// Overlays are loaded as browser sources from anywhere, so have their own CORS settings.
+async fn public_overlay_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/{login}", get(overlay::overlay))
+        .route("/{login}/events", get(overlay::events))
+        .layer(overlay_cors_layer().await)
+}
+
+/// EventSub deliveries, which are only handled once their signature (and callback path) has been
+/// verified.
+fn dashboard_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(dashboard::dashboard))
+        .route("/settings", put(dashboard::update_settings))
+        .route("/paused", put(dashboard::update_paused))
+        .route(
+            "/blacklist",
+            get(dashboard::blacklist).post(dashboard::blacklist_chatter),
+        )
+        .route("/blacklist/{id}", delete(dashboard::unblacklist_chatter))
+        .route("/logout", post(dashboard::logout))
+}
+
+pub fn webhook_routes(secrets: Arc<dyn SecretStore>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/callback", post(webhook_handler))
+        .route("/callback/{path}", post(webhook_handler))
+        .route_layer(middleware::from_fn_with_state(
+            secrets,
+            verify_external_ident,
+        ))
 }
 
 fn restricted_routes() -> Router<Arc<AppState>> {
@@ -145,9 +242,37 @@ fn restricted_routes() -> Router<Arc<AppState>> {
             post(admin::channel::new_channel).put(admin::channel::update_channel_data),
         )
         .route("/live", put(admin::channel::refresh_channel_state))
+        .route("/timezone", put(admin::channel::update_channel_timezone))
+        .route(
+            "/count-mode",
+            get(admin::channel::get_channel_count_mode)
+                .put(admin::channel::update_channel_count_mode),
+        )
+        .route(
+            "/paused",
+            get(admin::channel::get_channel_paused).put(admin::channel::update_channel_paused),
+        )
+        .route(
+            "/aliases",
+            get(admin::alias::pending_aliases)
+                .post(admin::alias::merge_aliases)
+                .put(admin::alias::repair_aliases),
+        )
         .route(
             "/bot-config",
             get(admin::channel::get_reply_config).put(admin::channel::update_channel_config),
+        )
+        .route(
+            "/reply-mode",
+            put(admin::channel::update_channel_reply_mode),
+        )
+        .route(
+            "/settings",
+            get(admin::channel::get_channel_settings).put(admin::channel::update_channel_settings),
+        )
+        .route(
+            "/dashboard-sessions/{channel_id}",
+            delete(admin::channel::revoke_dashboard_sessions),
         );
 
     let helix_routes = Router::new()
@@ -158,38 +283,185 @@ fn restricted_routes() -> Router<Arc<AppState>> {
             get(admin::helix::active_hooks)
                 .put(admin::helix::reset_hooks)
                 .delete(admin::helix::delete_hooks),
+        )
+        .route("/hooks/rotate", post(admin::helix::rotate_hook_secrets))
+        .route("/hooks/budget", get(admin::helix::hook_budget));
+
+    let note_routes = Router::new()
+        .route("/{channel_id}", get(admin::note::channel_notes))
+        .route(
+            "/{channel_id}/{chatter_id}",
+            get(admin::note::chatter_note)
+                .put(admin::note::update_chatter_note)
+                .delete(admin::note::delete_chatter_note),
         );
 
-    let irc_routes = Router::new().route("/reset", put(admin::reset_irc));
+    let milestone_routes = Router::new()
+        .route(
+            "/",
+            get(admin::milestone::milestones).post(admin::milestone::create_milestone),
+        )
+        .route("/{id}", delete(admin::milestone::delete_milestone));
 
-    Router::new()
-        .route("/session", get(admin::validate_session))
+    let keyword_routes = Router::new()
+        .route("/", post(admin::keyword::create_keyword))
+        .route("/{id}", put(admin::keyword::update_keyword));
+
+    let integration_routes = Router::new()
+        .route(
+            "/discord",
+            get(admin::integration::discord_webhooks)
+                .put(admin::integration::update_discord_webhook),
+        )
+        .route(
+            "/discord/{id}",
+            delete(admin::integration::delete_discord_webhook),
+        );
+
+    let irc_routes = Router::new()
+        .route("/reset", put(admin::reset_irc))
+        .route("/tap", get(admin::pool::irc_tap));
+
+    let pool_routes = Router::new()
+        .route("/stats", get(admin::pool::pool_stats))
+        .route("/rebalance", post(admin::pool::rebalance))
+        .route("/shards", get(admin::pool::shards))
+        .route("/reconnect/{id}", post(admin::pool::reconnect));
+
+    let db_routes = Router::new().route("/stats", get(admin::status::db_stats));
+
+    let token_routes = Router::new()
+        .route("/", get(admin::token::api_tokens))
+        .route("/{id}", delete(admin::token::revoke_api_token));
+
+    let score_routes = Router::new()
+        .route("/adjust", post(admin::score::adjust_score))
+        .route("/history", get(admin::score::score_history));
+
+    let chatter_routes = Router::new().route("/{id}", delete(admin::chatter::delete_chatter));
+
+    let status_routes = Router::new()
+        .route("/replicas", get(admin::status::replicas))
+        .route("/drift", get(admin::status::score_drift))
+        .route("/availability", get(admin::status::availability));
+
+    let router = Router::new()
+        .route(
+            "/session",
+            get(admin::validate_session).delete(admin::end_session),
+        )
+        .route("/trace", get(admin::status::trace_events))
+        .route("/audit", get(admin::audit::audit_log))
+        .route(
+            "/log-level",
+            get(admin::logging::log_level).put(admin::logging::update_log_level),
+        )
+        .route("/flags", get(admin::flag::feature_flags))
+        .route(
+            "/flags/{name}",
+            put(admin::flag::update_feature_flag).delete(admin::flag::reset_feature_flag),
+        )
+        .nest("/status", status_routes)
         .nest("/update", update_routes)
         .nest("/helix", helix_routes)
+        .nest("/notes", note_routes)
+        .nest("/milestones", milestone_routes)
+        .nest("/keywords", keyword_routes)
+        .nest("/integrations", integration_routes)
         .nest("/irc", irc_routes)
+        .nest("/pool", pool_routes)
+        .nest("/db", db_routes)
+        .nest("/scores", score_routes)
+        .nest("/chatters", chatter_routes)
+        .nest("/tokens", token_routes);
+
+    #[cfg(feature = "profiling")]
+    let router = router.nest(
+        "/debug",
+        Router::new()
+            .route("/profile", post(admin::debug::cpu_profile))
+            .route("/runtime", post(admin::debug::runtime_snapshot)),
+    );
+
+    router
+}
+
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    /// The most recent migration applied to the database
+    schema_version: Option<i64>,
+    /// The most recent migration embedded in this build
+    latest_migration: Option<i64>,
+    breakers: Breakers,
+    /// Whether Redis answered its most recent health check
+    redis: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Breakers {
+    postgres: BreakerStatus,
+    helix: BreakerStatus,
 }
 
-#[instrument]
-async fn check_health() -> ApiResult<&'static str> {
-    Ok(ApiResponse::ok("healthy"))
+#[instrument(skip(state))]
+async fn check_health(State(state): State<Arc<AppState>>) -> ApiResult<Health> {
+    let breakers = Breakers {
+        postgres: postgres_breaker().status(),
+        helix: helix_breaker().status(),
+    };
+
+    // stored data is still served while degraded, so this remains a successful response
+    let status = if availability().is_degraded() || breakers.postgres.state != BreakerState::Closed
+    {
+        "degraded"
+    } else {
+        "healthy"
+    };
+
+    // not worth waiting on a database that's known to be unreachable
+    let schema_version = match breakers.postgres.state {
+        BreakerState::Open => None,
+        _ => migrate::schema_version(state.database_pool)
+            .await
+            .inspect_err(|e| tracing::warn!(error = ?e, "failed to read schema version"))
+            .ok()
+            .flatten(),
+    };
+
+    Ok(ApiResponse::ok(Health {
+        status,
+        schema_version,
+        latest_migration: migrate::latest_version(),
+        breakers,
+        redis: redis_healthy(),
+    }))
 }
 
 pub async fn router(
     tx: tokio::sync::mpsc::UnboundedSender<SocketAddr>,
     database_pool: &'static Pool<Postgres>,
+    replicas: &'static ReplicaSet,
     redis_pool: ConnectionManager,
     totp_handler: Arc<Mutex<TOTPHandler>>,
 ) {
-    let secret_key = get_hmac_key().await.unwrap();
-    tracing::info!(secret_key, "HMAC SECRET KEY");
-
-    let (channel_ids, channel_logins) = initialize_channels(database_pool).await.unwrap();
-    let irc_connection = crate::irc::start(channel_logins.clone(), database_pool, 10)
+    shard::init().await.expect("invalid shard assignment");
+    let TrackedChannels {
+        ids: channel_ids,
+        logins: channel_logins,
+        owned_logins,
+    } = initialize_channels(database_pool).await.unwrap();
+    let guarded = score_store(database_pool, replicas, redis_pool.clone()).await;
+    spawn_dead_letter_replay(Arc::clone(&guarded));
+    let scores: Arc<dyn ScoreStore> = guarded;
+    let irc_connection = crate::irc::start(owned_logins, database_pool, Arc::clone(&scores), 10)
         .await
         .unwrap();
 
     let state = Arc::new(AppState {
         database_pool,
+        replicas,
+        scores,
         irc_connection,
         redis_pool: redis_pool.clone(),
         channels: Arc::new(RwLock::new(channel_logins)),
@@ -203,36 +475,78 @@ pub async fn router(
     });
 
     let server_state_clone = Arc::clone(&state);
+    util::reconcile::spawn_channel_reconciliation(Arc::clone(&state));
 
-    let external_post_routes = Router::new()
-        .route("/callback", post(webhook_handler))
-        .route_layer(middleware::from_fn(verify_external_ident));
+    #[cfg(feature = "grpc")]
+    {
+        let grpc_state = Arc::clone(&state);
+        tokio::spawn(async move {
+            if let Err(e) = crate::grpc::serve(grpc_state).await {
+                tracing::error!(error = ?e, "grpc server failed");
+            }
+        });
+    }
 
-    let init_auth_routes = Router::new().route("/new-session", post(admin::new_session));
+    let init_auth_routes = Router::new()
+        .route("/new-session", post(admin::new_session))
+        .route("/twitch/login", get(onboarding::twitch_login))
+        .route("/twitch/callback", get(onboarding::twitch_callback));
 
     let admin_routes = restricted_routes().route_layer(middleware::from_fn_with_state(
         state.clone(),
-        verify_session_ident,
+        verify_admin_ident,
+    ));
+
+    let me_routes = dashboard_routes().route_layer(middleware::from_fn_with_state(
+        state.clone(),
+        verify_broadcaster_session,
     ));
 
     let main_api_routes = Router::new()
         .route("/checkhealth", get(check_health))
+        .route("/search", get(search::search))
         .route("/search/{user}", get(chatter::search))
+        .route("/compare", get(chatter::compare))
         .layer(cors_layer().await);
 
     let (prometheus_layer, metric_handle) = PrometheusMetricLayer::pair();
 
-    let routes = Router::new()
+    // webhooks and the admin api have their own verification, so aren't rate limited
+    let limiter = rate_limiter(redis_pool.clone()).await;
+    let public_routes = Router::new()
         .merge(main_api_routes)
         .nest("/chatter", public_chatter_routes())
         .nest("/channel", public_channel_routes())
+        .nest("/keywords", public_keyword_routes())
+        .nest("/overlay", public_overlay_routes().await)
         .nest("/auth", init_auth_routes)
-        .nest("/_extern", external_post_routes)
+        .nest("/me", me_routes)
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&limiter),
+            rate_limit,
+        ));
+
+    let graphql_routes = Router::new()
+        .route("/api/graphql", post(graphql::graphql))
+        .layer(Extension(graphql::schema()))
+        .layer(cors_layer().await)
+        .route_layer(middleware::from_fn_with_state(limiter, rate_limit));
+
+    let routes = Router::new()
+        .merge(public_routes)
+        .nest(
+            "/_extern",
+            webhook_routes(Arc::new(PostgresSecretStore::new(database_pool))),
+        )
         .nest("/_admin", admin_routes);
 
     let app = Router::new()
         .nest("/api/v1", routes)
+        .merge(graphql_routes)
         .route("/metrics", get(|| async move { metric_handle.render() }))
+        .layer(middleware::from_fn(reject_while_draining))
+        // inside the trace layer so the request ID can be recorded on the request's span
+        .layer(middleware::from_fn(access_log))
         // setting on outermost-ish layer provides prometheus metrics on all routes
         .layer(prometheus_layer)
         .layer(
@@ -261,26 +575,14 @@ pub async fn router(
                         cfconnecting,
                         country,
                         user_agent = tracing::field::Empty,
+                        request_id = tracing::field::Empty,
+                        twitch_message_id = tracing::field::Empty,
+                        twitch_message_retry = tracing::field::Empty,
                     )
                 })
-                .on_response(
-                    |response: &Response, latency: std::time::Duration, _span: &tracing::Span| {
-                        let status = response.status();
-                        if let Some(err) = response.extensions().get::<Arc<RouteError>>() {
-                            tracing::error!(
-                                %status,
-                                latency_ms = latency.as_millis(),
-                                error = ?err,
-                                "processing_failure",
-                            );
-                            
-                        } else if status.is_server_error() || status.is_client_error() {
-                            tracing::warn!(%status, latency_ms = latency.as_millis(), "request_result_non2xx");
-                        } else {
-                            tracing::info!(%status, latency_ms = latency.as_millis(), "request_result_2xx");
-                        }
-                    },
-                ).on_request(|req: &Request, span: &tracing::Span| {
+                // logged by the access log middleware, which also sees the request
+                .on_response(())
+                .on_request(|req: &Request, span: &tracing::Span| {
                     let user_agent = req.headers()
                         .get(http::header::USER_AGENT)
                         .and_then(|v| v.to_str().ok())
@@ -299,7 +601,7 @@ pub async fn router(
         .unwrap();
 
     let socket_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port);
-    let listener = tokio::net::TcpListener::bind(socket_addr).await.unwrap();
+    let listener = TcpListener::bind(socket_addr).await.unwrap();
 
     // we want to trigger these every time we run as each new run uses a unique HMAC secret
     // if !cfg!(debug_assertions) {
@@ -321,8 +623,63 @@ pub async fn router(
         .unwrap();
     // }
 
+    let drain_timeout = shutdown::drain_timeout().await;
+    tokio::spawn(shutdown::listen_for_signals());
+
     tx.send(socket_addr).unwrap();
-    axum::serve(listener, app).await.unwrap()
+    // the peer address is the rate limiting fallback when there's no `cf-connecting-ip`
+    let server = serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await;
+
+    tokio::select! {
+        result = server => result.unwrap(),
+        _ = shutdown().drain_deadline(drain_timeout) => {
+            tracing::warn!(?drain_timeout, "drain timed out, dropping open connections");
+        }
+    }
+
+    tracing::info!("server drained");
+}
+
+/// Serves `app` on `listener` until the server has drained - over TLS, if it's configured (see
+/// `api::tls`).
+async fn serve(
+    listener: TcpListener,
+    app: IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+) -> BoxFuture<'static, std::io::Result<()>> {
+    #[cfg(feature = "tls")]
+    if let Some(acceptor) = tls::acceptor().await.expect("invalid TLS configuration") {
+        let port = listener.local_addr().unwrap().port();
+        tls::spawn_redirect(port)
+            .await
+            .expect("failed to start the redirect server");
+
+        // `ConnectInfo<SocketAddr>` is only implemented for plain TCP listeners and tapped ones
+        let listener = tls::TlsListener::new(listener, acceptor)
+            .unwrap()
+            .tap_io(|_| ());
+
+        return axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown().draining())
+            .into_future()
+            .boxed();
+    }
+
+    #[cfg(not(feature = "tls"))]
+    if var!(Var::TlsCertPath)
+        .await
+        .is_ok_and(|path| !path.trim().is_empty())
+    {
+        tracing::warn!("TLS_CERT_PATH is set, but TLS support wasn't built; serving plain HTTP");
+    }
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown().draining())
+        .into_future()
+        .boxed()
 }
 
 #[instrument(skip_all, err)]
@@ -330,13 +687,14 @@ pub async fn start_server(
     tx: UnboundedSender<SocketAddr>,
     mut rx: UnboundedReceiver<SocketAddr>,
     database_pool: &'static Pool<Postgres>,
+    replicas: &'static ReplicaSet,
     redis_pool: ConnectionManager,
     totp_handler: Arc<Mutex<TOTPHandler>>,
-) -> Result<Vec<JoinHandle<()>>, RouteError> {
+) -> Result<Vec<JoinHandle<()>>, ApiError> {
     tracing::info!("starting server");
 
     let server_handle = tokio::task::spawn(async move {
-        router(tx, database_pool, redis_pool, totp_handler).await;
+        router(tx, database_pool, replicas, redis_pool, totp_handler).await;
     });
 
     let logging_handle = tokio::task::spawn(async move {
@@ -355,97 +713,3 @@ pub async fn start_server(
     let handles = vec![server_handle, logging_handle];
     Ok(handles)
 }
-
-#[derive(Debug, Error)]
-pub enum RouteError {
-    #[error(transparent)]
-    JoinError(#[from] JoinError),
-
-    #[error(transparent)]
-    IrcClientError(#[from] ConnectionClientError),
-
-    #[error(transparent)]
-    QueryError(#[from] PgError),
-
-    #[error(transparent)]
-    ChannelFetch(#[from] ChannelError),
-
-    #[error("{0}")]
-    GenericStatusCode(StatusCode),
-
-    #[allow(dead_code)]
-    #[error("TOTP validation failure: {0}")]
-    ValidationError(String),
-
-    #[error(transparent)]
-    SerdeJsonError(#[from] serde_json::Error),
-
-    #[error(transparent)]
-    HelixError(#[from] HelixErr),
-
-    #[error(transparent)]
-    SignalError(#[from] mpsc::error::SendError<()>),
-
-    #[error(transparent)]
-    RedisError(#[from] RedisErr),
-
-    #[error(transparent)]
-    SqlxError(#[from] sqlx::error::Error),
-
-    #[error("invalid login or id '{0}'")]
-    InvalidUser(String),
-
-    #[error(transparent)]
-    TryRecvError(#[from] oneshot::error::TryRecvError),
-
-    #[error(transparent)]
-    ChannelRecvError(#[from] oneshot::error::RecvError),
-
-    #[error(transparent)]
-    ChannelSendError(#[from] SendError<(String, Sender<Vec<String>>)>),
-}
-
-impl RouteError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            Self::InvalidUser(_) => StatusCode::NOT_FOUND,
-            Self::GenericStatusCode(s) => *s,
-            Self::HelixError(e) => e.status_code(),
-            _ => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
-
-    fn client_message(&self) -> String {
-        match self {
-            Self::InvalidUser(id) => format!("unknown user '{id}'"),
-            Self::GenericStatusCode(_) => "unauthorized".into(),
-            Self::HelixError(e) => e.client_message(),
-            _ => "internal server error".into(),
-        }
-    }
-}
-
-#[derive(Serialize)]
-struct ErrorBody {
-    status: u16,
-    error: String,
-}
-
-impl IntoResponse for RouteError {
-    fn into_response(self) -> Response {
-        let status = self.status_code();
-        let message = self.client_message();
-
-        let mut response = (
-            status,
-            Json(ErrorBody {
-                status: status.as_u16(),
-                error: message,
-            }),
-        )
-            .into_response();
-
-        response.extensions_mut().insert(Arc::new(self));
-        response
-    }
-}
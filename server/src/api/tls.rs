@@ -0,0 +1,235 @@
+//! TLS termination, built with the `tls` feature, so that small deployments can expose the webhook
+//! endpoint (which Twitch only delivers to over HTTPS on port 443) without a proxy in front.
+//!
+//! TLS is enabled by setting `TLS_CERT_PATH` and `TLS_KEY_PATH` to a PEM certificate chain and
+//! private key; `SERVER_API_PORT` then serves HTTPS. If `TLS_REDIRECT_PORT` is set, plain HTTP
+//! requests to that port are redirected to the same path over HTTPS. Certificates are read once
+//! at startup, so a renewed certificate takes effect on the next restart.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::ParseIntError;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::State;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::serve::Listener;
+use http::header::HOST;
+use http::uri::Authority;
+use http::{HeaderMap, StatusCode, Uri};
+use thiserror::Error;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::pki_types::pem::{self, PemObject};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::server::TlsStream;
+
+use crate::util::env::{EnvErr, Var};
+use crate::util::shutdown::shutdown;
+use crate::var;
+
+/// Connections that haven't completed a handshake by now are dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Completed handshakes waiting to be picked up by the server.
+const ACCEPT_BACKLOG: usize = 128;
+
+pub type TlsResult<T> = core::result::Result<T, TlsError>;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error(transparent)]
+    EnvErr(#[from] EnvErr),
+
+    #[error("TLS_CERT_PATH and TLS_KEY_PATH must both be set to enable TLS")]
+    Incomplete,
+
+    #[error("failed to read {path}: {source}")]
+    Pem { path: String, source: pem::Error },
+
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+
+    #[error("invalid TLS_REDIRECT_PORT: {0}")]
+    InvalidPort(#[from] ParseIntError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Builds the acceptor from `TLS_CERT_PATH` and `TLS_KEY_PATH`, or returns `None` if neither is
+/// set.
+pub async fn acceptor() -> TlsResult<Option<TlsAcceptor>> {
+    let cert_path = var!(Var::TlsCertPath).await?.trim();
+    let key_path = var!(Var::TlsKeyPath).await?.trim();
+
+    match (cert_path.is_empty(), key_path.is_empty()) {
+        (true, true) => Ok(None),
+        (false, false) => {
+            let config = server_config(cert_path, key_path)?;
+            tracing::info!(cert_path, "serving over TLS");
+            Ok(Some(TlsAcceptor::from(Arc::new(config))))
+        }
+        _ => Err(TlsError::Incomplete),
+    }
+}
+
+fn server_config(cert_path: &str, key_path: &str) -> TlsResult<ServerConfig> {
+    let pem_err = |path: &str| {
+        let path = path.to_string();
+        move |source| TlsError::Pem { path, source }
+    };
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(pem_err(cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(pem_err(key_path))?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+
+    // the server only speaks HTTP/1
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Accepts TLS connections on a TCP listener.
+///
+/// Handshakes run in their own tasks, so a client that's slow to complete one doesn't hold up
+/// anyone else's connection.
+#[derive(Debug)]
+pub struct TlsListener {
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, acceptor: TlsAcceptor) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, rx) = mpsc::channel(ACCEPT_BACKLOG);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            // usually running out of file descriptors, which takes a moment to fix
+                            tracing::error!(error = ?e, "failed to accept connection");
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    },
+                    // the server has stopped listening
+                    _ = tx.closed() => return,
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => _ = tx.send((stream, addr)).await,
+                        Ok(Err(e)) => tracing::debug!(error = ?e, %addr, "TLS handshake failed"),
+                        Err(_) => tracing::debug!(%addr, "TLS handshake timed out"),
+                    }
+                });
+            }
+        });
+
+        Ok(Self { rx, local_addr })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(accepted) => accepted,
+            // the accept task only stops once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Spawns the HTTP -> HTTPS redirect server on `TLS_REDIRECT_PORT`, unless it isn't set.
+pub async fn spawn_redirect(https_port: u16) -> TlsResult<Option<JoinHandle<()>>> {
+    let port = var!(Var::TlsRedirectPort).await?.trim();
+    if port.is_empty() {
+        return Ok(None);
+    }
+
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port.parse()?);
+    let listener = TcpListener::bind(addr).await?;
+    let app = Router::new().fallback(redirect).with_state(https_port);
+
+    tracing::info!(%addr, "redirecting http to https");
+    Ok(Some(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown().draining())
+            .await
+        {
+            tracing::error!(error = ?e, "redirect server failed");
+        }
+    })))
+}
+
+async fn redirect(State(https_port): State<u16>, headers: HeaderMap, uri: Uri) -> Response {
+    let host = headers.get(HOST).and_then(|host| host.to_str().ok());
+    match host.and_then(|host| https_uri(host, https_port, &uri)) {
+        Some(location) => Redirect::permanent(&location).into_response(),
+        None => StatusCode::BAD_REQUEST.into_response(),
+    }
+}
+
+/// The HTTPS equivalent of a request made to `host` (the `Host` header) for `uri`.
+fn https_uri(host: &str, https_port: u16, uri: &Uri) -> Option<String> {
+    let host = host.parse::<Authority>().ok()?;
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+
+    Some(match https_port {
+        443 => format!("https://{}{path}", host.host()),
+        port => format!("https://{}:{port}{path}", host.host()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redirects_keep_the_host_and_path() {
+        let uri = Uri::from_static("/api/v1/_extern/callback?x=1");
+
+        assert_eq!(
+            https_uri("pea.fan:80", 443, &uri).as_deref(),
+            Some("https://pea.fan/api/v1/_extern/callback?x=1")
+        );
+        assert_eq!(
+            https_uri("[::1]:8080", 8443, &Uri::from_static("/")).as_deref(),
+            Some("https://[::1]:8443/")
+        );
+        assert_eq!(https_uri("not a host", 443, &uri), None);
+    }
+
+    #[test]
+    fn missing_certificates_are_reported() {
+        let Err(TlsError::Pem { path, .. }) = server_config("/nonexistent/cert.pem", "key.pem")
+        else {
+            panic!("config shouldn't build without a certificate");
+        };
+        assert_eq!(path, "/nonexistent/cert.pem");
+    }
+}
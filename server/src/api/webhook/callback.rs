@@ -0,0 +1,80 @@
+//! Where Twitch delivers each subscription's messages.
+//!
+//! Every subscription is given its own random path segment, which is appended to `CALLBACK_URL`
+//! and stored with the subscription. Deliveries for a subscription are only accepted on its own
+//! path, so requests to the callback that don't know a current path are turned away before their
+//! signature is even checked. Subscriptions stored before paths were introduced have none, and
+//! are delivered to `CALLBACK_URL` itself until they're next re-created.
+
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::api::webhook::secret::{SecretError, SecretResult};
+use crate::util::constant_time_cmp;
+use crate::util::env::{EnvErr, Var};
+use crate::var;
+
+/// Random bytes in each path segment.
+const PATH_BYTES: usize = 16;
+
+/// Generates a random path segment for a new subscription.
+pub fn generate_path() -> SecretResult<String> {
+    let mut bytes = [0u8; PATH_BYTES];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| SecretError::Unspecified)?;
+
+    Ok(hex::encode(bytes))
+}
+
+/// The URL Twitch delivers a subscription's messages to.
+pub async fn url(path: Option<&str>) -> Result<String, EnvErr> {
+    let base = var!(Var::CallbackUrl).await?;
+    Ok(join(base, path))
+}
+
+fn join(base: &str, path: Option<&str>) -> String {
+    match path {
+        Some(path) => format!("{}/{path}", base.trim_end_matches('/')),
+        None => base.to_string(),
+    }
+}
+
+/// Whether a delivery made to `requested` belongs to a subscription whose path is `stored`.
+pub fn accepts(stored: Option<&str>, requested: Option<&str>) -> bool {
+    match (stored, requested) {
+        (Some(stored), Some(requested)) => constant_time_cmp(stored, requested),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_are_appended_to_the_callback_url() {
+        let path = generate_path().unwrap();
+        assert_eq!(path.len(), PATH_BYTES * 2);
+        assert_ne!(path, generate_path().unwrap());
+
+        assert_eq!(
+            join("https://pea.fan/api/v1/_extern/callback/", Some("abc")),
+            "https://pea.fan/api/v1/_extern/callback/abc"
+        );
+        assert_eq!(
+            join("https://pea.fan/api/v1/_extern/callback", None),
+            "https://pea.fan/api/v1/_extern/callback"
+        );
+    }
+
+    #[test]
+    fn deliveries_are_only_accepted_on_the_subscriptions_path() {
+        assert!(accepts(Some("abc"), Some("abc")));
+        assert!(accepts(None, None));
+
+        assert!(!accepts(Some("abc"), Some("abd")));
+        assert!(!accepts(Some("abc"), None));
+        assert!(!accepts(None, Some("abc")));
+    }
+}
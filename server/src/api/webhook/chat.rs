@@ -0,0 +1,37 @@
+//! Receives chat from `channel.chat.message` notifications, for channels with the `eventsub_chat`
+//! flag on (see `util::flags`).
+//!
+//! Messages are handed to the same workers as chat received over IRC, and IRC stops handing over
+//! a channel's chat while the flag is on for it, so that each message is only handled once. Chat
+//! is still sent over IRC either way.
+//!
+//! Channels are subscribed when their hooks are reset while the flag is on for them (after which
+//! reconciliation keeps the subscription healthy); notifications for channels it's been turned
+//! off for are acknowledged and dropped, as their chat is back on IRC.
+
+use tracing::instrument;
+
+use crate::api::webhook::{ChannelChatMessagePayload, WebhookError, WebhookResult};
+use crate::db::prelude::ChannelId;
+use crate::irc::bridge::IrcHandle;
+use crate::irc::commands::IncomingMessage;
+use crate::irc::message::ChatMessage;
+use crate::util::flags::{Flag, flags};
+
+/// Hands a message to the workers, returning false if the flag is off for its channel.
+#[instrument(skip(irc, payload), fields(channel = payload.event.broadcaster_user_login))]
+pub async fn deliver(irc: &IrcHandle, payload: ChannelChatMessagePayload) -> WebhookResult<bool> {
+    let channel_id = ChannelId(payload.event.broadcaster_user_id.clone());
+    if !flags().is_on_for(Flag::EventSubChat, &channel_id) {
+        tracing::debug!("eventsub chat is off for channel - dropping message");
+        return Ok(false);
+    }
+
+    irc.msg_tx
+        .send(IncomingMessage::Privmsg(ChatMessage::from(payload.event)))
+        .await
+        .map_err(|_| WebhookError::ChatQueueClosed)?;
+
+    metrics::counter!("eventsub_chat_messages_total").increment(1);
+    Ok(true)
+}
@@ -1,38 +1,95 @@
 use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::sync::OnceCell;
 use tracing::instrument;
 
-use crate::api::webhook::{StreamGenericRequestType, WebhookError};
-use crate::db::prelude::ChannelId;
-use crate::util::helix::Helix;
+use crate::api::webhook::metric::stream_metrics_enabled;
+use crate::api::webhook::subscriber::{SubscriptionBuilder, SubscriptionRequest};
+use crate::api::webhook::{StreamGenericRequestType, SubscriptionGenericData, WebhookError};
+use crate::api::webhook::{callback, secret};
+use crate::db::db_pool;
+use crate::db::models::subscription::EventSubSubscription;
+use crate::db::prelude::{ChannelId, SubscriptionRepository};
+use crate::util::env::Var;
+use crate::util::flags::{Flag, flags};
+use crate::util::helix::{Helix, HelixErr};
+use crate::util::shard::sharding;
+use crate::var;
 
 type Result<T> = core::result::Result<T, WebhookError>;
 
 const HELIX_URL: &str = "https://api.twitch.tv/helix";
 
+/// Twitch's default `max_total_cost`, used if `EVENTSUB_MAX_TOTAL_COST` can't be parsed.
+const DEFAULT_MAX_TOTAL_COST: i64 = 10_000;
+
+/// Fraction of the budget past which each new subscription logs a warning.
+const BUDGET_WARN_RATIO: f64 = 0.9;
+
+/// The topics every tracked channel is subscribed to.
+pub const CHANNEL_TOPICS: [StreamGenericRequestType; 3] = [
+    StreamGenericRequestType::Online,
+    StreamGenericRequestType::Offline,
+    StreamGenericRequestType::Raid,
+];
+
+/// Topics tracked channels are also subscribed to when `EVENTSUB_STREAM_METRICS` is set.
+pub const METRIC_TOPICS: [StreamGenericRequestType; 2] = [
+    StreamGenericRequestType::Subscribe,
+    StreamGenericRequestType::Cheer,
+];
+
+/// Replaces the subscriptions for the channels in `ids` owned by this instance's shard; other
+/// shards' subscriptions are left alone. Channels with the `eventsub_chat` flag on are also
+/// subscribed to their chat.
 #[instrument(skip(ids))]
 pub async fn reset_hooks(ids: &[String]) -> Result<()> {
-    let active_hooks = Helix::get_active_subscriptions().await?;
+    let sharding = sharding();
+    let active_hooks: Vec<SubscriptionGenericData> = Helix::get_active_subscriptions()
+        .await?
+        .into_iter()
+        .filter(|hook| sharding.owns(&hook.condition.broadcaster_user_id))
+        .collect();
 
     tracing::debug!(count = active_hooks.len(), "active_hooks");
 
     if !active_hooks.is_empty() {
-         
         tracing::debug!("active_hooks populated");
         let active_ids: Vec<String> = active_hooks.into_iter().map(|hook| hook.id).collect();
 
         Helix::delete_subscriptions(&active_ids).await?;
     }
 
-    let mut futs: FuturesUnordered<_> = ids
+    let ids: Vec<String> = ids.iter().filter(|id| sharding.owns(id)).cloned().collect();
+    let pool = db_pool().await?;
+    let metric_topics: &[_] = if stream_metrics_enabled().await {
+        &METRIC_TOPICS
+    } else {
+        &[]
+    };
+
+    let mut futs: FuturesUnordered<_> = CHANNEL_TOPICS
         .iter()
-        .map(|id| {
-            Helix::create_subscription(ChannelId(id.clone()), StreamGenericRequestType::Online)
+        .chain(metric_topics)
+        .copied()
+        .flat_map(|notif_type| {
+            ids.iter()
+                .map(move |id| subscribe(pool, ChannelId(id.clone()), notif_type))
         })
         .collect();
 
-    futs.extend(ids.iter().map(|id| {
-        Helix::create_subscription(ChannelId(id.clone()), StreamGenericRequestType::Offline)
-    }));
+    // chat only arrives over EventSub for channels with the flag on (see `webhook::chat`)
+    for id in &ids {
+        let channel_id = ChannelId(id.clone());
+        if flags().is_on_for(Flag::EventSubChat, &channel_id) {
+            futs.push(subscribe(
+                pool,
+                channel_id,
+                StreamGenericRequestType::ChatMessage,
+            ));
+        }
+    }
 
     while let Some(result) = futs.next().await {
         match result {
@@ -43,3 +100,221 @@ pub async fn reset_hooks(ids: &[String]) -> Result<()> {
 
     Ok(())
 }
+
+/// How much of the client's EventSub cost limit the stored subscriptions use.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct SubscriptionBudget {
+    pub used: i64,
+    pub max: i64,
+    pub remaining: i64,
+}
+
+impl SubscriptionBudget {
+    pub async fn load(repo: &SubscriptionRepository) -> Result<Self> {
+        let used = repo.total_cost().await?;
+        let max = max_total_cost().await;
+        metrics::gauge!("eventsub_subscription_cost").set(used as f64);
+
+        Ok(Self {
+            used,
+            max,
+            remaining: (max - used).max(0),
+        })
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    pub fn is_near_limit(&self) -> bool {
+        self.used as f64 >= self.max as f64 * BUDGET_WARN_RATIO
+    }
+}
+
+async fn max_total_cost() -> i64 {
+    let value = var!(Var::EventSubMaxTotalCost).await.unwrap_or_default();
+    match value.trim().parse::<i64>() {
+        Ok(max) if max > 0 => max,
+        _ => {
+            tracing::warn!(value, "invalid EVENTSUB_MAX_TOTAL_COST, using the default");
+            DEFAULT_MAX_TOTAL_COST
+        }
+    }
+}
+
+/// Creates a webhook subscription signed with its own secret, and delivered to its own callback
+/// path.
+///
+/// The secret and path are stored before the subscription is requested, as Twitch sends the
+/// verification challenge (signed with that secret, to that path) before the request is guaranteed
+/// to have returned.
+///
+/// Refuses to subscribe once the subscription cost budget is used up, rather than letting Twitch
+/// reject the request.
+#[instrument(skip(pool))]
+pub async fn subscribe(
+    pool: &'static Pool<Postgres>,
+    channel_id: ChannelId,
+    notif_type: StreamGenericRequestType,
+) -> Result<SubscriptionGenericData> {
+    let repo = SubscriptionRepository::new(pool);
+    let budget = SubscriptionBudget::load(&repo).await?;
+    if budget.is_exhausted() {
+        tracing::error!(?budget, "subscription cost budget exhausted");
+        return Err(WebhookError::BudgetExhausted {
+            used: budget.used,
+            max: budget.max,
+        });
+    }
+
+    if budget.is_near_limit() {
+        tracing::warn!(?budget, "subscription cost budget nearly exhausted");
+    }
+
+    let secret = secret::generate()?;
+    let callback_path = callback::generate_path()?;
+    repo.set_secret(
+        &channel_id,
+        notif_type.as_str(),
+        &secret::seal(&secret).await?,
+        Some(&callback_path),
+    )
+    .await?;
+
+    let request =
+        subscription_request(&channel_id, notif_type, &secret, Some(&callback_path)).await?;
+    let subscription = Helix::create_subscription(&request).await?;
+    repo.set_subscription_id(
+        &channel_id,
+        notif_type.as_str(),
+        &subscription.id,
+        subscription.cost as i32,
+    )
+    .await?;
+
+    Ok(subscription)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RotationReport {
+    pub rotated: usize,
+    /// `{channel_id}:{subscription_type}` for each subscription that wasn't re-created
+    pub failed: Vec<String>,
+}
+
+/// Replaces the secret of every stored subscription, whatever its topic (chat subscriptions
+/// included), and re-subscribes with the new secret.
+#[instrument(skip(pool))]
+pub async fn rotate_secrets(pool: &'static Pool<Postgres>) -> Result<RotationReport> {
+    let repo = SubscriptionRepository::new(pool);
+    let mut report = RotationReport::default();
+
+    let sharding = sharding();
+    for subscription in repo.get_all().await? {
+        // other shards' subscriptions are sent to (and verified by) other instances
+        if !sharding.owns(&subscription.channel_id.0) {
+            continue;
+        }
+
+        match rotate(&repo, &subscription).await {
+            Ok(()) => report.rotated += 1,
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    channel_id = %subscription.channel_id,
+                    subscription_type = subscription.subscription_type,
+                    "failed to rotate webhook secret"
+                );
+
+                report.failed.push(format!(
+                    "{}:{}",
+                    subscription.channel_id, subscription.subscription_type
+                ));
+            }
+        }
+    }
+
+    tracing::info!(
+        rotated = report.rotated,
+        failed = report.failed.len(),
+        "rotated webhook secrets"
+    );
+    Ok(report)
+}
+
+async fn rotate(repo: &SubscriptionRepository, subscription: &EventSubSubscription) -> Result<()> {
+    let notif_type =
+        StreamGenericRequestType::from_subscription_type(&subscription.subscription_type).ok_or(
+            WebhookError::UnknownSubscriptionType(subscription.subscription_type.clone()),
+        )?;
+
+    let secret = secret::generate()?;
+    repo.rotate_secret(
+        &subscription.channel_id,
+        notif_type.as_str(),
+        &secret::seal(&secret).await?,
+    )
+    .await?;
+
+    // twitch won't create a second subscription with the same type and condition, so the old one
+    // goes first; anything it already sent is still verified against the previous secret
+    if let Some(id) = &subscription.subscription_id {
+        Helix::delete_subscriptions(std::slice::from_ref(id)).await?;
+    }
+
+    // the path stays the same, as messages already sent to it are still verified
+    let request = subscription_request(
+        &subscription.channel_id,
+        notif_type,
+        &secret,
+        subscription.callback_path.as_deref(),
+    )
+    .await?;
+    let created = Helix::create_subscription(&request).await?;
+    repo.set_subscription_id(
+        &subscription.channel_id,
+        notif_type.as_str(),
+        &created.id,
+        created.cost as i32,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Builds the request for a channel's subscription, delivered to `callback_path` and signed with
+/// `secret`.
+async fn subscription_request(
+    channel_id: &ChannelId,
+    notif_type: StreamGenericRequestType,
+    secret: &str,
+    callback_path: Option<&str>,
+) -> Result<SubscriptionRequest> {
+    let mut builder = SubscriptionBuilder::new(notif_type)
+        .broadcaster(channel_id.0.clone())
+        .webhook(callback::url(callback_path).await?, secret);
+
+    if notif_type.topic().condition.needs_user() {
+        builder = builder.user(bot_user_id().await?);
+    }
+
+    builder.build()
+}
+
+static BOT_USER_ID: OnceCell<String> = OnceCell::const_new();
+
+/// The id of the account the bot runs as (`USER_LOGIN`), which some subscriptions are made as.
+async fn bot_user_id() -> Result<&'static str> {
+    BOT_USER_ID
+        .get_or_try_init(|| async {
+            let login = var!(Var::UserLogin).await?.to_string();
+            Helix::fetch_users_by_login(vec![login])
+                .await?
+                .into_iter()
+                .next()
+                .map(|user| user.id)
+                .ok_or(WebhookError::HelixError(HelixErr::EmptyDataField))
+        })
+        .await
+        .map(String::as_str)
+}
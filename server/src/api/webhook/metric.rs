@@ -0,0 +1,62 @@
+//! Counts subscriptions and cheers delivered by EventSub as stream metrics (see
+//! `db::models::metric`).
+//!
+//! Channels are only subscribed to these topics when `EVENTSUB_STREAM_METRICS` is set, as Twitch
+//! only allows them for broadcasters that have authorized the client.
+
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::api::webhook::{SubscriptionGenericData, WebhookResult};
+use crate::db::models::metric::StreamMetric;
+use crate::db::prelude::{ChannelId, MetricRepository};
+use crate::util::env::Var;
+use crate::var;
+
+/// A `channel.subscribe` or `channel.cheer` notification.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StreamMetricPayload {
+    pub subscription: SubscriptionGenericData,
+    pub event: StreamMetricEvent,
+}
+
+/// Only the fields we count are kept; who subscribed or cheered isn't recorded.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StreamMetricEvent {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    /// Only present for cheers
+    #[serde(default)]
+    pub bits: Option<i64>,
+}
+
+pub async fn stream_metrics_enabled() -> bool {
+    match var!(Var::EventSubStreamMetrics).await {
+        Ok(val) => matches!(val.trim().to_lowercase().as_str(), "true" | "1"),
+        Err(_) => false,
+    }
+}
+
+#[instrument(skip(pool))]
+pub async fn record(
+    pool: &'static Pool<Postgres>,
+    metric: StreamMetric,
+    payload: StreamMetricPayload,
+) -> WebhookResult<()> {
+    let event = payload.event;
+    let amount = event.bits.unwrap_or(1);
+
+    MetricRepository::new(pool)
+        .insert(&ChannelId(event.broadcaster_user_id), metric, amount)
+        .await?;
+
+    tracing::debug!(
+        channel = event.broadcaster_user_login,
+        metric = metric.as_str(),
+        amount,
+        "stream metric recorded"
+    );
+
+    Ok(())
+}
@@ -1,18 +1,37 @@
+pub mod callback;
+pub mod chat;
 pub mod dispatch;
+pub mod metric;
+pub mod raid;
+pub mod reconcile;
+pub mod revocation;
+pub mod secret;
+pub mod secret_store;
+#[cfg(any(test, feature = "test-util"))]
+pub mod simulator;
+pub mod subscriber;
 
 use std::sync::Arc;
 
 use axum::{body::Body, extract::State};
-use http::{HeaderMap, StatusCode};
+use http::HeaderMap;
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 use tracing::instrument;
 
+use crate::api::error::ApiError;
 use crate::api::middleware::verify_external::{TWITCH_MESSAGE_TYPE_HEADER, VerifiedBody};
 use crate::api::server::AppState;
+use crate::api::webhook::revocation::RevocationPayload;
+use crate::api::webhook::secret::SecretError;
+use crate::api::webhook::subscriber::{TOPICS, Topic};
+use crate::db::PgError;
+use crate::db::models::metric::StreamMetric;
 use crate::db::{prelude::ChannelId, redis::set_stream_state};
+use crate::irc::ConnectionClientError;
+use crate::util::env::EnvErr;
 use crate::util::helix::HelixErr;
 
 pub trait StreamCommonEvent {
@@ -30,16 +49,17 @@ pub async fn webhook_handler(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
     body: VerifiedBody,
-) -> Result<Body, StatusCode> {
+) -> Result<Body, ApiError> {
     tracing::debug!("parsing incoming webhook");
 
-    let notification: serde_json::Value = body.as_json().map_err(|_| StatusCode::BAD_REQUEST)?;
+    let notification: serde_json::Value = body
+        .as_json()
+        .map_err(|_| ApiError::BadRequest("webhook body is not valid JSON".into()))?;
     let msg_type: WebhookMessageType = headers
         .get(TWITCH_MESSAGE_TYPE_HEADER)
         .and_then(|v| v.to_str().ok())
-        .ok_or(StatusCode::BAD_REQUEST)?
-        .try_into()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .ok_or_else(|| ApiError::BadRequest("missing message type header".into()))?
+        .try_into()?;
 
     tracing::info!(msg_type = ?msg_type, notification = %notification, "recv webhook notification");
 
@@ -50,11 +70,11 @@ pub async fn webhook_handler(
         }
         WebhookMessageType::Notify => {
             tracing::warn!("notify webhook");
-            handle_notify(&mut state.redis_pool.clone(), notification).await
+            handle_notify(&state, notification).await
         }
         WebhookMessageType::Revoke => {
             tracing::warn!("revoke webhook");
-            todo!()
+            handle_revoke(&state, notification).await
         }
     }
 }
@@ -63,11 +83,11 @@ pub async fn webhook_handler(
 pub async fn stream_event_notify<R: AsyncCommands + Sync, T>(
     redis_pool: &mut R,
     body: Value,
-) -> Result<Body, StatusCode>
+) -> Result<Body, ApiError>
 where
     T: StreamCommonEvent + StreamCommonSubscription + serde::de::DeserializeOwned + Clone + 'static,
 {
-    let payload: T = serde_json::from_value(body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let payload: T = serde_json::from_value(body).map_err(malformed_payload)?;
     let channel = if payload.broadcaster_login() == "testBroadcaster" {
         String::from("103033809")
     } else {
@@ -78,13 +98,9 @@ where
     tracing::debug!(channel, notif_type, "recv event notification");
 
     if notif_type == "stream.online" {
-        set_stream_state(redis_pool, &ChannelId(channel.clone()), true)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        set_stream_state(redis_pool, &ChannelId(channel.clone()), true).await?;
     } else if notif_type == "stream.offline" {
-        set_stream_state(redis_pool, &ChannelId(channel.clone()), false)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        set_stream_state(redis_pool, &ChannelId(channel.clone()), false).await?;
     }
 
     Ok(channel.into())
@@ -97,9 +113,9 @@ pub struct ChallengeRequest {
 }
 
 #[instrument]
-pub async fn handle_verify(raw_json: Value) -> Result<Body, StatusCode> {
+pub async fn handle_verify(raw_json: Value) -> Result<Body, ApiError> {
     let challenge: ChallengeRequest =
-        serde_json::from_value(raw_json).map_err(|_| StatusCode::BAD_REQUEST)?;
+        serde_json::from_value(raw_json).map_err(malformed_payload)?;
 
     // let broadcaster_id = &challenge.subscription.condition.broadcaster_user_id;
     // if challenge.subscription.r#type == "stream.offline" {
@@ -109,23 +125,58 @@ pub async fn handle_verify(raw_json: Value) -> Result<Body, StatusCode> {
     Ok(challenge.challenge.into())
 }
 
-#[instrument(skip(redis_pool))]
-pub async fn handle_notify<R: AsyncCommands + Sync>(
-    redis_pool: &mut R,
-    raw_json: Value,
-) -> Result<Body, StatusCode> {
+#[instrument(skip(state))]
+pub async fn handle_notify(state: &AppState, raw_json: Value) -> Result<Body, ApiError> {
     tracing::info!(?raw_json, "raw json body");
+    let redis_pool = &mut state.redis_pool.clone();
     match &raw_json["subscription"]["type"].as_str() {
         Some("stream.online") => {
-            stream_event_notify::<R, StreamOnlinePayload>(redis_pool, raw_json).await
+            stream_event_notify::<_, StreamOnlinePayload>(redis_pool, raw_json).await
         }
         Some("stream.offline") => {
-            stream_event_notify::<R, StreamOfflinePayload>(redis_pool, raw_json).await
+            stream_event_notify::<_, StreamOfflinePayload>(redis_pool, raw_json).await
+        }
+        Some("channel.raid") => {
+            let payload = serde_json::from_value(raw_json).map_err(malformed_payload)?;
+            raid::record(state.database_pool, payload).await?;
+            Ok(Body::empty())
+        }
+        Some("channel.chat.message") => {
+            let payload = serde_json::from_value(raw_json).map_err(malformed_payload)?;
+            chat::deliver(&state.irc_connection, payload).await?;
+            Ok(Body::empty())
+        }
+        Some(subscription_type @ ("channel.subscribe" | "channel.cheer")) => {
+            let metric = match *subscription_type {
+                "channel.cheer" => StreamMetric::Cheer,
+                _ => StreamMetric::Subscription,
+            };
+
+            let payload = serde_json::from_value(raw_json).map_err(malformed_payload)?;
+            metric::record(state.database_pool, metric, payload).await?;
+            Ok(Body::empty())
+        }
+        other => {
+            Err(WebhookError::UnknownSubscriptionType(other.unwrap_or_default().to_string()).into())
         }
-        _ => Err(StatusCode::BAD_REQUEST),
     }
 }
 
+#[instrument(skip(state, raw_json))]
+pub async fn handle_revoke(state: &AppState, raw_json: Value) -> Result<Body, ApiError> {
+    let payload: RevocationPayload = serde_json::from_value(raw_json).map_err(malformed_payload)?;
+
+    let remediation = revocation::process(state, payload).await?;
+    tracing::info!(?remediation, "processed subscription revocation");
+
+    Ok(Body::empty())
+}
+
+fn malformed_payload(e: serde_json::Error) -> ApiError {
+    tracing::warn!(error = ?e, "malformed webhook payload");
+    ApiError::BadRequest("malformed webhook payload".into())
+}
+
 pub type WebhookResult<T> = core::result::Result<T, WebhookError>;
 
 #[derive(Debug, Error)]
@@ -135,6 +186,36 @@ pub enum WebhookError {
 
     #[error(transparent)]
     HelixError(#[from] HelixErr),
+
+    #[error(transparent)]
+    QueryError(#[from] PgError),
+
+    #[error(transparent)]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    SecretError(#[from] SecretError),
+
+    #[error("unknown subscription type '{0}'")]
+    UnknownSubscriptionType(String),
+
+    #[error("{subscription_type} subscriptions need a {field}")]
+    IncompleteSubscription {
+        subscription_type: &'static str,
+        field: &'static str,
+    },
+
+    #[error(transparent)]
+    EnvError(#[from] EnvErr),
+
+    #[error("subscription cost budget exhausted ({used} of {max} used)")]
+    BudgetExhausted { used: i64, max: i64 },
+
+    #[error(transparent)]
+    IrcError(#[from] Box<ConnectionClientError>),
+
+    #[error("chat message queue is closed")]
+    ChatQueueClosed,
 }
 
 #[derive(Debug)]
@@ -163,10 +244,37 @@ impl TryFrom<&str> for WebhookMessageType {
     }
 }
 
-#[derive(Debug)]
+/// The EventSub topics we know how to subscribe to; see `subscriber::TOPICS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StreamGenericRequestType {
     Online,
     Offline,
+    Follow,
+    Subscribe,
+    Cheer,
+    Raid,
+    ChatMessage,
+}
+
+impl StreamGenericRequestType {
+    pub fn topic(self) -> &'static Topic {
+        TOPICS
+            .iter()
+            .find(|topic| topic.notif_type == self)
+            .expect("every subscription type has a topic")
+    }
+
+    /// The EventSub subscription type
+    pub fn as_str(&self) -> &'static str {
+        self.topic().subscription_type
+    }
+
+    pub fn from_subscription_type(value: &str) -> Option<Self> {
+        TOPICS
+            .iter()
+            .find(|topic| topic.subscription_type == value)
+            .map(|topic| topic.notif_type)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -181,47 +289,10 @@ pub struct StreamOfflinePayload {
     pub event: StreamOfflineEvent,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct StreamGenericRequest {
-    pub r#type: String,
-    pub version: String,
-    pub condition: BroadcasterUserId,
-    pub transport: Transport,
-}
-
-impl StreamGenericRequest {
-    pub fn new(
-        broadcaster_user_id: &str,
-        callback: &str,
-        secret: &str,
-        r#type: StreamGenericRequestType,
-    ) -> Self {
-        let broadcaster_user_id = broadcaster_user_id.to_string();
-        let condition = BroadcasterUserId {
-            broadcaster_user_id,
-        };
-        let transport = Transport {
-            method: "webhook".to_string(),
-            callback: callback.to_string(),
-            secret: Some(secret.to_owned()),
-        };
-
-        let notify_type = match r#type {
-            StreamGenericRequestType::Online => String::from("stream.online"),
-            StreamGenericRequestType::Offline => String::from("stream.offline"),
-        };
-
-        Self {
-            r#type: notify_type,
-            version: String::from("1"),
-            condition,
-            transport,
-        }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct BroadcasterUserId {
+    /// The receiving channel for raids
+    #[serde(alias = "to_broadcaster_user_id")]
     pub broadcaster_user_id: String,
 }
 
@@ -242,7 +313,7 @@ pub struct HelixDataGeneric<T> {
     pub pagination: HelixPagination,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct HelixPagination {
     pub cursor: Option<String>,
 }
@@ -255,10 +326,8 @@ pub struct SubscriptionGenericData {
     pub version: String,
     pub cost: usize,
 
-    /// NOTE:
-    ///  this doesn't handle for the case where we are required to provide
-    ///  a `user_id` over a `broadcaster_user_id`; not generalized here as
-    ///  we don't (currently) need to handle other condition types...
+    /// Only the channel the subscription is for is read back from Twitch, whatever the shape of
+    /// its condition; see `subscriber::Condition` for the full conditions.
     pub condition: BroadcasterUserId,
     pub transport: Transport,
     pub created_at: String,
@@ -281,6 +350,64 @@ pub struct StreamOfflineEvent {
     pub broadcaster_user_name: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelChatMessagePayload {
+    pub subscription: SubscriptionGenericData,
+    pub event: ChannelChatMessageEvent,
+}
+
+/// A `channel.chat.message` event; converts into an `irc::message::ChatMessage`.
+///
+/// Only the fields we use are kept - badges, cheers, replies and the like are ignored.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelChatMessageEvent {
+    pub broadcaster_user_id: String,
+    pub broadcaster_user_login: String,
+    pub broadcaster_user_name: String,
+    pub chatter_user_id: String,
+    pub chatter_user_login: String,
+    pub chatter_user_name: String,
+    pub message_id: String,
+    pub message: ChatMessageBody,
+    /// Empty if the chatter hasn't set a color
+    #[serde(default)]
+    pub color: String,
+    /// Only present for messages sent in a shared chat session
+    #[serde(default)]
+    pub source_broadcaster_user_id: Option<String>,
+    #[serde(default)]
+    pub badges: Vec<ChatBadge>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessageBody {
+    pub text: String,
+    /// The message split into text, emotes, mentions and cheermotes, in order
+    #[serde(default)]
+    pub fragments: Vec<ChatMessageFragment>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessageFragment {
+    pub r#type: String,
+    pub text: String,
+    /// Only present for `emote` fragments
+    #[serde(default)]
+    pub emote: Option<ChatMessageEmote>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessageEmote {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChatBadge {
+    /// e.g. `broadcaster`, `moderator` or `subscriber`
+    pub set_id: String,
+    pub id: String,
+}
+
 macro_rules! impl_stream_event {
     (
         $struct:ty,
@@ -0,0 +1,57 @@
+//! Records raids into tracked channels.
+//!
+//! Raids are stored so that score timelines can be marked with them, and published to the
+//! `EventBus`. Raiders are thanked in chat from IRC instead (see `RAID_THANKS`), as the raid's
+//! `USERNOTICE` arrives in the channel being counted.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::api::webhook::{SubscriptionGenericData, WebhookResult};
+use crate::db::models::raid::RaidEvent;
+use crate::db::prelude::{ChannelId, RaidRepository};
+use crate::irc::events::{LiveEvent, events};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelRaidPayload {
+    pub subscription: SubscriptionGenericData,
+    pub event: ChannelRaidEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelRaidEvent {
+    pub from_broadcaster_user_id: String,
+    pub from_broadcaster_user_login: String,
+    pub from_broadcaster_user_name: String,
+    pub to_broadcaster_user_id: String,
+    pub to_broadcaster_user_login: String,
+    pub to_broadcaster_user_name: String,
+    pub viewers: i64,
+}
+
+#[instrument(skip(pool))]
+pub async fn record(
+    pool: &'static Pool<Postgres>,
+    payload: ChannelRaidPayload,
+) -> WebhookResult<RaidEvent> {
+    let event = payload.event;
+    let raid = RaidRepository::new(pool)
+        .insert(
+            &ChannelId(event.to_broadcaster_user_id),
+            &event.from_broadcaster_user_id,
+            &event.from_broadcaster_user_login,
+            event.viewers,
+        )
+        .await?;
+
+    tracing::info!(
+        channel = event.to_broadcaster_user_login,
+        from = raid.from_broadcaster_login,
+        viewers = raid.viewers,
+        "raid recorded"
+    );
+
+    events().publish(LiveEvent::Raid(raid.clone()));
+    Ok(raid)
+}
@@ -0,0 +1,353 @@
+//! Periodically reconciles stored subscriptions with the subscriptions Twitch reports.
+//!
+//! A subscription can fail without a revocation ever being delivered - e.g. when the callback
+//! couldn't be verified - so it would otherwise stay broken until the hooks are next reset. Failed
+//! and missing subscriptions are re-created, and every disagreement between the two is counted in
+//! the `eventsub_reconcile_mismatches` gauge (labelled by `kind`) and raised on the `alert`
+//! tracing target.
+//!
+//! Nothing is re-created while the server is draining, as a failed subscription has to be deleted
+//! before it can be re-created, and a drain could end between the two.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::instrument;
+
+use crate::api::webhook::dispatch;
+use crate::api::webhook::revocation::RevocationReason;
+use crate::api::webhook::{
+    StreamGenericRequestType, SubscriptionGenericData, WebhookError, WebhookResult,
+};
+use crate::db::models::subscription::EventSubSubscription;
+use crate::db::prelude::SubscriptionRepository;
+use crate::util::helix::Helix;
+use crate::util::shard::sharding;
+use crate::util::shutdown::shutdown;
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60 * 15);
+
+/// Subscriptions changed more recently than this are left alone, as they may be mid-creation or
+/// waiting on a re-subscription scheduled by a revocation.
+const SETTLE_PERIOD: Duration = Duration::from_secs(60 * 10);
+
+/// Statuses Twitch leaves a subscription in when it has stopped delivering notifications, but
+/// re-creating it may fix.
+const FAILED_STATUSES: [&str; 2] = [
+    "webhook_callback_verification_failed",
+    "notification_failures_exceeded",
+];
+
+/// Statuses of subscriptions that are (or are about to be) delivering notifications.
+const HEALTHY_STATUSES: [&str; 2] = ["enabled", "webhook_callback_verification_pending"];
+
+/// How a stored subscription disagrees with Twitch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mismatch {
+    /// Twitch doesn't have the subscription at all
+    Missing,
+    /// Twitch has the subscription (with this id), but it's failed
+    Failed(String),
+    /// Twitch revoked the subscription with this status, but the revocation wasn't recorded
+    Revoked(String),
+    /// Twitch's subscription is healthy, but its id or status isn't the one stored
+    Stale,
+}
+
+impl Mismatch {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::Failed(_) => "failed",
+            Self::Revoked(_) => "revoked",
+            Self::Stale => "stale",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    pub checked: usize,
+    pub missing: usize,
+    pub failed: usize,
+    pub revoked: usize,
+    pub stale: usize,
+    /// Subscriptions Twitch has that aren't stored
+    pub orphaned: usize,
+    pub recreated: usize,
+    /// `{channel_id}:{subscription_type}` for each subscription that couldn't be re-created
+    pub errors: Vec<String>,
+}
+
+impl ReconcileReport {
+    pub fn mismatches(&self) -> usize {
+        self.missing + self.failed + self.revoked + self.stale + self.orphaned
+    }
+
+    fn record(&mut self, mismatch: &Mismatch) {
+        match mismatch {
+            Mismatch::Missing => self.missing += 1,
+            Mismatch::Failed(_) => self.failed += 1,
+            Mismatch::Revoked(_) => self.revoked += 1,
+            Mismatch::Stale => self.stale += 1,
+        }
+    }
+}
+
+/// Compares a stored subscription against Twitch's subscription of the same channel and type.
+fn compare(
+    local: &EventSubSubscription,
+    remote: Option<&SubscriptionGenericData>,
+) -> Option<Mismatch> {
+    let Some(remote) = remote else {
+        return Some(Mismatch::Missing);
+    };
+
+    if FAILED_STATUSES.contains(&remote.status.as_str()) {
+        Some(Mismatch::Failed(remote.id.clone()))
+    } else if !HEALTHY_STATUSES.contains(&remote.status.as_str()) {
+        Some(Mismatch::Revoked(remote.status.clone()))
+    } else if local.status != "enabled" || local.subscription_id.as_ref() != Some(&remote.id) {
+        Some(Mismatch::Stale)
+    } else {
+        None
+    }
+}
+
+/// Whether a stored subscription should currently exist on Twitch's side.
+fn is_expected(local: &EventSubSubscription) -> bool {
+    let settled = Utc::now().naive_utc() - local.updated_at
+        >= chrono::Duration::from_std(SETTLE_PERIOD).unwrap_or_default();
+
+    // subscriptions revoked for good (e.g. by the broadcaster) aren't expected back
+    settled
+        && (local.status == "enabled"
+            || RevocationReason::from_status(&local.status).should_resubscribe())
+}
+
+/// Reconciles this shard's stored subscriptions with Twitch's, re-creating any that are failed or
+/// missing.
+#[instrument(skip(pool))]
+pub async fn reconcile(pool: &'static Pool<Postgres>) -> WebhookResult<ReconcileReport> {
+    let sharding = sharding();
+    let repo = SubscriptionRepository::new(pool);
+
+    let mut remote: HashMap<(String, String), SubscriptionGenericData> =
+        Helix::get_active_subscriptions()
+            .await?
+            .into_iter()
+            .filter(|sub| sharding.owns(&sub.condition.broadcaster_user_id))
+            .map(|sub| {
+                let key = (
+                    sub.condition.broadcaster_user_id.clone(),
+                    sub.r#type.clone(),
+                );
+                (key, sub)
+            })
+            .collect();
+
+    let mut report = ReconcileReport::default();
+    for local in repo.get_all().await? {
+        if !sharding.owns(&local.channel_id.0) {
+            continue;
+        }
+
+        let key = (local.channel_id.0.clone(), local.subscription_type.clone());
+        let remote = remote.remove(&key);
+        if !is_expected(&local) {
+            continue;
+        }
+
+        report.checked += 1;
+        let Some(mismatch) = compare(&local, remote.as_ref()) else {
+            continue;
+        };
+
+        tracing::warn!(
+            channel_id = %local.channel_id,
+            subscription_type = local.subscription_type,
+            kind = mismatch.kind(),
+            "subscription doesn't match twitch"
+        );
+        report.record(&mismatch);
+
+        if shutdown().is_draining() {
+            // the remaining subscriptions weren't compared, so orphans can't be counted either
+            tracing::info!("draining, leaving the remaining subscriptions as they are");
+            return Ok(report);
+        }
+
+        let result = match &mismatch {
+            Mismatch::Missing => recreate(pool, &local, None).await,
+            Mismatch::Failed(id) => recreate(pool, &local, Some(id)).await,
+            Mismatch::Revoked(status) => repo
+                .mark_revoked(&local.channel_id, &local.subscription_type, status)
+                .await
+                .map_err(Into::into),
+            Mismatch::Stale => Ok(()),
+        };
+
+        match result {
+            Ok(()) if matches!(mismatch, Mismatch::Missing | Mismatch::Failed(_)) => {
+                report.recreated += 1
+            }
+            Ok(()) => (),
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    channel_id = %local.channel_id,
+                    subscription_type = local.subscription_type,
+                    "failed to reconcile subscription"
+                );
+
+                report
+                    .errors
+                    .push(format!("{}:{}", local.channel_id, local.subscription_type));
+            }
+        }
+    }
+
+    // anything left wasn't matched against a stored subscription
+    report.orphaned = remote.len();
+
+    Ok(report)
+}
+
+async fn recreate(
+    pool: &'static Pool<Postgres>,
+    local: &EventSubSubscription,
+    failed_id: Option<&String>,
+) -> WebhookResult<()> {
+    let notif_type = StreamGenericRequestType::from_subscription_type(&local.subscription_type)
+        .ok_or_else(|| WebhookError::UnknownSubscriptionType(local.subscription_type.clone()))?;
+
+    // twitch won't create a second subscription with the same type and condition
+    if let Some(id) = failed_id {
+        Helix::delete_subscriptions(std::slice::from_ref(id)).await?;
+    }
+
+    dispatch::subscribe(pool, local.channel_id.clone(), notif_type).await?;
+    Ok(())
+}
+
+fn report_metrics(report: &ReconcileReport) {
+    for (kind, count) in [
+        ("missing", report.missing),
+        ("failed", report.failed),
+        ("revoked", report.revoked),
+        ("stale", report.stale),
+        ("orphaned", report.orphaned),
+    ] {
+        metrics::gauge!("eventsub_reconcile_mismatches", "kind" => kind).set(count as f64);
+    }
+
+    metrics::counter!("eventsub_subscriptions_recreated_total").increment(report.recreated as u64);
+}
+
+pub fn spawn_subscription_reconciliation(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // the hooks are reset on startup, so there's nothing to reconcile until the first interval
+        let mut interval =
+            tokio::time::interval_at(Instant::now() + RECONCILE_INTERVAL, RECONCILE_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = shutdown().draining() => break,
+            }
+
+            match reconcile(pool).await {
+                Ok(report) => {
+                    report_metrics(&report);
+                    if report.mismatches() > 0 {
+                        tracing::warn!(
+                            target: "alert",
+                            ?report,
+                            "subscriptions didn't match twitch"
+                        );
+                    } else {
+                        tracing::debug!(checked = report.checked, "subscriptions match twitch");
+                    }
+                }
+                Err(e) => tracing::error!(error = ?e, "failed to reconcile subscriptions"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::prelude::ChannelId;
+
+    fn remote(id: &str, status: &str) -> SubscriptionGenericData {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "status": status,
+            "type": "stream.online",
+            "version": "1",
+            "cost": 1,
+            "condition": { "broadcaster_user_id": "103033809" },
+            "transport": { "method": "webhook", "callback": "https://example.com" },
+            "created_at": "2026-01-01T00:00:00Z"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn stored_subscriptions_are_compared_with_twitch() {
+        let settled = Utc::now().naive_utc() - chrono::Duration::hours(1);
+        let local = EventSubSubscription {
+            channel_id: ChannelId("103033809".into()),
+            subscription_type: "stream.online".into(),
+            subscription_id: Some("sub-1".into()),
+            secret: String::new(),
+            previous_secret: None,
+            rotated_at: None,
+            callback_path: None,
+            status: "enabled".into(),
+            revoked_at: None,
+            cost: 1,
+            created_at: settled,
+            updated_at: settled,
+        };
+        assert!(is_expected(&local));
+
+        assert_eq!(compare(&local, Some(&remote("sub-1", "enabled"))), None);
+        assert_eq!(compare(&local, None), Some(Mismatch::Missing));
+        assert_eq!(
+            compare(
+                &local,
+                Some(&remote("sub-1", "webhook_callback_verification_failed"))
+            ),
+            Some(Mismatch::Failed("sub-1".into()))
+        );
+        assert_eq!(
+            compare(&local, Some(&remote("sub-1", "authorization_revoked"))),
+            Some(Mismatch::Revoked("authorization_revoked".into()))
+        );
+        assert_eq!(
+            compare(&local, Some(&remote("sub-2", "enabled"))),
+            Some(Mismatch::Stale)
+        );
+
+        // revoked for good, or too recently changed to judge
+        let revoked = EventSubSubscription {
+            status: "authorization_revoked".into(),
+            ..local.clone()
+        };
+        assert!(!is_expected(&revoked));
+
+        let recent = EventSubSubscription {
+            updated_at: Utc::now().naive_utc(),
+            ..local
+        };
+        assert!(!is_expected(&recent));
+    }
+}
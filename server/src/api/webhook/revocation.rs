@@ -0,0 +1,184 @@
+//! Handles subscriptions revoked by Twitch.
+//!
+//! Every revocation is recorded against the subscription and raised as an event on the `alert`
+//! tracing target. What happens next depends on the reason: a subscription revoked after too many
+//! failed deliveries is re-created after a delay, and a broadcaster revoking authorization also
+//! removes their channel from the IRC connection.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::api::server::AppState;
+use crate::api::webhook::dispatch;
+use crate::api::webhook::{StreamGenericRequestType, SubscriptionGenericData, WebhookResult};
+use crate::db::prelude::SubscriptionRepository;
+use crate::db::prelude::{ChannelId, ChatterId, ChatterRepository, Repository};
+use crate::util::shard::sharding;
+
+/// Delay before re-subscribing, giving whatever caused the failed deliveries a chance to clear.
+const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(60 * 5);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RevocationPayload {
+    pub subscription: SubscriptionGenericData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevocationReason {
+    /// The broadcaster's account no longer exists
+    UserRemoved,
+    /// The broadcaster revoked our authorization
+    AuthorizationRevoked,
+    /// Twitch gave up after too many failed deliveries
+    NotificationFailuresExceeded,
+    /// The subscription's type or version is no longer supported
+    VersionRemoved,
+    Other(String),
+}
+
+impl RevocationReason {
+    pub fn from_status(status: &str) -> Self {
+        match status {
+            "user_removed" => Self::UserRemoved,
+            "authorization_revoked" => Self::AuthorizationRevoked,
+            "notification_failures_exceeded" => Self::NotificationFailuresExceeded,
+            "version_removed" => Self::VersionRemoved,
+            other => Self::Other(other.to_string()),
+        }
+    }
+
+    /// Only failed deliveries are worth retrying; anything else would be revoked again.
+    pub fn should_resubscribe(&self) -> bool {
+        matches!(self, Self::NotificationFailuresExceeded)
+    }
+
+    pub fn should_part(&self) -> bool {
+        matches!(self, Self::AuthorizationRevoked)
+    }
+}
+
+/// What was done in response to a revocation.
+#[derive(Debug, Default, Serialize)]
+pub struct Remediation {
+    pub resubscribe_scheduled: bool,
+    /// The channel parted, as `#login`
+    pub parted: Option<String>,
+}
+
+#[instrument(skip(state, payload), fields(subscription_id = payload.subscription.id))]
+pub async fn process(state: &AppState, payload: RevocationPayload) -> WebhookResult<Remediation> {
+    let subscription = payload.subscription;
+    let reason = RevocationReason::from_status(&subscription.status);
+    let channel_id = ChannelId(subscription.condition.broadcaster_user_id.clone());
+
+    tracing::warn!(
+        target: "alert",
+        channel_id = %channel_id,
+        subscription_type = subscription.r#type,
+        reason = subscription.status,
+        "webhook subscription revoked"
+    );
+
+    SubscriptionRepository::new(state.database_pool)
+        .mark_revoked(&channel_id, &subscription.r#type, &subscription.status)
+        .await?;
+
+    let mut remediation = Remediation::default();
+
+    // a channel moved to another shard (after a resize) is re-subscribed by its new shard instead
+    if reason.should_resubscribe() && !sharding().owns(&channel_id.0) {
+        tracing::info!(channel_id = %channel_id, "not re-subscribing to another shard's channel");
+    } else if reason.should_resubscribe() {
+        match StreamGenericRequestType::from_subscription_type(&subscription.r#type) {
+            Some(notif_type) => {
+                schedule_resubscribe(state.database_pool, channel_id.clone(), notif_type);
+                remediation.resubscribe_scheduled = true;
+            }
+            None => tracing::warn!(
+                subscription_type = subscription.r#type,
+                "not re-subscribing to unknown subscription type"
+            ),
+        }
+    }
+
+    if reason.should_part() {
+        let broadcaster = ChatterRepository::new(state.database_pool)
+            .get_by_id(&ChatterId(channel_id.0.clone()))
+            .await?;
+
+        match broadcaster {
+            Some(broadcaster) => {
+                let channel = state
+                    .irc_connection
+                    .remove_channel(broadcaster.login)
+                    .await
+                    .map_err(Box::new)?;
+
+                tracing::warn!(
+                    target: "alert",
+                    %channel,
+                    "parted channel after authorization was revoked"
+                );
+                remediation.parted = Some(channel);
+            }
+            None => tracing::warn!(channel_id = %channel_id, "unknown broadcaster - not parting"),
+        }
+    }
+
+    Ok(remediation)
+}
+
+fn schedule_resubscribe(
+    pool: &'static Pool<Postgres>,
+    channel_id: ChannelId,
+    notif_type: StreamGenericRequestType,
+) {
+    tracing::info!(
+        channel_id = %channel_id,
+        delay = ?RESUBSCRIBE_AFTER,
+        "scheduling re-subscription"
+    );
+
+    tokio::spawn(async move {
+        tokio::time::sleep(RESUBSCRIBE_AFTER).await;
+
+        match dispatch::subscribe(pool, channel_id.clone(), notif_type).await {
+            Ok(subscription) => {
+                tracing::info!(
+                    subscription_id = subscription.id,
+                    "re-subscribed after revocation"
+                )
+            }
+            Err(e) => tracing::error!(
+                target: "alert",
+                error = ?e,
+                channel_id = %channel_id,
+                "failed to re-subscribe after revocation"
+            ),
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remediation_depends_on_reason() {
+        let failures = RevocationReason::from_status("notification_failures_exceeded");
+        assert!(failures.should_resubscribe());
+        assert!(!failures.should_part());
+
+        let revoked = RevocationReason::from_status("authorization_revoked");
+        assert!(!revoked.should_resubscribe());
+        assert!(revoked.should_part());
+
+        for status in ["user_removed", "version_removed", "something_new"] {
+            let reason = RevocationReason::from_status(status);
+            assert!(!reason.should_resubscribe() && !reason.should_part());
+        }
+    }
+}
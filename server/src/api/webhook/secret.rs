@@ -0,0 +1,185 @@
+//! Webhook subscription secrets.
+//!
+//! Each subscription is signed with its own randomly-generated secret. With `EVENTSUB_SECRET_KEY`
+//! set, secrets are sealed with AES-256-GCM before they're written to the database and stored as
+//! `v1:{nonce}:{ciphertext}`; anything without the prefix is read back as-is, so secrets stored
+//! before the key was configured keep working until they're rotated.
+//!
+//! Release builds refuse to start without the key (see `require_key`); debug builds store secrets
+//! unencrypted.
+
+use std::sync::LazyLock;
+
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+use crate::util::env::{EnvErr, Var};
+use crate::var;
+
+const SEALED_PREFIX: &str = "v1:";
+
+static MASTER_KEY: LazyLock<OnceCell<Option<LessSafeKey>>> = LazyLock::new(OnceCell::new);
+async fn master_key() -> SecretResult<Option<&'static LessSafeKey>> {
+    MASTER_KEY
+        .get_or_try_init(|| async {
+            let hex_key = var!(Var::EventSubSecretKey).await?.trim();
+            if hex_key.is_empty() {
+                return Ok(None);
+            }
+
+            parse_key(hex_key).map(Some)
+        })
+        .await
+        .map(Option::as_ref)
+}
+
+/// Checks `EVENTSUB_SECRET_KEY` before the server starts: a malformed key is always an error, and
+/// a missing one is an error outside of debug builds.
+pub async fn require_key() -> SecretResult<()> {
+    if master_key().await?.is_some() {
+        return Ok(());
+    }
+
+    if !cfg!(debug_assertions) {
+        return Err(SecretError::KeyRequired);
+    }
+
+    tracing::error!(
+        "EVENTSUB_SECRET_KEY is unset - webhook secrets will be stored UNENCRYPTED; this is only \
+         allowed in debug builds"
+    );
+    Ok(())
+}
+
+fn parse_key(hex_key: &str) -> SecretResult<LessSafeKey> {
+    let bytes = hex::decode(hex_key).map_err(|_| SecretError::InvalidKey)?;
+    let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| SecretError::InvalidKey)?;
+
+    Ok(LessSafeKey::new(key))
+}
+
+/// Generates a random secret to sign a single webhook subscription with.
+pub fn generate() -> SecretResult<String> {
+    let mut bytes = [0u8; digest::SHA256_OUTPUT_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| SecretError::Unspecified)?;
+
+    Ok(hex::encode(bytes))
+}
+
+/// Encrypts a secret for storage, if a master key is configured.
+pub async fn seal(secret: &str) -> SecretResult<String> {
+    match master_key().await? {
+        Some(key) => seal_with(key, secret),
+        None => Ok(secret.to_string()),
+    }
+}
+
+/// Decrypts a stored secret.
+pub async fn open(stored: &str) -> SecretResult<String> {
+    if !stored.starts_with(SEALED_PREFIX) {
+        return Ok(stored.to_string());
+    }
+
+    let key = master_key().await?.ok_or(SecretError::MissingKey)?;
+    open_with(key, stored)
+}
+
+fn seal_with(key: &LessSafeKey, secret: &str) -> SecretResult<String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| SecretError::Unspecified)?;
+
+    let mut in_out = secret.as_bytes().to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| SecretError::Unspecified)?;
+
+    Ok(format!(
+        "{SEALED_PREFIX}{}:{}",
+        hex::encode(nonce),
+        hex::encode(in_out)
+    ))
+}
+
+fn open_with(key: &LessSafeKey, stored: &str) -> SecretResult<String> {
+    let (nonce, ciphertext) = stored
+        .strip_prefix(SEALED_PREFIX)
+        .and_then(|sealed| sealed.split_once(':'))
+        .ok_or(SecretError::Malformed)?;
+
+    let nonce: [u8; NONCE_LEN] = hex::decode(nonce)
+        .ok()
+        .and_then(|n| n.try_into().ok())
+        .ok_or(SecretError::Malformed)?;
+    let mut in_out = hex::decode(ciphertext).map_err(|_| SecretError::Malformed)?;
+
+    let plaintext = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| SecretError::Decrypt)?;
+
+    String::from_utf8(plaintext.to_vec()).map_err(|_| SecretError::Malformed)
+}
+
+pub type SecretResult<T> = core::result::Result<T, SecretError>;
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error(transparent)]
+    EnvError(#[from] EnvErr),
+
+    #[error("EVENTSUB_SECRET_KEY must be a hex-encoded 256-bit key")]
+    InvalidKey,
+
+    #[error("EVENTSUB_SECRET_KEY must be set to store webhook secrets")]
+    KeyRequired,
+
+    #[error("stored webhook secret is encrypted but EVENTSUB_SECRET_KEY is unset")]
+    MissingKey,
+
+    #[error("stored webhook secret is malformed")]
+    Malformed,
+
+    #[error("failed to decrypt stored webhook secret")]
+    Decrypt,
+
+    #[error(transparent)]
+    SqlxError(#[from] sqlx::Error),
+
+    #[error("ring::error::Unspecified error occurred")]
+    Unspecified,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sealed_secrets_round_trip() {
+        let key = parse_key(&"ab".repeat(32)).unwrap();
+        let secret = generate().unwrap();
+
+        let sealed = seal_with(&key, &secret).unwrap();
+        assert!(sealed.starts_with(SEALED_PREFIX));
+        assert!(!sealed.contains(&secret));
+        assert_eq!(open_with(&key, &sealed).unwrap(), secret);
+
+        let other = parse_key(&"cd".repeat(32)).unwrap();
+        assert!(matches!(
+            open_with(&other, &sealed),
+            Err(SecretError::Decrypt)
+        ));
+    }
+}
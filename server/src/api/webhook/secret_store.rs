@@ -0,0 +1,107 @@
+//! Where the verify middleware looks up the secrets a subscription's messages are signed with.
+//!
+//! Secrets are written by `dispatch` when a subscription is created or rotated, and are stored
+//! (sealed) with the subscription in Postgres, so every instance shares them and they survive
+//! restarts. The middleware is given a `SecretStore` rather than reading the table itself, so
+//! that verification can be tested against `MemorySecretStore`.
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{Pool, Postgres};
+
+use crate::api::webhook::secret::SecretResult;
+use crate::db::models::channel::ChannelId;
+use crate::db::models::subscription::EventSubSubscription;
+use crate::db::prelude::SubscriptionRepository;
+
+/// The secrets stored for a subscription, still sealed; see `api::webhook::secret`.
+#[derive(Debug, Clone, Default)]
+pub struct StoredSecrets {
+    pub secret: String,
+    /// The secret replaced by the last rotation, if any
+    pub previous_secret: Option<String>,
+    pub rotated_at: Option<NaiveDateTime>,
+    /// See `api::webhook::callback`
+    pub callback_path: Option<String>,
+}
+
+impl From<EventSubSubscription> for StoredSecrets {
+    fn from(value: EventSubSubscription) -> Self {
+        Self {
+            secret: value.secret,
+            previous_secret: value.previous_secret,
+            rotated_at: value.rotated_at,
+            callback_path: value.callback_path,
+        }
+    }
+}
+
+#[async_trait]
+pub trait SecretStore: Send + Sync {
+    /// The secrets for a channel's subscription of the given type, if it has any.
+    async fn get(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+    ) -> SecretResult<Option<StoredSecrets>>;
+}
+
+/// Reads secrets from the `eventsub_subscription` table.
+#[derive(Debug, Clone)]
+pub struct PostgresSecretStore {
+    pool: &'static Pool<Postgres>,
+}
+
+impl PostgresSecretStore {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl SecretStore for PostgresSecretStore {
+    async fn get(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+    ) -> SecretResult<Option<StoredSecrets>> {
+        Ok(SubscriptionRepository::new(self.pool)
+            .get(channel_id, subscription_type)
+            .await?
+            .map(StoredSecrets::from))
+    }
+}
+
+/// Secrets held in memory, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MemorySecretStore {
+    secrets: std::sync::Mutex<std::collections::HashMap<(ChannelId, String), StoredSecrets>>,
+}
+
+#[cfg(test)]
+impl MemorySecretStore {
+    pub fn insert(&self, channel_id: ChannelId, subscription_type: &str, secrets: StoredSecrets) {
+        self.secrets
+            .lock()
+            .unwrap()
+            .insert((channel_id, subscription_type.to_string()), secrets);
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl SecretStore for MemorySecretStore {
+    async fn get(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+    ) -> SecretResult<Option<StoredSecrets>> {
+        Ok(self
+            .secrets
+            .lock()
+            .unwrap()
+            .get(&(channel_id.clone(), subscription_type.to_string()))
+            .cloned())
+    }
+}
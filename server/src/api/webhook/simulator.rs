@@ -0,0 +1,416 @@
+//! Synthetic EventSub deliveries, for testing the webhook callback end to end without Twitch.
+//!
+//! An `EventSubSimulator` holds a subscription secret in memory and signs each message with it
+//! exactly as Twitch would, so deliveries go through the same signature, freshness and replay
+//! checks as real ones. `register` stores the secret against a channel's subscription so that the
+//! callback can look it up; messages can also be signed with some other secret, sent late, or
+//! sent again to check that they're rejected.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use chrono::{DateTime, SecondsFormat, Utc};
+use http::{HeaderMap, HeaderValue};
+use redis::aio::ConnectionManager;
+use ring::hmac::{self, Key};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde_json::{Value, json};
+use sqlx::{Pool, Postgres};
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::api::middleware::verify_external::{
+    HMAC_PREFIX, TWITCH_MESSAGE_ID, TWITCH_MESSAGE_RETRY, TWITCH_MESSAGE_SIGNATURE,
+    TWITCH_MESSAGE_TIMESTAMP, TWITCH_MESSAGE_TYPE_HEADER,
+};
+use crate::api::server::AppState;
+use crate::api::webhook::WebhookResult;
+use crate::api::webhook::secret::{self, SecretResult};
+use crate::db::prelude::{ChannelId, SubscriptionRepository};
+use crate::db::replica::ReplicaSet;
+use crate::db::store::PostgresStore;
+use crate::irc::IrcHandle;
+use crate::util::totp::TOTPHandler;
+
+/// A single delivery, before it's signed.
+#[derive(Debug, Clone)]
+pub struct SimulatedMessage {
+    pub id: String,
+    pub timestamp: String,
+    /// `notification`, `webhook_callback_verification` or `revocation`
+    pub message_type: &'static str,
+    pub retry: u32,
+    pub body: Value,
+}
+
+impl SimulatedMessage {
+    fn new(message_type: &'static str, body: Value) -> Self {
+        Self {
+            id: message_id(),
+            timestamp: timestamp(Utc::now()),
+            message_type,
+            retry: 0,
+            body,
+        }
+    }
+
+    /// The same message as sent at another time, e.g. long enough ago to be stale.
+    pub fn sent_at(mut self, at: DateTime<Utc>) -> Self {
+        self.timestamp = timestamp(at);
+        self
+    }
+
+    /// The message as Twitch would redeliver it: the same id and timestamp, with the retry count
+    /// bumped.
+    pub fn retried(&self) -> Self {
+        Self {
+            retry: self.retry + 1,
+            ..self.clone()
+        }
+    }
+
+    pub fn body_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.body).unwrap_or_default()
+    }
+
+    /// The headers Twitch sends with the message, signed with `secret`.
+    pub fn headers(&self, secret: &str) -> HeaderMap {
+        let key = Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let mut ctx = hmac::Context::with_key(&key);
+        ctx.update(self.id.as_bytes());
+        ctx.update(self.timestamp.as_bytes());
+        ctx.update(&self.body_bytes());
+        let signature = format!("{HMAC_PREFIX}{}", hex::encode(ctx.sign()));
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (TWITCH_MESSAGE_ID, self.id.clone()),
+            (TWITCH_MESSAGE_TIMESTAMP, self.timestamp.clone()),
+            (TWITCH_MESSAGE_SIGNATURE, signature),
+            (TWITCH_MESSAGE_TYPE_HEADER, self.message_type.to_string()),
+            (TWITCH_MESSAGE_RETRY, self.retry.to_string()),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+
+        headers
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventSubSimulator {
+    callback: String,
+    secret: String,
+    client: reqwest::Client,
+}
+
+impl EventSubSimulator {
+    /// Creates a simulator posting to `callback`, signing with a newly-generated secret.
+    pub fn new(callback: impl Into<String>) -> SecretResult<Self> {
+        Ok(Self::with_secret(callback, secret::generate()?))
+    }
+
+    pub fn with_secret(callback: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            callback: callback.into(),
+            secret: secret.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Stores this simulator's secret for a channel's subscription, as `dispatch::subscribe`
+    /// would but without a callback path, so deliveries go to the bare callback. Any secret already stored for the subscription is replaced, so this should only be
+    /// pointed at a scratch database.
+    pub async fn register(
+        &self,
+        pool: &'static Pool<Postgres>,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+    ) -> WebhookResult<()> {
+        SubscriptionRepository::new(pool)
+            .set_secret(
+                channel_id,
+                subscription_type,
+                &secret::seal(&self.secret).await?,
+                None,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub fn stream_online(&self, broadcaster_id: &str, broadcaster_login: &str) -> SimulatedMessage {
+        let event = json!({
+            "id": message_id(),
+            "broadcaster_user_id": broadcaster_id,
+            "broadcaster_user_login": broadcaster_login,
+            "broadcaster_user_name": broadcaster_login,
+            "type": "live",
+            "started_at": timestamp(Utc::now()),
+        });
+
+        self.notification("stream.online", broadcaster_id, event)
+    }
+
+    pub fn stream_offline(
+        &self,
+        broadcaster_id: &str,
+        broadcaster_login: &str,
+    ) -> SimulatedMessage {
+        let event = json!({
+            "broadcaster_user_id": broadcaster_id,
+            "broadcaster_user_login": broadcaster_login,
+            "broadcaster_user_name": broadcaster_login,
+        });
+
+        self.notification("stream.offline", broadcaster_id, event)
+    }
+
+    pub fn chat_message(
+        &self,
+        broadcaster_id: &str,
+        broadcaster_login: &str,
+        chatter_id: &str,
+        chatter_login: &str,
+        text: &str,
+    ) -> SimulatedMessage {
+        let event = json!({
+            "broadcaster_user_id": broadcaster_id,
+            "broadcaster_user_login": broadcaster_login,
+            "broadcaster_user_name": broadcaster_login,
+            "chatter_user_id": chatter_id,
+            "chatter_user_login": chatter_login,
+            "chatter_user_name": chatter_login,
+            "message_id": message_id(),
+            "message": {
+                "text": text,
+                "fragments": [{ "type": "text", "text": text }],
+            },
+            "message_type": "text",
+            "color": "",
+            "badges": [],
+        });
+
+        self.notification("channel.chat.message", broadcaster_id, event)
+    }
+
+    /// The challenge Twitch sends when a subscription is created.
+    pub fn verification(
+        &self,
+        subscription_type: &str,
+        broadcaster_id: &str,
+        challenge: &str,
+    ) -> SimulatedMessage {
+        let body = json!({
+            "challenge": challenge,
+            "subscription": self.subscription(
+                subscription_type,
+                broadcaster_id,
+                "webhook_callback_verification_pending",
+            ),
+        });
+
+        SimulatedMessage::new("webhook_callback_verification", body)
+    }
+
+    /// A subscription revoked by Twitch, where `status` is the reason (e.g. `user_removed`).
+    pub fn revocation(
+        &self,
+        subscription_type: &str,
+        broadcaster_id: &str,
+        status: &str,
+    ) -> SimulatedMessage {
+        let body = json!({
+            "subscription": self.subscription(subscription_type, broadcaster_id, status),
+        });
+
+        SimulatedMessage::new("revocation", body)
+    }
+
+    /// Posts a message signed with this simulator's secret.
+    pub async fn send(&self, message: &SimulatedMessage) -> reqwest::Result<reqwest::Response> {
+        self.send_signed_with(message, &self.secret).await
+    }
+
+    /// Posts a message signed with some other secret.
+    pub async fn send_signed_with(
+        &self,
+        message: &SimulatedMessage,
+        secret: &str,
+    ) -> reqwest::Result<reqwest::Response> {
+        self.client
+            .post(&self.callback)
+            .headers(message.headers(secret))
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(message.body_bytes())
+            .send()
+            .await
+    }
+
+    fn notification(
+        &self,
+        subscription_type: &str,
+        broadcaster_id: &str,
+        event: Value,
+    ) -> SimulatedMessage {
+        let body = json!({
+            "subscription": self.subscription(subscription_type, broadcaster_id, "enabled"),
+            "event": event,
+        });
+
+        SimulatedMessage::new("notification", body)
+    }
+
+    fn subscription(&self, subscription_type: &str, broadcaster_id: &str, status: &str) -> Value {
+        json!({
+            "id": message_id(),
+            "status": status,
+            "type": subscription_type,
+            "version": "1",
+            "cost": 1,
+            "condition": { "broadcaster_user_id": broadcaster_id },
+            "transport": { "method": "webhook", "callback": self.callback },
+            "created_at": timestamp(Utc::now()),
+        })
+    }
+}
+
+/// Serves `router` on a random local port, e.g. `webhook_routes` with `test_state`.
+pub async fn serve(router: Router) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let handle = tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, router).await {
+            tracing::error!(error = ?e, "simulated webhook server failed");
+        }
+    });
+
+    Ok((addr, handle))
+}
+
+/// State for serving the API without an IRC connection or read replicas.
+pub fn test_state(pool: &'static Pool<Postgres>, redis_pool: ConnectionManager) -> Arc<AppState> {
+    let replicas: &'static ReplicaSet = Box::leak(Box::new(
+        ReplicaSet::new(pool, "", std::time::Duration::from_secs(0))
+            .expect("replica set without replicas"),
+    ));
+
+    Arc::new(AppState {
+        database_pool: pool,
+        replicas,
+        scores: Arc::new(PostgresStore::new(pool)),
+        redis_pool,
+        irc_connection: IrcHandle::detached(),
+        channels: Arc::new(RwLock::new(Vec::new())),
+        channel_ids: Arc::new(RwLock::new(Vec::new())),
+        totp_handler: Arc::new(Mutex::new(TOTPHandler::new(
+            "simulated-totp-key-simulated-totp",
+        ))),
+    })
+}
+
+fn message_id() -> String {
+    let mut bytes = [0u8; 16];
+    _ = SystemRandom::new().fill(&mut bytes);
+    let hex = hex::encode(bytes);
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::server::webhook_routes;
+    use crate::api::webhook::callback;
+    use crate::api::webhook::secret_store::PostgresSecretStore;
+    use crate::db::db_pool;
+    use crate::db::prelude::{ChannelRepository, Repository};
+    use crate::db::redis::get_stream_state;
+    use crate::db::redis::redis_pool::redis_pool;
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL and REDIS_URL pointing at scratch instances"]
+    async fn simulated_deliveries_are_verified_deduplicated_and_dispatched() {
+        let pool = db_pool().await.unwrap();
+        let mut redis = redis_pool().await.unwrap().clone();
+        let channel_id = ChannelRepository::new(pool)
+            .get_all_channel_ids()
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(ChannelId)
+            .expect("at least one channel");
+
+        let router = Router::new()
+            .nest(
+                "/_extern",
+                webhook_routes(Arc::new(PostgresSecretStore::new(pool))),
+            )
+            .with_state(test_state(pool, redis.clone()));
+        let (addr, _server) = serve(router).await.unwrap();
+
+        let sim = EventSubSimulator::new(format!("http://{addr}/_extern/callback")).unwrap();
+        for subscription_type in ["stream.online", "stream.offline", "channel.chat.message"] {
+            sim.register(pool, &channel_id, subscription_type)
+                .await
+                .unwrap();
+        }
+
+        let challenge = sim.verification("stream.online", &channel_id.0, "challenge-string");
+        let response = sim.send(&challenge).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "challenge-string");
+
+        let online = sim.stream_online(&channel_id.0, "simulated");
+        assert_eq!(sim.send(&online).await.unwrap().status(), 200);
+        assert!(get_stream_state(&mut redis, &channel_id).await);
+
+        // redelivered, forged and stale messages are never dispatched
+        assert_eq!(sim.send(&online.retried()).await.unwrap().status(), 204);
+        let forged = sim.stream_offline(&channel_id.0, "simulated");
+        let response = sim.send_signed_with(&forged, "not-the-secret").await;
+        assert_eq!(response.unwrap().status(), 403);
+        let stale = sim
+            .stream_offline(&channel_id.0, "simulated")
+            .sent_at(Utc::now() - chrono::Duration::minutes(11));
+        assert_eq!(sim.send(&stale).await.unwrap().status(), 403);
+        assert!(get_stream_state(&mut redis, &channel_id).await);
+
+        let offline = sim.stream_offline(&channel_id.0, "simulated");
+        assert_eq!(sim.send(&offline).await.unwrap().status(), 200);
+        assert!(!get_stream_state(&mut redis, &channel_id).await);
+
+        // the subscription has no callback path, so nothing is accepted on one
+        let path = callback::generate_path().unwrap();
+        let misdirected = EventSubSimulator::with_secret(
+            format!("http://{addr}/_extern/callback/{path}"),
+            sim.secret(),
+        );
+        let offline = misdirected.stream_offline(&channel_id.0, "simulated");
+        assert_eq!(misdirected.send(&offline).await.unwrap().status(), 404);
+
+        // chat messages are verified, but aren't a subscription type the callback handles
+        let chat = sim.chat_message(&channel_id.0, "simulated", "1", "chatter", "hello");
+        assert_eq!(sim.send(&chat).await.unwrap().status(), 400);
+    }
+}
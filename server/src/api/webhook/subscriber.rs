@@ -0,0 +1,287 @@
+//! Typed EventSub subscription requests.
+//!
+//! Every topic that can be subscribed to is a row in `TOPICS`, giving its subscription type,
+//! version and the shape of its condition. `SubscriptionBuilder` checks that a request has
+//! everything its topic's condition needs before it's sent, so supporting another topic only takes
+//! a `StreamGenericRequestType` variant and a row in the table.
+//!
+//! Some conditions name the user the subscription is made as (e.g. the moderator for
+//! `channel.follow`) - for us, that's always the bot's account.
+
+use serde::Serialize;
+
+use crate::api::webhook::{
+    BroadcasterUserId, StreamGenericRequestType, Transport, WebhookError, WebhookResult,
+};
+
+/// The fields a topic's condition is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionKind {
+    /// `broadcaster_user_id`
+    Broadcaster,
+    /// `broadcaster_user_id` and `moderator_user_id`
+    Moderator,
+    /// `broadcaster_user_id` and `user_id`
+    Chat,
+    /// `to_broadcaster_user_id`, i.e. raids into the channel
+    RaidTo,
+}
+
+impl ConditionKind {
+    /// Whether the condition names the user the subscription is made as.
+    pub fn needs_user(self) -> bool {
+        matches!(self, Self::Moderator | Self::Chat)
+    }
+}
+
+#[derive(Debug)]
+pub struct Topic {
+    pub notif_type: StreamGenericRequestType,
+    pub subscription_type: &'static str,
+    pub version: &'static str,
+    pub condition: ConditionKind,
+}
+
+pub static TOPICS: [Topic; 7] = [
+    Topic {
+        notif_type: StreamGenericRequestType::Online,
+        subscription_type: "stream.online",
+        version: "1",
+        condition: ConditionKind::Broadcaster,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::Offline,
+        subscription_type: "stream.offline",
+        version: "1",
+        condition: ConditionKind::Broadcaster,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::Follow,
+        subscription_type: "channel.follow",
+        version: "2",
+        condition: ConditionKind::Moderator,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::Subscribe,
+        subscription_type: "channel.subscribe",
+        version: "1",
+        condition: ConditionKind::Broadcaster,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::Cheer,
+        subscription_type: "channel.cheer",
+        version: "1",
+        condition: ConditionKind::Broadcaster,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::Raid,
+        subscription_type: "channel.raid",
+        version: "1",
+        condition: ConditionKind::RaidTo,
+    },
+    Topic {
+        notif_type: StreamGenericRequestType::ChatMessage,
+        subscription_type: "channel.chat.message",
+        version: "1",
+        condition: ConditionKind::Chat,
+    },
+];
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModeratorCondition {
+    pub broadcaster_user_id: String,
+    pub moderator_user_id: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatCondition {
+    pub broadcaster_user_id: String,
+    pub user_id: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RaidCondition {
+    pub to_broadcaster_user_id: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Condition {
+    Broadcaster(BroadcasterUserId),
+    Moderator(ModeratorCondition),
+    Chat(ChatCondition),
+    Raid(RaidCondition),
+}
+
+/// The body of a request to create a subscription.
+#[derive(Serialize, Debug, Clone)]
+pub struct SubscriptionRequest {
+    pub r#type: &'static str,
+    pub version: &'static str,
+    pub condition: Condition,
+    pub transport: Transport,
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionBuilder {
+    topic: &'static Topic,
+    broadcaster_user_id: Option<String>,
+    user_id: Option<String>,
+    transport: Option<Transport>,
+}
+
+impl SubscriptionBuilder {
+    pub fn new(notif_type: StreamGenericRequestType) -> Self {
+        Self {
+            topic: notif_type.topic(),
+            broadcaster_user_id: None,
+            user_id: None,
+            transport: None,
+        }
+    }
+
+    /// The channel the subscription is for.
+    pub fn broadcaster(mut self, broadcaster_user_id: impl Into<String>) -> Self {
+        self.broadcaster_user_id = Some(broadcaster_user_id.into());
+        self
+    }
+
+    /// The user the subscription is made as; ignored by topics whose condition doesn't need one.
+    pub fn user(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    pub fn webhook(mut self, callback: impl Into<String>, secret: impl Into<String>) -> Self {
+        self.transport = Some(Transport {
+            method: "webhook".to_string(),
+            callback: callback.into(),
+            secret: Some(secret.into()),
+        });
+        self
+    }
+
+    pub fn build(self) -> WebhookResult<SubscriptionRequest> {
+        let subscription_type = self.topic.subscription_type;
+        let missing = |field| WebhookError::IncompleteSubscription {
+            subscription_type,
+            field,
+        };
+
+        let broadcaster_user_id = self
+            .broadcaster_user_id
+            .ok_or_else(|| missing("broadcaster"))?;
+        let user_id = || self.user_id.clone().ok_or_else(|| missing("user"));
+
+        let condition = match self.topic.condition {
+            ConditionKind::Broadcaster => Condition::Broadcaster(BroadcasterUserId {
+                broadcaster_user_id,
+            }),
+            ConditionKind::Moderator => Condition::Moderator(ModeratorCondition {
+                broadcaster_user_id,
+                moderator_user_id: user_id()?,
+            }),
+            ConditionKind::Chat => Condition::Chat(ChatCondition {
+                broadcaster_user_id,
+                user_id: user_id()?,
+            }),
+            ConditionKind::RaidTo => Condition::Raid(RaidCondition {
+                to_broadcaster_user_id: broadcaster_user_id,
+            }),
+        };
+
+        Ok(SubscriptionRequest {
+            r#type: subscription_type,
+            version: self.topic.version,
+            condition,
+            transport: self.transport.ok_or_else(|| missing("transport"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn every_type_has_one_topic() {
+        for notif_type in [
+            StreamGenericRequestType::Online,
+            StreamGenericRequestType::Offline,
+            StreamGenericRequestType::Follow,
+            StreamGenericRequestType::Subscribe,
+            StreamGenericRequestType::Cheer,
+            StreamGenericRequestType::Raid,
+            StreamGenericRequestType::ChatMessage,
+        ] {
+            let topics = TOPICS.iter().filter(|t| t.notif_type == notif_type);
+            assert_eq!(topics.count(), 1, "{notif_type:?}");
+            assert_eq!(
+                StreamGenericRequestType::from_subscription_type(notif_type.as_str()),
+                Some(notif_type)
+            );
+        }
+    }
+
+    #[test]
+    fn conditions_match_their_topic() {
+        let request = |notif_type| {
+            let request = SubscriptionBuilder::new(notif_type)
+                .broadcaster("103033809")
+                .user("1")
+                .webhook("https://pea.fan/callback", "secret")
+                .build()
+                .unwrap();
+            let body = serde_json::to_value(request).unwrap();
+            (body["version"].clone(), body["condition"].clone())
+        };
+
+        assert_eq!(
+            request(StreamGenericRequestType::Online),
+            (json!("1"), json!({ "broadcaster_user_id": "103033809" }))
+        );
+        assert_eq!(
+            request(StreamGenericRequestType::Follow),
+            (
+                json!("2"),
+                json!({ "broadcaster_user_id": "103033809", "moderator_user_id": "1" })
+            )
+        );
+        assert_eq!(
+            request(StreamGenericRequestType::ChatMessage),
+            (
+                json!("1"),
+                json!({ "broadcaster_user_id": "103033809", "user_id": "1" })
+            )
+        );
+        assert_eq!(
+            request(StreamGenericRequestType::Raid),
+            (json!("1"), json!({ "to_broadcaster_user_id": "103033809" }))
+        );
+    }
+
+    #[test]
+    fn incomplete_requests_are_refused() {
+        let result = SubscriptionBuilder::new(StreamGenericRequestType::Follow)
+            .broadcaster("103033809")
+            .webhook("https://pea.fan/callback", "secret")
+            .build();
+        assert!(matches!(
+            result,
+            Err(WebhookError::IncompleteSubscription { field: "user", .. })
+        ));
+
+        let result = SubscriptionBuilder::new(StreamGenericRequestType::Online)
+            .broadcaster("103033809")
+            .build();
+        assert!(matches!(
+            result,
+            Err(WebhookError::IncompleteSubscription {
+                field: "transport",
+                ..
+            })
+        ));
+    }
+}
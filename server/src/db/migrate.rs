@@ -0,0 +1,116 @@
+//! Schema migrations, embedded from `migrations/` at compile time.
+//!
+//! Pending migrations are applied on startup with `--migrate`, or on their own (without starting
+//! the server) with the `migrate` subcommand; otherwise startup only warns if the schema is behind.
+
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrateMode {
+    /// Leave the schema as-is
+    Skip,
+    /// Apply pending migrations, then start the server
+    OnStartup,
+    /// Apply pending migrations and exit
+    Only,
+}
+
+impl MigrateMode {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        // skip the binary name
+        _ = args.next();
+
+        let mut mode = Self::Skip;
+        for (i, arg) in args.enumerate() {
+            match arg.as_str() {
+                "migrate" if i == 0 => return Self::Only,
+                "--migrate" => mode = Self::OnStartup,
+                _ => (),
+            }
+        }
+
+        mode
+    }
+}
+
+/// The version of the most recently embedded migration.
+pub fn latest_version() -> Option<i64> {
+    MIGRATOR.iter().map(|m| m.version).max()
+}
+
+/// The version of the most recent migration applied to the database, or `None` if no migrations
+/// have been applied.
+#[instrument(skip(pool))]
+pub async fn schema_version(pool: &Pool<Postgres>) -> sqlx::Result<Option<i64>> {
+    let result = sqlx::query_scalar::<_, Option<i64>>(
+        r#"
+        SELECT MAX(version)
+        FROM _sqlx_migrations
+        WHERE success
+        "#,
+    )
+    .fetch_one(pool)
+    .await;
+
+    match result {
+        Ok(version) => Ok(version),
+        // the migrations table is created with the first migration
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Applies any pending migrations, returning the resulting schema version.
+#[instrument(skip(pool))]
+pub async fn run(pool: &Pool<Postgres>) -> Result<Option<i64>, MigrateError> {
+    let before = schema_version(pool).await?;
+    MIGRATOR.run(pool).await?;
+    let after = schema_version(pool).await?;
+
+    if before == after {
+        tracing::info!(version = ?after, "schema is up to date");
+    } else {
+        tracing::info!(from = ?before, to = ?after, "applied migrations");
+    }
+
+    Ok(after)
+}
+
+/// Warns if the database is missing migrations that this build expects.
+#[instrument(skip(pool))]
+pub async fn check_pending(pool: &Pool<Postgres>) {
+    match schema_version(pool).await {
+        Ok(version) if version < latest_version() => tracing::warn!(
+            version = ?version,
+            latest = ?latest_version(),
+            "database schema is behind - restart with `--migrate` to apply pending migrations"
+        ),
+        Ok(_) => (),
+        Err(e) => tracing::error!(error = ?e, "failed to read schema version"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mode(args: &[&str]) -> MigrateMode {
+        MigrateMode::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn migrate_mode_from_args() {
+        assert_eq!(mode(&["server"]), MigrateMode::Skip);
+        assert_eq!(mode(&["server", "--resume"]), MigrateMode::Skip);
+        assert_eq!(mode(&["server", "--migrate"]), MigrateMode::OnStartup);
+        assert_eq!(mode(&["server", "migrate"]), MigrateMode::Only);
+        // only a subcommand in the first position
+        assert_eq!(mode(&["server", "--resume", "migrate"]), MigrateMode::Skip);
+
+        assert!(latest_version().is_some());
+    }
+}
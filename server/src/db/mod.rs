@@ -6,25 +6,51 @@ use crate::util::env::Var;
 use crate::util::{env, helix};
 use crate::var;
 
+pub mod migrate;
 pub mod models;
 pub mod redis;
+pub mod replica;
 pub mod repositories;
+pub mod snapshot;
+pub mod store;
 
 pub mod prelude {
     pub use crate::db::PgError;
     pub use crate::db::db_pool;
 
-    pub use crate::db::models::channel::ChannelLeaderboardEntry;
     pub use crate::db::models::channel::{Channel, ChannelId};
-    pub use crate::db::models::chatter::ChatterLeaderboardEntry;
     pub use crate::db::models::chatter::{Chatter, ChatterId};
+    pub use crate::db::models::keyword::{Keyword, KeywordId};
     pub use crate::db::models::leaderboard::ScoreSummary;
 
     pub use crate::db::repositories::Repository;
     pub use crate::db::repositories::Tx;
+    pub use crate::db::repositories::alias::AliasRepository;
+    pub use crate::db::repositories::archive::ArchiveRepository;
+    pub use crate::db::repositories::audit::AuditRepository;
     pub use crate::db::repositories::channel::ChannelRepository;
     pub use crate::db::repositories::chatter::ChatterRepository;
+    pub use crate::db::repositories::deletion::DeletionRepository;
+    pub use crate::db::repositories::digest::DigestRepository;
+    pub use crate::db::repositories::export::ExportRepository;
+    pub use crate::db::repositories::flag::FeatureFlagRepository;
+    pub use crate::db::repositories::heatmap::HeatmapRepository;
+    pub use crate::db::repositories::integration::DiscordWebhookRepository;
+    pub use crate::db::repositories::keyword::KeywordRepository;
     pub use crate::db::repositories::leaderboard::LeaderboardRepository;
+    pub use crate::db::repositories::metric::MetricRepository;
+    pub use crate::db::repositories::milestone::MilestoneRepository;
+    pub use crate::db::repositories::note::NoteRepository;
+    pub use crate::db::repositories::period::PeriodRepository;
+    pub use crate::db::repositories::profile::ProfileRepository;
+    pub use crate::db::repositories::raid::RaidRepository;
+    pub use crate::db::repositories::rank::RankRepository;
+    pub use crate::db::repositories::search::SearchRepository;
+    pub use crate::db::repositories::settings::SettingsRepository;
+    pub use crate::db::repositories::stats::StatsRepository;
+    pub use crate::db::repositories::stream::StreamStatusRepository;
+    pub use crate::db::repositories::subscription::SubscriptionRepository;
+    pub use crate::db::repositories::token::ApiTokenRepository;
 }
 
 static DB_POOL: OnceCell<PgPool> = OnceCell::const_new();
@@ -0,0 +1,15 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::chatter::ChatterId;
+
+/// A previous login for a chatter, detected when a refresh from Helix returns a different login
+/// than the one stored.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Alias {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub detected_at: NaiveDateTime,
+    pub merged_at: Option<NaiveDateTime>,
+    pub merged_score: i64,
+}
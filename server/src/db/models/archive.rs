@@ -0,0 +1,27 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::keyword::KeywordId;
+
+/// A score event as it's written to (and read back from) an archive.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct ArchivedScoreEvent {
+    pub id: i32,
+    pub chatter_id: ChatterId,
+    pub channel_id: ChannelId,
+    pub keyword_id: KeywordId,
+    pub earned_at: NaiveDateTime,
+    pub msg_id: Option<String>,
+    pub flagged_at: Option<NaiveDateTime>,
+}
+
+/// A (UTC) day of score events that has been archived.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ArchivedDay {
+    pub day: NaiveDate,
+    pub object_key: String,
+    pub events: i64,
+    pub archived_at: NaiveDateTime,
+}
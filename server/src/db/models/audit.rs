@@ -0,0 +1,163 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::db::models::chatter::ChatterId;
+
+/// Actions taken through the admin API (or privileged chat commands) that are recorded in the
+/// audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    ChannelAdded,
+    ChannelDataRefreshed,
+    ChannelRepliesUpdated,
+    ChannelTimezoneUpdated,
+    ChannelCountModeUpdated,
+    ChannelPauseUpdated,
+    ChannelSettingsUpdated,
+    ChatterBlacklisted,
+    ChatterUnblacklisted,
+    StreamStateRefreshed,
+    AliasesMerged,
+    AliasesRepaired,
+    HooksDeleted,
+    HooksReset,
+    HookSecretsRotated,
+    NoteUpdated,
+    NoteDeleted,
+    MilestoneCreated,
+    MilestoneDeleted,
+    KeywordCreated,
+    KeywordUpdated,
+    DiscordWebhookUpdated,
+    DiscordWebhookDeleted,
+    IrcReset,
+    IrcRebalanced,
+    IrcReconnected,
+    ApiTokenRevoked,
+    ScoreAdjusted,
+    ChatterDeleted,
+    LogFilterUpdated,
+    FeatureFlagUpdated,
+    FeatureFlagReset,
+    DashboardSessionsRevoked,
+    AdminSessionEnded,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ChannelAdded => "channel_added",
+            Self::ChannelDataRefreshed => "channel_data_refreshed",
+            Self::ChannelRepliesUpdated => "channel_replies_updated",
+            Self::ChannelTimezoneUpdated => "channel_timezone_updated",
+            Self::ChannelCountModeUpdated => "channel_count_mode_updated",
+            Self::ChannelPauseUpdated => "channel_pause_updated",
+            Self::ChannelSettingsUpdated => "channel_settings_updated",
+            Self::ChatterBlacklisted => "chatter_blacklisted",
+            Self::ChatterUnblacklisted => "chatter_unblacklisted",
+            Self::StreamStateRefreshed => "stream_state_refreshed",
+            Self::AliasesMerged => "aliases_merged",
+            Self::AliasesRepaired => "aliases_repaired",
+            Self::HooksDeleted => "hooks_deleted",
+            Self::HooksReset => "hooks_reset",
+            Self::HookSecretsRotated => "hook_secrets_rotated",
+            Self::NoteUpdated => "note_updated",
+            Self::NoteDeleted => "note_deleted",
+            Self::MilestoneCreated => "milestone_created",
+            Self::MilestoneDeleted => "milestone_deleted",
+            Self::KeywordCreated => "keyword_created",
+            Self::KeywordUpdated => "keyword_updated",
+            Self::DiscordWebhookUpdated => "discord_webhook_updated",
+            Self::DiscordWebhookDeleted => "discord_webhook_deleted",
+            Self::IrcReset => "irc_reset",
+            Self::IrcRebalanced => "irc_rebalanced",
+            Self::IrcReconnected => "irc_reconnected",
+            Self::ApiTokenRevoked => "api_token_revoked",
+            Self::ScoreAdjusted => "score_adjusted",
+            Self::ChatterDeleted => "chatter_deleted",
+            Self::LogFilterUpdated => "log_filter_updated",
+            Self::FeatureFlagUpdated => "feature_flag_updated",
+            Self::FeatureFlagReset => "feature_flag_reset",
+            Self::DashboardSessionsRevoked => "dashboard_sessions_revoked",
+            Self::AdminSessionEnded => "admin_session_ended",
+        }
+    }
+}
+
+impl TryFrom<String> for AuditAction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        // serde's snake_case names match `as_str`
+        serde_json::from_value(Value::String(value.clone()))
+            .map_err(|_| format!("unknown audit action '{value}'"))
+    }
+}
+
+/// Who took an audited action; only one of these is set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditActor<'a> {
+    pub session_id: Option<i32>,
+    pub token_id: Option<&'a str>,
+    pub chatter_id: Option<&'a ChatterId>,
+}
+
+/// A single recorded admin action.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    /// The admin session the action was taken from, if it wasn't taken with an API token
+    pub session_id: Option<i32>,
+    /// The API token the action was taken with, if it wasn't taken from a session
+    pub token_id: Option<String>,
+    /// The broadcaster or moderator who took the action from chat, if it was taken from chat
+    pub chatter_id: Option<String>,
+    #[sqlx(try_from = "String")]
+    pub action: AuditAction,
+    /// What the action applied to (usually a channel id), if it applied to anything in particular
+    pub target: Option<String>,
+    /// The affected state before the action, where there was any
+    pub before: Option<Value>,
+    /// The affected state after the action, or its result
+    pub after: Option<Value>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Narrows down `AuditRepository::get_page`; unset fields match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub action: Option<AuditAction>,
+    pub session_id: Option<i32>,
+    pub token_id: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn actions_round_trip_through_their_names() {
+        for action in [
+            AuditAction::ChannelAdded,
+            AuditAction::HookSecretsRotated,
+            AuditAction::DiscordWebhookDeleted,
+            AuditAction::IrcReconnected,
+        ] {
+            assert_eq!(
+                serde_json::to_value(action).unwrap(),
+                Value::String(action.as_str().to_string())
+            );
+            assert_eq!(
+                AuditAction::try_from(action.as_str().to_string()),
+                Ok(action)
+            );
+        }
+
+        assert!(AuditAction::try_from("blacklist_updated".to_string()).is_err());
+    }
+}
@@ -1,9 +1,12 @@
 use core::fmt;
+use std::str::FromStr;
 
 use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::db::models::chatter::{ChatterId, ChatterScoreSummary};
+use crate::db::models::keyword::KeywordId;
+use crate::db::models::{IdError, validate_id};
 use crate::util::helix::HelixUser;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
@@ -22,14 +25,123 @@ pub struct Channel {
 #[derive(Debug, Clone, sqlx::FromRow, Serialize)]
 pub struct ChannelReplies {
     pub id: ChannelId,
+    /// The mode isn't `ReplyMode::Silent`
     pub enabled: bool,
+    #[sqlx(try_from = "String")]
+    pub mode: ReplyMode,
     pub login: String,
     pub name: String,
     pub color: String,
     pub image: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How the bot answers chat commands in a channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplyMode {
+    /// In chat, as a reply to the command
+    Reply,
+    /// By whisper to the chatter who used the command; nothing is posted in chat
+    Whisper,
+    /// Not at all
+    #[default]
+    Silent,
+}
+
+impl ReplyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reply => "reply",
+            Self::Whisper => "whisper",
+            Self::Silent => "silent",
+        }
+    }
+}
+
+impl TryFrom<String> for ReplyMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "reply" => Ok(Self::Reply),
+            "whisper" => Ok(Self::Whisper),
+            "silent" => Ok(Self::Silent),
+            _ => Err(format!("unknown reply mode '{value}'")),
+        }
+    }
+}
+
+/// How many increments a message earns for each keyword it mentions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CountMode {
+    /// One per keyword, however many times it's repeated
+    #[default]
+    Message,
+    /// One per occurrence of each keyword, up to the channel's `max_occurrences`
+    Occurrence,
+}
+
+impl CountMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Occurrence => "occurrence",
+        }
+    }
+}
+
+impl TryFrom<String> for CountMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "message" => Ok(Self::Message),
+            "occurrence" => Ok(Self::Occurrence),
+            _ => Err(format!("unknown count mode '{value}'")),
+        }
+    }
+}
+
+/// Per-channel keyword counting configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct ChannelCountConfig {
+    #[sqlx(try_from = "String")]
+    pub count_mode: CountMode,
+    /// Occurrences of a single keyword counted per message in `CountMode::Occurrence`
+    pub max_occurrences: i16,
+}
+
+impl ChannelCountConfig {
+    /// Matches the column defaults
+    pub const DEFAULT_MAX_OCCURRENCES: i16 = 5;
+    pub const MAX_OCCURRENCES_RANGE: std::ops::RangeInclusive<i16> = 1..=50;
+
+    /// Expands the occurrences of each keyword in a message into one id per increment.
+    pub fn increments(&self, occurrences: &[(KeywordId, usize)]) -> Vec<KeywordId> {
+        occurrences
+            .iter()
+            .flat_map(|&(id, count)| {
+                let count = match self.count_mode {
+                    CountMode::Message => 1,
+                    CountMode::Occurrence => count.min(self.max_occurrences.max(1) as usize),
+                };
+                std::iter::repeat_n(id, count)
+            })
+            .collect()
+    }
+}
+
+impl Default for ChannelCountConfig {
+    fn default() -> Self {
+        Self {
+            count_mode: CountMode::default(),
+            max_occurrences: Self::DEFAULT_MAX_OCCURRENCES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChannelLeaderboardEntry {
     pub id: ChannelId,
     pub name: String,
@@ -97,9 +209,26 @@ impl From<String> for ChannelId {
     }
 }
 
-impl From<&str> for ChannelId {
-    fn from(value: &str) -> Self {
-        ChannelId(value.to_string())
+impl ChannelId {
+    pub fn validate(&self) -> Result<(), IdError> {
+        validate_id(&self.0)
+    }
+}
+
+impl FromStr for ChannelId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_id(s)?;
+        Ok(ChannelId(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for ChannelId {
+    type Error = IdError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
     }
 }
 
@@ -136,3 +265,35 @@ impl From<super::chatter::Chatter> for Channel {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn increments_follow_the_count_mode() {
+        let occurrences = [(KeywordId(1), 3), (KeywordId(2), 1)];
+
+        let per_message = ChannelCountConfig::default();
+        assert_eq!(
+            per_message.increments(&occurrences),
+            vec![KeywordId(1), KeywordId(2)]
+        );
+
+        let per_occurrence = ChannelCountConfig {
+            count_mode: CountMode::Occurrence,
+            max_occurrences: 2,
+        };
+        assert_eq!(
+            per_occurrence.increments(&occurrences),
+            vec![KeywordId(1), KeywordId(1), KeywordId(2)]
+        );
+    }
+    #[test]
+    fn reply_modes_round_trip() {
+        for mode in [ReplyMode::Reply, ReplyMode::Whisper, ReplyMode::Silent] {
+            assert_eq!(ReplyMode::try_from(mode.as_str().to_string()), Ok(mode));
+        }
+        assert!(ReplyMode::try_from(String::from("loud")).is_err());
+    }
+}
@@ -1,9 +1,11 @@
 use core::fmt;
+use std::str::FromStr;
 
 use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::db::models::channel::{ChannelId, ChannelScoreSummary};
+use crate::db::models::{IdError, validate_id};
 use crate::util::helix::HelixUser;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
@@ -24,7 +26,7 @@ pub struct Chatter {
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatterLeaderboardEntry {
     pub id: ChatterId,
     pub login: String,
@@ -103,9 +105,26 @@ impl From<String> for ChatterId {
     }
 }
 
-impl From<&str> for ChatterId {
-    fn from(value: &str) -> Self {
-        ChatterId(value.to_string())
+impl ChatterId {
+    pub fn validate(&self) -> Result<(), IdError> {
+        validate_id(&self.0)
+    }
+}
+
+impl FromStr for ChatterId {
+    type Err = IdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_id(s)?;
+        Ok(ChatterId(s.to_string()))
+    }
+}
+
+impl TryFrom<&str> for ChatterId {
+    type Error = IdError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
     }
 }
 
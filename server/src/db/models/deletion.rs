@@ -0,0 +1,46 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+
+use crate::db::models::chatter::ChatterId;
+
+/// A request to delete a chatter's data.
+///
+/// The request is pending until it's confirmed with the token issued for it. Confirming it
+/// soft-deletes the chatter, whose data is then purged once `purge_after` has passed.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ChatterDeletion {
+    pub chatter_id: ChatterId,
+    #[serde(skip)]
+    pub confirmation_hash: String,
+    /// The confirmation token is rejected after this
+    pub confirm_by: NaiveDateTime,
+    pub requested_at: NaiveDateTime,
+    pub confirmed_at: Option<NaiveDateTime>,
+    /// `None` until the request is confirmed
+    pub purge_after: Option<NaiveDateTime>,
+}
+
+impl ChatterDeletion {
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+
+    pub fn can_confirm(&self) -> bool {
+        !self.is_confirmed() && Utc::now().naive_utc() < self.confirm_by
+    }
+}
+
+/// Rows removed by a purge.
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgedChatter {
+    pub chatter_id: ChatterId,
+    pub score_events: u64,
+    /// Hourly counts of archived score events
+    pub score_rollups: u64,
+    pub suppressed_score_events: u64,
+    pub scores: u64,
+    pub snapshots: u64,
+    pub notes: u64,
+    /// Logins whose legacy Redis keys were removed
+    pub legacy_logins: Vec<String>,
+}
@@ -0,0 +1,52 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+
+/// A chatter's score within a digest's period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestChatter {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub score: i64,
+}
+
+/// The chatter who climbed the most places on a channel's leaderboard within a digest's period.
+/// Chatters that weren't ranked before the period are counted as climbing from just below the
+/// last ranked chatter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestGainer {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub score: i64,
+    pub from_rank: i64,
+    pub to_rank: i64,
+}
+
+/// A channel's summary of the day before its `digest_hour`.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Digest {
+    pub id: i32,
+    pub channel_id: ChannelId,
+    pub channel_login: String,
+    /// The channel's local date the digest was compiled on
+    pub digest_on: NaiveDate,
+    pub period_start: NaiveDateTime,
+    pub period_end: NaiveDateTime,
+    /// Scores counted in the channel within the period
+    pub total: i64,
+    pub top_chatters: Json<Vec<DigestChatter>>,
+    pub biggest_gainer: Option<Json<DigestGainer>>,
+    pub created_at: NaiveDateTime,
+}
+
+/// A channel whose digest is due, along with the period it covers.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DueDigest {
+    pub channel_id: ChannelId,
+    pub digest_on: NaiveDate,
+    pub period_start: NaiveDateTime,
+    pub period_end: NaiveDateTime,
+}
@@ -0,0 +1,17 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::chatter::ChatterId;
+
+/// A single row of an exported channel leaderboard.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct LeaderboardExportRow {
+    pub ranking: i64,
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub name: String,
+    /// Summed across all keywords
+    pub score: i64,
+    pub first_scored_at: NaiveDateTime,
+    pub last_scored_at: NaiveDateTime,
+}
@@ -0,0 +1,21 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::channel::ChannelId;
+
+/// A stored feature flag; see `util::flags` for how it's checked.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct FeatureFlag {
+    pub name: String,
+    /// The flag is off everywhere while this is unset
+    pub enabled: bool,
+    /// Percentage of channels the flag is on for while enabled
+    pub rollout_percent: i16,
+    /// Channels the flag is on for while enabled, regardless of `rollout_percent`
+    pub channels: Vec<ChannelId>,
+    pub updated_at: NaiveDateTime,
+}
+
+impl FeatureFlag {
+    pub const ROLLOUT_RANGE: std::ops::RangeInclusive<i16> = 0..=100;
+}
@@ -0,0 +1,85 @@
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+
+pub const DAYS_PER_WEEK: usize = 7;
+pub const HOURS_PER_DAY: usize = 24;
+
+/// A single hour-of-week bucket from the `channel_heatmap` table.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct HeatmapBucket {
+    /// 0 (Sunday) through 6 (Saturday)
+    pub day_of_week: i16,
+    pub hour: i16,
+    pub total: i64,
+}
+
+/// Score counts for a channel by hour of the week, in the channel's local time.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelHeatmap {
+    pub channel_id: ChannelId,
+    pub timezone: String,
+    /// The largest single bucket, for scaling the heatmap
+    pub max: i64,
+    /// Indexed as `hours[day_of_week][hour]`, with weeks starting on Sunday
+    pub hours: Vec<Vec<i64>>,
+}
+
+impl ChannelHeatmap {
+    pub fn from_buckets(
+        channel_id: ChannelId,
+        timezone: String,
+        buckets: &[HeatmapBucket],
+    ) -> Self {
+        let mut hours = vec![vec![0; HOURS_PER_DAY]; DAYS_PER_WEEK];
+        for bucket in buckets {
+            let (day, hour) = (bucket.day_of_week as usize, bucket.hour as usize);
+            if day < DAYS_PER_WEEK && hour < HOURS_PER_DAY {
+                hours[day][hour] += bucket.total;
+            }
+        }
+
+        let max = hours.iter().flatten().copied().max().unwrap_or(0);
+
+        Self {
+            channel_id,
+            timezone,
+            max,
+            hours,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets_fill_a_dense_week() {
+        let buckets = vec![
+            HeatmapBucket {
+                day_of_week: 0,
+                hour: 0,
+                total: 3,
+            },
+            HeatmapBucket {
+                day_of_week: 6,
+                hour: 23,
+                total: 12,
+            },
+        ];
+
+        let heatmap = ChannelHeatmap::from_buckets(
+            ChannelId(String::from("1")),
+            String::from("UTC"),
+            &buckets,
+        );
+
+        assert_eq!(heatmap.hours.len(), DAYS_PER_WEEK);
+        assert!(heatmap.hours.iter().all(|day| day.len() == HOURS_PER_DAY));
+        assert_eq!(heatmap.hours[0][0], 3);
+        assert_eq!(heatmap.hours[6][23], 12);
+        assert_eq!(heatmap.hours.iter().flatten().sum::<i64>(), 15);
+        assert_eq!(heatmap.max, 12);
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::channel::ChannelId;
+
+/// The events a channel's Discord webhooks can be posted for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrationEvent {
+    /// The channel went live
+    StreamOnline,
+    /// A milestone was reached in the channel
+    MilestoneReached,
+    /// The channel's weekly leaderboard snapshot was finalised
+    LeaderboardSnapshot,
+    /// The channel's daily digest was compiled
+    DailyDigest,
+}
+
+impl IntegrationEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::StreamOnline => "stream_online",
+            Self::MilestoneReached => "milestone_reached",
+            Self::LeaderboardSnapshot => "leaderboard_snapshot",
+            Self::DailyDigest => "daily_digest",
+        }
+    }
+}
+
+impl TryFrom<String> for IntegrationEvent {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "stream_online" => Ok(Self::StreamOnline),
+            "milestone_reached" => Ok(Self::MilestoneReached),
+            "leaderboard_snapshot" => Ok(Self::LeaderboardSnapshot),
+            "daily_digest" => Ok(Self::DailyDigest),
+            _ => Err(format!("unknown integration event '{value}'")),
+        }
+    }
+}
+
+/// A Discord webhook posted to when an event happens in a channel.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct DiscordWebhook {
+    pub id: i32,
+    pub channel_id: ChannelId,
+    #[sqlx(try_from = "String")]
+    pub event: IntegrationEvent,
+    pub url: String,
+    /// `None` uses the event's default template
+    pub template: Option<String>,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl DiscordWebhook {
+    /// A copy without the url, which carries the token used to post to the webhook.
+    pub fn redacted(&self) -> Self {
+        Self {
+            url: "[redacted]".to_string(),
+            ..self.clone()
+        }
+    }
+}
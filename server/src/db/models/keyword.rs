@@ -0,0 +1,245 @@
+use core::fmt;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::chatter::ChatterId;
+use crate::irc::commands::Emote;
+use crate::irc::matcher::{MatchMode, Matcher};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct KeywordId(pub i32);
+
+impl fmt::Display for KeywordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// What a keyword is matched against.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeywordKind {
+    /// The literal text of a message
+    #[default]
+    Text,
+    /// Twitch emotes, from the message's `emotes` tag
+    Emote,
+}
+
+impl KeywordKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Emote => "emote",
+        }
+    }
+}
+
+impl TryFrom<String> for KeywordKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "text" => Ok(Self::Text),
+            "emote" => Ok(Self::Emote),
+            _ => Err(format!("unknown keyword kind '{value}'")),
+        }
+    }
+}
+
+/// Base keyword table model
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Keyword {
+    pub id: KeywordId,
+    /// Always lowercase; the emote's name for `KeywordKind::Emote`
+    pub word: String,
+    #[sqlx(try_from = "String")]
+    pub kind: KeywordKind,
+    /// Matches the emote by id rather than by name; only set for `KeywordKind::Emote`
+    pub emote_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Keyword {
+    /// Compares names case-insensitively without lowercasing `name` into a new string.
+    fn matches_emote(&self, emote_id: &str, name: &[char]) -> bool {
+        match &self.emote_id {
+            Some(id) => id == emote_id,
+            None => name
+                .iter()
+                .flat_map(|c| c.to_lowercase())
+                .eq(self.word.chars()),
+        }
+    }
+}
+
+/// Finds every tracked keyword in a message.
+///
+/// Emote keywords are matched here; text keywords are matched by a `Matcher`, which defaults to
+/// substring matching (see `irc::matcher`).
+#[derive(Debug, Clone)]
+pub struct KeywordMatcher {
+    text: Arc<dyn Matcher>,
+    emotes: Vec<Keyword>,
+}
+
+impl KeywordMatcher {
+    pub fn new(keywords: &[Keyword]) -> Self {
+        Self::with_matcher(keywords, MatchMode::default().build(keywords))
+    }
+
+    /// Matches the text keywords with `text` rather than by substring.
+    pub fn with_matcher(keywords: &[Keyword], text: Arc<dyn Matcher>) -> Self {
+        Self {
+            text,
+            emotes: keywords
+                .iter()
+                .filter(|keyword| keyword.kind == KeywordKind::Emote)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Returns how many times each keyword occurs in the message, in keyword order, omitting
+    /// keywords that don't occur at all.
+    ///
+    /// Tracked emotes are only counted as emote keywords - their names aren't also matched as
+    /// text - while untracked emotes are matched as text like the rest of the message.
+    ///
+    /// This runs for every chat message, most of which don't mention a keyword, so the text is
+    /// only copied when it has to be masked or lowercased. A lowercase message without emotes or
+    /// keywords doesn't allocate at all.
+    pub fn occurrences(&self, text: &str, emotes: &[Emote]) -> Vec<(KeywordId, usize)> {
+        let mut counts = HashMap::<KeywordId, usize>::new();
+        let mut masked = None;
+
+        if !self.emotes.is_empty() && !emotes.is_empty() {
+            let mut chars = text.chars().collect::<Vec<_>>();
+            let mut changed = false;
+            for emote in emotes {
+                let Some(range) = chars.get_mut(emote.start..=emote.end) else {
+                    continue;
+                };
+
+                if let Some(keyword) = self
+                    .emotes
+                    .iter()
+                    .find(|k| k.matches_emote(&emote.id, range))
+                {
+                    *counts.entry(keyword.id).or_default() += 1;
+                    range.fill(' ');
+                    changed = true;
+                }
+            }
+            masked = changed.then_some(chars);
+        }
+
+        let text = match masked {
+            Some(chars) => Cow::Owned(chars.into_iter().collect::<String>().to_lowercase()),
+            None => lowercase(text),
+        };
+        self.text.count(&text, &mut counts);
+
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by_key(|(id, _)| id.0);
+        counts
+    }
+}
+
+/// Borrows text that's already lowercase, rather than copying it.
+fn lowercase(text: &str) -> Cow<'_, str> {
+    match text.chars().all(|c| c.to_lowercase().eq([c])) {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(text.to_lowercase()),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct KeywordLeaderboardEntry {
+    pub id: ChatterId,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    pub total: i64,
+    pub ranking: i64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keyword(id: i32, word: &str) -> Keyword {
+        Keyword {
+            id: KeywordId(id),
+            word: word.to_string(),
+            kind: KeywordKind::Text,
+            emote_id: None,
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn emote(id: i32, word: &str, emote_id: Option<&str>) -> Keyword {
+        Keyword {
+            kind: KeywordKind::Emote,
+            emote_id: emote_id.map(String::from),
+            ..keyword(id, word)
+        }
+    }
+
+    fn used(id: &str, start: usize, end: usize) -> Emote {
+        Emote {
+            id: id.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn occurrences_are_counted_per_keyword() {
+        let matcher =
+            KeywordMatcher::new(&[keyword(1, "piss"), keyword(2, "pissing"), keyword(3, "pee")]);
+
+        assert_eq!(
+            matcher.occurrences("PISS piss pisspiss, pissing", &[]),
+            vec![(KeywordId(1), 5), (KeywordId(2), 1)]
+        );
+        assert!(matcher.occurrences("nothing here", &[]).is_empty());
+    }
+
+    #[test]
+    fn tracked_emotes_are_counted_apart_from_text() {
+        let matcher = KeywordMatcher::new(&[
+            keyword(1, "piss"),
+            emote(2, "plsuwupiss", Some("300")),
+            emote(3, "peepiss", None),
+        ]);
+
+        // offsets are in chars, so the multi-byte `ÿ` doesn't shift the emotes after it
+        let text = "plsuwuPiss piss PeePiss \u{ff} PogPiss";
+        let emotes = [used("300", 0, 9), used("301", 16, 22), used("302", 26, 32)];
+        assert_eq!(
+            matcher.occurrences(text, &emotes),
+            // the untracked `PogPiss` is still matched as text
+            vec![(KeywordId(1), 2), (KeywordId(2), 1), (KeywordId(3), 1)]
+        );
+
+        // out of range emotes are ignored
+        assert_eq!(
+            matcher.occurrences("piss", &[used("300", 2, 9)]),
+            vec![(KeywordId(1), 1)]
+        );
+    }
+
+    #[test]
+    fn only_text_with_uppercase_is_copied() {
+        assert!(matches!(lowercase("piss \u{ff}"), Cow::Borrowed(_)));
+        // titlecase letters aren't uppercase, but still have a lowercase form
+        assert_eq!(lowercase("PISS \u{1c5}"), "piss \u{1c6}");
+    }
+}
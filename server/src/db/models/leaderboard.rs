@@ -2,9 +2,11 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 #[allow(dead_code)]
+#[derive(sqlx::FromRow)]
 pub struct Score {
     pub channel_id: super::channel::ChannelId,
     pub chatter_id: super::chatter::ChatterId,
+    pub keyword_id: super::keyword::KeywordId,
     pub score: i64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
@@ -25,6 +27,160 @@ pub struct ScoreEvent {
     pub created_at: NaiveDateTime,
 }
 
+/// What happens to score events whose message was removed by a moderator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationAction {
+    /// Marks the events with `flagged_at`, leaving scores untouched
+    Flag,
+    /// Deletes the events; the `score_event_decrement_trigger` updates the totals
+    Rollback,
+}
+
+/// Why a keyword match was recorded as suppressed instead of being counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressReason {
+    /// The chatter was over `SCORE_RATE_LIMIT_PER_MINUTE` in the channel
+    RateLimit,
+    /// Another match in the same message was already counted (`SCORE_ONE_PER_MESSAGE`)
+    PerMessage,
+}
+
+impl SuppressReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate_limit",
+            Self::PerMessage => "per_message",
+        }
+    }
+}
+
+/// The score events affected by a CLEARMSG or CLEARCHAT.
+#[derive(Debug, Clone)]
+pub enum ModerationTarget {
+    /// A single deleted message (message ids are unique across channels)
+    Message(String),
+    /// All recent messages from a chatter who was timed out or banned in a channel
+    Chatter {
+        channel_id: super::channel::ChannelId,
+        chatter_id: super::chatter::ChatterId,
+    },
+}
+
+/// A manual correction to a chatter's score in a channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreAdjustment {
+    /// Adds to (or, if negative, subtracts from) the current score
+    By(i64),
+    /// Replaces the current score
+    To(i64),
+}
+
+impl ScoreAdjustment {
+    /// Parses `+N`/`-N` as a relative adjustment, or `=N` as an absolute one.
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Some(score) = value.strip_prefix('=') {
+            return score.parse().ok().map(Self::To);
+        }
+
+        if !value.starts_with(['+', '-']) {
+            return None;
+        }
+
+        value.parse().ok().map(Self::By)
+    }
+
+    /// The score after the adjustment, or `None` if it would be negative.
+    pub fn apply(&self, current: i64) -> Option<i64> {
+        let adjusted = match self {
+            Self::By(delta) => current.checked_add(*delta)?,
+            Self::To(score) => *score,
+        };
+
+        (adjusted >= 0).then_some(adjusted)
+    }
+}
+
+/// A chatter's score in a channel either side of a `ScoreAdjustment`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdjustedScore {
+    pub chatter_id: super::chatter::ChatterId,
+    pub channel_id: super::channel::ChannelId,
+    pub keyword_id: super::keyword::KeywordId,
+    pub before: i64,
+    pub after: i64,
+}
+
+/// The span a leaderboard's scores are counted over.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+    /// The running totals, rather than a snapshot
+    #[default]
+    All,
+}
+
+impl Period {
+    /// The periods captured into `leaderboard_snapshot`
+    pub const SNAPSHOTTED: [Period; 3] = [Period::Day, Period::Week, Period::Month];
+
+    /// The `date_trunc` field a period starts on, or `None` for all-time.
+    pub fn as_date_trunc_field(&self) -> Option<&'static str> {
+        match self {
+            Period::Day => Some("day"),
+            Period::Week => Some("week"),
+            Period::Month => Some("month"),
+            Period::All => None,
+        }
+    }
+}
+
+/// Identifies the current state of a leaderboard, for conditional requests.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct LeaderboardVersion {
+    /// Changes whenever any score, channel, chatter or live state the leaderboard is built from
+    /// does; `None` if there were no matching channels
+    pub tag: Option<String>,
+    /// When anything the leaderboard is built from last changed
+    pub modified: Option<NaiveDateTime>,
+}
+
+/// A chatter or channel ranked by their score within the current `Period`.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PeriodLeaderboardRow {
+    pub id: String,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    pub score: i64,
+    /// For channels, the broadcaster's own score as a chatter in any channel
+    pub score_as_chatter: i64,
+    pub ranking: i64,
+    /// Channels the chatter scored in, or chatters that scored in the channel
+    pub score_count: i64,
+}
+
+/// The outcome of capturing a period into `leaderboard_snapshot`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeriodCapture {
+    pub written: u64,
+    /// The start of the previous period, if this capture was the one that finalised it
+    pub finalised: Option<NaiveDateTime>,
+}
+
+/// A past period's snapshot, captured for the last time now that the period has ended.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotFinalised {
+    pub period: Period,
+    pub period_start: NaiveDateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum TimeWindow {
     Yesterday,
@@ -72,3 +228,22 @@ impl TimeWindow {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adjustments_parse_and_apply() {
+        assert_eq!(ScoreAdjustment::parse("+5"), Some(ScoreAdjustment::By(5)));
+        assert_eq!(ScoreAdjustment::parse("-3"), Some(ScoreAdjustment::By(-3)));
+        assert_eq!(ScoreAdjustment::parse("=10"), Some(ScoreAdjustment::To(10)));
+        assert_eq!(ScoreAdjustment::parse("5"), None);
+        assert_eq!(ScoreAdjustment::parse("+x"), None);
+
+        assert_eq!(ScoreAdjustment::By(-3).apply(5), Some(2));
+        assert_eq!(ScoreAdjustment::To(10).apply(5), Some(10));
+        assert_eq!(ScoreAdjustment::By(-6).apply(5), None);
+        assert_eq!(ScoreAdjustment::To(-1).apply(5), None);
+    }
+}
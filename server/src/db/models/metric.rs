@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::leaderboard::Period;
+
+/// A family of stream events counted separately from keyword scores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMetric {
+    /// New, resubscribed and gifted subscriptions, counted once each
+    Subscription,
+    /// Bits cheered
+    Cheer,
+}
+
+impl StreamMetric {
+    pub const ALL: [StreamMetric; 2] = [StreamMetric::Subscription, StreamMetric::Cheer];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Subscription => "subscription",
+            Self::Cheer => "cheer",
+        }
+    }
+}
+
+impl TryFrom<String> for StreamMetric {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "subscription" => Ok(Self::Subscription),
+            "cheer" => Ok(Self::Cheer),
+            _ => Err(format!("unknown stream metric '{value}'")),
+        }
+    }
+}
+
+/// A channel's total for a single metric.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct MetricTotal {
+    #[sqlx(try_from = "String")]
+    pub metric: StreamMetric,
+    /// Number of events
+    pub events: i64,
+    /// Sum of the events' amounts; the same as `events` for subscriptions, and bits for cheers
+    pub amount: i64,
+}
+
+/// A channel's totals for every metric within a `Period`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelMetrics {
+    pub channel_id: ChannelId,
+    pub period: Period,
+    pub totals: Vec<MetricTotal>,
+}
+
+impl ChannelMetrics {
+    /// Metrics without any events are included with zero totals.
+    pub fn from_totals(channel_id: ChannelId, period: Period, totals: &[MetricTotal]) -> Self {
+        let totals = StreamMetric::ALL
+            .into_iter()
+            .map(|metric| {
+                totals
+                    .iter()
+                    .find(|total| total.metric == metric)
+                    .cloned()
+                    .unwrap_or(MetricTotal {
+                        metric,
+                        events: 0,
+                        amount: 0,
+                    })
+            })
+            .collect();
+
+        Self {
+            channel_id,
+            period,
+            totals,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn every_metric_has_a_total() {
+        let cheers = MetricTotal {
+            metric: StreamMetric::Cheer,
+            events: 2,
+            amount: 600,
+        };
+
+        let metrics = ChannelMetrics::from_totals(
+            ChannelId(String::from("1")),
+            Period::Week,
+            std::slice::from_ref(&cheers),
+        );
+
+        assert_eq!(
+            metrics.totals,
+            [
+                MetricTotal {
+                    metric: StreamMetric::Subscription,
+                    events: 0,
+                    amount: 0,
+                },
+                cheers,
+            ]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+
+/// What a milestone's threshold is compared against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MilestoneKind {
+    /// The channel's total across every chatter
+    ChannelTotal,
+    /// A single chatter's score in the channel
+    ChatterScore,
+}
+
+impl MilestoneKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ChannelTotal => "channel_total",
+            Self::ChatterScore => "chatter_score",
+        }
+    }
+}
+
+impl TryFrom<String> for MilestoneKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "channel_total" => Ok(Self::ChannelTotal),
+            "chatter_score" => Ok(Self::ChatterScore),
+            _ => Err(format!("unknown milestone kind '{value}'")),
+        }
+    }
+}
+
+/// A threshold announced in chat when it's crossed.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct Milestone {
+    pub id: i32,
+    /// `None` applies the milestone to every channel
+    pub channel_id: Option<ChannelId>,
+    #[sqlx(try_from = "String")]
+    pub kind: MilestoneKind,
+    pub threshold: i64,
+    /// Reached at every multiple of `threshold`, rather than only once
+    pub repeating: bool,
+    pub created_at: NaiveDateTime,
+}
+
+impl Milestone {
+    /// Returns the value reached if a count going from `prev` to `new` crosses this milestone.
+    pub fn crossed(&self, prev: i64, new: i64) -> Option<i64> {
+        if self.threshold <= 0 || new <= prev {
+            return None;
+        }
+
+        if self.repeating {
+            let reached = new / self.threshold * self.threshold;
+            (reached > prev && reached > 0).then_some(reached)
+        } else {
+            (prev < self.threshold && new >= self.threshold).then_some(self.threshold)
+        }
+    }
+}
+
+/// The counts a milestone is checked against, as of a single score event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, sqlx::FromRow)]
+pub struct MilestoneTotals {
+    pub channel_total: i64,
+    pub chatter_score: i64,
+}
+
+/// A milestone crossed by a score event.
+#[derive(Debug, Clone, Serialize)]
+pub struct MilestoneReached {
+    pub milestone_id: i32,
+    pub kind: MilestoneKind,
+    pub channel_id: ChannelId,
+    pub channel_login: String,
+    /// The chatter whose score event crossed the milestone
+    pub chatter_id: ChatterId,
+    pub chatter_login: String,
+    pub value: i64,
+    pub reached_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn milestone(threshold: i64, repeating: bool) -> Milestone {
+        Milestone {
+            id: 1,
+            channel_id: None,
+            kind: MilestoneKind::ChannelTotal,
+            threshold,
+            repeating,
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn milestones_are_crossed_once_or_at_every_multiple() {
+        let once = milestone(100, false);
+        assert_eq!(once.crossed(99, 100), Some(100));
+        assert_eq!(once.crossed(98, 102), Some(100));
+        assert_eq!(once.crossed(100, 101), None);
+        assert_eq!(once.crossed(199, 200), None);
+
+        let every = milestone(1000, true);
+        assert_eq!(every.crossed(999, 1000), Some(1000));
+        assert_eq!(every.crossed(2999, 3001), Some(3000));
+        assert_eq!(every.crossed(1000, 1001), None);
+        assert_eq!(every.crossed(0, 999), None);
+
+        // rolled back scores aren't milestones
+        assert_eq!(every.crossed(1001, 1000), None);
+    }
+}
@@ -1,9 +1,62 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
+pub mod alias;
+pub mod archive;
+pub mod audit;
 pub mod channel;
 pub mod chatter;
+pub mod deletion;
+pub mod digest;
+pub mod export;
+pub mod flag;
+pub mod heatmap;
+pub mod integration;
+pub mod keyword;
 pub mod leaderboard;
+pub mod metric;
+pub mod milestone;
+pub mod note;
+pub mod overlay;
+pub mod profile;
+pub mod raid;
+pub mod rank;
+pub mod search;
+pub mod settings;
+pub mod stats;
+pub mod stream;
+pub mod subscription;
+pub mod token;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum IdError {
+    #[error("id is empty")]
+    Empty,
+
+    #[error("id '{0}' is not numeric")]
+    NonNumeric(String),
+}
+
+/// Twitch user ids (and by extension channel ids) are non-empty numeric strings
+pub fn validate_id(id: &str) -> Result<(), IdError> {
+    if id.is_empty() {
+        return Err(IdError::Empty);
+    }
+
+    if !id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(IdError::NonNumeric(id.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Allows repositories to reject invalid ids before a query is sent
+impl From<IdError> for sqlx::Error {
+    fn from(value: IdError) -> Self {
+        sqlx::Error::Encode(Box::new(value))
+    }
+}
 
 #[inline]
 const fn default_offset() -> i64 {
@@ -16,10 +69,11 @@ const fn default_limit() -> i64 {
 }
 
 #[allow(dead_code)]
-#[derive(Debug, sqlx::FromRow, Default)]
+#[derive(Debug, Clone, sqlx::FromRow, Default)]
 pub struct Session {
     pub id: i32,
-    pub token: String,
+    /// Hex-encoded SHA-256 of the session token; the token itself is only known to its holder
+    pub token_hash: String,
     pub created_at: NaiveDateTime,
     pub expires_at: NaiveDateTime,
 }
@@ -36,7 +90,7 @@ pub struct Pagination {
     pub score_page: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaginatedResponse<T> {
     pub items: Vec<T>,
     pub page: i64,
@@ -62,3 +116,19 @@ impl<T> PaginatedResponse<T> {
 }
 
 pub mod prelude {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::prelude::{ChannelId, ChatterId};
+
+    #[test]
+    fn ids_require_numeric_values() {
+        assert!("123456789".parse::<ChatterId>().is_ok());
+        assert_eq!(ChannelId::try_from(""), Err(IdError::Empty));
+        assert_eq!(
+            ChatterId::try_from("12ab"),
+            Err(IdError::NonNumeric(String::from("12ab")))
+        );
+    }
+}
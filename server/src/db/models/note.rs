@@ -0,0 +1,39 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+
+/// A moderator's private note on a chatter, scoped to a single channel.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ChatterNote {
+    pub channel_id: ChannelId,
+    pub chatter_id: ChatterId,
+    pub chatter_login: String,
+    pub note: String,
+    /// Short labels such as `counts contested` or `alt account of X`
+    pub flags: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A single edit to a `ChatterNote`.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ChatterNoteAudit {
+    pub id: i32,
+    /// One of `create`, `update` or `delete`
+    pub action: String,
+    /// The note as it was after the edit; `None` for deletions
+    pub note: Option<String>,
+    pub flags: Option<Vec<String>>,
+    pub author: String,
+    /// The admin session the edit was made from
+    pub session_id: Option<i32>,
+    pub changed_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatterNoteHistory {
+    pub note: Option<ChatterNote>,
+    pub history: Vec<ChatterNoteAudit>,
+}
@@ -0,0 +1,102 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+
+use crate::db::models::channel::ChannelId;
+
+/// A chatter as shown on an overlay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayChatter {
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    pub score: i64,
+}
+
+/// The chatter most recently counted in a channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastIncrementer {
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub counted_at: NaiveDateTime,
+}
+
+/// Everything a browser-source overlay shows for a channel.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct Overlay {
+    #[serde(skip)]
+    pub channel_id: ChannelId,
+    pub channel: String,
+    pub total: i64,
+    /// `None` until somebody is counted in the channel
+    pub last_incrementer: Option<Json<LastIncrementer>>,
+    pub top: Json<Vec<OverlayChatter>>,
+}
+
+impl Overlay {
+    /// Identifies this state of the overlay; only a counted score or a correction changes it.
+    pub fn tag(&self) -> String {
+        let last = self
+            .last_incrementer
+            .as_ref()
+            .map(|last| last.counted_at.and_utc().timestamp_micros())
+            .unwrap_or_default();
+        let top = self
+            .top
+            .iter()
+            .map(|chatter| format!("{}:{}", chatter.login, chatter.score))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let mut hasher = DefaultHasher::new();
+        (self.total, last, top).hash(&mut hasher);
+        format!("{:x}", hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn tags_change_with_what_overlays_show() {
+        let chatter = |login: &str, score| OverlayChatter {
+            login: login.to_string(),
+            name: login.to_string(),
+            color: String::from("#000000"),
+            image: String::new(),
+            score,
+        };
+        let counted_at = NaiveDate::from_ymd_opt(2026, 10, 18)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let mut overlay = Overlay {
+            channel_id: ChannelId(String::from("1")),
+            channel: String::from("chan"),
+            total: 10,
+            last_incrementer: None,
+            top: Json(vec![chatter("a", 6), chatter("b", 4)]),
+        };
+        let tag = overlay.tag();
+        assert_eq!(overlay.clone().tag(), tag);
+
+        overlay.last_incrementer = Some(Json(LastIncrementer {
+            login: String::from("b"),
+            name: String::from("b"),
+            color: String::from("#000000"),
+            counted_at,
+        }));
+        let counted = overlay.tag();
+        assert_ne!(counted, tag);
+
+        overlay.top.0.swap(0, 1);
+        assert_ne!(overlay.tag(), counted);
+    }
+}
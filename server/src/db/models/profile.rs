@@ -0,0 +1,101 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelScoreSummary;
+use crate::db::models::chatter::ChatterId;
+
+/// A chatter's global standing, along with when they were first and last counted.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChatterProfileRow {
+    pub id: ChatterId,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    pub total: i64,
+    pub ranking: i64,
+    /// `None` if the chatter has never been counted (e.g. a channel that hasn't chatted)
+    pub first_seen: Option<NaiveDateTime>,
+    pub last_seen: Option<NaiveDateTime>,
+}
+
+/// Scores counted in a single (UTC) day.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct DailyScore {
+    pub day: NaiveDate,
+    pub total: i64,
+}
+
+/// Everything shown on a chatter's profile: their global standing, their score and rank in each
+/// channel, and their daily activity over recent days.
+#[derive(Debug, Clone)]
+pub struct ChatterProfile {
+    pub chatter: ChatterProfileRow,
+    pub channel_scores: Vec<ChannelScoreSummary>,
+    /// One entry for every day in the window, oldest first
+    pub activity: Vec<DailyScore>,
+}
+
+impl ChatterProfile {
+    /// Fills in the `len` days starting at `start` from `daily`, with zeroes for days without
+    /// scores.
+    pub fn new(
+        chatter: ChatterProfileRow,
+        channel_scores: Vec<ChannelScoreSummary>,
+        start: NaiveDate,
+        len: u64,
+        daily: &[DailyScore],
+    ) -> Self {
+        let activity = start
+            .iter_days()
+            .take(len as usize)
+            .map(|day| DailyScore {
+                day,
+                total: daily
+                    .iter()
+                    .find(|score| score.day == day)
+                    .map_or(0, |score| score.total),
+            })
+            .collect();
+
+        Self {
+            chatter,
+            channel_scores,
+            activity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn activity_has_every_day() {
+        let chatter = ChatterProfileRow {
+            id: ChatterId(String::from("1")),
+            login: String::from("chatter"),
+            name: String::from("Chatter"),
+            color: String::from("#000000"),
+            image: String::new(),
+            total: 4,
+            ranking: 1,
+            first_seen: None,
+            last_seen: None,
+        };
+        let start = NaiveDate::from_ymd_opt(2026, 10, 16).unwrap();
+        let daily = vec![DailyScore {
+            day: NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+            total: 4,
+        }];
+
+        let profile = ChatterProfile::new(chatter, Vec::new(), start, 3, &daily);
+        let totals: Vec<i64> = profile.activity.iter().map(|day| day.total).collect();
+
+        assert_eq!(totals, [0, 4, 0]);
+        assert_eq!(
+            profile.activity[2].day,
+            NaiveDate::from_ymd_opt(2026, 10, 18).unwrap()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+
+/// A raid into a tracked channel.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct RaidEvent {
+    pub id: i32,
+    /// The channel that was raided
+    pub channel_id: ChannelId,
+    /// The raiding channel, which may not be tracked
+    pub from_broadcaster_id: String,
+    pub from_broadcaster_login: String,
+    pub viewers: i64,
+    pub raided_at: NaiveDateTime,
+}
+
+/// Scores counted in a single hour.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize)]
+pub struct HourlyScore {
+    /// The start of the hour, in UTC
+    pub hour: NaiveDateTime,
+    pub total: i64,
+}
+
+/// A channel's scores by hour, marked with the raids it received over the same hours so that
+/// spikes can be put in context.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreTimeline {
+    pub channel_id: ChannelId,
+    /// One entry for every hour in the timeline, oldest first
+    pub hours: Vec<HourlyScore>,
+    pub raids: Vec<RaidEvent>,
+}
+
+impl ScoreTimeline {
+    /// Builds a timeline of the `len` hours starting at `start`, which should be the start of an
+    /// hour; hours without scores are filled in with zeroes.
+    pub fn from_hours(
+        channel_id: ChannelId,
+        start: NaiveDateTime,
+        len: i64,
+        hourly: &[HourlyScore],
+        raids: Vec<RaidEvent>,
+    ) -> Self {
+        let hours = (0..len)
+            .map(|offset| {
+                let hour = start + chrono::Duration::hours(offset);
+                let total = hourly
+                    .iter()
+                    .filter(|bucket| bucket.hour == hour)
+                    .map(|bucket| bucket.total)
+                    .sum();
+
+                HourlyScore { hour, total }
+            })
+            .collect();
+
+        Self {
+            channel_id,
+            hours,
+            raids,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn timelines_have_every_hour() {
+        let start = NaiveDate::from_ymd_opt(2026, 10, 18)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let hourly = vec![
+            HourlyScore {
+                hour: start + chrono::Duration::hours(1),
+                total: 4,
+            },
+            // outside the timeline
+            HourlyScore {
+                hour: start + chrono::Duration::hours(5),
+                total: 9,
+            },
+        ];
+
+        let timeline =
+            ScoreTimeline::from_hours(ChannelId(String::from("1")), start, 3, &hourly, Vec::new());
+
+        let totals: Vec<i64> = timeline.hours.iter().map(|hour| hour.total).collect();
+        assert_eq!(totals, [0, 4, 0]);
+        assert_eq!(timeline.hours[0].hour, start);
+        assert_eq!(timeline.hours[2].hour, start + chrono::Duration::hours(2));
+    }
+}
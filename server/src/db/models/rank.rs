@@ -0,0 +1,115 @@
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+
+/// A chatter's position on a single channel's leaderboard.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ChannelRank {
+    pub channel_id: ChannelId,
+    pub chatter_id: ChatterId,
+    /// Summed across all keywords
+    pub score: i64,
+    /// 1-based, with ties ordered by whoever scored first
+    pub rank: i64,
+    /// Number of chatters with a score on the channel
+    pub ranked_chatters: i64,
+}
+
+/// A chatter's position on the global leaderboard, summed across every channel.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct GlobalRank {
+    pub chatter_id: ChatterId,
+    pub score: i64,
+    /// 1-based, with ties ordered by whoever was seen first
+    pub rank: i64,
+}
+
+/// One side of a `Versus`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Contender {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub name: String,
+    /// `None` without a channel, or if the chatter hasn't scored on it
+    pub channel: Option<ChannelRank>,
+    pub global: Option<GlobalRank>,
+}
+
+impl Contender {
+    /// The score the comparison is decided on: the channel's if there is one, otherwise the
+    /// global score.
+    fn score(&self, on_channel: bool) -> i64 {
+        if on_channel {
+            self.channel.as_ref().map_or(0, |rank| rank.score)
+        } else {
+            self.global.as_ref().map_or(0, |rank| rank.score)
+        }
+    }
+}
+
+/// Two chatters compared head to head, on a channel (if one is given) and globally.
+#[derive(Debug, Clone, Serialize)]
+pub struct Versus {
+    pub channel_id: Option<ChannelId>,
+    pub contenders: [Contender; 2],
+}
+
+impl Versus {
+    /// The contenders ordered leader first, and the margin between them; a margin of 0 is a tie.
+    pub fn standings(&self) -> (&Contender, &Contender, i64) {
+        let on_channel = self.channel_id.is_some();
+        let [a, b] = &self.contenders;
+        let (a_score, b_score) = (a.score(on_channel), b.score(on_channel));
+
+        if a_score >= b_score {
+            (a, b, a_score - b_score)
+        } else {
+            (b, a, b_score - a_score)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn contender(login: &str, channel: Option<i64>, global: i64) -> Contender {
+        let chatter_id = ChatterId(login.to_string());
+        Contender {
+            chatter_id: chatter_id.clone(),
+            login: login.to_string(),
+            name: login.to_string(),
+            channel: channel.map(|score| ChannelRank {
+                channel_id: ChannelId(String::from("1")),
+                chatter_id: chatter_id.clone(),
+                score,
+                rank: 1,
+                ranked_chatters: 2,
+            }),
+            global: Some(GlobalRank {
+                chatter_id,
+                score: global,
+                rank: 1,
+            }),
+        }
+    }
+
+    #[test]
+    fn standings_use_the_channel_score_when_there_is_one() {
+        let versus = Versus {
+            channel_id: Some(ChannelId(String::from("1"))),
+            contenders: [contender("a", None, 100), contender("b", Some(3), 1)],
+        };
+        let (leader, trailer, margin) = versus.standings();
+        assert_eq!((leader.login.as_str(), trailer.login.as_str()), ("b", "a"));
+        assert_eq!(margin, 3);
+
+        let versus = Versus {
+            channel_id: None,
+            ..versus
+        };
+        let (leader, _, margin) = versus.standings();
+        assert_eq!((leader.login.as_str(), margin), ("a", 99));
+    }
+}
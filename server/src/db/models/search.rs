@@ -0,0 +1,18 @@
+use serde::Serialize;
+
+use crate::db::models::chatter::ChatterId;
+
+/// A chatter (or broadcaster) matched by login or display name.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct UserSearchMatch {
+    pub id: ChatterId,
+    pub login: String,
+    pub name: String,
+    pub color: String,
+    pub image: String,
+    pub total: i64,
+    /// `None` unless the user is a tracked channel
+    pub channel_total: Option<i64>,
+    /// Trigram similarity of the closer of `login` and `name`, in `0.0..=1.0`
+    pub similarity: f32,
+}
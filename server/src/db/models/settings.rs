@@ -0,0 +1,136 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::db::models::channel::ReplyMode;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::keyword::KeywordId;
+
+/// A channel's behaviour settings. Channels that have never had their settings changed use
+/// `ChannelSettings::default`, which matches the column defaults.
+///
+/// The reply mode is stored with the rest of the channel's reply configuration, but is read and
+/// written along with these.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    #[sqlx(try_from = "String")]
+    pub reply_mode: ReplyMode,
+    /// Keywords that aren't counted in the channel
+    pub muted_keywords: Vec<KeywordId>,
+    /// Seconds a chatter has to wait between chat commands; 0 turns the cooldown off
+    pub command_cooldown_secs: i32,
+    /// Reached milestones are announced in chat
+    pub milestones: bool,
+    /// Incoming raids are thanked in chat
+    pub raid_thanks: bool,
+    /// The hour (in the channel's timezone) the daily digest is compiled after; `None` turns
+    /// digests off
+    pub digest_hour: Option<i32>,
+    /// Digests are announced in chat while the channel is live
+    pub digest_in_chat: bool,
+    /// Chatters that aren't counted in the channel. Managed through the channel's blacklist
+    /// rather than as a setting, so it's ignored when settings are replaced.
+    #[serde(default, skip_deserializing)]
+    pub blacklist: Vec<ChatterId>,
+}
+
+impl ChannelSettings {
+    pub const COMMAND_COOLDOWN_RANGE: std::ops::RangeInclusive<i32> = 0..=3600;
+    pub const DIGEST_HOUR_RANGE: std::ops::RangeInclusive<i32> = 0..=23;
+
+    /// Whether the bot posts in the channel's chat at all; channels that get whispers instead
+    /// don't get announcements either.
+    pub fn is_whitelisted(&self) -> bool {
+        self.reply_mode == ReplyMode::Reply
+    }
+
+    pub fn announces_milestones(&self) -> bool {
+        self.is_whitelisted() && self.milestones
+    }
+
+    pub fn thanks_raids(&self) -> bool {
+        self.is_whitelisted() && self.raid_thanks
+    }
+
+    pub fn announces_digests(&self) -> bool {
+        self.is_whitelisted() && self.digest_in_chat
+    }
+
+    pub fn is_blacklisted(&self, chatter_id: &ChatterId) -> bool {
+        self.blacklist.contains(chatter_id)
+    }
+
+    /// Returns a description of the first invalid setting, if any.
+    pub fn validate(&self) -> Result<(), String> {
+        if !Self::COMMAND_COOLDOWN_RANGE.contains(&self.command_cooldown_secs) {
+            return Err(format!(
+                "command_cooldown_secs must be within {:?}",
+                Self::COMMAND_COOLDOWN_RANGE
+            ));
+        }
+
+        if let Some(hour) = self.digest_hour
+            && !Self::DIGEST_HOUR_RANGE.contains(&hour)
+        {
+            return Err(format!(
+                "digest_hour must be within {:?}",
+                Self::DIGEST_HOUR_RANGE
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            reply_mode: ReplyMode::default(),
+            muted_keywords: Vec::new(),
+            command_cooldown_secs: 0,
+            milestones: true,
+            raid_thanks: true,
+            digest_hour: None,
+            digest_in_chat: false,
+            blacklist: Vec::new(),
+        }
+    }
+}
+
+/// A chatter on a channel's blacklist.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct BlacklistedChatter {
+    pub chatter_id: ChatterId,
+    /// The chatter's current login if they've chatted, otherwise the login they were added with
+    pub login: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn announcements_need_replies_in_chat() {
+        let mut settings = ChannelSettings::default();
+        assert!(!settings.announces_milestones());
+
+        settings.reply_mode = ReplyMode::Reply;
+        assert!(settings.announces_milestones() && settings.thanks_raids());
+
+        settings.raid_thanks = false;
+        assert!(!settings.thanks_raids());
+
+        settings.digest_in_chat = true;
+        assert!(settings.announces_digests());
+
+        settings.digest_hour = Some(24);
+        assert!(settings.validate().is_err());
+
+        settings.digest_hour = Some(9);
+        settings.command_cooldown_secs = -1;
+        assert!(settings.validate().is_err());
+
+        settings.blacklist.push(ChatterId(String::from("123")));
+        assert!(settings.is_blacklisted(&ChatterId(String::from("123"))));
+    }
+}
@@ -0,0 +1,35 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::chatter::ChatterId;
+
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct TableStats {
+    pub table_name: String,
+    /// From the planner statistics, so may lag slightly behind the actual count
+    pub estimated_rows: i64,
+    /// `None` for tables without a write timestamp, or with no rows
+    pub last_write_at: Option<NaiveDateTime>,
+}
+
+/// A channel's counting statistics as stored, which `irc::stats` keeps current from there on.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChannelCountStats {
+    pub total: i64,
+    pub unique_chatters: i64,
+    /// `None` while nobody has been counted in the channel
+    pub top_chatter_id: Option<ChatterId>,
+    pub top_chatter_login: Option<String>,
+    pub top_chatter_score: Option<i64>,
+    /// Scores counted since the start of the channel's day, in its timezone
+    pub today: i64,
+    /// When the channel's day ends (in UTC)
+    pub day_ends_at: NaiveDateTime,
+}
+
+/// Scores counted in a single minute, keyed by minutes since the epoch.
+#[derive(Debug, Clone, Copy, sqlx::FromRow)]
+pub struct MinuteCount {
+    pub minute: i64,
+    pub count: i64,
+}
@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::db::models::channel::ChannelId;
+use crate::irc::room_state::RoomState;
+
+/// A channel's live state as of the last refresh from Helix.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct StreamStatus {
+    pub channel_id: ChannelId,
+    pub is_live: bool,
+    /// Zero while offline
+    pub viewer_count: i64,
+    /// Empty while offline
+    pub game: String,
+    pub title: String,
+    pub started_at: Option<NaiveDateTime>,
+    pub updated_at: NaiveDateTime,
+    /// Counting is paused for the channel, whether or not it's live
+    pub paused: bool,
+    /// The channel's chat modes, if it's joined by this instance
+    #[sqlx(skip)]
+    pub room_state: Option<RoomState>,
+}
+
+/// A currently-live stream, as reported by Helix.
+#[derive(Debug, Clone)]
+pub struct LiveStream {
+    pub channel_id: ChannelId,
+    pub login: String,
+    pub viewer_count: i64,
+    pub game: String,
+    pub title: String,
+    pub started_at: NaiveDateTime,
+}
+
+/// A tracked channel that went live since the previous refresh.
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamOnline {
+    pub channel_id: ChannelId,
+    pub channel_login: String,
+    pub game: String,
+    pub title: String,
+    pub started_at: NaiveDateTime,
+}
+
+impl From<&LiveStream> for StreamOnline {
+    fn from(stream: &LiveStream) -> Self {
+        Self {
+            channel_id: stream.channel_id.clone(),
+            channel_login: stream.login.clone(),
+            game: stream.game.clone(),
+            title: stream.title.clone(),
+            started_at: stream.started_at,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use chrono::NaiveDateTime;
+
+use crate::db::models::channel::ChannelId;
+
+/// An EventSub webhook subscription and the secret its notifications are signed with.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct EventSubSubscription {
+    pub channel_id: ChannelId,
+    /// e.g. `stream.online`
+    pub subscription_type: String,
+    /// `None` until Twitch has accepted the subscription
+    pub subscription_id: Option<String>,
+    /// Sealed; see `api::webhook::secret`
+    pub secret: String,
+    /// The secret replaced by the last rotation, if any
+    pub previous_secret: Option<String>,
+    pub rotated_at: Option<NaiveDateTime>,
+    /// Appended to `CALLBACK_URL`; see `api::webhook::callback`
+    pub callback_path: Option<String>,
+    /// `enabled`, or the reason Twitch gave for revoking the subscription
+    pub status: String,
+    pub revoked_at: Option<NaiveDateTime>,
+    /// The cost Twitch reported for the subscription; `0` until it's been accepted
+    pub cost: i32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
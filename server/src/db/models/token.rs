@@ -0,0 +1,18 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+/// An issued API token. Only the token's claims are stored; the signed token itself is shown once
+/// when it's minted.
+#[derive(Debug, Clone, sqlx::FromRow, Serialize)]
+pub struct ApiToken {
+    pub id: String,
+    /// Who or what the token was issued to
+    pub label: String,
+    pub scopes: Vec<String>,
+    /// Ids of the channels an `increment` token may push to
+    pub channels: Vec<String>,
+    pub created_at: NaiveDateTime,
+    /// `None` never expires
+    pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
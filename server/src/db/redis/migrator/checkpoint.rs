@@ -0,0 +1,212 @@
+//! Checkpoint records for the Redis -> Postgres migration.
+//!
+//! Each phase of the migration records the keys it has finished with in `migration_progress` and
+//! marks itself as complete in `migration_phase`, which allows an interrupted migration to be
+//! resumed (via `--resume`) without redoing (or double-counting) work that has already been
+//! committed.
+
+use std::collections::HashSet;
+
+use sqlx::{Pool, Postgres, Transaction};
+use tracing::instrument;
+
+use crate::db::prelude::Chatter;
+use crate::db::repositories::sql_fragment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationPhase {
+    Channels,
+    Chatters,
+    /// Chatter logins Helix couldn't resolve, whose scores weren't migrated
+    Unresolved,
+    Scores,
+}
+
+impl MigrationPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationPhase::Channels => "channels",
+            MigrationPhase::Chatters => "chatters",
+            MigrationPhase::Unresolved => "unresolved",
+            MigrationPhase::Scores => "scores",
+        }
+    }
+}
+
+impl core::fmt::Display for MigrationPhase {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Returns true if the process was started with the `--resume` flag
+pub fn resume_requested() -> bool {
+    std::env::args().any(|arg| arg == "--resume")
+}
+
+#[derive(Debug)]
+pub struct Checkpoint<'a>(pub &'a Pool<Postgres>);
+
+impl<'a> Checkpoint<'a> {
+    /// Clears all checkpoint records; used when a migration is started from scratch.
+    #[instrument(skip(self))]
+    pub async fn reset(&self) -> Result<(), sqlx::Error> {
+        tracing::warn!("clearing migration checkpoints");
+
+        let mut tx = self.0.begin().await?;
+        sqlx::query("DELETE FROM migration_progress")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM migration_phase")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn begin(&self, phase: MigrationPhase, total: usize) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO migration_phase (phase, total, started_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (phase)
+            DO UPDATE SET total = $2
+            "#,
+        )
+        .bind(phase.as_str())
+        .bind(total as i64)
+        .execute(self.0)
+        .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn is_complete(&self, phase: MigrationPhase) -> Result<bool, sqlx::Error> {
+        let completed = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM migration_phase
+                WHERE phase = $1
+                AND completed_at IS NOT NULL
+            )
+            "#,
+        )
+        .bind(phase.as_str())
+        .fetch_one(self.0)
+        .await?;
+
+        Ok(completed)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn complete(&self, phase: MigrationPhase) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE migration_phase
+            SET completed_at = NOW()
+            WHERE phase = $1
+            "#,
+        )
+        .bind(phase.as_str())
+        .execute(self.0)
+        .await?;
+
+        tracing::info!(%phase, "migration phase complete");
+        Ok(())
+    }
+
+    /// Retrieves the set of keys that have already been migrated for a given phase.
+    #[instrument(skip(self))]
+    pub async fn completed_keys(
+        &self,
+        phase: MigrationPhase,
+    ) -> Result<HashSet<String>, sqlx::Error> {
+        let keys: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT key FROM migration_progress
+            WHERE phase = $1
+            "#,
+        )
+        .bind(phase.as_str())
+        .fetch_all(self.0)
+        .await?;
+
+        Ok(keys.into_iter().collect())
+    }
+
+    #[instrument(skip(self, keys), fields(keys_count = keys.len()))]
+    pub async fn mark_keys(
+        &self,
+        phase: MigrationPhase,
+        keys: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.0.begin().await?;
+        Self::mark_keys_in_tx(&mut tx, phase, keys).await?;
+
+        tx.commit().await
+    }
+
+    /// Records migrated keys as part of an existing transaction, so that the checkpoint is only
+    /// ever committed alongside the data it describes.
+    #[instrument(skip(tx, keys), fields(keys_count = keys.len()))]
+    pub async fn mark_keys_in_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        phase: MigrationPhase,
+        keys: &[String],
+    ) -> Result<(), sqlx::Error> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO migration_progress (phase, key, migrated_at)
+            SELECT $1, key, NOW()
+            FROM UNNEST($2::text[]) AS keys(key)
+            ON CONFLICT (phase, key)
+            DO NOTHING
+            "#,
+        )
+        .bind(phase.as_str())
+        .bind(keys)
+        .execute(tx.as_mut())
+        .await?
+        .rows_affected();
+
+        // keys that were already recorded aren't counted twice
+        let processed = sqlx::query_scalar::<_, i64>(
+            r#"
+            UPDATE migration_phase
+            SET processed = processed + $2
+            WHERE phase = $1
+            RETURNING processed
+            "#,
+        )
+        .bind(phase.as_str())
+        .bind(inserted as i64)
+        .fetch_optional(tx.as_mut())
+        .await?
+        .unwrap_or_default();
+
+        tracing::info!(%phase, processed, "migration progress");
+        Ok(())
+    }
+
+    /// Loads chatters that were resolved during a previous (interrupted) run.
+    #[instrument(skip(self, logins), fields(logins_count = logins.len()))]
+    pub async fn resolved_chatters(&self, logins: &[String]) -> Result<Vec<Chatter>, sqlx::Error> {
+        if logins.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        sqlx::query_as::<_, Chatter>(&format!(
+            "SELECT {} FROM chatter WHERE login = ANY($1)",
+            sql_fragment::CHATTER_FIELDS
+        ))
+        .bind(logins)
+        .fetch_all(self.0)
+        .await
+    }
+}
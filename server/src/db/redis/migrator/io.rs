@@ -5,6 +5,7 @@ use sqlx::{Pool, Postgres, Transaction};
 use tracing::instrument;
 
 use crate::db::prelude::{Channel, ChannelId, ChatterId};
+use crate::db::redis::migrator::checkpoint::{Checkpoint, MigrationPhase};
 use crate::db::redis::migrator::{LeaderboardMap, LeaderboardRow, transform, util};
 use crate::db::redis::redis_pool::{self, KeyType, RedisResult};
 use crate::redis_key;
@@ -70,7 +71,7 @@ where
             Ok(val) => val,
             Err(e) => {
                 tracing::error!(error = ?e, "failed to fetch aliases from cache");
-                return Err(redis_pool::RedisErr::RedisClientError(e));
+                return Err(redis_pool::RedisErr::from(e));
             }
         };
 
@@ -107,13 +108,36 @@ impl<'a> PgHandler<'a> {
         offset_days: i64,
     ) -> Result<(), sqlx::Error> {
         let timestamp = util::create_timestamp(offset_days);
+        let checkpoint = Checkpoint(self.0);
+
+        // score events aren't idempotent, so chatters that were migrated in a previous run are
+        // skipped entirely rather than re-inserted
+        let migrated = checkpoint.completed_keys(MigrationPhase::Scores).await?;
+        let total = leaderboards.len();
+        let pending: LeaderboardMap = leaderboards
+            .into_iter()
+            .filter(|(chatter_id, _)| !migrated.contains(chatter_id))
+            .collect();
+
+        tracing::info!(
+            phase = %MigrationPhase::Scores,
+            skipped = total - pending.len(),
+            pending = pending.len(),
+            "migrating cached leaderboards"
+        );
+
+        checkpoint.begin(MigrationPhase::Scores, total).await?;
+
         let mut tx = self.0.begin().await?;
 
         Triggers(&mut tx).disable().await?;
 
-        for (chatter_id, leaderboard) in leaderboards {
+        for (chatter_id, leaderboard) in pending {
             tracing::debug!(chatter_id, ?leaderboard, "handling leaderboard");
 
+            Checkpoint::mark_keys_in_tx(&mut tx, MigrationPhase::Scores, &[chatter_id.clone()])
+                .await?;
+
             let chatter_id = ChatterId(chatter_id);
 
             for (channel_id, score) in leaderboard {
@@ -281,26 +305,29 @@ impl<'a> PgHandler<'a> {
         .execute(tx.as_mut())
         .await?;
 
-        sqlx::query!(
+        // scores are kept per keyword, matching the `score` primary key
+        sqlx::query(
             r#"
             INSERT INTO score (
                 chatter_id,
                 channel_id,
+                keyword_id,
                 score,
                 updated_at
             )
             SELECT 
                 chatter_id,
                 channel_id, 
+                keyword_id,
                 COUNT(*), 
                 NOW()
             FROM score_event 
-            GROUP BY chatter_id, channel_id
-            ON CONFLICT (chatter_id, channel_id)
+            GROUP BY chatter_id, channel_id, keyword_id
+            ON CONFLICT (chatter_id, channel_id, keyword_id)
             DO UPDATE SET 
                 score = EXCLUDED.score, 
                 updated_at = EXCLUDED.updated_at
-            "#
+            "#,
         )
         .execute(tx.as_mut())
         .await?;
@@ -5,11 +5,13 @@ use sqlx::{Pool, Postgres};
 use tracing::instrument;
 
 use crate::db::prelude::*;
+use crate::db::redis::migrator::checkpoint::{Checkpoint, MigrationPhase};
 use crate::db::redis::migrator::io::PgHandler;
 use crate::db::redis::migrator::util::KeyList;
 use crate::db::redis::redis_pool::{KeyType, RedisResult};
 use crate::util::helix::Helix;
 
+pub mod checkpoint;
 pub mod io;
 pub mod transform;
 pub mod util;
@@ -19,13 +21,31 @@ pub type LeaderboardMap = HashMap<String, LeaderboardRow>;
 
 const DEFAULT_TIMESTAMP_OFFSET: i64 = 120;
 
+/// Runs the full Redis -> Postgres migration.
+///
+/// If the process was started with `--resume`, phases and keys recorded as complete by a previous
+/// run are skipped; otherwise all checkpoints are cleared and the migration starts from scratch.
 #[instrument(skip(redis_pool, database_pool))]
 pub async fn process_initial_migration<R: AsyncCommands + Sync>(
     redis_pool: R,
     database_pool: &'static Pool<Postgres>,
 ) -> RedisResult<()> {
+    let checkpoint = Checkpoint(database_pool);
+    let resume = checkpoint::resume_requested();
+    tracing::info!(resume, "starting redis migration");
+    if !resume {
+        checkpoint.reset().await?;
+    }
+
     let mut migrator = Migrator::new(redis_pool, database_pool);
-    migrator.migrate_cached_channels().await?;
+
+    if checkpoint.is_complete(MigrationPhase::Channels).await? {
+        tracing::info!(phase = %MigrationPhase::Channels, "skipping completed phase");
+    } else {
+        migrator.migrate_cached_channels().await?;
+        checkpoint.complete(MigrationPhase::Channels).await?;
+    }
+
     let (cached_chatters, resolved_chatters) = migrator.migrate_cached_chatters().await?;
 
     let (resolved, rejected) = migrator
@@ -34,10 +54,15 @@ pub async fn process_initial_migration<R: AsyncCommands + Sync>(
 
     tracing::error!(?rejected, "INVALID CHATTERS");
 
-    migrator
-        .postgres_handler
-        .migrate(resolved, DEFAULT_TIMESTAMP_OFFSET)
-        .await?;
+    if checkpoint.is_complete(MigrationPhase::Scores).await? {
+        tracing::info!(phase = %MigrationPhase::Scores, "skipping completed phase");
+    } else {
+        migrator
+            .postgres_handler
+            .migrate(resolved, DEFAULT_TIMESTAMP_OFFSET)
+            .await?;
+        checkpoint.complete(MigrationPhase::Scores).await?;
+    }
 
     Ok(())
 }
@@ -98,6 +123,11 @@ where
 
         tracing::debug!(channel_ids = ?parsed_channel_ids, "retrieved and parsed channel_id list from redis keys");
 
+        let checkpoint = Checkpoint(self.database_pool);
+        checkpoint
+            .begin(MigrationPhase::Channels, parsed_channel_ids.len())
+            .await?;
+
         let helix_channels = Helix::fetch_users_by_id(&mut parsed_channel_ids).await?;
         let broadcaster_chatters: Vec<Chatter> =
             helix_channels.into_iter().map(Chatter::from).collect();
@@ -114,20 +144,79 @@ where
 
         self.postgres_handler.insert_reply_config(&channels).await?;
 
+        let channel_ids: Vec<String> = channels.iter().map(|ch| ch.id.to_string()).collect();
+        checkpoint
+            .mark_keys(MigrationPhase::Channels, &channel_ids)
+            .await?;
+
         Ok(())
     }
 
     pub async fn migrate_cached_chatters(&mut self) -> RedisResult<(Vec<String>, Vec<Chatter>)> {
         let chatter_repo = ChatterRepository::new(self.database_pool);
+        let checkpoint = Checkpoint(self.database_pool);
         let mut redis_handler = io::RedisHandler(&mut self.redis_connection);
 
         let cached_chatters_raw = redis_handler.fetch_keys(KeyType::Chatter).await?;
         let parsed_chatters =
             cached_chatters_raw.parse(|name| name.split(':').nth(1).map(str::to_owned));
 
-        let helix_users = Helix::fetch_users_by_login(parsed_chatters.dedup().lowercase()).await?;
-        let resolved_chatters: Vec<Chatter> = helix_users.into_iter().map(Chatter::from).collect();
-        chatter_repo.insert_many(&resolved_chatters).await?;
+        let logins = parsed_chatters.dedup().lowercase();
+        let mut migrated = checkpoint.completed_keys(MigrationPhase::Chatters).await?;
+        let unresolved = checkpoint
+            .completed_keys(MigrationPhase::Unresolved)
+            .await?;
+        migrated.extend(unresolved);
+        let (done, pending): (Vec<String>, Vec<String>) = logins
+            .into_iter()
+            .partition(|login| migrated.contains(login));
+
+        tracing::info!(
+            phase = %MigrationPhase::Chatters,
+            skipped = done.len(),
+            pending = pending.len(),
+            "resolving cached chatters"
+        );
+
+        let mut resolved_chatters = checkpoint.resolved_chatters(&done).await?;
+
+        if !pending.is_empty() {
+            checkpoint
+                .begin(MigrationPhase::Chatters, done.len() + pending.len())
+                .await?;
+
+            let helix_users = Helix::fetch_users_by_login(pending.clone()).await?;
+            let fetched: Vec<Chatter> = helix_users.into_iter().map(Chatter::from).collect();
+            chatter_repo.insert_many(&fetched).await?;
+
+            // logins that helix couldn't resolve are still recorded so that a resumed run doesn't
+            // keep retrying them (they end up in the `rejected` map either way), but apart from
+            // the resolved ones so that their scores are merged if they turn up as an alias later
+            let (resolved, unresolved): (Vec<String>, Vec<String>) =
+                pending.into_iter().partition(|login| {
+                    fetched
+                        .iter()
+                        .any(|chatter| chatter.login.eq_ignore_ascii_case(login))
+                });
+            if !unresolved.is_empty() {
+                tracing::warn!(
+                    count = unresolved.len(),
+                    logins = ?unresolved,
+                    "unresolved chatter logins - their scores will only be merged as aliases"
+                );
+            }
+
+            checkpoint
+                .mark_keys(MigrationPhase::Chatters, &resolved)
+                .await?;
+            checkpoint
+                .mark_keys(MigrationPhase::Unresolved, &unresolved)
+                .await?;
+
+            resolved_chatters.extend(fetched);
+        }
+
+        checkpoint.complete(MigrationPhase::Chatters).await?;
 
         Ok((parsed_chatters, resolved_chatters))
     }
@@ -5,6 +5,8 @@ use super::redis::redis_pool::RedisResult;
 use crate::{db::prelude::ChannelId, util::helix::Helix};
 
 pub mod redis_pool;
+pub mod rename;
+pub mod sync;
 
 #[instrument(skip(redis_pool))]
 pub async fn clear_stream_states<R: AsyncCommands + Sync>(redis_pool: &mut R) -> RedisResult<()> {
@@ -1,33 +1,91 @@
-use redis::aio::ConnectionManager;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
 use tracing::instrument;
 
 use crate::util::env::{EnvErr, Var};
 use crate::util::helix::HelixErr;
 use crate::var;
 
+/// Every command fails with `RedisErr::Timeout` after this long, rather than waiting on a
+/// connection that has silently stopped responding.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(3);
+/// Reconnection is retried with exponential backoff, from 100ms up to `RECONNECT_MAX_DELAY`
+const RECONNECT_RETRIES: usize = 6;
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
 static REDIS_POOL: OnceCell<ConnectionManager> = OnceCell::const_new();
+static REDIS_HEALTHY: AtomicBool = AtomicBool::new(true);
 
 /// Retrieves a reference to a `redis::aio::ConnectionManager`.
 ///
 /// As the `redis::aio::ConnectionManager` is an `Arc<_>` under the hood, this reference can be
-/// cloned to produce an owned instance of a Redis connection.
+/// cloned to produce an owned instance of a Redis connection. A dropped connection is
+/// re-established in the background; commands sent in the meantime fail rather than hang.
 pub async fn redis_pool() -> RedisResult<&'static ConnectionManager> {
     REDIS_POOL
         .get_or_try_init(|| async {
             let redis_url = var!(Var::RedisUrl).await?;
             let client = redis::Client::open(redis_url)?;
 
-            Ok(ConnectionManager::new(client).await?)
+            Ok(ConnectionManager::new_with_config(client, manager_config()).await?)
         })
         .await
 }
 
+fn manager_config() -> ConnectionManagerConfig {
+    ConnectionManagerConfig::new()
+        .set_response_timeout(Some(RESPONSE_TIMEOUT))
+        .set_connection_timeout(Some(CONNECTION_TIMEOUT))
+        .set_number_of_retries(RECONNECT_RETRIES)
+        .set_max_delay(RECONNECT_MAX_DELAY)
+}
+
+/// Whether Redis answered the most recent health check.
+pub fn redis_healthy() -> bool {
+    REDIS_HEALTHY.load(Ordering::Relaxed)
+}
+
+/// Spawns the background task that `PING`s Redis, exporting the result as the `redis_up` gauge
+/// and the round trip as the `redis_ping_seconds` histogram.
+///
+/// Failed pings also prompt the connection manager to reconnect, so an idle connection that has
+/// been dropped is replaced before a request needs it.
+pub fn spawn_redis_health_check(redis_pool: ConnectionManager) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let started = Instant::now();
+            let result = redis::cmd("PING")
+                .query_async::<String>(&mut redis_pool.clone())
+                .await
+                .map_err(RedisErr::from);
+            metrics::histogram!("redis_ping_seconds").record(started.elapsed().as_secs_f64());
+
+            let healthy = result.is_ok();
+            metrics::gauge!("redis_up").set(if healthy { 1.0 } else { 0.0 });
+
+            match (REDIS_HEALTHY.swap(healthy, Ordering::Relaxed), result) {
+                (true, Err(e)) => tracing::error!(error = ?e, "redis health check failed"),
+                (false, Ok(_)) => tracing::info!("redis reachable again"),
+                _ => (),
+            }
+        }
+    })
+}
+
 #[macro_export]
 /// Usage:
-/// ```no_run
+/// ```ignore
 /// redis_key!(
 ///     channel | user,
 ///     total | score | leaderboard,
@@ -140,8 +198,16 @@ pub enum RedisErr {
     #[error(transparent)]
     HelixError(#[from] HelixErr),
 
+    /// No response within `RESPONSE_TIMEOUT`
+    #[error("redis command timed out: {0}")]
+    Timeout(redis::RedisError),
+
+    /// The connection was refused or dropped; it's re-established in the background
+    #[error("redis unreachable: {0}")]
+    Unreachable(redis::RedisError),
+
     #[error(transparent)]
-    RedisClientError(#[from] redis::RedisError),
+    RedisClientError(redis::RedisError),
 
     #[error(transparent)]
     ParseError(#[from] redis::ParsingError),
@@ -150,5 +216,17 @@ pub enum RedisErr {
     SqlxError(#[from] sqlx::error::Error),
 }
 
+impl From<redis::RedisError> for RedisErr {
+    fn from(e: redis::RedisError) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        } else if e.is_connection_refusal() || e.is_connection_dropped() || e.is_io_error() {
+            Self::Unreachable(e)
+        } else {
+            Self::RedisClientError(e)
+        }
+    }
+}
+
 unsafe impl Send for RedisErr {}
 unsafe impl Sync for RedisErr {}
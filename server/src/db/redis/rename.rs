@@ -0,0 +1,504 @@
+//! Moves legacy Redis keys from a chatter's old login to their new login.
+//!
+//! The legacy keys are named after logins (see `sync`), so a rename strands a chatter's scores
+//! under their old login. A `LegacyRename` snapshots everything a rename touches, moves it in a
+//! single `MULTI`/`EXEC` pipeline, and can undo the move from the snapshot if a later step of the
+//! rename fails.
+
+use redis::AsyncCommands;
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::db::redis::redis_pool::RedisResult;
+use crate::redis_key;
+
+/// A sorted set member that also appears on the other side of the legacy layout.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MirroredEntry {
+    pub member: String,
+    pub score: i64,
+    /// The renamed login's score in the mirrored set (i.e. the channel's leaderboard for a
+    /// `user:{login}:leaderboard` entry); `None` if it's missing there
+    pub mirrored: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LegacyRename {
+    pub old_login: String,
+    pub new_login: String,
+    /// `user:{old}:leaderboard`, with `#{channel}` members
+    pub user_leaderboard: Vec<MirroredEntry>,
+    pub user_total: Option<i64>,
+    /// `channel:#{old}:leaderboard`, with chatter login members; empty unless the chatter is also
+    /// a channel
+    pub channel_leaderboard: Vec<MirroredEntry>,
+    pub channel_total: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum LegacyOp {
+    IncrBy {
+        key: String,
+        by: i64,
+    },
+    Set {
+        key: String,
+        value: i64,
+    },
+    ZIncrBy {
+        key: String,
+        member: String,
+        by: i64,
+    },
+    ZAdd {
+        key: String,
+        member: String,
+        score: i64,
+    },
+    ZRem {
+        key: String,
+        member: String,
+    },
+    /// Removes members left at or below zero after a compensating decrement
+    ZPrune {
+        key: String,
+    },
+    Del {
+        key: String,
+    },
+}
+
+impl LegacyRename {
+    /// Reads the legacy keys affected by renaming `old_login` to `new_login`.
+    #[instrument(skip(redis_pool))]
+    pub async fn snapshot<R: AsyncCommands + Sync>(
+        redis_pool: &mut R,
+        old_login: &str,
+        new_login: &str,
+        is_channel: bool,
+    ) -> RedisResult<Self> {
+        let old_login = old_login.to_lowercase();
+        let new_login = new_login.to_lowercase();
+        let old_member = format!("#{old_login}");
+
+        let user_scores: Vec<(String, i64)> = redis_pool
+            .zrange_withscores(redis_key!(user, leaderboard, &old_login), 0, -1)
+            .await?;
+        let user_total: Option<i64> = redis_pool.get(redis_key!(user, total, &old_login)).await?;
+
+        let mut pipeline = redis::pipe();
+        for (channel, _) in &user_scores {
+            let channel = channel.trim_start_matches('#');
+            pipeline.zscore(redis_key!(channel, leaderboard, channel), &old_login);
+        }
+        let mirrored: Vec<Option<i64>> = if user_scores.is_empty() {
+            Vec::new()
+        } else {
+            pipeline.query_async(redis_pool).await?
+        };
+        let user_leaderboard = zip_mirrored(user_scores, mirrored);
+
+        let (channel_leaderboard, channel_total) = if is_channel {
+            let channel_scores: Vec<(String, i64)> = redis_pool
+                .zrange_withscores(redis_key!(channel, leaderboard, &old_login), 0, -1)
+                .await?;
+            let channel_total: Option<i64> = redis_pool
+                .get(redis_key!(channel, total, &old_login))
+                .await?;
+
+            let mut pipeline = redis::pipe();
+            for (login, _) in &channel_scores {
+                pipeline.zscore(redis_key!(user, leaderboard, login), &old_member);
+            }
+            let mirrored: Vec<Option<i64>> = if channel_scores.is_empty() {
+                Vec::new()
+            } else {
+                pipeline.query_async(redis_pool).await?
+            };
+
+            (zip_mirrored(channel_scores, mirrored), channel_total)
+        } else {
+            (Vec::new(), None)
+        };
+
+        Ok(Self {
+            old_login,
+            new_login,
+            user_leaderboard,
+            user_total,
+            channel_leaderboard,
+            channel_total,
+        })
+    }
+
+    /// The `(channel, score)` pairs of the old login's leaderboard, as stored in Redis.
+    pub fn user_scores(&self) -> impl Iterator<Item = (&str, i64)> {
+        self.user_leaderboard
+            .iter()
+            .map(|entry| (entry.member.as_str(), entry.score))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.user_leaderboard.is_empty()
+            && self.user_total.is_none()
+            && self.channel_leaderboard.is_empty()
+            && self.channel_total.is_none()
+    }
+
+    /// Moves the old login's keys and members to the new login.
+    #[instrument(skip_all, fields(old_login = self.old_login, new_login = self.new_login))]
+    pub async fn apply<R: AsyncCommands + Sync>(&self, redis_pool: &mut R) -> RedisResult<()> {
+        execute(redis_pool, &self.forward_ops()).await
+    }
+
+    /// Reverts a successful `apply`, restoring the old login's keys from the snapshot.
+    #[instrument(skip_all, fields(old_login = self.old_login, new_login = self.new_login))]
+    pub async fn compensate<R: AsyncCommands + Sync>(&self, redis_pool: &mut R) -> RedisResult<()> {
+        execute(redis_pool, &self.compensating_ops()).await
+    }
+
+    fn rename_login<'a>(&'a self, login: &'a str) -> &'a str {
+        if login == self.old_login {
+            &self.new_login
+        } else {
+            login
+        }
+    }
+
+    /// Maps `#{old}` to `#{new}`, for a chatter that has scores in their own channel.
+    fn rename_channel_member(&self, member: &str) -> String {
+        format!("#{}", self.rename_login(member.trim_start_matches('#')))
+    }
+
+    fn forward_ops(&self) -> Vec<LegacyOp> {
+        let (old, new) = (&self.old_login, &self.new_login);
+        let mut ops = Vec::new();
+
+        for entry in &self.user_leaderboard {
+            ops.push(LegacyOp::ZIncrBy {
+                key: redis_key!(user, leaderboard, new),
+                member: self.rename_channel_member(&entry.member),
+                by: entry.score,
+            });
+
+            // the chatter's own channel is moved as a whole below
+            let channel = entry.member.trim_start_matches('#');
+            if let (false, Some(mirrored)) = (channel == old, entry.mirrored) {
+                let key = redis_key!(channel, leaderboard, channel);
+                ops.push(LegacyOp::ZRem {
+                    key: key.clone(),
+                    member: old.clone(),
+                });
+                ops.push(LegacyOp::ZIncrBy {
+                    key,
+                    member: new.clone(),
+                    by: mirrored,
+                });
+            }
+        }
+
+        if let Some(total) = self.user_total {
+            ops.push(LegacyOp::IncrBy {
+                key: redis_key!(user, total, new),
+                by: total,
+            });
+        }
+
+        ops.push(LegacyOp::Del {
+            key: redis_key!(user, leaderboard, old),
+        });
+        ops.push(LegacyOp::Del {
+            key: redis_key!(user, total, old),
+        });
+
+        if self.channel_leaderboard.is_empty() && self.channel_total.is_none() {
+            return ops;
+        }
+
+        for entry in &self.channel_leaderboard {
+            ops.push(LegacyOp::ZIncrBy {
+                key: redis_key!(channel, leaderboard, new),
+                member: self.rename_login(&entry.member).to_string(),
+                by: entry.score,
+            });
+
+            if let (false, Some(mirrored)) = (&entry.member == old, entry.mirrored) {
+                let key = redis_key!(user, leaderboard, &entry.member);
+                ops.push(LegacyOp::ZRem {
+                    key: key.clone(),
+                    member: format!("#{old}"),
+                });
+                ops.push(LegacyOp::ZIncrBy {
+                    key,
+                    member: format!("#{new}"),
+                    by: mirrored,
+                });
+            }
+        }
+
+        if let Some(total) = self.channel_total {
+            ops.push(LegacyOp::IncrBy {
+                key: redis_key!(channel, total, new),
+                by: total,
+            });
+        }
+
+        ops.push(LegacyOp::Del {
+            key: redis_key!(channel, leaderboard, old),
+        });
+        ops.push(LegacyOp::Del {
+            key: redis_key!(channel, total, old),
+        });
+
+        ops
+    }
+
+    fn compensating_ops(&self) -> Vec<LegacyOp> {
+        let (old, new) = (&self.old_login, &self.new_login);
+        let mut ops = Vec::new();
+
+        for entry in &self.user_leaderboard {
+            let new_key = redis_key!(user, leaderboard, new);
+            ops.push(LegacyOp::ZIncrBy {
+                key: new_key.clone(),
+                member: self.rename_channel_member(&entry.member),
+                by: -entry.score,
+            });
+            ops.push(LegacyOp::ZPrune { key: new_key });
+            ops.push(LegacyOp::ZAdd {
+                key: redis_key!(user, leaderboard, old),
+                member: entry.member.clone(),
+                score: entry.score,
+            });
+
+            let channel = entry.member.trim_start_matches('#');
+            if let (false, Some(mirrored)) = (channel == old, entry.mirrored) {
+                let key = redis_key!(channel, leaderboard, channel);
+                ops.push(LegacyOp::ZIncrBy {
+                    key: key.clone(),
+                    member: new.clone(),
+                    by: -mirrored,
+                });
+                ops.push(LegacyOp::ZPrune { key: key.clone() });
+                ops.push(LegacyOp::ZAdd {
+                    key,
+                    member: old.clone(),
+                    score: mirrored,
+                });
+            }
+        }
+
+        if let Some(total) = self.user_total {
+            ops.push(LegacyOp::IncrBy {
+                key: redis_key!(user, total, new),
+                by: -total,
+            });
+            ops.push(LegacyOp::Set {
+                key: redis_key!(user, total, old),
+                value: total,
+            });
+        }
+
+        for entry in &self.channel_leaderboard {
+            let new_key = redis_key!(channel, leaderboard, new);
+            ops.push(LegacyOp::ZIncrBy {
+                key: new_key.clone(),
+                member: self.rename_login(&entry.member).to_string(),
+                by: -entry.score,
+            });
+            ops.push(LegacyOp::ZPrune { key: new_key });
+            ops.push(LegacyOp::ZAdd {
+                key: redis_key!(channel, leaderboard, old),
+                member: entry.member.clone(),
+                score: entry.score,
+            });
+
+            if let (false, Some(mirrored)) = (&entry.member == old, entry.mirrored) {
+                let key = redis_key!(user, leaderboard, &entry.member);
+                ops.push(LegacyOp::ZIncrBy {
+                    key: key.clone(),
+                    member: format!("#{new}"),
+                    by: -mirrored,
+                });
+                ops.push(LegacyOp::ZPrune { key: key.clone() });
+                ops.push(LegacyOp::ZAdd {
+                    key,
+                    member: format!("#{old}"),
+                    score: mirrored,
+                });
+            }
+        }
+
+        if let Some(total) = self.channel_total {
+            ops.push(LegacyOp::IncrBy {
+                key: redis_key!(channel, total, new),
+                by: -total,
+            });
+            ops.push(LegacyOp::Set {
+                key: redis_key!(channel, total, old),
+                value: total,
+            });
+        }
+
+        ops
+    }
+}
+
+fn zip_mirrored(scores: Vec<(String, i64)>, mirrored: Vec<Option<i64>>) -> Vec<MirroredEntry> {
+    scores
+        .into_iter()
+        .zip(mirrored)
+        .map(|((member, score), mirrored)| MirroredEntry {
+            member,
+            score,
+            mirrored,
+        })
+        .collect()
+}
+
+async fn execute<R: AsyncCommands + Sync>(redis_pool: &mut R, ops: &[LegacyOp]) -> RedisResult<()> {
+    let mut pipeline = redis::pipe();
+    pipeline.atomic();
+
+    for op in ops {
+        match op {
+            LegacyOp::IncrBy { key, by } => pipeline.incr(key, *by).ignore(),
+            LegacyOp::Set { key, value } => pipeline.set(key, *value).ignore(),
+            LegacyOp::ZIncrBy { key, member, by } => pipeline.zincr(key, member, *by).ignore(),
+            LegacyOp::ZAdd { key, member, score } => pipeline.zadd(key, member, *score).ignore(),
+            LegacyOp::ZRem { key, member } => pipeline.zrem(key, member).ignore(),
+            LegacyOp::ZPrune { key } => pipeline.zrembyscore(key, "-inf", 0).ignore(),
+            LegacyOp::Del { key } => pipeline.del(key).ignore(),
+        };
+    }
+
+    let _: () = pipeline.query_async(redis_pool).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Value {
+        Int(i64),
+        ZSet(BTreeMap<String, i64>),
+    }
+
+    /// Applies ops to an in-memory keyspace with the same semantics as the Redis commands.
+    fn run(store: &mut HashMap<String, Value>, ops: &[LegacyOp]) {
+        fn zset<'a>(
+            store: &'a mut HashMap<String, Value>,
+            key: &str,
+        ) -> &'a mut BTreeMap<String, i64> {
+            match store
+                .entry(key.to_string())
+                .or_insert_with(|| Value::ZSet(BTreeMap::new()))
+            {
+                Value::ZSet(set) => set,
+                Value::Int(_) => panic!("{key} is not a sorted set"),
+            }
+        }
+
+        for op in ops {
+            match op {
+                LegacyOp::IncrBy { key, by } => {
+                    match store.entry(key.clone()).or_insert(Value::Int(0)) {
+                        Value::Int(val) => *val += by,
+                        Value::ZSet(_) => panic!("{key} is not an integer"),
+                    }
+                }
+                LegacyOp::Set { key, value } => {
+                    store.insert(key.clone(), Value::Int(*value));
+                }
+                LegacyOp::ZIncrBy { key, member, by } => {
+                    *zset(store, key).entry(member.clone()).or_default() += by;
+                }
+                LegacyOp::ZAdd { key, member, score } => {
+                    zset(store, key).insert(member.clone(), *score);
+                }
+                LegacyOp::ZRem { key, member } => {
+                    zset(store, key).remove(member);
+                }
+                LegacyOp::ZPrune { key } => zset(store, key).retain(|_, score| *score > 0),
+                LegacyOp::Del { key } => {
+                    store.remove(key);
+                }
+            }
+
+            // redis drops empty sorted sets
+            store.retain(|_, val| !matches!(val, Value::ZSet(set) if set.is_empty()));
+        }
+    }
+
+    fn zset(entries: &[(&str, i64)]) -> Value {
+        Value::ZSet(entries.iter().map(|(m, s)| (m.to_string(), *s)).collect())
+    }
+
+    fn entry(member: &str, score: i64, mirrored: Option<i64>) -> MirroredEntry {
+        MirroredEntry {
+            member: member.to_string(),
+            score,
+            mirrored,
+        }
+    }
+
+    #[test]
+    fn rename_moves_keys_and_compensation_restores_them() {
+        // `old` is also a channel, and has chatted in their own channel
+        let original: HashMap<String, Value> = [
+            ("user:old:total", Value::Int(7)),
+            ("user:old:leaderboard", zset(&[("#a", 5), ("#old", 2)])),
+            ("channel:#a:leaderboard", zset(&[("old", 5), ("x", 1)])),
+            ("channel:#old:total", Value::Int(5)),
+            ("channel:#old:leaderboard", zset(&[("old", 2), ("x", 3)])),
+            ("user:x:leaderboard", zset(&[("#a", 1), ("#old", 3)])),
+            ("user:new:total", Value::Int(1)),
+            ("user:new:leaderboard", zset(&[("#a", 1)])),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let rename = LegacyRename {
+            old_login: String::from("old"),
+            new_login: String::from("new"),
+            user_leaderboard: vec![entry("#a", 5, Some(5)), entry("#old", 2, Some(2))],
+            user_total: Some(7),
+            channel_leaderboard: vec![entry("old", 2, Some(2)), entry("x", 3, Some(3))],
+            channel_total: Some(5),
+        };
+
+        let mut store = original.clone();
+        run(&mut store, &rename.forward_ops());
+
+        assert!(
+            store
+                .keys()
+                .all(|key| !key.contains(":old:") && !key.contains("#old:"))
+        );
+        assert_eq!(store["user:new:total"], Value::Int(8));
+        assert_eq!(
+            store["user:new:leaderboard"],
+            zset(&[("#a", 6), ("#new", 2)])
+        );
+        assert_eq!(
+            store["channel:#a:leaderboard"],
+            zset(&[("new", 5), ("x", 1)])
+        );
+        assert_eq!(store["channel:#new:total"], Value::Int(5));
+        assert_eq!(
+            store["channel:#new:leaderboard"],
+            zset(&[("new", 2), ("x", 3)])
+        );
+        assert_eq!(store["user:x:leaderboard"], zset(&[("#a", 1), ("#new", 3)]));
+
+        run(&mut store, &rename.compensating_ops());
+        store.retain(|key, val| !(key == "channel:#new:total" && *val == Value::Int(0)));
+
+        assert_eq!(store, original);
+    }
+}
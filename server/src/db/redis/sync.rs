@@ -0,0 +1,229 @@
+//! Dual-write support for the Redis -> Postgres migration window.
+//!
+//! While the `score_dual_write` feature flag is on (see `util::flags`), each score increment
+//! recorded in Postgres is mirrored into the legacy Redis keys so that either store can be read
+//! from while the migration is in progress. The flag can be rolled out to some channels at a time;
+//! the reconciliation job only compares chatter and channel totals between the two stores (and
+//! reports any drift) while it's on for every channel, as totals are bound to drift otherwise.
+
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::redis::redis_pool::RedisResult;
+use crate::redis_key;
+use crate::util::flags::{Flag, flags};
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60 * 10);
+const RECONCILE_CHUNK_SIZE: usize = 500;
+
+/// Returns true if score increments are written to the legacy Redis keys for any channel.
+pub fn dual_write_enabled() -> bool {
+    flags().is_on(Flag::ScoreDualWrite)
+}
+
+/// Increments the legacy Redis keys for a single score event.
+///
+/// Mirrors the layout used by the old counter:
+///
+/// - `user:{login}:total` and `channel:#{channel}:total` hold running totals
+/// - `user:{login}:leaderboard` is a sorted set of `#{channel}` members
+/// - `channel:#{channel}:leaderboard` is a sorted set of chatter login members
+///
+/// Returns the channel's total and the chatter's score in the channel after the increment.
+#[instrument(skip(redis_pool))]
+pub async fn increment_legacy_score<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    login: &str,
+    channel: &str,
+) -> RedisResult<MilestoneTotals> {
+    let login = login.to_lowercase();
+    let channel = channel.to_lowercase();
+
+    let mut pipeline = redis::pipe();
+    pipeline
+        .atomic()
+        .incr(redis_key!(user, total, &login), 1)
+        .incr(redis_key!(channel, total, &channel), 1)
+        .zincr(
+            redis_key!(user, leaderboard, &login),
+            format!("#{channel}"),
+            1,
+        )
+        .zincr(redis_key!(channel, leaderboard, &channel), &login, 1);
+
+    let (_, channel_total, _, chatter_score): (i64, i64, f64, f64) =
+        pipeline.query_async(redis_pool).await?;
+
+    Ok(MilestoneTotals {
+        channel_total,
+        chatter_score: chatter_score as i64,
+    })
+}
+
+/// Removes a chatter's legacy Redis keys and their entries in each channel's leaderboard, taking
+/// their scores off the channel totals (as purging their score events does in Postgres).
+#[instrument(skip(redis_pool))]
+pub async fn remove_legacy_chatter<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    login: &str,
+) -> RedisResult<()> {
+    let login = login.to_lowercase();
+    let user_scores: Vec<(String, i64)> = redis_pool
+        .zrange_withscores(redis_key!(user, leaderboard, &login), 0, -1)
+        .await?;
+
+    let mut pipeline = redis::pipe();
+    pipeline.atomic();
+    for (channel, score) in &user_scores {
+        let channel = channel.trim_start_matches('#');
+        pipeline
+            .zrem(redis_key!(channel, leaderboard, channel), &login)
+            .ignore()
+            .decr(redis_key!(channel, total, channel), *score)
+            .ignore();
+    }
+
+    pipeline
+        .del(redis_key!(user, leaderboard, &login))
+        .ignore()
+        .del(redis_key!(user, total, &login))
+        .ignore();
+
+    let _: () = pipeline.query_async(redis_pool).await?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Drift {
+    pub key: String,
+    pub postgres: i64,
+    /// `None` if the key does not exist in Redis
+    pub redis: Option<i64>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DriftReport {
+    pub checked: usize,
+    pub drifted: Vec<Drift>,
+}
+
+/// Compares chatter and channel totals in Postgres against the legacy Redis totals.
+#[instrument(skip(redis_pool, pool))]
+pub async fn reconcile<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    pool: &Pool<Postgres>,
+) -> RedisResult<DriftReport> {
+    let chatters: Vec<(String, i64)> = sqlx::query_as("SELECT login, total FROM chatter")
+        .fetch_all(pool)
+        .await?;
+
+    let channels: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT ch.login, c.channel_total
+        FROM channel c
+        JOIN chatter ch ON ch.id = c.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let totals: Vec<(String, i64)> = chatters
+        .into_iter()
+        .map(|(login, total)| (redis_key!(user, total, &login), total))
+        .chain(
+            channels
+                .into_iter()
+                .map(|(login, total)| (redis_key!(channel, total, &login), total)),
+        )
+        .collect();
+
+    let mut report = DriftReport::default();
+    for chunk in totals.chunks(RECONCILE_CHUNK_SIZE) {
+        let keys: Vec<&str> = chunk.iter().map(|(key, _)| key.as_str()).collect();
+        let cached: Vec<Option<i64>> = redis::cmd("MGET")
+            .arg(&keys)
+            .query_async(redis_pool)
+            .await?;
+
+        report.checked += chunk.len();
+        report.drifted.extend(find_drift(chunk, &cached));
+    }
+
+    Ok(report)
+}
+
+fn find_drift(totals: &[(String, i64)], cached: &[Option<i64>]) -> Vec<Drift> {
+    totals
+        .iter()
+        .zip(cached)
+        .filter(|((_, postgres), redis)| **redis != Some(*postgres))
+        .map(|((key, postgres), redis)| Drift {
+            key: key.to_owned(),
+            postgres: *postgres,
+            redis: *redis,
+        })
+        .collect()
+}
+
+/// Spawns the background reconciliation job; it only reconciles while dual-writes are on for
+/// every channel.
+pub fn spawn_reconciliation<R>(mut redis_pool: R, pool: &'static Pool<Postgres>) -> JoinHandle<()>
+where
+    R: AsyncCommands + Sync + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RECONCILE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if !flags().is_fully_on(Flag::ScoreDualWrite) {
+                tracing::debug!("score dual-write not fully rolled out - skipping reconciliation");
+                continue;
+            }
+
+            match reconcile(&mut redis_pool, pool).await {
+                Ok(report) if report.drifted.is_empty() => {
+                    tracing::info!(checked = report.checked, "no score drift detected");
+                }
+                Ok(report) => {
+                    tracing::warn!(
+                        checked = report.checked,
+                        drifted = report.drifted.len(),
+                        sample = ?report.drifted.iter().take(10).collect::<Vec<_>>(),
+                        "score drift detected between redis and postgres"
+                    );
+                }
+                Err(e) => {
+                    tracing::error!(error = ?e, "score reconciliation failure");
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_drift_reports_mismatched_and_missing_keys() {
+        let totals = vec![
+            (String::from("user:a:total"), 10),
+            (String::from("user:b:total"), 5),
+            (String::from("channel:#c:total"), 3),
+        ];
+        let cached = vec![Some(10), Some(4), None];
+
+        let drift = find_drift(&totals, &cached);
+        assert_eq!(drift.len(), 2);
+        assert_eq!(drift[0].key, "user:b:total");
+        assert_eq!(drift[0].redis, Some(4));
+        assert_eq!(drift[1].key, "channel:#c:total");
+        assert_eq!(drift[1].redis, None);
+    }
+}
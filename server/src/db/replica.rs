@@ -0,0 +1,277 @@
+//! Optional read replica support.
+//!
+//! Replicas are configured via `DATABASE_REPLICA_URLS`; reads are round-robined across those
+//! replicas that are reachable and within `REPLICA_MAX_LAG_SECS` of the primary, falling back to
+//! the primary when none are available.
+
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{PgPool, Pool, Postgres};
+use tokio::sync::OnceCell;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::{PgResult, db_pool};
+use crate::util::env::Var;
+use crate::var;
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug)]
+pub struct Replica {
+    /// Host/port/database of the replica - credentials are never stored here
+    pub label: String,
+    pool: PgPool,
+    healthy: AtomicBool,
+    lag_ms: AtomicI64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicaStatus {
+    pub label: String,
+    pub healthy: bool,
+    pub lag_ms: i64,
+    pub connections: u32,
+    pub idle_connections: usize,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ReplicaProbe {
+    in_recovery: bool,
+    /// Null if the replica isn't streaming WAL from the primary
+    caught_up: Option<bool>,
+    /// Null until the replica has replayed a transaction
+    replay_lag_secs: Option<f64>,
+}
+
+impl ReplicaProbe {
+    /// How far behind the primary the replica is, or why it can't be used.
+    ///
+    /// The last replayed transaction only tells us how stale the replica is while it has WAL left
+    /// to replay; once everything it has received is replayed, it's as current as the primary
+    /// (however long ago that transaction was).
+    fn lag_ms(&self) -> Result<i64, &'static str> {
+        if !self.in_recovery {
+            return Err("not in recovery - the server isn't a replica (or has been promoted)");
+        }
+
+        if self.caught_up == Some(true) {
+            return Ok(0);
+        }
+
+        match self.replay_lag_secs {
+            Some(secs) => Ok((secs.max(0.0) * 1000.0) as i64),
+            None => Err("no transactions replayed yet"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReplicaSet {
+    primary: &'static Pool<Postgres>,
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+    max_lag: Duration,
+}
+
+static REPLICA_SET: OnceCell<ReplicaSet> = OnceCell::const_new();
+pub async fn replica_set() -> PgResult<&'static ReplicaSet> {
+    REPLICA_SET
+        .get_or_try_init(|| async {
+            let primary = db_pool().await?;
+            let urls = var!(Var::DatabaseReplicaUrls).await?;
+            let max_lag = var!(Var::ReplicaMaxLagSecs)
+                .await?
+                .parse::<u64>()
+                .unwrap_or(10);
+
+            ReplicaSet::new(primary, urls, Duration::from_secs(max_lag))
+        })
+        .await
+}
+
+impl ReplicaSet {
+    pub fn new(primary: &'static Pool<Postgres>, urls: &str, max_lag: Duration) -> PgResult<Self> {
+        let mut replicas = Vec::new();
+        for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+            let options: PgConnectOptions = url.parse()?;
+            let label = format!(
+                "{}:{}/{}",
+                options.get_host(),
+                options.get_port(),
+                options.get_database().unwrap_or_default()
+            );
+
+            tracing::info!(replica = label, "registering read replica");
+
+            // lazily connect so that an unreachable replica doesn't prevent startup; the health
+            // check will mark it as unhealthy on its first pass
+            let pool = PgPoolOptions::new()
+                .acquire_timeout(Duration::from_secs(5))
+                .connect_lazy_with(options);
+
+            replicas.push(Replica {
+                label,
+                pool,
+                healthy: AtomicBool::new(false),
+                lag_ms: AtomicI64::new(0),
+            });
+        }
+
+        Ok(Self {
+            primary,
+            replicas,
+            next: AtomicUsize::new(0),
+            max_lag,
+        })
+    }
+
+    /// Returns a pool suitable for read-only queries.
+    ///
+    /// Healthy replicas are selected round-robin; if no replica is healthy (or none are
+    /// configured), the primary is returned instead.
+    pub fn reader(&'static self) -> &'static Pool<Postgres> {
+        let count = self.replicas.len();
+        if count == 0 {
+            return self.primary;
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..count {
+            let replica = &self.replicas[(start + offset) % count];
+            if replica.healthy.load(Ordering::Relaxed) {
+                return &replica.pool;
+            }
+        }
+
+        tracing::debug!("no healthy read replicas - falling back to primary");
+        self.primary
+    }
+
+    #[instrument(skip(self))]
+    pub async fn check_health(&self) {
+        for replica in &self.replicas {
+            let probe = sqlx::query_as::<_, ReplicaProbe>(
+                r#"
+                SELECT
+                    pg_is_in_recovery() AS in_recovery,
+                    pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() AS caught_up,
+                    EXTRACT(EPOCH FROM (NOW() - pg_last_xact_replay_timestamp()))::float8
+                        AS replay_lag_secs
+                "#,
+            )
+            .fetch_one(&replica.pool)
+            .await;
+
+            let healthy = match probe.map(|probe| probe.lag_ms()) {
+                Ok(Ok(lag_ms)) => {
+                    replica.lag_ms.store(lag_ms, Ordering::Relaxed);
+
+                    if lag_ms > self.max_lag.as_millis() as i64 {
+                        tracing::warn!(
+                            replica = replica.label,
+                            lag_ms,
+                            "replica lag exceeds threshold"
+                        );
+                        false
+                    } else {
+                        true
+                    }
+                }
+                Ok(Err(reason)) => {
+                    tracing::warn!(replica = replica.label, reason, "replica is unusable");
+                    false
+                }
+                Err(e) => {
+                    tracing::error!(error = ?e, replica = replica.label, "replica health check failure");
+                    false
+                }
+            };
+
+            if replica.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                tracing::warn!(replica = replica.label, healthy, "replica health changed");
+            }
+        }
+    }
+
+    pub fn status(&self) -> Vec<ReplicaStatus> {
+        self.replicas
+            .iter()
+            .map(|replica| ReplicaStatus {
+                label: replica.label.clone(),
+                healthy: replica.healthy.load(Ordering::Relaxed),
+                lag_ms: replica.lag_ms.load(Ordering::Relaxed),
+                connections: replica.pool.size(),
+                idle_connections: replica.pool.num_idle(),
+            })
+            .collect()
+    }
+
+    /// Spawns the background task that periodically re-evaluates replica health.
+    pub fn spawn_health_check(&'static self) -> Option<JoinHandle<()>> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.check_health().await;
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leak_set(urls: &str) -> &'static ReplicaSet {
+        let primary: &'static PgPool = Box::leak(Box::new(
+            PgPool::connect_lazy("postgres://primary/db").unwrap(),
+        ));
+
+        Box::leak(Box::new(
+            ReplicaSet::new(primary, urls, Duration::from_secs(10)).unwrap(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn reader_uses_primary_without_replicas() {
+        let set = leak_set("");
+        assert!(std::ptr::eq(set.reader(), set.primary));
+    }
+
+    #[tokio::test]
+    async fn reader_skips_unhealthy_replicas() {
+        let set = leak_set("postgres://replica-a/db, postgres://replica-b/db");
+        assert!(std::ptr::eq(set.reader(), set.primary));
+
+        set.replicas[1].healthy.store(true, Ordering::Relaxed);
+        for _ in 0..4 {
+            assert!(std::ptr::eq(set.reader(), &set.replicas[1].pool));
+        }
+
+        assert_eq!(set.status()[0].label, "replica-a:5432/db");
+    }
+
+    #[test]
+    fn replica_lag_from_probe() {
+        let probe = |in_recovery, caught_up, replay_lag_secs| ReplicaProbe {
+            in_recovery,
+            caught_up,
+            replay_lag_secs,
+        };
+
+        // an idle primary leaves the last replayed transaction arbitrarily old
+        assert_eq!(probe(true, Some(true), Some(3600.0)).lag_ms(), Ok(0));
+        assert_eq!(probe(true, Some(false), Some(2.5)).lag_ms(), Ok(2500));
+        assert_eq!(probe(true, None, Some(1.0)).lag_ms(), Ok(1000));
+        assert!(probe(true, Some(false), None).lag_ms().is_err());
+        assert!(probe(false, None, None).lag_ms().is_err());
+    }
+}
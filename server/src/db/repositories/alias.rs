@@ -0,0 +1,149 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult, Transaction};
+use tracing::instrument;
+
+use crate::db::models::alias::Alias;
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+
+pub struct AliasRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl AliasRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn insert(&self, chatter_id: &ChatterId, login: &str) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO alias (chatter_id, login, detected_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (chatter_id, login)
+            DO NOTHING
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(login.to_lowercase())
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves aliases that have not yet had their legacy scores merged, optionally limited to
+    /// a set of chatters.
+    #[instrument(skip(self, chatter_ids))]
+    pub async fn get_pending(&self, chatter_ids: Option<&[ChatterId]>) -> SqlxResult<Vec<Alias>> {
+        let ids = chatter_ids.map(|ids| ids.iter().map(|id| id.0.clone()).collect::<Vec<_>>());
+
+        sqlx::query_as::<_, Alias>(
+            r#"
+            SELECT
+                chatter_id,
+                login,
+                detected_at,
+                merged_at,
+                merged_score
+            FROM alias
+            WHERE merged_at IS NULL
+            AND ($1::text[] IS NULL OR chatter_id = ANY($1))
+            ORDER BY detected_at ASC
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Returns true if the login's legacy data was already attributed to a chatter by the initial
+    /// Redis migration (i.e. the rename happened after the migration ran). Logins that Helix
+    /// couldn't resolve during the migration are checkpointed separately, so their scores are
+    /// still merged.
+    #[instrument(skip(self))]
+    pub async fn migrated_with_chatter(&self, login: &str) -> SqlxResult<bool> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM migration_progress
+                WHERE phase = 'chatters'
+                AND LOWER(key) = LOWER($1)
+            )
+            "#,
+        )
+        .bind(login)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Resolves a channel's id from a (possibly historic) login.
+    #[instrument(skip(self))]
+    pub async fn resolve_channel(&self, login: &str) -> SqlxResult<Option<ChannelId>> {
+        sqlx::query_scalar::<_, ChannelId>(
+            r#"
+            SELECT c.id
+            FROM channel c
+            JOIN chatter ch ON ch.id = c.id
+            WHERE ch.login = $1
+            OR c.id IN (SELECT chatter_id FROM alias WHERE login = $1)
+            LIMIT 1
+            "#,
+        )
+        .bind(login.to_lowercase())
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Locks an alias until the transaction ends, returning false if it has already been merged.
+    #[instrument(skip(tx))]
+    pub async fn lock_pending_in_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        chatter_id: &ChatterId,
+        login: &str,
+    ) -> SqlxResult<bool> {
+        let pending = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT 1
+            FROM alias
+            WHERE chatter_id = $1
+            AND login = $2
+            AND merged_at IS NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(login)
+        .fetch_optional(tx.as_mut())
+        .await?;
+
+        Ok(pending.is_some())
+    }
+
+    /// Returns false if the alias had already been merged, in which case nothing is updated.
+    #[instrument(skip(tx))]
+    pub async fn mark_merged_in_tx(
+        tx: &mut Transaction<'_, Postgres>,
+        chatter_id: &ChatterId,
+        login: &str,
+        merged_score: i64,
+    ) -> SqlxResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE alias
+            SET merged_at = NOW(),
+                merged_score = $3
+            WHERE chatter_id = $1
+            AND login = $2
+            AND merged_at IS NULL
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(login)
+        .bind(merged_score)
+        .execute(tx.as_mut())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,218 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::{Pool, Postgres, Result as SqlxResult, Transaction};
+use tracing::instrument;
+
+use crate::db::models::archive::{ArchivedDay, ArchivedScoreEvent};
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::profile::DailyScore;
+
+/// Held for the length of a day's archival, so that only one instance archives at a time
+const ARCHIVE_LOCK: &str = "SELECT pg_try_advisory_xact_lock(hashtext('score_event_archive'))";
+
+pub struct ArchiveRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl ArchiveRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Retrieves when the oldest score event earned before `cutoff` was earned, if there is one.
+    #[instrument(skip(self))]
+    pub async fn get_oldest_before(
+        &self,
+        cutoff: NaiveDateTime,
+    ) -> SqlxResult<Option<NaiveDateTime>> {
+        sqlx::query_scalar::<_, Option<NaiveDateTime>>(
+            "SELECT MIN(earned_at) FROM score_event WHERE earned_at < $1",
+        )
+        .bind(cutoff)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Starts archiving a day's events. Returns `None` if another instance is already archiving.
+    #[instrument(skip(self))]
+    pub async fn begin(&self, day: NaiveDate) -> SqlxResult<Option<DayArchive>> {
+        let mut tx = self.pool.begin().await?;
+        if !sqlx::query_scalar::<_, bool>(ARCHIVE_LOCK)
+            .fetch_one(&mut *tx)
+            .await?
+        {
+            tx.rollback().await?;
+            return Ok(None);
+        }
+
+        Ok(Some(DayArchive { tx, day }))
+    }
+
+    /// Retrieves the archived days between `from` and `to` (inclusive), oldest first.
+    #[instrument(skip(self))]
+    pub async fn get_archived_days(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> SqlxResult<Vec<ArchivedDay>> {
+        sqlx::query_as::<_, ArchivedDay>(
+            r#"
+            SELECT day, object_key, events, archived_at
+            FROM score_event_archive
+            WHERE day BETWEEN $1 AND $2
+            ORDER BY day
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves the scores still in `score_event` for each day between `from` and `to`
+    /// (inclusive), for days with any. Scores are optionally limited to a channel and/or chatter.
+    #[instrument(skip(self))]
+    pub async fn get_live_daily(
+        &self,
+        channel_id: Option<&ChannelId>,
+        chatter_id: Option<&ChatterId>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> SqlxResult<Vec<DailyScore>> {
+        sqlx::query_as::<_, DailyScore>(
+            r#"
+            SELECT date_trunc('day', e.earned_at)::date AS day, COUNT(*) AS total
+            FROM score_event e
+            JOIN chatter ch ON ch.id = e.chatter_id
+            WHERE ($1::varchar IS NULL OR e.channel_id = $1)
+            AND ($2::varchar IS NULL OR e.chatter_id = $2)
+            AND e.earned_at >= $3
+            AND e.earned_at < $4 + INTERVAL '1 day'
+            AND ch.deleted_at IS NULL
+            GROUP BY day
+            ORDER BY day
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves which of the chatters exist and aren't hidden.
+    #[instrument(skip(self, chatter_ids), fields(chatters = chatter_ids.len()))]
+    pub async fn get_visible_chatters(&self, chatter_ids: &[String]) -> SqlxResult<Vec<ChatterId>> {
+        sqlx::query_scalar::<_, ChatterId>(
+            "SELECT id FROM chatter WHERE id = ANY($1) AND deleted_at IS NULL",
+        )
+        .bind(chatter_ids)
+        .fetch_all(self.pool)
+        .await
+    }
+}
+
+/// A day of score events being archived, along with the transaction (and lock) it's archived in.
+/// Dropping it without committing leaves the day as it was.
+pub struct DayArchive {
+    tx: Transaction<'static, Postgres>,
+    pub day: NaiveDate,
+}
+
+impl DayArchive {
+    /// Retrieves the day's existing archive, if it was archived before.
+    #[instrument(skip(self), fields(day = %self.day))]
+    pub async fn get_archived(&mut self) -> SqlxResult<Option<ArchivedDay>> {
+        sqlx::query_as::<_, ArchivedDay>(
+            r#"
+            SELECT day, object_key, events, archived_at
+            FROM score_event_archive
+            WHERE day = $1
+            "#,
+        )
+        .bind(self.day)
+        .fetch_optional(&mut *self.tx)
+        .await
+    }
+
+    /// Retrieves (and locks) the day's events that are still in `score_event`, in the order they
+    /// were earned.
+    #[instrument(skip(self), fields(day = %self.day))]
+    pub async fn get_events(&mut self) -> SqlxResult<Vec<ArchivedScoreEvent>> {
+        sqlx::query_as::<_, ArchivedScoreEvent>(
+            r#"
+            SELECT id, chatter_id, channel_id, keyword_id, earned_at, msg_id, flagged_at
+            FROM score_event
+            WHERE earned_at >= $1
+            AND earned_at < $1 + INTERVAL '1 day'
+            ORDER BY earned_at, id
+            FOR UPDATE
+            "#,
+        )
+        .bind(self.day)
+        .fetch_all(&mut *self.tx)
+        .await
+    }
+
+    /// Moves the archived events out of `score_event` into their hourly rollups, and records the
+    /// day's archive. Returns the number of events moved.
+    ///
+    /// Only `event_ids` are moved, so events written for the day while it was being archived are
+    /// left for next time. Their totals are left as they are, as the rollups keep them counted.
+    #[instrument(skip(self, event_ids), fields(day = %self.day, events = event_ids.len()))]
+    pub async fn commit(
+        mut self,
+        event_ids: &[i32],
+        object_key: &str,
+        archived_events: i64,
+    ) -> SqlxResult<u64> {
+        // see `decrement_score_totals`
+        sqlx::query("SELECT set_config('pea_fan.archiving', 'on', true)")
+            .execute(&mut *self.tx)
+            .await?;
+
+        let moved = sqlx::query_scalar::<_, i64>(
+            r#"
+            WITH moved AS (
+                DELETE FROM score_event
+                WHERE id = ANY($1)
+                RETURNING channel_id, chatter_id, earned_at
+            ),
+            rolled_up AS (
+                INSERT INTO score_event_rollup (channel_id, chatter_id, hour, total)
+                SELECT channel_id, chatter_id, date_trunc('hour', earned_at), COUNT(*)
+                FROM moved
+                GROUP BY 1, 2, 3
+                ON CONFLICT (channel_id, chatter_id, hour)
+                DO UPDATE SET
+                    total = score_event_rollup.total + EXCLUDED.total
+            )
+            SELECT COUNT(*) FROM moved
+            "#,
+        )
+        .bind(event_ids)
+        .fetch_one(&mut *self.tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO score_event_archive (day, object_key, events)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (day)
+            DO UPDATE SET
+                object_key = EXCLUDED.object_key,
+                events = EXCLUDED.events,
+                archived_at = NOW()
+            "#,
+        )
+        .bind(self.day)
+        .bind(object_key)
+        .bind(archived_events)
+        .execute(&mut *self.tx)
+        .await?;
+
+        self.tx.commit().await?;
+        Ok(moved as u64)
+    }
+}
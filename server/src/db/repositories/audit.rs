@@ -0,0 +1,109 @@
+use serde_json::Value;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::audit::{AuditAction, AuditActor, AuditEntry, AuditFilter};
+
+/// Matches `AuditFilter`'s fields, bound as `$1` to `$6`.
+const AUDIT_MATCH: &str = r#"
+    ($1::varchar IS NULL OR action = $1)
+    AND ($2::int4 IS NULL OR session_id = $2)
+    AND ($3::varchar IS NULL OR token_id = $3)
+    AND ($4::varchar IS NULL OR target = $4)
+    AND ($5::timestamp IS NULL OR created_at >= $5)
+    AND ($6::timestamp IS NULL OR created_at < $6)
+"#;
+
+pub struct AuditRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl AuditRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self, before, after))]
+    pub async fn record(
+        &self,
+        actor: AuditActor<'_>,
+        action: AuditAction,
+        target: Option<&str>,
+        before: Option<&Value>,
+        after: Option<&Value>,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (session_id, token_id, chatter_id, action, target, before, after)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(actor.session_id)
+        .bind(actor.token_id)
+        .bind(actor.chatter_id)
+        .bind(action.as_str())
+        .bind(target)
+        .bind(before)
+        .bind(after)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves entries matching `filter`, newest first.
+    #[instrument(skip(self))]
+    pub async fn get_page(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+        offset: i64,
+    ) -> SqlxResult<PaginatedResponse<AuditEntry>> {
+        let action = filter.action.map(|a| a.as_str());
+
+        let items = sqlx::query_as::<_, AuditEntry>(&format!(
+            r#"
+            SELECT id, session_id, token_id, chatter_id, action, target, before, after, created_at
+            FROM audit_log
+            WHERE {AUDIT_MATCH}
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            OFFSET $8
+            "#
+        ))
+        .bind(action)
+        .bind(filter.session_id)
+        .bind(&filter.token_id)
+        .bind(&filter.target)
+        .bind(filter.since)
+        .bind(filter.until)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool)
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM audit_log
+            WHERE {AUDIT_MATCH}
+            "#
+        ))
+        .bind(action)
+        .bind(filter.session_id)
+        .bind(&filter.token_id)
+        .bind(&filter.target)
+        .bind(filter.since)
+        .bind(filter.until)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(PaginatedResponse::new(
+            items,
+            total,
+            limit,
+            offset / limit + 1,
+        ))
+    }
+}
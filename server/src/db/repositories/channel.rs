@@ -3,7 +3,9 @@ use tracing::instrument;
 
 use super::sql_fragment;
 use crate::db::PgError;
-use crate::db::models::channel::{Channel, ChannelId, ChannelReplies};
+use crate::db::models::channel::{
+    Channel, ChannelCountConfig, ChannelId, ChannelReplies, ReplyMode,
+};
 use crate::db::prelude::Tx;
 use crate::db::repositories::Repository;
 
@@ -152,17 +154,18 @@ impl ChannelRepository {
         Ok(())
     }
 
+    /// Toggles a channel's replies between `ReplyMode::Silent` and `ReplyMode::Reply`.
     #[instrument(skip(self))]
     pub async fn update_channel_config(&self, channel: &ChannelId) -> SqlxResult<()> {
-        match sqlx::query!(
+        match sqlx::query(
             r#"
             UPDATE reply SET
-                enabled = NOT enabled,
+                mode = CASE WHEN mode = 'silent' THEN 'reply' ELSE 'silent' END,
                 updated_at = NOW()
             WHERE id = $1
             "#,
-            channel.0,
         )
+        .bind(channel)
         .execute(self.pool)
         .await
         {
@@ -177,6 +180,25 @@ impl ChannelRepository {
         }
     }
 
+    /// Returns false if the channel has no reply configuration.
+    #[instrument(skip(self))]
+    pub async fn set_reply_mode(&self, channel: &ChannelId, mode: ReplyMode) -> SqlxResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE reply
+            SET mode = $2,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel)
+        .bind(mode.as_str())
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     #[instrument(skip(self))]
     pub async fn get_reply_config(&self, channel: &str) -> SqlxResult<ChannelReplies> {
         let result = sqlx::query_as::<_, ChannelReplies>(
@@ -206,5 +228,88 @@ impl ChannelRepository {
         Ok(configs)
     }
 
+    /// Returns `None` if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn get_count_config(
+        &self,
+        channel_id: &ChannelId,
+    ) -> SqlxResult<Option<ChannelCountConfig>> {
+        sqlx::query_as::<_, ChannelCountConfig>(
+            r#"
+            SELECT count_mode, max_occurrences
+            FROM channel
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Returns false if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn set_count_config(
+        &self,
+        channel_id: &ChannelId,
+        config: &ChannelCountConfig,
+    ) -> SqlxResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE channel
+            SET count_mode = $2,
+                max_occurrences = $3,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(config.count_mode.as_str())
+        .bind(config.max_occurrences)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns false if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn is_paused(&self, channel_id: &ChannelId) -> SqlxResult<bool> {
+        Ok(self.paused(channel_id).await?.unwrap_or(false))
+    }
+
+    /// Returns `None` if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn paused(&self, channel_id: &ChannelId) -> SqlxResult<Option<bool>> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT paused
+            FROM channel
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Returns false if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn set_paused(&self, channel_id: &ChannelId, paused: bool) -> SqlxResult<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE channel
+            SET paused = $2,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(paused)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     // pub async fn get_all_reply_configs()
 }
@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use sqlx::{Pool, Postgres, Result as SqlxResult};
 use tracing::instrument;
 
@@ -12,6 +13,90 @@ pub struct ChatterRepository {
 }
 
 impl ChatterRepository {
+    /// Inserts a placeholder row for a chatter that hasn't been fetched from Helix yet.
+    ///
+    /// Existing rows are left untouched; the stub's `updated_at` should be old enough that the
+    /// row is considered stale until it has been hydrated.
+    #[instrument(skip(self, item))]
+    pub async fn insert_stub(&self, item: &Chatter) -> SqlxResult<()> {
+        item.id.validate()?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO chatter (
+                id,
+                login,
+                name,
+                color,
+                image,
+                total,
+                private,
+                created_at,
+                updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, 0, false, $6, $7)
+            ON CONFLICT (id)
+            DO NOTHING
+            "#,
+        )
+        .bind(&item.id)
+        .bind(&item.login)
+        .bind(&item.name)
+        .bind(&item.color)
+        .bind(&item.image)
+        .bind(item.created_at)
+        .bind(item.updated_at)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Chatters whose data is older than `stale_before`, least recently refreshed first - except
+    /// that chatters within the top `visible_rank` of the global leaderboard or any channel's
+    /// leaderboard come before everyone else.
+    #[instrument(skip(self))]
+    pub async fn get_stale(
+        &self,
+        stale_before: NaiveDateTime,
+        visible_rank: i64,
+        limit: i64,
+    ) -> SqlxResult<Vec<ChatterId>> {
+        sqlx::query_scalar::<_, ChatterId>(
+            r#"
+            WITH visible AS (
+                SELECT id FROM chatter_leaderboard WHERE ranking <= $2
+                UNION
+                SELECT chatter_id FROM ranked_scores_view_per_channel WHERE ranking <= $2
+            )
+            SELECT c.id
+            FROM chatter c
+            LEFT JOIN visible v ON v.id = c.id
+            WHERE GREATEST(c.refreshed_at, c.updated_at) < $1
+            AND c.deleted_at IS NULL
+            ORDER BY v.id IS NOT NULL DESC, GREATEST(c.refreshed_at, c.updated_at) ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(stale_before)
+        .bind(visible_rank)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Records that chatters were checked against Helix, so that chatters Helix no longer returns
+    /// aren't retried on every refresh.
+    #[instrument(skip(self, ids), fields(count = ids.len()))]
+    pub async fn mark_refreshed(&self, ids: &[ChatterId]) -> SqlxResult<()> {
+        sqlx::query("UPDATE chatter SET refreshed_at = now() WHERE id = ANY($1)")
+            .bind(ids)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     pub async fn search_by_login(&self, query: &str) -> SqlxResult<Vec<ChatterSearchResult>> {
         match sqlx::query_as::<_, ChatterSearchResult>(
@@ -53,6 +138,8 @@ impl Repository for ChatterRepository {
 
     #[instrument(skip(self, item))]
     async fn insert(&self, item: &Self::Output) -> SqlxResult<()> {
+        item.id.validate()?;
+
         match sqlx::query!(
             r#"
             INSERT INTO chatter (
@@ -0,0 +1,200 @@
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::deletion::{ChatterDeletion, PurgedChatter};
+
+const DELETION_FIELDS: &str =
+    "chatter_id, confirmation_hash, confirm_by, requested_at, confirmed_at, purge_after";
+
+pub struct DeletionRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl DeletionRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get(&self, chatter_id: &ChatterId) -> SqlxResult<Option<ChatterDeletion>> {
+        sqlx::query_as::<_, ChatterDeletion>(&format!(
+            "SELECT {DELETION_FIELDS} FROM chatter_deletion WHERE chatter_id = $1"
+        ))
+        .bind(chatter_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Records a pending request, replacing the token of an earlier pending request. Returns `None`
+    /// if the chatter's deletion has already been confirmed.
+    #[instrument(skip(self, confirmation_hash))]
+    pub async fn request(
+        &self,
+        chatter_id: &ChatterId,
+        confirmation_hash: &str,
+        confirm_by: NaiveDateTime,
+    ) -> SqlxResult<Option<ChatterDeletion>> {
+        sqlx::query_as::<_, ChatterDeletion>(&format!(
+            r#"
+            INSERT INTO chatter_deletion (chatter_id, confirmation_hash, confirm_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (chatter_id)
+            DO UPDATE SET
+                confirmation_hash = EXCLUDED.confirmation_hash,
+                confirm_by = EXCLUDED.confirm_by,
+                requested_at = now()
+            WHERE chatter_deletion.confirmed_at IS NULL
+            RETURNING {DELETION_FIELDS}
+            "#
+        ))
+        .bind(chatter_id)
+        .bind(confirmation_hash)
+        .bind(confirm_by)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Confirms a pending request and soft-deletes the chatter. Returns `None` if there was no
+    /// pending request.
+    #[instrument(skip(self))]
+    pub async fn confirm(
+        &self,
+        chatter_id: &ChatterId,
+        purge_after: NaiveDateTime,
+    ) -> SqlxResult<Option<ChatterDeletion>> {
+        let mut tx = self.pool.begin().await?;
+
+        let deletion = sqlx::query_as::<_, ChatterDeletion>(&format!(
+            r#"
+            UPDATE chatter_deletion
+            SET confirmed_at = now(), purge_after = $2
+            WHERE chatter_id = $1 AND confirmed_at IS NULL
+            RETURNING {DELETION_FIELDS}
+            "#
+        ))
+        .bind(chatter_id)
+        .bind(purge_after)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if deletion.is_some() {
+            sqlx::query("UPDATE chatter SET deleted_at = now() WHERE id = $1")
+                .bind(chatter_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(deletion)
+    }
+
+    /// Confirmed requests whose purge is due.
+    #[instrument(skip(self))]
+    pub async fn get_due(&self) -> SqlxResult<Vec<ChatterDeletion>> {
+        sqlx::query_as::<_, ChatterDeletion>(&format!(
+            r#"
+            SELECT {DELETION_FIELDS}
+            FROM chatter_deletion
+            WHERE purge_after <= now()
+            ORDER BY purge_after
+            "#
+        ))
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// The chatter's current login and every login they've been seen under, which the legacy Redis
+    /// keys may be named after.
+    #[instrument(skip(self))]
+    pub async fn logins(&self, chatter_id: &ChatterId) -> SqlxResult<Vec<String>> {
+        sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT login FROM chatter WHERE id = $1
+            UNION
+            SELECT login FROM alias WHERE chatter_id = $1
+            "#,
+        )
+        .bind(chatter_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Everything stored about a chatter, as a single JSON document.
+    #[instrument(skip(self))]
+    pub async fn export(&self, chatter_id: &ChatterId) -> SqlxResult<Value> {
+        sqlx::query_scalar::<_, Value>(
+            r#"
+            SELECT jsonb_build_object(
+                'chatter', (SELECT to_jsonb(c) FROM chatter c WHERE c.id = $1),
+                'aliases', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(a) ORDER BY a.detected_at), '[]')
+                    FROM alias a WHERE a.chatter_id = $1
+                ),
+                'scores', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(s) ORDER BY s.channel_id, s.keyword_id), '[]')
+                    FROM score s WHERE s.chatter_id = $1
+                ),
+                'score_events', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(e) ORDER BY e.earned_at), '[]')
+                    FROM score_event e WHERE e.chatter_id = $1
+                ),
+                'suppressed_score_events', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(e) ORDER BY e.suppressed_at), '[]')
+                    FROM suppressed_score_event e WHERE e.chatter_id = $1
+                ),
+                'notes', (
+                    SELECT COALESCE(jsonb_agg(to_jsonb(n) ORDER BY n.channel_id), '[]')
+                    FROM chatter_note n WHERE n.chatter_id = $1
+                )
+            )
+            "#,
+        )
+        .bind(chatter_id)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Removes the chatter and everything recorded about them in a single transaction.
+    ///
+    /// Score events (and the rollups of archived events) are deleted individually so that their
+    /// decrement triggers take them off the channels' totals and heatmaps. Archive files aren't
+    /// rewritten, but archived history only includes chatters that still exist.
+    #[instrument(skip(self))]
+    pub async fn purge(&self, chatter_id: &ChatterId) -> SqlxResult<PurgedChatter> {
+        let mut tx = self.pool.begin().await?;
+        let mut delete = async |table: &str| -> SqlxResult<u64> {
+            Ok(
+                sqlx::query(&format!("DELETE FROM {table} WHERE chatter_id = $1"))
+                    .bind(chatter_id)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected(),
+            )
+        };
+
+        let purged = PurgedChatter {
+            chatter_id: chatter_id.clone(),
+            score_events: delete("score_event").await?,
+            score_rollups: delete("score_event_rollup").await?,
+            suppressed_score_events: delete("suppressed_score_event").await?,
+            scores: delete("score").await?,
+            snapshots: delete("leaderboard_snapshot").await?,
+            notes: delete("chatter_note").await?,
+            legacy_logins: Vec::new(),
+        };
+        // note history isn't tied to the note, so it isn't removed along with it
+        delete("chatter_note_audit").await?;
+
+        // aliases and the deletion request go with the chatter
+        sqlx::query("DELETE FROM chatter WHERE id = $1")
+            .bind(chatter_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(purged)
+    }
+}
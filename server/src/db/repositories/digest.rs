@@ -0,0 +1,195 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::digest::{Digest, DueDigest};
+
+const DIGEST_COLUMNS: &str = r#"
+    d.id,
+    d.channel_id,
+    COALESCE(ch.login, d.channel_id) AS channel_login,
+    d.digest_on,
+    d.period_start,
+    d.period_end,
+    d.total,
+    d.top_chatters,
+    d.biggest_gainer,
+    d.created_at
+"#;
+
+pub struct DigestRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl DigestRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Retrieves the channels whose `digest_hour` has passed today (in the channel's timezone)
+    /// without a digest having been compiled. Each digest covers the day up to that hour.
+    #[instrument(skip(self))]
+    pub async fn get_due(&self) -> SqlxResult<Vec<DueDigest>> {
+        sqlx::query_as::<_, DueDigest>(
+            r#"
+            WITH local AS (
+                SELECT
+                    c.id,
+                    c.timezone,
+                    s.digest_hour,
+                    to_channel_local(NOW() AT TIME ZONE 'UTC', c.timezone) AS local_now
+                FROM channel c
+                JOIN channel_settings s ON s.channel_id = c.id
+                WHERE s.digest_hour IS NOT NULL
+            ),
+            due AS (
+                SELECT
+                    id,
+                    timezone,
+                    local_now::date AS digest_on,
+                    date_trunc('day', local_now) + make_interval(hours => digest_hour) AS local_end
+                FROM local
+                WHERE EXTRACT(HOUR FROM local_now) >= digest_hour
+            )
+            SELECT
+                d.id AS channel_id,
+                d.digest_on,
+                ((d.local_end - INTERVAL '1 day') AT TIME ZONE d.timezone) AT TIME ZONE 'UTC'
+                    AS period_start,
+                (d.local_end AT TIME ZONE d.timezone) AT TIME ZONE 'UTC' AS period_end
+            FROM due d
+            WHERE NOT EXISTS (
+                SELECT 1 FROM digest g
+                WHERE g.channel_id = d.id
+                AND g.digest_on = d.digest_on
+            )
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Compiles and stores a due digest.
+    ///
+    /// Returns `None` if the channel already has a digest for the day, e.g. because another
+    /// instance compiled it first.
+    #[instrument(skip(self))]
+    pub async fn compile(&self, due: &DueDigest, top: i64) -> SqlxResult<Option<Digest>> {
+        sqlx::query_as::<_, Digest>(&format!(
+            r#"
+            WITH counts AS (
+                SELECT
+                    e.chatter_id,
+                    COUNT(*) FILTER (WHERE e.earned_at < $3) AS before,
+                    COUNT(*) FILTER (WHERE e.earned_at >= $3) AS during
+                FROM score_event e
+                WHERE e.channel_id = $1
+                AND e.earned_at < $4
+                AND e.chatter_id NOT IN (SELECT id FROM chatter WHERE deleted_at IS NOT NULL)
+                GROUP BY e.chatter_id
+            ),
+            ranked AS (
+                SELECT
+                    c.chatter_id,
+                    ch.login,
+                    c.during,
+                    RANK() OVER (ORDER BY c.before DESC) AS from_rank,
+                    RANK() OVER (ORDER BY c.before + c.during DESC) AS to_rank
+                FROM counts c
+                JOIN chatter ch ON ch.id = c.chatter_id
+            ),
+            top AS (
+                SELECT chatter_id, login, during
+                FROM ranked
+                WHERE during > 0
+                ORDER BY during DESC, login
+                LIMIT $5
+            ),
+            gainer AS (
+                SELECT chatter_id, login, during, from_rank, to_rank
+                FROM ranked
+                WHERE during > 0
+                AND from_rank > to_rank
+                ORDER BY from_rank - to_rank DESC, during DESC, login
+                LIMIT 1
+            ),
+            inserted AS (
+                INSERT INTO digest (
+                    channel_id,
+                    digest_on,
+                    period_start,
+                    period_end,
+                    total,
+                    top_chatters,
+                    biggest_gainer
+                )
+                SELECT
+                    $1,
+                    $2,
+                    $3,
+                    $4,
+                    (SELECT COALESCE(SUM(during), 0)::INT8 FROM ranked),
+                    (
+                        SELECT COALESCE(
+                            jsonb_agg(
+                                jsonb_build_object(
+                                    'chatter_id', chatter_id,
+                                    'login', login,
+                                    'score', during
+                                )
+                                ORDER BY during DESC, login
+                            ),
+                            '[]'
+                        )
+                        FROM top
+                    ),
+                    (
+                        SELECT jsonb_build_object(
+                            'chatter_id', chatter_id,
+                            'login', login,
+                            'score', during,
+                            'from_rank', from_rank,
+                            'to_rank', to_rank
+                        )
+                        FROM gainer
+                    )
+                ON CONFLICT (channel_id, digest_on) DO NOTHING
+                RETURNING *
+            )
+            SELECT {DIGEST_COLUMNS}
+            FROM inserted d
+            LEFT JOIN chatter ch ON ch.id = d.channel_id
+            "#
+        ))
+        .bind(&due.channel_id)
+        .bind(due.digest_on)
+        .bind(due.period_start)
+        .bind(due.period_end)
+        .bind(top)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves a channel's most recent digests, newest first.
+    #[instrument(skip(self))]
+    pub async fn get_for_channel(
+        &self,
+        channel_id: &ChannelId,
+        limit: i64,
+    ) -> SqlxResult<Vec<Digest>> {
+        sqlx::query_as::<_, Digest>(&format!(
+            r#"
+            SELECT {DIGEST_COLUMNS}
+            FROM digest d
+            LEFT JOIN chatter ch ON ch.id = d.channel_id
+            WHERE d.channel_id = $1
+            ORDER BY d.digest_on DESC
+            LIMIT $2
+            "#
+        ))
+        .bind(channel_id)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await
+    }
+}
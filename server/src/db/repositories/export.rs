@@ -0,0 +1,126 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+use sqlx::{Pool, Postgres, Result as SqlxResult, Transaction};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::export::LeaderboardExportRow;
+
+/// Rows fetched from the cursor per round trip
+const EXPORT_BATCH_SIZE: usize = 500;
+/// How long an export's cursor may stay open in total
+const EXPORT_DEADLINE: Duration = Duration::from_secs(10 * 60);
+/// Per-`FETCH` statement timeout, and how long the cursor may sit idle between fetches (e.g.
+/// while a slow client drains the previous batch) before the server closes it
+const EXPORT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reads whole leaderboards through a server-side cursor, so only a single batch of rows is held
+/// in memory at a time.
+pub struct ExportRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl ExportRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Streams a channel's leaderboard in rank order (matching `ranked_scores_view_per_channel`),
+    /// in batches of up to `EXPORT_BATCH_SIZE` rows.
+    ///
+    /// The cursor's transaction holds a connection until the stream is exhausted or dropped, or
+    /// until the export runs past `EXPORT_DEADLINE` or stalls for longer than
+    /// `EXPORT_IDLE_TIMEOUT`, either of which ends the stream with an error.
+    #[instrument(skip(self))]
+    pub fn channel_leaderboard(
+        &self,
+        channel_id: ChannelId,
+    ) -> impl Stream<Item = SqlxResult<Vec<LeaderboardExportRow>>> + Send + use<> {
+        let pool = self.pool;
+
+        let deadline = Instant::now() + EXPORT_DEADLINE;
+
+        futures::stream::try_unfold(
+            None,
+            move |cursor: Option<Transaction<'static, Postgres>>| {
+                let channel_id = channel_id.clone();
+                async move {
+                    let mut tx = match cursor {
+                        Some(tx) => tx,
+                        None => declare_cursor(pool, &channel_id).await?,
+                    };
+
+                    if Instant::now() >= deadline {
+                        tracing::warn!(%channel_id, "export deadline exceeded - closing cursor");
+                        tx.rollback().await?;
+                        return Err(sqlx::Error::Io(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "export deadline exceeded",
+                        )));
+                    }
+
+                    let rows = sqlx::query_as::<_, LeaderboardExportRow>(&format!(
+                        "FETCH FORWARD {EXPORT_BATCH_SIZE} FROM leaderboard_export"
+                    ))
+                    .fetch_all(&mut *tx)
+                    .await?;
+
+                    if rows.is_empty() {
+                        // nothing was written, so this just closes the cursor
+                        tx.rollback().await?;
+                        return Ok(None);
+                    }
+
+                    Ok(Some((rows, Some(tx))))
+                }
+            },
+        )
+    }
+}
+
+async fn declare_cursor(
+    pool: &'static Pool<Postgres>,
+    channel_id: &ChannelId,
+) -> SqlxResult<Transaction<'static, Postgres>> {
+    let mut tx = pool.begin().await?;
+    let timeout_ms = EXPORT_IDLE_TIMEOUT.as_millis().to_string();
+    sqlx::query(
+        r#"
+        SELECT
+            set_config('statement_timeout', $1, true),
+            set_config('idle_in_transaction_session_timeout', $1, true)
+        "#,
+    )
+    .bind(&timeout_ms)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        DECLARE leaderboard_export NO SCROLL CURSOR FOR
+        SELECT
+            ROW_NUMBER() OVER (
+                ORDER BY SUM(s.score) DESC, MIN(s.created_at), s.chatter_id
+            ) AS ranking,
+            s.chatter_id,
+            c.login,
+            c.name,
+            SUM(s.score)::INT8 AS score,
+            MIN(s.created_at) AS first_scored_at,
+            MAX(s.updated_at) AS last_scored_at
+        FROM score s
+        JOIN chatter c ON c.id = s.chatter_id
+        WHERE s.channel_id = $1
+        AND c.deleted_at IS NULL
+        GROUP BY s.chatter_id, c.login, c.name
+        ORDER BY ranking
+        "#,
+    )
+    .bind(channel_id)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(tx)
+}
@@ -0,0 +1,84 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::flag::FeatureFlag;
+
+pub struct FeatureFlagRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl FeatureFlagRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<FeatureFlag>> {
+        sqlx::query_as::<_, FeatureFlag>(
+            r#"
+            SELECT name, enabled, rollout_percent, channels, updated_at
+            FROM feature_flag
+            ORDER BY name
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get(&self, name: &str) -> SqlxResult<Option<FeatureFlag>> {
+        sqlx::query_as::<_, FeatureFlag>(
+            r#"
+            SELECT name, enabled, rollout_percent, channels, updated_at
+            FROM feature_flag
+            WHERE name = $1
+            "#,
+        )
+        .bind(name)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Replaces a flag's state, creating it if it was still on its default.
+    #[instrument(skip(self))]
+    pub async fn set(
+        &self,
+        name: &str,
+        enabled: bool,
+        rollout_percent: i16,
+        channels: &[ChannelId],
+    ) -> SqlxResult<FeatureFlag> {
+        sqlx::query_as::<_, FeatureFlag>(
+            r#"
+            INSERT INTO feature_flag (name, enabled, rollout_percent, channels)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name)
+            DO UPDATE SET
+                enabled = $2,
+                rollout_percent = $3,
+                channels = $4,
+                updated_at = NOW()
+            RETURNING name, enabled, rollout_percent, channels, updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(enabled)
+        .bind(rollout_percent)
+        .bind(channels)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Drops a flag's stored state, returning it to its default. Returns false if it was already
+    /// on its default.
+    #[instrument(skip(self))]
+    pub async fn delete(&self, name: &str) -> SqlxResult<bool> {
+        let result = sqlx::query("DELETE FROM feature_flag WHERE name = $1")
+            .bind(name)
+            .execute(self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::NaiveDateTime;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::heatmap::HeatmapBucket;
+use crate::db::models::raid::HourlyScore;
+
+pub struct HeatmapRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl HeatmapRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_buckets(&self, channel_id: &ChannelId) -> SqlxResult<Vec<HeatmapBucket>> {
+        sqlx::query_as::<_, HeatmapBucket>(
+            r#"
+            SELECT day_of_week, hour, total
+            FROM channel_heatmap
+            WHERE channel_id = $1
+            ORDER BY day_of_week, hour
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves a channel's score counts for each hour since `since`, skipping hours without any.
+    #[instrument(skip(self))]
+    pub async fn get_hourly(
+        &self,
+        channel_id: &ChannelId,
+        since: NaiveDateTime,
+    ) -> SqlxResult<Vec<HourlyScore>> {
+        sqlx::query_as::<_, HourlyScore>(
+            r#"
+            SELECT date_trunc('hour', earned_at) AS hour, COUNT(*) AS total
+            FROM score_event
+            WHERE channel_id = $1 AND earned_at >= $2
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(since)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves a channel's timezone, or `None` if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn get_timezone(&self, channel_id: &ChannelId) -> SqlxResult<Option<String>> {
+        sqlx::query_scalar::<_, String>("SELECT timezone FROM channel WHERE id = $1")
+            .bind(channel_id)
+            .fetch_optional(self.pool)
+            .await
+    }
+
+    /// Sets a channel's timezone and rebuilds its heatmap to match.
+    ///
+    /// Returns false without making any changes if the timezone isn't known to Postgres.
+    #[instrument(skip(self))]
+    pub async fn set_timezone(&self, channel_id: &ChannelId, timezone: &str) -> SqlxResult<bool> {
+        let valid = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)",
+        )
+        .bind(timezone)
+        .fetch_one(self.pool)
+        .await?;
+
+        if !valid {
+            return Ok(false);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE channel
+            SET timezone = $2,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(timezone)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("SELECT recalc_channel_heatmap($1)")
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+}
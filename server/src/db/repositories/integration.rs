@@ -0,0 +1,113 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::integration::{DiscordWebhook, IntegrationEvent};
+
+pub struct DiscordWebhookRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl DiscordWebhookRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<DiscordWebhook>> {
+        sqlx::query_as::<_, DiscordWebhook>(
+            r#"
+            SELECT id, channel_id, event, url, template, enabled, created_at, updated_at
+            FROM discord_webhook
+            ORDER BY channel_id, event
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get(
+        &self,
+        channel_id: &ChannelId,
+        event: IntegrationEvent,
+    ) -> SqlxResult<Option<DiscordWebhook>> {
+        sqlx::query_as::<_, DiscordWebhook>(
+            r#"
+            SELECT id, channel_id, event, url, template, enabled, created_at, updated_at
+            FROM discord_webhook
+            WHERE channel_id = $1 AND event = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(event.as_str())
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves the enabled webhooks for an event, optionally limited to a single channel.
+    #[instrument(skip(self))]
+    pub async fn get_enabled(
+        &self,
+        event: IntegrationEvent,
+        channel_id: Option<&ChannelId>,
+    ) -> SqlxResult<Vec<DiscordWebhook>> {
+        sqlx::query_as::<_, DiscordWebhook>(
+            r#"
+            SELECT id, channel_id, event, url, template, enabled, created_at, updated_at
+            FROM discord_webhook
+            WHERE enabled AND event = $1 AND ($2::varchar IS NULL OR channel_id = $2)
+            "#,
+        )
+        .bind(event.as_str())
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Sets a channel's webhook for an event, replacing any existing one.
+    #[instrument(skip(self, url))]
+    pub async fn upsert(
+        &self,
+        channel_id: &ChannelId,
+        event: IntegrationEvent,
+        url: &str,
+        template: Option<&str>,
+        enabled: bool,
+    ) -> SqlxResult<DiscordWebhook> {
+        sqlx::query_as::<_, DiscordWebhook>(
+            r#"
+            INSERT INTO discord_webhook (channel_id, event, url, template, enabled)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (channel_id, event) DO UPDATE SET
+                url = EXCLUDED.url,
+                template = EXCLUDED.template,
+                enabled = EXCLUDED.enabled,
+                updated_at = now()
+            RETURNING id, channel_id, event, url, template, enabled, created_at, updated_at
+            "#,
+        )
+        .bind(channel_id)
+        .bind(event.as_str())
+        .bind(url)
+        .bind(template)
+        .bind(enabled)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Returns the deleted webhook, or `None` if there was no webhook with the id.
+    #[instrument(skip(self))]
+    pub async fn delete(&self, id: i32) -> SqlxResult<Option<DiscordWebhook>> {
+        sqlx::query_as::<_, DiscordWebhook>(
+            r#"
+            DELETE FROM discord_webhook
+            WHERE id = $1
+            RETURNING id, channel_id, event, url, template, enabled, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await
+    }
+}
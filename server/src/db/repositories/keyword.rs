@@ -0,0 +1,142 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::keyword::{Keyword, KeywordId, KeywordKind, KeywordLeaderboardEntry};
+
+pub struct KeywordRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl KeywordRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<Keyword>> {
+        sqlx::query_as::<_, Keyword>(
+            "SELECT id, word, kind, emote_id, created_at FROM keyword ORDER BY id",
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_by_word(&self, word: &str, kind: KeywordKind) -> SqlxResult<Option<Keyword>> {
+        sqlx::query_as::<_, Keyword>(
+            r#"
+            SELECT id, word, kind, emote_id, created_at
+            FROM keyword
+            WHERE word = $1 AND kind = $2
+            "#,
+        )
+        .bind(word.to_lowercase())
+        .bind(kind.as_str())
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_by_id(&self, id: KeywordId) -> SqlxResult<Option<Keyword>> {
+        sqlx::query_as::<_, Keyword>(
+            "SELECT id, word, kind, emote_id, created_at FROM keyword WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Returns `None` if the word is already tracked as the same kind of keyword.
+    #[instrument(skip(self))]
+    pub async fn insert(
+        &self,
+        word: &str,
+        kind: KeywordKind,
+        emote_id: Option<&str>,
+    ) -> SqlxResult<Option<Keyword>> {
+        sqlx::query_as::<_, Keyword>(
+            r#"
+            INSERT INTO keyword (word, kind, emote_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (word, kind) DO NOTHING
+            RETURNING id, word, kind, emote_id, created_at
+            "#,
+        )
+        .bind(word.to_lowercase())
+        .bind(kind.as_str())
+        .bind(emote_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Returns `None` if there was no keyword with the id.
+    #[instrument(skip(self))]
+    pub async fn update(
+        &self,
+        id: KeywordId,
+        word: &str,
+        kind: KeywordKind,
+        emote_id: Option<&str>,
+    ) -> SqlxResult<Option<Keyword>> {
+        sqlx::query_as::<_, Keyword>(
+            r#"
+            UPDATE keyword
+            SET word = $2, kind = $3, emote_id = $4
+            WHERE id = $1
+            RETURNING id, word, kind, emote_id, created_at
+            "#,
+        )
+        .bind(id)
+        .bind(word.to_lowercase())
+        .bind(kind.as_str())
+        .bind(emote_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves chatters ranked by their score for a single keyword, across all channels.
+    #[instrument(skip(self))]
+    pub async fn get_leaderboard(
+        &self,
+        keyword_id: &KeywordId,
+        limit: i64,
+        offset: i64,
+    ) -> SqlxResult<PaginatedResponse<KeywordLeaderboardEntry>> {
+        let total_items = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM keyword_leaderboard WHERE keyword_id = $1",
+        )
+        .bind(keyword_id)
+        .fetch_one(self.pool)
+        .await?;
+
+        let entries = sqlx::query_as::<_, KeywordLeaderboardEntry>(
+            r#"
+            SELECT
+                id,
+                login,
+                name,
+                color,
+                image,
+                total,
+                ranking
+            FROM keyword_leaderboard
+            WHERE keyword_id = $1
+            ORDER BY ranking ASC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(keyword_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(PaginatedResponse::new(
+            entries,
+            total_items,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+}
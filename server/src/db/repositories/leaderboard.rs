@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres, Result as SqlxResult};
 use tracing::instrument;
@@ -7,9 +10,13 @@ use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
 use crate::db::models::channel::{ChannelLeaderboardRow, ChannelScoreSummary};
 use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
 use crate::db::models::chatter::{ChatterLeaderboardRow, ChatterScoreSummary};
-use crate::db::models::leaderboard::{Score, TimeWindow};
+use crate::db::models::keyword::KeywordId;
+use crate::db::models::leaderboard::{AdjustedScore, Period, Score, ScoreAdjustment};
+use crate::db::models::leaderboard::{LeaderboardVersion, ModerationAction, ModerationTarget};
+use crate::db::models::leaderboard::{SuppressReason, TimeWindow};
+use crate::db::models::milestone::MilestoneTotals;
 use crate::db::prelude::{Channel, ChannelRepository, Chatter};
-use crate::db::prelude::{ChatterRepository, Repository, ScoreSummary};
+use crate::db::prelude::{ChatterRepository, Repository, ScoreSummary, Tx};
 
 pub struct LeaderboardRepository {
     pool: &'static Pool<Postgres>,
@@ -38,28 +45,242 @@ impl LeaderboardRepository {
         Self { pool }
     }
 
+    /// The version of a leaderboard built from `channel_id`'s scores, or from every channel's if
+    /// unset. Period leaderboards also change when the period rolls over.
+    #[instrument(skip(self))]
+    pub async fn get_version(
+        &self,
+        channel_id: Option<&ChannelId>,
+        period: Period,
+    ) -> SqlxResult<LeaderboardVersion> {
+        sqlx::query_as::<_, LeaderboardVersion>(
+            r#"
+            SELECT
+                md5(
+                    string_agg(
+                        c.id || ':' || c.version || ':' || COALESCE(s.is_live, false)::text,
+                        ',' ORDER BY c.id
+                    ) || COALESCE(date_trunc($2, CURRENT_TIMESTAMP)::text, '')
+                ) AS tag,
+                GREATEST(
+                    MAX(c.updated_at),
+                    MAX(s.updated_at),
+                    date_trunc($2, CURRENT_TIMESTAMP)::timestamp
+                ) AS modified
+            FROM channel c
+            LEFT JOIN stream_status s ON s.channel_id = c.id
+            WHERE $1::varchar IS NULL OR c.id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .bind(period.as_date_trunc_field())
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Records a single score event; `msg_id` is the id of the IRC message it was counted from.
     #[instrument(skip(self))]
     pub async fn record_score_event(
         &self,
         chatter_id: &ChatterId,
         channel_id: &ChannelId,
+        keyword_id: &KeywordId,
+        msg_id: &str,
     ) -> SqlxResult<()> {
-        tracing::debug!(%chatter_id, %channel_id,  "inserting new score_event");
+        tracing::debug!(%chatter_id, %channel_id, %keyword_id, "inserting new score_event");
+        chatter_id.validate()?;
+        channel_id.validate()?;
 
-        sqlx::query!(
+        sqlx::query(
             r#"
-            INSERT INTO score_event (chatter_id, channel_id, earned_at)
-            VALUES ($1, $2, NOW())
+            INSERT INTO score_event (chatter_id, channel_id, keyword_id, msg_id, earned_at)
+            VALUES ($1, $2, $3, NULLIF($4, ''), NOW())
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .bind(keyword_id)
+        .bind(msg_id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records a single score event as `record_score_event` does, returning the channel's total
+    /// and the chatter's score in the channel as of the event.
+    ///
+    /// The channel row stays locked (by the increment trigger) until the transaction commits, so
+    /// concurrent events in a channel each see a distinct total.
+    #[instrument(skip(self))]
+    pub async fn record_score_event_with_totals(
+        &self,
+        chatter_id: &ChatterId,
+        channel_id: &ChannelId,
+        keyword_id: &KeywordId,
+        msg_id: &str,
+    ) -> SqlxResult<MilestoneTotals> {
+        chatter_id.validate()?;
+        channel_id.validate()?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO score_event (chatter_id, channel_id, keyword_id, msg_id, earned_at)
+            VALUES ($1, $2, $3, NULLIF($4, ''), NOW())
             "#,
-            chatter_id.0,
-            channel_id.0,
         )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .bind(keyword_id)
+        .bind(msg_id)
+        .execute(&mut *tx)
+        .await?;
+
+        let totals = sqlx::query_as::<_, MilestoneTotals>(
+            r#"
+            SELECT
+                b.channel_total,
+                COALESCE((
+                    SELECT SUM(s.score)::INT8
+                    FROM score s
+                    WHERE s.chatter_id = $1 AND s.channel_id = b.id
+                ), 0) AS chatter_score
+            FROM channel b
+            WHERE b.id = $2
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(totals)
+    }
+
+    /// Records a keyword match that was dropped by the score limits; these never reach the totals.
+    #[instrument(skip(self))]
+    pub async fn record_suppressed_score_event(
+        &self,
+        chatter_id: &ChatterId,
+        channel_id: &ChannelId,
+        keyword_id: &KeywordId,
+        msg_id: &str,
+        reason: SuppressReason,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO suppressed_score_event (chatter_id, channel_id, keyword_id, msg_id, reason)
+            VALUES ($1, $2, $3, NULLIF($4, ''), $5)
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .bind(keyword_id)
+        .bind(msg_id)
+        .bind(reason.as_str())
         .execute(self.pool)
         .await?;
 
         Ok(())
     }
 
+    /// Marks a chat message as counted, returning `false` if it already had been.
+    #[instrument(skip(self))]
+    pub async fn claim_message(&self, channel_id: &ChannelId, msg_id: &str) -> SqlxResult<bool> {
+        let claimed = sqlx::query(
+            r#"
+            INSERT INTO processed_message (msg_id, channel_id)
+            VALUES ($1, $2)
+            ON CONFLICT (msg_id) DO NOTHING
+            "#,
+        )
+        .bind(msg_id)
+        .bind(channel_id)
+        .execute(self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(claimed > 0)
+    }
+
+    /// Drops a message's claim, e.g. after its scores failed to record.
+    #[instrument(skip(self))]
+    pub async fn release_message(&self, msg_id: &str) -> SqlxResult<()> {
+        sqlx::query("DELETE FROM processed_message WHERE msg_id = $1")
+            .bind(msg_id)
+            .execute(self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forgets messages counted more than `older_than_secs` seconds ago, returning how many were
+    /// removed.
+    #[instrument(skip(self))]
+    pub async fn purge_processed_messages(&self, older_than_secs: i64) -> SqlxResult<u64> {
+        let purged = sqlx::query(
+            r#"
+            DELETE FROM processed_message
+            WHERE processed_at < NOW() - make_interval(secs => $1)
+            "#,
+        )
+        .bind(older_than_secs as f64)
+        .execute(self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(purged)
+    }
+
+    /// Flags or rolls back the targeted score events that were earned within the last
+    /// `window_secs` seconds, returning the number of events affected.
+    #[instrument(skip(self))]
+    pub async fn moderate_score_events(
+        &self,
+        target: &ModerationTarget,
+        action: ModerationAction,
+        window_secs: i64,
+    ) -> SqlxResult<u64> {
+        let statement = match action {
+            ModerationAction::Flag => "UPDATE score_event SET flagged_at = NOW()",
+            ModerationAction::Rollback => "DELETE FROM score_event",
+        };
+
+        let (filter, ids) = match target {
+            ModerationTarget::Message(msg_id) => ("msg_id = $2", vec![msg_id.as_str()]),
+            ModerationTarget::Chatter {
+                channel_id,
+                chatter_id,
+            } => {
+                channel_id.validate()?;
+                chatter_id.validate()?;
+                (
+                    "chatter_id = $2 AND channel_id = $3",
+                    vec![chatter_id.0.as_str(), channel_id.0.as_str()],
+                )
+            }
+        };
+
+        // events that were already flagged have been moderated once, so they're left alone
+        let query = format!(
+            r#"
+            {statement}
+            WHERE {filter}
+            AND earned_at >= NOW() - make_interval(secs => $1)
+            AND flagged_at IS NULL
+            "#
+        );
+
+        let mut query = sqlx::query(&query).bind(window_secs as f64);
+        for id in ids {
+            query = query.bind(id);
+        }
+
+        Ok(query.execute(self.pool).await?.rows_affected())
+    }
+
     #[instrument(skip(self))]
     pub async fn record_score_events_multi(
         &self,
@@ -141,32 +362,34 @@ impl LeaderboardRepository {
         &self,
         channel: &Chatter,
         chatter: &Chatter,
+        keyword_id: &KeywordId,
         value: i64,
     ) -> SqlxResult<Option<ScoreSummary>> {
-        let score = sqlx::query_as!(
-            ScoreSummary,
+        let score = sqlx::query_as::<_, ScoreSummary>(
             r#"
             INSERT INTO score (
                 channel_id,
                 chatter_id,
+                keyword_id,
                 score,
                 created_at,
                 updated_at
             )
-            VALUES ($1, $2, $3, NOW(), NOW())
-            ON CONFLICT (channel_id, chatter_id)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            ON CONFLICT (channel_id, chatter_id, keyword_id)
             DO UPDATE SET
-                score = score.score + $3,
+                score = score.score + $4,
                 updated_at = NOW()
             RETURNING 
                 channel_id,
                 chatter_id,
                 score
             "#,
-            channel.id.0,
-            chatter.id.0,
-            value
         )
+        .bind(&channel.id)
+        .bind(&chatter.id)
+        .bind(keyword_id)
+        .bind(value)
         .fetch_optional(self.pool)
         .await;
 
@@ -195,8 +418,69 @@ impl LeaderboardRepository {
         &self,
         channel: &Chatter,
         chatter: &Chatter,
+        keyword_id: &KeywordId,
     ) -> SqlxResult<Option<ScoreSummary>> {
-        self.increment_by(channel, chatter, 1).await
+        self.increment_by(channel, chatter, keyword_id, 1).await
+    }
+
+    /// Applies a manual correction to a chatter's score in a channel and recalculates both totals.
+    ///
+    /// Returns `None` (leaving the score untouched) if the adjustment would make it negative.
+    /// Adjustments don't create score events, so windowed leaderboards aren't affected.
+    #[instrument(skip(self))]
+    pub async fn adjust_score(
+        &self,
+        chatter_id: &ChatterId,
+        channel_id: &ChannelId,
+        keyword_id: &KeywordId,
+        adjustment: ScoreAdjustment,
+    ) -> SqlxResult<Option<AdjustedScore>> {
+        let mut tx = Tx::begin(self.pool).await?;
+
+        let before = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT score
+            FROM score
+            WHERE chatter_id = $1
+            AND channel_id = $2
+            AND keyword_id = $3
+            FOR UPDATE
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .bind(keyword_id)
+        .fetch_optional(&mut **tx.inner_mut()?)
+        .await?
+        .unwrap_or(0);
+
+        let Some(after) = adjustment.apply(before) else {
+            tx.rollback().await?;
+            return Ok(None);
+        };
+
+        match adjustment {
+            ScoreAdjustment::By(delta) => {
+                tx.increment_score_by(chatter_id, channel_id, keyword_id, delta)
+                    .await?
+            }
+            ScoreAdjustment::To(score) => {
+                tx.update_score(chatter_id, channel_id, keyword_id, score)
+                    .await?
+            }
+        };
+
+        tx.recalculate_chatter_total(chatter_id).await?;
+        tx.recalculate_channel_total(channel_id).await?;
+        tx.commit().await?;
+
+        Ok(Some(AdjustedScore {
+            chatter_id: chatter_id.clone(),
+            channel_id: channel_id.clone(),
+            keyword_id: *keyword_id,
+            before,
+            after,
+        }))
     }
 
     #[instrument(skip(self))]
@@ -218,21 +502,44 @@ impl LeaderboardRepository {
         &self,
         channel_id: &ChannelId,
         chatter_id: &ChatterId,
+        keyword_id: &KeywordId,
     ) -> SqlxResult<Option<Score>> {
-        sqlx::query_as!(
-            Score,
+        sqlx::query_as::<_, Score>(
             r#"
             SELECT * FROM score
             WHERE chatter_id = $1 
             AND channel_id = $2
+            AND keyword_id = $3
             "#,
-            &channel_id.0,
-            &chatter_id.0
         )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .bind(keyword_id)
         .fetch_optional(self.pool)
         .await
     }
 
+    /// A chatter's score in a channel, summed across all keywords.
+    #[instrument(skip(self))]
+    pub async fn get_chatter_channel_score(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+    ) -> SqlxResult<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COALESCE(SUM(score), 0)::INT8
+            FROM score
+            WHERE chatter_id = $1
+            AND channel_id = $2
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(channel_id)
+        .fetch_one(self.pool)
+        .await
+    }
+
     #[instrument(skip(self))]
     pub async fn get_single_channel_leaderboard(
         &self,
@@ -334,39 +641,134 @@ impl LeaderboardRepository {
         }
     }
 
+    /// Retrieves the leaderboard rows for a set of chatters in one query, in no particular order;
+    /// unknown IDs are skipped.
+    #[instrument(skip(self, ids), fields(ids = ids.len()))]
+    pub async fn get_chatter_rows(
+        &self,
+        ids: &[ChatterId],
+    ) -> SqlxResult<Vec<ChatterLeaderboardRow>> {
+        let ids: Vec<&str> = ids.iter().map(|id| id.0.as_str()).collect();
+
+        sqlx::query_as::<_, ChatterLeaderboardRow>(
+            r#"
+            SELECT
+                c.id,
+                c.name,
+                c.login,
+                c.color,
+                c.image,
+                c.total,
+                c.private,
+                c.ranking,
+                COALESCE(s.total_scores, 0) AS total_scores,
+                c.created_at,
+                c.updated_at
+            FROM chatter_leaderboard c
+            LEFT JOIN (
+                SELECT chatter_id, COUNT(DISTINCT channel_id) AS total_scores
+                FROM score sc
+                WHERE chatter_id = ANY($1)
+                AND NOT EXISTS (
+                    SELECT 1 FROM chatter d
+                    WHERE d.id = sc.chatter_id AND d.deleted_at IS NOT NULL
+                )
+                GROUP BY chatter_id
+            ) s ON s.chatter_id = c.id
+            WHERE c.id = ANY($1)
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves the leaderboard rows for a set of channels in one query, in no particular order;
+    /// unknown IDs are skipped.
+    #[instrument(skip(self, ids), fields(ids = ids.len()))]
+    pub async fn get_channel_rows(
+        &self,
+        ids: &[ChannelId],
+    ) -> SqlxResult<Vec<ChannelLeaderboardRow>> {
+        let ids: Vec<&str> = ids.iter().map(|id| id.0.as_str()).collect();
+
+        sqlx::query_as::<_, ChannelLeaderboardRow>(
+            r#"
+            SELECT
+                ch.id,
+                ch.name,
+                ch.login,
+                ch.color,
+                ch.image,
+                ch.total_chatter,
+                ch.total_channel,
+                ch.ranking,
+                COALESCE(s.total_scores, 0) AS total_scores,
+                ch.created_at,
+                ch.updated_at
+            FROM channel_leaderboard ch
+            LEFT JOIN (
+                SELECT channel_id, COUNT(DISTINCT chatter_id) AS total_scores
+                FROM score sc
+                WHERE channel_id = ANY($1)
+                AND NOT EXISTS (
+                    SELECT 1 FROM chatter d
+                    WHERE d.id = sc.chatter_id AND d.deleted_at IS NOT NULL
+                )
+                GROUP BY channel_id
+            ) s ON s.channel_id = ch.id
+            WHERE ch.id = ANY($1)
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(self.pool)
+        .await
+    }
+
     #[instrument(skip(self))]
     pub async fn get_chatter_leaderboard(
         &self,
         limit: i64,
         offset: i64,
     ) -> SqlxResult<PaginatedResponse<ChatterLeaderboardEntry>> {
-        let total_items: i64 = sqlx::query_scalar!("SELECT COUNT(*) FROM chatter")
-            .fetch_one(self.pool)
-            .await?
-            .unwrap_or_default();
+        let total_items: i64 =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM chatter WHERE deleted_at IS NULL")
+                .fetch_one(self.pool)
+                .await?;
 
+        // the page is selected first so that channel counts are only computed for its chatters
         let chatters = sqlx::query_as::<_, ChatterLeaderboardRow>(
             r#"
+            WITH page AS (
+                SELECT *
+                FROM ranked_scores_view_chatters
+                ORDER BY ranking ASC
+                LIMIT $1 OFFSET $2
+            )
             SELECT
-                c.id, 
+                c.id,
                 c.name,
                 c.login,
-                c.color, 
+                c.color,
                 c.image,
                 c.total,
                 c.private,
                 c.ranking,
                 COALESCE(s.total_scores, 0) AS total_scores,
-                c.created_at, 
+                c.created_at,
                 c.updated_at
-            FROM ranked_scores_view_chatters c
+            FROM page c
             LEFT JOIN (
-                SELECT chatter_id, COUNT(*) AS total_scores
-                FROM ranked_scores_view_per_channel
+                SELECT chatter_id, COUNT(DISTINCT channel_id) AS total_scores
+                FROM score sc
+                WHERE chatter_id IN (SELECT id FROM page)
+                AND NOT EXISTS (
+                    SELECT 1 FROM chatter d
+                    WHERE d.id = sc.chatter_id AND d.deleted_at IS NOT NULL
+                )
                 GROUP BY chatter_id
             ) s ON s.chatter_id = c.id
             ORDER BY c.ranking ASC
-            LIMIT $1 OFFSET $2
             "#,
         )
         .bind(limit)
@@ -381,16 +783,14 @@ impl LeaderboardRepository {
             Vec::new()
         };
 
-        let mut entries = Vec::new();
-        for chatter in chatters {
-            let score_summaries: Vec<ChannelScoreSummary> = scores
-                .iter()
-                .filter(|s| s.chatter_id == chatter.id)
-                .cloned()
-                .collect();
-
-            entries.push(chatter.into_leaderboard_entry(score_summaries));
-        }
+        let mut scores = group_by(scores, |s| s.chatter_id.clone());
+        let entries = chatters
+            .into_iter()
+            .map(|chatter| {
+                let score_summaries = scores.remove(&chatter.id).unwrap_or_default();
+                chatter.into_leaderboard_entry(score_summaries)
+            })
+            .collect();
 
         Ok(PaginatedResponse::new(
             entries,
@@ -411,28 +811,39 @@ impl LeaderboardRepository {
             .fetch_one(self.pool)
             .await?;
 
+        // the page is selected first so that chatter counts are only computed for its channels
         let channels = sqlx::query_as::<_, ChannelLeaderboardRow>(
             r#"
-            SELECT 
-                ch.id, 
-                ch.name, 
+            WITH page AS (
+                SELECT *
+                FROM channel_leaderboard
+                ORDER BY ranking ASC
+                LIMIT $1 OFFSET $2
+            )
+            SELECT
+                ch.id,
+                ch.name,
                 ch.login,
                 ch.image,
                 ch.color,
                 ch.total_chatter,
-                ch.total_channel, 
+                ch.total_channel,
                 ch.ranking,
                 COALESCE(s.total_scores, 0) AS total_scores,
-                ch.created_at, 
+                ch.created_at,
                 ch.updated_at
-            FROM channel_leaderboard ch
+            FROM page ch
             LEFT JOIN (
-                SELECT channel_id, COUNT(*) as total_scores
-                FROM ranked_scores_view_per_channel
+                SELECT channel_id, COUNT(DISTINCT chatter_id) AS total_scores
+                FROM score sc
+                WHERE channel_id IN (SELECT id FROM page)
+                AND NOT EXISTS (
+                    SELECT 1 FROM chatter d
+                    WHERE d.id = sc.chatter_id AND d.deleted_at IS NOT NULL
+                )
                 GROUP BY channel_id
             ) s ON s.channel_id = ch.id
             ORDER BY ch.ranking ASC
-            LIMIT $1 OFFSET $2
             "#,
         )
         .bind(limit)
@@ -448,16 +859,14 @@ impl LeaderboardRepository {
             Vec::new()
         };
 
-        let mut entries = Vec::new();
-        for channel in channels {
-            let score_summaries: Vec<ChatterScoreSummary> = scores
-                .iter()
-                .filter(|s| s.channel_id == channel.id)
-                .cloned()
-                .collect();
-
-            entries.push(channel.into_leaderboard_entry(score_summaries));
-        }
+        let mut scores = group_by(scores, |s| s.channel_id.clone());
+        let entries = channels
+            .into_iter()
+            .map(|channel| {
+                let score_summaries = scores.remove(&channel.id).unwrap_or_default();
+                channel.into_leaderboard_entry(score_summaries)
+            })
+            .collect();
 
         Ok(PaginatedResponse::new(
             entries,
@@ -468,7 +877,7 @@ impl LeaderboardRepository {
     }
 
     #[instrument(skip(self, ids, score_pagination))]
-    async fn get_chatter_scores_batch(
+    pub async fn get_chatter_scores_batch(
         &self,
         ids: &[ChannelId],
         score_pagination: &ScorePagination,
@@ -533,7 +942,7 @@ impl LeaderboardRepository {
     }
 
     #[instrument(skip(self, ids))]
-    async fn get_channel_scores_batch(
+    pub async fn get_channel_scores_batch(
         &self,
         ids: &[ChatterId],
     ) -> SqlxResult<Vec<ChannelScoreSummary>> {
@@ -587,3 +996,27 @@ impl LeaderboardRepository {
             .collect())
     }
 }
+
+/// Groups batch-fetched rows by the entity they belong to, keeping their order within each group.
+fn group_by<K: Eq + Hash, T>(rows: Vec<T>, key: impl Fn(&T) -> K) -> HashMap<K, Vec<T>> {
+    let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+    for row in rows {
+        groups.entry(key(&row)).or_default().push(row);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grouping_keeps_row_order() {
+        let rows = vec![("a", 1), ("b", 1), ("a", 2), ("a", 3), ("b", 2)];
+        let groups = group_by(rows, |(key, _)| *key);
+
+        assert_eq!(groups["a"], [("a", 1), ("a", 2), ("a", 3)]);
+        assert_eq!(groups["b"], [("b", 1), ("b", 2)]);
+    }
+}
@@ -0,0 +1,60 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::leaderboard::Period;
+use crate::db::models::metric::{MetricTotal, StreamMetric};
+
+pub struct MetricRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl MetricRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn insert(
+        &self,
+        channel_id: &ChannelId,
+        metric: StreamMetric,
+        amount: i64,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO stream_metric_event (channel_id, metric, amount)
+            VALUES ($1, $2, $3)
+            "#,
+        )
+        .bind(channel_id)
+        .bind(metric.as_str())
+        .bind(amount)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves a channel's totals for the metrics it has events for in the current `period`.
+    #[instrument(skip(self))]
+    pub async fn get_totals(
+        &self,
+        channel_id: &ChannelId,
+        period: Period,
+    ) -> SqlxResult<Vec<MetricTotal>> {
+        sqlx::query_as::<_, MetricTotal>(
+            r#"
+            SELECT metric, COUNT(*) AS events, SUM(amount)::INT8 AS amount
+            FROM stream_metric_event
+            WHERE channel_id = $1
+            AND ($2::text IS NULL OR occurred_at >= date_trunc($2, CURRENT_TIMESTAMP))
+            GROUP BY metric
+            "#,
+        )
+        .bind(channel_id)
+        .bind(period.as_date_trunc_field())
+        .fetch_all(self.pool)
+        .await
+    }
+}
@@ -0,0 +1,81 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::milestone::{Milestone, MilestoneKind};
+
+pub struct MilestoneRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl MilestoneRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<Milestone>> {
+        sqlx::query_as::<_, Milestone>(
+            r#"
+            SELECT id, channel_id, kind, threshold, repeating, created_at
+            FROM milestone
+            ORDER BY channel_id NULLS FIRST, kind, threshold
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves the milestones that apply to a channel, including global milestones.
+    #[instrument(skip(self))]
+    pub async fn get_for_channel(&self, channel_id: &ChannelId) -> SqlxResult<Vec<Milestone>> {
+        sqlx::query_as::<_, Milestone>(
+            r#"
+            SELECT id, channel_id, kind, threshold, repeating, created_at
+            FROM milestone
+            WHERE channel_id = $1 OR channel_id IS NULL
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn insert(
+        &self,
+        channel_id: Option<&ChannelId>,
+        kind: MilestoneKind,
+        threshold: i64,
+        repeating: bool,
+    ) -> SqlxResult<Milestone> {
+        sqlx::query_as::<_, Milestone>(
+            r#"
+            INSERT INTO milestone (channel_id, kind, threshold, repeating)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, channel_id, kind, threshold, repeating, created_at
+            "#,
+        )
+        .bind(channel_id)
+        .bind(kind.as_str())
+        .bind(threshold)
+        .bind(repeating)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Returns the deleted milestone, or `None` if there was no milestone with the id.
+    #[instrument(skip(self))]
+    pub async fn delete(&self, id: i32) -> SqlxResult<Option<Milestone>> {
+        sqlx::query_as::<_, Milestone>(
+            r#"
+            DELETE FROM milestone
+            WHERE id = $1
+            RETURNING id, channel_id, kind, threshold, repeating, created_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await
+    }
+}
@@ -8,11 +8,35 @@ use tracing::instrument;
 
 use crate::db::models::channel::ChannelId;
 use crate::db::models::chatter::ChatterId;
+use crate::db::models::keyword::KeywordId;
 use crate::db::prelude::{Channel, Chatter, ScoreSummary};
 
+pub mod alias;
+pub mod archive;
+pub mod audit;
 pub mod channel;
 pub mod chatter;
+pub mod deletion;
+pub mod digest;
+pub mod export;
+pub mod flag;
+pub mod heatmap;
+pub mod integration;
+pub mod keyword;
 pub mod leaderboard;
+pub mod metric;
+pub mod milestone;
+pub mod note;
+pub mod period;
+pub mod profile;
+pub mod raid;
+pub mod rank;
+pub mod search;
+pub mod settings;
+pub mod stats;
+pub mod stream;
+pub mod subscription;
+pub mod token;
 
 pub struct Tx<'a> {
     inner: Option<Transaction<'a, Postgres>>,
@@ -67,6 +91,8 @@ impl<'a> Tx<'a> {
 
     #[instrument(skip(self, item))]
     pub async fn insert_chatter(&mut self, item: &Chatter) -> SqlxResult<()> {
+        item.id.validate()?;
+
         sqlx::query!(
             r#"
             INSERT INTO chatter (
@@ -168,8 +194,10 @@ impl<'a> Tx<'a> {
         &mut self,
         chatter_id: &ChatterId,
         channel_id: &ChannelId,
+        keyword_id: &KeywordId,
     ) -> SqlxResult<ScoreSummary> {
-        self.increment_score_by(chatter_id, channel_id, 1).await
+        self.increment_score_by(chatter_id, channel_id, keyword_id, 1)
+            .await
     }
 
     #[instrument(skip(self, chatter_id, channel_id, score))]
@@ -177,6 +205,7 @@ impl<'a> Tx<'a> {
         &mut self,
         chatter_id: &ChatterId,
         channel_id: &ChannelId,
+        keyword_id: &KeywordId,
         score: i64,
     ) -> SqlxResult<ScoreSummary> {
         sqlx::query_as::<_, ScoreSummary>(
@@ -184,14 +213,15 @@ impl<'a> Tx<'a> {
             INSERT INTO score (
                 channel_id,
                 chatter_id,
+                keyword_id,
                 score,
                 created_at,
                 updated_at
             )
-            VALUES ($1, $2, $3, NOW(), NOW())
-            ON CONFLICT (chatter_id, channel_id)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            ON CONFLICT (chatter_id, channel_id, keyword_id)
             DO UPDATE SET
-                score = score.score + $3,
+                score = score.score + $4,
                 updated_at = NOW()
             RETURNING 
                 chatter_id, 
@@ -201,6 +231,7 @@ impl<'a> Tx<'a> {
         )
         .bind(channel_id)
         .bind(chatter_id)
+        .bind(keyword_id)
         .bind(score)
         .fetch_one(&mut **self.inner_mut()?)
         .await
@@ -238,11 +269,12 @@ impl<'a> Tx<'a> {
     }
 
     #[instrument(skip(self, chatter_id, channel_id, score))]
-    /// Alternatively 'set_score' - overwrites the score referenced by the foreign key `(channel_id, chatter_id)`
+    /// Alternatively 'set_score' - overwrites the score referenced by the foreign key `(channel_id, chatter_id, keyword_id)`
     pub async fn update_score(
         &mut self,
         chatter_id: &ChatterId,
         channel_id: &ChannelId,
+        keyword_id: &KeywordId,
         score: i64,
     ) -> SqlxResult<ScoreSummary> {
         sqlx::query_as::<_, ScoreSummary>(
@@ -250,14 +282,15 @@ impl<'a> Tx<'a> {
             INSERT INTO score (
                 channel_id,
                 chatter_id,
+                keyword_id,
                 score,
                 created_at,
                 updated_at
             )
-            VALUES ($1, $2, $3, NOW(), NOW())
-            ON CONFLICT (chatter_id, channel_id)
+            VALUES ($1, $2, $3, $4, NOW(), NOW())
+            ON CONFLICT (chatter_id, channel_id, keyword_id)
             DO UPDATE SET
-                score = $3,
+                score = $4,
                 updated_at = NOW()
             RETURNING 
                 chatter_id, 
@@ -267,6 +300,7 @@ impl<'a> Tx<'a> {
         )
         .bind(channel_id)
         .bind(chatter_id)
+        .bind(keyword_id)
         .bind(score)
         .fetch_one(&mut **self.inner_mut()?)
         .await
@@ -0,0 +1,206 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::note::{ChatterNote, ChatterNoteAudit};
+
+pub struct NoteRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl NoteRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_for_channel(&self, channel_id: &ChannelId) -> SqlxResult<Vec<ChatterNote>> {
+        sqlx::query_as::<_, ChatterNote>(
+            r#"
+            SELECT
+                n.channel_id,
+                n.chatter_id,
+                c.login AS chatter_login,
+                n.note,
+                n.flags,
+                n.created_at,
+                n.updated_at
+            FROM chatter_note n
+            JOIN chatter c ON c.id = n.chatter_id
+            WHERE n.channel_id = $1
+            ORDER BY n.updated_at DESC
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+    ) -> SqlxResult<Option<ChatterNote>> {
+        sqlx::query_as::<_, ChatterNote>(
+            r#"
+            SELECT
+                n.channel_id,
+                n.chatter_id,
+                c.login AS chatter_login,
+                n.note,
+                n.flags,
+                n.created_at,
+                n.updated_at
+            FROM chatter_note n
+            JOIN chatter c ON c.id = n.chatter_id
+            WHERE n.channel_id = $1
+            AND n.chatter_id = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves every edit made to a chatter's note in a channel, newest first.
+    #[instrument(skip(self))]
+    pub async fn get_history(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+    ) -> SqlxResult<Vec<ChatterNoteAudit>> {
+        sqlx::query_as::<_, ChatterNoteAudit>(
+            r#"
+            SELECT
+                id,
+                action,
+                note,
+                flags,
+                author,
+                session_id,
+                changed_at
+            FROM chatter_note_audit
+            WHERE channel_id = $1
+            AND chatter_id = $2
+            ORDER BY changed_at DESC, id DESC
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Creates or replaces a chatter's note, recording the edit in the audit trail.
+    #[instrument(skip(self, note))]
+    pub async fn upsert(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+        note: &str,
+        flags: &[String],
+        author: &str,
+        session_id: Option<i32>,
+    ) -> SqlxResult<()> {
+        channel_id.validate()?;
+        chatter_id.validate()?;
+
+        let mut tx = self.pool.begin().await?;
+        let created = sqlx::query_scalar::<_, bool>(
+            r#"
+            INSERT INTO chatter_note (channel_id, chatter_id, note, flags)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (channel_id, chatter_id)
+            DO UPDATE SET
+                note = $3,
+                flags = $4,
+                updated_at = NOW()
+            RETURNING (xmax = 0)
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .bind(note)
+        .bind(flags)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let action = if created { "create" } else { "update" };
+        sqlx::query(
+            r#"
+            INSERT INTO chatter_note_audit (
+                channel_id,
+                chatter_id,
+                action,
+                note,
+                flags,
+                author,
+                session_id
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .bind(action)
+        .bind(note)
+        .bind(flags)
+        .bind(author)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes a chatter's note, recording the deletion in the audit trail.
+    ///
+    /// Returns false if there was no note to delete.
+    #[instrument(skip(self))]
+    pub async fn delete(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+        author: &str,
+        session_id: Option<i32>,
+    ) -> SqlxResult<bool> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM chatter_note
+            WHERE channel_id = $1
+            AND chatter_id = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+
+        if !deleted {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO chatter_note_audit (channel_id, chatter_id, action, author, session_id)
+            VALUES ($1, $2, 'delete', $3, $4)
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .bind(author)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+}
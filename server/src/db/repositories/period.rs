@@ -0,0 +1,316 @@
+use chrono::NaiveDateTime;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::leaderboard::{Period, PeriodCapture, PeriodLeaderboardRow};
+
+/// Every (channel, chatter) score in the current period of `$1`, from the latest snapshot - or
+/// rolled up from `score_event` if the period hasn't been captured yet
+const PERIOD_SCORES: &str = r#"
+    current AS (
+        SELECT channel_id, chatter_id, score
+        FROM leaderboard_snapshot
+        WHERE period = $1 AND period_start = date_trunc($1, CURRENT_TIMESTAMP)
+    ),
+    deleted AS (
+        SELECT id FROM chatter WHERE deleted_at IS NOT NULL
+    ),
+    scores AS (
+        SELECT channel_id, chatter_id, score FROM current
+        WHERE chatter_id NOT IN (SELECT id FROM deleted)
+        UNION ALL
+        SELECT channel_id, chatter_id, COUNT(*)::INT8 AS score
+        FROM score_event
+        WHERE NOT EXISTS (SELECT 1 FROM current)
+        AND earned_at >= date_trunc($1, CURRENT_TIMESTAMP)
+        AND chatter_id NOT IN (SELECT id FROM deleted)
+        GROUP BY channel_id, chatter_id
+    )
+"#;
+
+pub struct PeriodRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl PeriodRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Captures the current period's scores into `leaderboard_snapshot`, replacing its previous
+    /// capture.
+    ///
+    /// The previous period is recaptured too until it has been captured after it ended, so that
+    /// its snapshot includes events from the last interval before the boundary; the capture that
+    /// does so reports the previous period as finalised.
+    #[instrument(skip(self))]
+    pub async fn capture(&self, period: Period) -> SqlxResult<PeriodCapture> {
+        let Some(field) = period.as_date_trunc_field() else {
+            return Ok(PeriodCapture::default());
+        };
+
+        let mut tx = self.pool.begin().await?;
+        let (previous_is_final, previous_start) = sqlx::query_as::<_, (bool, NaiveDateTime)>(
+            r#"
+            SELECT
+                COALESCE(MAX(captured_at) >= date_trunc($1, CURRENT_TIMESTAMP), false),
+                date_trunc($1, CURRENT_TIMESTAMP - ('1 ' || $1)::interval)::timestamp
+            FROM leaderboard_snapshot
+            WHERE period = $1
+            AND period_start = date_trunc($1, CURRENT_TIMESTAMP - ('1 ' || $1)::interval)
+            "#,
+        )
+        .bind(field)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let periods_ago: &[i32] = if previous_is_final { &[0] } else { &[0, 1] };
+
+        let mut capture = PeriodCapture::default();
+        for &ago in periods_ago {
+            sqlx::query(
+                r#"
+                DELETE FROM leaderboard_snapshot
+                WHERE period = $1
+                AND period_start = date_trunc($1, CURRENT_TIMESTAMP - $2 * ('1 ' || $1)::interval)
+                "#,
+            )
+            .bind(field)
+            .bind(ago)
+            .execute(&mut *tx)
+            .await?;
+
+            let written = sqlx::query(
+                r#"
+                INSERT INTO leaderboard_snapshot (period, period_start, channel_id, chatter_id, score)
+                SELECT
+                    $1,
+                    date_trunc($1, CURRENT_TIMESTAMP - $2 * ('1 ' || $1)::interval),
+                    channel_id,
+                    chatter_id,
+                    COUNT(*)
+                FROM score_event
+                WHERE earned_at >= date_trunc($1, CURRENT_TIMESTAMP - $2 * ('1 ' || $1)::interval)
+                AND earned_at < date_trunc($1, CURRENT_TIMESTAMP - ($2 - 1) * ('1 ' || $1)::interval)
+                GROUP BY channel_id, chatter_id
+                "#,
+            )
+            .bind(field)
+            .bind(ago)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            // an empty period is recaptured on every run, so it's never reported as finalised
+            if ago == 1 && written > 0 {
+                capture.finalised = Some(previous_start);
+            }
+            capture.written += written;
+        }
+
+        tx.commit().await?;
+        Ok(capture)
+    }
+
+    /// Retrieves the top `limit` chatters in a channel within the period starting at
+    /// `period_start`, as (login, score) pairs.
+    #[instrument(skip(self))]
+    pub async fn get_channel_top_chatters(
+        &self,
+        period: Period,
+        period_start: NaiveDateTime,
+        channel_id: &ChannelId,
+        limit: i64,
+    ) -> SqlxResult<Vec<(String, i64)>> {
+        let field = snapshotted_field(period)?;
+
+        sqlx::query_as::<_, (String, i64)>(
+            r#"
+            SELECT c.login, s.score
+            FROM leaderboard_snapshot s
+            JOIN chatter c ON c.id = s.chatter_id
+            WHERE s.period = $1 AND s.period_start = $2 AND s.channel_id = $3
+            AND c.deleted_at IS NULL
+            ORDER BY s.score DESC, s.chatter_id ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(field)
+        .bind(period_start)
+        .bind(channel_id)
+        .bind(limit)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves chatters ranked by their score within the current `period`, across all channels.
+    ///
+    /// Entries don't include per-channel scores. `Period::All` is rejected, as it should use
+    /// `LeaderboardRepository::get_chatter_leaderboard` instead.
+    #[instrument(skip(self))]
+    pub async fn get_chatter_leaderboard(
+        &self,
+        period: Period,
+        limit: i64,
+        offset: i64,
+    ) -> SqlxResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        let field = snapshotted_field(period)?;
+
+        let total_items = sqlx::query_scalar::<_, i64>(&format!(
+            "WITH {PERIOD_SCORES} SELECT COUNT(DISTINCT chatter_id) FROM scores"
+        ))
+        .bind(field)
+        .fetch_one(self.pool)
+        .await?;
+
+        let rows = sqlx::query_as::<_, PeriodLeaderboardRow>(&format!(
+            r#"
+            WITH {PERIOD_SCORES},
+            ranked AS (
+                SELECT
+                    chatter_id,
+                    SUM(score)::INT8 AS score,
+                    COUNT(*) AS score_count,
+                    ROW_NUMBER() OVER (ORDER BY SUM(score) DESC, chatter_id ASC) AS ranking
+                FROM scores
+                GROUP BY chatter_id
+            )
+            SELECT
+                c.id,
+                c.login,
+                c.name,
+                c.color,
+                c.image,
+                r.score,
+                r.score AS score_as_chatter,
+                r.ranking,
+                r.score_count
+            FROM ranked r
+            JOIN chatter c ON c.id = r.chatter_id
+            ORDER BY r.ranking ASC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(field)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool)
+        .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| ChatterLeaderboardEntry {
+                id: ChatterId(row.id),
+                login: row.login,
+                name: row.name,
+                color: row.color,
+                image: row.image,
+                total: row.score,
+                ranking: row.ranking,
+                channel_scores: Vec::new(),
+                total_scores: row.score_count,
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            total_items,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+
+    /// Retrieves channels ranked by the score earned in them within the current `period`.
+    ///
+    /// Entries don't include per-chatter scores. `Period::All` is rejected, as it should use
+    /// `LeaderboardRepository::get_channel_leaderboard` instead.
+    #[instrument(skip(self))]
+    pub async fn get_channel_leaderboard(
+        &self,
+        period: Period,
+        limit: i64,
+        offset: i64,
+    ) -> SqlxResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        let field = snapshotted_field(period)?;
+
+        let total_items = sqlx::query_scalar::<_, i64>(&format!(
+            "WITH {PERIOD_SCORES} SELECT COUNT(DISTINCT channel_id) FROM scores"
+        ))
+        .bind(field)
+        .fetch_one(self.pool)
+        .await?;
+
+        let rows = sqlx::query_as::<_, PeriodLeaderboardRow>(&format!(
+            r#"
+            WITH {PERIOD_SCORES},
+            ranked AS (
+                SELECT
+                    channel_id,
+                    SUM(score)::INT8 AS score,
+                    COUNT(*) AS score_count,
+                    ROW_NUMBER() OVER (ORDER BY SUM(score) DESC, channel_id ASC) AS ranking
+                FROM scores
+                GROUP BY channel_id
+            ),
+            as_chatter AS (
+                SELECT chatter_id, SUM(score)::INT8 AS score
+                FROM scores
+                WHERE chatter_id IN (SELECT channel_id FROM ranked)
+                GROUP BY chatter_id
+            )
+            SELECT
+                c.id,
+                c.login,
+                c.name,
+                c.color,
+                c.image,
+                r.score,
+                COALESCE(a.score, 0) AS score_as_chatter,
+                r.ranking,
+                r.score_count
+            FROM ranked r
+            JOIN chatter c ON c.id = r.channel_id
+            LEFT JOIN as_chatter a ON a.chatter_id = r.channel_id
+            ORDER BY r.ranking ASC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(field)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool)
+        .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| ChannelLeaderboardEntry {
+                id: ChannelId(row.id),
+                login: row.login,
+                name: row.name,
+                color: row.color,
+                image: row.image,
+                total_channel: row.score,
+                total_chatter: row.score_as_chatter,
+                ranking: row.ranking,
+                chatter_scores: Vec::new(),
+                total_scores: row.score_count,
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            total_items,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+}
+
+fn snapshotted_field(period: Period) -> SqlxResult<&'static str> {
+    period.as_date_trunc_field().ok_or_else(|| {
+        sqlx::Error::InvalidArgument(format!("{period:?} leaderboards aren't snapshotted"))
+    })
+}
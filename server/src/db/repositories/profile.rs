@@ -0,0 +1,78 @@
+use chrono::NaiveDate;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::profile::{ChatterProfileRow, DailyScore};
+
+pub struct ProfileRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl ProfileRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Retrieves a chatter's global standing and first/last counted times; `None` if there's no
+    /// (visible) chatter with the login.
+    #[instrument(skip(self))]
+    pub async fn get_by_login(&self, login: &str) -> SqlxResult<Option<ChatterProfileRow>> {
+        sqlx::query_as::<_, ChatterProfileRow>(
+            r#"
+            SELECT
+                c.id,
+                c.login,
+                c.name,
+                c.color,
+                c.image,
+                c.total,
+                c.ranking,
+                seen.first_seen,
+                seen.last_seen
+            FROM chatter_leaderboard c
+            LEFT JOIN LATERAL (
+                SELECT
+                    -- archived events are only kept to the hour, in their rollups
+                    LEAST(
+                        MIN(earned_at),
+                        (SELECT MIN(hour) FROM score_event_rollup WHERE chatter_id = c.id)
+                    ) AS first_seen,
+                    GREATEST(
+                        MAX(earned_at),
+                        (SELECT MAX(hour) FROM score_event_rollup WHERE chatter_id = c.id)
+                    ) AS last_seen
+                FROM score_event
+                WHERE chatter_id = c.id
+            ) seen ON TRUE
+            WHERE c.login = $1
+            "#,
+        )
+        .bind(login)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves a chatter's scores across every channel for each day since `since`, for days
+    /// with any.
+    #[instrument(skip(self))]
+    pub async fn get_daily(
+        &self,
+        chatter_id: &ChatterId,
+        since: NaiveDate,
+    ) -> SqlxResult<Vec<DailyScore>> {
+        sqlx::query_as::<_, DailyScore>(
+            r#"
+            SELECT date_trunc('day', earned_at)::date AS day, COUNT(*) AS total
+            FROM score_event
+            WHERE chatter_id = $1 AND earned_at >= $2
+            GROUP BY day
+            ORDER BY day
+            "#,
+        )
+        .bind(chatter_id)
+        .bind(since)
+        .fetch_all(self.pool)
+        .await
+    }
+}
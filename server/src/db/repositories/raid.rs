@@ -0,0 +1,60 @@
+use chrono::NaiveDateTime;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::raid::RaidEvent;
+
+pub struct RaidRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl RaidRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn insert(
+        &self,
+        channel_id: &ChannelId,
+        from_broadcaster_id: &str,
+        from_broadcaster_login: &str,
+        viewers: i64,
+    ) -> SqlxResult<RaidEvent> {
+        sqlx::query_as::<_, RaidEvent>(
+            r#"
+            INSERT INTO raid_event (channel_id, from_broadcaster_id, from_broadcaster_login, viewers)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, channel_id, from_broadcaster_id, from_broadcaster_login, viewers, raided_at
+            "#,
+        )
+        .bind(channel_id)
+        .bind(from_broadcaster_id)
+        .bind(from_broadcaster_login)
+        .bind(viewers)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Retrieves the raids a channel has received since `since`, oldest first.
+    #[instrument(skip(self))]
+    pub async fn get_since(
+        &self,
+        channel_id: &ChannelId,
+        since: NaiveDateTime,
+    ) -> SqlxResult<Vec<RaidEvent>> {
+        sqlx::query_as::<_, RaidEvent>(
+            r#"
+            SELECT id, channel_id, from_broadcaster_id, from_broadcaster_login, viewers, raided_at
+            FROM raid_event
+            WHERE channel_id = $1 AND raided_at >= $2
+            ORDER BY raided_at
+            "#,
+        )
+        .bind(channel_id)
+        .bind(since)
+        .fetch_all(self.pool)
+        .await
+    }
+}
@@ -0,0 +1,121 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::{Chatter, ChatterId};
+use crate::db::models::rank::{ChannelRank, Contender, GlobalRank, Versus};
+
+/// Looks up a single chatter's rank without ranking everyone else.
+///
+/// Ranks are counted rather than windowed, matching the ordering of
+/// `ranked_scores_view_per_channel`: higher scores first, then whoever scored first.
+pub struct RankRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl RankRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Returns `None` if the chatter has no score on the channel.
+    #[instrument(skip(self))]
+    pub async fn get_channel_rank(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+    ) -> SqlxResult<Option<ChannelRank>> {
+        sqlx::query_as::<_, ChannelRank>(
+            r#"
+            WITH totals AS (
+                SELECT
+                    chatter_id,
+                    SUM(score)::INT8 AS score,
+                    MIN(created_at) AS created_at
+                FROM score s
+                WHERE channel_id = $1
+                AND NOT EXISTS (
+                    SELECT 1 FROM chatter c
+                    WHERE c.id = s.chatter_id AND c.deleted_at IS NOT NULL
+                )
+                GROUP BY chatter_id
+            ),
+            target AS (
+                SELECT score, created_at
+                FROM totals
+                WHERE chatter_id = $2
+            )
+            SELECT
+                $1 AS channel_id,
+                $2 AS chatter_id,
+                t.score,
+                (
+                    SELECT COUNT(*)
+                    FROM totals o
+                    WHERE o.score > t.score
+                    OR (o.score = t.score AND o.created_at < t.created_at)
+                ) + 1 AS rank,
+                (SELECT COUNT(*) FROM totals) AS ranked_chatters
+            FROM target t
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Returns `None` if the chatter isn't on the global leaderboard.
+    #[instrument(skip(self))]
+    pub async fn get_global_rank(&self, chatter_id: &ChatterId) -> SqlxResult<Option<GlobalRank>> {
+        sqlx::query_as::<_, GlobalRank>(
+            r#"
+            SELECT id AS chatter_id, total AS score, ranking AS rank
+            FROM chatter_leaderboard
+            WHERE id = $1
+            "#,
+        )
+        .bind(chatter_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Compares two chatters on a channel, if one is given, and globally.
+    #[instrument(skip(self, chatters), fields(chatters = ?chatters.each_ref().map(|c| &c.login)))]
+    pub async fn get_versus(
+        &self,
+        channel_id: Option<&ChannelId>,
+        chatters: [Chatter; 2],
+    ) -> SqlxResult<Versus> {
+        let [a, b] = chatters;
+        let contenders = [
+            self.get_contender(channel_id, a).await?,
+            self.get_contender(channel_id, b).await?,
+        ];
+
+        Ok(Versus {
+            channel_id: channel_id.cloned(),
+            contenders,
+        })
+    }
+
+    async fn get_contender(
+        &self,
+        channel_id: Option<&ChannelId>,
+        chatter: Chatter,
+    ) -> SqlxResult<Contender> {
+        let channel = match channel_id {
+            Some(channel_id) => self.get_channel_rank(channel_id, &chatter.id).await?,
+            None => None,
+        };
+        let global = self.get_global_rank(&chatter.id).await?;
+
+        Ok(Contender {
+            chatter_id: chatter.id,
+            login: chatter.login,
+            name: chatter.name,
+            channel,
+            global,
+        })
+    }
+}
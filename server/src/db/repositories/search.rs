@@ -0,0 +1,115 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::search::UserSearchMatch;
+
+/// Matches a login or (lowercased) display name against the query (`$1`) by prefix (`$2`) or
+/// trigram similarity; the gist indexes on `login` and `lower(name)` cover both.
+const USER_MATCH: &str = r#"
+    (
+        c.login LIKE $2
+        OR lower(c.name) LIKE $2
+        OR c.login % $1
+        OR lower(c.name) % $1
+    )
+    AND c.deleted_at IS NULL
+"#;
+
+/// Searches chatters and channels together, for autocompletion.
+pub struct SearchRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl SearchRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// `query` is expected to be lowercased already. Results are ranked with an exact login match
+    /// first, then prefix matches, then by similarity, with higher totals breaking ties.
+    #[instrument(skip(self))]
+    pub async fn search_users(
+        &self,
+        query: &str,
+        limit: i64,
+        offset: i64,
+    ) -> SqlxResult<PaginatedResponse<UserSearchMatch>> {
+        let prefix = format!("{}%", escape_like(query));
+
+        let items = sqlx::query_as::<_, UserSearchMatch>(&format!(
+            r#"
+            SELECT
+                c.id,
+                c.login,
+                c.name,
+                c.color,
+                c.image,
+                c.total,
+                ch.channel_total,
+                GREATEST(similarity(c.login, $1), similarity(lower(c.name), $1)) AS similarity
+            FROM chatter c
+            LEFT JOIN channel ch ON ch.id = c.id
+            WHERE {USER_MATCH}
+            ORDER BY
+                c.login = $1 DESC,
+                (c.login LIKE $2 OR lower(c.name) LIKE $2) DESC,
+                similarity DESC,
+                c.total DESC,
+                c.login
+            LIMIT $3
+            OFFSET $4
+            "#
+        ))
+        .bind(query)
+        .bind(&prefix)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(self.pool)
+        .await?;
+
+        let total = sqlx::query_scalar::<_, i64>(&format!(
+            r#"
+            SELECT COUNT(*)
+            FROM chatter c
+            WHERE {USER_MATCH}
+            "#
+        ))
+        .bind(query)
+        .bind(&prefix)
+        .fetch_one(self.pool)
+        .await?;
+
+        Ok(PaginatedResponse::new(
+            items,
+            total,
+            limit,
+            offset / limit + 1,
+        ))
+    }
+}
+
+/// Escapes `LIKE` wildcards - underscores are common in logins.
+fn escape_like(query: &str) -> String {
+    let mut escaped = String::with_capacity(query.len());
+    for c in query.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("plss"), "plss");
+        assert_eq!(escape_like("pls_u"), "pls\\_u");
+        assert_eq!(escape_like("100%\\"), "100\\%\\\\");
+    }
+}
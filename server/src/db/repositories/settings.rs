@@ -0,0 +1,186 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::chatter::ChatterId;
+use crate::db::models::settings::{BlacklistedChatter, ChannelSettings};
+
+pub struct SettingsRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl SettingsRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Returns `None` if the id isn't a tracked channel. Tracked channels without stored settings
+    /// get the defaults.
+    #[instrument(skip(self))]
+    pub async fn get(&self, channel_id: &ChannelId) -> SqlxResult<Option<ChannelSettings>> {
+        sqlx::query_as::<_, ChannelSettings>(
+            r#"
+            SELECT
+                COALESCE(r.mode, 'silent') AS reply_mode,
+                COALESCE(s.muted_keywords, '{}') AS muted_keywords,
+                COALESCE(s.command_cooldown_secs, 0) AS command_cooldown_secs,
+                COALESCE(s.milestones, true) AS milestones,
+                COALESCE(s.raid_thanks, true) AS raid_thanks,
+                s.digest_hour,
+                COALESCE(s.digest_in_chat, false) AS digest_in_chat,
+                ARRAY(
+                    SELECT b.chatter_id::text
+                    FROM channel_blacklist b
+                    WHERE b.channel_id = c.id
+                    ORDER BY b.chatter_id
+                ) AS blacklist
+            FROM channel c
+            LEFT JOIN reply r ON r.id = c.id
+            LEFT JOIN channel_settings s ON s.channel_id = c.id
+            WHERE c.id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Replaces a channel's settings. Returns false if the id isn't a tracked channel.
+    #[instrument(skip(self))]
+    pub async fn set(
+        &self,
+        channel_id: &ChannelId,
+        settings: &ChannelSettings,
+    ) -> SqlxResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO channel_settings (
+                channel_id,
+                muted_keywords,
+                command_cooldown_secs,
+                milestones,
+                raid_thanks,
+                digest_hour,
+                digest_in_chat
+            )
+            SELECT id, $2, $3, $4, $5, $6, $7
+            FROM channel
+            WHERE id = $1
+            ON CONFLICT (channel_id)
+            DO UPDATE SET
+                muted_keywords = $2,
+                command_cooldown_secs = $3,
+                milestones = $4,
+                raid_thanks = $5,
+                digest_hour = $6,
+                digest_in_chat = $7,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(channel_id)
+        .bind(&settings.muted_keywords)
+        .bind(settings.command_cooldown_secs)
+        .bind(settings.milestones)
+        .bind(settings.raid_thanks)
+        .bind(settings.digest_hour)
+        .bind(settings.digest_in_chat)
+        .execute(&mut *tx)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO reply (id, mode)
+            VALUES ($1, $2)
+            ON CONFLICT (id)
+            DO UPDATE SET
+                mode = $2,
+                updated_at = NOW()
+            WHERE reply.mode <> $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(settings.reply_mode.as_str())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_blacklist(
+        &self,
+        channel_id: &ChannelId,
+    ) -> SqlxResult<Vec<BlacklistedChatter>> {
+        sqlx::query_as::<_, BlacklistedChatter>(
+            r#"
+            SELECT
+                b.chatter_id,
+                COALESCE(c.login, b.login) AS login,
+                b.created_at
+            FROM channel_blacklist b
+            LEFT JOIN chatter c ON c.id = b.chatter_id
+            WHERE b.channel_id = $1
+            ORDER BY b.created_at DESC
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Returns false if the chatter was already blacklisted in the channel.
+    #[instrument(skip(self))]
+    pub async fn add_to_blacklist(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+        login: &str,
+    ) -> SqlxResult<bool> {
+        chatter_id.validate()?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO channel_blacklist (channel_id, chatter_id, login)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (channel_id, chatter_id)
+            DO NOTHING
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .bind(login)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns false if the chatter wasn't blacklisted in the channel.
+    #[instrument(skip(self))]
+    pub async fn remove_from_blacklist(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+    ) -> SqlxResult<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM channel_blacklist
+            WHERE channel_id = $1
+            AND chatter_id = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(chatter_id)
+        .execute(self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
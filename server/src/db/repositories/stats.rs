@@ -0,0 +1,178 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::overlay::Overlay;
+use crate::db::models::stats::{ChannelCountStats, MinuteCount, TableStats};
+
+pub struct StatsRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl StatsRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Retrieves the row count of every table, along with the most recent write to the tables
+    /// that record one.
+    #[instrument(skip(self))]
+    pub async fn get_table_stats(&self) -> SqlxResult<Vec<TableStats>> {
+        sqlx::query_as::<_, TableStats>(
+            r#"
+            WITH last_write(table_name, last_write_at) AS (
+                SELECT 'chatter', MAX(updated_at) FROM chatter
+                UNION ALL SELECT 'channel', MAX(updated_at) FROM channel
+                UNION ALL SELECT 'score', MAX(updated_at) FROM score
+                UNION ALL SELECT 'score_event', MAX(earned_at) FROM score_event
+                UNION ALL SELECT 'keyword', MAX(created_at) FROM keyword
+                UNION ALL SELECT 'alias', MAX(detected_at) FROM alias
+                UNION ALL SELECT 'chatter_note', MAX(updated_at) FROM chatter_note
+                UNION ALL SELECT 'stream_status', MAX(updated_at) FROM stream_status
+                UNION ALL SELECT 'eventsub_subscription', MAX(updated_at) FROM eventsub_subscription
+                UNION ALL SELECT 'leaderboard_snapshot', MAX(captured_at) FROM leaderboard_snapshot
+            )
+            SELECT
+                s.relname::text AS table_name,
+                s.n_live_tup AS estimated_rows,
+                w.last_write_at
+            FROM pg_stat_user_tables s
+            LEFT JOIN last_write w ON w.table_name = s.relname
+            WHERE s.schemaname = 'public'
+            ORDER BY s.relname
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves a channel's total, number of counted chatters, top chatter and count for the
+    /// current day (in the channel's timezone), or `None` if the channel isn't known.
+    #[instrument(skip(self))]
+    pub async fn get_channel_count_stats(
+        &self,
+        channel_id: &ChannelId,
+    ) -> SqlxResult<Option<ChannelCountStats>> {
+        sqlx::query_as::<_, ChannelCountStats>(
+            r#"
+            WITH day AS (
+                SELECT
+                    c.channel_total,
+                    date_trunc('day', to_channel_local(NOW() AT TIME ZONE 'UTC', c.timezone))
+                        AS local_start,
+                    c.timezone
+                FROM channel c
+                WHERE c.id = $1
+            )
+            SELECT
+                d.channel_total AS total,
+                (SELECT COUNT(*) FROM score WHERE channel_id = $1 AND score > 0) AS unique_chatters,
+                top.chatter_id AS top_chatter_id,
+                top.login AS top_chatter_login,
+                top.score AS top_chatter_score,
+                (
+                    SELECT COUNT(*) FROM score_event
+                    WHERE channel_id = $1
+                    AND earned_at >= (d.local_start AT TIME ZONE d.timezone) AT TIME ZONE 'UTC'
+                ) AS today,
+                ((d.local_start + INTERVAL '1 day') AT TIME ZONE d.timezone) AT TIME ZONE 'UTC'
+                    AS day_ends_at
+            FROM day d
+            LEFT JOIN LATERAL (
+                SELECT s.chatter_id, ch.login, s.score
+                FROM score s
+                JOIN chatter ch ON ch.id = s.chatter_id
+                WHERE s.channel_id = $1 AND s.score > 0
+                ORDER BY s.score DESC, s.created_at ASC
+                LIMIT 1
+            ) top ON true
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves a channel's score counts for each minute of the last hour, oldest first.
+    #[instrument(skip(self))]
+    pub async fn get_recent_minute_counts(
+        &self,
+        channel_id: &ChannelId,
+    ) -> SqlxResult<Vec<MinuteCount>> {
+        sqlx::query_as::<_, MinuteCount>(
+            r#"
+            SELECT
+                (EXTRACT(EPOCH FROM date_trunc('minute', earned_at)) / 60)::int8 AS minute,
+                COUNT(*) AS count
+            FROM score_event
+            WHERE channel_id = $1
+            AND earned_at >= NOW() AT TIME ZONE 'UTC' - INTERVAL '1 hour'
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves what a channel's overlay shows - its total, the chatter most recently counted
+    /// there and its top chatters - or `None` if the channel isn't known.
+    #[instrument(skip(self))]
+    pub async fn get_overlay(&self, login: &str, top: i64) -> SqlxResult<Option<Overlay>> {
+        sqlx::query_as::<_, Overlay>(
+            r#"
+            SELECT
+                c.id AS channel_id,
+                b.login AS channel,
+                c.channel_total AS total,
+                (
+                    SELECT jsonb_build_object(
+                        'login', ch.login,
+                        'name', ch.name,
+                        'color', ch.color,
+                        'counted_at', e.earned_at
+                    )
+                    FROM score_event e
+                    JOIN chatter ch ON ch.id = e.chatter_id
+                    WHERE e.channel_id = c.id
+                    AND ch.deleted_at IS NULL
+                    ORDER BY e.earned_at DESC
+                    LIMIT 1
+                ) AS last_incrementer,
+                (
+                    SELECT COALESCE(
+                        jsonb_agg(
+                            jsonb_build_object(
+                                'login', t.login,
+                                'name', t.name,
+                                'color', t.color,
+                                'image', t.image,
+                                'score', t.score
+                            )
+                            ORDER BY t.score DESC, t.created_at ASC
+                        ),
+                        '[]'
+                    )
+                    FROM (
+                        SELECT ch.login, ch.name, ch.color, ch.image, s.score, s.created_at
+                        FROM score s
+                        JOIN chatter ch ON ch.id = s.chatter_id
+                        WHERE s.channel_id = c.id
+                        AND s.score > 0
+                        AND ch.deleted_at IS NULL
+                        ORDER BY s.score DESC, s.created_at ASC
+                        LIMIT $2
+                    ) t
+                ) AS top
+            FROM channel c
+            JOIN chatter b ON b.id = c.id
+            WHERE b.login = $1
+            "#,
+        )
+        .bind(login)
+        .bind(top)
+        .fetch_optional(self.pool)
+        .await
+    }
+}
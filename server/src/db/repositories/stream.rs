@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::stream::{LiveStream, StreamStatus};
+
+pub struct StreamStatusRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl StreamStatusRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_by_channel(&self, channel_id: &ChannelId) -> SqlxResult<Option<StreamStatus>> {
+        sqlx::query_as::<_, StreamStatus>(
+            r#"
+            SELECT
+                s.channel_id,
+                s.is_live,
+                s.viewer_count,
+                s.game,
+                s.title,
+                s.started_at,
+                s.updated_at,
+                COALESCE(c.paused, FALSE) AS paused
+            FROM stream_status s
+            LEFT JOIN channel c ON c.id = s.channel_id
+            WHERE s.channel_id = $1
+            "#,
+        )
+        .bind(channel_id)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Retrieves the stream status of each of `channel_ids` that has one.
+    #[instrument(skip(self, channel_ids), fields(channels = channel_ids.len()))]
+    pub async fn get_by_channels(
+        &self,
+        channel_ids: &[ChannelId],
+    ) -> SqlxResult<Vec<StreamStatus>> {
+        let ids: Vec<&str> = channel_ids.iter().map(|id| id.0.as_str()).collect();
+
+        sqlx::query_as::<_, StreamStatus>(
+            r#"
+            SELECT
+                s.channel_id,
+                s.is_live,
+                s.viewer_count,
+                s.game,
+                s.title,
+                s.started_at,
+                s.updated_at,
+                COALESCE(c.paused, FALSE) AS paused
+            FROM stream_status s
+            LEFT JOIN channel c ON c.id = s.channel_id
+            WHERE s.channel_id = ANY($1)
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(self.pool)
+        .await
+    }
+
+    /// Retrieves the IDs of every channel that was live as of the last refresh.
+    #[instrument(skip(self))]
+    pub async fn get_live_channel_ids(&self) -> SqlxResult<HashSet<ChannelId>> {
+        let ids = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT channel_id
+            FROM stream_status
+            WHERE is_live
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(ids.into_iter().map(ChannelId).collect())
+    }
+
+    /// Replaces the live state of the tracked channels in one transaction; any tracked channel
+    /// without an entry in `live` is marked offline.
+    ///
+    /// Returns the IDs of the channels in `live` that weren't live as of the previous refresh.
+    #[instrument(skip(self, tracked, live), fields(tracked = tracked.len(), live = live.len()))]
+    pub async fn refresh(
+        &self,
+        tracked: &[ChannelId],
+        live: &[LiveStream],
+    ) -> SqlxResult<HashSet<ChannelId>> {
+        let mut tx = self.pool.begin().await?;
+
+        let previously_live: HashSet<ChannelId> = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT channel_id
+            FROM stream_status
+            WHERE is_live
+            FOR UPDATE
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(ChannelId)
+        .collect();
+
+        let tracked_ids: Vec<&str> = tracked.iter().map(|id| id.0.as_str()).collect();
+        sqlx::query(
+            r#"
+            INSERT INTO stream_status (channel_id, is_live, viewer_count, game, title, started_at)
+            SELECT t.id, false, 0, '', '', NULL
+            FROM UNNEST($1::varchar[]) AS t(id)
+            JOIN channel c ON c.id = t.id
+            ON CONFLICT (channel_id) DO UPDATE SET
+                is_live = false,
+                viewer_count = 0,
+                game = '',
+                title = '',
+                started_at = NULL,
+                updated_at = now()
+            "#,
+        )
+        .bind(&tracked_ids)
+        .execute(&mut *tx)
+        .await?;
+
+        if !live.is_empty() {
+            let ids: Vec<&str> = live.iter().map(|s| s.channel_id.0.as_str()).collect();
+            let viewers: Vec<i64> = live.iter().map(|s| s.viewer_count).collect();
+            let games: Vec<&str> = live.iter().map(|s| s.game.as_str()).collect();
+            let titles: Vec<&str> = live.iter().map(|s| s.title.as_str()).collect();
+            let started: Vec<_> = live.iter().map(|s| s.started_at).collect();
+
+            sqlx::query(
+                r#"
+                INSERT INTO stream_status (channel_id, is_live, viewer_count, game, title, started_at)
+                SELECT t.id, true, t.viewers, t.game, t.title, t.started
+                FROM UNNEST($1::varchar[], $2::int8[], $3::text[], $4::text[], $5::timestamp[])
+                    AS t(id, viewers, game, title, started)
+                JOIN channel c ON c.id = t.id
+                ON CONFLICT (channel_id) DO UPDATE SET
+                    is_live = true,
+                    viewer_count = EXCLUDED.viewer_count,
+                    game = EXCLUDED.game,
+                    title = EXCLUDED.title,
+                    started_at = EXCLUDED.started_at,
+                    updated_at = now()
+                "#,
+            )
+            .bind(&ids)
+            .bind(&viewers)
+            .bind(&games)
+            .bind(&titles)
+            .bind(&started)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(live
+            .iter()
+            .map(|stream| stream.channel_id.clone())
+            .filter(|id| !previously_live.contains(id))
+            .collect())
+    }
+}
@@ -0,0 +1,210 @@
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::channel::ChannelId;
+use crate::db::models::subscription::EventSubSubscription;
+
+pub struct SubscriptionRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl SubscriptionRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<EventSubSubscription>> {
+        sqlx::query_as::<_, EventSubSubscription>(
+            r#"
+            SELECT
+                channel_id,
+                subscription_type,
+                subscription_id,
+                secret,
+                previous_secret,
+                rotated_at,
+                callback_path,
+                status,
+                revoked_at,
+                cost,
+                created_at,
+                updated_at
+            FROM eventsub_subscription
+            ORDER BY channel_id, subscription_type
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+    ) -> SqlxResult<Option<EventSubSubscription>> {
+        sqlx::query_as::<_, EventSubSubscription>(
+            r#"
+            SELECT
+                channel_id,
+                subscription_type,
+                subscription_id,
+                secret,
+                previous_secret,
+                rotated_at,
+                callback_path,
+                status,
+                revoked_at,
+                cost,
+                created_at,
+                updated_at
+            FROM eventsub_subscription
+            WHERE channel_id = $1
+            AND subscription_type = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(subscription_type)
+        .fetch_optional(self.pool)
+        .await
+    }
+
+    /// Stores a new secret and callback path for a channel's subscription, replacing any previous
+    /// secret and clearing the subscription id until Twitch accepts the new subscription.
+    #[instrument(skip(self, secret))]
+    pub async fn set_secret(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+        secret: &str,
+        callback_path: Option<&str>,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO eventsub_subscription (channel_id, subscription_type, secret, callback_path)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (channel_id, subscription_type) DO UPDATE SET
+                subscription_id = NULL,
+                cost = 0,
+                secret = EXCLUDED.secret,
+                previous_secret = NULL,
+                rotated_at = NULL,
+                callback_path = EXCLUDED.callback_path,
+                status = 'enabled',
+                revoked_at = NULL,
+                updated_at = now()
+            "#,
+        )
+        .bind(channel_id)
+        .bind(subscription_type)
+        .bind(secret)
+        .bind(callback_path)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replaces a subscription's secret, keeping the current one as the previous secret so that
+    /// messages already signed with it can still be verified.
+    #[instrument(skip(self, secret))]
+    pub async fn rotate_secret(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+        secret: &str,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE eventsub_subscription
+            SET
+                previous_secret = secret,
+                secret = $3,
+                rotated_at = now(),
+                updated_at = now()
+            WHERE channel_id = $1
+            AND subscription_type = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(subscription_type)
+        .bind(secret)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the subscription Twitch accepted, along with what it costs.
+    #[instrument(skip(self))]
+    pub async fn set_subscription_id(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+        subscription_id: &str,
+        cost: i32,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE eventsub_subscription
+            SET
+                subscription_id = $3,
+                cost = $4,
+                status = 'enabled',
+                revoked_at = NULL,
+                updated_at = now()
+            WHERE channel_id = $1
+            AND subscription_type = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(subscription_type)
+        .bind(subscription_id)
+        .bind(cost)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn mark_revoked(
+        &self,
+        channel_id: &ChannelId,
+        subscription_type: &str,
+        reason: &str,
+    ) -> SqlxResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE eventsub_subscription
+            SET status = $3, revoked_at = now(), updated_at = now()
+            WHERE channel_id = $1
+            AND subscription_type = $2
+            "#,
+        )
+        .bind(channel_id)
+        .bind(subscription_type)
+        .bind(reason)
+        .execute(self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Sums the cost of every subscription Twitch has accepted and not since revoked, which is
+    /// what counts against the client's `max_total_cost`.
+    #[instrument(skip(self))]
+    pub async fn total_cost(&self) -> SqlxResult<i64> {
+        sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COALESCE(SUM(cost), 0)::INT8
+            FROM eventsub_subscription
+            WHERE subscription_id IS NOT NULL
+            AND status = 'enabled'
+            "#,
+        )
+        .fetch_one(self.pool)
+        .await
+    }
+}
@@ -0,0 +1,86 @@
+use chrono::NaiveDateTime;
+use sqlx::{Pool, Postgres, Result as SqlxResult};
+use tracing::instrument;
+
+use crate::db::models::token::ApiToken;
+
+pub struct ApiTokenRepository {
+    pool: &'static Pool<Postgres>,
+}
+
+impl ApiTokenRepository {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn get_all(&self) -> SqlxResult<Vec<ApiToken>> {
+        sqlx::query_as::<_, ApiToken>(
+            r#"
+            SELECT id, label, scopes, channels, created_at, expires_at, revoked_at
+            FROM api_token
+            ORDER BY created_at DESC
+            "#,
+        )
+        .fetch_all(self.pool)
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn insert(
+        &self,
+        id: &str,
+        label: &str,
+        scopes: &[String],
+        channels: &[String],
+        expires_at: Option<NaiveDateTime>,
+    ) -> SqlxResult<ApiToken> {
+        sqlx::query_as::<_, ApiToken>(
+            r#"
+            INSERT INTO api_token (id, label, scopes, channels, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, label, scopes, channels, created_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(id)
+        .bind(label)
+        .bind(scopes)
+        .bind(channels)
+        .bind(expires_at)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Whether the token was issued and hasn't been revoked. Expiry is checked against the token's
+    /// own claims.
+    #[instrument(skip(self))]
+    pub async fn is_active(&self, id: &str) -> SqlxResult<bool> {
+        sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM api_token
+                WHERE id = $1 AND revoked_at IS NULL
+            )
+            "#,
+        )
+        .bind(id)
+        .fetch_one(self.pool)
+        .await
+    }
+
+    /// Returns the revoked token, or `None` if there was no active token with the id.
+    #[instrument(skip(self))]
+    pub async fn revoke(&self, id: &str) -> SqlxResult<Option<ApiToken>> {
+        sqlx::query_as::<_, ApiToken>(
+            r#"
+            UPDATE api_token
+            SET revoked_at = now()
+            WHERE id = $1 AND revoked_at IS NULL
+            RETURNING id, label, scopes, channels, created_at, expires_at, revoked_at
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(self.pool)
+        .await
+    }
+}
@@ -0,0 +1,1014 @@
+//! Full database snapshots, for moving a deployment without `pg_dump`.
+//!
+//! A snapshot is a gzipped tarball holding a `manifest.json` followed by one JSON-lines file per
+//! table, written in foreign key order:
+//!
+//! ```sh
+//! piss-fan-server snapshot create [--output <PATH>]
+//! piss-fan-server snapshot restore <PATH> [--on-conflict skip|overwrite|merge-sum]
+//! ```
+//!
+//! With `STORAGE_BACKEND=s3`, `<PATH>` is a key in the bucket: snapshots are streamed there as a
+//! multipart upload once they're assembled, and downloaded before they're restored.
+//!
+//! Snapshots are read in a single repeatable-read transaction, and restored in a single
+//! transaction that's rolled back if anything fails (including a row count that doesn't match the
+//! manifest). Keywords are matched by ID, so scores keep pointing at the same keyword.
+//!
+//! Score events (and the hourly rollups of archived events) are restored without firing their
+//! triggers, as the totals and scores they'd add are restored from their own rows; channel heatmaps
+//! are rebuilt from them afterwards.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{NaiveDateTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use futures::TryStreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgRow;
+use sqlx::{PgConnection, Pool, Postgres};
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::db::migrate;
+use crate::db::models::channel::{ChannelCountConfig, CountMode};
+use crate::db::models::keyword::KeywordKind;
+use crate::util::storage::{FileLocation, StorageError};
+
+/// Bumped whenever the layout of the archive or any of its rows changes
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+const MANIFEST_FILE: &str = "manifest.json";
+/// The assembled (or downloaded) snapshot, in its staging directory
+const ARCHIVE_FILE: &str = "snapshot.tar.gz";
+/// Rows inserted per statement on restore
+const RESTORE_BATCH_SIZE: usize = 1000;
+const USAGE: &str = "usage: snapshot create [--output <PATH>] | snapshot restore <PATH> [--on-conflict skip|overwrite|merge-sum]";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("invalid snapshot: {0}")]
+    Invalid(String),
+
+    #[error(
+        "snapshot version {0} is not supported (expected {SNAPSHOT_VERSION}); snapshots from before \
+         version {SNAPSHOT_VERSION} don't include score events, so one has to be created again"
+    )]
+    Outdated(u32),
+
+    #[error("snapshot version {0} is newer than this server supports ({SNAPSHOT_VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+type Result<T> = core::result::Result<T, SnapshotError>;
+
+/// How rows that already exist in the target database are handled on restore.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keep the existing row
+    #[default]
+    Skip,
+    /// Replace the existing row with the snapshot's
+    Overwrite,
+    /// Replace the existing row, but add the snapshot's scores/totals to the existing ones
+    MergeSum,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "skip" => Ok(Self::Skip),
+            "overwrite" => Ok(Self::Overwrite),
+            "merge-sum" => Ok(Self::MergeSum),
+            _ => Err(SnapshotError::Usage(format!(
+                "unknown conflict strategy '{s}'"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotCommand {
+    Create {
+        output: PathBuf,
+    },
+    Restore {
+        path: PathBuf,
+        strategy: ConflictStrategy,
+    },
+}
+
+impl SnapshotCommand {
+    /// Returns `None` unless `snapshot` is the first argument.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Result<Self>> {
+        // skip the binary name
+        _ = args.next();
+        if args.next().as_deref() != Some("snapshot") {
+            return None;
+        }
+
+        Some(Self::parse(args))
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        match args.next().as_deref() {
+            Some("create") => {
+                let output = match (args.next().as_deref(), args.next()) {
+                    (None, _) => default_output(),
+                    (Some("--output"), Some(path)) => PathBuf::from(path),
+                    _ => return Err(usage()),
+                };
+
+                Ok(Self::Create { output })
+            }
+            Some("restore") => {
+                let path = args
+                    .next()
+                    .filter(|arg| !arg.starts_with("--"))
+                    .ok_or(usage())?;
+                let strategy = match (args.next().as_deref(), args.next()) {
+                    (None, _) => ConflictStrategy::default(),
+                    (Some("--on-conflict"), Some(strategy)) => strategy.parse()?,
+                    _ => return Err(usage()),
+                };
+
+                Ok(Self::Restore {
+                    path: PathBuf::from(path),
+                    strategy,
+                })
+            }
+            _ => Err(usage()),
+        }
+    }
+}
+
+pub async fn run(pool: &'static Pool<Postgres>, command: SnapshotCommand) -> Result<()> {
+    match command {
+        SnapshotCommand::Create { output } => _ = create(pool, &output).await?,
+        SnapshotCommand::Restore { path, strategy } => restore(pool, &path, strategy).await?,
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// The latest migration applied to the source database
+    pub schema_version: Option<i64>,
+    pub server_version: String,
+    pub created_at: NaiveDateTime,
+    /// Row count per table file
+    pub tables: BTreeMap<String, u64>,
+}
+
+/// Writes a snapshot of the database to `output`.
+#[instrument(skip(pool))]
+pub async fn create(pool: &'static Pool<Postgres>, output: &Path) -> Result<Manifest> {
+    let staging = Staging::new()?;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut tables = BTreeMap::new();
+    tables.insert(
+        KeywordRow::FILE.into(),
+        dump::<KeywordRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        ChatterRow::FILE.into(),
+        dump::<ChatterRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        ChannelRow::FILE.into(),
+        dump::<ChannelRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        AliasRow::FILE.into(),
+        dump::<AliasRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        ScoreRow::FILE.into(),
+        dump::<ScoreRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        ScoreEventRow::FILE.into(),
+        dump::<ScoreEventRow>(&mut tx, &staging.0).await?,
+    );
+    tables.insert(
+        ScoreEventRollupRow::FILE.into(),
+        dump::<ScoreEventRollupRow>(&mut tx, &staging.0).await?,
+    );
+    tx.commit().await?;
+
+    let manifest = Manifest {
+        version: SNAPSHOT_VERSION,
+        schema_version: migrate::schema_version(pool).await?,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now().naive_utc(),
+        tables,
+    };
+
+    let output = FileLocation::resolve(output).await?;
+    let tarball = staging.0.join(ARCHIVE_FILE);
+    let encoder = GzEncoder::new(
+        BufWriter::new(File::create(&tarball)?),
+        Compression::default(),
+    );
+    let mut archive = tar::Builder::new(encoder);
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(manifest.created_at.and_utc().timestamp() as u64);
+    archive.append_data(&mut header, MANIFEST_FILE, manifest_json.as_slice())?;
+
+    for file in TABLE_ORDER {
+        archive.append_path_with_name(staging.0.join(file), file)?;
+    }
+    archive.into_inner()?.finish()?.flush()?;
+
+    let mut writer = output.writer().await?;
+    if let Err(e) = tokio::io::copy(&mut tokio::fs::File::open(&tarball).await?, &mut writer).await
+    {
+        writer.abort().await?;
+        return Err(e.into());
+    }
+    writer.complete().await?;
+
+    tracing::info!(%output, tables = ?manifest.tables, "created snapshot");
+    Ok(manifest)
+}
+
+/// Restores a snapshot into the database, resolving existing rows with `strategy`.
+#[instrument(skip(pool))]
+pub async fn restore(
+    pool: &'static Pool<Postgres>,
+    path: &Path,
+    strategy: ConflictStrategy,
+) -> Result<()> {
+    let staging = Staging::new()?;
+    let local = match FileLocation::resolve(path).await? {
+        FileLocation::Local(path) => path,
+        FileLocation::Remote { store, key } => {
+            let tarball = staging.0.join(ARCHIVE_FILE);
+            if !store.download(&key, &tarball).await? {
+                return Err(SnapshotError::Invalid(format!("no snapshot at '{key}'")));
+            }
+            tarball
+        }
+    };
+
+    let mut archive = tar::Archive::new(GzDecoder::new(BufReader::new(File::open(&local)?)));
+    let mut entries = archive.entries()?;
+
+    let manifest: Manifest = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry.path()?.as_os_str() != MANIFEST_FILE {
+                return Err(SnapshotError::Invalid(format!(
+                    "expected {MANIFEST_FILE} as the first entry"
+                )));
+            }
+            serde_json::from_reader(entry)?
+        }
+        None => return Err(SnapshotError::Invalid("archive is empty".into())),
+    };
+
+    if manifest.version < SNAPSHOT_VERSION {
+        return Err(SnapshotError::Outdated(manifest.version));
+    }
+    if manifest.version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(manifest.version));
+    }
+
+    let schema_version = migrate::schema_version(pool).await?;
+    if manifest.schema_version != schema_version {
+        tracing::warn!(
+            snapshot = ?manifest.schema_version,
+            database = ?schema_version,
+            "snapshot was taken from a database on a different schema version"
+        );
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT set_config('pea_fan.restoring', 'on', true)")
+        .execute(&mut *tx)
+        .await?;
+
+    let mut restored = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        let file = entry.path()?.to_string_lossy().into_owned();
+        let reader = BufReader::new(entry);
+
+        let rows = match file.as_str() {
+            KeywordRow::FILE => load::<KeywordRow>(&mut tx, reader, strategy).await?,
+            ChatterRow::FILE => load::<ChatterRow>(&mut tx, reader, strategy).await?,
+            ChannelRow::FILE => load::<ChannelRow>(&mut tx, reader, strategy).await?,
+            AliasRow::FILE => load::<AliasRow>(&mut tx, reader, strategy).await?,
+            ScoreRow::FILE => load::<ScoreRow>(&mut tx, reader, strategy).await?,
+            ScoreEventRow::FILE => load::<ScoreEventRow>(&mut tx, reader, strategy).await?,
+            ScoreEventRollupRow::FILE => {
+                load::<ScoreEventRollupRow>(&mut tx, reader, strategy).await?
+            }
+            _ => return Err(SnapshotError::Invalid(format!("unexpected entry '{file}'"))),
+        };
+        restored.insert(file, rows);
+    }
+
+    if restored != manifest.tables {
+        return Err(SnapshotError::Invalid(format!(
+            "restored rows {restored:?} don't match the manifest {:?}",
+            manifest.tables
+        )));
+    }
+
+    // keywords and score events are restored with their IDs, so the sequences have to catch up
+    sqlx::query("SELECT setval('keyword_id_seq', GREATEST((SELECT MAX(id) FROM keyword), 1))")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(
+        "SELECT setval('score_event_id_seq', GREATEST((SELECT MAX(id) FROM score_event), 1))",
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("SELECT recalc_channel_heatmap(id) FROM channel")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    tracing::info!(path = ?path, ?strategy, tables = ?restored, "restored snapshot");
+    Ok(())
+}
+
+/// The order tables are written and restored in, such that foreign keys are always satisfied
+const TABLE_ORDER: [&str; 7] = [
+    KeywordRow::FILE,
+    ChatterRow::FILE,
+    ChannelRow::FILE,
+    AliasRow::FILE,
+    ScoreRow::FILE,
+    ScoreEventRow::FILE,
+    ScoreEventRollupRow::FILE,
+];
+
+#[async_trait::async_trait]
+trait SnapshotTable:
+    Serialize + DeserializeOwned + for<'r> sqlx::FromRow<'r, PgRow> + Send + Sync + Unpin
+{
+    const FILE: &'static str;
+    const SELECT: &'static str;
+
+    /// Inserts a batch of rows, returning the number of rows written.
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64>;
+}
+
+async fn dump<T: SnapshotTable>(conn: &mut PgConnection, dir: &Path) -> Result<u64> {
+    let mut out = BufWriter::new(File::create(dir.join(T::FILE))?);
+    let mut rows = sqlx::query_as::<_, T>(T::SELECT).fetch(&mut *conn);
+
+    let mut count = 0;
+    while let Some(row) = rows.try_next().await? {
+        serde_json::to_writer(&mut out, &row)?;
+        out.write_all(b"\n")?;
+        count += 1;
+    }
+    out.flush()?;
+
+    Ok(count)
+}
+
+async fn load<T: SnapshotTable>(
+    conn: &mut PgConnection,
+    reader: impl BufRead,
+    strategy: ConflictStrategy,
+) -> Result<u64> {
+    let (mut count, mut written) = (0, 0);
+    let mut batch = Vec::with_capacity(RESTORE_BATCH_SIZE);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        batch.push(serde_json::from_str::<T>(&line)?);
+        if batch.len() == RESTORE_BATCH_SIZE {
+            written += T::insert(conn, &batch, strategy).await?;
+            count += batch.len() as u64;
+            batch.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        written += T::insert(conn, &batch, strategy).await?;
+        count += batch.len() as u64;
+    }
+
+    tracing::debug!(table = T::FILE, count, written, "restored table");
+    Ok(count)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct KeywordRow {
+    id: i32,
+    word: String,
+    // absent from snapshots taken before emote keywords
+    #[serde(default = "default_keyword_kind")]
+    kind: String,
+    #[serde(default)]
+    emote_id: Option<String>,
+    created_at: NaiveDateTime,
+}
+
+fn default_keyword_kind() -> String {
+    KeywordKind::default().as_str().to_string()
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for KeywordRow {
+    const FILE: &'static str = "keywords.jsonl";
+    const SELECT: &'static str =
+        "SELECT id, word, kind, emote_id, created_at FROM keyword ORDER BY id";
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        _strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        // a keyword is only what it matches, so there's nothing to overwrite or merge
+        let result = sqlx::query(
+            r#"
+            INSERT INTO keyword (id, word, kind, emote_id, created_at)
+            SELECT * FROM UNNEST(
+                $1::INT4[], $2::VARCHAR[], $3::VARCHAR[], $4::VARCHAR[], $5::TIMESTAMP[]
+            )
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(rows.iter().map(|r| r.id).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.word.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.kind.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.emote_id.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.created_at).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ChatterRow {
+    id: String,
+    login: String,
+    name: String,
+    color: String,
+    image: String,
+    total: i64,
+    private: bool,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    // absent from snapshots taken before chatters could be deleted
+    #[serde(default)]
+    deleted_at: Option<NaiveDateTime>,
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for ChatterRow {
+    const FILE: &'static str = "chatters.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT id, login, name, color, image, total, private, created_at, updated_at, deleted_at
+        FROM chatter
+        ORDER BY id
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        let on_conflict = match strategy {
+            ConflictStrategy::Skip => "DO NOTHING",
+            ConflictStrategy::Overwrite => {
+                r#"DO UPDATE SET
+                    login = EXCLUDED.login,
+                    name = EXCLUDED.name,
+                    color = EXCLUDED.color,
+                    image = EXCLUDED.image,
+                    total = EXCLUDED.total,
+                    private = EXCLUDED.private,
+                    created_at = EXCLUDED.created_at,
+                    updated_at = EXCLUDED.updated_at,
+                    deleted_at = EXCLUDED.deleted_at"#
+            }
+            ConflictStrategy::MergeSum => {
+                r#"DO UPDATE SET
+                    login = EXCLUDED.login,
+                    name = EXCLUDED.name,
+                    color = EXCLUDED.color,
+                    image = EXCLUDED.image,
+                    total = chatter.total + EXCLUDED.total,
+                    private = EXCLUDED.private,
+                    created_at = LEAST(chatter.created_at, EXCLUDED.created_at),
+                    updated_at = GREATEST(chatter.updated_at, EXCLUDED.updated_at),
+                    deleted_at = COALESCE(chatter.deleted_at, EXCLUDED.deleted_at)"#
+            }
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO chatter (
+                id, login, name, color, image, total, private, created_at, updated_at, deleted_at
+            )
+            SELECT * FROM UNNEST(
+                $1::VARCHAR[], $2::VARCHAR[], $3::VARCHAR[], $4::VARCHAR[], $5::VARCHAR[],
+                $6::INT8[], $7::BOOL[], $8::TIMESTAMP[], $9::TIMESTAMP[], $10::TIMESTAMP[]
+            )
+            ON CONFLICT (id) {on_conflict}
+            "#
+        ))
+        .bind(rows.iter().map(|r| r.id.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.login.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.name.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.color.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.image.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.total).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.private).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.created_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.updated_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.deleted_at).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ChannelRow {
+    id: String,
+    channel_total: i64,
+    timezone: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+    // absent from snapshots taken before channels had a count mode
+    #[serde(default = "default_count_mode")]
+    count_mode: String,
+    #[serde(default = "default_max_occurrences")]
+    max_occurrences: i16,
+    #[serde(default)]
+    paused: bool,
+}
+
+fn default_count_mode() -> String {
+    CountMode::default().as_str().to_string()
+}
+
+fn default_max_occurrences() -> i16 {
+    ChannelCountConfig::DEFAULT_MAX_OCCURRENCES
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for ChannelRow {
+    const FILE: &'static str = "channels.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT
+            id, channel_total, timezone, created_at, updated_at, count_mode, max_occurrences, paused
+        FROM channel
+        ORDER BY id
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        let on_conflict = match strategy {
+            ConflictStrategy::Skip => "DO NOTHING",
+            ConflictStrategy::Overwrite => {
+                r#"DO UPDATE SET
+                    channel_total = EXCLUDED.channel_total,
+                    timezone = EXCLUDED.timezone,
+                    created_at = EXCLUDED.created_at,
+                    updated_at = EXCLUDED.updated_at,
+                    count_mode = EXCLUDED.count_mode,
+                    max_occurrences = EXCLUDED.max_occurrences,
+                    paused = EXCLUDED.paused"#
+            }
+            ConflictStrategy::MergeSum => {
+                r#"DO UPDATE SET
+                    channel_total = channel.channel_total + EXCLUDED.channel_total,
+                    timezone = EXCLUDED.timezone,
+                    created_at = LEAST(channel.created_at, EXCLUDED.created_at),
+                    updated_at = GREATEST(channel.updated_at, EXCLUDED.updated_at),
+                    count_mode = EXCLUDED.count_mode,
+                    max_occurrences = EXCLUDED.max_occurrences,
+                    paused = EXCLUDED.paused"#
+            }
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO channel (
+                id, channel_total, timezone, created_at, updated_at, count_mode, max_occurrences,
+                paused
+            )
+            SELECT * FROM UNNEST(
+                $1::VARCHAR[], $2::INT8[], $3::TEXT[], $4::TIMESTAMP[], $5::TIMESTAMP[],
+                $6::VARCHAR[], $7::INT2[], $8::BOOL[]
+            )
+            ON CONFLICT (id) {on_conflict}
+            "#
+        ))
+        .bind(rows.iter().map(|r| r.id.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.channel_total).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.timezone.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.created_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.updated_at).collect::<Vec<_>>())
+        .bind(
+            rows.iter()
+                .map(|r| r.count_mode.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rows.iter().map(|r| r.max_occurrences).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.paused).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct AliasRow {
+    chatter_id: String,
+    login: String,
+    detected_at: NaiveDateTime,
+    merged_at: Option<NaiveDateTime>,
+    merged_score: i64,
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for AliasRow {
+    const FILE: &'static str = "aliases.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT chatter_id, login, detected_at, merged_at, merged_score
+        FROM alias
+        ORDER BY chatter_id, login
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        let on_conflict = match strategy {
+            ConflictStrategy::Skip => "DO NOTHING",
+            ConflictStrategy::Overwrite => {
+                r#"DO UPDATE SET
+                    detected_at = EXCLUDED.detected_at,
+                    merged_at = EXCLUDED.merged_at,
+                    merged_score = EXCLUDED.merged_score"#
+            }
+            ConflictStrategy::MergeSum => {
+                r#"DO UPDATE SET
+                    detected_at = LEAST(alias.detected_at, EXCLUDED.detected_at),
+                    merged_at = GREATEST(alias.merged_at, EXCLUDED.merged_at),
+                    merged_score = alias.merged_score + EXCLUDED.merged_score"#
+            }
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO alias (chatter_id, login, detected_at, merged_at, merged_score)
+            SELECT * FROM UNNEST(
+                $1::VARCHAR[], $2::VARCHAR[], $3::TIMESTAMP[], $4::TIMESTAMP[], $5::INT8[]
+            )
+            ON CONFLICT (chatter_id, login) {on_conflict}
+            "#
+        ))
+        .bind(
+            rows.iter()
+                .map(|r| r.chatter_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rows.iter().map(|r| r.login.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.detected_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.merged_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.merged_score).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ScoreRow {
+    chatter_id: String,
+    channel_id: String,
+    keyword_id: i32,
+    score: i64,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for ScoreRow {
+    const FILE: &'static str = "scores.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT chatter_id, channel_id, keyword_id, score, created_at, updated_at
+        FROM score
+        ORDER BY chatter_id, channel_id, keyword_id
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        let on_conflict = match strategy {
+            ConflictStrategy::Skip => "DO NOTHING",
+            ConflictStrategy::Overwrite => {
+                r#"DO UPDATE SET
+                    score = EXCLUDED.score,
+                    created_at = EXCLUDED.created_at,
+                    updated_at = EXCLUDED.updated_at"#
+            }
+            ConflictStrategy::MergeSum => {
+                r#"DO UPDATE SET
+                    score = score.score + EXCLUDED.score,
+                    created_at = LEAST(score.created_at, EXCLUDED.created_at),
+                    updated_at = GREATEST(score.updated_at, EXCLUDED.updated_at)"#
+            }
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO score (chatter_id, channel_id, keyword_id, score, created_at, updated_at)
+            SELECT * FROM UNNEST(
+                $1::VARCHAR[], $2::VARCHAR[], $3::INT4[], $4::INT8[], $5::TIMESTAMP[],
+                $6::TIMESTAMP[]
+            )
+            ON CONFLICT (chatter_id, channel_id, keyword_id) {on_conflict}
+            "#
+        ))
+        .bind(
+            rows.iter()
+                .map(|r| r.chatter_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            rows.iter()
+                .map(|r| r.channel_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rows.iter().map(|r| r.keyword_id).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.score).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.created_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.updated_at).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ScoreEventRow {
+    id: i32,
+    chatter_id: String,
+    channel_id: String,
+    keyword_id: i32,
+    msg_id: Option<String>,
+    earned_at: NaiveDateTime,
+    flagged_at: Option<NaiveDateTime>,
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for ScoreEventRow {
+    const FILE: &'static str = "score_events.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT id, chatter_id, channel_id, keyword_id, msg_id, earned_at, flagged_at
+        FROM score_event
+        ORDER BY id
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        // events from another database get new IDs when they're merged, rather than colliding
+        // with this database's own events
+        let (id, on_conflict) = match strategy {
+            ConflictStrategy::Skip => ("id", "ON CONFLICT (id) DO NOTHING"),
+            ConflictStrategy::Overwrite => (
+                "id",
+                r#"ON CONFLICT (id) DO UPDATE SET
+                    chatter_id = EXCLUDED.chatter_id,
+                    channel_id = EXCLUDED.channel_id,
+                    keyword_id = EXCLUDED.keyword_id,
+                    msg_id = EXCLUDED.msg_id,
+                    earned_at = EXCLUDED.earned_at,
+                    flagged_at = EXCLUDED.flagged_at"#,
+            ),
+            ConflictStrategy::MergeSum => ("nextval('score_event_id_seq')", ""),
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO score_event (
+                id, chatter_id, channel_id, keyword_id, msg_id, earned_at, flagged_at
+            )
+            SELECT {id}, chatter_id, channel_id, keyword_id, msg_id, earned_at, flagged_at
+            FROM UNNEST(
+                $1::INT4[], $2::VARCHAR[], $3::VARCHAR[], $4::INT4[], $5::VARCHAR[],
+                $6::TIMESTAMP[], $7::TIMESTAMP[]
+            ) AS e(id, chatter_id, channel_id, keyword_id, msg_id, earned_at, flagged_at)
+            {on_conflict}
+            "#
+        ))
+        .bind(rows.iter().map(|r| r.id).collect::<Vec<_>>())
+        .bind(
+            rows.iter()
+                .map(|r| r.chatter_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            rows.iter()
+                .map(|r| r.channel_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rows.iter().map(|r| r.keyword_id).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.msg_id.clone()).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.earned_at).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.flagged_at).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+struct ScoreEventRollupRow {
+    channel_id: String,
+    chatter_id: String,
+    hour: NaiveDateTime,
+    total: i64,
+}
+
+#[async_trait::async_trait]
+impl SnapshotTable for ScoreEventRollupRow {
+    const FILE: &'static str = "score_event_rollups.jsonl";
+    const SELECT: &'static str = r#"
+        SELECT channel_id, chatter_id, hour, total
+        FROM score_event_rollup
+        ORDER BY channel_id, chatter_id, hour
+    "#;
+
+    async fn insert(
+        conn: &mut PgConnection,
+        rows: &[Self],
+        strategy: ConflictStrategy,
+    ) -> sqlx::Result<u64> {
+        let on_conflict = match strategy {
+            ConflictStrategy::Skip => "DO NOTHING",
+            ConflictStrategy::Overwrite => "DO UPDATE SET total = EXCLUDED.total",
+            ConflictStrategy::MergeSum => {
+                "DO UPDATE SET total = score_event_rollup.total + EXCLUDED.total"
+            }
+        };
+
+        let result = sqlx::query(&format!(
+            r#"
+            INSERT INTO score_event_rollup (channel_id, chatter_id, hour, total)
+            SELECT * FROM UNNEST($1::VARCHAR[], $2::VARCHAR[], $3::TIMESTAMP[], $4::INT8[])
+            ON CONFLICT (channel_id, chatter_id, hour) {on_conflict}
+            "#
+        ))
+        .bind(
+            rows.iter()
+                .map(|r| r.channel_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(
+            rows.iter()
+                .map(|r| r.chatter_id.clone())
+                .collect::<Vec<_>>(),
+        )
+        .bind(rows.iter().map(|r| r.hour).collect::<Vec<_>>())
+        .bind(rows.iter().map(|r| r.total).collect::<Vec<_>>())
+        .execute(conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// A scratch directory for table files while the archive is assembled (or for a downloaded
+/// archive), removed on drop.
+struct Staging(PathBuf);
+
+impl Staging {
+    fn new() -> Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "pea-fan-snapshot-{}-{}",
+            std::process::id(),
+            Utc::now().timestamp_millis()
+        ));
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from(format!(
+        "snapshot-{}.tar.gz",
+        Utc::now().format("%Y%m%dT%H%M%S")
+    ))
+}
+
+fn usage() -> SnapshotError {
+    SnapshotError::Usage(USAGE.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(args: &[&str]) -> Option<Result<SnapshotCommand>> {
+        SnapshotCommand::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn snapshot_command_from_args() {
+        assert!(command(&["server", "migrate"]).is_none());
+        assert!(matches!(command(&["server", "snapshot"]), Some(Err(_))));
+        assert!(matches!(
+            command(&["server", "snapshot", "restore", "--on-conflict", "skip"]),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            command(&["server", "snapshot", "create"]),
+            Some(Ok(SnapshotCommand::Create { .. }))
+        ));
+
+        let restore = command(&[
+            "server",
+            "snapshot",
+            "restore",
+            "a.tar.gz",
+            "--on-conflict",
+            "merge-sum",
+        ]);
+        assert_eq!(
+            restore.unwrap().unwrap(),
+            SnapshotCommand::Restore {
+                path: PathBuf::from("a.tar.gz"),
+                strategy: ConflictStrategy::MergeSum,
+            }
+        );
+    }
+}
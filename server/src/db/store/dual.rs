@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::ChannelLeaderboardEntry;
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::db::store::{
+    PostgresStore, RankTarget, RedisStore, ScoreIncrement, ScoreStore, StoreResult,
+};
+use crate::util::alias::MergeResult;
+use crate::util::flags::{Flag, flags};
+
+/// Records scores in Postgres and mirrors increments into the legacy Redis keys for channels with
+/// the `score_dual_write` flag on, for the migration window (see `db::redis::sync`).
+///
+/// Postgres is the source of truth, so every read goes to it and a failed mirror is logged (and
+/// picked up by the reconciliation job) rather than failing the increment.
+#[derive(Debug, Clone)]
+pub struct DualWriteStore {
+    primary: PostgresStore,
+    legacy: RedisStore,
+}
+
+impl DualWriteStore {
+    pub fn new(primary: PostgresStore, legacy: RedisStore) -> Self {
+        Self { primary, legacy }
+    }
+}
+
+#[async_trait]
+impl ScoreStore for DualWriteStore {
+    #[instrument(skip(self))]
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>> {
+        let totals = self.primary.increment(score, with_totals).await?;
+        if !flags().is_on_for(Flag::ScoreDualWrite, score.channel_id) {
+            return Ok(totals);
+        }
+
+        if let Err(e) = self.legacy.increment(score, false).await {
+            tracing::error!(
+                error = ?e,
+                login = score.chatter_login,
+                channel_name = score.channel_login,
+                "legacy score mirror failure"
+            );
+        }
+
+        Ok(totals)
+    }
+
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>> {
+        self.primary.get_rank(target).await
+    }
+
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        self.primary.get_chatter_leaderboard(limit, offset).await
+    }
+
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        self.primary
+            .get_channel_leaderboard(limit, offset, scores)
+            .await
+    }
+
+    /// Postgres merges already move the legacy keys while dual-writes are enabled.
+    async fn merge_aliases(
+        &self,
+        chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>> {
+        self.primary.merge_aliases(chatter_ids).await
+    }
+}
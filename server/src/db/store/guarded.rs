@@ -0,0 +1,362 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::keyword::KeywordId;
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::db::store::{RankTarget, ScoreIncrement, ScoreStore, StoreError, StoreResult};
+use crate::util::alias::MergeResult;
+use crate::util::breaker::CircuitBreaker;
+
+/// Increments held beyond this drop the oldest; a dead letter costs a few hundred bytes.
+const DEAD_LETTER_CAPACITY: usize = 10_000;
+const REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// An increment that couldn't be recorded, kept until it can be.
+#[derive(Debug, Clone)]
+struct DeadLetter {
+    chatter_id: ChatterId,
+    chatter_login: String,
+    channel_id: ChannelId,
+    channel_login: String,
+    keyword_id: KeywordId,
+    msg_id: String,
+}
+
+impl DeadLetter {
+    fn increment(&self) -> ScoreIncrement<'_> {
+        ScoreIncrement {
+            chatter_id: &self.chatter_id,
+            chatter_login: &self.chatter_login,
+            channel_id: &self.channel_id,
+            channel_login: &self.channel_login,
+            keyword_id: &self.keyword_id,
+            msg_id: &self.msg_id,
+        }
+    }
+}
+
+impl From<&ScoreIncrement<'_>> for DeadLetter {
+    fn from(score: &ScoreIncrement<'_>) -> Self {
+        Self {
+            chatter_id: score.chatter_id.clone(),
+            chatter_login: score.chatter_login.to_string(),
+            channel_id: score.channel_id.clone(),
+            channel_login: score.channel_login.to_string(),
+            keyword_id: *score.keyword_id,
+            msg_id: score.msg_id.to_string(),
+        }
+    }
+}
+
+/// The last leaderboard pages read, by `(limit, offset)` and - for channels - the score page.
+#[derive(Debug, Default)]
+struct LeaderboardCache {
+    chatters: HashMap<(i64, i64), PaginatedResponse<ChatterLeaderboardEntry>>,
+    channels: HashMap<(i64, i64, i64, i64), PaginatedResponse<ChannelLeaderboardEntry>>,
+}
+
+/// Wraps the score store in a circuit breaker (see `util::breaker`).
+///
+/// While the breaker is open, leaderboards are served from the last pages read and increments
+/// are queued in a dead-letter buffer rather than failing; `spawn_dead_letter_replay` records
+/// them once the store recovers. Other reads fail with `StoreError::Unavailable`.
+///
+/// Only errors that mean the database is unreachable count towards the breaker - a constraint
+/// violation says nothing about the database's health.
+#[derive(Debug)]
+pub struct GuardedStore {
+    inner: Box<dyn ScoreStore>,
+    breaker: &'static CircuitBreaker,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
+    cache: Mutex<LeaderboardCache>,
+}
+
+impl GuardedStore {
+    pub fn new(inner: impl ScoreStore + 'static, breaker: &'static CircuitBreaker) -> Self {
+        Self {
+            inner: Box::new(inner),
+            breaker,
+            dead_letters: Mutex::new(VecDeque::new()),
+            cache: Mutex::new(LeaderboardCache::default()),
+        }
+    }
+
+    /// Number of increments waiting to be recorded.
+    pub fn dead_letters(&self) -> usize {
+        self.dead_letters.lock().unwrap().len()
+    }
+
+    fn dead_letter(&self, score: &ScoreIncrement<'_>) {
+        let mut dead_letters = self.dead_letters.lock().unwrap();
+        if dead_letters.len() == DEAD_LETTER_CAPACITY {
+            dead_letters.pop_front();
+            metrics::counter!("score_dead_letters_dropped_total").increment(1);
+        }
+
+        dead_letters.push_back(DeadLetter::from(score));
+        metrics::gauge!("score_dead_letters").set(dead_letters.len() as f64);
+    }
+
+    /// Reports the outcome of a call to the breaker, passing the result through.
+    fn observe<T>(&self, result: StoreResult<T>) -> StoreResult<T> {
+        match &result {
+            Err(e) if is_outage(e) => self.breaker.record_failure(),
+            _ => self.breaker.record_success(),
+        }
+
+        result
+    }
+
+    /// Records dead-lettered increments in the order they were counted, while the breaker allows
+    /// it, returning how many were recorded.
+    #[instrument(skip(self))]
+    pub async fn replay(&self) -> usize {
+        let mut replayed = 0;
+
+        loop {
+            let Some(dead_letter) = self.dead_letters.lock().unwrap().front().cloned() else {
+                break;
+            };
+            if !self.breaker.allow() {
+                break;
+            }
+
+            match self.observe(self.inner.increment(&dead_letter.increment(), false).await) {
+                Ok(_) => replayed += 1,
+                // left at the front, to be tried again on the next replay
+                Err(e) if is_outage(&e) => break,
+                Err(e) => {
+                    tracing::error!(error = ?e, ?dead_letter, "discarding dead-lettered increment")
+                }
+            }
+
+            let mut dead_letters = self.dead_letters.lock().unwrap();
+            dead_letters.pop_front();
+            metrics::gauge!("score_dead_letters").set(dead_letters.len() as f64);
+        }
+
+        replayed
+    }
+}
+
+/// Returns true for errors that mean the database couldn't be reached, rather than that a query
+/// was rejected.
+fn is_outage(e: &StoreError) -> bool {
+    matches!(
+        e,
+        StoreError::Unavailable
+            | StoreError::Sqlx(
+                sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::Io(_)
+                    | sqlx::Error::Tls(_)
+                    | sqlx::Error::Protocol(_)
+                    | sqlx::Error::WorkerCrashed
+            )
+    )
+}
+
+#[async_trait]
+impl ScoreStore for GuardedStore {
+    /// Dead-lettered increments are reported as recorded, without totals, so that the rest of a
+    /// message's keywords are still counted.
+    #[instrument(skip(self))]
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>> {
+        // anything already queued goes first, so increments are recorded in order
+        if self.dead_letters() > 0 || !self.breaker.allow() {
+            self.dead_letter(score);
+            return Ok(None);
+        }
+
+        match self.observe(self.inner.increment(score, with_totals).await) {
+            Err(e) if is_outage(&e) => {
+                tracing::warn!(error = ?e, msg_id = score.msg_id, "dead-lettering increment");
+                self.dead_letter(score);
+                Ok(None)
+            }
+            result => result,
+        }
+    }
+
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>> {
+        if !self.breaker.allow() {
+            return Err(StoreError::Unavailable);
+        }
+
+        self.observe(self.inner.get_rank(target).await)
+    }
+
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        let key = (limit, offset);
+        if self.breaker.allow() {
+            match self.observe(self.inner.get_chatter_leaderboard(limit, offset).await) {
+                Ok(page) => {
+                    self.cache
+                        .lock()
+                        .unwrap()
+                        .chatters
+                        .insert(key, page.clone());
+                    return Ok(page);
+                }
+                Err(e) if !is_outage(&e) => return Err(e),
+                Err(e) => tracing::warn!(error = ?e, "serving cached chatter leaderboard"),
+            }
+        }
+
+        let cache = self.cache.lock().unwrap();
+        cache
+            .chatters
+            .get(&key)
+            .cloned()
+            .ok_or(StoreError::Unavailable)
+    }
+
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        let key = (limit, offset, scores.limit, scores.offset);
+        if self.breaker.allow() {
+            let result = self
+                .inner
+                .get_channel_leaderboard(limit, offset, scores)
+                .await;
+
+            match self.observe(result) {
+                Ok(page) => {
+                    self.cache
+                        .lock()
+                        .unwrap()
+                        .channels
+                        .insert(key, page.clone());
+                    return Ok(page);
+                }
+                Err(e) if !is_outage(&e) => return Err(e),
+                Err(e) => tracing::warn!(error = ?e, "serving cached channel leaderboard"),
+            }
+        }
+
+        let cache = self.cache.lock().unwrap();
+        cache
+            .channels
+            .get(&key)
+            .cloned()
+            .ok_or(StoreError::Unavailable)
+    }
+
+    async fn merge_aliases(
+        &self,
+        chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>> {
+        if !self.breaker.allow() {
+            return Err(StoreError::Unavailable);
+        }
+
+        self.observe(self.inner.merge_aliases(chatter_ids).await)
+    }
+}
+
+/// Replays dead-lettered increments every `REPLAY_INTERVAL` until none are left.
+pub fn spawn_dead_letter_replay(store: Arc<GuardedStore>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REPLAY_INTERVAL);
+        loop {
+            interval.tick().await;
+            if store.dead_letters() == 0 {
+                continue;
+            }
+
+            let replayed = store.replay().await;
+            if replayed > 0 {
+                tracing::info!(
+                    replayed,
+                    remaining = store.dead_letters(),
+                    "recorded dead-lettered increments"
+                );
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::store::MemoryStore;
+
+    fn score<'a>(
+        chatter_id: &'a ChatterId,
+        channel_id: &'a ChannelId,
+        msg_id: &'a str,
+    ) -> ScoreIncrement<'a> {
+        ScoreIncrement {
+            chatter_id,
+            chatter_login: "chatter",
+            channel_id,
+            channel_login: "channel",
+            keyword_id: &KeywordId(1),
+            msg_id,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn increments_are_dead_lettered_while_open_and_replayed_in_order() {
+        let breaker = Box::leak(Box::new(CircuitBreaker::new(
+            "test",
+            1,
+            Duration::from_secs(10),
+        )));
+        let store = GuardedStore::new(MemoryStore::default(), breaker);
+        let (chatter, channel) = (ChatterId(String::from("1")), ChannelId(String::from("2")));
+
+        store
+            .increment(&score(&chatter, &channel, "a"), false)
+            .await
+            .unwrap();
+        store.get_chatter_leaderboard(10, 0).await.unwrap();
+        breaker.record_failure();
+
+        for msg_id in ["b", "c"] {
+            let totals = store
+                .increment(&score(&chatter, &channel, msg_id), true)
+                .await;
+            assert!(totals.unwrap().is_none());
+        }
+        assert_eq!(store.dead_letters(), 2);
+        assert_eq!(store.replay().await, 0);
+
+        // the leaderboard page read before the breaker opened is served, but nothing else is
+        let cached = store.get_chatter_leaderboard(10, 0).await.unwrap();
+        assert_eq!(cached.items[0].total, 1);
+        assert!(matches!(
+            store.get_chatter_leaderboard(10, 10).await,
+            Err(StoreError::Unavailable)
+        ));
+
+        // the probe is the first dead letter; the rest follow once it's succeeded
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(store.replay().await, 2);
+        assert_eq!(store.dead_letters(), 0);
+
+        let leaderboard = store.get_chatter_leaderboard(10, 0).await.unwrap();
+        assert_eq!(leaderboard.items[0].total, 3);
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use redis::AsyncCommands;
+use redis::aio::ConnectionManager;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::prelude::Repository;
+use crate::db::prelude::{AliasRepository, ChannelRepository, Chatter, ChatterRepository};
+use crate::db::redis::redis_pool::{KeyType, RedisKey};
+use crate::db::redis::rename::LegacyRename;
+use crate::db::redis::sync::increment_legacy_score;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::db::repositories::sql_fragment::CHATTER_FIELDS;
+use crate::db::store::{RankTarget, ScoreIncrement, ScoreStore, StoreResult};
+use crate::redis_key;
+use crate::util::alias::MergeResult;
+
+/// Scores kept in the legacy Redis keys, as laid out by the old counter (see
+/// `increment_legacy_score`).
+///
+/// The legacy keys are named by login and only hold scores, so ids are resolved to logins - and
+/// leaderboard entries to profiles - through the `chatter` table. Ranks and leaderboards read
+/// every total key, so this store isn't suited to serving reads for a large deployment.
+#[derive(Debug, Clone)]
+pub struct RedisStore {
+    redis_pool: ConnectionManager,
+    pool: &'static Pool<Postgres>,
+}
+
+impl RedisStore {
+    pub fn new(redis_pool: ConnectionManager, pool: &'static Pool<Postgres>) -> Self {
+        Self { redis_pool, pool }
+    }
+
+    /// Every total of a key type as (login, total) pairs, highest first.
+    #[instrument(skip(self))]
+    async fn totals(&self, key_type: KeyType) -> StoreResult<Vec<(String, i64)>> {
+        let mut conn = self.redis_pool.clone();
+        let keys: Vec<String> = conn.keys(RedisKey::Score(key_type).wildcard()).await?;
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let values: Vec<Option<i64>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+
+        let prefix = String::from(key_type);
+        let mut totals: Vec<(String, i64)> = keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, total)| {
+                let login = key.strip_prefix(prefix.as_str())?.strip_suffix(":total")?;
+                Some((login.to_string(), total?))
+            })
+            .collect();
+
+        totals.sort_by(|(a_login, a), (b_login, b)| b.cmp(a).then_with(|| a_login.cmp(b_login)));
+        Ok(totals)
+    }
+
+    /// Profiles for a page of logins, by login.
+    async fn profiles(&self, logins: &[&str]) -> StoreResult<HashMap<String, Chatter>> {
+        let chatters = sqlx::query_as::<_, Chatter>(&format!(
+            "SELECT {CHATTER_FIELDS} FROM chatter WHERE login = ANY($1)"
+        ))
+        .bind(logins)
+        .fetch_all(self.pool)
+        .await?;
+
+        Ok(chatters
+            .into_iter()
+            .map(|chatter| (chatter.login.clone(), chatter))
+            .collect())
+    }
+
+    async fn login_of(&self, id: &str) -> StoreResult<Option<String>> {
+        Ok(ChatterRepository::new(self.pool)
+            .get_by_id(&ChatterId(id.to_string()))
+            .await?
+            .map(|chatter| chatter.login))
+    }
+}
+
+fn page<T>(items: &[T], limit: i64, offset: i64) -> &[T] {
+    let start = (offset.max(0) as usize).min(items.len());
+    let end = start.saturating_add(limit.max(0) as usize).min(items.len());
+    &items[start..end]
+}
+
+#[async_trait]
+impl ScoreStore for RedisStore {
+    #[instrument(skip(self))]
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>> {
+        let mut conn = self.redis_pool.clone();
+        let totals =
+            increment_legacy_score(&mut conn, score.chatter_login, score.channel_login).await?;
+
+        Ok(with_totals.then_some(totals))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>> {
+        let (id, key_type) = match target {
+            RankTarget::Chatter(id) => (&id.0, KeyType::Chatter),
+            RankTarget::Channel(id) => (&id.0, KeyType::Channel),
+        };
+
+        let Some(login) = self.login_of(id).await? else {
+            return Ok(None);
+        };
+
+        let totals = self.totals(key_type).await?;
+        let Some(own) = totals.iter().find(|(l, _)| *l == login).map(|(_, t)| *t) else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            1 + totals.iter().filter(|(_, total)| *total > own).count() as i64,
+        ))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        let totals = self.totals(KeyType::Chatter).await?;
+        let page = page(&totals, limit, offset);
+        let logins: Vec<&str> = page.iter().map(|(login, _)| login.as_str()).collect();
+        let mut profiles = self.profiles(&logins).await?;
+
+        let mut pipeline = redis::pipe();
+        for login in &logins {
+            pipeline.zcard(redis_key!(user, leaderboard, login));
+        }
+        let counts: Vec<i64> = if logins.is_empty() {
+            Vec::new()
+        } else {
+            pipeline.query_async(&mut self.redis_pool.clone()).await?
+        };
+
+        // legacy logins that no longer belong to a known chatter keep their place in the ranking
+        let entries = page
+            .iter()
+            .zip(counts)
+            .enumerate()
+            .filter_map(|(idx, ((login, total), count))| {
+                let chatter = profiles.remove(login)?;
+                Some(ChatterLeaderboardEntry {
+                    id: chatter.id,
+                    login: chatter.login,
+                    name: chatter.name,
+                    color: chatter.color,
+                    image: chatter.image,
+                    total: *total,
+                    ranking: offset + idx as i64 + 1,
+                    channel_scores: Vec::new(),
+                    total_scores: count,
+                })
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            totals.len() as i64,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+
+    /// Entries don't include per-chatter scores.
+    #[instrument(skip(self))]
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        _scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        let totals = self.totals(KeyType::Channel).await?;
+        let page = page(&totals, limit, offset);
+        let logins: Vec<&str> = page.iter().map(|(login, _)| login.as_str()).collect();
+        let mut profiles = self.profiles(&logins).await?;
+
+        let mut pipeline = redis::pipe();
+        for login in &logins {
+            pipeline
+                .zcard(redis_key!(channel, leaderboard, login))
+                .get(redis_key!(user, total, login));
+        }
+        let values: Vec<Option<i64>> = if logins.is_empty() {
+            Vec::new()
+        } else {
+            pipeline.query_async(&mut self.redis_pool.clone()).await?
+        };
+        let counts = values
+            .chunks(2)
+            .map(|pair| (pair[0].unwrap_or_default(), pair[1]));
+
+        let entries = page
+            .iter()
+            .zip(counts)
+            .enumerate()
+            .filter_map(|(idx, ((login, total), (count, as_chatter)))| {
+                let chatter = profiles.remove(login)?;
+                Some(ChannelLeaderboardEntry {
+                    id: ChannelId::from(chatter.id),
+                    login: chatter.login,
+                    name: chatter.name,
+                    color: chatter.color,
+                    image: chatter.image,
+                    total_channel: *total,
+                    total_chatter: as_chatter.unwrap_or_default(),
+                    ranking: offset + idx as i64 + 1,
+                    chatter_scores: Vec::new(),
+                    total_scores: count,
+                })
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            totals.len() as i64,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+
+    /// Moves the legacy keys of each pending alias to the chatter's current login.
+    ///
+    /// Aliases are left pending, as marking them merged is up to the store that records their
+    /// scores; a repeated merge finds nothing left under the old login.
+    #[instrument(skip(self))]
+    async fn merge_aliases(
+        &self,
+        chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>> {
+        let pending = AliasRepository::new(self.pool)
+            .get_pending(chatter_ids)
+            .await?;
+
+        let mut conn = self.redis_pool.clone();
+        let mut merged = Vec::with_capacity(pending.len());
+        for alias in pending {
+            let Some(new_login) = self.login_of(&alias.chatter_id.0).await? else {
+                continue;
+            };
+
+            let is_channel = ChannelRepository::new(self.pool)
+                .get_by_id(&ChannelId::from(alias.chatter_id.clone()))
+                .await?
+                .is_some();
+            let snapshot =
+                LegacyRename::snapshot(&mut conn, &alias.login, &new_login, is_channel).await?;
+
+            let move_keys = new_login != alias.login && !snapshot.is_empty();
+            if move_keys {
+                snapshot.apply(&mut conn).await?;
+            }
+
+            merged.push(MergeResult {
+                chatter_id: alias.chatter_id.clone(),
+                login: alias.login.clone(),
+                merged_score: snapshot.user_scores().map(|(_, score)| score).sum(),
+                legacy_keys_moved: move_keys,
+            });
+        }
+
+        Ok(merged)
+    }
+}
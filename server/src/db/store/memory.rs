@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::db::store::{RankTarget, ScoreIncrement, ScoreStore, StoreResult};
+use crate::util::alias::MergeResult;
+
+/// Scores held in memory, for tests.
+///
+/// Profiles only have the logins seen in increments; there's no legacy counter, so there are
+/// never any aliases to merge.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    inner: Mutex<MemoryScores>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryScores {
+    /// Keyed by (channel id, chatter id)
+    scores: HashMap<(String, String), i64>,
+    /// Logins by id
+    logins: HashMap<String, String>,
+}
+
+impl MemoryScores {
+    /// Totals by id, highest first, along with the number of distinct keys in each total.
+    fn totals(&self, by_channel: bool) -> Vec<(String, i64, i64)> {
+        let mut totals: HashMap<&str, (i64, i64)> = HashMap::new();
+        for ((channel, chatter), score) in &self.scores {
+            let id = if by_channel { channel } else { chatter };
+            let entry = totals.entry(id).or_default();
+            entry.0 += score;
+            entry.1 += 1;
+        }
+
+        let mut totals: Vec<(String, i64, i64)> = totals
+            .into_iter()
+            .map(|(id, (total, count))| (id.to_string(), total, count))
+            .collect();
+        totals.sort_by(|(a_id, a, _), (b_id, b, _)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+        totals
+    }
+
+    fn login(&self, id: &str) -> String {
+        self.logins.get(id).cloned().unwrap_or_default()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The chatter's score in the channel, or 0 if they haven't scored there.
+    pub fn score(&self, channel_id: &ChannelId, chatter_id: &ChatterId) -> i64 {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .scores
+            .get(&(channel_id.0.clone(), chatter_id.0.clone()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl ScoreStore for MemoryStore {
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .logins
+            .insert(score.chatter_id.0.clone(), score.chatter_login.to_string());
+        inner
+            .logins
+            .insert(score.channel_id.0.clone(), score.channel_login.to_string());
+
+        let key = (score.channel_id.0.clone(), score.chatter_id.0.clone());
+        let chatter_score = {
+            let entry = inner.scores.entry(key).or_default();
+            *entry += 1;
+            *entry
+        };
+
+        let channel_total = inner
+            .scores
+            .iter()
+            .filter(|((channel, _), _)| *channel == score.channel_id.0)
+            .map(|(_, score)| score)
+            .sum();
+
+        Ok(with_totals.then_some(MilestoneTotals {
+            channel_total,
+            chatter_score,
+        }))
+    }
+
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>> {
+        let (id, by_channel) = match target {
+            RankTarget::Chatter(id) => (&id.0, false),
+            RankTarget::Channel(id) => (&id.0, true),
+        };
+
+        let totals = self.inner.lock().unwrap().totals(by_channel);
+        let Some(own) = totals.iter().find(|(i, _, _)| i == id).map(|(_, t, _)| *t) else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            1 + totals.iter().filter(|(_, total, _)| *total > own).count() as i64,
+        ))
+    }
+
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        let inner = self.inner.lock().unwrap();
+        let totals = inner.totals(false);
+
+        let entries = totals
+            .iter()
+            .enumerate()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(idx, (id, total, count))| ChatterLeaderboardEntry {
+                id: ChatterId(id.clone()),
+                login: inner.login(id),
+                name: inner.login(id),
+                color: String::new(),
+                image: String::new(),
+                total: *total,
+                ranking: idx as i64 + 1,
+                channel_scores: Vec::new(),
+                total_scores: *count,
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            totals.len() as i64,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        _scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        let inner = self.inner.lock().unwrap();
+        let totals = inner.totals(true);
+        let as_chatter: HashMap<String, i64> = inner
+            .totals(false)
+            .into_iter()
+            .map(|(id, total, _)| (id, total))
+            .collect();
+
+        let entries = totals
+            .iter()
+            .enumerate()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .map(|(idx, (id, total, count))| ChannelLeaderboardEntry {
+                id: ChannelId(id.clone()),
+                login: inner.login(id),
+                name: inner.login(id),
+                color: String::new(),
+                image: String::new(),
+                total_channel: *total,
+                total_chatter: as_chatter.get(id).copied().unwrap_or_default(),
+                ranking: idx as i64 + 1,
+                chatter_scores: Vec::new(),
+                total_scores: *count,
+            })
+            .collect();
+
+        Ok(PaginatedResponse::new(
+            entries,
+            totals.len() as i64,
+            limit,
+            offset / limit.max(1) + 1,
+        ))
+    }
+
+    async fn merge_aliases(
+        &self,
+        _chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::models::keyword::KeywordId;
+
+    async fn count(store: &MemoryStore, chatter: &str, channel: &str) -> MilestoneTotals {
+        let score = ScoreIncrement {
+            chatter_id: &ChatterId(chatter.to_string()),
+            chatter_login: &format!("login{chatter}"),
+            channel_id: &ChannelId(channel.to_string()),
+            channel_login: &format!("login{channel}"),
+            keyword_id: &KeywordId(1),
+            msg_id: "",
+        };
+
+        store.increment(&score, true).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn memory_store_ranks_counted_scores() {
+        let store = MemoryStore::new();
+        count(&store, "1", "10").await;
+        count(&store, "2", "10").await;
+        count(&store, "2", "20").await;
+
+        let totals = count(&store, "2", "10").await;
+        assert_eq!(totals.channel_total, 3);
+        assert_eq!(totals.chatter_score, 2);
+
+        let rank = async |id: &str| {
+            let id = ChatterId(id.to_string());
+            store.get_rank(RankTarget::Chatter(&id)).await.unwrap()
+        };
+        assert_eq!(rank("2").await, Some(1));
+        assert_eq!(rank("1").await, Some(2));
+        assert_eq!(rank("3").await, None);
+
+        let channels = store
+            .get_channel_leaderboard(1, 1, &ScorePagination::new(0, 0))
+            .await
+            .unwrap();
+        assert_eq!(channels.total_items, 2);
+        assert_eq!(channels.items[0].login, "login20");
+        assert_eq!(channels.items[0].ranking, 2);
+        assert_eq!(channels.items[0].total_channel, 1);
+    }
+}
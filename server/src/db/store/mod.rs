@@ -0,0 +1,136 @@
+//! Storage backends for scores.
+//!
+//! Counting and the score-reading handlers go through a `ScoreStore` rather than calling the
+//! repositories (or the legacy Redis helpers) directly, so that the backend can be swapped out -
+//! `MemoryStore` in tests, or another store for score events in future. Chatter and channel
+//! profiles, keywords, milestones, etc. are always read from Postgres.
+//!
+//! `score_store` builds the store used by the server: Postgres, mirrored into the legacy Redis
+//! keys while `SCORE_DUAL_WRITE` is enabled, behind a circuit breaker.
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::{ChannelId, ChannelLeaderboardEntry};
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::keyword::KeywordId;
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::redis::redis_pool::RedisErr;
+use crate::db::replica::ReplicaSet;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::util::alias::MergeResult;
+use crate::util::breaker::postgres_breaker;
+
+pub mod dual;
+pub mod guarded;
+pub mod legacy;
+#[cfg(test)]
+pub mod memory;
+pub mod postgres;
+
+pub use dual::DualWriteStore;
+pub use guarded::GuardedStore;
+pub use legacy::RedisStore;
+#[cfg(test)]
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+
+pub type StoreResult<T> = core::result::Result<T, StoreError>;
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Redis(#[from] RedisErr),
+
+    /// The store's circuit breaker is open; see `GuardedStore`
+    #[error("score store unavailable")]
+    Unavailable,
+}
+
+impl From<redis::RedisError> for StoreError {
+    fn from(value: redis::RedisError) -> Self {
+        Self::Redis(RedisErr::from(value))
+    }
+}
+
+/// A single keyword counted for a chatter in a channel.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreIncrement<'a> {
+    pub chatter_id: &'a ChatterId,
+    pub chatter_login: &'a str,
+    pub channel_id: &'a ChannelId,
+    pub channel_login: &'a str,
+    pub keyword_id: &'a KeywordId,
+    /// The id of the IRC message the score was counted from
+    pub msg_id: &'a str,
+}
+
+/// What a rank is looked up for.
+#[derive(Debug, Clone, Copy)]
+pub enum RankTarget<'a> {
+    /// A chatter's rank by their total across every channel
+    Chatter(&'a ChatterId),
+    /// A channel's rank by the total scored in it
+    Channel(&'a ChannelId),
+}
+
+#[async_trait]
+pub trait ScoreStore: Send + Sync + fmt::Debug {
+    /// Records a score, returning the channel's total and the chatter's score in the channel as
+    /// of it if `with_totals` is set (e.g. to check milestones).
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>>;
+
+    /// Returns `None` if the chatter or channel has no score.
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>>;
+
+    /// Retrieves chatters ranked by their all-time total.
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>>;
+
+    /// Retrieves channels ranked by their all-time total; `scores` paginates each channel's
+    /// chatter scores, where the store has them.
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>>;
+
+    /// Merges scores recorded under chatters' previous logins, optionally limited to a set of
+    /// chatters.
+    async fn merge_aliases(
+        &self,
+        chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>>;
+}
+
+/// Builds the store used for counting and score reads. Increments are mirrored into the legacy
+/// Redis keys for channels with the `score_dual_write` flag on.
+pub async fn score_store(
+    pool: &'static Pool<Postgres>,
+    replicas: &'static ReplicaSet,
+    redis_pool: ConnectionManager,
+) -> Arc<GuardedStore> {
+    let postgres = PostgresStore::new(pool).with_replicas(replicas);
+
+    Arc::new(GuardedStore::new(
+        DualWriteStore::new(postgres, RedisStore::new(redis_pool, pool)),
+        postgres_breaker(),
+    ))
+}
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::db::models::PaginatedResponse;
+use crate::db::models::channel::ChannelLeaderboardEntry;
+use crate::db::models::chatter::{ChatterId, ChatterLeaderboardEntry};
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::prelude::LeaderboardRepository;
+use crate::db::redis::redis_pool::redis_pool;
+use crate::db::replica::ReplicaSet;
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::db::store::{RankTarget, ScoreIncrement, ScoreStore, StoreResult};
+use crate::util::alias::{self, MergeResult};
+
+/// Scores recorded as `score_event`s, with totals maintained by the increment triggers.
+///
+/// Reads go to a healthy replica if `with_replicas` is set. Alias merges still need Redis, as
+/// that's where scores from before the migration (and so under previous logins) are kept.
+#[derive(Debug, Clone)]
+pub struct PostgresStore {
+    pool: &'static Pool<Postgres>,
+    replicas: Option<&'static ReplicaSet>,
+}
+
+impl PostgresStore {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self {
+            pool,
+            replicas: None,
+        }
+    }
+
+    pub fn with_replicas(mut self, replicas: &'static ReplicaSet) -> Self {
+        self.replicas = Some(replicas);
+        self
+    }
+
+    fn reader(&self) -> &'static Pool<Postgres> {
+        self.replicas
+            .map_or(self.pool, |replicas| replicas.reader())
+    }
+}
+
+#[async_trait]
+impl ScoreStore for PostgresStore {
+    #[instrument(skip(self))]
+    async fn increment(
+        &self,
+        score: &ScoreIncrement<'_>,
+        with_totals: bool,
+    ) -> StoreResult<Option<MilestoneTotals>> {
+        let repo = LeaderboardRepository::new(self.pool);
+
+        // the totals are only read when asked for, as they need their own transaction
+        if with_totals {
+            let totals = repo
+                .record_score_event_with_totals(
+                    score.chatter_id,
+                    score.channel_id,
+                    score.keyword_id,
+                    score.msg_id,
+                )
+                .await?;
+
+            Ok(Some(totals))
+        } else {
+            repo.record_score_event(
+                score.chatter_id,
+                score.channel_id,
+                score.keyword_id,
+                score.msg_id,
+            )
+            .await?;
+
+            Ok(None)
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_rank(&self, target: RankTarget<'_>) -> StoreResult<Option<i64>> {
+        let repo = LeaderboardRepository::new(self.reader());
+
+        Ok(match target {
+            RankTarget::Chatter(id) => repo.get_chatter_rank(id).await?,
+            RankTarget::Channel(id) => repo.get_channel_rank(id).await?,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn get_chatter_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> StoreResult<PaginatedResponse<ChatterLeaderboardEntry>> {
+        Ok(LeaderboardRepository::new(self.reader())
+            .get_chatter_leaderboard(limit, offset)
+            .await?)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_leaderboard(
+        &self,
+        limit: i64,
+        offset: i64,
+        scores: &ScorePagination,
+    ) -> StoreResult<PaginatedResponse<ChannelLeaderboardEntry>> {
+        Ok(LeaderboardRepository::new(self.reader())
+            .get_channel_leaderboard(limit, offset, scores)
+            .await?)
+    }
+
+    #[instrument(skip(self))]
+    async fn merge_aliases(
+        &self,
+        chatter_ids: Option<&[ChatterId]>,
+    ) -> StoreResult<Vec<MergeResult>> {
+        let mut conn = redis_pool().await?.clone();
+        Ok(alias::merge_pending(&mut conn, self.pool, chatter_ids).await?)
+    }
+}
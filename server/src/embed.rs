@@ -0,0 +1,267 @@
+//! Embeds the counting engine in another chat bot.
+//!
+//! `CounterService` provides keyword matching, scoring and storage without the rest of the server:
+//! there's no IRC connection, API, or EventSub webhook. The host bot owns its own connection and
+//! feeds raw IRC lines to `on_message`. As stream state normally comes from the webhook, every
+//! matching message is counted - it's up to the host to only pass along messages it wants counted.
+//!
+//! ```no_run
+//! use pea_fan::embed::{CounterConfig, CounterService};
+//!
+//! # async fn run() -> pea_fan::embed::EmbedResult<()> {
+//! let service = CounterService::start(CounterConfig::new("postgres://localhost/pea_fan")).await?;
+//!
+//! let line = "@user-id=1;room-id=2;id=abc :someone!someone@someone.tmi.twitch.tv PRIVMSG #channel :hello";
+//! service.on_message(line).await?;
+//!
+//! let score = service.query("channel", "someone").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use irc::proto::Message;
+use irc::proto::error::ProtocolError;
+use sqlx::PgPool;
+use sqlx::migrate::MigrateError;
+use thiserror::Error;
+use tracing::instrument;
+
+use crate::db::migrate::MIGRATOR;
+use crate::db::models::keyword::KeywordMatcher;
+use crate::db::prelude::{AliasRepository, ChannelId, ChatterRepository, Keyword, KeywordId};
+use crate::db::prelude::{ChannelRepository, KeywordRepository, LeaderboardRepository, Repository};
+use crate::db::store::PostgresStore;
+use crate::irc::error::ConnectionClientError;
+use crate::irc::hydrate::HydrationQueue;
+use crate::irc::matcher::MatchMode;
+use crate::irc::parse::parse_incoming;
+use crate::irc::worker::{countable_message, increment_score, keyword_increments};
+
+#[derive(Debug, Clone)]
+pub struct CounterConfig {
+    /// Postgres connection string
+    pub database_url: String,
+    /// Applies the bundled migrations on start
+    pub run_migrations: bool,
+    /// Refreshes unknown and stale chatters from Helix in the background. This needs the same
+    /// Helix credentials in the environment as the server; otherwise chatters are only stored with
+    /// the details from their message tags.
+    pub hydrate_chatters: bool,
+    /// How text keywords are matched; see `irc::matcher`
+    pub match_mode: MatchMode,
+}
+
+impl CounterConfig {
+    pub fn new(database_url: impl Into<String>) -> Self {
+        Self {
+            database_url: database_url.into(),
+            run_migrations: true,
+            hydrate_chatters: false,
+            match_mode: MatchMode::default(),
+        }
+    }
+}
+
+pub struct CounterService {
+    pool: &'static PgPool,
+    store: PostgresStore,
+    /// Loaded on start, matching the server
+    keywords: Arc<[Keyword]>,
+    matcher: KeywordMatcher,
+    hydrator: Option<HydrationQueue>,
+}
+
+impl CounterService {
+    /// Connects to the database and loads the tracked keywords.
+    ///
+    /// The connection pool lives for the rest of the program, so this should only be called once.
+    #[instrument(skip(config), fields(run_migrations = config.run_migrations))]
+    pub async fn start(config: CounterConfig) -> EmbedResult<Self> {
+        let pool: &'static PgPool =
+            Box::leak(Box::new(PgPool::connect(&config.database_url).await?));
+
+        if config.run_migrations {
+            MIGRATOR.run(pool).await?;
+        }
+
+        let keywords: Arc<[Keyword]> = KeywordRepository::new(pool).get_all().await?.into();
+        tracing::info!(
+            keywords = ?keywords.iter().map(|k| &k.word).collect::<Vec<_>>(),
+            "started embedded counter"
+        );
+
+        let hydrator = config
+            .hydrate_chatters
+            .then(|| HydrationQueue::spawn(pool).0);
+
+        Ok(Self {
+            pool,
+            store: PostgresStore::new(pool),
+            matcher: KeywordMatcher::with_matcher(&keywords, config.match_mode.build(&keywords)),
+            keywords,
+            hydrator,
+        })
+    }
+
+    pub fn keywords(&self) -> &[Keyword] {
+        &self.keywords
+    }
+
+    /// Parses a raw IRC line and records a score event for each keyword it mentions (or each
+    /// occurrence, if the channel counts occurrences).
+    ///
+    /// Returns one keyword id per score event; anything other than a chat message or (re)sub
+    /// message is ignored, as are messages in paused channels and messages that have already been
+    /// counted (see `irc::dedupe`). Messages in channels that aren't tracked are refused with
+    /// `EmbedError::UnknownChannel`.
+    #[instrument(skip(self))]
+    pub async fn on_message(&self, raw_irc_line: &str) -> EmbedResult<Vec<KeywordId>> {
+        let message: Message = raw_irc_line.trim_end().parse()?;
+        let Some(event) = parse_incoming(&message) else {
+            return Ok(Vec::new());
+        };
+
+        let Some(message) = countable_message(&event) else {
+            return Ok(Vec::new());
+        };
+
+        let occurrences = self.matcher.occurrences(&message.text, &message.emotes);
+        if occurrences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        match ChannelRepository::new(self.pool)
+            .paused(&message.channel_id)
+            .await?
+        {
+            Some(false) => (),
+            Some(true) => return Ok(Vec::new()),
+            None => return Err(EmbedError::UnknownChannel(message.channel_id.clone())),
+        }
+
+        let matched = keyword_increments(self.pool, &message.channel_id, &occurrences).await?;
+        if matched.is_empty() {
+            return Ok(matched);
+        }
+
+        let counted = increment_score(
+            self.pool,
+            &self.store,
+            self.hydrator.as_ref(),
+            &message,
+            &matched,
+        )
+        .await?;
+
+        // a message that has already been counted records no score events
+        match counted {
+            Some(_) => Ok(matched),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// A chatter's score in a channel across all keywords, by login.
+    ///
+    /// Returns `None` if either login is unknown; channels are also matched by previous logins.
+    #[instrument(skip(self))]
+    pub async fn query(&self, channel: &str, chatter: &str) -> EmbedResult<Option<i64>> {
+        let channel = channel.trim_start_matches('#').to_lowercase();
+        let Some(channel_id) = AliasRepository::new(self.pool)
+            .resolve_channel(&channel)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let chatter = match ChatterRepository::new(self.pool)
+            .get_by_login(&chatter.trim_start_matches('@').to_lowercase())
+            .await
+        {
+            Ok(chatter) => chatter,
+            Err(sqlx::Error::RowNotFound) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let score = LeaderboardRepository::new(self.pool)
+            .get_chatter_channel_score(&channel_id, &chatter.id)
+            .await?;
+
+        Ok(Some(score))
+    }
+}
+
+pub type EmbedResult<T> = core::result::Result<T, EmbedError>;
+
+#[derive(Debug, Error)]
+pub enum EmbedError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+
+    #[error(transparent)]
+    Parse(#[from] ProtocolError),
+
+    #[error(transparent)]
+    Counter(#[from] ConnectionClientError),
+
+    #[error("channel {0} isn't tracked")]
+    UnknownChannel(ChannelId),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use crate::db::models::keyword::KeywordKind;
+    use crate::db::prelude::ChatterId;
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL pointing at a scratch instance"]
+    async fn redelivered_messages_are_only_counted_once() {
+        let mut config = CounterConfig::new(std::env::var("DATABASE_URL").unwrap());
+        config.run_migrations = false;
+        let service = CounterService::start(config).await.unwrap();
+
+        let channels = ChannelRepository::new(service.pool);
+        let mut channel_id = None;
+        for id in channels.get_all_channel_ids().await.unwrap() {
+            let id = ChannelId(id);
+            if channels.paused(&id).await.unwrap() == Some(false) {
+                channel_id = Some(id);
+                break;
+            }
+        }
+        let channel_id = channel_id.expect("at least one unpaused channel");
+        let login = ChatterRepository::new(service.pool)
+            .get_by_id(&ChatterId::from(channel_id.clone()))
+            .await
+            .unwrap()
+            .expect("channel's chatter row")
+            .login;
+        let keyword = service
+            .keywords()
+            .iter()
+            .find(|keyword| keyword.kind == KeywordKind::Text)
+            .expect("at least one text keyword");
+
+        // unique per run, so that earlier runs' claims don't count
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let line = format!(
+            "@id={:08x}-0000-4000-8000-000000000001;room-id={channel_id};user-id={channel_id} \
+             :{login}!{login}@{login}.tmi.twitch.tv PRIVMSG #{login} :{}",
+            nanos as u32, keyword.word
+        );
+
+        let first = service.on_message(&line).await.unwrap();
+        assert!(first.contains(&keyword.id));
+        assert!(service.on_message(&line).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use http::StatusCode;
+use tonic::{Request, Response, Status};
+use tracing::instrument;
+
+use crate::api::auth::{Actor, Scope};
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::spawn_protected;
+use crate::api::server::AppState;
+use crate::db::models::audit::AuditAction;
+use crate::db::prelude::{ApiTokenRepository, ChatterId};
+use crate::grpc::authorize;
+use crate::grpc::proto::admin_server::Admin;
+use crate::grpc::proto::{MergeAliasesRequest, MergeAliasesResponse, MergedAlias};
+use crate::grpc::proto::{ResetIrcRequest, ResetIrcResponse};
+use crate::grpc::proto::{RevokeApiTokenRequest, RevokeApiTokenResponse};
+
+/// The admin RPCs, audited in the same way as their `/_admin` counterparts.
+#[derive(Debug)]
+pub struct AdminService {
+    state: Arc<AppState>,
+}
+
+impl AdminService {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+
+    async fn actor<T>(&self, request: &Request<T>) -> Result<Actor, Status> {
+        let claims = authorize(&self.state, request, Scope::Admin).await?;
+        Ok(Actor::Token(claims))
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    #[instrument(skip_all)]
+    async fn reset_irc(
+        &self,
+        request: Request<ResetIrcRequest>,
+    ) -> Result<Response<ResetIrcResponse>, Status> {
+        let actor = self.actor(&request).await?;
+
+        self.state
+            .irc_connection
+            .connection
+            .reset_tx
+            .send(())
+            .await
+            .map_err(ApiError::from)?;
+
+        Audit::new(AuditAction::IrcReset)
+            .record(self.state.database_pool, &actor)
+            .await;
+
+        Ok(Response::new(ResetIrcResponse {}))
+    }
+
+    #[instrument(skip_all)]
+    async fn merge_aliases(
+        &self,
+        request: Request<MergeAliasesRequest>,
+    ) -> Result<Response<MergeAliasesResponse>, Status> {
+        let actor = self.actor(&request).await?;
+        let chatter_ids = request
+            .into_inner()
+            .chatter_ids
+            .into_iter()
+            .map(ChatterId::from)
+            .collect::<Vec<_>>();
+
+        let state = Arc::clone(&self.state);
+        let merged = spawn_protected(async move {
+            let ids = (!chatter_ids.is_empty()).then_some(chatter_ids.as_slice());
+            let merged = state.scores.merge_aliases(ids).await?;
+
+            let action = match ids {
+                Some(_) => AuditAction::AliasesRepaired,
+                None => AuditAction::AliasesMerged,
+            };
+            Audit::new(action)
+                .after(&merged)
+                .record(state.database_pool, &actor)
+                .await;
+
+            Ok(merged)
+        })
+        .await?;
+
+        Ok(Response::new(MergeAliasesResponse {
+            merged: merged
+                .into_iter()
+                .map(|result| MergedAlias {
+                    chatter_id: result.chatter_id.0,
+                    login: result.login,
+                    merged_score: result.merged_score,
+                })
+                .collect(),
+        }))
+    }
+
+    #[instrument(skip_all, fields(id = request.get_ref().id))]
+    async fn revoke_api_token(
+        &self,
+        request: Request<RevokeApiTokenRequest>,
+    ) -> Result<Response<RevokeApiTokenResponse>, Status> {
+        let actor = self.actor(&request).await?;
+        let id = request.into_inner().id;
+
+        let state = Arc::clone(&self.state);
+        spawn_protected(async move {
+            let token = ApiTokenRepository::new(state.database_pool)
+                .revoke(&id)
+                .await?
+                .ok_or(ApiError::GenericStatusCode(StatusCode::NOT_FOUND))?;
+
+            tracing::info!(id, label = token.label, "api token revoked");
+            Audit::new(AuditAction::ApiTokenRevoked)
+                .target(&token.id)
+                .after(&token)
+                .record(state.database_pool, &actor)
+                .await;
+
+            Ok(())
+        })
+        .await?;
+
+        Ok(Response::new(RevokeApiTokenResponse {}))
+    }
+}
@@ -0,0 +1,287 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::Stream;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+use tracing::instrument;
+
+use crate::api::auth::Scope;
+use crate::api::dto::v1::{ChannelEntry, ChannelScore, ChatterEntry, Page, Profile};
+use crate::api::error::ApiError;
+use crate::api::server::AppState;
+use crate::db::models::channel::ChannelId;
+use crate::db::models::leaderboard::Period;
+use crate::db::prelude::{ChatterRepository, LeaderboardRepository, PeriodRepository};
+use crate::db::prelude::{Repository, StreamStatusRepository};
+use crate::db::repositories::leaderboard::ScorePagination;
+use crate::grpc::authorize;
+use crate::grpc::proto::get_chatter_request::Chatter as ChatterQuery;
+use crate::grpc::proto::leaderboards_server::Leaderboards;
+use crate::grpc::proto::{self, Board, Increment, LeaderboardEntry, LeaderboardPage};
+use crate::grpc::proto::{GetChatterRequest, GetLeaderboardRequest, StreamIncrementsRequest};
+use crate::irc::events::increments;
+
+const DEFAULT_LIMIT: i64 = 25;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug)]
+pub struct LeaderboardService {
+    state: Arc<AppState>,
+}
+
+impl LeaderboardService {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self { state }
+    }
+}
+
+type IncrementStream = Pin<Box<dyn Stream<Item = Result<Increment, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Leaderboards for LeaderboardService {
+    #[instrument(skip_all, fields(request = ?request.get_ref()))]
+    async fn get_leaderboard(
+        &self,
+        request: Request<GetLeaderboardRequest>,
+    ) -> Result<Response<LeaderboardPage>, Status> {
+        authorize(&self.state, &request, Scope::Read).await?;
+
+        let request = request.into_inner();
+        let period = Period::from(request.period());
+        let limit = page_limit(request.limit);
+        let offset = request.page.max(0) * limit;
+        let reader = self.state.replicas.reader();
+
+        let page = match (request.board(), period) {
+            (Board::Chatters, Period::All) => {
+                let segment = self
+                    .state
+                    .scores
+                    .get_chatter_leaderboard(limit, offset)
+                    .await
+                    .map_err(ApiError::from)?;
+                chatter_page(segment.into())
+            }
+            (Board::Chatters, period) => {
+                let segment = PeriodRepository::new(reader)
+                    .get_chatter_leaderboard(period, limit, offset)
+                    .await
+                    .map_err(ApiError::from)?;
+                chatter_page(segment.into())
+            }
+            (Board::Channels, period) => {
+                let segment = match period {
+                    Period::All => self
+                        .state
+                        .scores
+                        .get_channel_leaderboard(limit, offset, &ScorePagination::new(0, 0))
+                        .await
+                        .map_err(ApiError::from)?,
+                    period => PeriodRepository::new(reader)
+                        .get_channel_leaderboard(period, limit, offset)
+                        .await
+                        .map_err(ApiError::from)?,
+                };
+
+                let live = StreamStatusRepository::new(reader)
+                    .get_live_channel_ids()
+                    .await
+                    .map_err(ApiError::from)?;
+
+                let mut page: Page<ChannelEntry> = segment.into();
+                for entry in page.items.iter_mut() {
+                    entry.live = live.contains(&ChannelId(entry.profile.id.clone()));
+                }
+
+                channel_page(page)
+            }
+        };
+
+        Ok(Response::new(page))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_chatter(
+        &self,
+        request: Request<GetChatterRequest>,
+    ) -> Result<Response<proto::Chatter>, Status> {
+        authorize(&self.state, &request, Scope::Read).await?;
+
+        let reader = self.state.replicas.reader();
+        let id = match request.into_inner().chatter {
+            Some(ChatterQuery::Id(id)) => id,
+            Some(ChatterQuery::Login(login)) => {
+                ChatterRepository::new(reader)
+                    .get_by_login(&login.to_lowercase())
+                    .await
+                    .map_err(ApiError::from)?
+                    .id
+                    .0
+            }
+            None => return Err(Status::invalid_argument("an id or login is required")),
+        };
+
+        let entry: ChatterEntry = LeaderboardRepository::new(reader)
+            .get_single_chatter_leaderboard(id.clone().into())
+            .await
+            .map_err(ApiError::from)?
+            .ok_or(ApiError::InvalidUser(id))?
+            .into();
+
+        Ok(Response::new(proto::Chatter {
+            profile: Some(entry.profile.into()),
+            total: entry.total_as_chatter,
+            ranking: entry.ranking,
+            channel_scores: entry
+                .channel_scores
+                .into_iter()
+                .map(proto::ChannelScore::from)
+                .collect(),
+        }))
+    }
+
+    type StreamIncrementsStream = IncrementStream;
+
+    #[instrument(skip_all)]
+    async fn stream_increments(
+        &self,
+        request: Request<StreamIncrementsRequest>,
+    ) -> Result<Response<Self::StreamIncrementsStream>, Status> {
+        let claims = authorize(&self.state, &request, Scope::Read).await?;
+        let channel_ids = request.into_inner().channel_ids;
+        tracing::info!(token = claims.id, ?channel_ids, "streaming increments");
+
+        let rx = increments().subscribe();
+        let stream = futures::stream::unfold((rx, 0u64), move |(mut rx, mut missed)| {
+            let channel_ids = channel_ids.clone();
+            async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(score) => {
+                            if !channel_ids.is_empty() && !channel_ids.contains(&score.channel_id.0)
+                            {
+                                continue;
+                            }
+
+                            let increment = Increment {
+                                chatter_id: score.chatter_id.0.clone(),
+                                chatter_login: score.chatter_login.clone(),
+                                channel_id: score.channel_id.0.clone(),
+                                channel_login: score.channel_login.clone(),
+                                keyword_id: score.keyword_id.0,
+                                counted_at: score.counted_at.and_utc().timestamp_millis(),
+                                missed,
+                            };
+
+                            return Some((Ok(increment), (rx, 0)));
+                        }
+                        // counted rather than reported straight away, so the client sees it
+                        // alongside the next increment it does get
+                        Err(RecvError::Lagged(skipped)) => missed += skipped,
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// `0` (unset) uses the default limit.
+fn page_limit(limit: i64) -> i64 {
+    match limit {
+        ..=0 => DEFAULT_LIMIT,
+        limit => limit.min(MAX_LIMIT),
+    }
+}
+
+fn chatter_page(page: Page<ChatterEntry>) -> LeaderboardPage {
+    LeaderboardPage {
+        entries: page
+            .items
+            .into_iter()
+            .map(|entry| LeaderboardEntry {
+                profile: Some(entry.profile.into()),
+                score: entry.total_as_chatter,
+                ranking: entry.ranking,
+                live: false,
+            })
+            .collect(),
+        page: page.page,
+        page_size: page.page_size,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+    }
+}
+
+fn channel_page(page: Page<ChannelEntry>) -> LeaderboardPage {
+    LeaderboardPage {
+        entries: page
+            .items
+            .into_iter()
+            .map(|entry| LeaderboardEntry {
+                profile: Some(entry.profile.into()),
+                score: entry.total_as_broadcaster,
+                ranking: entry.ranking,
+                live: entry.live,
+            })
+            .collect(),
+        page: page.page,
+        page_size: page.page_size,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+    }
+}
+
+impl From<proto::Period> for Period {
+    fn from(value: proto::Period) -> Self {
+        match value {
+            proto::Period::All => Period::All,
+            proto::Period::Day => Period::Day,
+            proto::Period::Week => Period::Week,
+            proto::Period::Month => Period::Month,
+        }
+    }
+}
+
+impl From<Profile> for proto::Profile {
+    fn from(value: Profile) -> Self {
+        Self {
+            id: value.id,
+            login: value.login,
+            name: value.name,
+            color: value.color,
+            image: value.image,
+        }
+    }
+}
+
+impl From<ChannelScore> for proto::ChannelScore {
+    fn from(value: ChannelScore) -> Self {
+        Self {
+            channel: Some(value.channel.into()),
+            score: value.score,
+            ranking: value.ranking,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn requests_are_paged_within_limits() {
+        assert_eq!(page_limit(0), DEFAULT_LIMIT);
+        assert_eq!(page_limit(-5), DEFAULT_LIMIT);
+        assert_eq!(page_limit(10), 10);
+        assert_eq!(page_limit(1000), MAX_LIMIT);
+
+        // proto3 enums default to their first value when unset
+        let request = GetLeaderboardRequest::default();
+        assert_eq!(request.board(), Board::Chatters);
+        assert_eq!(Period::from(request.period()), Period::All);
+    }
+}
@@ -0,0 +1,117 @@
+//! The gRPC server, built with the `grpc` feature, for internal services that want typed access to
+//! the same counters and leaderboards as the REST API.
+//!
+//! The services are defined in `proto/pea_fan/v1/pea_fan.proto` and call into the same repositories
+//! and score store as the REST handlers. Every call is authenticated with an API token (see
+//! `api::auth`) sent as `authorization: Bearer <token>` metadata.
+
+pub mod admin;
+pub mod leaderboard;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::ParseIntError;
+use std::sync::Arc;
+
+use http::StatusCode;
+use thiserror::Error;
+use tonic::{Code, Request, Status};
+
+use crate::api::auth::{self, AuthError, Scope, TokenClaims};
+use crate::api::error::ApiError;
+use crate::api::server::AppState;
+use crate::grpc::admin::AdminService;
+use crate::grpc::leaderboard::LeaderboardService;
+use crate::grpc::proto::admin_server::AdminServer;
+use crate::grpc::proto::leaderboards_server::LeaderboardsServer;
+use crate::util::env::{EnvErr, Var};
+use crate::var;
+
+pub mod proto {
+    tonic::include_proto!("pea_fan.v1");
+}
+
+pub type GrpcResult<T> = core::result::Result<T, GrpcError>;
+
+#[derive(Debug, Error)]
+pub enum GrpcError {
+    #[error(transparent)]
+    EnvErr(#[from] EnvErr),
+
+    #[error("invalid GRPC_PORT: {0}")]
+    InvalidPort(#[from] ParseIntError),
+
+    #[error(transparent)]
+    Transport(#[from] tonic::transport::Error),
+}
+
+/// Serves the gRPC services on `GRPC_PORT` until the server fails.
+pub async fn serve(state: Arc<AppState>) -> GrpcResult<()> {
+    let port = var!(Var::GrpcPort).await?.parse::<u16>()?;
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port);
+
+    tracing::info!(%addr, "starting grpc server");
+    tonic::transport::Server::builder()
+        .add_service(LeaderboardsServer::new(LeaderboardService::new(
+            Arc::clone(&state),
+        )))
+        .add_service(AdminServer::new(AdminService::new(state)))
+        .serve(addr)
+        .await?;
+
+    Ok(())
+}
+
+/// Checks that a request's token allows `required`, as with `api::auth::Authorized`.
+async fn authorize<T>(
+    state: &AppState,
+    request: &Request<T>,
+    required: Scope,
+) -> Result<TokenClaims, Status> {
+    let headers = request.metadata().clone().into_headers();
+    let token = auth::bearer_token(&headers).ok_or(ApiError::from(AuthError::MissingToken))?;
+
+    auth::authenticate(state.database_pool, token, required)
+        .await
+        .map_err(|e| ApiError::from(e).into())
+}
+
+impl From<ApiError> for Status {
+    fn from(e: ApiError) -> Self {
+        let code = match e.status_code() {
+            StatusCode::BAD_REQUEST => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::CONFLICT => Code::FailedPrecondition,
+            StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+            StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+            _ => Code::Internal,
+        };
+
+        // as with REST responses, the underlying error is only logged
+        if code == Code::Internal || code == Code::Unavailable {
+            tracing::error!(error = ?e, "grpc request failed");
+        }
+
+        Status::new(code, e.client_message())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn api_errors_map_to_grpc_codes() {
+        let status = Status::from(ApiError::InvalidUser("foo".to_string()));
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(status.message(), "unknown user 'foo'");
+
+        let status = Status::from(ApiError::from(AuthError::MissingScope(Scope::Admin)));
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let status = Status::from(ApiError::SqlxError(sqlx::Error::PoolClosed));
+        assert_eq!(status.code(), Code::Unavailable);
+        assert_eq!(status.message(), "database unavailable");
+    }
+}
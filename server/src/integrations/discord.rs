@@ -0,0 +1,363 @@
+//! Posts events from the `EventBus` to the Discord webhooks configured for each channel.
+//!
+//! Each channel has at most one webhook per `IntegrationEvent`, with an optional template; the
+//! event's default template is used otherwise. Templates are plain text with `{placeholder}`s,
+//! which are replaced with the event's values (see `placeholders`).
+//!
+//! Posts are retried with exponential backoff on network errors, `5xx`s, and rate limits (using
+//! Discord's `Retry-After` where given); any other rejection is logged and dropped.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+use serde_json::json;
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::integration::{DiscordWebhook, IntegrationEvent};
+use crate::db::models::leaderboard::{Period, SnapshotFinalised};
+use crate::db::models::milestone::{MilestoneKind, MilestoneReached};
+use crate::db::models::stream::StreamOnline;
+use crate::db::prelude::{
+    ChatterId, ChatterRepository, DiscordWebhookRepository, PeriodRepository, Repository,
+};
+use crate::irc::events::{LiveEvent, events};
+use crate::util::{digest, http_client, template};
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long a single post attempt may take; a hung request counts as a failed attempt
+const POST_TIMEOUT: Duration = Duration::from_secs(10);
+/// The number of chatters listed in a leaderboard snapshot post
+const LEADERBOARD_SIZE: i64 = 5;
+/// Discord rejects messages longer than this
+const MAX_CONTENT_CHARS: usize = 2000;
+
+/// Webhook URLs are restricted to Discord so that admins can't point the server at arbitrary hosts
+pub const WEBHOOK_URL_PREFIXES: [&str; 2] = [
+    "https://discord.com/api/webhooks/",
+    "https://discordapp.com/api/webhooks/",
+];
+
+pub type DiscordResult<T> = core::result::Result<T, DiscordError>;
+
+#[derive(Debug, Error)]
+pub enum DiscordError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error("discord rejected the webhook post: {0}")]
+    Rejected(StatusCode),
+
+    #[error("webhook post failed after {0} attempts")]
+    Exhausted(u32),
+}
+
+impl IntegrationEvent {
+    pub fn default_template(&self) -> &'static str {
+        match self {
+            Self::StreamOnline => {
+                "{channel} is live: {title} ({game}) - https://twitch.tv/{channel}"
+            }
+            Self::MilestoneReached => "{milestone} in {channel} just hit {value}!",
+            Self::LeaderboardSnapshot => {
+                "top chatters in {channel} for the week of {period_start}:\n{leaderboard}"
+            }
+            Self::DailyDigest => {
+                "{channel} digest for {date}: {total} counted\n{top_chatters}\n{gainer}"
+            }
+        }
+    }
+}
+
+/// Returns true if `url` looks like a Discord webhook URL.
+pub fn is_webhook_url(url: &str) -> bool {
+    WEBHOOK_URL_PREFIXES
+        .iter()
+        .any(|prefix| url.len() > prefix.len() && url.starts_with(prefix))
+}
+
+/// Renders a template, cut down to what Discord accepts.
+fn render(template: &str, placeholders: &[(&str, String)]) -> String {
+    template::truncate_chars(template::render(template, placeholders), MAX_CONTENT_CHARS)
+}
+
+fn stream_placeholders(stream: &StreamOnline) -> Vec<(&'static str, String)> {
+    vec![
+        ("channel", stream.channel_login.clone()),
+        ("title", stream.title.clone()),
+        ("game", stream.game.clone()),
+        (
+            "started_at",
+            stream.started_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+        ),
+    ]
+}
+
+fn milestone_placeholders(reached: &MilestoneReached) -> Vec<(&'static str, String)> {
+    let milestone = match reached.kind {
+        MilestoneKind::ChannelTotal => String::from("the channel total"),
+        MilestoneKind::ChatterScore => format!("{}'s score", reached.chatter_login),
+    };
+
+    vec![
+        ("channel", reached.channel_login.clone()),
+        ("chatter", reached.chatter_login.clone()),
+        ("kind", reached.kind.as_str().to_string()),
+        ("milestone", milestone),
+        ("value", reached.value.to_string()),
+    ]
+}
+
+fn leaderboard_placeholders(
+    channel_login: &str,
+    snapshot: &SnapshotFinalised,
+    top: &[(String, i64)],
+) -> Vec<(&'static str, String)> {
+    let leaderboard = top
+        .iter()
+        .enumerate()
+        .map(|(idx, (login, score))| format!("{}. {login} - {score}", idx + 1))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    vec![
+        ("channel", channel_login.to_string()),
+        (
+            "period_start",
+            snapshot.period_start.format("%Y-%m-%d").to_string(),
+        ),
+        ("leaderboard", leaderboard),
+    ]
+}
+
+/// Posts `content` to a webhook, retrying on failures that might be transient.
+#[instrument(skip(client, url, content))]
+async fn post(client: &reqwest::Client, url: &str, content: &str) -> DiscordResult<()> {
+    // titles and chatter logins come from twitch, so they're never allowed to ping anyone
+    let body = json!({
+        "content": content,
+        "allowed_mentions": { "parse": [] },
+    });
+
+    let mut backoff = BASE_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        let request = client.post(url).json(&body).timeout(POST_TIMEOUT);
+        let delay = match request.send().await {
+            Ok(res) if res.status().is_success() => return Ok(()),
+            Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<f64>().ok())
+                .map(Duration::from_secs_f64)
+                .unwrap_or(backoff),
+            Ok(res) if res.status().is_server_error() => backoff,
+            Ok(res) => return Err(DiscordError::Rejected(res.status())),
+            Err(e) if attempt == MAX_ATTEMPTS => return Err(e.into()),
+            Err(e) => {
+                tracing::warn!(error = ?e, attempt, "discord webhook post failed");
+                backoff
+            }
+        };
+
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(delay.min(MAX_BACKOFF)).await;
+            backoff *= 2;
+        }
+    }
+
+    Err(DiscordError::Exhausted(MAX_ATTEMPTS))
+}
+
+async fn post_to(client: &reqwest::Client, webhook: &DiscordWebhook, content: String) {
+    match post(client, &webhook.url, &content).await {
+        Ok(()) => tracing::debug!(webhook = webhook.id, "posted to discord webhook"),
+        Err(e) => tracing::error!(
+            error = ?e,
+            webhook = webhook.id,
+            channel = ?webhook.channel_id,
+            event = webhook.event.as_str(),
+            "failed to post to discord webhook"
+        ),
+    }
+}
+
+fn content(webhook: &DiscordWebhook, placeholders: &[(&str, String)]) -> String {
+    let template = webhook
+        .template
+        .as_deref()
+        .unwrap_or(webhook.event.default_template());
+
+    render(template, placeholders)
+}
+
+/// Posts a single event to every webhook configured for it.
+#[instrument(skip(pool, client))]
+async fn deliver(
+    pool: &'static Pool<Postgres>,
+    client: &reqwest::Client,
+    event: &LiveEvent,
+) -> DiscordResult<()> {
+    let repo = DiscordWebhookRepository::new(pool);
+
+    match event {
+        LiveEvent::StreamOnline(stream) => {
+            let placeholders = stream_placeholders(stream);
+            for webhook in repo
+                .get_enabled(IntegrationEvent::StreamOnline, Some(&stream.channel_id))
+                .await?
+            {
+                post_to(client, &webhook, content(&webhook, &placeholders)).await;
+            }
+        }
+        LiveEvent::MilestoneReached(reached) => {
+            let placeholders = milestone_placeholders(reached);
+            for webhook in repo
+                .get_enabled(
+                    IntegrationEvent::MilestoneReached,
+                    Some(&reached.channel_id),
+                )
+                .await?
+            {
+                post_to(client, &webhook, content(&webhook, &placeholders)).await;
+            }
+        }
+        LiveEvent::SnapshotFinalised(snapshot) if snapshot.period == Period::Week => {
+            let periods = PeriodRepository::new(pool);
+            let chatters = ChatterRepository::new(pool);
+
+            for webhook in repo
+                .get_enabled(IntegrationEvent::LeaderboardSnapshot, None)
+                .await?
+            {
+                let top = periods
+                    .get_channel_top_chatters(
+                        snapshot.period,
+                        snapshot.period_start,
+                        &webhook.channel_id,
+                        LEADERBOARD_SIZE,
+                    )
+                    .await?;
+
+                // nobody scored in the channel that week
+                if top.is_empty() {
+                    continue;
+                }
+
+                let Some(channel) = chatters
+                    .get_by_id(&ChatterId(webhook.channel_id.0.clone()))
+                    .await?
+                else {
+                    continue;
+                };
+
+                let placeholders = leaderboard_placeholders(&channel.login, snapshot, &top);
+                post_to(client, &webhook, content(&webhook, &placeholders)).await;
+            }
+        }
+        LiveEvent::DigestCompiled(compiled) => {
+            let placeholders = digest::placeholders(compiled);
+            for webhook in repo
+                .get_enabled(IntegrationEvent::DailyDigest, Some(&compiled.channel_id))
+                .await?
+            {
+                post_to(client, &webhook, content(&webhook, &placeholders)).await;
+            }
+        }
+        LiveEvent::SnapshotFinalised(_) | LiveEvent::Raid(_) => (),
+    }
+
+    Ok(())
+}
+
+/// Spawns a task posting each published event to its configured webhooks.
+///
+/// Events are delivered concurrently, so a webhook that's being retried doesn't hold up the
+/// others.
+pub fn spawn_discord_webhooks(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    let mut rx = events().subscribe();
+    tokio::spawn(async move {
+        // webhook posts have their own retries (honouring discord's `Retry-After`), so only the
+        // client is shared, not `http_client::send`
+        let client = http_client::client().await;
+
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "discord webhooks fell behind, skipped events");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            tokio::spawn(async move {
+                if let Err(e) = deliver(pool, client, &event).await {
+                    tracing::error!(error = ?e, "failed to deliver event to discord webhooks");
+                }
+            });
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::db::models::channel::ChannelId;
+
+    #[test]
+    fn templates_replace_every_placeholder() {
+        let snapshot = SnapshotFinalised {
+            period: Period::Week,
+            period_start: NaiveDate::from_ymd_opt(2026, 6, 29)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        };
+        let top = vec![(String::from("a"), 12), (String::from("b"), 3)];
+
+        assert_eq!(
+            render(
+                IntegrationEvent::LeaderboardSnapshot.default_template(),
+                &leaderboard_placeholders("chan", &snapshot, &top)
+            ),
+            "top chatters in chan for the week of 2026-06-29:\n1. a - 12\n2. b - 3"
+        );
+
+        let stream = StreamOnline {
+            channel_id: ChannelId(String::from("1")),
+            channel_login: String::from("chan"),
+            game: String::from("Just Chatting"),
+            title: String::from("{game} hello"),
+            started_at: snapshot.period_start,
+        };
+
+        // values aren't themselves rendered, but unknown placeholders are left as-is
+        assert_eq!(
+            render(
+                "{title} / {game} / {viewers}",
+                &stream_placeholders(&stream)
+            ),
+            "{game} hello / Just Chatting / {viewers}"
+        );
+    }
+
+    #[test]
+    fn only_discord_urls_are_webhooks() {
+        assert!(is_webhook_url("https://discord.com/api/webhooks/1/abc"));
+        assert!(!is_webhook_url("https://discord.com/api/webhooks/"));
+        assert!(!is_webhook_url("http://discord.com/api/webhooks/1/abc"));
+        assert!(!is_webhook_url("https://example.com/api/webhooks/1/abc"));
+    }
+}
@@ -0,0 +1,3 @@
+//! Posts notable events to external services, as configured per channel in the database.
+
+pub mod discord;
@@ -1,21 +1,74 @@
+use std::sync::Arc;
+
+use serde::Serialize;
 use tokio::sync::{mpsc, oneshot};
 use tracing::instrument;
 
-use crate::irc::commands::{IrcQuery, OutgoingCommand};
+use crate::irc::channels::ConnectionStats;
+use crate::irc::commands::{IncomingMessage, IrcQuery, OutgoingCommand};
 use crate::irc::connection::ConnectionHandle;
 use crate::irc::error::ClientResult;
+use crate::irc::queue::QueueSender;
+use crate::irc::rate_limit::{JoinScheduler, JoinStats};
+use crate::irc::tap::IrcTap;
+use crate::irc::worker::ScoreCounter;
 
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub struct IrcHandle {
-    pub cmd_tx: mpsc::Sender<OutgoingCommand>,
+    pub cmd_tx: QueueSender<OutgoingCommand>,
+    /// Hands chat received over EventSub to the workers
+    pub msg_tx: QueueSender<IncomingMessage>,
     pub query_tx: mpsc::Sender<IrcQuery>,
 
     /// Used to trigger connection resets
     pub connection: ConnectionHandle,
+
+    /// Shared by every connection so reconnects can't exceed the join limit
+    pub joins: Arc<JoinScheduler>,
+
+    /// Raw messages received by the connection, for debugging
+    pub tap: IrcTap,
+
+    /// Counts increments pushed through the API; `None` when detached
+    pub counter: Option<Arc<ScoreCounter>>,
+}
+
+/// Join state across every IRC connection.
+#[derive(Debug, Serialize)]
+pub struct PoolStats {
+    /// Shared by every connection
+    pub joins: JoinStats,
+    pub connections: Vec<ConnectionStats>,
 }
 
 impl IrcHandle {
+    /// A handle that isn't attached to a connection, for exercising the API without IRC; queries
+    /// fail as there's nothing to answer them.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn detached() -> Self {
+        let (cmd_tx, _) =
+            crate::irc::queue::bounded("irc_commands", 1, crate::irc::queue::Backpressure::Block);
+        let (msg_tx, _) =
+            crate::irc::queue::bounded("irc_messages", 1, crate::irc::queue::Backpressure::Block);
+        let (query_tx, _) = mpsc::channel(1);
+        let (reset_tx, _) = mpsc::channel(1);
+        let (_, generation_rx) = tokio::sync::watch::channel(0);
+
+        Self {
+            cmd_tx,
+            msg_tx,
+            query_tx,
+            connection: ConnectionHandle {
+                reset_tx,
+                generation_rx,
+            },
+            joins: Arc::new(JoinScheduler::new(1)),
+            tap: IrcTap::new(),
+            counter: None,
+        }
+    }
+
     #[instrument(skip(self))]
     pub async fn joined_channels(&self) -> ClientResult<Vec<String>> {
         let (tx, rx) = oneshot::channel();
@@ -26,18 +79,61 @@ impl IrcHandle {
         Ok(rx.await?)
     }
 
+    /// The channels the connection joins (and rejoins after reconnecting), whether or not they've
+    /// been joined yet.
+    #[instrument(skip(self))]
+    pub async fn tracked_channels(&self) -> ClientResult<Vec<String>> {
+        let (tx, rx) = oneshot::channel();
+        self.query_tx
+            .send(IrcQuery::GetTrackedChannels { reply: tx })
+            .await?;
+
+        Ok(rx.await?)
+    }
+
     pub async fn insert_channel(&self, channel: String) -> ClientResult<String> {
         let (tx, rx) = oneshot::channel();
         self.query_tx
-            .send(IrcQuery::InsertNewChannel {
-                channel,
-                reply: tx,
-            })
+            .send(IrcQuery::InsertNewChannel { channel, reply: tx })
             .await?;
 
         Ok(rx.await?)
     }
 
+    /// Parts a channel and stops rejoining it, including after a restart.
+    pub async fn remove_channel(&self, channel: String) -> ClientResult<String> {
+        let (tx, rx) = oneshot::channel();
+        self.query_tx
+            .send(IrcQuery::RemoveChannel { channel, reply: tx })
+            .await?;
+
+        Ok(rx.await?)
+    }
+
+    pub fn join_stats(&self) -> JoinStats {
+        self.joins.stats()
+    }
+
+    #[instrument(skip(self))]
+    pub async fn stats(&self) -> ClientResult<PoolStats> {
+        let (tx, rx) = oneshot::channel();
+        self.query_tx.send(IrcQuery::GetStats { reply: tx }).await?;
+
+        Ok(PoolStats {
+            joins: self.join_stats(),
+            connections: vec![rx.await?],
+        })
+    }
+
+    /// Queues joins for any missing channels immediately, returning how many were queued.
+    #[instrument(skip(self))]
+    pub async fn resync(&self) -> ClientResult<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.query_tx.send(IrcQuery::Resync { reply: tx }).await?;
+
+        Ok(rx.await?)
+    }
+
     #[allow(dead_code)]
     #[instrument]
     pub async fn force_reconnect(&mut self) {
@@ -1,11 +1,23 @@
 #![allow(dead_code)]
 
-use std::collections::HashSet;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::sync::mpsc;
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::MissedTickBehavior;
 use tracing::instrument;
 
+use crate::irc::coordination::{Coordinator, OwnershipStats};
+use crate::irc::keepalive::KeepaliveStats;
+use crate::irc::membership;
+use crate::irc::rate_limit::JoinScheduler;
+
+/// A JOIN that hasn't been confirmed within this long is retried.
+const JOIN_RETRY_AFTER: Duration = Duration::from_secs(30);
+
 #[derive(Debug)]
 pub enum ChannelEvent {
     /// JOIN observed for our user
@@ -14,6 +26,19 @@ pub enum ChannelEvent {
     /// PART observed for our user
     Parted(String),
 
+    /// Channel added to the desired set at runtime
+    Added(String),
+
+    /// Channel removed from the desired set at runtime
+    Removed(String),
+
+    /// Snapshot of the manager's state, requested via the API
+    Stats(oneshot::Sender<ConnectionStats>),
+
+    /// Queue any missing channels now rather than at the next check, replying with how many were
+    /// queued
+    Resync(oneshot::Sender<usize>),
+
     /// Connection up
     Connected,
 
@@ -21,66 +46,142 @@ pub enum ChannelEvent {
     Disconnected,
 }
 
+/// A connection's channel membership and join queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionStats {
+    pub id: usize,
+    pub expected: usize,
+    pub joined: usize,
+    /// Expected channels that aren't joined, sorted
+    pub missing: Vec<String>,
+    /// Channels waiting for a join slot, in the order they'll be joined
+    pub queued: Vec<String>,
+    /// Channels with an unconfirmed JOIN
+    pub awaiting_confirmation: usize,
+    /// Which instance holds each channel, when channels are shared between instances
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<OwnershipStats>,
+    /// PING round trips and missed keepalives
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keepalive: Option<KeepaliveStats>,
+}
+
 /// Commands to send back to the supervisor to execute on the socket
 #[derive(Debug)]
 pub enum ChannelAction {
     Join(Vec<String>),
+    Part(Vec<String>),
 }
 
+/// Tracks which channels should be joined and rejoins any that are missing.
+///
+/// Joins are queued and sent one at a time as the `JoinScheduler` allows, so that reconnecting
+/// with a large channel list doesn't trip Twitch's join throttling. Channels that were joined
+/// before a restart are queued first, and channels added at runtime skip the queue.
+///
+/// With a `Coordinator`, only the expected channels this instance holds a lease for are joined,
+/// and channels whose lease moves to another instance are parted.
 #[derive(Debug)]
 pub struct ChannelManager {
     expected: HashSet<String>,
     joined: HashSet<String>,
+    previously_joined: HashSet<String>,
+    /// Channels waiting for a join slot
+    pending: VecDeque<String>,
+    /// When a JOIN was last sent for each unconfirmed channel
+    requested: HashMap<String, Instant>,
+    scheduler: Arc<JoinScheduler>,
+    redis_pool: ConnectionManager,
+    coordinator: Option<Coordinator>,
+    /// Expected channels this instance holds a lease for; unused without a coordinator
+    owned: HashSet<String>,
+    /// When the leases in `owned` lapse unless they're renewed
+    leases_expire: Option<Instant>,
+    keepalive: Option<watch::Receiver<KeepaliveStats>>,
     event_rx: mpsc::Receiver<ChannelEvent>,
     action_tx: mpsc::Sender<ChannelAction>,
     nick: String,
 }
 
 impl ChannelManager {
-    #[instrument(skip(event_rx, action_tx))]
+    #[instrument(skip(channels, previously_joined, redis_pool, event_rx, action_tx))]
     pub fn new(
         channels: Vec<String>,
+        previously_joined: HashSet<String>,
+        scheduler: Arc<JoinScheduler>,
+        redis_pool: ConnectionManager,
         nick: String,
         event_rx: mpsc::Receiver<ChannelEvent>,
         action_tx: mpsc::Sender<ChannelAction>,
     ) -> Self {
         let expected: HashSet<String> = channels
-            .into_iter()
-            .map(|ch| {
-                if ch.starts_with('#') {
-                    ch
-                } else {
-                    format!("#{ch}")
-                }
-            })
+            .iter()
+            .map(|ch| membership::normalize(ch))
             .collect();
 
         Self {
             expected,
             joined: HashSet::new(),
+            previously_joined,
+            pending: VecDeque::new(),
+            requested: HashMap::new(),
+            scheduler,
+            redis_pool,
+            coordinator: None,
+            owned: HashSet::new(),
+            leases_expire: None,
+            keepalive: None,
             event_rx,
             action_tx,
             nick,
         }
     }
 
+    /// Only joins the expected channels this instance holds a lease for.
+    pub fn with_coordinator(mut self, coordinator: Option<Coordinator>) -> Self {
+        self.coordinator = coordinator;
+        self
+    }
+
+    /// Includes the connection's keepalive stats in its `ConnectionStats`.
+    pub fn with_keepalive(mut self, keepalive: watch::Receiver<KeepaliveStats>) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
     #[instrument(skip(self))]
     pub async fn run(mut self) {
         const MIN_CHECK: Duration = Duration::from_secs(5);
         const MAX_CHECK: Duration = Duration::from_secs(480);
 
-        let mut check_interval = Duration::from_secs(0); // start joining channels immediately
+        let mut check_interval = MIN_CHECK;
         let mut check_timer = Box::pin(tokio::time::sleep(check_interval));
 
+        // polled while channels are queued, and pushed back while the scheduler is throttling
+        let mut join_timer = Box::pin(tokio::time::sleep(Duration::ZERO));
+
+        // leases are claimed on the first tick, so nothing is joined until they're held
+        let mut lease_timer = tokio::time::interval(
+            self.coordinator
+                .as_ref()
+                .map_or(MAX_CHECK, Coordinator::renew_every),
+        );
+        lease_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
         tracing::info!("starting channel manager");
+        self.queue_missing();
 
         loop {
+            self.scheduler.set_queued(self.pending.len());
+
             tokio::select! {
                 Some(event) = self.event_rx.recv() => {
                     match event {
                         ChannelEvent::Joined(channel) => {
                             tracing::debug!(%channel, "JOIN recv");
 
+                            self.requested.remove(&channel);
+                            membership::record_joined(&mut self.redis_pool, &channel).await;
                             self.joined.insert(channel);
                         }
 
@@ -88,23 +189,60 @@ impl ChannelManager {
                             tracing::debug!(%channel, "PART recv");
 
                             self.joined.remove(&channel);
+                            membership::record_parted(&mut self.redis_pool, &channel).await;
+
+                            if self.wants(&channel) && !self.pending.contains(&channel) {
+                                tracing::warn!(%channel, "queueing rejoin due to unexpected PART");
+                                self.pending.push_front(channel);
+                            }
+                        }
+
+                        ChannelEvent::Added(channel) => {
+                            let channel = membership::normalize(&channel);
+                            tracing::debug!(%channel, "channel added");
+
+                            membership::record_desired(&mut self.redis_pool, &channel).await;
+                            self.expected.insert(channel.clone());
+                            self.sync_leases().await;
 
-                            if self.expected.contains(&channel) {
-                                tracing::warn!(%channel, "attempting rejoin due to unexpected PART");
-                                _ = self.action_tx
-                                    .send(ChannelAction::Join(vec![channel]))
-                                    .await;
+                            if self.wants(&channel) && !self.joined.contains(&channel) {
+                                self.pending.retain(|ch| ch != &channel);
+                                self.pending.push_front(channel);
                             }
                         }
 
+                        ChannelEvent::Removed(channel) => {
+                            tracing::debug!(%channel, "channel removed");
+
+                            membership::record_removed(&mut self.redis_pool, &channel).await;
+                            self.expected.remove(&channel);
+                            self.owned.remove(&channel);
+                            if let Some(coordinator) = self.coordinator.as_mut()
+                                && let Err(e) = coordinator.release(&[&channel]).await
+                            {
+                                tracing::warn!(error = ?e, %channel, "failed to release channel lease");
+                            }
+                            self.pending.retain(|ch| ch != &channel);
+                            self.requested.remove(&channel);
+                        }
+
+                        ChannelEvent::Stats(reply) => {
+                            _ = reply.send(self.stats().await);
+                        }
+
+                        ChannelEvent::Resync(reply) => {
+                            let queued = self.queue_missing();
+                            tracing::info!(queued, "resync requested");
+                            _ = reply.send(queued);
+
+                            check_interval = MIN_CHECK;
+                            check_timer.set(tokio::time::sleep(check_interval));
+                        }
+
                         ChannelEvent::Connected => {
                             self.joined.clear();
-                            let all: Vec<String> = self.expected.iter().cloned().collect();
-                            tracing::info!(count = all.len(), "initial JOIN on connect");
-
-                            _ = self.action_tx
-                                .send(ChannelAction::Join(all))
-                                .await;
+                            self.requested.clear();
+                            self.queue_missing();
 
                             check_interval = MIN_CHECK;
                             check_timer.set(tokio::time::sleep(check_interval));
@@ -114,17 +252,53 @@ impl ChannelManager {
 
                             tracing::debug!("supervisor initiated disconnect");
                             self.joined.clear();
+                            self.pending.clear();
+                            self.requested.clear();
                         }
                     }
                 }
 
+                _ = join_timer.as_mut(), if !self.pending.is_empty() => {
+                    let Some(channel) = self.next_pending() else {
+                        continue;
+                    };
+
+                    if let Err(wait) = self.scheduler.try_acquire() {
+                        tracing::trace!(?wait, queued = self.pending.len() + 1, "join throttled");
+                        self.pending.push_front(channel);
+                        join_timer.set(tokio::time::sleep(wait));
+                        continue;
+                    }
+
+                    tracing::debug!(%channel, remaining = self.pending.len(), "sending queued JOIN");
+                    self.requested.insert(channel.clone(), Instant::now());
+
+                    // the connection has gone away, and a new manager is started with the next one
+                    if self.action_tx.send(ChannelAction::Join(vec![channel])).await.is_err() {
+                        tracing::debug!("action channel closed - stopping channel manager");
+                        self.scheduler.set_queued(0);
+                        return;
+                    }
+                }
+
+                _ = lease_timer.tick(), if self.coordinator.is_some() => {
+                    self.sync_leases().await;
+
+                    // the connection has gone away, as above
+                    if !self.part_unowned().await {
+                        self.scheduler.set_queued(0);
+                        return;
+                    }
+
+                    if self.queue_missing() > 0 {
+                        join_timer.set(tokio::time::sleep(Duration::ZERO));
+                    }
+                }
+
                 _ = check_timer.as_mut() => {
-                    let missing: Vec<String> = self.expected
-                        .difference(&self.joined)
-                        .cloned()
-                        .collect::<Vec<_>>();
+                    let queued = self.queue_missing();
 
-                    if missing.is_empty() {
+                    if queued == 0 && self.pending.is_empty() {
                         check_interval = (check_interval * 2).min(MAX_CHECK);
                         tracing::debug!(
                             joined = self.joined.len(),
@@ -133,18 +307,11 @@ impl ChannelManager {
                         );
                     } else {
                         tracing::warn!(
-                            missing_count = missing.len(),
-                            ?missing,
-                            expected = ?self.expected,
-                            "attempting to join missing channels"
+                            queued,
+                            pending = self.pending.len(),
+                            "waiting to join missing channels"
                         );
 
-                        let truncate = (5).min(missing.len());
-
-                        _ = self.action_tx
-                            .send(ChannelAction::Join(missing[..truncate].to_vec()))
-                            .await;
-
                         check_interval = MIN_CHECK;
                     }
 
@@ -154,8 +321,156 @@ impl ChannelManager {
         }
     }
 
+    /// Whether a channel should be joined by this instance.
+    fn wants(&self, channel: &str) -> bool {
+        self.expected.contains(channel)
+            && (self.coordinator.is_none() || self.owned.contains(channel))
+    }
+
+    /// Renews this instance's leases, keeping the channels it held before if Redis can't be
+    /// reached until their leases would have lapsed (by which point another instance may have
+    /// taken them over); without a coordinator this does nothing.
+    async fn sync_leases(&mut self) {
+        let Some(coordinator) = self.coordinator.as_mut() else {
+            return;
+        };
+
+        // leases are set with their TTL from when they were claimed, not when the reply arrives
+        let renewing_at = Instant::now();
+        let ttl = coordinator.ttl();
+        match coordinator.sync(&self.expected).await {
+            Ok(owned) => {
+                let gained = owned.difference(&self.owned).count();
+                let lost = self.owned.difference(&owned).count();
+                if gained > 0 || lost > 0 {
+                    tracing::info!(
+                        gained,
+                        lost,
+                        owned = owned.len(),
+                        "channel ownership changed"
+                    );
+                }
+
+                self.owned = owned;
+                self.leases_expire = Some(renewing_at + ttl);
+            }
+            Err(e) => {
+                tracing::warn!(error = ?e, "failed to renew channel leases");
+
+                if !self.owned.is_empty()
+                    && self
+                        .leases_expire
+                        .is_none_or(|expire| Instant::now() >= expire)
+                {
+                    tracing::warn!(
+                        lapsed = self.owned.len(),
+                        "channel leases have lapsed - parting their channels"
+                    );
+                    self.owned.clear();
+                }
+            }
+        }
+    }
+
+    /// Parts joined channels that this instance no longer holds, returning `false` if the
+    /// connection has gone away.
+    async fn part_unowned(&mut self) -> bool {
+        if self.coordinator.is_none() {
+            return true;
+        }
+
+        let unowned: Vec<String> = self
+            .joined
+            .iter()
+            .filter(|ch| !self.wants(ch))
+            .cloned()
+            .collect();
+
+        self.pending.retain(|ch| self.owned.contains(ch));
+        if unowned.is_empty() {
+            return true;
+        }
+
+        tracing::info!(channels = ?unowned, "parting channels held by another instance");
+        self.action_tx
+            .send(ChannelAction::Part(unowned))
+            .await
+            .is_ok()
+    }
+
+    /// Queues expected channels that aren't joined, queued, or awaiting a recent JOIN, returning
+    /// how many were queued.
+    fn queue_missing(&mut self) -> usize {
+        let mut missing: Vec<String> = self
+            .expected
+            .iter()
+            .filter(|ch| self.wants(ch))
+            .filter(|ch| !self.joined.contains(*ch) && !self.pending.contains(*ch))
+            .filter(|ch| {
+                self.requested
+                    .get(*ch)
+                    .is_none_or(|at| at.elapsed() >= JOIN_RETRY_AFTER)
+            })
+            .cloned()
+            .collect();
+
+        missing.sort_by(|a, b| {
+            self.previously_joined
+                .contains(b)
+                .cmp(&self.previously_joined.contains(a))
+                .then_with(|| a.cmp(b))
+        });
+
+        let queued = missing.len();
+        self.pending.extend(missing);
+
+        queued
+    }
+
+    /// Pops the next queued channel that still needs joining.
+    fn next_pending(&mut self) -> Option<String> {
+        while let Some(channel) = self.pending.pop_front() {
+            if self.wants(&channel) && !self.joined.contains(&channel) {
+                return Some(channel);
+            }
+        }
+
+        None
+    }
+
+    async fn stats(&mut self) -> ConnectionStats {
+        let mut missing: Vec<String> = self
+            .expected
+            .iter()
+            .filter(|ch| self.wants(ch) && !self.joined.contains(*ch))
+            .cloned()
+            .collect();
+        missing.sort();
+
+        let ownership = match self.coordinator.as_mut() {
+            Some(coordinator) => coordinator
+                .ownership(&self.expected)
+                .await
+                .inspect_err(|e| tracing::warn!(error = ?e, "failed to read channel leases"))
+                .ok(),
+            None => None,
+        };
+
+        ConnectionStats {
+            // there's a single connection for now
+            id: 0,
+            expected: self.expected.len(),
+            joined: self.joined.len(),
+            missing,
+            queued: self.pending.iter().cloned().collect(),
+            awaiting_confirmation: self.requested.len(),
+            ownership,
+            keepalive: self.keepalive.as_ref().map(|rx| rx.borrow().clone()),
+        }
+    }
+
     #[instrument(skip(self))]
     pub fn add_channel(&mut self, channel: String) {
-        self.expected.insert(channel);
+        self.expected.insert(membership::normalize(&channel));
     }
 }
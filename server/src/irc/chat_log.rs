@@ -0,0 +1,400 @@
+//! Persistent chat logs.
+//!
+//! Messages sent to joined channels are written as JSON lines under `CHAT_LOG_DIR/{channel}/`, so
+//! that backfills and audits can be re-run against our own record of chat. Each line keeps the raw
+//! message alongside the fields most queries want, so logs can be re-parsed if parsing changes.
+//!
+//! A channel's file is rotated once it passes `CHAT_LOG_MAX_FILE_MB` or has been open for
+//! `CHAT_LOG_ROTATE_SECS`, and files are named by when they were opened so they sort in order;
+//! only the newest `CHAT_LOG_RETAIN_FILES` are kept per channel. Files are written on a dedicated
+//! thread - the connection only queues records, dropping the oldest queued records if the writer
+//! falls `CHAT_LOG_CAPACITY` records behind.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use irc::proto::{Command, Message, Prefix};
+use serde::{Deserialize, Serialize};
+
+use crate::irc::queue::{self, Backpressure, QueueSender};
+use crate::irc::tap::channel_of;
+use crate::util::env::{EnvResult, Var};
+use crate::var;
+
+const CHAT_LOG_CAPACITY: usize = 4096;
+const FILE_NAME_FORMAT: &str = "%Y%m%dT%H%M%S";
+const FILE_EXTENSION: &str = "jsonl";
+
+#[derive(Debug, Clone)]
+pub struct ChatLogConfig {
+    pub dir: PathBuf,
+    pub max_file_bytes: u64,
+    pub rotate_after: Duration,
+    /// `0` keeps every file
+    pub retain_files: usize,
+}
+
+/// The chat log configuration, or `None` if `CHAT_LOG_DIR` is unset.
+pub async fn chat_log_config() -> Option<ChatLogConfig> {
+    let dir = var!(Var::ChatLogDir).await.ok()?.trim();
+    if dir.is_empty() {
+        return None;
+    }
+
+    let max_file_mb: u64 = parse_or(var!(Var::ChatLogMaxFileMb).await, 64);
+    let rotate_secs: u64 = parse_or(var!(Var::ChatLogRotateSecs).await, 60 * 60 * 24);
+
+    Some(ChatLogConfig {
+        dir: PathBuf::from(dir),
+        max_file_bytes: max_file_mb.max(1) * 1024 * 1024,
+        rotate_after: Duration::from_secs(rotate_secs.max(1)),
+        retain_files: parse_or(var!(Var::ChatLogRetainFiles).await, 30),
+    })
+}
+
+fn parse_or<T: FromStr>(val: EnvResult<&str>, default: T) -> T {
+    match val {
+        Ok(val) => val.trim().parse().unwrap_or_else(|_| {
+            tracing::warn!(val, "invalid chat log setting - using default");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// A single logged message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatLogRecord {
+    pub received_at: NaiveDateTime,
+    /// As `#login`
+    pub channel: String,
+    pub command: String,
+    /// The sender's login; `None` for messages from the server itself
+    pub login: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    /// The message text, or the trailing parameter for commands other than PRIVMSG
+    pub text: Option<String>,
+    pub raw: String,
+}
+
+impl ChatLogRecord {
+    /// Returns `None` for messages that weren't sent to a channel, and for JOINs and PARTs.
+    pub fn from_message(msg: &Message, received_at: NaiveDateTime) -> Option<Self> {
+        let (command, text) = match &msg.command {
+            Command::JOIN(..) | Command::PART(..) => return None,
+            Command::PRIVMSG(_, text) => ("PRIVMSG".to_string(), Some(text.clone())),
+            Command::NOTICE(_, text) => ("NOTICE".to_string(), Some(text.clone())),
+            Command::Raw(command, args) => (command.clone(), args.get(1).cloned()),
+            _ => return None,
+        };
+        let channel = channel_of(msg)?;
+
+        let login = match &msg.prefix {
+            Some(Prefix::Nickname(nick, _, _)) => Some(nick.to_lowercase()),
+            _ => None,
+        };
+
+        let tags = msg
+            .tags
+            .iter()
+            .flatten()
+            .map(|tag| (tag.0.clone(), tag.1.clone().unwrap_or_default()))
+            .collect();
+
+        Some(Self {
+            received_at,
+            channel,
+            command,
+            login,
+            tags,
+            text,
+            raw: msg.to_string().trim_end().to_string(),
+        })
+    }
+}
+
+/// Queues messages to be written to the chat log; does nothing if logging is disabled.
+#[derive(Debug, Clone, Default)]
+pub struct ChatLogger {
+    tx: Option<QueueSender<ChatLogRecord>>,
+}
+
+impl ChatLogger {
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Starts the writer thread, creating the log directory if it doesn't exist.
+    pub fn spawn(config: ChatLogConfig) -> io::Result<(Self, JoinHandle<()>)> {
+        fs::create_dir_all(&config.dir)?;
+
+        // a slow disk shouldn't hold up counting, so it's the log that loses messages
+        let (tx, rx) = queue::bounded("chat_log", CHAT_LOG_CAPACITY, Backpressure::DropOldest);
+        let handle = std::thread::Builder::new()
+            .name("chat-log".to_string())
+            .spawn(move || {
+                let mut writer = ChatLogWriter::new(config);
+                while let Ok(record) = rx.recv_blocking() {
+                    let mut batch = vec![record];
+                    while let Ok(record) = rx.try_recv() {
+                        batch.push(record);
+                    }
+
+                    for record in &batch {
+                        if let Err(e) = writer.write(record) {
+                            tracing::error!(error = ?e, channel = record.channel, "chat log write failed");
+                        }
+                    }
+
+                    if let Err(e) = writer.flush() {
+                        tracing::error!(error = ?e, "chat log flush failed");
+                    }
+                }
+            })?;
+
+        Ok((Self { tx: Some(tx) }, handle))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    pub fn record(&self, msg: &Message) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+
+        let Some(record) = ChatLogRecord::from_message(msg, Utc::now().naive_utc()) else {
+            return;
+        };
+
+        if tx.len() + 1 == CHAT_LOG_CAPACITY {
+            tracing::warn!("chat log writer is behind - dropping the oldest messages");
+        }
+
+        _ = tx.send_now(record);
+    }
+}
+
+#[derive(Debug)]
+struct OpenLog {
+    file: BufWriter<File>,
+    opened_at: NaiveDateTime,
+    written: u64,
+}
+
+/// Owns every channel's current log file.
+#[derive(Debug)]
+struct ChatLogWriter {
+    config: ChatLogConfig,
+    files: HashMap<String, OpenLog>,
+}
+
+impl ChatLogWriter {
+    fn new(config: ChatLogConfig) -> Self {
+        Self {
+            config,
+            files: HashMap::new(),
+        }
+    }
+
+    /// Writes a record to its channel's file, rotating first if the file is full or too old.
+    /// Records are timed by when they were received, not when they're written.
+    fn write(&mut self, record: &ChatLogRecord) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let rotate = self.files.get(&record.channel).is_none_or(|log| {
+            log.written >= self.config.max_file_bytes
+                || record
+                    .received_at
+                    .signed_duration_since(log.opened_at)
+                    .to_std()
+                    .is_ok_and(|age| age >= self.config.rotate_after)
+        });
+
+        if rotate {
+            if let Some(mut previous) = self.files.remove(&record.channel) {
+                previous.file.flush()?;
+            }
+
+            let log = self.open(&record.channel, record.received_at)?;
+            self.files.insert(record.channel.clone(), log);
+        }
+
+        let log = self
+            .files
+            .get_mut(&record.channel)
+            .expect("opened above if missing");
+        log.file.write_all(&line)?;
+        log.written += line.len() as u64;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for log in self.files.values_mut() {
+            log.file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn open(&self, channel: &str, at: NaiveDateTime) -> io::Result<OpenLog> {
+        let dir = self.channel_dir(channel);
+        fs::create_dir_all(&dir)?;
+
+        // files opened within the same second are told apart (and kept in order) by a sequence
+        let stem = at.format(FILE_NAME_FORMAT).to_string();
+        let mut seq = 0;
+        let mut path = dir.join(format!("{stem}-{seq:03}.{FILE_EXTENSION}"));
+        while path.exists() {
+            seq += 1;
+            path = dir.join(format!("{stem}-{seq:03}.{FILE_EXTENSION}"));
+        }
+
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)?;
+        tracing::info!(path = %path.display(), "opened chat log");
+
+        if let Err(e) = self.prune(&dir) {
+            tracing::warn!(error = ?e, dir = %dir.display(), "failed to prune chat logs");
+        }
+
+        Ok(OpenLog {
+            file: BufWriter::new(file),
+            opened_at: at,
+            written: 0,
+        })
+    }
+
+    fn channel_dir(&self, channel: &str) -> PathBuf {
+        let name: String = channel
+            .trim_start_matches('#')
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+
+        self.config.dir.join(name)
+    }
+
+    /// Deletes the oldest files in a channel's directory beyond the retention limit.
+    fn prune(&self, dir: &Path) -> io::Result<()> {
+        if self.config.retain_files == 0 {
+            return Ok(());
+        }
+
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == FILE_EXTENSION))
+            .collect();
+
+        // names sort by the time they were opened
+        files.sort();
+        let excess = files.len().saturating_sub(self.config.retain_files);
+        for path in &files[..excess] {
+            fs::remove_file(path)?;
+            tracing::info!(path = %path.display(), "removed old chat log");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(channel: &str, at: &str, text: &str) -> ChatLogRecord {
+        let msg: Message = format!(
+            "@id=1;user-id=2;room-id=3 :someone!someone@someone.tmi.twitch.tv PRIVMSG {channel} :{text}\r\n"
+        )
+        .parse()
+        .unwrap();
+        let at = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        ChatLogRecord::from_message(&msg, at).unwrap()
+    }
+
+    fn log_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn records_keep_the_fields_needed_for_backfills() {
+        let record = record("#Foo", "2026-01-01 00:00:00", "hello there");
+
+        assert_eq!(record.channel, "#foo");
+        assert_eq!(record.command, "PRIVMSG");
+        assert_eq!(record.login.as_deref(), Some("someone"));
+        assert_eq!(record.text.as_deref(), Some("hello there"));
+        assert_eq!(record.tags.get("user-id").map(String::as_str), Some("2"));
+
+        let join: Message = ":a!a@a.tmi.twitch.tv JOIN #foo\r\n".parse().unwrap();
+        assert!(ChatLogRecord::from_message(&join, record.received_at).is_none());
+    }
+
+    #[test]
+    fn files_rotate_by_age_and_size_and_old_files_are_pruned() {
+        let dir = std::env::temp_dir().join(format!(
+            "chat-log-test-{}-{}",
+            std::process::id(),
+            Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let mut writer = ChatLogWriter::new(ChatLogConfig {
+            dir: dir.clone(),
+            max_file_bytes: 1024,
+            rotate_after: Duration::from_secs(60 * 60),
+            retain_files: 2,
+        });
+
+        writer
+            .write(&record("#foo", "2026-01-01 00:00:00", "first"))
+            .unwrap();
+        writer
+            .write(&record("#foo", "2026-01-01 00:30:00", "same file"))
+            .unwrap();
+        writer
+            .write(&record("#bar", "2026-01-01 00:30:00", "other channel"))
+            .unwrap();
+        // rotated by age...
+        let long = "x".repeat(1024);
+        writer
+            .write(&record("#foo", "2026-01-01 01:00:00", &long))
+            .unwrap();
+        // ...then by size, within the same second
+        writer
+            .write(&record("#foo", "2026-01-01 01:00:00", "latest"))
+            .unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(
+            log_files(&dir.join("foo")),
+            ["20260101T010000-000.jsonl", "20260101T010000-001.jsonl"]
+        );
+        assert_eq!(log_files(&dir.join("bar")), ["20260101T003000-000.jsonl"]);
+
+        let latest = fs::read_to_string(dir.join("foo/20260101T010000-001.jsonl")).unwrap();
+        let records: Vec<ChatLogRecord> = latest
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].text.as_deref(), Some("latest"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
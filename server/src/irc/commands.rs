@@ -1,54 +1,126 @@
+use chrono::NaiveDateTime;
+use thiserror::Error;
 use tokio::sync::oneshot;
 
-#[derive(Debug, Default)]
+use crate::db::models::IdError;
+use crate::db::prelude::{ChannelId, ChatterId};
+use crate::irc::channels::ConnectionStats;
+use crate::irc::message::ChatMessage;
+use crate::irc::permission::{Badge, PermissionLevel};
+
+#[derive(Debug, Clone)]
 pub struct IrcTags {
-    pub user_id: String,
+    pub user_id: ChatterId,
     pub user_login: String,
-
-    #[allow(dead_code)]
     pub display_name: String,
-
     pub color: String,
     pub channel_name: String,
-    pub channel_id: String,
-    pub source_channel_id: String,
+    pub channel_id: ChannelId,
+    /// Only present for messages sent in a shared chat session
+    pub source_channel_id: Option<ChannelId>,
     pub msg_id: String,
+    /// Empty unless the message uses twitch emotes
+    pub emotes: Vec<Emote>,
+    /// From the `tmi-sent-ts` tag
+    pub sent_at: Option<NaiveDateTime>,
+    /// From the `badges` tag
+    pub badges: Vec<Badge>,
+    pub permission: PermissionLevel,
 }
 
-#[derive(Debug)]
+/// A single use of an emote in a message, from the `emotes` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Emote {
+    pub id: String,
+    /// Char (not byte) offset of the emote's first char in the message
+    pub start: usize,
+    /// Inclusive
+    pub end: usize,
+}
+
+impl IrcTags {
+    /// Returns true for messages sent during a shared chat session that originated in the other
+    /// channel.
+    pub fn is_shared_from_elsewhere(&self) -> bool {
+        match &self.source_channel_id {
+            // TODO i still want to increment if the source is not a tracked channel
+            //  but i cant be bothered rn lowkey
+            Some(source_channel_id) => self.channel_id != *source_channel_id,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TagError {
+    #[error("missing required tag '{0}'")]
+    Missing(&'static str),
+
+    #[error("invalid id in tag '{tag}': {source}")]
+    InvalidId {
+        tag: &'static str,
+        #[source]
+        source: IdError,
+    },
+}
+
+/// The kind of event a USERNOTICE was sent for, from its `msg-id` tag.
+///
+/// The chatter the notice is about (e.g. the subscriber or raider) is in the notice's `IrcTags`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UserNoticeType {
-    // Sub,
+    Sub {
+        plan: String,
+    },
+    Resub {
+        cumulative_months: u32,
+        plan: String,
+    },
+    Raid {
+        viewer_count: u32,
+    },
+    Announcement,
+
+    #[allow(dead_code)]
+    /// Not actually "dead" as we need to assign to it for logging
+    Other(String),
     // SubGift,
     // SubMysteryGift,
     // GiftPaidUpgrade,
     // RewardGift,
     // AnonGiftPaidUpgrade,
-    // Raid,
     // Unraid,
     // BitsBadgeTier,
     // SharedChatNotice,
-    Resub,
+}
 
-    #[allow(dead_code)]
-    /// Not actually "dead" as we need to assign to it for logging
-    Other(String),
+impl UserNoticeType {
+    /// Returns true if the notice's message was written by the chatter themselves (i.e. the
+    /// message attached to a (re)sub), so it can be counted like a regular chat message.
+    pub fn has_chatter_message(&self) -> bool {
+        matches!(self, Self::Sub { .. } | Self::Resub { .. })
+    }
 }
 
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum IncomingMessage {
+    /// A chatter was timed out or banned, or (without a target) the whole chat was cleared
     Clearchat {
-        channel: String,
-        chatter: String,
+        channel_id: ChannelId,
+        channel_name: String,
+        target_user_id: Option<ChatterId>,
+        target_login: Option<String>,
+        /// Timeout length in seconds; `None` for a permanent ban
+        ban_duration: Option<u32>,
     },
+    /// A single message was deleted
     Clearmsg {
-        channel: String,
-        message: String,
-    },
-    Privmsg {
-        tags: IrcTags,
-        text: String,
+        channel_name: String,
+        login: String,
+        target_msg_id: String,
     },
+    Privmsg(ChatMessage),
     Notice {
         channel: String,
         message: String,
@@ -60,9 +132,10 @@ pub enum IncomingMessage {
         id: Option<String>,
     },
     Usernotice {
-        channel: String,
-        chatter: String,
+        tags: IrcTags,
         notice_type: UserNoticeType,
+        /// The chatter's own message, if they included one
+        text: Option<String>,
     },
     Join {
         channel: String,
@@ -74,13 +147,75 @@ pub enum IncomingMessage {
     },
 }
 
+/// The kind of an `IncomingMessage`, used by event handlers to declare which events they handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Privmsg,
+    Usernotice,
+    Clearchat,
+    Clearmsg,
+    Other,
+}
+
+impl IncomingMessage {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::Privmsg(_) => EventKind::Privmsg,
+            Self::Usernotice { .. } => EventKind::Usernotice,
+            Self::Clearchat { .. } => EventKind::Clearchat,
+            Self::Clearmsg { .. } => EventKind::Clearmsg,
+            _ => EventKind::Other,
+        }
+    }
+
+    /// The login of the channel the event was sent in, without the leading `#`.
+    pub fn channel_name(&self) -> Option<&str> {
+        match self {
+            Self::Privmsg(message) => Some(&message.channel_login),
+            Self::Usernotice { tags, .. } => Some(&tags.channel_name),
+            Self::Clearchat { channel_name, .. } | Self::Clearmsg { channel_name, .. } => {
+                Some(channel_name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true for events sent during a shared chat session that originated in the other
+    /// channel.
+    pub fn is_shared_from_elsewhere(&self) -> bool {
+        match self {
+            Self::Privmsg(message) => message.is_shared_from_elsewhere(),
+            Self::Usernotice { tags, .. } => tags.is_shared_from_elsewhere(),
+            _ => false,
+        }
+    }
+}
+
 pub enum OutgoingCommand {
     Reply { message: irc::proto::Message },
 }
 
 pub enum IrcQuery {
-    GetJoinedChannels { reply: oneshot::Sender<Vec<String>> },
-    InsertNewChannel { channel: String, reply: oneshot::Sender<String> },
+    GetJoinedChannels {
+        reply: oneshot::Sender<Vec<String>>,
+    },
+    GetTrackedChannels {
+        reply: oneshot::Sender<Vec<String>>,
+    },
+    InsertNewChannel {
+        channel: String,
+        reply: oneshot::Sender<String>,
+    },
+    RemoveChannel {
+        channel: String,
+        reply: oneshot::Sender<String>,
+    },
+    GetStats {
+        reply: oneshot::Sender<ConnectionStats>,
+    },
+    Resync {
+        reply: oneshot::Sender<usize>,
+    },
 }
 
 #[derive(Debug)]
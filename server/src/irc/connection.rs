@@ -1,10 +1,13 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
 use futures::StreamExt;
 use irc::client::{Client, data};
+use redis::aio::ConnectionManager;
 use tokio::sync::mpsc;
 use tokio::sync::watch;
 use tracing::instrument;
@@ -13,25 +16,44 @@ use crate::irc::IrcQuery;
 use crate::irc::channels::ChannelAction;
 use crate::irc::channels::ChannelEvent;
 use crate::irc::channels::ChannelManager;
+use crate::irc::chat_log::ChatLogger;
 use crate::irc::commands::TwitchCapability;
+use crate::irc::coordination::{Coordinator, LeaseConfig};
 use crate::irc::error::ClientResult;
 use crate::irc::error::ConnectionClientError;
+use crate::irc::keepalive::{Keepalive, PING_INTERVAL};
+use crate::irc::membership::{self, RestoredMembership};
 use crate::irc::parse::is_counter_user;
 use crate::irc::parse::is_pong;
 use crate::irc::parse::parse_incoming;
+use crate::irc::queue::{QueueReceiver, QueueSender};
+use crate::irc::rate_limit::JoinScheduler;
+use crate::irc::room_state::room_states;
+use crate::irc::tap::IrcTap;
 use crate::irc::worker::COUNTER_USER;
+use crate::util::availability::{Service, availability};
 use crate::util::env;
+use crate::util::flags::{Flag, flags};
 
 use super::commands::{IncomingMessage, OutgoingCommand};
 
-const KEEPALIVE_INTERVAL: u64 = 180;
+const RECONNECT_BASE_DELAY: u64 = 3;
+const RECONNECT_MAX_DELAY: u64 = 60;
 
 #[derive(Debug)]
 pub struct ConnectionSupervisor {
     channels: Vec<String>,
+    previously_joined: HashSet<String>,
+    joins: Arc<JoinScheduler>,
+    redis_pool: ConnectionManager,
+    tap: IrcTap,
+    chat_log: ChatLogger,
     reset_rx: mpsc::Receiver<()>,
     generation_tx: watch::Sender<u64>,
     generation: u64,
+    endpoint: IrcEndpoint,
+    /// Shares channels with other instances when set; see `irc::coordination`
+    leases: Option<LeaseConfig>,
 }
 
 /// Signals that can be used by any task to request or observe a reconnect
@@ -46,8 +68,14 @@ pub struct ConnectionHandle {
 }
 
 impl ConnectionSupervisor {
-    #[instrument]
-    pub fn new(channels: Vec<String>) -> (Self, ConnectionHandle) {
+    #[instrument(skip(membership, redis_pool, tap, chat_log))]
+    pub fn new(
+        membership: RestoredMembership,
+        joins: Arc<JoinScheduler>,
+        redis_pool: ConnectionManager,
+        tap: IrcTap,
+        chat_log: ChatLogger,
+    ) -> (Self, ConnectionHandle) {
         let (reset_tx, reset_rx) = mpsc::channel(4);
         let (generation_tx, generation_rx) = watch::channel(0u64);
 
@@ -57,20 +85,39 @@ impl ConnectionSupervisor {
         };
 
         let supervisor = Self {
-            channels,
+            channels: membership.channels,
+            previously_joined: membership.previously_joined,
+            joins,
+            redis_pool,
+            tap,
+            chat_log,
             reset_rx,
             generation_tx,
             generation: 0,
+            endpoint: IrcEndpoint::default(),
+            leases: None,
         };
 
         (supervisor, handle)
     }
 
+    /// Connects somewhere other than Twitch, e.g. a `MockTwitchServer` in tests.
+    pub fn with_endpoint(mut self, endpoint: IrcEndpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Only joins the channels this instance holds a lease for.
+    pub fn with_leases(mut self, leases: Option<LeaseConfig>) -> Self {
+        self.leases = leases;
+        self
+    }
+
     /// Main event loop, where each iteration reflects one full connection lifecycle.
     pub async fn run(
         &mut self,
-        msg_tx: async_channel::Sender<IncomingMessage>,
-        mut cmd_rx: mpsc::Receiver<OutgoingCommand>,
+        msg_tx: QueueSender<IncomingMessage>,
+        cmd_rx: QueueReceiver<OutgoingCommand>,
         mut query_rx: mpsc::Receiver<IrcQuery>,
     ) {
         loop {
@@ -78,7 +125,7 @@ impl ConnectionSupervisor {
             _ = self.generation_tx.send(self.generation);
 
             match self
-                .run_single_connection(&msg_tx, &mut cmd_rx, &mut query_rx)
+                .run_single_connection(&msg_tx, &cmd_rx, &mut query_rx)
                 .await
             {
                 Ok(reason) => {
@@ -88,6 +135,10 @@ impl ConnectionSupervisor {
                         gen = self.generation,
                         "connection ended"
                     );
+
+                    if matches!(reason, DisconnectReason::KeepaliveTimeout) {
+                        availability().record_failure(Service::Irc);
+                    }
                 }
                 Err(e) => {
                     tracing::error!(
@@ -96,40 +147,57 @@ impl ConnectionSupervisor {
                         gen = self.generation,
                         "connection error"
                     );
+
+                    availability().record_failure(Service::Irc);
                 }
             }
 
-            // momentary wait prior to attempting reconnection
-            tokio::time::sleep(Duration::from_secs(3)).await;
+            // stop reconnecting once the failure cap is hit and let the availability probe tell
+            // us when twitch is reachable again
+            if availability().is_down(Service::Irc) {
+                tracing::warn!(gen = self.generation, "irc unreachable - pausing reconnects");
+                availability().wait_until_up(Service::Irc).await;
+            }
+
+            tokio::time::sleep(reconnect_delay()).await;
         }
     }
 
     async fn run_single_connection(
         &mut self,
-        msg_tx: &async_channel::Sender<IncomingMessage>,
-        cmd_rx: &mut mpsc::Receiver<OutgoingCommand>,
+        msg_tx: &QueueSender<IncomingMessage>,
+        cmd_rx: &QueueReceiver<OutgoingCommand>,
         query_rx: &mut mpsc::Receiver<IrcQuery>,
     ) -> Result<DisconnectReason, ConnectionClientError> {
-        let mut ping_interval = tokio::time::interval(Duration::from_secs(KEEPALIVE_INTERVAL));
-        let ack_deadline = Duration::from_secs(15);
+        let mut ping_interval = tokio::time::interval(PING_INTERVAL);
+        let (mut keepalive, keepalive_rx) = Keepalive::new();
 
         let (event_tx, event_rx) = mpsc::channel(64);
         let (action_tx, mut action_rx) = mpsc::channel(16);
 
         let channel_mgr = ChannelManager::new(
             self.channels.clone(),
+            self.previously_joined.clone(),
+            Arc::clone(&self.joins),
+            self.redis_pool.clone(),
             COUNTER_USER.to_string(),
             event_rx,
             action_tx,
-        );
+        )
+        .with_coordinator(
+            self.leases
+                .clone()
+                .map(|config| Coordinator::new(config, self.redis_pool.clone())),
+        )
+        .with_keepalive(keepalive_rx);
 
         let mgr_handle = tokio::spawn(channel_mgr.run());
-        let mut client = ConnectionClient::init(&self.channels).await?;
+        let mut client = ConnectionClient::init(&self.channels, &self.endpoint).await?;
 
         client.connect().await?;
+        availability().record_success(Service::Irc);
 
         let mut stream = client.inner.stream()?;
-        let mut last_ack = Instant::now();
 
         loop {
             tokio::select! {
@@ -141,10 +209,9 @@ impl ConnectionSupervisor {
                         if is_pong(&msg) {
                             tracing::info!(
                                 command = ?msg.command,
-                                time_since_last_ack = ?last_ack.elapsed(),
+                                latency = ?keepalive.pong(Instant::now()),
                                 "keepalive_acknowledged"
                             );
-                            last_ack = Instant::now();
                         }
 
                         // If we aren't handling a PONG, handle JOIN/PART commands for our user,
@@ -153,21 +220,36 @@ impl ConnectionSupervisor {
                         match &msg.command {
                             irc::proto::Command::JOIN(channel, _, _) => {
                                 // handle JOIN
+                                self.tap.publish(&msg, None);
                                 if is_counter_user(&msg, COUNTER_USER) {
+                                    if !client.joined.contains(channel) {
+                                        client.joined.push(channel.clone());
+                                    }
+
                                     _ = event_tx.try_send(ChannelEvent::Joined(channel.clone()));
                                 }
                             }
 
                             irc::proto::Command::PART(channel, _) => {
                                 // handle PART
+                                self.tap.publish(&msg, None);
                                 if is_counter_user(&msg, COUNTER_USER) {
+                                    client.joined.retain(|ch| ch != channel);
+                                    room_states().forget(channel);
                                     _ = event_tx.try_send(ChannelEvent::Parted(channel.clone()));
                                 }
                             }
 
                             _ => {
                                 // offload to worker
-                                if let Some(parsed) = parse_incoming(&msg) {
+                                room_states().observe(&msg);
+                                let parsed = parse_incoming(&msg);
+                                self.tap.publish(&msg, parsed.as_ref());
+                                self.chat_log.record(&msg);
+
+                                if let Some(parsed) = parsed
+                                    && !delivered_over_eventsub(&parsed)
+                                {
                                     _ = msg_tx.send(parsed).await;
                                 }
                             }
@@ -175,11 +257,12 @@ impl ConnectionSupervisor {
 
                     }
 
-                // Send a PING if we haven't seen one recently to make sure we are still connected
+                // Send a PING periodically to make sure we are still connected, and to measure
+                // the connection's latency
                 _ = ping_interval.tick() => {
-                    if last_ack.elapsed() > ack_deadline + Duration::from_secs(KEEPALIVE_INTERVAL) {
+                    if !keepalive.ping(Instant::now()) {
                         tracing::warn!(
-                            time_since_last_ack = ?last_ack.elapsed(),
+                            stats = ?keepalive.stats(),
                             "keepalive_timeout",
                         );
 
@@ -199,12 +282,50 @@ impl ConnectionSupervisor {
                             }
                         }
 
+                        IrcQuery::GetTrackedChannels { reply } => {
+                            if let Err(e) = reply.send(client.channels.clone()) {
+                                tracing::error!(data = ?e, "api_query_response_fail");
+                            }
+                        }
+
                         IrcQuery::InsertNewChannel { channel, reply } => {
                             tracing::info!("api_insert_new_channel");
-                            if let Err(e) = reply.send(client.insert_channel(&channel).await?) {
+
+                            // keep the channel across reconnects and restarts; the channel manager
+                            // queues the JOIN ahead of any other pending joins
+                            let channel = client.insert_channel(&channel);
+                            self.channels.push(channel.clone());
+                            _ = event_tx.send(ChannelEvent::Added(channel.clone())).await;
+
+                            if let Err(e) = reply.send(channel) {
                                 tracing::error!(data = ?e, "failed while inserting and joining new channel");
                             }
                         }
+
+                        // answered by the channel manager; dropping the reply if it has gone away
+                        // surfaces as a receive error for the caller
+                        IrcQuery::GetStats { reply } => {
+                            _ = event_tx.send(ChannelEvent::Stats(reply)).await;
+                        }
+
+                        IrcQuery::Resync { reply } => {
+                            _ = event_tx.send(ChannelEvent::Resync(reply)).await;
+                        }
+
+                        IrcQuery::RemoveChannel { channel, reply } => {
+                            tracing::info!("api_remove_channel");
+
+                            let channel = client.remove_channel(&channel);
+                            self.channels.retain(|ch| membership::normalize(ch) != channel);
+                            if client.joined.contains(&channel) {
+                                client.inner.send_part(&channel)?;
+                            }
+                            _ = event_tx.send(ChannelEvent::Removed(channel.clone())).await;
+
+                            if let Err(e) = reply.send(channel) {
+                                tracing::error!(data = ?e, "failed while removing channel");
+                            }
+                        }
                     }
                 }
 
@@ -217,14 +338,34 @@ impl ConnectionSupervisor {
                             tracing::info!(%join_str, "executing JOIN");
                             client.inner.send_join(&join_str)?;
                         }
+
+                        ChannelAction::Part(channels) => {
+                            let part_str = channels.join(",");
+
+                            tracing::info!(%part_str, "executing PART");
+                            client.inner.send_part(&part_str)?;
+                        }
                     }
                 }
 
                 // Worker command (external)
-                Some(command) = cmd_rx.recv() => {
+                Ok(command) = cmd_rx.recv() => {
                     match command {
                         OutgoingCommand::Reply { message } => {
-                            if let Err(e) = client.inner.send(message) {
+                            let suppressed = match &message.command {
+                                irc::proto::Command::PRIVMSG(channel, _)
+                                    if suppresses_replies(channel) => room_states()
+                                    .take_reply(channel, Instant::now())
+                                    .err()
+                                    .map(|mode| (channel, mode)),
+                                _ => None,
+                            };
+
+                            if let Some((channel, mode)) = suppressed {
+                                tracing::info!(channel, mode, "reply suppressed by chat mode");
+                                metrics::counter!("irc_replies_suppressed_total", "mode" => mode)
+                                    .increment(1);
+                            } else if let Err(e) = client.inner.send(message) {
                                 tracing::error!(error = ?e, "send failed");
                             }
                         }
@@ -243,6 +384,48 @@ impl ConnectionSupervisor {
     }
 }
 
+/// Backs off exponentially with consecutive failures, capped at `RECONNECT_MAX_DELAY` seconds
+fn reconnect_delay() -> Duration {
+    let failures = availability().failures_for(Service::Irc).min(8);
+    Duration::from_secs((RECONNECT_BASE_DELAY << failures).min(RECONNECT_MAX_DELAY))
+}
+
+/// Chat in channels with the `eventsub_chat` flag on is received from EventSub instead (see
+/// `api::webhook::chat`), so isn't handed to the workers a second time.
+fn delivered_over_eventsub(message: &IncomingMessage) -> bool {
+    matches!(
+        message,
+        IncomingMessage::Privmsg(msg) if flags().is_on_for(Flag::EventSubChat, &msg.channel_id)
+    )
+}
+
+/// Whether replies to a channel are held back by its chat modes, per the `reply_suppression`
+/// flag. Channels whose id isn't known yet follow the flag's global switch.
+fn suppresses_replies(channel: &str) -> bool {
+    match room_states().room_id(channel) {
+        Some(channel_id) => flags().is_on_for(Flag::ReplySuppression, &channel_id),
+        None => flags().get(Flag::ReplySuppression).enabled,
+    }
+}
+
+/// The IRC server a connection is made to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcEndpoint {
+    pub server: String,
+    pub port: u16,
+    pub use_tls: bool,
+}
+
+impl Default for IrcEndpoint {
+    fn default() -> Self {
+        Self {
+            server: TTV_IRC_URI.to_string(),
+            port: TTV_IRC_PORT,
+            use_tls: true,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ConnectionClient {
     pub inner: irc::client::Client,
@@ -252,23 +435,41 @@ pub struct ConnectionClient {
 }
 
 impl ConnectionClient {
+    /// Creates a client for the bot user, authenticating with the token from the environment.
     #[instrument]
-    pub async fn init(channels: &Vec<String>) -> ClientResult<Self> {
-        let channels: Vec<String> = channels.iter().map(|chan| format!("#{chan}")).collect();
+    pub async fn init(channels: &Vec<String>, endpoint: &IrcEndpoint) -> ClientResult<Self> {
+        let nickname = crate::var!(env::Var::UserLogin).await?.to_string();
+        let password = format!("oauth:{}", crate::var!(env::Var::UserToken).await?);
+
+        Self::init_as(channels, endpoint, nickname, password).await
+    }
+
+    /// Creates a client for an arbitrary user; `password` is sent as-is.
+    #[instrument(skip(password))]
+    pub async fn init_as(
+        channels: &Vec<String>,
+        endpoint: &IrcEndpoint,
+        nickname: String,
+        password: String,
+    ) -> ClientResult<Self> {
+        let channels: Vec<String> = channels
+            .iter()
+            .map(|chan| membership::normalize(chan))
+            .collect();
         tracing::trace!(?channels, "reformatted channel list");
 
         let config = data::Config {
-            use_tls: Some(true),
-            nickname: Some(crate::var!(env::Var::UserLogin).await?.to_string()),
-            password: Some(format!("oauth:{}", crate::var!(env::Var::UserToken).await?)),
+            use_tls: Some(endpoint.use_tls),
+            nickname: Some(nickname),
+            password: Some(password),
 
-            server: Some(TTV_IRC_URI.to_string()),
-            port: Some(TTV_IRC_PORT),
+            server: Some(endpoint.server.clone()),
+            port: Some(endpoint.port),
             ping_time: Some(280),
             ..data::Config::default()
         };
 
-        let connection = Client::from_config(config.clone()).await.unwrap();
+        let connection = Client::from_config(config).await?;
 
         Ok(Self {
             channels,
@@ -277,16 +478,25 @@ impl ConnectionClient {
         })
     }
 
+    /// Tracks a channel added at runtime; the JOIN itself is scheduled by the `ChannelManager`.
     #[instrument(skip(self))]
-    pub async fn insert_channel(&mut self, channel: &str) -> ClientResult<String> {
-        let channel = format!("#{channel}");
-        self.channels.push(channel.clone());
+    pub fn insert_channel(&mut self, channel: &str) -> String {
+        let channel = membership::normalize(channel);
+        if !self.channels.contains(&channel) {
+            self.channels.push(channel.clone());
+        }
 
-        tracing::info!("attempting to join new channel");
+        tracing::info!("queueing join for new channel");
+        channel
+    }
 
-        self.join_channel(&channel).await?;
+    /// Stops tracking a channel; the caller sends the PART.
+    #[instrument(skip(self))]
+    pub fn remove_channel(&mut self, channel: &str) -> String {
+        let channel = membership::normalize(channel);
+        self.channels.retain(|ch| ch != &channel);
 
-        Ok(channel)
+        channel
     }
 
     #[instrument(skip_all)]
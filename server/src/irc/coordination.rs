@@ -0,0 +1,312 @@
+//! Shares channels between server instances, so that each channel is joined (and its messages
+//! counted) by one instance at a time.
+//!
+//! Every instance heartbeats into `irc:instances` (or `irc:instances:{shard}`, when channels are
+//! sharded; see `util::shard`), and channels are assigned to the live instances
+//! by rendezvous hashing, so an instance starting or stopping only moves the channels it gains or
+//! loses. An instance only joins a channel while it holds the channel's lease (`irc:lease:#login`),
+//! which it renews for as long as the channel is assigned to it and releases otherwise. If an
+//! instance goes away, it drops out of `irc:instances` and its leases expire, and its channels are
+//! taken over by the instances they're reassigned to.
+//!
+//! `IRC_LEASE_TTL_SECS=0` disables coordination, in which case every channel is joined.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisResult, Script};
+use ring::digest::{SHA256, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use tracing::instrument;
+
+use crate::util::env::Var;
+use crate::util::shard::sharding;
+use crate::var;
+
+const INSTANCES_KEY: &str = "irc:instances";
+const LEASE_PREFIX: &str = "irc:lease:";
+const DEFAULT_LEASE_TTL_SECS: u64 = 30;
+
+/// Claims, or renews, each lease in `KEYS` for instance `ARGV[1]` for `ARGV[2]`ms, returning `1`
+/// for each lease the instance holds afterwards and `0` for each held by another instance.
+const CLAIM_SCRIPT: &str = r#"
+local held = {}
+for i, key in ipairs(KEYS) do
+    local owner = redis.call('GET', key)
+    if owner == false or owner == ARGV[1] then
+        redis.call('SET', key, ARGV[1], 'PX', ARGV[2])
+        held[i] = 1
+    else
+        held[i] = 0
+    end
+end
+return held
+"#;
+
+/// Releases each lease in `KEYS` held by instance `ARGV[1]`, returning how many were released.
+const RELEASE_SCRIPT: &str = r#"
+local released = 0
+for _, key in ipairs(KEYS) do
+    if redis.call('GET', key) == ARGV[1] then
+        redis.call('DEL', key)
+        released = released + 1
+    end
+end
+return released
+"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseConfig {
+    /// Identifies this instance in `irc:instances` and as the holder of its leases
+    pub instance: String,
+    pub ttl: Duration,
+    /// Where this instance heartbeats; instances only share channels with others in their shard
+    pub instances_key: String,
+}
+
+/// Reads `INSTANCE_ID` and `IRC_LEASE_TTL_SECS`, returning `None` if coordination is disabled.
+pub async fn lease_config() -> Option<LeaseConfig> {
+    let ttl = match var!(Var::IrcLeaseTtlSecs).await {
+        Ok(val) => match val.trim().parse::<u64>() {
+            Ok(0) => return None,
+            Ok(secs) => secs,
+            Err(_) => {
+                tracing::warn!(val, "invalid irc lease ttl - using default");
+                DEFAULT_LEASE_TTL_SECS
+            }
+        },
+        Err(_) => DEFAULT_LEASE_TTL_SECS,
+    };
+
+    // a restarted instance keeps its id, so it can pick its leases straight back up. without one,
+    // the id has to be unique to this process - a shared fallback (e.g. an unexported `HOSTNAME`)
+    // would have every instance believe it holds every lease
+    let instance = match var!(Var::InstanceId).await {
+        Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+        _ => {
+            let instance = random_instance_id();
+            tracing::warn!(
+                instance,
+                "INSTANCE_ID is unset - using a random instance id; leases held before a restart \
+                 are only picked back up once they expire"
+            );
+            instance
+        }
+    };
+
+    let sharding = sharding();
+    let instances_key = match sharding.is_sharded() {
+        true => format!("{INSTANCES_KEY}:{}", sharding.index),
+        false => INSTANCES_KEY.to_string(),
+    };
+
+    Some(LeaseConfig {
+        instance,
+        ttl: Duration::from_secs(ttl),
+        instances_key,
+    })
+}
+
+fn random_instance_id() -> String {
+    let mut bytes = [0u8; 8];
+    match SystemRandom::new().fill(&mut bytes) {
+        Ok(()) => format!("pea-fan-{}", hex::encode(bytes)),
+        // the pid and start time together are as good as unique
+        Err(_) => format!(
+            "pea-fan-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ),
+    }
+}
+
+/// The instances sharing channels, and which of them holds each channel's lease.
+#[derive(Debug, Clone, Serialize)]
+pub struct OwnershipStats {
+    pub instance: String,
+    /// Live instances, as of the last heartbeat
+    pub instances: Vec<String>,
+    /// The holder of each expected channel's lease, if any
+    pub owners: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Debug)]
+pub struct Coordinator {
+    config: LeaseConfig,
+    redis_pool: ConnectionManager,
+    claim: Script,
+    release: Script,
+    /// As of the last heartbeat
+    instances: Vec<String>,
+}
+
+impl Coordinator {
+    pub fn new(config: LeaseConfig, redis_pool: ConnectionManager) -> Self {
+        Self {
+            instances: vec![config.instance.clone()],
+            config,
+            redis_pool,
+            claim: Script::new(CLAIM_SCRIPT),
+            release: Script::new(RELEASE_SCRIPT),
+        }
+    }
+
+    /// How long a lease is held for without being renewed.
+    pub fn ttl(&self) -> Duration {
+        self.config.ttl
+    }
+
+    /// Leases are renewed well within their TTL, so a slow round trip doesn't let one lapse.
+    pub fn renew_every(&self) -> Duration {
+        self.config.ttl / 3
+    }
+
+    /// Heartbeats, then claims the leases of the channels assigned to this instance and releases
+    /// any others it holds, returning the channels it holds a lease for.
+    #[instrument(skip(self, channels), fields(instance = self.config.instance, channels = channels.len()))]
+    pub async fn sync(&mut self, channels: &HashSet<String>) -> RedisResult<HashSet<String>> {
+        self.instances = self.heartbeat().await?;
+
+        let (assigned, unassigned): (Vec<&String>, Vec<&String>) = channels
+            .iter()
+            .partition(|ch| assignee(ch, &self.instances) == Some(self.config.instance.as_str()));
+
+        self.release(&unassigned).await?;
+        if assigned.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        let mut invocation = self.claim.prepare_invoke();
+        for channel in &assigned {
+            invocation.key(lease_key(channel));
+        }
+        let held: Vec<u8> = invocation
+            .arg(&self.config.instance)
+            .arg(self.config.ttl.as_millis() as u64)
+            .invoke_async(&mut self.redis_pool)
+            .await?;
+
+        Ok(assigned
+            .into_iter()
+            .zip(held)
+            .filter(|(_, held)| *held == 1)
+            .map(|(channel, _)| channel.clone())
+            .collect())
+    }
+
+    /// Releases this instance's leases on `channels`, so they can be taken over straight away.
+    #[instrument(skip(self, channels), fields(channels = channels.len()))]
+    pub async fn release(&mut self, channels: &[&String]) -> RedisResult<()> {
+        if channels.is_empty() {
+            return Ok(());
+        }
+
+        let mut invocation = self.release.prepare_invoke();
+        for channel in channels {
+            invocation.key(lease_key(channel));
+        }
+        let released: u64 = invocation
+            .arg(&self.config.instance)
+            .invoke_async(&mut self.redis_pool)
+            .await?;
+
+        if released > 0 {
+            tracing::info!(released, "released channel leases");
+        }
+
+        Ok(())
+    }
+
+    pub async fn ownership(&mut self, channels: &HashSet<String>) -> RedisResult<OwnershipStats> {
+        let mut channels: Vec<&String> = channels.iter().collect();
+        channels.sort();
+
+        let holders: Vec<Option<String>> = if channels.is_empty() {
+            Vec::new()
+        } else {
+            let keys: Vec<String> = channels.iter().map(|ch| lease_key(ch)).collect();
+            self.redis_pool.mget(keys).await?
+        };
+
+        Ok(OwnershipStats {
+            instance: self.config.instance.clone(),
+            instances: self.instances.clone(),
+            owners: channels.into_iter().cloned().zip(holders).collect(),
+        })
+    }
+
+    /// Refreshes this instance's entry in `irc:instances` and returns every live instance.
+    async fn heartbeat(&mut self) -> RedisResult<Vec<String>> {
+        let now = chrono::Utc::now().timestamp_millis();
+        let expires = now + self.config.ttl.as_millis() as i64;
+        let key = &self.config.instances_key;
+
+        let (instances,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .zadd(key, &self.config.instance, expires)
+            .ignore()
+            .zrembyscore(key, "-inf", now)
+            .ignore()
+            .zrange(key, 0, -1)
+            .query_async(&mut self.redis_pool)
+            .await?;
+
+        Ok(instances)
+    }
+}
+
+/// The instance a channel is assigned to, by rendezvous hashing.
+pub fn assignee<'a>(channel: &str, instances: &'a [String]) -> Option<&'a str> {
+    instances
+        .iter()
+        .max_by_key(|instance| weight(channel, instance))
+        .map(String::as_str)
+}
+
+fn weight(channel: &str, instance: &str) -> [u8; 8] {
+    let hash = digest(&SHA256, format!("{channel}\0{instance}").as_bytes());
+    let mut weight = [0; 8];
+    weight.copy_from_slice(&hash.as_ref()[..8]);
+
+    weight
+}
+
+fn lease_key(channel: &str) -> String {
+    format!("{LEASE_PREFIX}{channel}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn random_instance_ids_differ() {
+        let (a, b) = (random_instance_id(), random_instance_id());
+        assert!(a.starts_with("pea-fan-"));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn channels_only_move_to_or_from_changed_instances() {
+        let channels: Vec<String> = (0..200).map(|i| format!("#channel{i}")).collect();
+        let two = ["a", "b"].map(String::from);
+        let three = ["a", "b", "c"].map(String::from);
+
+        let mut counts = BTreeMap::new();
+        for channel in &channels {
+            let before = assignee(channel, &two).unwrap();
+            let after = assignee(channel, &three).unwrap();
+
+            // a new instance only takes channels, and never moves them between the others
+            assert!(after == before || after == "c");
+            *counts.entry(after).or_insert(0) += 1;
+        }
+
+        // roughly even, with a very generous margin
+        assert_eq!(counts.len(), 3);
+        assert!(counts.values().all(|count| *count > 30));
+        assert_eq!(assignee("#channel", &[]), None);
+    }
+}
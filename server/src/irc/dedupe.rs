@@ -0,0 +1,88 @@
+//! Makes sure each chat message is counted at most once.
+//!
+//! A message can be delivered more than once - Twitch replays recent messages to a connection that
+//! rejoins a channel, and a message can arrive over both IRC and EventSub - and would otherwise be
+//! counted each time. Before a message's scores are recorded, its id is claimed in the
+//! `processed_message` table; a message whose id has already been claimed (by this instance or any
+//! other) is skipped. Claims are kept for `CLAIM_TTL`, well past the point a message could be
+//! redelivered, then purged.
+//!
+//! The scores aren't recorded in the claim's transaction (the store may not be Postgres), so a
+//! claim is released if the message's scores fail to record, leaving it to be counted when it's
+//! redelivered.
+
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+
+use crate::db::prelude::LeaderboardRepository;
+use crate::irc::message::ChatMessage;
+
+/// How long a counted message's id is remembered for.
+pub const CLAIM_TTL: Duration = Duration::from_secs(60 * 60 * 24);
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Claims a message for counting, returning `false` if it's already been counted.
+///
+/// Messages without an id can't be told apart, so they're always counted.
+pub async fn first_delivery(
+    pool: &'static Pool<Postgres>,
+    message: &ChatMessage,
+) -> sqlx::Result<bool> {
+    if message.message_id.is_empty() {
+        return Ok(true);
+    }
+
+    let claimed = LeaderboardRepository::new(pool)
+        .claim_message(&message.channel_id, &message.message_id)
+        .await?;
+
+    if !claimed {
+        tracing::info!(
+            channel = %message.channel_id,
+            msg_id = message.message_id,
+            "message already counted - skipping"
+        );
+        metrics::counter!("score_duplicate_messages_total").increment(1);
+    }
+
+    Ok(claimed)
+}
+
+/// Releases a message's claim, so that it's counted when it's next delivered.
+pub async fn release(pool: &'static Pool<Postgres>, message: &ChatMessage) {
+    if message.message_id.is_empty() {
+        return;
+    }
+
+    if let Err(e) = LeaderboardRepository::new(pool)
+        .release_message(&message.message_id)
+        .await
+    {
+        tracing::error!(
+            error = ?e,
+            channel = %message.channel_id,
+            msg_id = message.message_id,
+            "failed to release message claim - the message won't be counted if redelivered"
+        );
+    }
+}
+
+pub fn spawn_claim_purge(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match LeaderboardRepository::new(pool)
+                .purge_processed_messages(CLAIM_TTL.as_secs() as i64)
+                .await
+            {
+                Ok(0) => (),
+                Ok(purged) => tracing::debug!(purged, "purged processed message ids"),
+                Err(e) => tracing::error!(error = ?e, "failed to purge processed message ids"),
+            }
+        }
+    })
+}
@@ -0,0 +1,93 @@
+//! Announces compiled daily digests in chat, for channels that are live and have `digest_in_chat`
+//! set (see `util::digest`).
+
+use std::sync::Arc;
+
+use irc::proto::Message;
+use sqlx::PgPool;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+
+use crate::db::models::digest::Digest;
+use crate::db::redis::get_stream_state;
+use crate::db::redis::redis_pool::redis_pool;
+use crate::irc::commands::OutgoingCommand;
+use crate::irc::error::ClientResult;
+use crate::irc::events::{LiveEvent, events};
+use crate::irc::queue::QueueSender;
+use crate::irc::rate_limit::Bucket;
+use crate::irc::worker::channel_settings;
+use crate::util::availability::availability;
+use crate::util::digest::{CHAT_TEMPLATE, placeholders};
+use crate::util::template;
+
+/// Twitch drops chat messages longer than this
+const MAX_MESSAGE_CHARS: usize = 500;
+
+async fn announce(
+    pool: &'static PgPool,
+    cmd_tx: &QueueSender<OutgoingCommand>,
+    rate_limiter: &Bucket,
+    digest: &Digest,
+) -> ClientResult<()> {
+    if !channel_settings(pool, &digest.channel_id)
+        .await?
+        .announces_digests()
+        || availability().is_degraded()
+    {
+        return Ok(());
+    }
+
+    let mut conn = redis_pool().await?.clone();
+    if !get_stream_state(&mut conn, &digest.channel_id).await {
+        tracing::debug!(
+            channel = digest.channel_login,
+            "offline - not announcing digest"
+        );
+        return Ok(());
+    }
+
+    let message = Message {
+        tags: None,
+        prefix: None,
+        command: irc::proto::Command::PRIVMSG(
+            format!("#{}", digest.channel_login),
+            template::truncate_chars(
+                template::render(CHAT_TEMPLATE, &placeholders(digest)),
+                MAX_MESSAGE_CHARS,
+            ),
+        ),
+    };
+
+    rate_limiter.acquire_one().await?;
+    cmd_tx.send(OutgoingCommand::Reply { message }).await?;
+
+    Ok(())
+}
+
+/// Spawns a task announcing each compiled digest in its channel's chat.
+pub fn spawn_digest_announcements(
+    pool: &'static PgPool,
+    cmd_tx: QueueSender<OutgoingCommand>,
+    rate_limiter: Arc<Bucket>,
+) -> JoinHandle<()> {
+    let mut rx = events().subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(skipped, "digest announcements fell behind, skipped events");
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            if let LiveEvent::DigestCompiled(digest) = event.as_ref()
+                && let Err(e) = announce(pool, &cmd_tx, &rate_limiter, digest).await
+            {
+                tracing::error!(error = ?e, "failed to announce daily digest");
+            }
+        }
+    })
+}
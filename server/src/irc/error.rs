@@ -3,6 +3,7 @@ use thiserror::Error;
 use tokio::sync::{AcquireError, mpsc::error::SendError, oneshot::error::RecvError};
 
 use crate::db::redis::redis_pool::RedisErr;
+use crate::db::store::StoreError;
 use crate::irc::commands::{IrcQuery, OutgoingCommand};
 
 pub type ClientResult<T> = core::result::Result<T, ConnectionClientError>;
@@ -10,7 +11,7 @@ pub type ClientResult<T> = core::result::Result<T, ConnectionClientError>;
 #[derive(Debug, Error)]
 pub enum ConnectionClientError {
     #[error(transparent)]
-    MpscSendOutgoingCommand(#[from] SendError<OutgoingCommand>),
+    QueueSendOutgoingCommand(#[from] async_channel::SendError<OutgoingCommand>),
 
     #[error(transparent)]
     MpscSendIrcQuery(#[from] SendError<IrcQuery>),
@@ -44,4 +45,17 @@ pub enum ConnectionClientError {
 
     #[error(transparent)]
     HelixError(#[from] crate::util::helix::HelixErr),
+
+    #[error("score store unavailable")]
+    StoreUnavailable,
+}
+
+impl From<StoreError> for ConnectionClientError {
+    fn from(value: StoreError) -> Self {
+        match value {
+            StoreError::Sqlx(e) => Self::SqlxError(e),
+            StoreError::Redis(e) => Self::Redis(e),
+            StoreError::Unavailable => Self::StoreUnavailable,
+        }
+    }
 }
@@ -0,0 +1,127 @@
+//! Broadcasts notable events to anything listening for them (e.g. a live feed or an integration),
+//! without the code producing them knowing who that is.
+//!
+//! Events are published from counting (milestones), the stream status refresh (channels going
+//! live), leaderboard captures (finalised period snapshots), EventSub (raids) and the daily digest
+//! job (compiled digests).
+//!
+//! Events are dropped while nothing is subscribed. Subscribers that fall more than
+//! `EVENT_CAPACITY` events behind miss the oldest of them, as with any `broadcast` channel.
+//!
+//! Every counted score is broadcast separately by the `IncrementBus`, so that the volume of scores
+//! can't push rarer events out of a slow subscriber's buffer.
+
+use std::sync::{Arc, LazyLock};
+
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::db::models::digest::Digest;
+use crate::db::models::leaderboard::SnapshotFinalised;
+use crate::db::models::milestone::MilestoneReached;
+use crate::db::models::raid::RaidEvent;
+use crate::db::models::stream::StreamOnline;
+use crate::db::prelude::{ChannelId, ChatterId, KeywordId};
+use crate::db::store::ScoreIncrement;
+
+const EVENT_CAPACITY: usize = 64;
+const INCREMENT_CAPACITY: usize = 1024;
+
+static EVENTS: LazyLock<EventBus> = LazyLock::new(EventBus::new);
+static INCREMENTS: LazyLock<IncrementBus> = LazyLock::new(IncrementBus::new);
+
+/// Retrieves a reference to the global `EventBus`.
+pub fn events() -> &'static EventBus {
+    &EVENTS
+}
+
+/// Retrieves a reference to the global `IncrementBus`.
+pub fn increments() -> &'static IncrementBus {
+    &INCREMENTS
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum LiveEvent {
+    MilestoneReached(MilestoneReached),
+    StreamOnline(StreamOnline),
+    SnapshotFinalised(SnapshotFinalised),
+    Raid(RaidEvent),
+    DigestCompiled(Digest),
+}
+
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<Arc<LiveEvent>>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn publish(&self, event: LiveEvent) {
+        _ = self.tx.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.tx.subscribe()
+    }
+}
+
+/// A score, as it was counted.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreCounted {
+    pub chatter_id: ChatterId,
+    pub chatter_login: String,
+    pub channel_id: ChannelId,
+    pub channel_login: String,
+    pub keyword_id: KeywordId,
+    pub counted_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncrementBus {
+    tx: broadcast::Sender<Arc<ScoreCounted>>,
+}
+
+impl Default for IncrementBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IncrementBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(INCREMENT_CAPACITY);
+        Self { tx }
+    }
+
+    /// Scores are only copied out while something is subscribed.
+    pub fn publish(&self, score: &ScoreIncrement<'_>) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+
+        _ = self.tx.send(Arc::new(ScoreCounted {
+            chatter_id: score.chatter_id.clone(),
+            chatter_login: score.chatter_login.to_string(),
+            channel_id: score.channel_id.clone(),
+            channel_login: score.channel_login.to_string(),
+            keyword_id: *score.keyword_id,
+            counted_at: Utc::now().naive_utc(),
+        }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<ScoreCounted>> {
+        self.tx.subscribe()
+    }
+}
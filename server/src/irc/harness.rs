@@ -0,0 +1,394 @@
+//! Runs the counting pipeline without Postgres, Redis or Twitch, for tests.
+//!
+//! `Pipeline` takes a message through the same steps as `KeywordHandler` - the global and channel
+//! blacklists, keyword matching, the channel's count mode and muted keywords, the score limits,
+//! then the increment and its milestones - but reads the channel's configuration from its own
+//! fields and records scores in a `MemoryStore`. Time is read from a `TestClock`, which only moves
+//! when it's advanced, so rate limits and cooldowns can be stepped through exactly.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use crate::db::models::channel::ChannelCountConfig;
+use crate::db::models::keyword::{Keyword, KeywordKind, KeywordMatcher};
+use crate::db::models::leaderboard::SuppressReason;
+use crate::db::models::milestone::{Milestone, MilestoneKind, MilestoneReached};
+use crate::db::models::settings::ChannelSettings;
+use crate::db::prelude::{ChannelId, ChatterId, KeywordId};
+use crate::db::store::{MemoryStore, ScoreIncrement, ScoreStore};
+use crate::irc::commands::{Emote, IncomingMessage};
+use crate::irc::message::ChatMessage;
+use crate::irc::milestone;
+use crate::irc::mock::fixtures::user_id;
+use crate::irc::permission::PermissionLevel;
+use crate::irc::score_limit::{ScoreLimiter, ScorePolicy};
+use crate::irc::worker::{CommandCooldowns, countable_message, unmuted_increments};
+
+/// A clock that starts whenever it's created and only moves when it's advanced.
+#[derive(Debug)]
+pub struct TestClock {
+    start: std::time::Instant,
+    start_tokio: tokio::time::Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+            start_tokio: tokio::time::Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+}
+
+impl TestClock {
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+
+    /// For the score limits, which use tokio's `Instant`
+    pub fn now(&self) -> tokio::time::Instant {
+        self.start_tokio + self.elapsed()
+    }
+
+    /// For command cooldowns, which use std's `Instant`
+    pub fn now_std(&self) -> std::time::Instant {
+        self.start + self.elapsed()
+    }
+}
+
+/// Builds a chat message from a chatter in a channel. Ids are derived from the logins (see
+/// `fixtures::user_id`), so the same login always has the same id.
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    message: ChatMessage,
+}
+
+pub fn chat(channel: &str, login: &str, text: &str) -> MessageBuilder {
+    let channel = channel.trim_start_matches('#').to_lowercase();
+    let login = login.to_lowercase();
+
+    MessageBuilder {
+        message: ChatMessage {
+            channel_id: ChannelId(user_id(&channel)),
+            channel_login: channel,
+            source_channel_id: None,
+            user_id: ChatterId(user_id(&login)),
+            display_name: login.clone(),
+            user_login: login,
+            color: String::new(),
+            text: text.to_string(),
+            emotes: Vec::new(),
+            message_id: String::new(),
+            sent_at: NaiveDateTime::default(),
+            badges: Vec::new(),
+            permission: PermissionLevel::Everyone,
+        },
+    }
+}
+
+impl MessageBuilder {
+    pub fn id(mut self, message_id: &str) -> Self {
+        self.message.message_id = message_id.to_string();
+        self
+    }
+
+    pub fn from_id(mut self, chatter_id: &str) -> Self {
+        self.message.user_id = ChatterId(chatter_id.to_string());
+        self
+    }
+
+    /// Adds an emote with the given id, spanning `start..=end` chars of the text.
+    pub fn emote(mut self, id: &str, start: usize, end: usize) -> Self {
+        self.message.emotes.push(Emote {
+            id: id.to_string(),
+            start,
+            end,
+        });
+        self
+    }
+
+    /// Marks the message as relayed from another channel in a shared chat session.
+    pub fn shared_from(mut self, channel: &str) -> Self {
+        self.message.source_channel_id = Some(ChannelId(user_id(channel)));
+        self
+    }
+
+    pub fn permission(mut self, permission: PermissionLevel) -> Self {
+        self.message.permission = permission;
+        self
+    }
+
+    pub fn build(self) -> ChatMessage {
+        self.message
+    }
+}
+
+pub fn keyword(id: i32, word: &str) -> Keyword {
+    Keyword {
+        id: KeywordId(id),
+        word: word.to_lowercase(),
+        kind: KeywordKind::Text,
+        emote_id: None,
+        created_at: NaiveDateTime::default(),
+    }
+}
+
+pub fn emote_keyword(id: i32, name: &str, emote_id: &str) -> Keyword {
+    Keyword {
+        kind: KeywordKind::Emote,
+        emote_id: Some(emote_id.to_string()),
+        ..keyword(id, name)
+    }
+}
+
+pub fn milestone(id: i32, kind: MilestoneKind, threshold: i64, repeating: bool) -> Milestone {
+    Milestone {
+        id,
+        channel_id: None,
+        kind,
+        threshold,
+        repeating,
+        created_at: NaiveDateTime::default(),
+    }
+}
+
+/// What happened to a message.
+#[derive(Debug, Default)]
+pub struct Counted {
+    pub counted: Vec<KeywordId>,
+    pub suppressed: Vec<(KeywordId, SuppressReason)>,
+    pub reached: Vec<MilestoneReached>,
+}
+
+/// The counting pipeline for a single channel configuration.
+#[derive(Debug)]
+pub struct Pipeline {
+    pub matcher: KeywordMatcher,
+    pub count_config: ChannelCountConfig,
+    pub settings: ChannelSettings,
+    pub paused: bool,
+    pub milestones: Vec<Milestone>,
+    pub store: MemoryStore,
+    pub clock: TestClock,
+    limiter: ScoreLimiter,
+    cooldowns: CommandCooldowns,
+}
+
+impl Pipeline {
+    /// Counts `keywords` with the default settings and no score limits.
+    pub fn new(keywords: &[Keyword]) -> Self {
+        Self {
+            matcher: KeywordMatcher::new(keywords),
+            count_config: ChannelCountConfig::default(),
+            settings: ChannelSettings::default(),
+            paused: false,
+            milestones: Vec::new(),
+            store: MemoryStore::new(),
+            clock: TestClock::default(),
+            limiter: ScoreLimiter::new(ScorePolicy::default()),
+            cooldowns: CommandCooldowns::default(),
+        }
+    }
+
+    /// Needs a tokio runtime when `policy` has a rate limit, as the limiter sweeps its windows in
+    /// the background.
+    pub fn with_policy(mut self, policy: ScorePolicy) -> Self {
+        self.limiter = ScoreLimiter::new(policy);
+        self
+    }
+
+    /// Counts a chat message as `KeywordHandler` would, as of the clock's current time.
+    pub async fn count(&self, message: ChatMessage) -> Counted {
+        let event = IncomingMessage::Privmsg(message);
+        let Some(message) = countable_message(&event) else {
+            return Counted::default();
+        };
+
+        if self.paused || self.settings.is_blacklisted(&message.user_id) {
+            return Counted::default();
+        }
+
+        let occurrences = self.matcher.occurrences(&message.text, &message.emotes);
+        let matched = unmuted_increments(
+            &self.count_config,
+            &self.settings.muted_keywords,
+            &occurrences,
+        );
+        let admission = self.limiter.admit_at(
+            self.clock.now(),
+            &message.channel_id,
+            &message.user_id,
+            matched,
+        );
+
+        let mut reached = Vec::new();
+        for keyword_id in &admission.counted {
+            let score = ScoreIncrement {
+                chatter_id: &message.user_id,
+                chatter_login: &message.user_login,
+                channel_id: &message.channel_id,
+                channel_login: &message.channel_login,
+                keyword_id,
+                msg_id: &message.message_id,
+            };
+
+            let totals = self.store.increment(&score, true).await.unwrap();
+            reached.extend(milestone::reached(
+                &self.milestones,
+                totals.expect("totals were asked for"),
+                &message,
+            ));
+        }
+
+        Counted {
+            counted: admission.counted,
+            suppressed: admission.suppressed,
+            reached,
+        }
+    }
+
+    /// Whether the chatter can use a command now, given the channel's command cooldown.
+    pub fn command(&self, message: &ChatMessage) -> bool {
+        let cooldown = Duration::from_secs(self.settings.command_cooldown_secs.max(0) as u64);
+        self.cooldowns.take(message, cooldown, self.clock.now_std())
+    }
+
+    /// The chatter's score in the channel.
+    pub fn score(&self, channel: &str, login: &str) -> i64 {
+        self.store
+            .score(&ChannelId(user_id(channel)), &ChatterId(user_id(login)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::models::channel::CountMode;
+    use crate::db::store::RankTarget;
+    use crate::irc::score_limit::RATE_LIMIT_WINDOW;
+    use crate::irc::worker::ID_BLACKLIST;
+
+    #[tokio::test]
+    async fn blacklisted_and_muted_messages_are_not_counted() {
+        let mut pipeline = Pipeline::new(&[keyword(1, "piss"), emote_keyword(2, "peepiss", "300")]);
+        pipeline.settings.muted_keywords = vec![KeywordId(1)];
+        pipeline.settings.blacklist = vec![ChatterId(user_id("blocked"))];
+
+        let counted = pipeline
+            .count(
+                chat("chan", "chatter", "piss PeePiss")
+                    .emote("300", 5, 11)
+                    .build(),
+            )
+            .await;
+        assert_eq!(counted.counted, vec![KeywordId(2)]);
+
+        let blocked = chat("chan", "blocked", "PeePiss")
+            .emote("300", 0, 6)
+            .build();
+        assert!(pipeline.count(blocked).await.counted.is_empty());
+
+        let bot = chat("chan", "bot", "PeePiss")
+            .emote("300", 0, 6)
+            .from_id(ID_BLACKLIST[0])
+            .build();
+        assert!(pipeline.count(bot).await.counted.is_empty());
+
+        pipeline.paused = true;
+        let paused = chat("chan", "chatter", "PeePiss")
+            .emote("300", 0, 6)
+            .build();
+        assert!(pipeline.count(paused).await.counted.is_empty());
+
+        assert_eq!(pipeline.score("chan", "chatter"), 1);
+        assert_eq!(
+            pipeline
+                .store
+                .get_rank(RankTarget::Chatter(&ChatterId(user_id("blocked"))))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn occurrences_are_capped_by_the_rate_limit_until_the_window_passes() {
+        let mut pipeline = Pipeline::new(&[keyword(1, "piss")]).with_policy(ScorePolicy {
+            max_per_minute: Some(3),
+            one_per_message: false,
+        });
+        pipeline.count_config.count_mode = CountMode::Occurrence;
+
+        let counted = pipeline
+            .count(chat("chan", "chatter", "piss piss").build())
+            .await;
+        assert_eq!(counted.counted.len(), 2);
+
+        pipeline.clock.advance(RATE_LIMIT_WINDOW / 2);
+        let counted = pipeline
+            .count(chat("chan", "chatter", "piss piss").build())
+            .await;
+        assert_eq!(counted.counted.len(), 1);
+        assert_eq!(
+            counted.suppressed,
+            vec![(KeywordId(1), SuppressReason::RateLimit)]
+        );
+
+        // the first message's increments have left the window, but not the second's
+        pipeline.clock.advance(RATE_LIMIT_WINDOW / 2);
+        let counted = pipeline
+            .count(chat("chan", "chatter", "piss piss piss").build())
+            .await;
+        assert_eq!(counted.counted.len(), 2);
+        assert_eq!(pipeline.score("chan", "chatter"), 5);
+    }
+
+    #[tokio::test]
+    async fn milestones_are_reached_by_the_increment_that_crosses_them() {
+        let mut pipeline = Pipeline::new(&[keyword(1, "piss")]);
+        pipeline.count_config.count_mode = CountMode::Occurrence;
+        pipeline.milestones = vec![
+            milestone(1, MilestoneKind::ChannelTotal, 3, true),
+            milestone(2, MilestoneKind::ChatterScore, 2, false),
+        ];
+
+        let first = pipeline.count(chat("chan", "a", "piss piss").build()).await;
+        let reached: Vec<_> = first
+            .reached
+            .iter()
+            .map(|r| (r.milestone_id, r.value))
+            .collect();
+        assert_eq!(reached, vec![(2, 2)]);
+
+        let second = pipeline
+            .count(chat("chan", "b", "piss piss piss piss").build())
+            .await;
+        let reached: Vec<_> = second
+            .reached
+            .iter()
+            .map(|r| (r.milestone_id, r.value))
+            .collect();
+        assert_eq!(reached, vec![(1, 3), (2, 2), (1, 6)]);
+    }
+
+    #[test]
+    fn commands_wait_out_the_channel_cooldown() {
+        let mut pipeline = Pipeline::new(&[]);
+        pipeline.settings.command_cooldown_secs = 30;
+        let message = chat("chan", "chatter", "!pisscount").build();
+
+        assert!(pipeline.command(&message));
+        pipeline.clock.advance(Duration::from_secs(29));
+        assert!(!pipeline.command(&message));
+        pipeline.clock.advance(Duration::from_secs(1));
+        assert!(pipeline.command(&message));
+    }
+}
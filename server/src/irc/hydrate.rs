@@ -0,0 +1,204 @@
+//! Background Helix hydration for chatters seen over IRC.
+//!
+//! Unknown chatters get a stub row (built from their chat message) as soon as they're seen, so their
+//! score can be recorded straight away. Their ids are then queued and resolved against Helix in
+//! batches of up to `MAX_BATCH_SIZE`, and the stub rows updated once the data arrives.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::prelude::{Chatter, ChatterId, ChatterRepository, Repository};
+use crate::irc::message::ChatMessage;
+use crate::util::alias;
+use crate::util::helix::Helix;
+
+/// Helix accepts up to 100 ids per `users` request
+const MAX_BATCH_SIZE: usize = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+const QUEUE_CAPACITY: usize = 1024;
+const STUB_COLOR: &str = "#000000";
+
+#[derive(Debug, Clone)]
+pub struct HydrationQueue {
+    tx: mpsc::Sender<ChatterId>,
+    /// Ids that are queued or currently being fetched
+    pending: Arc<Mutex<HashSet<ChatterId>>>,
+}
+
+impl HydrationQueue {
+    pub fn spawn(pool: &'static PgPool) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let queue = Self {
+            tx,
+            pending: Arc::new(Mutex::new(HashSet::new())),
+        };
+
+        let handle = tokio::spawn({
+            let pending = Arc::clone(&queue.pending);
+            async move { run(rx, pending, pool).await }
+        });
+
+        (queue, handle)
+    }
+
+    /// Queues a chatter to be refreshed from Helix.
+    ///
+    /// Never blocks the caller - if the queue is full the id is dropped, and will be queued again
+    /// the next time the chatter is seen with stale data.
+    pub fn enqueue(&self, id: ChatterId) {
+        if !self.pending.lock().unwrap().insert(id.clone()) {
+            return;
+        }
+
+        if let Err(e) = self.tx.try_send(id) {
+            let id = match e {
+                TrySendError::Full(id) => {
+                    tracing::warn!(chatter_id = %id, "hydration queue full - dropping chatter");
+                    id
+                }
+                TrySendError::Closed(id) => {
+                    tracing::error!(chatter_id = %id, "hydration queue closed");
+                    id
+                }
+            };
+
+            self.pending.lock().unwrap().remove(&id);
+        }
+    }
+}
+
+/// Builds a placeholder chatter from a message they sent. The login is the one Twitch sent with
+/// the message (see `parse::parse_tags`), and everything else is replaced once it's hydrated.
+///
+/// `updated_at` is set to the epoch so the row remains stale (and is requeued when the chatter is
+/// next seen) until it has been hydrated.
+pub fn stub_chatter(message: &ChatMessage) -> Chatter {
+    let color = if message.color.is_empty() {
+        String::from(STUB_COLOR)
+    } else {
+        message.color.clone()
+    };
+
+    let name = if message.display_name.is_empty() {
+        message.user_login.clone()
+    } else {
+        message.display_name.clone()
+    };
+
+    Chatter {
+        id: message.user_id.clone(),
+        login: message.user_login.to_lowercase(),
+        name,
+        color,
+        image: String::new(),
+        total: 0,
+        private: false,
+        created_at: Utc::now().naive_utc(),
+        updated_at: NaiveDateTime::default(),
+    }
+}
+
+async fn run(
+    mut rx: mpsc::Receiver<ChatterId>,
+    pending: Arc<Mutex<HashSet<ChatterId>>>,
+    pool: &'static PgPool,
+) {
+    tracing::info!("chatter hydration queue started");
+    while let Some(batch) = next_batch(&mut rx).await {
+        if let Err(e) = hydrate(pool, &batch).await {
+            tracing::error!(error = ?e, batch_size = batch.len(), "chatter hydration failure");
+        }
+
+        let mut pending = pending.lock().unwrap();
+        batch.iter().for_each(|id| {
+            pending.remove(id);
+        });
+    }
+}
+
+/// Waits for a queued id, then collects any further ids until either the batch is full or
+/// `FLUSH_INTERVAL` has elapsed.
+///
+/// Returns `None` once the queue has been closed and drained.
+async fn next_batch(rx: &mut mpsc::Receiver<ChatterId>) -> Option<Vec<ChatterId>> {
+    let mut batch = vec![rx.recv().await?];
+    let deadline = tokio::time::sleep(FLUSH_INTERVAL);
+    tokio::pin!(deadline);
+
+    while batch.len() < MAX_BATCH_SIZE {
+        tokio::select! {
+            id = rx.recv() => match id {
+                Some(id) => batch.push(id),
+                None => break,
+            },
+            _ = &mut deadline => break,
+        }
+    }
+
+    Some(batch)
+}
+
+#[instrument(skip(pool, batch), fields(batch_size = batch.len()))]
+async fn hydrate(pool: &'static PgPool, batch: &[ChatterId]) -> Result<(), HydrationError> {
+    let mut ids: Vec<String> = batch.iter().map(|id| id.0.clone()).collect();
+    let chatters: Vec<Chatter> = Helix::fetch_users_by_id(&mut ids)
+        .await?
+        .into_iter()
+        .map(Chatter::from)
+        .collect();
+
+    if chatters.len() < batch.len() {
+        // deleted or suspended accounts aren't returned by helix; their stubs are kept as-is
+        tracing::debug!(
+            requested = batch.len(),
+            retrieved = chatters.len(),
+            "some chatters could not be hydrated"
+        );
+    }
+
+    if chatters.is_empty() {
+        return Ok(());
+    }
+
+    ChatterRepository::new(pool).insert_many(&chatters).await?;
+    alias::spawn_merge_for(pool, chatters.into_iter().map(|ch| ch.id).collect());
+
+    tracing::debug!("hydrated chatter batch");
+    Ok(())
+}
+
+#[derive(Debug, thiserror::Error)]
+enum HydrationError {
+    #[error(transparent)]
+    Helix(#[from] crate::util::helix::HelixErr),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_are_capped_and_flushed() {
+        let (tx, mut rx) = mpsc::channel(QUEUE_CAPACITY);
+        for id in 0..150 {
+            tx.send(ChatterId(id.to_string())).await.unwrap();
+        }
+
+        assert_eq!(next_batch(&mut rx).await.unwrap().len(), MAX_BATCH_SIZE);
+        assert_eq!(next_batch(&mut rx).await.unwrap().len(), 50);
+
+        drop(tx);
+        assert!(next_batch(&mut rx).await.is_none());
+    }
+}
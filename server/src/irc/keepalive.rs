@@ -0,0 +1,175 @@
+//! Active keepalive probing for an IRC connection.
+//!
+//! The connection sends a PING every `PING_INTERVAL` and times the PONG that answers it. A PING
+//! still unanswered when the next one is due counts as missed; the connection is reported as
+//! unhealthy after a single miss, and dropped (so that the supervisor reconnects) after
+//! `MAX_MISSED` in a row. Round-trip latencies of the most recent PONGs are summarised in
+//! `ConnectionStats`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::watch;
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Consecutive unanswered PINGs before the connection is dropped.
+pub const MAX_MISSED: u32 = 3;
+
+/// How many of the most recent round trips the latency percentiles are taken over.
+const LATENCY_SAMPLES: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct KeepaliveStats {
+    /// `false` while the most recent PING has gone unanswered
+    pub healthy: bool,
+    /// Unanswered PINGs in a row
+    pub missed: u32,
+    /// Unanswered PINGs over the connection's lifetime
+    pub total_missed: u64,
+    pub last_latency_ms: Option<f64>,
+    pub p50_latency_ms: Option<f64>,
+    pub p99_latency_ms: Option<f64>,
+    /// Round trips the percentiles are taken over
+    pub samples: usize,
+}
+
+#[derive(Debug)]
+pub struct Keepalive {
+    /// When the unanswered PING was sent
+    outstanding: Option<Instant>,
+    missed: u32,
+    total_missed: u64,
+    latencies: VecDeque<Duration>,
+    stats_tx: watch::Sender<KeepaliveStats>,
+}
+
+impl Keepalive {
+    /// Returns the tracker, and a receiver that sees its stats after every PING and PONG.
+    pub fn new() -> (Self, watch::Receiver<KeepaliveStats>) {
+        let (stats_tx, stats_rx) = watch::channel(KeepaliveStats {
+            healthy: true,
+            ..Default::default()
+        });
+
+        let keepalive = Self {
+            outstanding: None,
+            missed: 0,
+            total_missed: 0,
+            latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
+            stats_tx,
+        };
+
+        (keepalive, stats_rx)
+    }
+
+    /// Records a PING sent at `now`, first counting the previous PING as missed if it was never
+    /// answered. Returns `false` once `MAX_MISSED` PINGs in a row have gone unanswered, in which
+    /// case the connection should be dropped rather than pinged again.
+    pub fn ping(&mut self, now: Instant) -> bool {
+        if self.outstanding.replace(now).is_some() {
+            self.missed += 1;
+            self.total_missed += 1;
+        }
+
+        self.publish();
+        self.missed < MAX_MISSED
+    }
+
+    /// Records a PONG received at `now`, returning the round trip if it answers a PING.
+    pub fn pong(&mut self, now: Instant) -> Option<Duration> {
+        let latency = now.saturating_duration_since(self.outstanding.take()?);
+        self.missed = 0;
+
+        if self.latencies.len() == LATENCY_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+
+        self.publish();
+        Some(latency)
+    }
+
+    pub fn stats(&self) -> KeepaliveStats {
+        let mut sorted = self.latencies.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+
+        KeepaliveStats {
+            healthy: self.missed == 0,
+            missed: self.missed,
+            total_missed: self.total_missed,
+            last_latency_ms: self.latencies.back().map(as_ms),
+            p50_latency_ms: percentile(&sorted, 50).map(as_ms),
+            p99_latency_ms: percentile(&sorted, 99).map(as_ms),
+            samples: sorted.len(),
+        }
+    }
+
+    fn publish(&self) {
+        self.stats_tx.send_replace(self.stats());
+    }
+}
+
+fn as_ms(duration: &Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Nearest-rank percentile of an ascending list.
+fn percentile(sorted: &[Duration], pct: usize) -> Option<&Duration> {
+    let rank = (sorted.len() * pct).div_ceil(100);
+    sorted.get(rank.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn round_trips_are_summarised() {
+        let (mut keepalive, stats_rx) = Keepalive::new();
+        let start = Instant::now();
+
+        for n in 1..=100 {
+            let sent = start + PING_INTERVAL * n;
+            assert!(keepalive.ping(sent));
+            assert_eq!(keepalive.pong(sent + MS * n), Some(MS * n));
+        }
+
+        // unsolicited PONGs aren't round trips
+        assert_eq!(keepalive.pong(start), None);
+
+        let stats = stats_rx.borrow().clone();
+        assert!(stats.healthy);
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.last_latency_ms, Some(100.0));
+        assert_eq!(stats.p50_latency_ms, Some(50.0));
+        assert_eq!(stats.p99_latency_ms, Some(99.0));
+    }
+
+    #[test]
+    fn unanswered_pings_drop_the_connection() {
+        let (mut keepalive, stats_rx) = Keepalive::new();
+        let start = Instant::now();
+
+        assert!(keepalive.ping(start));
+        assert!(keepalive.ping(start + PING_INTERVAL));
+        assert!(!stats_rx.borrow().healthy);
+
+        // an answer resets the run of misses
+        keepalive.pong(start + PING_INTERVAL + MS);
+        assert_eq!(stats_rx.borrow().missed, 0);
+
+        let pings = (1..=MAX_MISSED + 1)
+            .map(|n| keepalive.ping(start + PING_INTERVAL * (n + 1)))
+            .collect::<Vec<_>>();
+        assert_eq!(pings.iter().filter(|ok| !**ok).count(), 1);
+        assert!(!pings.last().unwrap());
+
+        let stats = stats_rx.borrow().clone();
+        assert_eq!(stats.missed, MAX_MISSED);
+        assert_eq!(stats.total_missed, 1 + MAX_MISSED as u64);
+    }
+}
@@ -0,0 +1,301 @@
+//! How text keywords are found in chat messages.
+//!
+//! `KeywordMatcher` handles emote keywords itself and hands the rest of the message to a
+//! `Matcher`, chosen with `KEYWORD_MATCH_MODE`:
+//!
+//! - `substring` (the default) counts every occurrence of a keyword, including inside other words,
+//!   with a single Aho-Corasick pass over the message for all keywords
+//! - `word` counts only occurrences that aren't part of a longer word
+//! - `regex` treats each keyword as a regular expression
+//!
+//! Messages are lowercased before they're matched, and keywords are stored lowercase.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use regex::{Regex, RegexBuilder};
+
+use crate::db::models::keyword::{Keyword, KeywordId, KeywordKind, KeywordMatcher};
+use crate::util::env::Var;
+use crate::var;
+
+/// Compiled size limit for each regex keyword, so that a pathological pattern can't use
+/// unbounded memory. Matching itself always runs in linear time.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// Finds text keywords in a message.
+pub trait Matcher: fmt::Debug + Send + Sync {
+    /// Adds the number of times each keyword occurs in `text`, which is already lowercase, to
+    /// `counts`.
+    fn count(&self, text: &str, counts: &mut HashMap<KeywordId, usize>);
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    #[default]
+    Substring,
+    Word,
+    Regex,
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "substring" => Ok(Self::Substring),
+            "word" => Ok(Self::Word),
+            "regex" => Ok(Self::Regex),
+            other => Err(format!("unknown keyword match mode '{other}'")),
+        }
+    }
+}
+
+impl MatchMode {
+    /// Builds a matcher for this mode from the text keywords in `keywords`.
+    pub fn build(self, keywords: &[Keyword]) -> Arc<dyn Matcher> {
+        let text = keywords
+            .iter()
+            .filter(|keyword| keyword.kind == KeywordKind::Text);
+
+        match self {
+            Self::Substring => Arc::new(SubstringMatcher::new(text)),
+            Self::Word => Arc::new(WordMatcher(SubstringMatcher::new(text))),
+            Self::Regex => Arc::new(RegexMatcher::new(text)),
+        }
+    }
+}
+
+/// Reads `KEYWORD_MATCH_MODE`, falling back to substring matching if it isn't recognised.
+pub async fn match_mode() -> MatchMode {
+    let mode = var!(Var::KeywordMatchMode).await.unwrap_or_default();
+    mode.parse().unwrap_or_else(|e| {
+        tracing::error!(error = e, "falling back to substring keyword matching");
+        MatchMode::default()
+    })
+}
+
+/// Builds the keyword matcher used for counting, in the configured mode.
+pub async fn keyword_matcher(keywords: &[Keyword]) -> KeywordMatcher {
+    let mode = match_mode().await;
+    tracing::info!(?mode, "matching keywords");
+
+    KeywordMatcher::with_matcher(keywords, mode.build(keywords))
+}
+
+/// Every occurrence of each keyword, overlapping occurrences included.
+#[derive(Debug, Clone)]
+pub struct SubstringMatcher {
+    /// Indexed by pattern id
+    ids: Vec<KeywordId>,
+    automaton: AhoCorasick,
+}
+
+impl SubstringMatcher {
+    pub fn new<'a>(keywords: impl IntoIterator<Item = &'a Keyword>) -> Self {
+        let (ids, words): (Vec<_>, Vec<_>) = keywords
+            .into_iter()
+            .map(|keyword| (keyword.id, &keyword.word))
+            .unzip();
+
+        // overlapping matches, so a keyword contained in another (e.g. `piss` in `pissing`) is
+        // still counted - as it would be with a substring search
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::Standard)
+            .build(words)
+            .expect("tracked keywords are small enough to build an automaton");
+
+        Self { ids, automaton }
+    }
+
+    fn matches<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> impl Iterator<Item = (KeywordId, usize, usize)> + 'a {
+        self.automaton.find_overlapping_iter(text).map(|found| {
+            (
+                self.ids[found.pattern().as_usize()],
+                found.start(),
+                found.end(),
+            )
+        })
+    }
+}
+
+impl Matcher for SubstringMatcher {
+    fn count(&self, text: &str, counts: &mut HashMap<KeywordId, usize>) {
+        for (id, _, _) in self.matches(text) {
+            *counts.entry(id).or_default() += 1;
+        }
+    }
+}
+
+/// Occurrences of each keyword that aren't surrounded by other letters or digits, e.g. `piss` in
+/// `piss!` but not in `pissing`.
+#[derive(Debug, Clone)]
+pub struct WordMatcher(SubstringMatcher);
+
+impl Matcher for WordMatcher {
+    fn count(&self, text: &str, counts: &mut HashMap<KeywordId, usize>) {
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+        for (id, start, end) in self.0.matches(text) {
+            if !is_word(text[..start].chars().next_back()) && !is_word(text[end..].chars().next()) {
+                *counts.entry(id).or_default() += 1;
+            }
+        }
+    }
+}
+
+/// Non-overlapping matches of each keyword as a regular expression. Keywords that aren't valid
+/// expressions are logged and never match; empty matches (e.g. from `a*`) aren't counted.
+#[derive(Debug, Clone)]
+pub struct RegexMatcher {
+    patterns: Vec<(KeywordId, Regex)>,
+}
+
+impl RegexMatcher {
+    pub fn new<'a>(keywords: impl IntoIterator<Item = &'a Keyword>) -> Self {
+        let patterns = keywords
+            .into_iter()
+            .filter_map(|keyword| match compile(&keyword.word) {
+                Ok(regex) => Some((keyword.id, regex)),
+                Err(e) => {
+                    tracing::error!(error = %e, keyword = keyword.word, "invalid regex keyword");
+                    None
+                }
+            })
+            .collect();
+
+        Self { patterns }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+impl Matcher for RegexMatcher {
+    fn count(&self, text: &str, counts: &mut HashMap<KeywordId, usize>) {
+        for (id, regex) in &self.patterns {
+            let found = regex.find_iter(text).filter(|m| !m.is_empty()).count();
+            if found > 0 {
+                *counts.entry(*id).or_default() += found;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDateTime;
+    use tinyrand::{RandRange, Seeded, StdRand};
+
+    use super::*;
+
+    fn keyword(id: i32, word: &str) -> Keyword {
+        Keyword {
+            id: KeywordId(id),
+            word: word.to_string(),
+            kind: KeywordKind::Text,
+            emote_id: None,
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn counts(matcher: &dyn Matcher, text: &str) -> Vec<(KeywordId, usize)> {
+        let mut counts = HashMap::new();
+        matcher.count(text, &mut counts);
+
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by_key(|(id, _)| id.0);
+        counts
+    }
+
+    #[test]
+    fn modes_are_parsed_from_the_environment() {
+        assert_eq!("".parse(), Ok(MatchMode::Substring));
+        assert_eq!(" Word ".parse(), Ok(MatchMode::Word));
+        assert_eq!("regex".parse(), Ok(MatchMode::Regex));
+        assert!("fuzzy".parse::<MatchMode>().is_err());
+    }
+
+    #[test]
+    fn each_mode_counts_differently() {
+        let keywords = [keyword(1, "piss"), keyword(2, "pe+")];
+        let text = "piss pissing pe+ peeee _piss";
+
+        assert_eq!(
+            counts(MatchMode::Substring.build(&keywords).as_ref(), text),
+            vec![(KeywordId(1), 3), (KeywordId(2), 1)]
+        );
+        assert_eq!(
+            counts(MatchMode::Word.build(&keywords).as_ref(), text),
+            vec![(KeywordId(1), 1), (KeywordId(2), 1)]
+        );
+        assert_eq!(
+            counts(MatchMode::Regex.build(&keywords).as_ref(), text),
+            vec![(KeywordId(1), 3), (KeywordId(2), 2)]
+        );
+    }
+
+    #[test]
+    fn word_boundaries_respect_multibyte_chars() {
+        let matcher = MatchMode::Word.build(&[keyword(1, "piss")]);
+        assert_eq!(
+            counts(matcher.as_ref(), "ÿpiss piss… «piss»"),
+            vec![(KeywordId(1), 2)]
+        );
+    }
+
+    #[test]
+    fn invalid_and_empty_regexes_never_match() {
+        let matcher = MatchMode::Regex.build(&[keyword(1, "(piss"), keyword(2, "x*")]);
+        assert_eq!(counts(matcher.as_ref(), "piss (piss"), vec![]);
+        assert_eq!(
+            counts(matcher.as_ref(), "piss xxx"),
+            vec![(KeywordId(2), 1)]
+        );
+    }
+
+    /// Random patterns and messages, built from characters that are meaningful to regexes and
+    /// multibyte characters, must never panic or count empty matches.
+    #[test]
+    fn regex_mode_handles_arbitrary_input() {
+        const ALPHABET: &[char] = &[
+            'p', 'i', 's', 'S', ' ', '.', '*', '+', '?', '(', ')', '[', ']', '{', '}', '|', '^',
+            '$', '\\', '-', ',', '1', 'ÿ', '…', '🚽', '\u{180B}', '\u{0}',
+        ];
+
+        let mut rng = StdRand::seed(0x5eed);
+        let mut random = |max_len: usize| -> String {
+            let len = rng.next_range(0..max_len);
+            (0..len)
+                .map(|_| ALPHABET[rng.next_range(0..ALPHABET.len())])
+                .collect()
+        };
+
+        for _ in 0..2000 {
+            let pattern = random(12);
+            let text = random(64).to_lowercase();
+            let matcher = RegexMatcher::new(&[keyword(1, &pattern)]);
+
+            let found = counts(&matcher, &text);
+            assert!(found.len() <= 1);
+            if let Some((_, count)) = found.first() {
+                // non-overlapping and non-empty, so there's at most one match per char
+                assert!(*count <= text.chars().count(), "{pattern:?} in {text:?}");
+            }
+        }
+
+        // the size limit rejects patterns that would compile to something huge
+        assert!(compile("(((a{100}){100}){100})").is_err());
+    }
+}
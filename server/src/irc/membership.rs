@@ -0,0 +1,165 @@
+//! Persists which channels the connection should be in, and which it had joined, across restarts.
+//!
+//! Both sets live in Redis: `irc:channels:desired` holds every channel the connection has been
+//! asked to join, and `irc:channels:joined` holds the channels Twitch last confirmed a JOIN for. On
+//! startup the desired set is merged with the stored channel list, and previously-joined channels
+//! are rejoined first. Channels removed at runtime are kept in `irc:channels:removed` and left out
+//! of the merge until they're added again. Persistence is best-effort - failures are logged and never interrupt the
+//! connection.
+
+use std::collections::HashSet;
+
+use redis::AsyncCommands;
+use tracing::instrument;
+
+use crate::db::redis::redis_pool::RedisResult;
+
+const DESIRED_KEY: &str = "irc:channels:desired";
+const JOINED_KEY: &str = "irc:channels:joined";
+const REMOVED_KEY: &str = "irc:channels:removed";
+
+/// Membership restored from a previous run.
+#[derive(Debug, Default)]
+pub struct RestoredMembership {
+    /// Every channel to join, as `#login`, with previously-joined channels first
+    pub channels: Vec<String>,
+    pub previously_joined: HashSet<String>,
+}
+
+/// Formats a channel as `#login`.
+pub fn normalize(channel: &str) -> String {
+    format!("#{}", channel.trim_start_matches('#').to_lowercase())
+}
+
+/// Merges the stored channel list with the membership persisted by a previous run, and records the
+/// result as the new desired state.
+#[instrument(skip(redis_pool, channels))]
+pub async fn restore<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    channels: &[String],
+) -> RedisResult<RestoredMembership> {
+    let desired: HashSet<String> = redis_pool.smembers(DESIRED_KEY).await?;
+    let joined: HashSet<String> = redis_pool.smembers(JOINED_KEY).await?;
+    let removed: HashSet<String> = redis_pool.smembers(REMOVED_KEY).await?;
+
+    let restored = merge(channels, desired, joined, &removed);
+    tracing::info!(
+        count = restored.channels.len(),
+        previously_joined = restored.previously_joined.len(),
+        "restored channel membership"
+    );
+
+    if !restored.channels.is_empty() {
+        let _: () = redis_pool.sadd(DESIRED_KEY, &restored.channels).await?;
+    }
+
+    Ok(restored)
+}
+
+fn merge(
+    channels: &[String],
+    desired: HashSet<String>,
+    joined: HashSet<String>,
+    removed: &HashSet<String>,
+) -> RestoredMembership {
+    let mut all: Vec<String> = channels
+        .iter()
+        .map(|ch| normalize(ch))
+        .chain(desired.iter().map(|ch| normalize(ch)))
+        .filter(|ch| !removed.contains(ch))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let previously_joined: HashSet<String> = joined.iter().map(|ch| normalize(ch)).collect();
+
+    // stable join order: previously-joined channels first, then alphabetically
+    all.sort_by(|a, b| {
+        previously_joined
+            .contains(b)
+            .cmp(&previously_joined.contains(a))
+            .then_with(|| a.cmp(b))
+    });
+
+    RestoredMembership {
+        channels: all,
+        previously_joined,
+    }
+}
+
+#[instrument(skip(redis_pool))]
+pub async fn record_desired<R: AsyncCommands + Sync>(redis_pool: &mut R, channel: &str) {
+    let channel = normalize(channel);
+    let result: redis::RedisResult<()> = redis::pipe()
+        .sadd(DESIRED_KEY, &channel)
+        .srem(REMOVED_KEY, &channel)
+        .query_async(redis_pool)
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(error = ?e, channel, "failed to persist desired channel");
+    }
+}
+
+#[instrument(skip(redis_pool))]
+pub async fn record_joined<R: AsyncCommands + Sync>(redis_pool: &mut R, channel: &str) {
+    let result: redis::RedisResult<()> = redis_pool.sadd(JOINED_KEY, normalize(channel)).await;
+    if let Err(e) = result {
+        tracing::warn!(error = ?e, channel, "failed to persist joined channel");
+    }
+}
+
+#[instrument(skip(redis_pool))]
+pub async fn record_parted<R: AsyncCommands + Sync>(redis_pool: &mut R, channel: &str) {
+    let result: redis::RedisResult<()> = redis_pool.srem(JOINED_KEY, normalize(channel)).await;
+    if let Err(e) = result {
+        tracing::warn!(error = ?e, channel, "failed to persist parted channel");
+    }
+}
+
+/// Records a channel as removed, so that it isn't rejoined after a restart.
+#[instrument(skip(redis_pool))]
+pub async fn record_removed<R: AsyncCommands + Sync>(redis_pool: &mut R, channel: &str) {
+    let channel = normalize(channel);
+    let result: redis::RedisResult<()> = redis::pipe()
+        .srem(DESIRED_KEY, &channel)
+        .srem(JOINED_KEY, &channel)
+        .sadd(REMOVED_KEY, &channel)
+        .query_async(redis_pool)
+        .await;
+
+    if let Err(e) = result {
+        tracing::warn!(error = ?e, channel, "failed to persist removed channel");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restored_channels_are_merged_with_joined_first() {
+        let channels = ["Alpha", "#beta", "gamma"].map(String::from);
+        let desired = HashSet::from(["#delta", "#alpha"].map(String::from));
+        let joined = HashSet::from(["#gamma", "#delta"].map(String::from));
+
+        let restored = merge(&channels, desired, joined, &HashSet::new());
+
+        assert_eq!(
+            restored.channels,
+            ["#delta", "#gamma", "#alpha", "#beta"].map(String::from)
+        );
+        assert!(restored.previously_joined.contains("#gamma"));
+    }
+
+    #[test]
+    fn removed_channels_are_not_restored() {
+        let channels = ["alpha", "beta"].map(String::from);
+        let desired = HashSet::from(["#gamma".to_string()]);
+        let joined = HashSet::from(["#beta".to_string()]);
+        let removed = HashSet::from(["#beta", "#gamma"].map(String::from));
+
+        let restored = merge(&channels, desired, joined, &removed);
+
+        assert_eq!(restored.channels, ["#alpha".to_string()]);
+    }
+}
@@ -0,0 +1,197 @@
+//! Chat messages, independent of the transport they arrived over.
+//!
+//! Messages are received over IRC (as a PRIVMSG's tags and text), but Twitch also delivers them
+//! as `channel.chat.message` EventSub notifications. Both convert into a `ChatMessage`, which is
+//! what keyword counting and chat commands work with, so either transport can feed them.
+
+use chrono::{NaiveDateTime, Utc};
+
+use crate::api::webhook::ChannelChatMessageEvent;
+use crate::db::prelude::{ChannelId, ChatterId};
+use crate::irc::commands::{Emote, IrcTags};
+use crate::irc::permission::{Badge, PermissionLevel};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    pub channel_id: ChannelId,
+    /// Without the leading `#`
+    pub channel_login: String,
+    /// Only present for messages sent in a shared chat session
+    pub source_channel_id: Option<ChannelId>,
+    pub user_id: ChatterId,
+    pub user_login: String,
+    pub display_name: String,
+    pub color: String,
+    pub text: String,
+    /// Empty unless the message uses twitch emotes
+    pub emotes: Vec<Emote>,
+    pub message_id: String,
+    /// When Twitch says the message was sent, or when it was received if the transport doesn't
+    /// say
+    pub sent_at: NaiveDateTime,
+    pub badges: Vec<Badge>,
+    pub permission: PermissionLevel,
+}
+
+impl ChatMessage {
+    /// Returns true for messages sent during a shared chat session that originated in the other
+    /// channel.
+    pub fn is_shared_from_elsewhere(&self) -> bool {
+        self.source_channel_id
+            .as_ref()
+            .is_some_and(|source_channel_id| self.channel_id != *source_channel_id)
+    }
+}
+
+impl From<(IrcTags, String)> for ChatMessage {
+    fn from((tags, text): (IrcTags, String)) -> Self {
+        Self {
+            channel_id: tags.channel_id,
+            channel_login: tags.channel_name,
+            source_channel_id: tags.source_channel_id,
+            user_id: tags.user_id,
+            user_login: tags.user_login,
+            display_name: tags.display_name,
+            color: tags.color,
+            text,
+            emotes: tags.emotes,
+            message_id: tags.msg_id,
+            sent_at: tags.sent_at.unwrap_or_else(|| Utc::now().naive_utc()),
+            badges: tags.badges,
+            permission: tags.permission,
+        }
+    }
+}
+
+impl From<ChannelChatMessageEvent> for ChatMessage {
+    fn from(event: ChannelChatMessageEvent) -> Self {
+        // emote positions are given by fragment rather than by offset, so they're counted out in
+        // chars to match the IRC `emotes` tag
+        let mut emotes = Vec::new();
+        let mut offset = 0;
+        for fragment in &event.message.fragments {
+            let len = fragment.text.chars().count();
+            if let Some(emote) = &fragment.emote
+                && len > 0
+            {
+                emotes.push(Emote {
+                    id: emote.id.clone(),
+                    start: offset,
+                    end: offset + len - 1,
+                });
+            }
+
+            offset += len;
+        }
+
+        // outside of shared chat sessions the source is null
+        let source_channel_id = event
+            .source_broadcaster_user_id
+            .filter(|id| !id.is_empty())
+            .map(ChannelId);
+
+        let badges: Vec<Badge> = event
+            .badges
+            .into_iter()
+            .map(|badge| Badge {
+                set_id: badge.set_id,
+                version: badge.id,
+            })
+            .collect();
+        let permission = PermissionLevel::from_badges(&badges);
+
+        Self {
+            channel_id: ChannelId(event.broadcaster_user_id),
+            channel_login: event.broadcaster_user_login,
+            source_channel_id,
+            user_id: ChatterId(event.chatter_user_id),
+            user_login: event.chatter_user_login,
+            display_name: event.chatter_user_name,
+            color: event.color,
+            text: event.message.text,
+            emotes,
+            message_id: event.message_id,
+            sent_at: Utc::now().naive_utc(),
+            badges,
+            permission,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use irc::proto::message::Tag;
+    use irc::proto::{Message, Prefix};
+
+    use super::*;
+    use crate::irc::commands::IncomingMessage;
+    use crate::irc::parse::parse_incoming;
+
+    #[test]
+    fn irc_and_eventsub_messages_convert_alike() {
+        let tags = [
+            ("room-id", "103033809"),
+            ("user-id", "123456789"),
+            ("display-name", "Chatter"),
+            ("color", "#FF0000"),
+            ("id", "abc-123"),
+            ("emotes", "25:5-9"),
+            ("badges", "broadcaster/1"),
+            ("tmi-sent-ts", "1700000000000"),
+        ];
+        let msg = Message {
+            tags: Some(
+                tags.iter()
+                    .map(|(k, v)| Tag(k.to_string(), Some(v.to_string())))
+                    .collect(),
+            ),
+            prefix: Some(Prefix::Nickname(
+                "chatter".into(),
+                "chatter".into(),
+                "tmi".into(),
+            )),
+            command: irc::proto::Command::PRIVMSG("#channel".into(), "piss Kappa".into()),
+        };
+        let Some(IncomingMessage::Privmsg(from_irc)) = parse_incoming(&msg) else {
+            panic!("expected a PRIVMSG");
+        };
+
+        let event: ChannelChatMessageEvent = serde_json::from_value(serde_json::json!({
+            "broadcaster_user_id": "103033809",
+            "broadcaster_user_login": "channel",
+            "broadcaster_user_name": "Channel",
+            "chatter_user_id": "123456789",
+            "chatter_user_login": "chatter",
+            "chatter_user_name": "Chatter",
+            "message_id": "abc-123",
+            "message": {
+                "text": "piss Kappa",
+                "fragments": [
+                    { "type": "text", "text": "piss " },
+                    { "type": "emote", "text": "Kappa", "emote": { "id": "25" } }
+                ]
+            },
+            "color": "#FF0000",
+            "badges": [{ "set_id": "broadcaster", "id": "1", "info": "" }],
+            "source_broadcaster_user_id": null
+        }))
+        .unwrap();
+        let from_eventsub = ChatMessage::from(event);
+
+        assert_eq!(
+            from_irc.sent_at,
+            chrono::DateTime::from_timestamp_millis(1700000000000)
+                .unwrap()
+                .naive_utc()
+        );
+        assert_eq!(
+            ChatMessage {
+                sent_at: from_irc.sent_at,
+                ..from_eventsub
+            },
+            from_irc
+        );
+        assert_eq!(from_irc.permission, PermissionLevel::Broadcaster);
+        assert!(!from_irc.is_shared_from_elsewhere());
+    }
+}
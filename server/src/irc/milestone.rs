@@ -0,0 +1,131 @@
+//! Announces milestones crossed while counting.
+//!
+//! Milestones are checked against the totals as of each score event (see `increment_score`), so
+//! concurrent events can't both claim - or both skip over - the same milestone. Every milestone
+//! reached is published to the `EventBus`, but only announced in chat for channels that have
+//! replies enabled.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use irc::proto::Message;
+use sqlx::PgPool;
+
+use crate::db::models::milestone::{Milestone, MilestoneKind, MilestoneReached, MilestoneTotals};
+use crate::irc::commands::OutgoingCommand;
+use crate::irc::events::{LiveEvent, events};
+use crate::irc::message::ChatMessage;
+use crate::irc::queue::QueueSender;
+use crate::irc::rate_limit::Bucket;
+use crate::irc::worker::channel_settings;
+use crate::util::availability::availability;
+
+/// Returns the milestones crossed by a single score event, given the totals after it.
+pub fn reached(
+    milestones: &[Milestone],
+    totals: MilestoneTotals,
+    message: &ChatMessage,
+) -> Vec<MilestoneReached> {
+    milestones
+        .iter()
+        .filter_map(|milestone| {
+            let new = match milestone.kind {
+                MilestoneKind::ChannelTotal => totals.channel_total,
+                MilestoneKind::ChatterScore => totals.chatter_score,
+            };
+
+            milestone
+                .crossed(new - 1, new)
+                .map(|value| MilestoneReached {
+                    milestone_id: milestone.id,
+                    kind: milestone.kind,
+                    channel_id: message.channel_id.clone(),
+                    channel_login: message.channel_login.clone(),
+                    chatter_id: message.user_id.clone(),
+                    chatter_login: message.user_login.clone(),
+                    value,
+                    reached_at: Utc::now().naive_utc(),
+                })
+        })
+        .collect()
+}
+
+fn announcement(reached: &MilestoneReached) -> String {
+    match reached.kind {
+        MilestoneKind::ChannelTotal => format!(
+            "this chat just hit {} in total - congrats everyone",
+            reached.value
+        ),
+        MilestoneKind::ChatterScore => format!(
+            "@{} just hit {} in this channel - congrats",
+            reached.chatter_login, reached.value
+        ),
+    }
+}
+
+pub struct MilestoneAnnouncer {
+    pool: &'static PgPool,
+    cmd_tx: QueueSender<OutgoingCommand>,
+    rate_limiter: Arc<Bucket>,
+}
+
+impl MilestoneAnnouncer {
+    pub fn new(
+        pool: &'static PgPool,
+        cmd_tx: QueueSender<OutgoingCommand>,
+        rate_limiter: Arc<Bucket>,
+    ) -> Self {
+        Self {
+            pool,
+            cmd_tx,
+            rate_limiter,
+        }
+    }
+
+    /// Publishes each milestone and queues its announcement; announcements wait for a reply
+    /// permit in the background, so they never hold up counting.
+    pub async fn announce(&self, reached: Vec<MilestoneReached>) {
+        for milestone in reached {
+            tracing::info!(
+                channel = milestone.channel_login,
+                chatter = milestone.chatter_login,
+                kind = milestone.kind.as_str(),
+                value = milestone.value,
+                "milestone reached"
+            );
+
+            let announce = match channel_settings(self.pool, &milestone.channel_id).await {
+                Ok(settings) => settings.announces_milestones() && !availability().is_degraded(),
+                Err(e) => {
+                    tracing::error!(error = ?e, "failed to check replies for milestone");
+                    false
+                }
+            };
+
+            if announce {
+                let message = Message {
+                    tags: None,
+                    prefix: None,
+                    command: irc::proto::Command::PRIVMSG(
+                        format!("#{}", milestone.channel_login),
+                        announcement(&milestone),
+                    ),
+                };
+
+                let (cmd_tx, rate_limiter) = (self.cmd_tx.clone(), Arc::clone(&self.rate_limiter));
+                tokio::spawn(async move {
+                    if let Err(e) = rate_limiter.acquire_one().await {
+                        tracing::error!(error = ?e, "failed to acquire milestone reply permit");
+                        return;
+                    }
+
+                    if let Err(e) = cmd_tx.send(OutgoingCommand::Reply { message }).await {
+                        tracing::error!(error = ?e, "failed to queue milestone announcement");
+                    }
+                });
+            }
+
+            events().publish(LiveEvent::MilestoneReached(milestone));
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Canned Twitch IRC lines for playing back through a `MockTwitchServer`.
+//!
+//! Lines are tagged the way Twitch tags them, so they parse with `parse_incoming` like real chat.
+//! Ids are derived from logins and a per-process counter so that every message is distinct, but
+//! the same login always maps to the same user id.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SERVER_HOST: &str = "tmi.twitch.tv";
+
+static NEXT_MSG_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_msg_id() -> String {
+    let n = NEXT_MSG_ID.fetch_add(1, Ordering::Relaxed);
+    format!("00000000-0000-4000-8000-{n:012}")
+}
+
+/// A stable numeric user id for a login.
+pub fn user_id(login: &str) -> String {
+    // FNV-1a, so ids don't change between runs
+    let hash = login
+        .to_lowercase()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+
+    (hash % 1_000_000_000 + 1_000_000_000).to_string()
+}
+
+/// A chat message from a chatter in a channel.
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub channel: String,
+    pub room_id: String,
+    pub login: String,
+    pub user_id: String,
+    pub text: String,
+    pub msg_id: String,
+    /// The raw `emotes` tag, e.g. `25:0-4`
+    pub emotes: String,
+    /// Set for messages relayed from another channel in a shared chat session
+    pub source_room_id: Option<String>,
+}
+
+impl ChatMessage {
+    pub fn new(channel: &str, room_id: &str, login: &str, text: &str) -> Self {
+        Self {
+            channel: channel.trim_start_matches('#').to_lowercase(),
+            room_id: room_id.to_string(),
+            login: login.to_lowercase(),
+            user_id: user_id(login),
+            text: text.to_string(),
+            msg_id: next_msg_id(),
+            emotes: String::new(),
+            source_room_id: None,
+        }
+    }
+
+    pub fn with_emotes(mut self, emotes: &str) -> Self {
+        self.emotes = emotes.to_string();
+        self
+    }
+
+    pub fn shared_from(mut self, source_room_id: &str) -> Self {
+        self.source_room_id = Some(source_room_id.to_string());
+        self
+    }
+
+    pub fn to_line(&self) -> String {
+        let Self {
+            channel,
+            room_id,
+            login,
+            user_id,
+            text,
+            msg_id,
+            emotes,
+            source_room_id,
+        } = self;
+        let source_room_id = source_room_id.as_deref().unwrap_or_default();
+
+        format!(
+            "@badge-info=;badges=;color=#1E90FF;display-name={login};emotes={emotes};\
+             first-msg=0;flags=;id={msg_id};mod=0;returning-chatter=0;room-id={room_id};\
+             source-room-id={source_room_id};subscriber=0;tmi-sent-ts=1700000000000;turbo=0;\
+             user-id={user_id};user-type= \
+             :{login}!{login}@{login}.{SERVER_HOST} PRIVMSG #{channel} :{text}"
+        )
+    }
+}
+
+impl From<ChatMessage> for String {
+    fn from(value: ChatMessage) -> Self {
+        value.to_line()
+    }
+}
+
+/// A chatter timed out for `duration` seconds, or banned if `None`.
+pub fn clearchat(channel: &str, room_id: &str, login: &str, duration: Option<u32>) -> String {
+    let duration = duration
+        .map(|secs| format!("ban-duration={secs};"))
+        .unwrap_or_default();
+
+    format!(
+        "@{duration}room-id={room_id};target-user-id={};tmi-sent-ts=1700000000000 \
+         :{SERVER_HOST} CLEARCHAT #{channel} :{login}",
+        user_id(login)
+    )
+}
+
+/// A single message deleted by a moderator.
+pub fn clearmsg(channel: &str, login: &str, msg_id: &str) -> String {
+    format!(
+        "@login={login};room-id=;target-msg-id={msg_id};tmi-sent-ts=1700000000000 \
+         :{SERVER_HOST} CLEARMSG #{channel} :deleted"
+    )
+}
+
+/// Twitch asking the client to reconnect, e.g. ahead of server maintenance.
+pub fn reconnect() -> String {
+    format!(":{SERVER_HOST} RECONNECT")
+}
+
+/// `count` distinct chatters each sending `keyword` once.
+pub fn keyword_burst(channel: &str, room_id: &str, keyword: &str, count: usize) -> Vec<String> {
+    (0..count)
+        .map(|n| ChatMessage::new(channel, room_id, &format!("chatter{n}"), keyword).to_line())
+        .collect()
+}
+
+/// A short stretch of ordinary chat: messages with and without a keyword, an emote-only message,
+/// a deleted message and a timeout.
+pub fn mixed_chat(channel: &str, room_id: &str, keyword: &str) -> Vec<String> {
+    let deleted = ChatMessage::new(channel, room_id, "spammer", &format!("{keyword} {keyword}"));
+
+    vec![
+        ChatMessage::new(channel, room_id, "regular", "hello chat").to_line(),
+        ChatMessage::new(channel, room_id, "regular", &format!("{keyword} again")).to_line(),
+        ChatMessage::new(channel, room_id, "lurker", "Kappa")
+            .with_emotes("25:0-4")
+            .to_line(),
+        deleted.to_line(),
+        clearmsg(channel, "spammer", &deleted.msg_id),
+        clearchat(channel, room_id, "spammer", Some(600)),
+        ChatMessage::new(channel, room_id, "another", &format!("what is {keyword}")).to_line(),
+    ]
+}
@@ -0,0 +1,358 @@
+//! A local stand-in for Twitch's IRC server, so that connections can be tested without reaching
+//! out to Twitch.
+//!
+//! `MockTwitchServer` speaks plaintext IRC on a random local port and answers just enough of the
+//! Twitch dialect for `ConnectionClient` and `ConnectionSupervisor` to run against it:
+//! registration, `CAP REQ`, `JOIN`/`PART` and `PING`. Everything the client sends is recorded so
+//! tests can assert on it, and canned messages from `fixtures` can be played back to the client.
+//!
+//! Only one client is served at a time; a client connecting after another has disconnected picks
+//! up any lines that were queued in the meantime.
+
+pub mod fixtures;
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::irc::connection::IrcEndpoint;
+
+const SERVER_HOST: &str = "tmi.twitch.tv";
+
+#[derive(Debug)]
+enum ServerAction {
+    Send(String),
+    Disconnect,
+}
+
+/// What the server has seen from its clients.
+#[derive(Debug, Default)]
+struct Recorded {
+    /// Every line sent by a client, without its line ending
+    lines: Vec<String>,
+    /// Channels currently joined by the client, with the leading `#`
+    joined: Vec<String>,
+    connections: usize,
+}
+
+#[derive(Debug)]
+pub struct MockTwitchServer {
+    addr: SocketAddr,
+    action_tx: mpsc::UnboundedSender<ServerAction>,
+    recorded: Arc<Mutex<Recorded>>,
+    updated: Arc<Notify>,
+    task: JoinHandle<()>,
+}
+
+impl MockTwitchServer {
+    /// Starts listening on a random port on the loopback interface.
+    pub async fn start() -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+
+        let (action_tx, action_rx) = mpsc::unbounded_channel();
+        let recorded = Arc::new(Mutex::new(Recorded::default()));
+        let updated = Arc::new(Notify::new());
+
+        let task = tokio::spawn(accept_loop(
+            listener,
+            action_rx,
+            Arc::clone(&recorded),
+            Arc::clone(&updated),
+        ));
+
+        Ok(Self {
+            addr,
+            action_tx,
+            recorded,
+            updated,
+            task,
+        })
+    }
+
+    /// Where a client should connect to reach this server.
+    pub fn endpoint(&self) -> IrcEndpoint {
+        IrcEndpoint {
+            server: self.addr.ip().to_string(),
+            port: self.addr.port(),
+            use_tls: false,
+        }
+    }
+
+    /// Queues a raw line (without a line ending) to be sent to the client.
+    pub fn send(&self, line: impl Into<String>) {
+        _ = self.action_tx.send(ServerAction::Send(line.into()));
+    }
+
+    /// Queues a sequence of lines, e.g. one of the canned streams from `fixtures`.
+    pub fn play<I>(&self, lines: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        for line in lines {
+            self.send(line);
+        }
+    }
+
+    /// Sends a server-initiated keepalive, which the client is expected to answer with a PONG.
+    pub fn ping(&self) {
+        self.send(format!("PING :{SERVER_HOST}"));
+    }
+
+    /// Closes the current connection once any lines queued before it have been sent.
+    pub fn disconnect(&self) {
+        _ = self.action_tx.send(ServerAction::Disconnect);
+    }
+
+    /// Every line sent by clients so far.
+    pub fn received(&self) -> Vec<String> {
+        self.recorded.lock().unwrap().lines.clone()
+    }
+
+    /// Channels joined by the current client.
+    pub fn joined(&self) -> Vec<String> {
+        self.recorded.lock().unwrap().joined.clone()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.recorded.lock().unwrap().connections
+    }
+
+    /// Waits for a client to send a line matching `pred`, returning the first one (including any
+    /// received before the call), or `None` if none arrives within `timeout`.
+    pub async fn wait_for<F>(&self, timeout: Duration, pred: F) -> Option<String>
+    where
+        F: Fn(&str) -> bool,
+    {
+        tokio::time::timeout(timeout, async {
+            loop {
+                // registered before checking, so an update in between isn't missed
+                let updated = self.updated.notified();
+                tokio::pin!(updated);
+                updated.as_mut().enable();
+
+                if let Some(line) = self.received().into_iter().find(|line| pred(line)) {
+                    return line;
+                }
+                updated.await;
+            }
+        })
+        .await
+        .ok()
+    }
+
+    /// Waits until the client has joined `channel` (with or without the leading `#`).
+    pub async fn wait_for_join(&self, channel: &str, timeout: Duration) -> bool {
+        let channel = format!("#{}", channel.trim_start_matches('#'));
+        tokio::time::timeout(timeout, async {
+            loop {
+                let updated = self.updated.notified();
+                tokio::pin!(updated);
+                updated.as_mut().enable();
+
+                if self.joined().contains(&channel) {
+                    return;
+                }
+                updated.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+impl Drop for MockTwitchServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    mut action_rx: mpsc::UnboundedReceiver<ServerAction>,
+    recorded: Arc<Mutex<Recorded>>,
+    updated: Arc<Notify>,
+) {
+    while let Ok((socket, _)) = listener.accept().await {
+        {
+            let mut recorded = recorded.lock().unwrap();
+            recorded.connections += 1;
+            recorded.joined.clear();
+        }
+        updated.notify_waiters();
+
+        if let Err(e) = serve(socket, &mut action_rx, &recorded, &updated).await {
+            tracing::debug!(error = ?e, "mock irc connection closed");
+        }
+    }
+}
+
+async fn serve(
+    socket: TcpStream,
+    action_rx: &mut mpsc::UnboundedReceiver<ServerAction>,
+    recorded: &Mutex<Recorded>,
+    updated: &Notify,
+) -> std::io::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut nick = String::new();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    return Ok(());
+                };
+
+                let replies = respond(&line, &mut nick, recorded);
+                recorded.lock().unwrap().lines.push(line);
+                updated.notify_waiters();
+
+                for reply in replies {
+                    writer.write_all(format!("{reply}\r\n").as_bytes()).await?;
+                }
+            }
+
+            Some(action) = action_rx.recv() => match action {
+                ServerAction::Send(line) => {
+                    writer.write_all(format!("{line}\r\n").as_bytes()).await?;
+                }
+                ServerAction::Disconnect => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Lines sent back in response to a line from the client, as Twitch would.
+fn respond(line: &str, nick: &mut String, recorded: &Mutex<Recorded>) -> Vec<String> {
+    let (command, params) = line.split_once(' ').unwrap_or((line, ""));
+    let trailing = params.split_once(':').map(|(_, t)| t).unwrap_or(params);
+
+    match command.to_ascii_uppercase().as_str() {
+        "CAP" if params.starts_with("REQ") => {
+            vec![format!(":{SERVER_HOST} CAP * ACK :{trailing}")]
+        }
+
+        "NICK" => {
+            *nick = params.trim().to_lowercase();
+            vec![
+                format!(":{SERVER_HOST} 001 {nick} :Welcome, GLHF!"),
+                format!(":{SERVER_HOST} 375 {nick} :-"),
+                format!(":{SERVER_HOST} 376 {nick} :>"),
+            ]
+        }
+
+        "JOIN" => {
+            let channels: Vec<String> = params
+                .split(',')
+                .map(|ch| ch.trim().to_lowercase())
+                .filter(|ch| !ch.is_empty())
+                .collect();
+
+            let mut recorded = recorded.lock().unwrap();
+            channels
+                .into_iter()
+                .flat_map(|channel| {
+                    if !recorded.joined.contains(&channel) {
+                        recorded.joined.push(channel.clone());
+                    }
+
+                    [
+                        format!(":{nick}!{nick}@{nick}.{SERVER_HOST} JOIN {channel}"),
+                        format!(":{nick}.{SERVER_HOST} 353 {nick} = {channel} :{nick}"),
+                        format!(":{nick}.{SERVER_HOST} 366 {nick} {channel} :End of /NAMES list"),
+                    ]
+                })
+                .collect()
+        }
+
+        "PART" => {
+            let mut recorded = recorded.lock().unwrap();
+            params
+                .split(',')
+                .map(|ch| ch.trim().to_lowercase())
+                .filter(|ch| !ch.is_empty())
+                .map(|channel| {
+                    recorded.joined.retain(|ch| *ch != channel);
+                    format!(":{nick}!{nick}@{nick}.{SERVER_HOST} PART {channel}")
+                })
+                .collect()
+        }
+
+        "PING" => vec![format!(":{SERVER_HOST} PONG {SERVER_HOST} :{trailing}")],
+
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::irc::commands::IncomingMessage;
+    use crate::irc::connection::ConnectionClient;
+    use crate::irc::parse::parse_incoming;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn client_receives_played_messages() {
+        let server = MockTwitchServer::start().await.unwrap();
+        let mut client = ConnectionClient::init_as(
+            &vec!["SomeChannel".to_string()],
+            &server.endpoint(),
+            "counter".to_string(),
+            "oauth:token".to_string(),
+        )
+        .await
+        .unwrap();
+
+        client.connect().await.unwrap();
+        client.join_all_channels().await.unwrap();
+
+        // the client only flushes its outgoing queue while its stream is polled
+        let mut stream = client.inner.stream().unwrap();
+        let (msg_tx, mut msg_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                if let Some(IncomingMessage::Privmsg(message)) = parse_incoming(&msg) {
+                    _ = msg_tx.send(message);
+                }
+            }
+        });
+
+        assert!(server.wait_for_join("somechannel", TIMEOUT).await);
+        let cap = server
+            .wait_for(TIMEOUT, |line| line.starts_with("CAP REQ"))
+            .await;
+        assert!(cap.unwrap().contains("twitch.tv/tags"));
+
+        server.play(fixtures::keyword_burst("somechannel", "123", "peepo", 2));
+        server.ping();
+
+        let mut privmsgs = Vec::new();
+        while privmsgs.len() < 2 {
+            let msg = tokio::time::timeout(TIMEOUT, msg_rx.recv()).await.unwrap();
+            privmsgs.push(msg.unwrap());
+        }
+
+        assert_eq!(privmsgs[0].channel_id.0, "123");
+        assert_eq!(privmsgs[0].channel_login, "somechannel");
+        assert_eq!(privmsgs[1].text, "peepo");
+        assert_ne!(privmsgs[0].user_id, privmsgs[1].user_id);
+
+        // keepalives are answered by the irc client itself
+        let pong = server
+            .wait_for(TIMEOUT, |line| line.starts_with("PONG"))
+            .await;
+        assert!(pong.is_some());
+    }
+}
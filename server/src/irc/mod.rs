@@ -2,11 +2,34 @@ use std::{sync::Arc, time::Duration};
 
 pub mod bridge;
 pub mod channels;
+pub mod chat_log;
 pub mod commands;
 pub mod connection;
+pub mod coordination;
+pub mod dedupe;
+pub mod digest;
 pub mod error;
+pub mod events;
+#[cfg(test)]
+pub mod harness;
+pub mod hydrate;
+pub mod keepalive;
+pub mod matcher;
+pub mod membership;
+pub mod message;
+pub mod milestone;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+pub mod moderation;
 pub mod parse;
+pub mod permission;
+pub mod queue;
 pub mod rate_limit;
+pub mod room_state;
+pub mod router;
+pub mod score_limit;
+pub mod stats;
+pub mod tap;
 pub mod worker;
 
 pub use bridge::IrcHandle;
@@ -19,34 +42,144 @@ use tinyrand_std::ClockSeed;
 use tokio::sync::mpsc;
 use tracing::instrument;
 
-use crate::irc::{connection::ConnectionSupervisor, rate_limit::Bucket, worker::WorkerPool};
+use crate::db::prelude::{Keyword, KeywordRepository};
+use crate::db::redis::redis_pool::redis_pool;
+use crate::db::store::ScoreStore;
+use crate::irc::{
+    chat_log::ChatLogger, connection::ConnectionSupervisor, connection::IrcEndpoint,
+    hydrate::HydrationQueue, membership::RestoredMembership, milestone::MilestoneAnnouncer,
+    queue::Backpressure, rate_limit::Bucket, rate_limit::JoinScheduler, score_limit::ScoreLimiter,
+    tap::IrcTap, worker::KeywordHandler, worker::ScoreCounter, worker::WorkerPool,
+};
 
 pub async fn start(
     channels: Vec<String>,
     pool: &'static PgPool,
+    store: Arc<dyn ScoreStore>,
     worker_count: usize,
 ) -> ClientResult<IrcHandle> {
-    tracing::info!("starting up irc connection");
+    start_at(IrcEndpoint::default(), channels, pool, store, worker_count).await
+}
 
-    let (mut supervisor, conn_handle) = ConnectionSupervisor::new(channels);
+/// Starts the connection and worker pipeline against `endpoint` rather than Twitch, e.g. a
+/// `MockTwitchServer` in tests. Scores counted by the workers are recorded through `store`.
+pub async fn start_at(
+    endpoint: IrcEndpoint,
+    channels: Vec<String>,
+    pool: &'static PgPool,
+    store: Arc<dyn ScoreStore>,
+    worker_count: usize,
+) -> ClientResult<IrcHandle> {
+    tracing::info!(
+        server = endpoint.server,
+        port = endpoint.port,
+        "starting up irc connection"
+    );
 
-    let (msg_tx, msg_rx) = async_channel::bounded(256);
-    let (cmd_tx, cmd_rx) = mpsc::channel(64);
+    // channels joined before a restart are restored and rejoined first, at the configured rate
+    let mut redis_pool = redis_pool().await?.clone();
+    let restored = match membership::restore(&mut redis_pool, &channels).await {
+        Ok(restored) => restored,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to restore channel membership");
+            RestoredMembership {
+                channels: channels
+                    .iter()
+                    .map(|ch| membership::normalize(ch))
+                    .collect(),
+                ..Default::default()
+            }
+        }
+    };
+    let joins = Arc::new(JoinScheduler::new(rate_limit::join_rate().await));
+    let tap = IrcTap::new();
+
+    // chat is only logged to disk when `CHAT_LOG_DIR` is set
+    let chat_log = match chat_log::chat_log_config().await {
+        Some(config) => match ChatLogger::spawn(config) {
+            Ok((logger, _writer_handle)) => logger,
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to start chat log writer");
+                ChatLogger::disabled()
+            }
+        },
+        None => ChatLogger::disabled(),
+    };
+
+    // with several instances running, each only joins the channels it holds a lease for
+    let (supervisor, conn_handle) = ConnectionSupervisor::new(
+        restored,
+        Arc::clone(&joins),
+        redis_pool,
+        tap.clone(),
+        chat_log,
+    );
+    let mut supervisor = supervisor
+        .with_endpoint(endpoint)
+        .with_leases(coordination::lease_config().await);
+
+    // messages are only dropped if the connection itself falls behind: while the workers are
+    // behind (e.g. on a slow database), the connection waits for them rather than queueing more
+    let (msg_tx, msg_rx) = queue::bounded("irc_messages", 256, Backpressure::Block);
+    let (cmd_tx, cmd_rx) = queue::bounded("irc_commands", 64, Backpressure::Block);
     let (query_tx, query_rx) = mpsc::channel(32);
 
     // one permit per bucket, polls for an empty bucket every 500ms - if the bucket is empty, waits
     // an additional 1100s before refilling to ensure irc rate limits are adhered to
     let rate_limiter = Arc::new(Bucket::new(Duration::from_millis(1100), 1));
-    let _workers = WorkerPool::spawn(worker_count, msg_rx, cmd_tx.clone(), rate_limiter, pool);
 
+    // unknown/stale chatters are resolved against helix in the background, in batches
+    let (hydrator, _hydration_handle) = HydrationQueue::spawn(pool);
+
+    // keywords are only loaded on startup, so newly-added keywords are counted after a restart
+    let keywords: Arc<[Keyword]> = KeywordRepository::new(pool).get_all().await?.into();
+    tracing::info!(
+        keywords = ?keywords.iter().map(|k| &k.word).collect::<Vec<_>>(),
+        "tracking keywords"
+    );
+
+    // anti-spam limits are tracked per connection, so they reset on restart
+    let score_limiter = ScoreLimiter::new(score_limit::score_policy().await);
+    // milestones reached while counting are announced in chat and published for live consumers
+    let announcer = MilestoneAnnouncer::new(pool, cmd_tx.clone(), Arc::clone(&rate_limiter));
+    // digests compiled by this instance are announced in the chat of live channels
+    let _digest_handle =
+        digest::spawn_digest_announcements(pool, cmd_tx.clone(), Arc::clone(&rate_limiter));
+    // text keywords are matched according to `KEYWORD_MATCH_MODE`
+    let matcher = matcher::keyword_matcher(&keywords).await;
+    // keywords matched in chat and increments pushed through the api are counted the same way
+    let counter = Arc::new(ScoreCounter::new(
+        pool,
+        store,
+        hydrator,
+        score_limiter,
+        announcer,
+    ));
+    let keyword_handler = KeywordHandler::new(pool, matcher, Arc::clone(&counter));
+
+    let _workers = WorkerPool::spawn(
+        worker_count,
+        msg_rx,
+        cmd_tx.clone(),
+        rate_limiter,
+        keyword_handler,
+        pool,
+    );
+
+    // chat received over EventSub joins the same queue (see `api::webhook::chat`)
+    let eventsub_tx = msg_tx.clone();
     tokio::spawn(async move {
         supervisor.run(msg_tx, cmd_rx, query_rx).await;
     });
 
     Ok(IrcHandle {
         cmd_tx,
+        msg_tx: eventsub_tx,
         query_tx,
         connection: conn_handle,
+        joins,
+        tap,
+        counter: Some(counter),
     })
 }
 
@@ -61,13 +194,21 @@ pub fn idx(max: usize) -> usize {
 #[derive(Debug)]
 pub enum ReplyReason {
     BotCountQueried,
+    /// `!pissbattle` templates, with `{leader}`, `{trailer}` and `{margin}` placeholders
+    BattleWon,
+    /// `!pissbattle` templates, with `{leader}` and `{trailer}` placeholders
+    BattleTied,
+    BattleSelf,
 }
 
 impl ReplyReason {
     #[instrument(skip_all, ret(level = "info"))]
     pub fn get_reply(&self) -> &'static str {
         let reasons = match self {
-            ReplyReason::BotCountQueried => Self::BOT_COUNT_QUERY,
+            ReplyReason::BotCountQueried => &Self::BOT_COUNT_QUERY[..],
+            ReplyReason::BattleWon => &Self::BATTLE_WON[..],
+            ReplyReason::BattleTied => &Self::BATTLE_TIED[..],
+            ReplyReason::BattleSelf => &Self::BATTLE_SELF[..],
         };
 
         reasons[idx(reasons.len() - 1)]
@@ -81,4 +222,159 @@ impl ReplyReason {
         "you think youre clever dont you but you arent.",
         "dont you dare ask me for that information ever again.",
     ];
+
+    const BATTLE_WON: [&'static str; 5] = [
+        "{leader} is out-pissing {trailer} by {margin} here. embarrassing tbh.",
+        "{trailer} is {margin} behind {leader} here. step it up.",
+        "{leader} leads {trailer} by {margin}. {trailer}, are you even trying.",
+        "{leader} wins by {margin}. {trailer} should reflect on their choices.",
+        "{margin} points separate {leader} from {trailer}. it isnt close.",
+    ];
+
+    const BATTLE_TIED: [&'static str; 3] = [
+        "{leader} and {trailer} are dead even here. equally disappointing.",
+        "its a tie between {leader} and {trailer}. nobody wins, least of all me.",
+        "{leader} and {trailer} are tied. get a room.",
+    ];
+
+    const BATTLE_SELF: [&'static str; 3] = [
+        "you cant battle yourself. well you can but its sad to watch.",
+        "pick an opponent that isnt you.",
+        "you win. and lose. congratulations i guess.",
+    ];
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+    use crate::db::db_pool;
+    use crate::db::models::keyword::KeywordKind;
+    use crate::db::prelude::Repository;
+    use crate::db::prelude::{ChannelId, ChannelRepository, ChatterId, ChatterRepository};
+    use crate::db::redis::{get_stream_state, set_stream_state};
+    use crate::db::store::PostgresStore;
+    use crate::irc::mock::{MockTwitchServer, fixtures};
+
+    /// Unique per run, so that score events from earlier runs aren't counted.
+    fn msg_id(n: u32) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        format!("{:08x}-0000-4000-8000-{n:012}", nanos as u32)
+    }
+
+    async fn recorded(pool: &'static PgPool, msg_ids: &[String]) -> i64 {
+        sqlx::query_scalar("SELECT count(*) FROM score_event WHERE msg_id = ANY($1)")
+            .bind(msg_ids)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL and REDIS_URL pointing at scratch instances"]
+    async fn keywords_from_chat_are_recorded_in_postgres() {
+        let pool = db_pool().await.unwrap();
+        let mut redis = redis_pool().await.unwrap().clone();
+
+        let channel_id = ChannelRepository::new(pool)
+            .get_all_channel_ids()
+            .await
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(ChannelId)
+            .expect("at least one channel");
+        let channel = ChatterRepository::new(pool)
+            .get_by_id(&ChatterId::from(channel_id.clone()))
+            .await
+            .unwrap()
+            .expect("channel's chatter row");
+        assert!(
+            !ChannelRepository::new(pool)
+                .is_paused(&channel_id)
+                .await
+                .unwrap(),
+            "channel must not be paused"
+        );
+        let keyword = KeywordRepository::new(pool)
+            .get_all()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|keyword| keyword.kind == KeywordKind::Text)
+            .expect("at least one text keyword");
+
+        // only live channels are counted
+        let was_online = get_stream_state(&mut redis, &channel_id).await;
+        set_stream_state(&mut redis, &channel_id, true)
+            .await
+            .unwrap();
+
+        let server = MockTwitchServer::start().await.unwrap();
+        let store = Arc::new(PostgresStore::new(pool));
+        let _handle = start_at(
+            server.endpoint(),
+            vec![channel.login.clone()],
+            pool,
+            store,
+            1,
+        )
+        .await
+        .unwrap();
+        assert!(
+            server
+                .wait_for_join(&channel.login, Duration::from_secs(5))
+                .await
+        );
+
+        let message = |n, login: &str, text: &str| {
+            let mut message =
+                fixtures::ChatMessage::new(&channel.login, &channel_id.0, login, text);
+            message.msg_id = msg_id(n);
+            message
+        };
+        let mut blacklisted = message(1, "pipeline_bot", &keyword.word);
+        blacklisted.user_id = worker::ID_BLACKLIST[0].to_string();
+        let uncounted = message(2, "pipeline_chatter", "hello chat");
+        let counted = message(3, "pipeline_chatter", &keyword.word);
+        let replayed = counted.clone();
+        let last = message(4, "pipeline_chatter_2", &keyword.word);
+        let msg_ids = [
+            blacklisted.msg_id.clone(),
+            uncounted.msg_id.clone(),
+            counted.msg_id.clone(),
+            last.msg_id.clone(),
+        ];
+
+        // with a single worker, the earlier messages have been handled once the last is recorded
+        server.play([blacklisted, uncounted, counted, replayed, last]);
+        let mut attempts = 0;
+        while recorded(pool, &msg_ids[3..]).await == 0 && attempts < 50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            attempts += 1;
+        }
+
+        assert_eq!(recorded(pool, &msg_ids[3..]).await, 1);
+        assert_eq!(recorded(pool, &msg_ids[2..3]).await, 1);
+        assert_eq!(recorded(pool, &msg_ids[..2]).await, 0);
+
+        // the decrement trigger takes the test's score back off the totals
+        sqlx::query("DELETE FROM score_event WHERE msg_id = ANY($1)")
+            .bind(&msg_ids[..])
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM processed_message WHERE msg_id = ANY($1)")
+            .bind(&msg_ids[..])
+            .execute(pool)
+            .await
+            .unwrap();
+        set_stream_state(&mut redis, &channel_id, was_online)
+            .await
+            .unwrap();
+    }
 }
@@ -0,0 +1,93 @@
+//! Optional handling of score events for messages removed by moderators.
+//!
+//! Deleted messages (CLEARMSG) and timeouts/bans (CLEARCHAT) are always logged, but their score
+//! events are only flagged or rolled back when `SCORE_MODERATION_POLICY` is set. Only events
+//! earned within `SCORE_MODERATION_WINDOW_SECS` of the removal are affected, so an old ban doesn't
+//! wipe out a chatter's history.
+
+use sqlx::PgPool;
+use tracing::instrument;
+
+use crate::db::models::leaderboard::{ModerationAction, ModerationTarget};
+use crate::db::prelude::LeaderboardRepository;
+use crate::util::env::Var;
+use crate::var;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModerationPolicy {
+    pub action: ModerationAction,
+    pub window_secs: i64,
+}
+
+impl ModerationPolicy {
+    /// Returns `None` if the policy is disabled or invalid.
+    pub fn parse(action: &str, window_secs: &str) -> Option<Self> {
+        let action = match action.trim().to_lowercase().as_str() {
+            "flag" => ModerationAction::Flag,
+            "rollback" => ModerationAction::Rollback,
+            "" => return None,
+            other => {
+                tracing::warn!(policy = other, "unknown score moderation policy - ignoring");
+                return None;
+            }
+        };
+
+        let window_secs = match window_secs.trim().parse::<i64>() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                tracing::warn!(window_secs, "invalid score moderation window - ignoring");
+                return None;
+            }
+        };
+
+        Some(Self {
+            action,
+            window_secs,
+        })
+    }
+}
+
+pub async fn moderation_policy() -> Option<ModerationPolicy> {
+    let action = var!(Var::ScoreModerationPolicy).await.ok()?;
+    let window_secs = var!(Var::ScoreModerationWindowSecs).await.ok()?;
+
+    ModerationPolicy::parse(action, window_secs)
+}
+
+/// Applies the configured policy to the score events for a removed message or chatter.
+#[instrument(skip(pool))]
+pub async fn apply(pool: &'static PgPool, target: ModerationTarget) -> sqlx::Result<()> {
+    let Some(policy) = moderation_policy().await else {
+        return Ok(());
+    };
+
+    // the legacy Redis keys aren't touched; any difference shows up in the dual-write drift report
+    let affected = LeaderboardRepository::new(pool)
+        .moderate_score_events(&target, policy.action, policy.window_secs)
+        .await?;
+
+    if affected > 0 {
+        tracing::info!(?target, ?policy, affected, "moderated score events");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy_parses_action_and_window() {
+        assert_eq!(
+            ModerationPolicy::parse("Rollback", "120"),
+            Some(ModerationPolicy {
+                action: ModerationAction::Rollback,
+                window_secs: 120,
+            })
+        );
+        assert_eq!(ModerationPolicy::parse("", "300"), None);
+        assert_eq!(ModerationPolicy::parse("delete", "300"), None);
+        assert_eq!(ModerationPolicy::parse("flag", "-1"), None);
+    }
+}
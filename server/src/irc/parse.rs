@@ -1,11 +1,19 @@
 //! Pure functions for parsing and transforming IRC `Message` data into domain types
 
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::DateTime;
+use irc::proto::message::Tag;
 use irc::proto::{Command, Response};
 use tracing::instrument;
 
+use crate::db::models::IdError;
 use crate::irc::{
     UserNoticeType,
-    commands::{IncomingMessage, IrcTags},
+    commands::{Emote, IncomingMessage, IrcTags, TagError},
+    message::ChatMessage,
+    permission::{PermissionLevel, parse_badges},
 };
 
 /// This recieves the message before `parse_incoming`; we want this information to ensure
@@ -22,13 +30,16 @@ pub fn is_pong(msg: &irc::proto::Message) -> bool {
 pub fn parse_incoming(msg: &irc::proto::Message) -> Option<IncomingMessage> {
     match &msg.command {
         Command::PRIVMSG(channel, content) => {
-            let tags = parse_tags(msg, channel);
+            let tags = match parse_tags(msg, channel) {
+                Ok(tags) => tags,
+                Err(e) => {
+                    tracing::warn!(error = %e, channel, tags = ?msg.tags, "rejected PRIVMSG tags");
+                    return None;
+                }
+            };
             let message = content.to_string();
 
-            Some(IncomingMessage::Privmsg {
-                tags,
-                text: message,
-            })
+            Some(IncomingMessage::Privmsg(ChatMessage::from((tags, message))))
         }
 
         Command::PING(_s1, _) | Command::PONG(_s1, _) => None,
@@ -102,12 +113,47 @@ pub fn parse_incoming(msg: &irc::proto::Message) -> Option<IncomingMessage> {
         // NOTE first item in `content` vector is always channel_name (source: made it the fuck up)
         Command::Raw(command, content) => match command.to_lowercase().as_str() {
             "usernotice" => {
-                let (notice_type, message) = parse_usernotice_tags(msg, content);
-                tracing::info!(channel = content[0], ?notice_type, ?message, "USERNOTICE:");
+                let channel = content.first()?;
+                let tags = match parse_tags(msg, channel) {
+                    Ok(tags) => tags,
+                    Err(e) => {
+                        tracing::warn!(error = %e, channel, tags = ?msg.tags, "rejected USERNOTICE tags");
+                        return None;
+                    }
+                };
 
-                None
+                let (notice_type, text) = parse_usernotice_tags(msg, content);
+                tracing::info!(channel, ?notice_type, ?text, "USERNOTICE:");
+
+                Some(IncomingMessage::Usernotice {
+                    tags,
+                    notice_type,
+                    text,
+                })
             }
 
+            "clearchat" => match parse_clearchat(msg, content) {
+                Ok(clear) => {
+                    tracing::info!(?clear, "CLEARCHAT");
+                    Some(clear)
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, tags = ?msg.tags, "rejected CLEARCHAT tags");
+                    None
+                }
+            },
+
+            "clearmsg" => match parse_clearmsg(msg, content) {
+                Ok(clear) => {
+                    tracing::info!(?clear, "CLEARMSG");
+                    Some(clear)
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, tags = ?msg.tags, "rejected CLEARMSG tags");
+                    None
+                }
+            },
+
             _ => {
                 tracing::debug!(
                     command = ?msg.command,
@@ -132,23 +178,43 @@ pub fn parse_incoming(msg: &irc::proto::Message) -> Option<IncomingMessage> {
     }
 }
 #[instrument(level = "trace")]
-pub fn parse_tags(msg: &irc::proto::Message, channel: &str) -> IrcTags {
-    let mut result = IrcTags {
-        channel_name: channel.rsplit('#').next().unwrap_or("UNKNOWN").to_string(),
-        ..Default::default()
-    };
-
-    for tag in msg.tags.clone().unwrap_or_default() {
-        match (tag.0.as_str(), tag.1) {
-            ("room-id", Some(room_id)) => result.channel_id = room_id,
-            ("source-room-id", Some(source_room_id)) => result.source_channel_id = source_room_id,
-            ("display-name", Some(name)) => {
-                result.user_login = name.to_lowercase();
-                result.display_name = name;
+pub fn parse_tags(msg: &irc::proto::Message, channel: &str) -> Result<IrcTags, TagError> {
+    let mut user_id = None;
+    let mut channel_id = None;
+    let mut source_channel_id = None;
+    let mut login = None;
+    let mut display_name = String::new();
+    let mut color = String::new();
+    let mut msg_id = String::new();
+    let mut emotes = Vec::new();
+    let mut sent_at = None;
+    let mut badges = Vec::new();
+    let mut moderator = false;
+
+    // tags are borrowed, and only the values that are kept are copied out
+    for Tag(key, value) in msg.tags.iter().flatten() {
+        match (key.as_str(), value.as_deref()) {
+            ("room-id", Some(room_id)) => channel_id = Some(parse_id("room-id", room_id)?),
+            // twitch sends an empty `source-room-id` outside of shared chat sessions
+            ("source-room-id", Some(source_room_id)) if !source_room_id.is_empty() => {
+                source_channel_id = Some(parse_id("source-room-id", source_room_id)?)
+            }
+            ("display-name", Some(name)) => display_name = name.to_string(),
+            // only sent with USERNOTICEs, whose prefix is the server rather than the chatter
+            ("login", Some(l)) => login = Some(l.to_string()),
+            ("user-id", Some(id)) => user_id = Some(parse_id("user-id", id)?),
+            ("color", Some(c)) => color = c.to_string(),
+            ("id", Some(id)) => msg_id = id.to_string(),
+            ("emotes", Some(e)) => emotes = parse_emotes(e),
+            ("tmi-sent-ts", Some(ts)) => {
+                sent_at = ts
+                    .parse()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_millis)
+                    .map(|ts| ts.naive_utc())
             }
-            ("user-id", Some(user_id)) => result.user_id = user_id,
-            ("color", Some(color)) => result.color = color,
-            ("id", Some(msg_id)) => result.msg_id = msg_id,
+            ("mod", Some(m)) => moderator = m == "1",
+            ("badges", Some(b)) => badges = parse_badges(b),
             _ => (),
         }
     }
@@ -157,47 +223,157 @@ pub fn parse_tags(msg: &irc::proto::Message, channel: &str) -> IrcTags {
     //     result.channel_id = result.source_channel_id.clone();
     // }
 
-    result
+    // the `mod` tag covers moderators whose badge has been replaced by another (e.g. staff)
+    let mut permission = PermissionLevel::from_badges(&badges);
+    if moderator {
+        permission = permission.max(PermissionLevel::Moderator);
+    }
+
+    // a PRIVMSG's prefix is `<login>!<login>@<login>.tmi.twitch.tv`. display names can differ
+    // from logins entirely (e.g. localized names), so they're only a last resort - chatters stored
+    // with one are corrected when they're hydrated from helix
+    let user_login = login
+        .or_else(|| match &msg.prefix {
+            Some(irc::proto::Prefix::Nickname(nick, _, _)) => Some(nick.to_lowercase()),
+            _ => None,
+        })
+        .unwrap_or_else(|| display_name.to_lowercase());
+
+    Ok(IrcTags {
+        user_id: user_id.ok_or(TagError::Missing("user-id"))?,
+        user_login,
+        display_name,
+        color,
+        channel_name: channel.rsplit('#').next().unwrap_or("UNKNOWN").to_string(),
+        channel_id: channel_id.ok_or(TagError::Missing("room-id"))?,
+        source_channel_id,
+        msg_id,
+        emotes,
+        sent_at,
+        badges,
+        permission,
+    })
+}
+
+/// Parses the `emotes` tag (e.g. `25:0-4,12-16/1902:6-10`) into one entry per use, ordered by
+/// position. Malformed ranges are skipped rather than rejecting the message.
+fn parse_emotes(value: &str) -> Vec<Emote> {
+    let mut emotes = value
+        .split('/')
+        .filter_map(|emote| emote.split_once(':'))
+        .flat_map(|(id, ranges)| {
+            ranges.split(',').filter_map(move |range| {
+                let (start, end) = range.split_once('-')?;
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start <= end).then(|| Emote {
+                    id: id.to_string(),
+                    start,
+                    end,
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    emotes.sort_by_key(|emote| emote.start);
+    emotes
+}
+
+fn parse_id<T>(tag: &'static str, value: &str) -> Result<T, TagError>
+where
+    T: FromStr<Err = IdError>,
+{
+    value
+        .parse()
+        .map_err(|source| TagError::InvalidId { tag, source })
+}
+
+/// Collects a message's tags into a map, skipping any without a value.
+pub(crate) fn tag_map(msg: &irc::proto::Message) -> HashMap<&str, &str> {
+    msg.tags
+        .iter()
+        .flatten()
+        .filter_map(|Tag(key, value)| Some((key.as_str(), value.as_deref()?)))
+        .collect()
 }
 
 #[instrument(level = "trace")]
 pub fn parse_usernotice_tags(
     msg: &irc::proto::Message,
-    content: &Vec<String>,
+    content: &[String],
 ) -> (UserNoticeType, Option<String>) {
-    let mut notice_type = UserNoticeType::Other(String::from(""));
-
-    for tag in msg.tags.clone().unwrap_or_default() {
-        match (tag.0.as_str(), tag.1) {
-            ("msg-id", Some(msg_id)) => {
-                match msg_id.as_str() {
-                    "resub" => notice_type = UserNoticeType::Resub,
-                    _ => notice_type = UserNoticeType::Other(msg_id.clone()),
-                    // "sub" => reason = UserNoticeType::Sub,
-                    // "raid" => reason = UserNoticeType::Raid,
-                    // "unraid" => reason = UserNoticeType::Unraid,
-                    // "subgift" => reason = UserNoticeType::SubGift,
-                    // "rewardgift" => reason = UserNoticeType::RewardGift,
-                    // "bitsbadgetier" => reason = UserNoticeType::BitsBadgeTier,
-                    // "submysterygift" => reason = UserNoticeType::SubMysteryGift,
-                    // "giftpaidupgrade" => reason = UserNoticeType::GiftPaidUpgrade,
-                    // "sharedchatnotice" => reason = UserNoticeType::SharedChatNotice,
-                    // "anongiftpaidupgrade" => reason = UserNoticeType::AnonGiftPaidUpgrade,
-                }
-            }
-            _ => {}
-        }
-    }
+    let tags = tag_map(msg);
+
+    let param = |name: &str| tags.get(name).copied().unwrap_or_default().to_string();
+    let numeric_param = |name: &str| param(name).parse::<u32>().unwrap_or_default();
 
-    let message: Option<String> = if content.len() > 1 {
-        Some(content[1].clone())
-    } else {
-        None
+    let notice_type = match tags.get("msg-id").copied() {
+        Some("sub") => UserNoticeType::Sub {
+            plan: param("msg-param-sub-plan"),
+        },
+        Some("resub") => UserNoticeType::Resub {
+            cumulative_months: numeric_param("msg-param-cumulative-months"),
+            plan: param("msg-param-sub-plan"),
+        },
+        Some("raid") => UserNoticeType::Raid {
+            viewer_count: numeric_param("msg-param-viewerCount"),
+        },
+        Some("announcement") => UserNoticeType::Announcement,
+        Some(other) => UserNoticeType::Other(other.to_string()),
+        None => UserNoticeType::Other(String::new()),
     };
 
+    let message = content.get(1).filter(|m| !m.is_empty()).cloned();
+
     (notice_type, message)
 }
 
+#[instrument(level = "trace")]
+pub fn parse_clearchat(
+    msg: &irc::proto::Message,
+    content: &[String],
+) -> Result<IncomingMessage, TagError> {
+    let tags = tag_map(msg);
+    let channel = content.first().ok_or(TagError::Missing("channel"))?;
+
+    let channel_id = parse_id(
+        "room-id",
+        tags.get("room-id").ok_or(TagError::Missing("room-id"))?,
+    )?;
+    let target_user_id = tags
+        .get("target-user-id")
+        .map(|id| parse_id("target-user-id", id))
+        .transpose()?;
+
+    Ok(IncomingMessage::Clearchat {
+        channel_id,
+        channel_name: channel.trim_start_matches('#').to_string(),
+        target_user_id,
+        target_login: content.get(1).map(|login| login.to_lowercase()),
+        ban_duration: tags.get("ban-duration").and_then(|d| d.parse().ok()),
+    })
+}
+
+/// Twitch sends an empty `room-id` with CLEARMSG, so only the channel name is available; the
+/// deleted message is identified by `target-msg-id` alone.
+#[instrument(level = "trace")]
+pub fn parse_clearmsg(
+    msg: &irc::proto::Message,
+    content: &[String],
+) -> Result<IncomingMessage, TagError> {
+    let mut tags = tag_map(msg);
+    let channel = content.first().ok_or(TagError::Missing("channel"))?;
+
+    Ok(IncomingMessage::Clearmsg {
+        channel_name: channel.trim_start_matches('#').to_string(),
+        login: tags.remove("login").unwrap_or_default().to_string(),
+        target_msg_id: tags
+            .remove("target-msg-id")
+            .filter(|id| !id.is_empty())
+            .ok_or(TagError::Missing("target-msg-id"))?
+            .to_string(),
+    })
+}
+
 #[instrument(skip_all, level = "trace")]
 pub fn format_username(msg_parts: Vec<&str>) -> String {
     if msg_parts.len() != 1 {
@@ -219,7 +395,6 @@ pub fn is_counter_user(msg: &irc::proto::Message, counter_user: &str) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
-    use irc::proto::message::Tag;
     use irc::proto::{Command, Message, Prefix};
 
     fn make_privmsg(channel: &str, text: &str, tags: Vec<Tag>) -> Message {
@@ -259,34 +434,87 @@ mod test {
     #[test]
     fn parse_tags_extracts_all_fields() {
         let msg = make_privmsg("#testchannel", "test", standard_tags());
-        let tags = parse_tags(&msg, "#testchannel");
+        let tags = parse_tags(&msg, "#testchannel").unwrap();
 
         assert_eq!(tags.channel_name, "testchannel");
-        assert_eq!(tags.channel_id, "123456789");
-        assert_eq!(tags.user_id, "123456789");
+        assert_eq!(tags.channel_id.0, "123456789");
+        assert_eq!(tags.user_id.0, "123456789");
+        assert_eq!(tags.source_channel_id, None);
         assert_eq!(tags.color, "#0000FF");
         assert_eq!(tags.msg_id, "example-message-uuid");
+        assert_eq!(tags.permission, PermissionLevel::Broadcaster);
+        assert_eq!(
+            tags.emotes,
+            vec![Emote {
+                id: "62835".into(),
+                start: 0,
+                end: 10
+            }]
+        );
     }
 
     #[test]
-    fn parse_tags_handles_missing_tags() {
+    fn parse_emotes_orders_uses_and_skips_malformed_ranges() {
+        let emote = |id: &str, start, end| Emote {
+            id: id.into(),
+            start,
+            end,
+        };
+
+        assert_eq!(
+            parse_emotes("25:0-4,12-16/1902:6-10/bad:x-1,9-3"),
+            vec![emote("25", 0, 4), emote("1902", 6, 10), emote("25", 12, 16)]
+        );
+        assert!(parse_emotes("").is_empty());
+    }
+
+    #[test]
+    fn parse_tags_rejects_missing_ids() {
         let msg = make_privmsg("#testchannel", "test", vec![]);
-        let tags = parse_tags(&msg, "#testchannel");
+        let err = parse_tags(&msg, "#testchannel").unwrap_err();
 
-        assert_eq!(tags.channel_name, "testchannel");
-        assert_eq!(tags.user_id, "");
-        assert_eq!(tags.channel_id, "");
+        assert_eq!(err, TagError::Missing("user-id"));
+        assert!(parse_incoming(&msg).is_none());
+    }
+
+    #[test]
+    fn parse_tags_rejects_invalid_ids() {
+        let mut tags = standard_tags();
+        tags.push(Tag("source-room-id".into(), Some("not-an-id".into())));
+
+        let msg = make_privmsg("#testchannel", "test", tags);
+        let err = parse_tags(&msg, "#testchannel").unwrap_err();
+
+        assert!(matches!(
+            err,
+            TagError::InvalidId {
+                tag: "source-room-id",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_tags_takes_the_login_from_the_prefix() {
+        let mut tags = standard_tags();
+        tags.retain(|Tag(key, _)| key != "display-name");
+        tags.push(Tag("display-name".into(), Some("ユーザー".into())));
+
+        let msg = make_privmsg("#testchannel", "hello", tags);
+        let tags = parse_tags(&msg, "#testchannel").unwrap();
+        assert_eq!(tags.user_login, "someuser");
+        assert_eq!(tags.display_name, "ユーザー");
     }
 
     #[test]
     fn parse_tags_strips_channel_prefix() {
-        let msg = make_privmsg("#testchannel", "test", vec![]);
-        let tags = parse_tags(&msg, "#testchannel");
+        let msg = make_privmsg("#testchannel", "test", standard_tags());
+        let tags = parse_tags(&msg, "#testchannel").unwrap();
 
         assert_eq!(tags.channel_name, "testchannel");
 
         // edge case: PROBABLY won't occur but who could say
-        let tags = parse_tags(&msg, "unprefixed");
+        let tags = parse_tags(&msg, "unprefixed").unwrap();
         assert_eq!(tags.channel_name, "unprefixed");
     }
 
@@ -297,10 +525,141 @@ mod test {
 
         assert!(matches!(
             parsed,
-            Some(IncomingMessage::Privmsg { ref text, .. }) if text == "test"
+            Some(IncomingMessage::Privmsg(ref message)) if message.text == "test"
         ));
     }
 
+    fn make_usernotice(text: Option<&str>, mut tags: Vec<Tag>) -> Message {
+        tags.extend(standard_tags());
+        tags.push(Tag("login".into(), Some("example_login".into())));
+
+        let mut args = vec![String::from("#testchannel")];
+        args.extend(text.map(String::from));
+
+        Message {
+            tags: Some(tags),
+            prefix: Some(Prefix::ServerName("tmi.twitch.tv".into())),
+            command: Command::Raw("USERNOTICE".into(), args),
+        }
+    }
+
+    #[test]
+    fn parse_incoming_returns_resub_with_message() {
+        let msg = make_usernotice(
+            Some("piss"),
+            vec![
+                Tag("msg-id".into(), Some("resub".into())),
+                Tag("msg-param-cumulative-months".into(), Some("12".into())),
+                Tag("msg-param-sub-plan".into(), Some("1000".into())),
+            ],
+        );
+
+        let Some(IncomingMessage::Usernotice {
+            tags,
+            notice_type,
+            text,
+        }) = parse_incoming(&msg)
+        else {
+            panic!("expected a USERNOTICE");
+        };
+
+        assert_eq!(tags.user_login, "example_login");
+        assert_eq!(tags.channel_name, "testchannel");
+        assert_eq!(
+            notice_type,
+            UserNoticeType::Resub {
+                cumulative_months: 12,
+                plan: String::from("1000"),
+            }
+        );
+        assert!(notice_type.has_chatter_message());
+        assert_eq!(text.as_deref(), Some("piss"));
+    }
+
+    #[test]
+    fn parse_incoming_returns_raid() {
+        let msg = make_usernotice(
+            None,
+            vec![
+                Tag("msg-id".into(), Some("raid".into())),
+                Tag("msg-param-viewerCount".into(), Some("42".into())),
+            ],
+        );
+
+        let Some(IncomingMessage::Usernotice {
+            notice_type, text, ..
+        }) = parse_incoming(&msg)
+        else {
+            panic!("expected a USERNOTICE");
+        };
+
+        assert_eq!(notice_type, UserNoticeType::Raid { viewer_count: 42 });
+        assert!(!notice_type.has_chatter_message());
+        assert_eq!(text, None);
+    }
+
+    #[test]
+    fn parse_incoming_returns_clearchat_timeout() {
+        let msg = Message {
+            tags: Some(vec![
+                Tag("ban-duration".into(), Some("600".into())),
+                Tag("room-id".into(), Some("12345678".into())),
+                Tag("target-user-id".into(), Some("87654321".into())),
+            ]),
+            prefix: Some(Prefix::ServerName("tmi.twitch.tv".into())),
+            command: Command::Raw(
+                "CLEARCHAT".into(),
+                vec!["#testchannel".into(), "Example_Login".into()],
+            ),
+        };
+
+        let Some(IncomingMessage::Clearchat {
+            channel_id,
+            channel_name,
+            target_user_id,
+            target_login,
+            ban_duration,
+        }) = parse_incoming(&msg)
+        else {
+            panic!("expected a CLEARCHAT");
+        };
+
+        assert_eq!(channel_id.0, "12345678");
+        assert_eq!(channel_name, "testchannel");
+        assert_eq!(target_user_id.map(|id| id.0).as_deref(), Some("87654321"));
+        assert_eq!(target_login.as_deref(), Some("example_login"));
+        assert_eq!(ban_duration, Some(600));
+    }
+
+    #[test]
+    fn parse_incoming_returns_clearmsg_without_room_id() {
+        let msg = Message {
+            tags: Some(vec![
+                Tag("login".into(), Some("example_login".into())),
+                Tag("room-id".into(), Some("".into())),
+                Tag("target-msg-id".into(), Some("abc-123-def".into())),
+            ]),
+            prefix: Some(Prefix::ServerName("tmi.twitch.tv".into())),
+            command: Command::Raw(
+                "CLEARMSG".into(),
+                vec!["#testchannel".into(), "piss".into()],
+            ),
+        };
+
+        let Some(IncomingMessage::Clearmsg {
+            channel_name,
+            login,
+            target_msg_id,
+        }) = parse_incoming(&msg)
+        else {
+            panic!("expected a CLEARMSG");
+        };
+
+        assert_eq!(channel_name, "testchannel");
+        assert_eq!(login, "example_login");
+        assert_eq!(target_msg_id, "abc-123-def");
+    }
+
     #[test]
     fn parse_incoming_ignores_ping() {
         let msg = Message {
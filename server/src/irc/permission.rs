@@ -0,0 +1,78 @@
+//! Chat badges, and the permission level they give a chatter in the channel.
+
+/// A single chat badge, e.g. `subscriber/12` from the IRC `badges` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Badge {
+    /// e.g. `broadcaster`, `moderator`, `vip` or `subscriber`
+    pub set_id: String,
+    /// For subscriber badges, the tier's month threshold
+    pub version: String,
+}
+
+/// What a chatter is allowed to do in a channel, from least to most privileged.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PermissionLevel {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl PermissionLevel {
+    /// The highest level given by any of a chatter's badges.
+    pub fn from_badges(badges: &[Badge]) -> Self {
+        badges
+            .iter()
+            .map(|badge| match badge.set_id.as_str() {
+                "broadcaster" => Self::Broadcaster,
+                // staff badges don't make someone a moderator of the channel
+                "moderator" => Self::Moderator,
+                "vip" => Self::Vip,
+                "subscriber" | "founder" => Self::Subscriber,
+                _ => Self::Everyone,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Parses the IRC `badges` tag (e.g. `broadcaster/1,subscriber/12`). Malformed badges are skipped.
+pub fn parse_badges(value: &str) -> Vec<Badge> {
+    value
+        .split(',')
+        .filter_map(|badge| {
+            let (set_id, version) = badge.split_once('/')?;
+            (!set_id.is_empty()).then(|| Badge {
+                set_id: set_id.to_string(),
+                version: version.to_string(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn the_highest_badge_sets_the_level() {
+        let badges = parse_badges("vip/1,subscriber/24,glhf-pledge/1");
+        assert_eq!(badges.len(), 3);
+        assert_eq!(badges[1].version, "24");
+        assert_eq!(PermissionLevel::from_badges(&badges), PermissionLevel::Vip);
+
+        let badges = parse_badges("broadcaster/1,moderator/1");
+        assert_eq!(
+            PermissionLevel::from_badges(&badges),
+            PermissionLevel::Broadcaster
+        );
+
+        assert_eq!(
+            PermissionLevel::from_badges(&parse_badges("")),
+            PermissionLevel::Everyone
+        );
+        assert!(PermissionLevel::Moderator > PermissionLevel::Vip);
+    }
+}
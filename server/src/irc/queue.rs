@@ -0,0 +1,181 @@
+//! Bounded queues between the stages of the message pipeline.
+//!
+//! Every queue has a fixed capacity and an explicit policy for when it fills up: queues feeding
+//! the score pipeline block their sender, slowing the connection down to the rate messages can be
+//! counted, while queues feeding best-effort consumers (such as the chat log) drop their oldest
+//! items. Each queue's depth is reported as the `irc_queue_depth` gauge, and items dropped to make
+//! room are counted by `irc_queue_dropped_total`, both labelled by `queue`.
+
+use std::fmt;
+
+use async_channel::{Receiver, RecvError, SendError, TryRecvError, TrySendError};
+
+/// What a queue does when it's full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Senders wait for room
+    Block,
+    /// The oldest item is dropped to make room
+    DropOldest,
+}
+
+pub fn bounded<T>(
+    name: &'static str,
+    capacity: usize,
+    policy: Backpressure,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    let (tx, rx) = async_channel::bounded(capacity);
+    report_depth(name, 0);
+
+    (QueueSender { name, policy, tx }, QueueReceiver { name, rx })
+}
+
+pub struct QueueSender<T> {
+    name: &'static str,
+    policy: Backpressure,
+    tx: async_channel::Sender<T>,
+}
+
+impl<T> QueueSender<T> {
+    /// Queues an item, waiting for room if the queue blocks.
+    pub async fn send(&self, item: T) -> Result<(), SendError<T>> {
+        match self.policy {
+            Backpressure::Block => self.tx.send(item).await?,
+            Backpressure::DropOldest => self.displace(item)?,
+        }
+
+        report_depth(self.name, self.tx.len());
+        Ok(())
+    }
+
+    /// Queues an item without waiting; a full blocking queue rejects the item instead.
+    pub fn send_now(&self, item: T) -> Result<(), TrySendError<T>> {
+        match self.policy {
+            Backpressure::Block => self.tx.try_send(item)?,
+            Backpressure::DropOldest => {
+                self.displace(item).map_err(|e| TrySendError::Closed(e.0))?
+            }
+        }
+
+        report_depth(self.name, self.tx.len());
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tx.is_empty()
+    }
+
+    fn displace(&self, item: T) -> Result<(), SendError<T>> {
+        if self.tx.force_send(item)?.is_some() {
+            metrics::counter!("irc_queue_dropped_total", "queue" => self.name).increment(1);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct QueueReceiver<T> {
+    name: &'static str,
+    rx: Receiver<T>,
+}
+
+impl<T> QueueReceiver<T> {
+    pub async fn recv(&self) -> Result<T, RecvError> {
+        let item = self.rx.recv().await;
+        report_depth(self.name, self.rx.len());
+
+        item
+    }
+
+    /// Blocks the current thread; for consumers running outside of the runtime.
+    pub fn recv_blocking(&self) -> Result<T, RecvError> {
+        let item = self.rx.recv_blocking();
+        report_depth(self.name, self.rx.len());
+
+        item
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let item = self.rx.try_recv();
+        if item.is_ok() {
+            report_depth(self.name, self.rx.len());
+        }
+
+        item
+    }
+}
+
+// derived impls would require `T: Clone`/`T: Debug`
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            policy: self.policy,
+            tx: self.tx.clone(),
+        }
+    }
+}
+
+impl<T> Clone for QueueReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            rx: self.rx.clone(),
+        }
+    }
+}
+
+impl<T> fmt::Debug for QueueSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueSender")
+            .field("name", &self.name)
+            .field("policy", &self.policy)
+            .field("len", &self.tx.len())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for QueueReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueReceiver")
+            .field("name", &self.name)
+            .field("len", &self.rx.len())
+            .finish()
+    }
+}
+
+fn report_depth(name: &'static str, depth: usize) {
+    metrics::gauge!("irc_queue_depth", "queue" => name).set(depth as f64);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn full_queues_follow_their_policy() {
+        let (tx, rx) = bounded("test_drop", 2, Backpressure::DropOldest);
+        for i in 0..4 {
+            tx.send(i).await.unwrap();
+        }
+        assert_eq!(tx.len(), 2);
+        assert_eq!(rx.recv().await.unwrap(), 2);
+        assert_eq!(rx.recv().await.unwrap(), 3);
+
+        let (tx, rx) = bounded("test_block", 1, Backpressure::Block);
+        tx.send(0).await.unwrap();
+        assert!(matches!(tx.send_now(1), Err(TrySendError::Full(1))));
+
+        // the blocked send completes once there's room
+        let sender = tx.clone();
+        let blocked = tokio::spawn(async move { sender.send(1).await });
+        assert_eq!(rx.recv().await.unwrap(), 0);
+        blocked.await.unwrap().unwrap();
+        assert_eq!(rx.try_recv().unwrap(), 1);
+    }
+}
@@ -1,9 +1,24 @@
-use std::sync::Arc;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
+use serde::Serialize;
 use tokio::sync::{AcquireError, Semaphore};
-use tokio::time::{Duration, MissedTickBehavior, interval};
+use tokio::time::{Duration, Instant, MissedTickBehavior, interval};
 use tracing::instrument;
 
+use crate::util::env::Var;
+use crate::var;
+
+/// Twitch measures the join limit over a rolling 10 second window.
+pub const JOIN_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_JOIN_RATE: usize = 20;
+
+/// Twitch allows 3 whispers per second and 100 per minute; a whisper permit is refilled every
+/// `WHISPER_INTERVAL`, up to `WHISPER_BURST` at once.
+pub const WHISPER_INTERVAL: Duration = Duration::from_millis(600);
+pub const WHISPER_BURST: usize = 3;
+
 #[derive(Debug)]
 pub struct Bucket {
     sem: Arc<Semaphore>,
@@ -66,6 +81,95 @@ impl Drop for Bucket {
     }
 }
 
+/// Joins allowed per `JOIN_WINDOW`, from `IRC_JOIN_RATE`.
+pub async fn join_rate() -> usize {
+    match var!(Var::IrcJoinRate).await {
+        Ok(val) => match val.trim().parse::<usize>() {
+            Ok(rate) if rate > 0 => rate,
+            _ => {
+                tracing::warn!(val, "invalid irc join rate - using default");
+                DEFAULT_JOIN_RATE
+            }
+        },
+        Err(_) => DEFAULT_JOIN_RATE,
+    }
+}
+
+/// Enforces the authenticated join limit across every connection.
+///
+/// Unlike `Bucket`, this never waits: callers keep their own queue of channels and ask for a slot
+/// whenever they want to send a JOIN, so they can keep handling events while throttled. The
+/// scheduler outlives individual connections, so reconnecting doesn't reset the window.
+#[derive(Debug)]
+pub struct JoinScheduler {
+    limit: usize,
+    /// Send times of the joins within the current window
+    sent: Mutex<VecDeque<Instant>>,
+    queued: AtomicUsize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JoinStats {
+    pub limit: usize,
+    pub window_secs: u64,
+    /// Channels waiting for a join slot
+    pub queue_depth: usize,
+    /// Joins sent within the current window
+    pub recent_joins: usize,
+}
+
+impl JoinScheduler {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            sent: Mutex::new(VecDeque::new()),
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes a join slot if one is free, otherwise returns how long until the next one frees up.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().unwrap();
+        while sent
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= JOIN_WINDOW)
+        {
+            sent.pop_front();
+        }
+
+        if sent.len() < self.limit {
+            sent.push_back(now);
+            return Ok(());
+        }
+
+        let oldest = sent.front().copied().unwrap_or(now);
+        Err(JOIN_WINDOW.saturating_sub(now.duration_since(oldest)))
+    }
+
+    /// Records how many channels are waiting for a slot.
+    pub fn set_queued(&self, queued: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> JoinStats {
+        let recent_joins = self
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|at| at.elapsed() < JOIN_WINDOW)
+            .count();
+
+        JoinStats {
+            limit: self.limit,
+            window_secs: JOIN_WINDOW.as_secs(),
+            queue_depth: self.queued.load(Ordering::Relaxed),
+            recent_joins,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -78,6 +182,20 @@ mod test {
         assert!(acq);
     }
 
+    #[tokio::test(start_paused = true)]
+    async fn join_scheduler_enforces_window() {
+        let scheduler = JoinScheduler::new(2);
+
+        assert!(scheduler.try_acquire().is_ok());
+        tokio::time::advance(Duration::from_secs(4)).await;
+        assert!(scheduler.try_acquire().is_ok());
+        assert_eq!(scheduler.try_acquire(), Err(Duration::from_secs(6)));
+
+        tokio::time::advance(Duration::from_secs(6)).await;
+        assert!(scheduler.try_acquire().is_ok());
+        assert_eq!(scheduler.stats().recent_joins, 2);
+    }
+
     // #[tokio::test(start_paused = true)]
     // async fn acquire_succeeds_when_permit() {
     //     let limiter = Bucket::new(Duration::from_millis(1500), 5);
@@ -0,0 +1,241 @@
+//! Tracks each joined channel's chat modes, so that replies aren't sent where Twitch would drop
+//! them (or where they'd be unwelcome).
+//!
+//! Twitch sends every mode in a `ROOMSTATE` when a channel is joined, and after that only the modes
+//! that change. Replies are suppressed entirely in emote-only, followers-only and subscribers-only
+//! chats; in slow mode, only one reply is sent per slow interval and the rest are dropped. None of
+//! this applies in channels where the bot is a moderator (from its `USERSTATE`), as moderators
+//! aren't held to chat modes.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use irc::proto::{Command, Message};
+use serde::Serialize;
+
+use crate::db::prelude::ChannelId;
+use crate::irc::membership;
+use crate::irc::parse::tag_map;
+use crate::irc::permission::{PermissionLevel, parse_badges};
+
+static ROOM_STATES: LazyLock<RoomStates> = LazyLock::new(RoomStates::default);
+
+/// Retrieves a reference to the global `RoomStates`.
+pub fn room_states() -> &'static RoomStates {
+    &ROOM_STATES
+}
+
+/// A channel's chat modes, as of its last `ROOMSTATE`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RoomState {
+    pub emote_only: bool,
+    /// Minutes chatters must have followed for, or `None` while followers-only mode is off
+    pub followers_only: Option<u32>,
+    /// Seconds chatters must wait between messages; 0 while slow mode is off
+    pub slow: u32,
+    pub subs_only: bool,
+    /// The bot is one of the channel's moderators (or its broadcaster)
+    pub moderator: bool,
+    /// The channel's id, as given in its first `ROOMSTATE`
+    pub room_id: Option<ChannelId>,
+    #[serde(skip)]
+    last_reply: Option<Instant>,
+}
+
+impl RoomState {
+    /// Updates the modes present in a `ROOMSTATE`'s tags, leaving the rest as they were.
+    fn apply(&mut self, tags: &HashMap<&str, &str>) {
+        let flag = |name: &str| tags.get(name).map(|value| *value == "1");
+        let number = |name: &str| tags.get(name).and_then(|value| value.parse::<i64>().ok());
+
+        if let Some(room_id) = tags.get("room-id") {
+            self.room_id = Some(ChannelId(room_id.to_string()));
+        }
+        if let Some(emote_only) = flag("emote-only") {
+            self.emote_only = emote_only;
+        }
+        if let Some(subs_only) = flag("subs-only") {
+            self.subs_only = subs_only;
+        }
+        if let Some(minutes) = number("followers-only") {
+            // -1 turns followers-only mode off
+            self.followers_only = u32::try_from(minutes).ok();
+        }
+        if let Some(secs) = number("slow") {
+            self.slow = u32::try_from(secs).unwrap_or_default();
+        }
+    }
+
+    /// Whether a reply can be sent at `now`, recording it as sent if so; otherwise returns the mode
+    /// that's holding it back.
+    fn take_reply(&mut self, now: Instant) -> Result<(), &'static str> {
+        if !self.moderator {
+            if self.emote_only {
+                return Err("emote-only");
+            }
+            if self.subs_only {
+                return Err("subs-only");
+            }
+            if self.followers_only.is_some() {
+                return Err("followers-only");
+            }
+
+            let interval = Duration::from_secs(self.slow.into());
+            if self
+                .last_reply
+                .is_some_and(|last| now.saturating_duration_since(last) < interval)
+            {
+                return Err("slow");
+            }
+        }
+
+        self.last_reply = Some(now);
+        Ok(())
+    }
+}
+
+/// The `RoomState` of every joined channel, keyed by `#login`.
+#[derive(Debug, Default)]
+pub struct RoomStates {
+    rooms: Mutex<HashMap<String, RoomState>>,
+}
+
+impl RoomStates {
+    /// Updates the channel's state from a `ROOMSTATE` or the bot's own `USERSTATE`; any other
+    /// message is ignored.
+    pub fn observe(&self, msg: &Message) {
+        let Command::Raw(command, content) = &msg.command else {
+            return;
+        };
+        let Some(channel) = content.first() else {
+            return;
+        };
+
+        // every USERNOTICE, CLEARCHAT and CLEARMSG passes through here too
+        let roomstate = command.eq_ignore_ascii_case("roomstate");
+        if !roomstate && !command.eq_ignore_ascii_case("userstate") {
+            return;
+        }
+
+        let tags = tag_map(msg);
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+
+        if roomstate {
+            rooms
+                .entry(membership::normalize(channel))
+                .or_default()
+                .apply(&tags);
+        } else {
+            let badges = parse_badges(tags.get("badges").copied().unwrap_or_default());
+            let moderating = PermissionLevel::from_badges(&badges) >= PermissionLevel::Moderator;
+
+            rooms
+                .entry(membership::normalize(channel))
+                .or_default()
+                .moderator = moderating || tags.get("mod").is_some_and(|m| *m == "1");
+        }
+    }
+
+    /// The channel's id, once its `ROOMSTATE` has been received.
+    pub fn room_id(&self, channel: &str) -> Option<ChannelId> {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms
+            .get(&membership::normalize(channel))
+            .and_then(|room| room.room_id.clone())
+    }
+
+    pub fn get(&self, channel: &str) -> Option<RoomState> {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms.get(&membership::normalize(channel)).cloned()
+    }
+
+    /// Drops a channel's state once it's been parted.
+    pub fn forget(&self, channel: &str) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms.remove(&membership::normalize(channel));
+    }
+
+    /// Whether a reply can be sent to the channel at `now`; see `RoomState::take_reply`. Channels
+    /// without a known state are assumed to allow it.
+    pub fn take_reply(&self, channel: &str, now: Instant) -> Result<(), &'static str> {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        match rooms.get_mut(&membership::normalize(channel)) {
+            Some(room) => room.take_reply(now),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use irc::proto::message::Tag;
+
+    use super::*;
+
+    fn message(command: &str, tags: &[(&str, &str)]) -> Message {
+        Message {
+            tags: Some(
+                tags.iter()
+                    .map(|(name, value)| Tag(name.to_string(), Some(value.to_string())))
+                    .collect(),
+            ),
+            prefix: None,
+            command: Command::Raw(command.into(), vec!["#TestChannel".into()]),
+        }
+    }
+
+    #[test]
+    fn partial_roomstates_only_change_their_modes() {
+        let rooms = RoomStates::default();
+        rooms.observe(&message(
+            "ROOMSTATE",
+            &[
+                ("room-id", "123456789"),
+                ("emote-only", "0"),
+                ("followers-only", "10"),
+                ("slow", "0"),
+                ("subs-only", "0"),
+            ],
+        ));
+        rooms.observe(&message("ROOMSTATE", &[("followers-only", "-1")]));
+        rooms.observe(&message("ROOMSTATE", &[("slow", "30")]));
+
+        let room = rooms.get("testchannel").unwrap();
+        assert_eq!(room.followers_only, None);
+        assert_eq!(room.slow, 30);
+        assert!(!room.emote_only && !room.subs_only);
+        assert_eq!(rooms.room_id("#testchannel"), room.room_id);
+        assert_eq!(room.room_id, Some(ChannelId(String::from("123456789"))));
+    }
+
+    #[test]
+    fn replies_follow_the_chat_modes_unless_moderating() {
+        let rooms = RoomStates::default();
+        let now = Instant::now();
+        assert_eq!(rooms.take_reply("#unknown", now), Ok(()));
+
+        rooms.observe(&message("ROOMSTATE", &[("slow", "30")]));
+        assert_eq!(rooms.take_reply("testchannel", now), Ok(()));
+        assert_eq!(
+            rooms.take_reply("testchannel", now + Duration::from_secs(10)),
+            Err("slow")
+        );
+        assert_eq!(
+            rooms.take_reply("testchannel", now + Duration::from_secs(30)),
+            Ok(())
+        );
+
+        rooms.observe(&message("ROOMSTATE", &[("emote-only", "1")]));
+        assert_eq!(
+            rooms.take_reply("testchannel", now + Duration::from_secs(60)),
+            Err("emote-only")
+        );
+
+        rooms.observe(&message("USERSTATE", &[("mod", "1")]));
+        assert_eq!(
+            rooms.take_reply("testchannel", now + Duration::from_secs(61)),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Dispatches incoming IRC events to the handlers that are interested in them.
+//!
+//! Each handler declares the event kinds (and optionally channels) it handles when it's
+//! registered. For every event, the interested handlers run concurrently in their own tasks, each
+//! with its own timeout - so a handler that errors, hangs or panics is logged and skipped without
+//! affecting the others. Outcomes and durations are recorded per handler as
+//! `irc_handler_events_total` and `irc_handler_duration_seconds`.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use tracing::{Instrument, instrument};
+
+use crate::irc::commands::{EventKind, IncomingMessage};
+use crate::irc::error::ClientResult;
+
+const DEFAULT_HANDLER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[async_trait::async_trait]
+pub trait EventHandler: Send + Sync + 'static {
+    /// Used to label logs and metrics
+    fn name(&self) -> &'static str;
+
+    /// Only read once, when the handler is registered.
+    fn interest(&self) -> Interest;
+
+    fn timeout(&self) -> Duration {
+        DEFAULT_HANDLER_TIMEOUT
+    }
+
+    async fn handle(&self, event: &IncomingMessage) -> ClientResult<()>;
+}
+
+/// The events a handler is called for.
+#[derive(Debug, Clone, Default)]
+pub struct Interest {
+    kinds: HashSet<EventKind>,
+    /// `None` matches every channel
+    channels: Option<HashSet<String>>,
+}
+
+impl Interest {
+    pub fn kinds(kinds: &[EventKind]) -> Self {
+        Self {
+            kinds: kinds.iter().copied().collect(),
+            channels: None,
+        }
+    }
+
+    /// Limits the handler to events from the given channel logins.
+    #[allow(dead_code)]
+    pub fn in_channels<I, S>(mut self, channels: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let channels = channels
+            .into_iter()
+            .map(|ch| ch.as_ref().trim_start_matches('#').to_lowercase())
+            .collect();
+
+        self.channels = Some(channels);
+        self
+    }
+
+    fn matches(&self, event: &IncomingMessage) -> bool {
+        if !self.kinds.contains(&event.kind()) {
+            return false;
+        }
+
+        match (&self.channels, event.channel_name()) {
+            (None, _) => true,
+            (Some(channels), Some(channel)) => channels.contains(channel),
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Ok,
+    Error,
+    Timeout,
+    Panic,
+}
+
+impl Outcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Error => "error",
+            Self::Timeout => "timeout",
+            Self::Panic => "panic",
+        }
+    }
+}
+
+struct Registered {
+    handler: Arc<dyn EventHandler>,
+    interest: Interest,
+    timeout: Duration,
+}
+
+#[derive(Default)]
+pub struct EventRouter {
+    handlers: Vec<Registered>,
+}
+
+impl EventRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, handler: impl EventHandler) -> Self {
+        let interest = handler.interest();
+        let timeout = handler.timeout();
+        tracing::debug!(
+            handler = handler.name(),
+            ?interest,
+            ?timeout,
+            "registered event handler"
+        );
+
+        self.handlers.push(Registered {
+            handler: Arc::new(handler),
+            interest,
+            timeout,
+        });
+
+        self
+    }
+
+    /// Runs every interested handler for the event and waits for them all to finish.
+    #[instrument(skip_all, fields(kind = ?event.kind(), channel = event.channel_name()))]
+    pub async fn route(&self, event: IncomingMessage) {
+        // every handler ignores messages relayed from the other side of a shared chat session
+        if event.is_shared_from_elsewhere() {
+            tracing::debug!("discarding shared event: source_id != channel_id");
+            return;
+        }
+
+        let interested: Vec<_> = self
+            .handlers
+            .iter()
+            .filter(|registered| registered.interest.matches(&event))
+            .collect();
+
+        if interested.is_empty() {
+            tracing::info!(message = ?event, "received_unhandled_message");
+            return;
+        }
+
+        let event = Arc::new(event);
+        join_all(
+            interested
+                .into_iter()
+                .map(|registered| run_handler(registered, Arc::clone(&event))),
+        )
+        .await;
+    }
+}
+
+async fn run_handler(registered: &Registered, event: Arc<IncomingMessage>) {
+    let handler = Arc::clone(&registered.handler);
+    let name = handler.name();
+    let timeout = registered.timeout;
+    let started = Instant::now();
+
+    let task = tokio::spawn(
+        async move { tokio::time::timeout(timeout, handler.handle(&event)).await }
+            .instrument(tracing::info_span!("event_handler", handler = name)),
+    );
+
+    let outcome = match task.await {
+        Ok(Ok(Ok(()))) => Outcome::Ok,
+        Ok(Ok(Err(e))) => {
+            tracing::error!(handler = name, error = ?e, "event handler failed");
+            Outcome::Error
+        }
+        Ok(Err(_)) => {
+            tracing::warn!(handler = name, ?timeout, "event handler timed out");
+            Outcome::Timeout
+        }
+        Err(e) => {
+            tracing::error!(handler = name, error = ?e, "event handler panicked");
+            Outcome::Panic
+        }
+    };
+
+    metrics::counter!(
+        "irc_handler_events_total",
+        "handler" => name,
+        "outcome" => outcome.as_str()
+    )
+    .increment(1);
+    metrics::histogram!("irc_handler_duration_seconds", "handler" => name)
+        .record(started.elapsed().as_secs_f64());
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    enum Behavior {
+        Count,
+        Panic,
+        Hang,
+    }
+
+    struct TestHandler {
+        name: &'static str,
+        interest: Interest,
+        behavior: Behavior,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl TestHandler {
+        fn new(name: &'static str, interest: Interest, behavior: Behavior) -> Self {
+            Self {
+                name,
+                interest,
+                behavior,
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl EventHandler for TestHandler {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn interest(&self) -> Interest {
+            self.interest.clone()
+        }
+
+        fn timeout(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+
+        async fn handle(&self, _event: &IncomingMessage) -> ClientResult<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.behavior {
+                Behavior::Count => Ok(()),
+                Behavior::Panic => panic!("handler panic"),
+                Behavior::Hang => {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn clearmsg(channel: &str) -> IncomingMessage {
+        IncomingMessage::Clearmsg {
+            channel_name: channel.to_string(),
+            login: String::from("example_login"),
+            target_msg_id: String::from("abc-123-def"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failing_handlers_do_not_affect_others() {
+        let counter = TestHandler::new(
+            "counter",
+            Interest::kinds(&[EventKind::Clearmsg]),
+            Behavior::Count,
+        );
+        let panicking = TestHandler::new(
+            "panicking",
+            Interest::kinds(&[EventKind::Clearmsg]),
+            Behavior::Panic,
+        );
+        let hanging = TestHandler::new(
+            "hanging",
+            Interest::kinds(&[EventKind::Clearmsg]),
+            Behavior::Hang,
+        );
+        let calls = Arc::clone(&counter.calls);
+
+        let router = EventRouter::new()
+            .register(panicking)
+            .register(hanging)
+            .register(counter);
+
+        router.route(clearmsg("testchannel")).await;
+        router.route(clearmsg("testchannel")).await;
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn handlers_only_receive_events_they_are_interested_in() {
+        let privmsg_only = TestHandler::new(
+            "privmsg_only",
+            Interest::kinds(&[EventKind::Privmsg]),
+            Behavior::Count,
+        );
+        let single_channel = TestHandler::new(
+            "single_channel",
+            Interest::kinds(&[EventKind::Clearmsg]).in_channels(["#TestChannel"]),
+            Behavior::Count,
+        );
+        let privmsg_calls = Arc::clone(&privmsg_only.calls);
+        let channel_calls = Arc::clone(&single_channel.calls);
+
+        let router = EventRouter::new()
+            .register(privmsg_only)
+            .register(single_channel);
+
+        router.route(clearmsg("testchannel")).await;
+        router.route(clearmsg("otherchannel")).await;
+
+        assert_eq!(privmsg_calls.load(Ordering::SeqCst), 0);
+        assert_eq!(channel_calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,264 @@
+//! Anti-spam limits on how often a chatter can score in a channel.
+//!
+//! Both limits are off unless configured:
+//!
+//! - `SCORE_RATE_LIMIT_PER_MINUTE` caps the increments a chatter can earn in a single channel
+//!   within any rolling minute.
+//! - `SCORE_ONE_PER_MESSAGE` counts at most one increment per message, even if several keywords
+//!   match (or one keyword occurs several times, in channels that count occurrences).
+//!
+//! Keyword matches over either limit are recorded as suppressed rather than counted. Rate limit
+//! windows are only kept in memory (so they reset with the connection), and idle windows are
+//! swept every `DECAY_INTERVAL`.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant, MissedTickBehavior, interval};
+
+use crate::db::models::leaderboard::SuppressReason;
+use crate::db::prelude::{ChannelId, ChatterId, KeywordId};
+use crate::util::env::Var;
+use crate::var;
+
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+const DECAY_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ScorePolicy {
+    /// Increments allowed per chatter per channel within `RATE_LIMIT_WINDOW`
+    pub max_per_minute: Option<usize>,
+    pub one_per_message: bool,
+}
+
+impl ScorePolicy {
+    pub fn parse(max_per_minute: &str, one_per_message: &str) -> Self {
+        let max_per_minute = match max_per_minute.trim() {
+            "" => None,
+            val => match val.parse::<usize>() {
+                Ok(max) if max > 0 => Some(max),
+                _ => {
+                    tracing::warn!(val, "invalid score rate limit - ignoring");
+                    None
+                }
+            },
+        };
+
+        Self {
+            max_per_minute,
+            one_per_message: matches!(one_per_message.trim().to_lowercase().as_str(), "true" | "1"),
+        }
+    }
+}
+
+pub async fn score_policy() -> ScorePolicy {
+    let max_per_minute = var!(Var::ScoreRateLimitPerMinute).await.unwrap_or_default();
+    let one_per_message = var!(Var::ScoreOnePerMessage).await.unwrap_or_default();
+
+    ScorePolicy::parse(max_per_minute, one_per_message)
+}
+
+/// The keywords from a single message, split by whether they should be counted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Admission {
+    pub counted: Vec<KeywordId>,
+    pub suppressed: Vec<(KeywordId, SuppressReason)>,
+}
+
+type Windows = Arc<Mutex<HashMap<(ChannelId, ChatterId), VecDeque<Instant>>>>;
+
+#[derive(Debug)]
+pub struct ScoreLimiter {
+    policy: ScorePolicy,
+    /// Times of the increments each chatter earned per channel within the current window
+    windows: Windows,
+    decay: Option<JoinHandle<()>>,
+}
+
+impl ScoreLimiter {
+    pub fn new(policy: ScorePolicy) -> Self {
+        let windows = Windows::default();
+        let decay = policy.max_per_minute.map(|_| {
+            let windows = Arc::clone(&windows);
+            let mut decay_interval = interval(DECAY_INTERVAL);
+            decay_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            tokio::spawn(async move {
+                loop {
+                    decay_interval.tick().await;
+                    decay(&windows, Instant::now());
+                }
+            })
+        });
+
+        tracing::info!(?policy, "score limits");
+        Self {
+            policy,
+            windows,
+            decay,
+        }
+    }
+
+    /// Decides which of a message's matched keywords count towards the chatter's score.
+    pub fn admit(
+        &self,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+        matched: Vec<KeywordId>,
+    ) -> Admission {
+        self.admit_at(Instant::now(), channel_id, chatter_id, matched)
+    }
+
+    /// `admit`, as of `now`.
+    pub(crate) fn admit_at(
+        &self,
+        now: Instant,
+        channel_id: &ChannelId,
+        chatter_id: &ChatterId,
+        matched: Vec<KeywordId>,
+    ) -> Admission {
+        let mut admission = Admission::default();
+        let mut matched = matched.into_iter();
+
+        if self.policy.one_per_message {
+            admission.counted.extend(matched.next());
+            admission
+                .suppressed
+                .extend(matched.by_ref().map(|id| (id, SuppressReason::PerMessage)));
+        } else {
+            admission.counted.extend(matched);
+        }
+
+        let Some(max) = self.policy.max_per_minute else {
+            return admission;
+        };
+
+        let mut windows = self.windows.lock().unwrap();
+        let window = windows
+            .entry((channel_id.clone(), chatter_id.clone()))
+            .or_default();
+        expire(window, now);
+
+        let allowed = max
+            .saturating_sub(window.len())
+            .min(admission.counted.len());
+        let over = admission.counted.split_off(allowed);
+        window.extend(std::iter::repeat_n(now, allowed));
+        admission
+            .suppressed
+            .extend(over.into_iter().map(|id| (id, SuppressReason::RateLimit)));
+
+        admission
+    }
+}
+
+impl Drop for ScoreLimiter {
+    fn drop(&mut self) {
+        if let Some(decay) = &self.decay {
+            decay.abort();
+        }
+    }
+}
+
+fn expire(window: &mut VecDeque<Instant>, now: Instant) {
+    while window
+        .front()
+        .is_some_and(|at| now.duration_since(*at) >= RATE_LIMIT_WINDOW)
+    {
+        window.pop_front();
+    }
+}
+
+/// Drops the windows of chatters who haven't scored within the last `RATE_LIMIT_WINDOW`.
+fn decay(windows: &Windows, now: Instant) {
+    let mut windows = windows.lock().unwrap();
+    windows.retain(|_, window| {
+        expire(window, now);
+        !window.is_empty()
+    });
+
+    tracing::trace!(active = windows.len(), "decayed score limit windows");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ids(ids: &[i32]) -> Vec<KeywordId> {
+        ids.iter().map(|id| KeywordId(*id)).collect()
+    }
+
+    #[test]
+    fn policy_is_parsed_from_env_values() {
+        assert_eq!(ScorePolicy::parse("", ""), ScorePolicy::default());
+        assert_eq!(ScorePolicy::parse("0", "false"), ScorePolicy::default());
+        assert_eq!(
+            ScorePolicy::parse(" 5 ", "TRUE"),
+            ScorePolicy {
+                max_per_minute: Some(5),
+                one_per_message: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn increments_over_the_cap_are_suppressed_until_the_window_passes() {
+        let limiter = ScoreLimiter::new(ScorePolicy {
+            max_per_minute: Some(3),
+            one_per_message: false,
+        });
+        let (channel, chatter) = (
+            ChannelId::from("1".to_string()),
+            ChatterId::from("2".to_string()),
+        );
+        let start = Instant::now();
+
+        let first = limiter.admit_at(start, &channel, &chatter, ids(&[1, 2]));
+        assert_eq!(first.counted, ids(&[1, 2]));
+
+        let second = limiter.admit_at(start, &channel, &chatter, ids(&[1, 2]));
+        assert_eq!(second.counted, ids(&[1]));
+        assert_eq!(
+            second.suppressed,
+            vec![(KeywordId(2), SuppressReason::RateLimit)]
+        );
+
+        // other chatters have their own window
+        let other = ChatterId::from("3".to_string());
+        assert_eq!(
+            limiter.admit_at(start, &channel, &other, ids(&[1])).counted,
+            ids(&[1])
+        );
+
+        let later = start + RATE_LIMIT_WINDOW;
+        assert_eq!(
+            limiter
+                .admit_at(later, &channel, &chatter, ids(&[1]))
+                .counted,
+            ids(&[1])
+        );
+
+        decay(&limiter.windows, later + RATE_LIMIT_WINDOW);
+        assert!(limiter.windows.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_one_keyword_is_counted_per_message() {
+        let limiter = ScoreLimiter::new(ScorePolicy {
+            max_per_minute: None,
+            one_per_message: true,
+        });
+        let (channel, chatter) = (
+            ChannelId::from("1".to_string()),
+            ChatterId::from("2".to_string()),
+        );
+
+        let admission = limiter.admit(&channel, &chatter, ids(&[1, 2]));
+        assert_eq!(admission.counted, ids(&[1]));
+        assert_eq!(
+            admission.suppressed,
+            vec![(KeywordId(2), SuppressReason::PerMessage)]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Per-channel counting statistics for `!pissstats`: the channel's total, how many chatters have
+//! been counted there, its top chatter, today's count and the count over the last hour.
+//!
+//! Aggregating these over a channel's scores on every use of the command would be expensive, so a
+//! channel's stats are read from the database the first time they're asked for and then kept
+//! current by `increment_score`, which records every score counted in a tracked channel here (and
+//! reads the score's totals to do so). A channel's stats are reread once its day (in the channel's
+//! timezone) ends, which also picks up any score corrections made in the meantime.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex, MutexGuard};
+
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+use crate::db::models::milestone::MilestoneTotals;
+use crate::db::models::stats::{ChannelCountStats, MinuteCount};
+use crate::db::prelude::{ChannelId, ChatterId, StatsRepository};
+use crate::db::store::ScoreIncrement;
+
+/// The count rate is over this many of the most recent minutes.
+const RATE_WINDOW_MINUTES: i64 = 60;
+
+static STATS: LazyLock<StatsCache> = LazyLock::new(StatsCache::default);
+
+/// Retrieves a reference to the global `StatsCache`.
+pub fn channel_stats() -> &'static StatsCache {
+    &STATS
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopChatter {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub score: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelStats {
+    pub total: i64,
+    pub unique_chatters: i64,
+    pub top_chatter: Option<TopChatter>,
+    pub today: i64,
+    day_ends_at: NaiveDateTime,
+    /// Scores counted per minute since the epoch over the last hour, oldest first
+    minutes: VecDeque<(i64, i64)>,
+}
+
+impl ChannelStats {
+    fn new(stats: ChannelCountStats, minutes: Vec<MinuteCount>) -> Self {
+        let top_chatter = match (
+            stats.top_chatter_id,
+            stats.top_chatter_login,
+            stats.top_chatter_score,
+        ) {
+            (Some(chatter_id), Some(login), Some(score)) => Some(TopChatter {
+                chatter_id,
+                login,
+                score,
+            }),
+            _ => None,
+        };
+
+        Self {
+            total: stats.total,
+            unique_chatters: stats.unique_chatters,
+            top_chatter,
+            today: stats.today,
+            day_ends_at: stats.day_ends_at,
+            minutes: minutes.into_iter().map(|m| (m.minute, m.count)).collect(),
+        }
+    }
+
+    fn is_current(&self, now: NaiveDateTime) -> bool {
+        now < self.day_ends_at
+    }
+
+    /// Counts a score. Without the totals it was recorded with (e.g. it was dead-lettered), the
+    /// counted chatters and top chatter are left as they are until the stats are next reread.
+    fn record(
+        &mut self,
+        chatter_id: &ChatterId,
+        login: &str,
+        totals: Option<MilestoneTotals>,
+        now: NaiveDateTime,
+    ) {
+        self.today += 1;
+
+        let minute = minute(now);
+        match self.minutes.back_mut() {
+            Some((last, count)) if *last == minute => *count += 1,
+            _ => self.minutes.push_back((minute, 1)),
+        }
+        while self
+            .minutes
+            .front()
+            .is_some_and(|(m, _)| *m <= minute - RATE_WINDOW_MINUTES)
+        {
+            self.minutes.pop_front();
+        }
+
+        let Some(totals) = totals else {
+            self.total += 1;
+            return;
+        };
+
+        self.total = totals.channel_total;
+        if totals.chatter_score == 1 {
+            self.unique_chatters += 1;
+        }
+
+        // ties go to whoever got there first, as on the leaderboard
+        match &mut self.top_chatter {
+            Some(top) if &top.chatter_id == chatter_id => top.score = totals.chatter_score,
+            Some(top) if top.score >= totals.chatter_score => (),
+            top => {
+                *top = Some(TopChatter {
+                    chatter_id: chatter_id.clone(),
+                    login: login.to_string(),
+                    score: totals.chatter_score,
+                })
+            }
+        }
+    }
+
+    /// Scores counted over the last hour.
+    pub fn last_hour(&self, now: NaiveDateTime) -> i64 {
+        let minute = minute(now);
+        self.minutes
+            .iter()
+            .filter(|(m, _)| *m > minute - RATE_WINDOW_MINUTES)
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+fn minute(at: NaiveDateTime) -> i64 {
+    at.and_utc().timestamp().div_euclid(60)
+}
+
+/// The stats of each channel `!pissstats` has been used in.
+#[derive(Debug, Default)]
+pub struct StatsCache {
+    channels: Mutex<HashMap<ChannelId, ChannelStats>>,
+}
+
+impl StatsCache {
+    /// Whether the channel's stats are being kept, so its scores' totals are needed.
+    pub fn is_tracked(&self, channel_id: &ChannelId) -> bool {
+        self.lock().contains_key(channel_id)
+    }
+
+    /// Counts a score in its channel's stats, if they're being kept.
+    pub fn record(&self, score: &ScoreIncrement<'_>, totals: Option<MilestoneTotals>) {
+        let now = Utc::now().naive_utc();
+        let mut channels = self.lock();
+        if let Some(stats) = channels.get_mut(score.channel_id)
+            && stats.is_current(now)
+        {
+            stats.record(score.chatter_id, score.chatter_login, totals, now);
+        }
+    }
+
+    /// The channel's stats, read from the database if they aren't being kept yet or its day has
+    /// ended. Returns `None` for channels that aren't known.
+    ///
+    /// A score counted while the stats are being read may be missed until they're next reread.
+    pub async fn get(
+        &self,
+        pool: &'static Pool<Postgres>,
+        channel_id: &ChannelId,
+    ) -> sqlx::Result<Option<ChannelStats>> {
+        let now = Utc::now().naive_utc();
+        if let Some(stats) = self.lock().get(channel_id)
+            && stats.is_current(now)
+        {
+            return Ok(Some(stats.clone()));
+        }
+
+        let repo = StatsRepository::new(pool);
+        let Some(stats) = repo.get_channel_count_stats(channel_id).await? else {
+            return Ok(None);
+        };
+        let minutes = repo.get_recent_minute_counts(channel_id).await?;
+
+        let stats = ChannelStats::new(stats, minutes);
+        self.lock().insert(channel_id.clone(), stats.clone());
+        Ok(Some(stats))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<ChannelId, ChannelStats>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    fn at(hour: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, 18)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap()
+    }
+
+    fn totals(channel_total: i64, chatter_score: i64) -> Option<MilestoneTotals> {
+        Some(MilestoneTotals {
+            channel_total,
+            chatter_score,
+        })
+    }
+
+    #[test]
+    fn scores_update_counts_top_chatter_and_rate() {
+        let (leader, newcomer) = (ChatterId(String::from("1")), ChatterId(String::from("2")));
+        let mut stats = ChannelStats::new(
+            ChannelCountStats {
+                total: 10,
+                unique_chatters: 1,
+                top_chatter_id: Some(leader.clone()),
+                top_chatter_login: Some(String::from("leader")),
+                top_chatter_score: Some(10),
+                today: 2,
+                day_ends_at: at(0, 0) + Duration::days(1),
+            },
+            vec![MinuteCount {
+                minute: minute(at(11, 30)),
+                count: 2,
+            }],
+        );
+
+        stats.record(&newcomer, "newcomer", totals(11, 1), at(12, 0));
+        stats.record(&leader, "leader", totals(12, 11), at(12, 0));
+        assert_eq!(
+            (stats.total, stats.unique_chatters, stats.today),
+            (12, 2, 4)
+        );
+        assert_eq!(stats.top_chatter.as_ref().unwrap().score, 11);
+
+        // a score without totals still counts, but can't change who's on top
+        stats.record(&newcomer, "newcomer", None, at(12, 20));
+        assert_eq!(stats.total, 13);
+        assert_eq!(stats.top_chatter.as_ref().unwrap().login, "leader");
+
+        assert_eq!(stats.last_hour(at(12, 20)), 5);
+        // the scores from 11:30 have aged out
+        assert_eq!(stats.last_hour(at(12, 45)), 3);
+        assert!(!stats.is_current(at(0, 0) + Duration::days(1)));
+    }
+}
@@ -0,0 +1,172 @@
+//! Broadcasts raw IRC messages, along with their parsed form, so that parsing can be debugged
+//! against live traffic.
+//!
+//! Messages are only formatted while something is subscribed, so an idle tap costs a receiver
+//! count check per message. Subscribers that fall more than `TAP_CAPACITY` messages behind skip
+//! ahead rather than slowing down the connection.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use futures::Stream;
+use irc::proto::{Command, Message};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::irc::commands::IncomingMessage;
+use crate::irc::membership;
+
+const TAP_CAPACITY: usize = 256;
+
+/// Taps are closed after this long, so a forgotten session doesn't stay open indefinitely.
+pub const MAX_TAP_DURATION: Duration = Duration::from_secs(60 * 10);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TapEvent {
+    /// As `#login`; `None` for messages that aren't sent to a channel
+    pub channel: Option<String>,
+    /// Re-serialized from the received message, so may differ from the wire format in optional
+    /// details (e.g. a trailing parameter's leading `:`)
+    pub raw: String,
+    /// `None` for messages the workers ignore
+    pub parsed: Option<String>,
+    pub received_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TapQuery {
+    pub channel: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum TapItem {
+    Event(Arc<TapEvent>),
+    /// The subscriber fell behind and this many messages were skipped
+    Lagged(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct IrcTap {
+    tx: broadcast::Sender<Arc<TapEvent>>,
+}
+
+impl Default for IrcTap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IrcTap {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(TAP_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn publish(&self, msg: &Message, parsed: Option<&IncomingMessage>) {
+        if !self.is_active() {
+            return;
+        }
+
+        _ = self.tx.send(Arc::new(TapEvent {
+            channel: channel_of(msg),
+            raw: msg.to_string().trim_end().to_string(),
+            parsed: parsed.map(|p| format!("{p:?}")),
+            received_at: Utc::now().naive_utc(),
+        }));
+    }
+
+    /// Streams messages sent to a single channel until `max_duration` has passed.
+    pub fn subscribe(
+        &self,
+        channel: &str,
+        max_duration: Duration,
+    ) -> impl Stream<Item = TapItem> + Send + use<> {
+        let channel = membership::normalize(channel);
+        let deadline = tokio::time::Instant::now() + max_duration;
+        let rx = self.tx.subscribe();
+
+        futures::stream::unfold((rx, channel), move |(mut rx, channel)| async move {
+            loop {
+                let item = match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Err(_) | Ok(Err(RecvError::Closed)) => return None,
+                    Ok(Err(RecvError::Lagged(skipped))) => TapItem::Lagged(skipped),
+                    Ok(Ok(event)) if event.channel.as_deref() == Some(channel.as_str()) => {
+                        TapItem::Event(event)
+                    }
+                    Ok(Ok(_)) => continue,
+                };
+
+                return Some((item, (rx, channel)));
+            }
+        })
+    }
+}
+
+/// The channel a message was sent to, as `#login`.
+pub(crate) fn channel_of(msg: &Message) -> Option<String> {
+    let target = match &msg.command {
+        Command::PRIVMSG(target, _)
+        | Command::NOTICE(target, _)
+        | Command::JOIN(target, _, _)
+        | Command::PART(target, _) => target,
+        Command::Raw(_, args) => args.first()?,
+        _ => return None,
+    };
+
+    target
+        .starts_with('#')
+        .then(|| membership::normalize(target))
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+
+    fn message(raw: &str) -> Message {
+        raw.parse().unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribers_only_see_their_channel_until_the_deadline() {
+        let tap = IrcTap::new();
+        assert!(!tap.is_active());
+
+        let stream = tap.subscribe("Foo", Duration::from_secs(5));
+        assert!(tap.is_active());
+
+        tap.publish(&message(":a!a@a.tmi.twitch.tv PRIVMSG #bar :hi\r\n"), None);
+        tap.publish(
+            &message(":a!a@a.tmi.twitch.tv PRIVMSG #foo :hi there\r\n"),
+            None,
+        );
+        tap.publish(
+            &message("@msg-id=raid :tmi.twitch.tv USERNOTICE #foo\r\n"),
+            None,
+        );
+
+        let items: Vec<TapItem> = stream.collect().await;
+        let raw: Vec<&str> = items
+            .iter()
+            .map(|item| match item {
+                TapItem::Event(event) => event.raw.as_str(),
+                TapItem::Lagged(_) => panic!("unexpected lag"),
+            })
+            .collect();
+
+        assert_eq!(
+            raw,
+            [
+                ":a!a@a.tmi.twitch.tv PRIVMSG #foo :hi there",
+                "@msg-id=raid :tmi.twitch.tv USERNOTICE #foo",
+            ]
+        );
+    }
+}
@@ -1,32 +1,65 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use irc::proto::Message;
 use irc::proto::message::Tag;
+use serde::Serialize;
 use sqlx::PgPool;
 use tokio::sync::Mutex;
-use tokio::{sync::mpsc, task::JoinHandle};
+use tokio::task::JoinHandle;
 use tracing::instrument;
 
+use crate::api::auth::Actor;
+use crate::api::error::ApiError;
+use crate::api::handlers::admin::audit::Audit;
+use crate::api::handlers::admin::score::{self, ScoreCorrection};
+use crate::db::models::audit::AuditAction;
+use crate::db::models::channel::{ChannelCountConfig, ReplyMode};
+use crate::db::models::keyword::KeywordMatcher;
+use crate::db::models::leaderboard::{ModerationTarget, ScoreAdjustment, SuppressReason};
+use crate::db::models::milestone::MilestoneReached;
+use crate::db::models::rank::Contender;
+use crate::db::models::settings::ChannelSettings;
 use crate::db::prelude::{
-    ChannelId, ChannelRepository, Chatter, ChatterId, ChatterRepository, LeaderboardRepository,
-    Repository,
+    ChannelId, ChannelRepository, ChatterId, ChatterRepository, KeywordId, LeaderboardRepository,
+    RankRepository, Repository,
 };
 use crate::db::redis::get_stream_state;
 use crate::db::redis::redis_pool::redis_pool;
+use crate::db::store::{ScoreIncrement, ScoreStore};
 use crate::irc::ReplyReason;
-use crate::irc::commands::{IncomingMessage, IrcTags, OutgoingCommand};
+use crate::irc::commands::{EventKind, IncomingMessage, OutgoingCommand, UserNoticeType};
+use crate::irc::dedupe;
 use crate::irc::error::{ClientResult, ConnectionClientError};
+use crate::irc::events::increments;
+use crate::irc::hydrate::{HydrationQueue, stub_chatter};
+use crate::irc::message::ChatMessage;
+use crate::irc::milestone::{self, MilestoneAnnouncer};
+use crate::irc::moderation;
 use crate::irc::parse::format_username;
-use crate::irc::rate_limit::Bucket;
+use crate::irc::permission::PermissionLevel;
+use crate::irc::queue::{QueueReceiver, QueueSender};
+use crate::irc::rate_limit::{Bucket, WHISPER_BURST, WHISPER_INTERVAL};
+use crate::irc::router::{EventHandler, EventRouter, Interest};
+use crate::irc::score_limit::ScoreLimiter;
+use crate::irc::stats::channel_stats;
+use crate::util::availability::availability;
 use crate::util::channel::update_threshold_elapsed;
+use crate::util::env::Var;
 use crate::util::helix::Helix;
+use crate::util::milestones;
+use crate::util::settings::settings;
+use crate::util::template;
+use crate::var;
 
 const TRAILER_CHAR: char = '\u{180B}';
 const KEYWORD: &str = "piss";
 // pub const COUNTER_USER: &str = "pee_liker";
 pub const COUNTER_USER: &str = "ghhhuhgguh";
 
-const ID_BLACKLIST: [&str; 1] = [
+pub(crate) const ID_BLACKLIST: [&str; 1] = [
     // i am allowing the bots because its kind of funny
     // "19264788",   // Nightbot
     // "100135110",  // StreamElements
@@ -55,27 +88,41 @@ pub struct WorkerPool {
 impl WorkerPool {
     pub fn spawn(
         count: usize,
-        msg_rx: async_channel::Receiver<IncomingMessage>,
-        cmd_tx: mpsc::Sender<OutgoingCommand>,
+        msg_rx: QueueReceiver<IncomingMessage>,
+        cmd_tx: QueueSender<OutgoingCommand>,
         rate_limiter: Arc<Bucket>,
+        keyword_handler: KeywordHandler,
         pool: &'static PgPool,
     ) -> Self {
         let last_message = Arc::new(Mutex::new(LastMessage::default()));
+        let router = Arc::new(
+            EventRouter::new()
+                .register(CounterCommandHandler {
+                    pool,
+                    cmd_tx: cmd_tx.clone(),
+                    rate_limiter: Arc::clone(&rate_limiter),
+                    whisper_limiter: Bucket::new(WHISPER_INTERVAL, WHISPER_BURST),
+                    cooldowns: CommandCooldowns::default(),
+                    last_message: Arc::clone(&last_message),
+                })
+                .register(keyword_handler)
+                .register(RaidHandler {
+                    pool,
+                    cmd_tx,
+                    rate_limiter,
+                })
+                .register(ModerationHandler { pool }),
+        );
+
         let workers = (0..count)
             .map(|id| {
                 let rx = msg_rx.clone();
-                let tx = cmd_tx.clone();
-                let rate_limiter = Arc::clone(&rate_limiter);
-                let last_message = Arc::clone(&last_message);
+                let router = Arc::clone(&router);
 
                 tokio::spawn(async move {
                     tracing::info!(worker_id = id, "worker started");
                     while let Ok(msg) = rx.recv().await {
-                        if let Err(e) =
-                            handle_message(msg, &tx, &last_message, &rate_limiter, pool).await
-                        {
-                            tracing::error!(?e, worker_id = id, "worker error");
-                        }
+                        router.route(msg).await;
                     }
                 })
             })
@@ -89,135 +136,569 @@ impl WorkerPool {
 }
 
 #[instrument(skip(pool), err)]
-async fn is_whitelisted_channel(
+pub(crate) async fn reply_mode(
     pool: &'static PgPool,
-    channel_id: &str,
-) -> Result<bool, ConnectionClientError> {
-    let repo = ChannelRepository::new(pool);
-    let row = repo.get_reply_config(channel_id).await?;
+    channel_id: &ChannelId,
+) -> Result<ReplyMode, ConnectionClientError> {
+    Ok(channel_settings(pool, channel_id).await?.reply_mode)
+}
+
+/// The channel's current settings (see `util::settings`).
+pub(crate) async fn channel_settings(
+    pool: &'static PgPool,
+    channel_id: &ChannelId,
+) -> Result<ChannelSettings, ConnectionClientError> {
+    Ok(settings().get(pool, channel_id).await?)
+}
+
+/// Chat commands the bot replies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChatCommand {
+    /// `!pisscount [user]`
+    Count,
+    /// `!rank [user]`
+    Rank,
+    /// `!pissbattle <user>`
+    Battle,
+    /// `!adjust <user> <+N|-N|=N> [reason]`
+    Adjust,
+    /// `!pausecount`, which stops the channel's messages from being counted
+    Pause,
+    /// `!resumecount`
+    Resume,
+    /// `!pissstats`, the channel's counting stats
+    Stats,
+}
+
+impl ChatCommand {
+    fn parse(text: &str) -> Option<Self> {
+        if text.starts_with("!pisscount") {
+            return Some(Self::Count);
+        }
 
-    Ok(row.enabled)
+        match text.split(' ').next() {
+            Some("!rank") => Some(Self::Rank),
+            Some("!pissbattle") => Some(Self::Battle),
+            Some("!adjust") => Some(Self::Adjust),
+            Some("!pausecount") => Some(Self::Pause),
+            Some("!resumecount") => Some(Self::Resume),
+            Some("!pissstats") => Some(Self::Stats),
+            _ => None,
+        }
+    }
+
+    /// The lowest permission level a chatter needs to use the command; anyone else is ignored.
+    fn permission(&self) -> PermissionLevel {
+        match self {
+            Self::Count | Self::Rank | Self::Battle | Self::Stats => PermissionLevel::Everyone,
+            Self::Adjust | Self::Pause | Self::Resume => PermissionLevel::Moderator,
+        }
+    }
 }
 
-#[instrument(skip_all, err)]
-async fn handle_message(
-    msg: IncomingMessage,
-    cmd_tx: &mpsc::Sender<OutgoingCommand>,
-    last_message: &Arc<Mutex<LastMessage>>,
-    bucket: &Arc<Bucket>,
+/// Replies to chat command invocations in channels that have replies enabled, either in chat or
+/// by whisper depending on the channel's `ReplyMode`.
+struct CounterCommandHandler {
     pool: &'static PgPool,
-) -> Result<(), ConnectionClientError> {
-    let rate_limiter = bucket.clone();
-    match msg {
-        IncomingMessage::Privmsg { tags, text } => {
-            let channel = format!("{}.#{}", &tags.channel_id, &tags.channel_name);
-            let chatter = format!("{}.{}", &tags.user_id, &tags.user_login);
-
-            if &tags.source_channel_id != "" && &tags.channel_id != &tags.source_channel_id {
-                // TODO i still want to increment if the source is not a tracked channel
-                //  but i cant be bothered rn lowkey
-                tracing::debug!(
-                    tags.channel_id,
-                    tags.source_channel_id,
-                    text,
-                    "discarding shared msg: source_id != channel_id"
-                );
+    cmd_tx: QueueSender<OutgoingCommand>,
+    rate_limiter: Arc<Bucket>,
+    /// Whispers are limited separately from chat messages
+    whisper_limiter: Bucket,
+    cooldowns: CommandCooldowns,
+    last_message: Arc<Mutex<LastMessage>>,
+}
 
-                return Ok(());
-            }
+/// When each chatter last used a command in each channel, for the channels' command cooldowns.
+#[derive(Debug, Default)]
+pub(crate) struct CommandCooldowns {
+    last_used: std::sync::Mutex<HashMap<(ChannelId, ChatterId), Instant>>,
+}
 
-            tracing::info!(channel, chatter, content = text, "PRIVMSG");
-
-            // check for command invocation
-            if text.starts_with("!pisscount")
-                && is_whitelisted_channel(pool, &tags.channel_id).await?
-            {
-                tracing::debug!("handling counter command");
-                let repo = ChatterRepository::new(pool);
-                let mut reply = build_query_response(&repo, &text, &tags).await?;
-
-                // we use a mutex here as we do one read/one write; we're atomically comparing every
-                // outgoing response to its predecessor, appending to the message if they are the same.
-                let mut guard = last_message.lock().await;
-                tracing::trace!(
-                    prev_msg_content = ?guard.message,
-                    prev_in_channel = ?guard.channel,
-                    prev_tagged_chatter = ?guard.tagged_chatter,
-                    curr_msg_content = ?reply,
-                    curr_in_channel = ?tags.channel_name,
-                    curr_tagged_chatter = ?tags.user_login,
-                );
+impl CommandCooldowns {
+    /// Past this, entries older than the longest possible cooldown are dropped
+    const MAX_TRACKED: usize = 10_000;
 
-                if &guard.channel == &tags.channel_name
-                    && &guard.message == &reply
-                    && &guard.tagged_chatter == &tags.user_login
-                {
-                    // circumvent "duplicate message" filter if current content matches previous
-                    // message content
-                    reply.push(TRAILER_CHAR);
-                }
+    /// Whether the chatter can use a command at `now`, recording it as used if so.
+    pub(crate) fn take(&self, message: &ChatMessage, cooldown: Duration, now: Instant) -> bool {
+        if cooldown.is_zero() {
+            return true;
+        }
 
-                guard.channel = tags.channel_name.clone();
-                guard.message = reply.clone();
-                guard.tagged_chatter = tags.user_login.clone();
-
-                let channel = format!("#{0}", tags.channel_name);
-                let reply_tag = vec![Tag(
-                    String::from("reply-parent-msg-id"),
-                    Some(tags.msg_id.clone()),
-                )];
-
-                let response = Message {
-                    tags: Some(reply_tag),
-                    prefix: None,
-                    command: irc::proto::Command::PRIVMSG(channel, reply),
-                };
-
-                tracing::debug!(message = ?response, "final `irc::proto::Message` for output");
-
-                // ensure we adhere to rate limits to avoid being silently killed - note that we
-                // build the message first and then await the permit.
-                //
-                // we perhaps want to log any errors (which would indicate a dropped message), but
-                // this is a future pls problem for now.
-                rate_limiter.acquire_one().await?;
-                tracing::debug!(reply_for = tags.msg_id, "reply permit acquired");
-                cmd_tx
-                    .send(OutgoingCommand::Reply { message: response })
-                    .await?;
-
-            // if not invoking a command, check for keyword
-            } else if text.to_lowercase().contains(KEYWORD)
-                && !ID_BLACKLIST.contains(&tags.user_id.as_str())
-            {
-                // ensure we are only incrementing if channel is currently live
-                let mut conn = redis_pool().await?.clone();
-                let online = get_stream_state(&mut conn, &ChannelId(tags.channel_id.clone())).await;
-
-                tracing::trace!(online, "stream state for increment");
-
-                if online {
-                    tracing::info!(tags.user_login, tags.channel_name, "incrementing score");
-                    increment_score(pool, &tags).await?;
-                }
-            }
+        let mut last_used = self.last_used.lock().unwrap_or_else(|e| e.into_inner());
+        if last_used.len() >= Self::MAX_TRACKED {
+            let longest =
+                Duration::from_secs(*ChannelSettings::COMMAND_COOLDOWN_RANGE.end() as u64);
+            last_used.retain(|_, at| now.saturating_duration_since(*at) < longest);
+        }
+
+        let key = (message.channel_id.clone(), message.user_id.clone());
+        if last_used
+            .get(&key)
+            .is_some_and(|at| now.saturating_duration_since(*at) < cooldown)
+        {
+            return false;
+        }
+
+        last_used.insert(key, now);
+        true
+    }
+}
+
+#[async_trait::async_trait]
+impl EventHandler for CounterCommandHandler {
+    fn name(&self) -> &'static str {
+        "counter_command"
+    }
+
+    fn interest(&self) -> Interest {
+        Interest::kinds(&[EventKind::Privmsg])
+    }
+
+    // the reply permit can take a while to acquire when a channel is spamming the command
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+
+    async fn handle(&self, event: &IncomingMessage) -> ClientResult<()> {
+        let IncomingMessage::Privmsg(message) = event else {
+            return Ok(());
+        };
+
+        let channel = format!("{}.#{}", &message.channel_id, &message.channel_login);
+        let chatter = format!("{}.{}", &message.user_id, &message.user_login);
+        tracing::info!(channel, chatter, content = message.text, "PRIVMSG");
+
+        let Some(command) = ChatCommand::parse(&message.text) else {
+            return Ok(());
+        };
+
+        if message.permission < command.permission() {
+            tracing::debug!(?command, permission = ?message.permission, "not permitted");
+            return Ok(());
+        }
+
+        let settings = channel_settings(self.pool, &message.channel_id).await?;
+        let mode = settings.reply_mode;
+        if mode == ReplyMode::Silent {
+            return Ok(());
+        }
+
+        // moderators aren't held to the cooldown
+        let cooldown = Duration::from_secs(settings.command_cooldown_secs.max(0) as u64);
+        if message.permission < PermissionLevel::Moderator
+            && !self.cooldowns.take(message, cooldown, Instant::now())
+        {
+            tracing::debug!(?command, "command on cooldown");
+            return Ok(());
+        }
+
+        tracing::debug!(?command, ?mode, "handling counter command");
+        let repo = ChatterRepository::new(self.pool);
+        let mut reply = match command {
+            ChatCommand::Count => build_query_response(&repo, message).await?,
+            ChatCommand::Rank => build_rank_response(self.pool, &repo, message).await?,
+            ChatCommand::Battle => build_battle_response(self.pool, &repo, message).await?,
+            ChatCommand::Adjust => build_adjust_response(self.pool, &repo, message).await?,
+            ChatCommand::Pause => build_pause_response(self.pool, message, true).await?,
+            ChatCommand::Resume => build_pause_response(self.pool, message, false).await?,
+            ChatCommand::Stats => build_stats_response(self.pool, message).await?,
+        };
+
+        if mode == ReplyMode::Whisper {
+            return self.whisper(message, &reply).await;
+        }
+
+        // we use a mutex here as we do one read/one write; we're atomically comparing every
+        // outgoing response to its predecessor, appending to the message if they are the same.
+        let mut guard = self.last_message.lock().await;
+        tracing::trace!(
+            prev_msg_content = ?guard.message,
+            prev_in_channel = ?guard.channel,
+            prev_tagged_chatter = ?guard.tagged_chatter,
+            curr_msg_content = ?reply,
+            curr_in_channel = ?message.channel_login,
+            curr_tagged_chatter = ?message.user_login,
+        );
+
+        if &guard.channel == &message.channel_login
+            && &guard.message == &reply
+            && &guard.tagged_chatter == &message.user_login
+        {
+            // circumvent "duplicate message" filter if current content matches previous
+            // message content
+            reply.push(TRAILER_CHAR);
+        }
+
+        guard.channel = message.channel_login.clone();
+        guard.message = reply.clone();
+        guard.tagged_chatter = message.user_login.clone();
+        drop(guard);
+
+        let channel = format!("#{0}", message.channel_login);
+        let reply_tag = vec![Tag(
+            String::from("reply-parent-msg-id"),
+            Some(message.message_id.clone()),
+        )];
+
+        let response = Message {
+            tags: Some(reply_tag),
+            prefix: None,
+            command: irc::proto::Command::PRIVMSG(channel, reply),
+        };
+
+        tracing::debug!(message = ?response, "final `irc::proto::Message` for output");
+
+        // replies are only meaningful in the moment, so don't hold on to them while twitch
+        // is unreachable
+        if availability().is_degraded() {
+            tracing::debug!(
+                reply_for = message.message_id,
+                "degraded mode - dropping reply"
+            );
+            return Ok(());
+        }
+
+        // ensure we adhere to rate limits to avoid being silently killed - note that we
+        // build the message first and then await the permit.
+        //
+        // we perhaps want to log any errors (which would indicate a dropped message), but
+        // this is a future pls problem for now.
+        self.rate_limiter.acquire_one().await?;
+        tracing::debug!(reply_for = message.message_id, "reply permit acquired");
+        self.cmd_tx
+            .send(OutgoingCommand::Reply { message: response })
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl CounterCommandHandler {
+    /// Sends the reply to the chatter who used the command, rather than to the channel.
+    async fn whisper(&self, message: &ChatMessage, reply: &str) -> ClientResult<()> {
+        // an empty reply means there's nothing to say (see `build_query_response`)
+        if reply.is_empty() || availability().is_degraded() {
+            return Ok(());
+        }
+
+        self.whisper_limiter.acquire_one().await?;
+        tracing::debug!(reply_for = message.message_id, "whisper permit acquired");
+        Helix::send_whisper(&message.user_id, reply).await?;
+
+        Ok(())
+    }
+}
+
+/// Why keyword matches weren't counted at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountSkipped {
+    Paused,
+    Blacklisted,
+    Offline,
+    /// The message was already counted (see `irc::dedupe`)
+    Duplicate,
+}
+
+/// What happened to a message's keyword matches.
+#[derive(Debug, Default, Serialize)]
+pub struct CountOutcome {
+    pub counted: Vec<KeywordId>,
+    pub suppressed: Vec<(KeywordId, SuppressReason)>,
+    pub skipped: Option<CountSkipped>,
+}
 
-            Ok(())
+impl CountOutcome {
+    fn skipped(reason: CountSkipped) -> Self {
+        Self {
+            skipped: Some(reason),
+            ..Default::default()
         }
-        _ => {
-            tracing::info!(message = ?msg, "received_unhandled_message");
-            Ok(())
+    }
+}
+
+/// Counts a chatter's keyword matches in a channel - whether matched in chat or pushed through
+/// the API - through the channel's pause, blacklist and live state, its count mode and muted
+/// keywords, and the score limits, announcing any milestones reached.
+pub struct ScoreCounter {
+    pool: &'static PgPool,
+    store: Arc<dyn ScoreStore>,
+    hydrator: HydrationQueue,
+    score_limiter: ScoreLimiter,
+    announcer: MilestoneAnnouncer,
+}
+
+impl std::fmt::Debug for ScoreCounter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScoreCounter")
+            .field("score_limiter", &self.score_limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScoreCounter {
+    pub fn new(
+        pool: &'static PgPool,
+        store: Arc<dyn ScoreStore>,
+        hydrator: HydrationQueue,
+        score_limiter: ScoreLimiter,
+        announcer: MilestoneAnnouncer,
+    ) -> Self {
+        Self {
+            pool,
+            store,
+            hydrator,
+            score_limiter,
+            announcer,
+        }
+    }
+
+    pub async fn count(
+        &self,
+        message: &ChatMessage,
+        occurrences: &[(KeywordId, usize)],
+    ) -> ClientResult<CountOutcome> {
+        // paused channels are still joined (and their messages still logged), just not counted
+        if ChannelRepository::new(self.pool)
+            .is_paused(&message.channel_id)
+            .await?
+        {
+            tracing::debug!(message.channel_login, "channel paused - not counting");
+            return Ok(CountOutcome::skipped(CountSkipped::Paused));
+        }
+
+        if channel_settings(self.pool, &message.channel_id)
+            .await?
+            .is_blacklisted(&message.user_id)
+        {
+            tracing::debug!(
+                message.user_login,
+                message.channel_login,
+                "chatter blacklisted in channel - not counting"
+            );
+            return Ok(CountOutcome::skipped(CountSkipped::Blacklisted));
+        }
+
+        // ensure we are only incrementing if channel is currently live
+        let mut conn = redis_pool().await?.clone();
+        let online = get_stream_state(&mut conn, &message.channel_id).await;
+
+        tracing::trace!(online, "stream state for increment");
+
+        if !online {
+            return Ok(CountOutcome::skipped(CountSkipped::Offline));
+        }
+
+        let matched = keyword_increments(self.pool, &message.channel_id, occurrences).await?;
+
+        // claimed before the limiter sees the message, so a redelivery neither uses up the
+        // chatter's window nor records its suppressed scores again
+        if !dedupe::first_delivery(self.pool, message).await? {
+            return Ok(CountOutcome::skipped(CountSkipped::Duplicate));
+        }
+
+        let admission = self
+            .score_limiter
+            .admit(&message.channel_id, &message.user_id, matched);
+
+        if !admission.counted.is_empty() {
+            tracing::info!(
+                message.user_login,
+                message.channel_login,
+                matched = ?admission.counted,
+                "incrementing score"
+            );
+            let reached = record_claimed(
+                self.pool,
+                self.store.as_ref(),
+                Some(&self.hydrator),
+                message,
+                &admission.counted,
+            )
+            .await?;
+            self.announcer.announce(reached).await;
+        }
+
+        // after the increment, so a chatter's first message has created their row
+        record_suppressed(self.pool, message, &admission.suppressed).await;
+
+        Ok(CountOutcome {
+            counted: admission.counted,
+            suppressed: admission.suppressed,
+            skipped: None,
+        })
+    }
+}
+
+/// Counts keywords in chat messages and in the messages attached to (re)subs.
+pub struct KeywordHandler {
+    pool: &'static PgPool,
+    matcher: KeywordMatcher,
+    counter: Arc<ScoreCounter>,
+}
+
+impl KeywordHandler {
+    pub fn new(pool: &'static PgPool, matcher: KeywordMatcher, counter: Arc<ScoreCounter>) -> Self {
+        Self {
+            pool,
+            matcher,
+            counter,
         }
     }
 }
 
+#[async_trait::async_trait]
+impl EventHandler for KeywordHandler {
+    fn name(&self) -> &'static str {
+        "keyword_counter"
+    }
+
+    fn interest(&self) -> Interest {
+        Interest::kinds(&[EventKind::Privmsg, EventKind::Usernotice])
+    }
+
+    async fn handle(&self, event: &IncomingMessage) -> ClientResult<()> {
+        let Some(message) = countable_message(event) else {
+            return Ok(());
+        };
+
+        // command invocations are handled by the `CounterCommandHandler` instead
+        if matches!(event, IncomingMessage::Privmsg(_))
+            && ChatCommand::parse(&message.text).is_some()
+            && reply_mode(self.pool, &message.channel_id).await? != ReplyMode::Silent
+        {
+            return Ok(());
+        }
+
+        let occurrences = self.matcher.occurrences(&message.text, &message.emotes);
+        if occurrences.is_empty() {
+            return Ok(());
+        }
+
+        self.counter.count(&message, &occurrences).await?;
+        Ok(())
+    }
+}
+
+/// Thanks raiders in channels that have replies enabled, when `RAID_THANKS` is set.
+struct RaidHandler {
+    pool: &'static PgPool,
+    cmd_tx: QueueSender<OutgoingCommand>,
+    rate_limiter: Arc<Bucket>,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for RaidHandler {
+    fn name(&self) -> &'static str {
+        "raid_thanks"
+    }
+
+    fn interest(&self) -> Interest {
+        Interest::kinds(&[EventKind::Usernotice])
+    }
+
+    async fn handle(&self, event: &IncomingMessage) -> ClientResult<()> {
+        let IncomingMessage::Usernotice {
+            tags,
+            notice_type: UserNoticeType::Raid { viewer_count },
+            ..
+        } = event
+        else {
+            return Ok(());
+        };
+
+        tracing::info!(
+            tags.user_login,
+            tags.channel_name,
+            viewer_count,
+            "raid received"
+        );
+
+        if !raid_thanks_enabled().await
+            || !channel_settings(self.pool, &tags.channel_id)
+                .await?
+                .thanks_raids()
+        {
+            return Ok(());
+        }
+
+        if availability().is_degraded() {
+            tracing::debug!("degraded mode - not thanking raider");
+            return Ok(());
+        }
+
+        let name = if tags.display_name.is_empty() {
+            &tags.user_login
+        } else {
+            &tags.display_name
+        };
+
+        let message = Message {
+            tags: None,
+            prefix: None,
+            command: irc::proto::Command::PRIVMSG(
+                format!("#{}", tags.channel_name),
+                format!("thanks for the raid {name} - welcome in, all {viewer_count} of you"),
+            ),
+        };
+
+        self.rate_limiter.acquire_one().await?;
+        self.cmd_tx.send(OutgoingCommand::Reply { message }).await?;
+
+        Ok(())
+    }
+}
+
+async fn raid_thanks_enabled() -> bool {
+    match var!(Var::RaidThanks).await {
+        Ok(val) => matches!(val.trim().to_lowercase().as_str(), "true" | "1"),
+        Err(_) => false,
+    }
+}
+
+/// Applies the score moderation policy to deleted messages and timed out/banned chatters.
+struct ModerationHandler {
+    pool: &'static PgPool,
+}
+
+#[async_trait::async_trait]
+impl EventHandler for ModerationHandler {
+    fn name(&self) -> &'static str {
+        "score_moderation"
+    }
+
+    fn interest(&self) -> Interest {
+        Interest::kinds(&[EventKind::Clearchat, EventKind::Clearmsg])
+    }
+
+    async fn handle(&self, event: &IncomingMessage) -> ClientResult<()> {
+        let target = match event {
+            IncomingMessage::Clearmsg { target_msg_id, .. } => {
+                ModerationTarget::Message(target_msg_id.clone())
+            }
+            IncomingMessage::Clearchat {
+                channel_id,
+                target_user_id: Some(chatter_id),
+                ..
+            } => ModerationTarget::Chatter {
+                channel_id: channel_id.clone(),
+                chatter_id: chatter_id.clone(),
+            },
+            // a full chat clear isn't attributable to any chatter
+            _ => return Ok(()),
+        };
+
+        moderation::apply(self.pool, target).await?;
+        Ok(())
+    }
+}
+
 #[instrument(skip(repo))]
 pub async fn build_query_response(
     repo: &ChatterRepository,
-    message: &str,
-    tags: &IrcTags,
+    message: &ChatMessage,
 ) -> ClientResult<String> {
-    let mut parts = message.split(' ').collect::<Vec<_>>();
+    let mut parts = message.text.split(' ').collect::<Vec<_>>();
     let target = if parts.len() > 1 {
         parts[1] = parts[1].trim_start_matches('@');
 
@@ -231,8 +712,7 @@ pub async fn build_query_response(
             .await
             .map_err(ConnectionClientError::SqlxError)
     } else {
-        let chatter_id = ChatterId::from(tags.user_id.to_string());
-        repo.get_by_id(&chatter_id)
+        repo.get_by_id(&message.user_id)
             .await?
             .ok_or_else(|| ConnectionClientError::SqlxError(sqlx::Error::RowNotFound))
     };
@@ -265,59 +745,435 @@ pub async fn build_query_response(
     ))
 }
 
-#[instrument(skip_all)]
-async fn update_chatter_data(user_id: &str, chatter_repo: ChatterRepository) -> ClientResult<()> {
-    let mut target_id = vec![user_id.to_owned()];
+/// Applies `!adjust <user> <+N|-N|=N> [reason]` to that chatter's score on the channel, through
+/// the same (audited) path as the admin API, and builds the reply.
+#[instrument(skip(pool, repo))]
+pub async fn build_adjust_response(
+    pool: &'static PgPool,
+    repo: &ChatterRepository,
+    message: &ChatMessage,
+) -> ClientResult<String> {
+    let mut parts = message.text.split_whitespace().skip(1);
+    let (Some(login), Some(adjustment)) =
+        (parts.next(), parts.next().and_then(ScoreAdjustment::parse))
+    else {
+        return Ok(String::from("usage: !adjust <user> <+N|-N|=N> [reason]"));
+    };
+
+    let login = login.trim_start_matches('@').to_lowercase();
+    let reason = match parts.collect::<Vec<_>>().join(" ") {
+        reason if reason.is_empty() => format!("adjusted from chat by {}", message.user_login),
+        reason => reason,
+    };
 
-    let helix_chatter = Helix::fetch_users_by_id(&mut target_id).await?;
-    let chatter = Chatter::from(helix_chatter[0].clone());
+    let chatter = match repo.get_by_login(&login).await {
+        Ok(chatter) => chatter,
+        Err(sqlx::Error::RowNotFound) => return Ok(format!("{login} hasn't been counted yet")),
+        Err(e) => return Err(e.into()),
+    };
+
+    let correction = ScoreCorrection {
+        chatter_id: chatter.id,
+        channel_id: message.channel_id.clone(),
+        keyword: None,
+        adjustment,
+        reason,
+    };
 
-    chatter_repo.insert(&chatter).await?;
-    Ok(())
+    let actor = Actor::Chat(message.user_id.clone());
+    match score::apply_correction(pool, &actor, correction).await {
+        Ok(adjusted) => Ok(format!(
+            "{login}'s count here is now {} (was {})",
+            adjusted.after, adjusted.before
+        )),
+        Err(ApiError::BadRequest(reason)) => Ok(reason),
+        Err(err) => {
+            // as with `!pisscount`, an empty reply is filtered by twitch
+            tracing::error!(error = ?err, "failed to adjust score from chat");
+
+            Ok(String::default())
+        }
+    }
 }
 
+/// Pauses or resumes counting on the channel for `!pausecount`/`!resumecount`, auditing the
+/// change as the admin API does, and builds the reply.
 #[instrument(skip(pool))]
-pub async fn increment_score(pool: &'static sqlx::PgPool, tags: &IrcTags) -> ClientResult<()> {
-    let chatter_repo = ChatterRepository::new(pool);
+pub async fn build_pause_response(
+    pool: &'static PgPool,
+    message: &ChatMessage,
+    paused: bool,
+) -> ClientResult<String> {
+    let repo = ChannelRepository::new(pool);
+    let previous = repo.is_paused(&message.channel_id).await?;
+    if previous == paused {
+        let reply = if paused {
+            "counting is already paused here"
+        } else {
+            "counting isn't paused here"
+        };
+        return Ok(String::from(reply));
+    }
+
+    repo.set_paused(&message.channel_id, paused).await?;
+
+    let actor = Actor::Chat(message.user_id.clone());
+    tracing::info!(channel = %message.channel_id, paused, %actor, "updated channel pause");
+    Audit::new(AuditAction::ChannelPauseUpdated)
+        .target(&message.channel_id)
+        .before(&previous)
+        .after(&paused)
+        .record(pool, &actor)
+        .await;
+
+    let reply = if paused {
+        "counting is paused here until !resumecount"
+    } else {
+        "counting has resumed"
+    };
+    Ok(String::from(reply))
+}
+
+/// Builds the reply to `!rank`, with the invoking chatter's rank on the channel or, if a login is
+/// given, that chatter's.
+#[instrument(skip(pool, repo))]
+pub async fn build_rank_response(
+    pool: &'static PgPool,
+    repo: &ChatterRepository,
+    message: &ChatMessage,
+) -> ClientResult<String> {
+    let mut parts = message.text.split(' ').collect::<Vec<_>>();
+    let target = if parts.len() > 1 {
+        parts[1] = parts[1].trim_start_matches('@');
+        if parts[1].to_lowercase() == COUNTER_USER {
+            return Ok(ReplyReason::BotCountQueried.get_reply().to_string());
+        }
+
+        match repo.get_by_login(&parts[1].to_lowercase()).await {
+            Ok(chatter) => Some(chatter.id),
+            Err(sqlx::Error::RowNotFound) => None,
+            Err(e) => return Err(e.into()),
+        }
+    } else {
+        Some(message.user_id.clone())
+    };
+
+    let rank = match target {
+        Some(chatter_id) => {
+            RankRepository::new(pool)
+                .get_channel_rank(&message.channel_id, &chatter_id)
+                .await?
+        }
+        None => None,
+    };
+
+    let requested_user = format_username(parts);
+    Ok(match rank {
+        Some(rank) => format!(
+            "{requested_user} rank is #{} of {} ({} messages mentioning {KEYWORD})",
+            rank.rank, rank.ranked_chatters, rank.score
+        ),
+        None => format!("none of {requested_user} messages have mentioned {KEYWORD} here"),
+    })
+}
+
+/// Builds the reply to `!pissbattle`, comparing the invoking chatter with another on the channel
+/// and globally.
+#[instrument(skip(pool, repo))]
+pub async fn build_battle_response(
+    pool: &'static PgPool,
+    repo: &ChatterRepository,
+    message: &ChatMessage,
+) -> ClientResult<String> {
+    let Some(opponent) = message.text.split(' ').nth(1) else {
+        return Ok(String::from("battle who? try !pissbattle @user"));
+    };
+    let opponent = opponent.trim_start_matches('@').to_lowercase();
+    if opponent == COUNTER_USER {
+        return Ok(ReplyReason::BotCountQueried.get_reply().to_string());
+    }
+    if opponent == message.user_login.to_lowercase() {
+        return Ok(ReplyReason::BattleSelf.get_reply().to_string());
+    }
+
+    let opponent = match repo.get_by_login(&opponent).await {
+        Ok(chatter) => chatter,
+        Err(sqlx::Error::RowNotFound) => {
+            return Ok(format!(
+                "{opponent} has never been counted. thats a forfeit"
+            ));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let Some(challenger) = repo.get_by_id(&message.user_id).await? else {
+        return Ok(String::from(
+            "you havent been counted anywhere yet. thats a forfeit",
+        ));
+    };
+
+    let versus = RankRepository::new(pool)
+        .get_versus(Some(&message.channel_id), [challenger, opponent])
+        .await?;
+    let (leader, trailer, margin) = versus.standings();
+
+    let reason = if margin == 0 {
+        ReplyReason::BattleTied
+    } else {
+        ReplyReason::BattleWon
+    };
+    let global = |contender: &Contender| match &contender.global {
+        Some(rank) => format!("#{}", rank.rank),
+        None => String::from("unranked"),
+    };
+
+    Ok(format!(
+        "{} (globally {} is {} and {} is {})",
+        template::render(
+            reason.get_reply(),
+            &[
+                ("leader", leader.name.clone()),
+                ("trailer", trailer.name.clone()),
+                ("margin", margin.to_string()),
+            ]
+        ),
+        leader.name,
+        global(leader),
+        trailer.name,
+        global(trailer),
+    ))
+}
+
+/// Builds the reply to `!pissstats` from the channel's stats, which are kept as scores are
+/// counted rather than aggregated for each reply (see `irc::stats`).
+#[instrument(skip(pool))]
+pub async fn build_stats_response(
+    pool: &'static PgPool,
+    message: &ChatMessage,
+) -> ClientResult<String> {
+    let stats = match channel_stats().get(pool, &message.channel_id).await? {
+        Some(stats) if stats.total > 0 => stats,
+        _ => return Ok(format!("no messages have mentioned {KEYWORD} here yet")),
+    };
+
+    let top = match &stats.top_chatter {
+        Some(top) => format!(", {} the most ({})", top.login, top.score),
+        None => String::new(),
+    };
+    Ok(format!(
+        "{} messages from {} chatters have mentioned {KEYWORD} here{top} - {} today, {} in the last hour",
+        stats.total,
+        stats.unique_chatters,
+        stats.today,
+        stats.last_hour(chrono::Utc::now().naive_utc()),
+    ))
+}
+
+/// Returns a message that can mention keywords - chat messages, and the messages attached to
+/// (re)subs - unless it was sent by a blacklisted chatter.
+pub(crate) fn countable_message(event: &IncomingMessage) -> Option<Cow<'_, ChatMessage>> {
+    let message = match event {
+        IncomingMessage::Privmsg(message) => Cow::Borrowed(message),
+        IncomingMessage::Usernotice {
+            tags,
+            notice_type,
+            text: Some(text),
+        } if notice_type.has_chatter_message() => {
+            Cow::Owned(ChatMessage::from((tags.clone(), text.clone())))
+        }
+        _ => return None,
+    };
+
+    if ID_BLACKLIST.contains(&message.user_id.0.as_str()) {
+        return None;
+    }
+
+    Some(message)
+}
+
+/// Expands the keyword occurrences in a message into one id per increment, following the
+/// channel's count mode. Keywords muted in the channel's settings aren't counted. Untracked
+/// channels use the default (per-message) mode.
+pub(crate) async fn keyword_increments(
+    pool: &'static PgPool,
+    channel_id: &ChannelId,
+    occurrences: &[(KeywordId, usize)],
+) -> sqlx::Result<Vec<KeywordId>> {
+    let config = ChannelRepository::new(pool)
+        .get_count_config(channel_id)
+        .await?
+        .unwrap_or_default();
+
+    let muted = settings().get(pool, channel_id).await?.muted_keywords;
+    Ok(unmuted_increments(&config, &muted, occurrences))
+}
+
+/// The database-free half of `keyword_increments`.
+pub(crate) fn unmuted_increments(
+    config: &ChannelCountConfig,
+    muted: &[KeywordId],
+    occurrences: &[(KeywordId, usize)],
+) -> Vec<KeywordId> {
+    let occurrences: Vec<_> = occurrences
+        .iter()
+        .filter(|(id, _)| !muted.contains(id))
+        .copied()
+        .collect();
+
+    config.increments(&occurrences)
+}
+
+/// Records keyword matches dropped by the score limits; failures are only logged, as nothing
+/// depends on them.
+async fn record_suppressed(
+    pool: &'static PgPool,
+    message: &ChatMessage,
+    suppressed: &[(KeywordId, SuppressReason)],
+) {
     let score_repo = LeaderboardRepository::new(pool);
+    for (keyword_id, reason) in suppressed {
+        tracing::debug!(
+            login = message.user_login,
+            channel_name = message.channel_login,
+            keyword = %keyword_id,
+            reason = reason.as_str(),
+            "score suppressed"
+        );
+        metrics::counter!("score_suppressed_total", "reason" => reason.as_str()).increment(1);
+
+        if let Err(e) = score_repo
+            .record_suppressed_score_event(
+                &message.user_id,
+                &message.channel_id,
+                keyword_id,
+                &message.message_id,
+                *reason,
+            )
+            .await
+        {
+            tracing::error!(error = ?e, keyword = %keyword_id, "suppressed score insert failure");
+        }
+    }
+}
 
-    let chatter = chatter_repo.get_by_id(&tags.user_id.clone().into()).await?;
+/// Records one score per matched keyword in `store`, returning any milestones the scores crossed.
+/// Messages that have already been counted (see `irc::dedupe`) aren't counted again, and return
+/// `None`.
+///
+/// Without a `hydrator`, unknown chatters are only stored as a stub built from their message.
+#[instrument(skip(pool, store, hydrator))]
+pub async fn increment_score(
+    pool: &'static sqlx::PgPool,
+    store: &dyn ScoreStore,
+    hydrator: Option<&HydrationQueue>,
+    message: &ChatMessage,
+    keyword_ids: &[KeywordId],
+) -> ClientResult<Option<Vec<MilestoneReached>>> {
+    if !dedupe::first_delivery(pool, message).await? {
+        return Ok(None);
+    }
 
+    record_claimed(pool, store, hydrator, message, keyword_ids)
+        .await
+        .map(Some)
+}
+
+/// `increment_score` for a message that has already been claimed with `dedupe::first_delivery`.
+/// The claim is released if none of the scores could be recorded.
+async fn record_claimed(
+    pool: &'static sqlx::PgPool,
+    store: &dyn ScoreStore,
+    hydrator: Option<&HydrationQueue>,
+    message: &ChatMessage,
+    keyword_ids: &[KeywordId],
+) -> ClientResult<Vec<MilestoneReached>> {
+    let mut recorded = false;
+    let reached =
+        record_increments(pool, store, hydrator, message, keyword_ids, &mut recorded).await;
+    if reached.is_err() && !recorded {
+        // nothing was counted, so a redelivery (or retry) of the message has to count it
+        dedupe::release(pool, message).await;
+    }
+
+    reached
+}
+
+/// `increment_score`, once the message has been claimed. `recorded` is set once any of its scores
+/// have been recorded.
+async fn record_increments(
+    pool: &'static sqlx::PgPool,
+    store: &dyn ScoreStore,
+    hydrator: Option<&HydrationQueue>,
+    message: &ChatMessage,
+    keyword_ids: &[KeywordId],
+    recorded: &mut bool,
+) -> ClientResult<Vec<MilestoneReached>> {
+    let chatter_repo = ChatterRepository::new(pool);
+    let milestones = milestones::milestones()
+        .get(pool, &message.channel_id)
+        .await?;
+    let mut reached = Vec::new();
+
+    let chatter = chatter_repo.get_by_id(&message.user_id).await?;
+
+    // helix lookups happen in the background so they don't hold up the increment - unknown
+    // chatters get a stub row from their message until the hydration queue fills it in
     if !chatter.is_some() {
-        tracing::debug!(?tags.user_id, "creating chatter (not in database)");
-        update_chatter_data(&tags.user_id, chatter_repo).await?;
+        tracing::debug!(user_id = %message.user_id, "creating stub chatter (not in database)");
+        chatter_repo.insert_stub(&stub_chatter(message)).await?;
+        if let Some(hydrator) = hydrator {
+            hydrator.enqueue(message.user_id.clone());
+        }
     } else if let Some(db_data) = chatter
+        && let Some(hydrator) = hydrator
         && update_threshold_elapsed(&db_data)
     {
-        tracing::debug!(?tags.user_id, "updating chatter (stale data in database)");
-        update_chatter_data(&tags.user_id, chatter_repo).await?;
+        tracing::debug!(user_id = %message.user_id, "queueing chatter update (stale data in database)");
+        hydrator.enqueue(message.user_id.clone());
     }
 
-    match score_repo
-        .record_score_event(
-            &tags.user_id.clone().into(),
-            &tags.channel_id.clone().into(),
-        )
-        .await
-    {
-        Ok(_) => {
-            tracing::debug!(
-                channel = tags.channel_id,
-                chatter = tags.user_id,
-                channel_name = tags.channel_name,
-                login = tags.user_login,
-                "score event recorded"
-            );
-            Ok(())
-        }
-        Err(e) => {
-            tracing::error!(
-                error = ?e,
-                channel = tags.channel_id,
-                chatter = tags.user_id,
-                "score event insert failure"
-            );
-            Err(ConnectionClientError::SqlxError(e))
+    for keyword_id in keyword_ids {
+        let score = ScoreIncrement {
+            chatter_id: &message.user_id,
+            chatter_login: &message.user_login,
+            channel_id: &message.channel_id,
+            channel_login: &message.channel_login,
+            keyword_id,
+            msg_id: &message.message_id,
+        };
+
+        // the totals are only needed (and so only read) when there are milestones to check or
+        // the channel's stats are being kept
+        let with_totals = !milestones.is_empty() || channel_stats().is_tracked(&message.channel_id);
+        match store.increment(&score, with_totals).await {
+            Ok(totals) => {
+                *recorded = true;
+                increments().publish(&score);
+                channel_stats().record(&score, totals);
+                if let Some(totals) = totals {
+                    reached.extend(milestone::reached(&milestones, totals, message));
+                }
+            }
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    channel = %message.channel_id,
+                    chatter = %message.user_id,
+                    keyword = %keyword_id,
+                    "score event insert failure"
+                );
+                return Err(e.into());
+            }
         }
+
+        tracing::debug!(
+            channel = %message.channel_id,
+            chatter = %message.user_id,
+            keyword = %keyword_id,
+            channel_name = message.channel_login,
+            login = message.user_login,
+            "score event recorded"
+        );
     }
+
+    Ok(reached)
 }
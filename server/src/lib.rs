@@ -0,0 +1,14 @@
+#![warn(unused_crate_dependencies)]
+
+// only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
+
+pub mod api;
+pub mod db;
+pub mod embed;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod integrations;
+pub mod irc;
+pub mod util;
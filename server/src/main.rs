@@ -1,5 +1,3 @@
-#![warn(unused_crate_dependencies)]
-
 use std::net::SocketAddr;
 use std::sync::Arc;
 
@@ -7,19 +5,37 @@ use futures::future::join_all;
 use thiserror::Error;
 use tokio::sync::Mutex;
 
-use crate::api::server::RouteError;
-use crate::db::redis::redis_pool::{RedisErr, redis_pool};
-use crate::db::{PgError, db_pool};
-use crate::irc::ConnectionClientError;
-use crate::util::channel::ChannelError;
-use crate::util::env::Var;
-use crate::util::telemetry::Telemetry;
-use crate::util::totp;
-
-mod api;
-mod db;
-mod irc;
-mod util;
+use pea_fan::api;
+use pea_fan::api::auth::AuthError;
+use pea_fan::api::auth::command::{self as token, TokenCommand};
+use pea_fan::api::error::ApiError;
+use pea_fan::api::webhook::reconcile::spawn_subscription_reconciliation;
+use pea_fan::api::webhook::secret::{self, SecretError};
+use pea_fan::db::migrate::{self, MigrateMode};
+use pea_fan::db::redis::redis_pool::{RedisErr, redis_pool, spawn_redis_health_check};
+use pea_fan::db::redis::sync::spawn_reconciliation;
+use pea_fan::db::replica::replica_set;
+use pea_fan::db::snapshot::{self, SnapshotCommand, SnapshotError};
+use pea_fan::db::{PgError, db_pool};
+use pea_fan::integrations::discord::spawn_discord_webhooks;
+use pea_fan::irc::ConnectionClientError;
+use pea_fan::irc::dedupe::spawn_claim_purge;
+use pea_fan::util::archive::spawn_score_archival;
+use pea_fan::util::availability::availability;
+use pea_fan::util::channel::ChannelError;
+use pea_fan::util::deletion::spawn_chatter_purge;
+use pea_fan::util::digest::spawn_daily_digests;
+use pea_fan::util::env::Var;
+use pea_fan::util::export::{self, ExportArgs, ExportError};
+use pea_fan::util::flags::spawn_flag_listener;
+use pea_fan::util::live::spawn_stream_status_refresh;
+use pea_fan::util::overlay::spawn_overlay_updates;
+use pea_fan::util::period::spawn_leaderboard_snapshots;
+use pea_fan::util::refresh::spawn_chatter_refresh;
+use pea_fan::util::settings::spawn_settings_listener;
+use pea_fan::util::telemetry::Telemetry;
+use pea_fan::util::totp;
+use pea_fan::var;
 
 #[derive(Debug, Error)]
 enum RunnerErr {
@@ -42,7 +58,22 @@ enum RunnerErr {
     Channel(#[from] ChannelError),
 
     #[error(transparent)]
-    Router(#[from] RouteError),
+    Router(#[from] ApiError),
+
+    #[error(transparent)]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+
+    #[error(transparent)]
+    Export(#[from] ExportError),
+
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
+
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+
+    #[error(transparent)]
+    Secret(#[from] SecretError),
 }
 
 type Result<T> = core::result::Result<T, RunnerErr>;
@@ -53,7 +84,38 @@ async fn main() -> Result<()> {
     log_startup_init();
     
     let database_pool = db_pool().await?;
+    if let Some(args) = ExportArgs::from_args(std::env::args()) {
+        export::run(database_pool, &args?).await?;
+        telemetry_registry.shutdown();
+        return Ok(());
+    }
+    if let Some(command) = SnapshotCommand::from_args(std::env::args()) {
+        snapshot::run(database_pool, command?).await?;
+        telemetry_registry.shutdown();
+        return Ok(());
+    }
+    if let Some(command) = TokenCommand::from_args(std::env::args()) {
+        token::run(database_pool, command?).await?;
+        telemetry_registry.shutdown();
+        return Ok(());
+    }
+
+    match MigrateMode::from_args(std::env::args()) {
+        MigrateMode::Only => {
+            migrate::run(database_pool).await?;
+            telemetry_registry.shutdown();
+            return Ok(());
+        }
+        MigrateMode::OnStartup => _ = migrate::run(database_pool).await?,
+        MigrateMode::Skip => migrate::check_pending(database_pool).await,
+    }
+
+    // webhook secrets are written as soon as channels are subscribed to, so a missing key has to
+    // stop the server before anything is stored in plaintext
+    secret::require_key().await?;
+
     let redis_pool = redis_pool().await?;
+    let replicas = replica_set().await?;
 
     let totp_handler = {
         let totp_key = var!(Var::TOTPKey).await.unwrap();
@@ -61,20 +123,55 @@ async fn main() -> Result<()> {
     };
 
     let (tx_server_ready, rx_server_ready) = tokio::sync::mpsc::unbounded_channel::<SocketAddr>();
-    let mut handles = Vec::new();
+    let mut handles = vec![
+        availability().spawn_probe(),
+        spawn_redis_health_check(redis_pool.clone()),
+    ];
+
+    if let Some(health_check) = replicas.spawn_health_check() {
+        handles.push(health_check);
+    }
+
+    // flags are loaded before anything that checks them is started
+    handles.push(spawn_flag_listener(database_pool).await);
+    handles.push(spawn_reconciliation(redis_pool.clone(), database_pool));
+    handles.push(spawn_stream_status_refresh(database_pool));
+    handles.push(spawn_discord_webhooks(database_pool));
+    handles.push(spawn_subscription_reconciliation(database_pool));
+    handles.push(spawn_chatter_purge(database_pool));
+    handles.push(spawn_claim_purge(database_pool));
+    handles.push(spawn_settings_listener(database_pool));
+    handles.push(spawn_daily_digests(database_pool));
+    handles.push(spawn_overlay_updates(database_pool));
+
+    if let Some(snapshots) = spawn_leaderboard_snapshots(database_pool).await {
+        handles.push(snapshots);
+    }
+
+    if let Some(archival) = spawn_score_archival(database_pool).await {
+        handles.push(archival);
+    }
+
+    if let Some(refresh) = spawn_chatter_refresh(database_pool).await {
+        handles.push(refresh);
+    }
 
     let server_handles = api::server::start_server(
         tx_server_ready,
         rx_server_ready,
         database_pool,
+        replicas,
         redis_pool.clone(),
         totp_handler,
     )
     .await?;
 
-    handles.extend(server_handles);
+    // the background jobs run until the server has drained
+    _ = join_all(server_handles).await;
+    for handle in handles {
+        handle.abort();
+    }
 
-    _ = join_all(handles).await;
     telemetry_registry.shutdown();
     Ok(())
 }
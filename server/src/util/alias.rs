@@ -0,0 +1,197 @@
+//! Merges legacy scores recorded under a chatter's previous logins.
+//!
+//! Renames are detected by the `chatter_login_change_trigger` in Postgres, which records the old
+//! login in the `alias` table whenever a Helix refresh changes a chatter's login. Scores stored in
+//! Redis under that old login are then merged into the chatter's scores, and (while dual-writes
+//! are enabled) the legacy keys are moved to the new login. Both the admin merge endpoint and the
+//! background merge after a Helix refresh go through `merge_alias`, so the two stores are always
+//! updated together.
+
+use redis::AsyncCommands;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::db::models::alias::Alias;
+use crate::db::prelude::{AliasRepository, ChannelId, ChannelRepository, ChatterId};
+use crate::db::prelude::{ChatterRepository, Repository, Tx};
+use crate::db::redis::redis_pool::{RedisResult, redis_pool};
+use crate::db::redis::rename::LegacyRename;
+use crate::db::redis::sync;
+use crate::util;
+
+/// Merged score events are backdated so they don't show up in recent time windows; this matches
+/// the offset used by the initial Redis migration.
+const LEGACY_TIMESTAMP_OFFSET: i64 = 120;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeResult {
+    pub chatter_id: ChatterId,
+    pub login: String,
+    pub merged_score: i64,
+    /// Whether the legacy Redis keys were moved to the chatter's current login
+    pub legacy_keys_moved: bool,
+}
+
+/// Merges all pending aliases, optionally limited to a set of chatters.
+#[instrument(skip(redis_pool, pool, chatter_ids))]
+pub async fn merge_pending<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    pool: &'static Pool<Postgres>,
+    chatter_ids: Option<&[ChatterId]>,
+) -> RedisResult<Vec<MergeResult>> {
+    let pending = AliasRepository::new(pool).get_pending(chatter_ids).await?;
+    if !pending.is_empty() {
+        tracing::info!(count = pending.len(), "merging pending aliases");
+    }
+
+    let mut merged = Vec::with_capacity(pending.len());
+    for alias in pending {
+        if let Some(result) = merge_alias(redis_pool, pool, &alias).await? {
+            merged.push(result);
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Merges pending aliases for chatters that have just been refreshed from Helix.
+///
+/// The merge runs in the background and failures are only logged, as the refresh itself has
+/// already succeeded.
+pub fn spawn_merge_for(pool: &'static Pool<Postgres>, chatter_ids: Vec<ChatterId>) {
+    tokio::spawn(async move {
+        let result = async {
+            let mut conn = redis_pool().await?.clone();
+            merge_pending(&mut conn, pool, Some(&chatter_ids)).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::error!(error = ?e, "alias merge failure");
+        }
+    });
+}
+
+/// Merges an alias as a saga across both stores:
+///
+/// 1. snapshot the legacy Redis keys under the old login
+/// 2. record the merged score events and mark the alias merged, without committing
+/// 3. move the legacy keys to the new login (only while dual-writes are enabled, as Redis is
+///    otherwise a read-only snapshot of the old counter)
+/// 4. commit, undoing step 3 from the snapshot if the commit fails
+///
+/// A failure before the commit rolls the transaction back, leaving both stores untouched. The
+/// alias is locked for the duration, and `None` is returned if it was merged in the meantime (e.g.
+/// by a concurrent admin merge).
+#[instrument(skip(redis_pool, pool))]
+async fn merge_alias<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    pool: &'static Pool<Postgres>,
+    alias: &Alias,
+) -> RedisResult<Option<MergeResult>> {
+    let alias_repo = AliasRepository::new(pool);
+    let mut tx = Tx::begin(pool).await?;
+    if !AliasRepository::lock_pending_in_tx(tx.inner_mut()?, &alias.chatter_id, &alias.login)
+        .await?
+    {
+        tracing::info!(alias.login, "alias already merged, skipping");
+        return Ok(None);
+    }
+
+    let current = ChatterRepository::new(pool)
+        .get_by_id(&alias.chatter_id)
+        .await?;
+
+    // a chatter that renamed back to a previous login, or whose old login was already resolved to
+    // them by the initial migration, has nothing left to merge
+    let renamed_back = current.as_ref().is_some_and(|ch| ch.login == alias.login);
+    let already_counted = renamed_back || alias_repo.migrated_with_chatter(&alias.login).await?;
+
+    let is_channel = ChannelRepository::new(pool)
+        .get_by_id(&ChannelId::from(alias.chatter_id.clone()))
+        .await?
+        .is_some();
+    let new_login = current.map(|ch| ch.login).unwrap_or_default();
+    let snapshot = LegacyRename::snapshot(redis_pool, &alias.login, &new_login, is_channel).await?;
+
+    let leaderboard: Vec<(String, i64)> = if already_counted {
+        tracing::info!(alias.login, "alias scores already counted, skipping merge");
+        Vec::new()
+    } else {
+        snapshot
+            .user_scores()
+            .map(|(channel, score)| (channel.to_string(), score))
+            .collect()
+    };
+
+    let timestamp = util::create_timestamp(LEGACY_TIMESTAMP_OFFSET);
+    let mut merged_score = 0;
+
+    for (channel, score) in leaderboard {
+        let channel_login = channel.trim_start_matches('#');
+        let Some(channel_id) = alias_repo.resolve_channel(channel_login).await? else {
+            tracing::warn!(
+                alias.login,
+                channel_login,
+                "unknown channel in legacy leaderboard"
+            );
+            continue;
+        };
+
+        tx.record_score_events_multi(&alias.chatter_id, &channel_id, score, timestamp)
+            .await?;
+        merged_score += score;
+    }
+
+    // the alias is locked, so this only fails to mark it if it's been deleted since
+    if !AliasRepository::mark_merged_in_tx(
+        tx.inner_mut()?,
+        &alias.chatter_id,
+        &alias.login,
+        merged_score,
+    )
+    .await?
+    {
+        tracing::info!(alias.login, "alias already merged, skipping");
+        return Ok(None);
+    }
+
+    let move_keys = !renamed_back
+        && !new_login.is_empty()
+        && !snapshot.is_empty()
+        && sync::dual_write_enabled();
+
+    // the transaction rolls back when dropped if this fails
+    if move_keys {
+        snapshot.apply(redis_pool).await?;
+    }
+
+    if let Err(e) = tx.commit().await {
+        if move_keys && let Err(compensation) = snapshot.compensate(redis_pool).await {
+            tracing::error!(
+                error = ?compensation,
+                ?snapshot,
+                "failed to restore legacy keys after alias merge failure - restore manually"
+            );
+        }
+
+        return Err(e.into());
+    }
+
+    tracing::info!(
+        chatter_id = alias.chatter_id.0,
+        alias.login,
+        new_login,
+        merged_score,
+        legacy_keys_moved = move_keys,
+        "merged alias"
+    );
+
+    Ok(Some(MergeResult {
+        chatter_id: alias.chatter_id.clone(),
+        login: alias.login.clone(),
+        merged_score,
+        legacy_keys_moved: move_keys,
+    }))
+}
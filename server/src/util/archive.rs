@@ -0,0 +1,477 @@
+//! Archives score events once they're older than `SCORE_ARCHIVE_RETENTION_DAYS`, so that
+//! `score_event` doesn't grow forever.
+//!
+//! Events are archived a (UTC) day at a time, once the whole day has passed out of the retention
+//! window: the day's events are written to a gzipped CSV object (`score_event/YYYY-MM-DD.csv.gz`)
+//! in storage (see `util::storage`), then deleted from Postgres. What they counted towards isn't touched -
+//! scores and totals are kept separately - and each archived event is compacted into an hourly
+//! per-chatter rollup, so that heatmaps can still be rebuilt. Events written for an already
+//! archived day (e.g. imported with an alias) are merged into its archive the next time round.
+//!
+//! A day is deleted in the same transaction that locks its events and records its archive, once
+//! the object has been written, so a failure partway through leaves the events where they were.
+//! Only one instance archives at a time.
+//!
+//! `ScoreHistory` answers history queries over any range of days by merging archived days with
+//! the events still in Postgres.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Days, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::archive::{ArchivedDay, ArchivedScoreEvent};
+use crate::db::models::profile::DailyScore;
+use crate::db::prelude::{ArchiveRepository, ChannelId, ChatterId, KeywordId};
+use crate::util::env::Var;
+use crate::util::export::csv_field;
+use crate::util::storage::{ObjectStore, StorageError, storage};
+use crate::var;
+
+const CSV_HEADER: &str = "id,chatter_id,channel_id,keyword_id,earned_at,msg_id,flagged_at";
+/// Matches the JSON (serde) representation
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Days archived per run, so that working through a backlog doesn't hold up the job for long
+const MAX_DAYS_PER_RUN: usize = 7;
+/// The previous year's windowed leaderboard reads events from as far back as the start of last
+/// year, so events are kept for at least two years.
+pub const MIN_RETENTION_DAYS: u64 = 731;
+
+pub type ArchiveResult<T> = core::result::Result<T, ArchiveError>;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("archive '{0}' is missing")]
+    Missing(String),
+
+    #[error("invalid archive '{key}': {reason}")]
+    Invalid { key: String, reason: String },
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// The key a day's events are archived under.
+pub fn object_key(day: NaiveDate) -> String {
+    format!("score_event/{}.csv.gz", day.format("%Y-%m-%d"))
+}
+
+/// Moves score events older than the retention window out to storage.
+#[derive(Debug)]
+pub struct ScoreArchiver {
+    pool: &'static Pool<Postgres>,
+    store: Arc<dyn ObjectStore>,
+    retention_days: u64,
+}
+
+impl ScoreArchiver {
+    pub fn new(
+        pool: &'static Pool<Postgres>,
+        store: Arc<dyn ObjectStore>,
+        retention_days: u64,
+    ) -> Self {
+        if retention_days < MIN_RETENTION_DAYS {
+            tracing::warn!(
+                retention_days,
+                "score archive retention is too short, keeping events for {MIN_RETENTION_DAYS} days"
+            );
+        }
+
+        Self {
+            pool,
+            store,
+            retention_days: retention_days.max(MIN_RETENTION_DAYS),
+        }
+    }
+
+    /// Archives the days that have passed out of the retention window, up to `MAX_DAYS_PER_RUN`
+    /// of them. Returns the number of events archived.
+    #[instrument(skip(self))]
+    pub async fn archive_due(&self) -> ArchiveResult<u64> {
+        let cutoff =
+            (Utc::now().date_naive() - Days::new(self.retention_days)).and_time(NaiveTime::MIN);
+        let repo = ArchiveRepository::new(self.pool);
+
+        let mut archived = 0;
+        for _ in 0..MAX_DAYS_PER_RUN {
+            let Some(oldest) = repo.get_oldest_before(cutoff).await? else {
+                break;
+            };
+
+            match self.archive_day(&repo, oldest.date()).await? {
+                Some(events) => archived += events,
+                None => {
+                    tracing::debug!("score events are being archived elsewhere");
+                    break;
+                }
+            }
+        }
+
+        Ok(archived)
+    }
+
+    /// Returns `None` if another instance is archiving.
+    async fn archive_day(
+        &self,
+        repo: &ArchiveRepository,
+        day: NaiveDate,
+    ) -> ArchiveResult<Option<u64>> {
+        let Some(mut archive) = repo.begin(day).await? else {
+            return Ok(None);
+        };
+
+        let mut events = archive.get_events().await?;
+        let event_ids = events.iter().map(|event| event.id).collect::<Vec<_>>();
+
+        if let Some(existing) = archive.get_archived().await? {
+            let archived = read_archive(self.store.as_ref(), &existing.object_key).await?;
+            events = merge(archived, events);
+        }
+
+        let key = object_key(day);
+        let archived_events = events.len() as i64;
+        let bytes = tokio::task::spawn_blocking(move || encode(&events)).await??;
+        self.store.put(&key, bytes).await?;
+
+        let moved = archive.commit(&event_ids, &key, archived_events).await?;
+        tracing::info!(%day, moved, archived_events, "archived score events");
+
+        Ok(Some(moved))
+    }
+}
+
+/// Spawns the archival job, unless `SCORE_ARCHIVAL` isn't set (or storage can't be opened).
+pub async fn spawn_score_archival(pool: &'static Pool<Postgres>) -> Option<JoinHandle<()>> {
+    let enabled = var!(Var::ScoreArchival)
+        .await
+        .is_ok_and(|val| matches!(val.trim().to_lowercase().as_str(), "true" | "1"));
+    if !enabled {
+        tracing::info!("score archival disabled");
+        return None;
+    }
+
+    let store = match storage().await {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to open storage, score archival disabled");
+            return None;
+        }
+    };
+
+    let retention_days = var!(Var::ScoreArchiveRetentionDays)
+        .await
+        .ok()
+        .and_then(|days| days.trim().parse::<u64>().ok())
+        .unwrap_or(MIN_RETENTION_DAYS);
+    let archiver = ScoreArchiver::new(pool, store, retention_days);
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ARCHIVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match archiver.archive_due().await {
+                Ok(0) => (),
+                Ok(events) => tracing::debug!(events, "archived score events"),
+                Err(e) => tracing::error!(error = ?e, "failed to archive score events"),
+            }
+        }
+    }))
+}
+
+/// Reads score history over both archived days and the events still in Postgres.
+#[derive(Debug)]
+pub struct ScoreHistory {
+    pool: &'static Pool<Postgres>,
+}
+
+impl ScoreHistory {
+    pub fn new(pool: &'static Pool<Postgres>) -> Self {
+        Self { pool }
+    }
+
+    /// Scores counted on each (UTC) day from `from` to `to` inclusive, oldest first, optionally
+    /// limited to a channel and/or chatter. Every day in the range is included, with a zero for
+    /// days without scores. Like the rest of the API, hidden chatters' scores aren't included.
+    #[instrument(skip(self))]
+    pub async fn daily(
+        &self,
+        channel_id: Option<&ChannelId>,
+        chatter_id: Option<&ChatterId>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ArchiveResult<Vec<DailyScore>> {
+        let repo = ArchiveRepository::new(self.pool);
+
+        let mut totals = BTreeMap::<NaiveDate, i64>::new();
+        for day in repo
+            .get_live_daily(channel_id, chatter_id, from, to)
+            .await?
+        {
+            *totals.entry(day.day).or_default() += day.total;
+        }
+
+        // archived counts are kept per chatter until it's known which chatters are visible
+        let archived_days = repo.get_archived_days(from, to).await?;
+        let archived = archived_counts(&archived_days, channel_id, chatter_id).await?;
+
+        let chatter_ids = archived
+            .keys()
+            .map(|(_, chatter_id)| chatter_id.0.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let visible = repo
+            .get_visible_chatters(&chatter_ids)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        for ((day, chatter_id), total) in archived {
+            if visible.contains(&chatter_id) {
+                *totals.entry(day).or_default() += total;
+            }
+        }
+
+        Ok(from
+            .iter_days()
+            .take_while(|day| *day <= to)
+            .map(|day| DailyScore {
+                day,
+                total: totals.get(&day).copied().unwrap_or_default(),
+            })
+            .collect())
+    }
+}
+
+/// Counts archived events per day and chatter. Storage is only opened if any days were archived,
+/// so that history can be read without it as long as it doesn't reach back that far.
+async fn archived_counts(
+    days: &[ArchivedDay],
+    channel_id: Option<&ChannelId>,
+    chatter_id: Option<&ChatterId>,
+) -> ArchiveResult<HashMap<(NaiveDate, ChatterId), i64>> {
+    let mut counts = HashMap::new();
+    if days.is_empty() {
+        return Ok(counts);
+    }
+
+    let store = storage().await?;
+    for day in days {
+        for event in read_archive(store.as_ref(), &day.object_key).await? {
+            if channel_id.is_some_and(|id| *id != event.channel_id)
+                || chatter_id.is_some_and(|id| *id != event.chatter_id)
+            {
+                continue;
+            }
+
+            *counts
+                .entry((event.earned_at.date(), event.chatter_id))
+                .or_default() += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+async fn read_archive(
+    store: &dyn ObjectStore,
+    key: &str,
+) -> ArchiveResult<Vec<ArchivedScoreEvent>> {
+    let bytes = store
+        .get(key)
+        .await?
+        .ok_or_else(|| ArchiveError::Missing(key.to_string()))?;
+
+    tokio::task::spawn_blocking(move || decode(&bytes))
+        .await?
+        .map_err(|reason| ArchiveError::Invalid {
+            key: key.to_string(),
+            reason,
+        })
+}
+
+/// Adds events to those already archived, in the order they were earned.
+fn merge(
+    archived: Vec<ArchivedScoreEvent>,
+    events: Vec<ArchivedScoreEvent>,
+) -> Vec<ArchivedScoreEvent> {
+    let mut merged = archived
+        .into_iter()
+        .chain(events)
+        .map(|event| (event.id, event))
+        .collect::<HashMap<_, _>>()
+        .into_values()
+        .collect::<Vec<_>>();
+    merged.sort_by_key(|event| (event.earned_at, event.id));
+
+    merged
+}
+
+fn encode(events: &[ArchivedScoreEvent]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    writeln!(encoder, "{CSV_HEADER}")?;
+    for event in events {
+        writeln!(
+            encoder,
+            "{},{},{},{},{},{},{}",
+            event.id,
+            csv_field(&event.chatter_id.0),
+            csv_field(&event.channel_id.0),
+            event.keyword_id.0,
+            event.earned_at.format(TIMESTAMP_FORMAT),
+            event.msg_id.as_deref().map(csv_field).unwrap_or_default(),
+            event
+                .flagged_at
+                .map(|at| at.format(TIMESTAMP_FORMAT).to_string())
+                .unwrap_or_default(),
+        )?;
+    }
+
+    encoder.finish()
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<ArchivedScoreEvent>, String> {
+    let mut csv = String::new();
+    GzDecoder::new(bytes)
+        .read_to_string(&mut csv)
+        .map_err(|e| e.to_string())?;
+
+    let mut lines = csv.lines();
+    if lines.next() != Some(CSV_HEADER) {
+        return Err(String::from("unexpected header"));
+    }
+
+    lines
+        .enumerate()
+        .map(|(idx, line)| decode_record(line).map_err(|e| format!("line {}: {e}", idx + 2)))
+        .collect()
+}
+
+fn decode_record(line: &str) -> Result<ArchivedScoreEvent, String> {
+    let fields = split_record(line)?;
+    let [
+        id,
+        chatter_id,
+        channel_id,
+        keyword_id,
+        earned_at,
+        msg_id,
+        flagged_at,
+    ] = <[String; 7]>::try_from(fields).map_err(|fields| format!("{} fields", fields.len()))?;
+
+    let timestamp = |at: &str| {
+        NaiveDateTime::parse_from_str(at, TIMESTAMP_FORMAT).map_err(|e| format!("{at}: {e}"))
+    };
+
+    Ok(ArchivedScoreEvent {
+        id: id.parse().map_err(|_| format!("invalid id '{id}'"))?,
+        chatter_id: ChatterId(chatter_id),
+        channel_id: ChannelId(channel_id),
+        keyword_id: KeywordId(
+            keyword_id
+                .parse()
+                .map_err(|_| format!("invalid keyword id '{keyword_id}'"))?,
+        ),
+        earned_at: timestamp(&earned_at)?,
+        msg_id: (!msg_id.is_empty()).then_some(msg_id),
+        flagged_at: match flagged_at.as_str() {
+            "" => None,
+            at => Some(timestamp(at)?),
+        },
+    })
+}
+
+/// Splits a record written with `csv_field`, unquoting its fields.
+fn split_record(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(String::from("unterminated quote"));
+    }
+    fields.push(field);
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(id: i32, minute: u32, msg_id: Option<&str>) -> ArchivedScoreEvent {
+        let earned_at = NaiveDate::from_ymd_opt(2024, 1, 5)
+            .unwrap()
+            .and_hms_micro_opt(12, minute, 0, 250)
+            .unwrap();
+
+        ArchivedScoreEvent {
+            id,
+            chatter_id: ChatterId(String::from("123456789")),
+            channel_id: ChannelId(String::from("987654321")),
+            keyword_id: KeywordId(1),
+            earned_at,
+            msg_id: msg_id.map(str::to_string),
+            flagged_at: (id % 2 == 0).then_some(earned_at),
+        }
+    }
+
+    #[test]
+    fn archives_round_trip() {
+        let events = vec![
+            event(1, 0, Some("0b5c3e6a-9f1d-4c57-8f0e-2b7d9c1a4e33")),
+            event(2, 1, None),
+            event(3, 2, Some("odd, \"quoted\" id")),
+        ];
+
+        let bytes = encode(&events).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), events);
+        assert!(decode(&encode(&[]).unwrap()).unwrap().is_empty());
+        assert!(decode(b"not gzip").is_err());
+    }
+
+    #[test]
+    fn merging_keeps_each_event_once_in_order() {
+        let archived = vec![event(1, 0, None), event(3, 2, None)];
+        let late = vec![event(2, 1, None), event(3, 2, None)];
+
+        let ids = merge(archived, late)
+            .iter()
+            .map(|event| event.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [1, 2, 3]);
+    }
+}
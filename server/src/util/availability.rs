@@ -0,0 +1,213 @@
+//! Tracks the reachability of Twitch services (IRC and Helix).
+//!
+//! Failures are reported by the IRC connection supervisor and the Helix request helpers; once a
+//! service has failed `FAILURE_THRESHOLD` times in a row it is considered down and the probe task
+//! periodically checks whether the service is reachable again. The IRC supervisor waits for the
+//! probe rather than reconnecting directly; Helix requests are short-circuited by their own
+//! circuit breaker (see `util::breaker`). If both services are down at the same time (i.e. a
+//! Twitch outage), the monitor enters degraded mode until either service recovers.
+
+use std::sync::LazyLock;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::util::http_client;
+
+const FAILURE_THRESHOLD: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const IRC_PROBE_ADDR: &str = "irc.chat.twitch.tv:6697";
+const HELIX_PROBE_URI: &str = "https://api.twitch.tv/helix";
+
+static AVAILABILITY: LazyLock<AvailabilityMonitor> = LazyLock::new(AvailabilityMonitor::default);
+
+/// Retrieves a reference to the global `AvailabilityMonitor`.
+pub fn availability() -> &'static AvailabilityMonitor {
+    &AVAILABILITY
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    Irc,
+    Helix,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Availability {
+    Available,
+    Degraded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AvailabilityStatus {
+    pub state: Availability,
+    pub degraded_since: Option<NaiveDateTime>,
+    pub irc_failures: u32,
+    pub helix_failures: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct AvailabilityMonitor {
+    irc_failures: AtomicU32,
+    helix_failures: AtomicU32,
+    degraded_since: Mutex<Option<NaiveDateTime>>,
+}
+
+impl AvailabilityMonitor {
+    fn failures(&self, service: Service) -> &AtomicU32 {
+        match service {
+            Service::Irc => &self.irc_failures,
+            Service::Helix => &self.helix_failures,
+        }
+    }
+
+    /// Returns the number of consecutive failures recorded for a service.
+    pub fn failures_for(&self, service: Service) -> u32 {
+        self.failures(service).load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the service has failed enough consecutive times to be considered down.
+    pub fn is_down(&self, service: Service) -> bool {
+        self.failures_for(service) >= FAILURE_THRESHOLD
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.degraded_since.lock().unwrap().is_some()
+    }
+
+    pub fn record_failure(&self, service: Service) {
+        let failures = self.failures(service).fetch_add(1, Ordering::Relaxed) + 1;
+        if failures == FAILURE_THRESHOLD {
+            tracing::warn!(?service, failures, "service marked as unreachable");
+        }
+
+        self.update_state();
+    }
+
+    pub fn record_success(&self, service: Service) {
+        if self.failures(service).swap(0, Ordering::Relaxed) >= FAILURE_THRESHOLD {
+            tracing::info!(?service, "service reachable again");
+        }
+
+        self.update_state();
+    }
+
+    fn update_state(&self) {
+        let outage = self.is_down(Service::Irc) && self.is_down(Service::Helix);
+        let mut degraded_since = self.degraded_since.lock().unwrap();
+
+        match (outage, degraded_since.is_some()) {
+            (true, false) => {
+                tracing::error!("twitch unreachable - entering degraded mode");
+                *degraded_since = Some(chrono::Utc::now().naive_utc());
+            }
+            (false, true) => {
+                tracing::info!(since = ?degraded_since, "twitch reachable - leaving degraded mode");
+                *degraded_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn status(&self) -> AvailabilityStatus {
+        let degraded_since = *self.degraded_since.lock().unwrap();
+
+        AvailabilityStatus {
+            state: match degraded_since {
+                Some(_) => Availability::Degraded,
+                None => Availability::Available,
+            },
+            degraded_since,
+            irc_failures: self.failures_for(Service::Irc),
+            helix_failures: self.failures_for(Service::Helix),
+        }
+    }
+
+    /// Waits until a service is no longer considered down.
+    pub async fn wait_until_up(&self, service: Service) {
+        while self.is_down(service) {
+            tokio::time::sleep(PROBE_INTERVAL).await;
+        }
+    }
+
+    /// Spawns the background task that probes any service currently considered down.
+    pub fn spawn_probe(&'static self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PROBE_INTERVAL);
+            loop {
+                interval.tick().await;
+                for service in [Service::Irc, Service::Helix] {
+                    if !self.is_down(service) {
+                        continue;
+                    }
+
+                    if probe(service).await {
+                        self.record_success(service);
+                    } else {
+                        tracing::debug!(?service, "probe failed");
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Checks whether a service is reachable at all; any HTTP response that isn't a server error
+/// counts as reachable for Helix, as we only care about the service being up.
+#[instrument]
+async fn probe(service: Service) -> bool {
+    match service {
+        Service::Irc => matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(IRC_PROBE_ADDR)).await,
+            Ok(Ok(_))
+        ),
+        Service::Helix => {
+            // not through `http_client::send`, as a probe that retries would hide the outage it's checking for
+            match http_client::client()
+                .await
+                .get(HELIX_PROBE_URI)
+                .timeout(PROBE_TIMEOUT)
+                .send()
+                .await
+            {
+                Ok(res) => !res.status().is_server_error(),
+                Err(_) => false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn degraded_only_when_both_services_down() {
+        let monitor = AvailabilityMonitor::default();
+
+        for _ in 0..FAILURE_THRESHOLD {
+            monitor.record_failure(Service::Irc);
+        }
+        assert!(monitor.is_down(Service::Irc));
+        assert!(!monitor.is_degraded());
+
+        for _ in 0..FAILURE_THRESHOLD {
+            monitor.record_failure(Service::Helix);
+        }
+        assert!(monitor.is_degraded());
+        assert_eq!(monitor.status().state, Availability::Degraded);
+
+        monitor.record_success(Service::Helix);
+        assert!(!monitor.is_degraded());
+        assert!(monitor.is_down(Service::Irc));
+    }
+}
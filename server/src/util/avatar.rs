@@ -0,0 +1,229 @@
+//! On-demand validation and refresh of chatter avatars and colors.
+//!
+//! Profile images are hotlinked from Twitch's CDN, and the stored URL stops resolving once a user
+//! changes their avatar. Rather than refreshing every chatter from Helix, the stored URL is
+//! checked with a `HEAD` request when it's requested, and the chatter is only refetched from Helix
+//! if the URL no longer resolves. Successful checks are cached for `CHECK_TTL`, at most
+//! `MAX_CONCURRENT_CHECKS` checks run at once, and Helix refreshes are limited to `MAX_REFRESHES`
+//! per `REFRESH_WINDOW` across all callers.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio::sync::Semaphore;
+use tracing::instrument;
+
+use crate::db::prelude::{Chatter, ChatterId, ChatterRepository, Repository};
+use crate::util::alias;
+use crate::util::helix::{Helix, HelixErr};
+use crate::util::http_client;
+
+const CHECK_TTL: Duration = Duration::from_secs(10 * 60);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CACHED_CHECKS: usize = 10_000;
+const MAX_CONCURRENT_CHECKS: usize = 8;
+const MAX_REFRESHES: usize = 30;
+const REFRESH_WINDOW: Duration = Duration::from_secs(60);
+const CDN_HOST_SUFFIX: &str = ".jtvnw.net";
+
+static AVATAR_CACHE: LazyLock<AvatarCache> = LazyLock::new(AvatarCache::new);
+
+struct AvatarCache {
+    /// When each chatter's image next needs to be checked
+    checked: Mutex<HashMap<ChatterId, Instant>>,
+    /// Bounds the number of in-flight `HEAD` requests to the CDN
+    checks: Semaphore,
+    /// Start times of the Helix refreshes within the current window
+    refreshes: Mutex<VecDeque<Instant>>,
+}
+
+impl AvatarCache {
+    fn new() -> Self {
+        Self {
+            checked: Mutex::new(HashMap::new()),
+            checks: Semaphore::new(MAX_CONCURRENT_CHECKS),
+            refreshes: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn recently_checked(&self, id: &ChatterId) -> bool {
+        self.checked
+            .lock()
+            .unwrap()
+            .get(id)
+            .is_some_and(|until| Instant::now() < *until)
+    }
+
+    /// Skips checking a chatter's image again for `ttl`.
+    fn mark_checked(&self, id: ChatterId, ttl: Duration) {
+        let mut checked = self.checked.lock().unwrap();
+        if checked.len() >= MAX_CACHED_CHECKS {
+            let now = Instant::now();
+            checked.retain(|_, until| now < *until);
+        }
+
+        checked.insert(id, Instant::now() + ttl);
+    }
+
+    /// Takes a refresh permit if fewer than `MAX_REFRESHES` have been used in the last window.
+    fn try_acquire_refresh(&self) -> bool {
+        let mut refreshes = self.refreshes.lock().unwrap();
+        while refreshes
+            .front()
+            .is_some_and(|at| at.elapsed() >= REFRESH_WINDOW)
+        {
+            refreshes.pop_front();
+        }
+
+        if refreshes.len() >= MAX_REFRESHES {
+            return false;
+        }
+
+        refreshes.push_back(Instant::now());
+        true
+    }
+
+    /// Whether `url` resolves, or `None` if too many checks are already in flight.
+    async fn image_resolves(&self, url: &str) -> Option<bool> {
+        if !is_cdn_url(url) {
+            return Some(false);
+        }
+
+        let _permit = self.checks.try_acquire().ok()?;
+
+        let request = http_client::client().await.head(url).timeout(CHECK_TIMEOUT);
+        let resolves = match http_client::send("twitch_cdn", request).await {
+            Ok(res) => res.status().is_success(),
+            Err(e) => {
+                tracing::debug!(error = ?e, url, "avatar check failed");
+                false
+            }
+        };
+
+        Some(resolves)
+    }
+}
+
+/// Only Twitch CDN URLs are checked, so that stored data can't be used to make requests to
+/// arbitrary hosts.
+fn is_cdn_url(url: &str) -> bool {
+    url.strip_prefix("https://")
+        .and_then(|rest| rest.split('/').next())
+        .is_some_and(|host| host.ends_with(CDN_HOST_SUFFIX))
+}
+
+/// Retrieves a chatter, refreshing their image and color from Helix if the stored image URL no
+/// longer resolves.
+///
+/// Returns `None` if the chatter isn't in the database. If the refresh limit has been reached, the
+/// stored (possibly stale) chatter is returned as-is.
+#[instrument(skip(pool))]
+pub async fn fresh_chatter(pool: &'static PgPool, id: &ChatterId) -> AvatarResult<Option<Chatter>> {
+    let repo = ChatterRepository::new(pool);
+    let Some(chatter) = repo.get_by_id(id).await? else {
+        return Ok(None);
+    };
+
+    let cache = &*AVATAR_CACHE;
+    if cache.recently_checked(id) {
+        return Ok(Some(chatter));
+    }
+
+    match cache.image_resolves(&chatter.image).await {
+        Some(true) => {
+            cache.mark_checked(id.clone(), CHECK_TTL);
+            return Ok(Some(chatter));
+        }
+        Some(false) => {}
+        None => {
+            tracing::debug!(chatter_id = %id, "avatar checks saturated - serving stored image");
+            return Ok(Some(chatter));
+        }
+    }
+
+    if !cache.try_acquire_refresh() {
+        tracing::warn!(chatter_id = %id, "avatar refresh limit reached - serving stored image");
+        // don't re-check a known-stale image until a refresh could be made for it
+        cache.mark_checked(id.clone(), REFRESH_WINDOW);
+        return Ok(Some(chatter));
+    }
+
+    let Some(user) = Helix::fetch_users_by_id(&mut [id.0.clone()])
+        .await?
+        .into_iter()
+        .next()
+    else {
+        // deleted or suspended accounts aren't returned by helix
+        tracing::debug!(chatter_id = %id, "chatter not returned by helix - keeping stored image");
+        cache.mark_checked(id.clone(), CHECK_TTL);
+        return Ok(Some(chatter));
+    };
+
+    let refreshed = Chatter {
+        total: chatter.total,
+        private: chatter.private,
+        created_at: chatter.created_at,
+        ..Chatter::from(user)
+    };
+
+    if refreshed.image != chatter.image
+        || refreshed.color != chatter.color
+        || refreshed.login != chatter.login
+    {
+        tracing::info!(
+            chatter_id = %id,
+            old_image = chatter.image,
+            new_image = refreshed.image,
+            "avatar rotated - updating chatter"
+        );
+
+        repo.insert(&refreshed).await?;
+        alias::spawn_merge_for(pool, vec![id.clone()]);
+    }
+
+    cache.mark_checked(id.clone(), CHECK_TTL);
+    Ok(Some(refreshed))
+}
+
+pub type AvatarResult<T> = core::result::Result<T, AvatarError>;
+
+#[derive(Debug, Error)]
+pub enum AvatarError {
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+
+    #[error(transparent)]
+    Helix(#[from] HelixErr),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn only_cdn_urls_are_checked() {
+        assert!(is_cdn_url(
+            "https://static-cdn.jtvnw.net/jtv_user_pictures/abc-profile_image-300x300.png"
+        ));
+        assert!(!is_cdn_url(
+            "http://static-cdn.jtvnw.net/jtv_user_pictures/abc.png"
+        ));
+        assert!(!is_cdn_url("https://jtvnw.net.example.com/abc.png"));
+        assert!(!is_cdn_url(""));
+    }
+
+    #[test]
+    fn checks_are_skipped_until_their_ttl_expires() {
+        let cache = AvatarCache::new();
+        let id = ChatterId::from("12345".to_string());
+
+        assert!(!cache.recently_checked(&id));
+        cache.mark_checked(id.clone(), REFRESH_WINDOW);
+        assert!(cache.recently_checked(&id));
+        cache.mark_checked(id.clone(), Duration::ZERO);
+        assert!(!cache.recently_checked(&id));
+    }
+}
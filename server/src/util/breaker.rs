@@ -0,0 +1,223 @@
+//! Circuit breakers for the dependencies every request leans on (Postgres and Helix).
+//!
+//! A breaker counts consecutive failures; once `threshold` is reached it opens, and callers are
+//! short-circuited for `cool_down` rather than piling more requests onto a dependency that is
+//! already failing. After the cool-down a single request is let through as a probe (half-open):
+//! if it succeeds the breaker closes again, and if it fails the breaker stays open for another
+//! cool-down.
+//!
+//! Each breaker's state is exported as the `circuit_breaker_state` gauge (labelled by `breaker`;
+//! `0` closed, `1` half-open, `2` open) and reported by the health endpoint.
+
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+const POSTGRES_THRESHOLD: u32 = 5;
+const POSTGRES_COOL_DOWN: Duration = Duration::from_secs(15);
+const HELIX_THRESHOLD: u32 = 5;
+const HELIX_COOL_DOWN: Duration = Duration::from_secs(30);
+
+static POSTGRES: LazyLock<CircuitBreaker> =
+    LazyLock::new(|| CircuitBreaker::new("postgres", POSTGRES_THRESHOLD, POSTGRES_COOL_DOWN));
+static HELIX: LazyLock<CircuitBreaker> =
+    LazyLock::new(|| CircuitBreaker::new("helix", HELIX_THRESHOLD, HELIX_COOL_DOWN));
+
+/// Retrieves a reference to the breaker guarding score reads and writes.
+pub fn postgres_breaker() -> &'static CircuitBreaker {
+    &POSTGRES
+}
+
+/// Retrieves a reference to the breaker guarding Helix requests.
+pub fn helix_breaker() -> &'static CircuitBreaker {
+    &HELIX
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    HalfOpen,
+    Open,
+}
+
+impl BreakerState {
+    fn gauge(self) -> f64 {
+        match self {
+            Self::Closed => 0.0,
+            Self::HalfOpen => 1.0,
+            Self::Open => 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// How long until the next probe is let through, while open
+    pub retry_in_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    state: BreakerState,
+    failures: u32,
+    /// When the breaker opened, or when the current probe was let through while half-open
+    since: Instant,
+}
+
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    threshold: u32,
+    cool_down: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, threshold: u32, cool_down: Duration) -> Self {
+        metrics::gauge!("circuit_breaker_state", "breaker" => name).set(0.0);
+
+        Self {
+            name,
+            threshold,
+            cool_down,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                failures: 0,
+                since: Instant::now(),
+            }),
+        }
+    }
+
+    /// Returns whether a request may be made now. While half-open only one probe is let through
+    /// per cool-down - if a probe never reports back, another is allowed once it has passed.
+    pub fn allow(&self) -> bool {
+        let mut inner = self.lock();
+        let allowed = match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::Open | BreakerState::HalfOpen
+                if inner.since.elapsed() >= self.cool_down =>
+            {
+                self.transition(&mut inner, BreakerState::HalfOpen);
+                true
+            }
+            BreakerState::Open | BreakerState::HalfOpen => false,
+        };
+
+        if !allowed {
+            metrics::counter!("circuit_breaker_rejected_total", "breaker" => self.name)
+                .increment(1);
+        }
+
+        allowed
+    }
+
+    pub fn record_success(&self) {
+        let mut inner = self.lock();
+        inner.failures = 0;
+        if inner.state != BreakerState::Closed {
+            tracing::info!(breaker = self.name, "circuit breaker closed");
+            self.transition(&mut inner, BreakerState::Closed);
+        }
+    }
+
+    pub fn record_failure(&self) {
+        let mut inner = self.lock();
+        inner.failures = inner.failures.saturating_add(1);
+
+        match inner.state {
+            BreakerState::Closed if inner.failures >= self.threshold => {
+                tracing::error!(
+                    target: "alert",
+                    breaker = self.name,
+                    failures = inner.failures,
+                    "circuit breaker opened"
+                );
+                self.transition(&mut inner, BreakerState::Open);
+            }
+            BreakerState::HalfOpen => {
+                tracing::warn!(breaker = self.name, "circuit breaker probe failed");
+                self.transition(&mut inner, BreakerState::Open);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.lock().state
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.lock();
+        let retry_in_secs = (inner.state == BreakerState::Open).then(|| {
+            self.cool_down
+                .saturating_sub(inner.since.elapsed())
+                .as_secs()
+        });
+
+        BreakerStatus {
+            state: inner.state,
+            consecutive_failures: inner.failures,
+            retry_in_secs,
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: BreakerState) {
+        inner.state = state;
+        inner.since = Instant::now();
+        metrics::gauge!("circuit_breaker_state", "breaker" => self.name).set(state.gauge());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn opens_after_threshold_and_probes_after_cool_down() {
+        let breaker = CircuitBreaker::new("test", 2, Duration::from_secs(10));
+
+        breaker.record_failure();
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow());
+
+        // a single probe once the cool-down has passed, which reopens the breaker if it fails
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.allow());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(!breaker.allow());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.allow());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+        assert!(breaker.allow());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abandoned_probes_are_retried() {
+        let breaker = CircuitBreaker::new("test", 1, Duration::from_secs(10));
+        breaker.record_failure();
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.allow());
+        assert!(!breaker.allow());
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.allow());
+    }
+}
@@ -5,6 +5,8 @@ use thiserror::Error;
 use tracing::instrument;
 
 use crate::db::prelude::*;
+use crate::irc::error::ConnectionClientError;
+use crate::util::alias;
 use crate::util::helix::{Helix, HelixErr};
 
 #[instrument(skip(chatter), fields(chatter_id = chatter.id.0))]
@@ -115,6 +117,10 @@ pub async fn get_and_refresh_chatter_data(
     existing.extend_from_slice(&fetched);
 
     repo.insert_many(existing).await?;
+
+    let fetched_ids = fetched.iter().map(|f_br| f_br.id.clone()).collect();
+    alias::spawn_merge_for(repo.pool(), fetched_ids);
+
     tracing::debug!(
         refreshed_count = fetched.len(),
         old_total_count = pre_retain,
@@ -140,4 +146,7 @@ pub enum ChannelError {
 
     #[error(transparent)]
     SqlxError(#[from] sqlx::error::Error),
+
+    #[error(transparent)]
+    IrcError(#[from] Box<ConnectionClientError>),
 }
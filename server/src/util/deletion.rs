@@ -0,0 +1,131 @@
+//! Deletes a chatter's data on request.
+//!
+//! Deletion takes two steps: requesting it issues a short-lived confirmation token, and confirming
+//! with that token soft-deletes the chatter. A soft-deleted chatter is left out of leaderboards,
+//! ranks, search and exports, but nothing is removed until `CHATTER_PURGE_DELAY_HOURS` later, when
+//! the purge job removes their rows from Postgres and their legacy keys from Redis.
+
+use std::time::Duration;
+
+use redis::AsyncCommands;
+use ring::digest::{SHA256, SHA256_OUTPUT_LEN, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::deletion::PurgedChatter;
+use crate::db::prelude::{ChatterId, DeletionRepository};
+use crate::db::redis::redis_pool::{RedisResult, redis_pool};
+use crate::db::redis::sync;
+use crate::util::env::Var;
+use crate::var;
+
+/// How long a confirmation token can be used for.
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(60 * 15);
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Returns a new confirmation token, and the hash that's stored in its place.
+pub fn confirmation_token() -> (String, String) {
+    let mut bytes = [0u8; SHA256_OUTPUT_LEN];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system rng failure");
+
+    let token = hex::encode(bytes);
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(digest(&SHA256, token.as_bytes()))
+}
+
+/// How long confirmed deletions wait before being purged.
+pub async fn purge_delay() -> chrono::Duration {
+    let hours = var!(Var::ChatterPurgeDelayHours)
+        .await
+        .ok()
+        .and_then(|hours| hours.trim().parse::<i64>().ok())
+        .unwrap_or(72);
+
+    chrono::Duration::hours(hours.max(0))
+}
+
+/// Purges a soft-deleted chatter from both stores.
+///
+/// Postgres is purged first, as it's the source of truth; if removing the legacy keys then fails,
+/// the error is returned but the purge isn't retried, so the keys have to be removed by hand.
+#[instrument(skip(redis_pool, pool))]
+pub async fn purge_chatter<R: AsyncCommands + Sync>(
+    redis_pool: &mut R,
+    pool: &'static Pool<Postgres>,
+    chatter_id: &ChatterId,
+) -> RedisResult<PurgedChatter> {
+    let repo = DeletionRepository::new(pool);
+    let logins = repo.logins(chatter_id).await?;
+    let mut purged = repo.purge(chatter_id).await?;
+
+    for login in &logins {
+        sync::remove_legacy_chatter(redis_pool, login).await?;
+    }
+
+    purged.legacy_logins = logins;
+    Ok(purged)
+}
+
+/// Purges every chatter whose purge is due.
+#[instrument(skip(pool))]
+pub async fn purge_due(pool: &'static Pool<Postgres>) -> RedisResult<Vec<PurgedChatter>> {
+    let due = DeletionRepository::new(pool).get_due().await?;
+    if due.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut conn = redis_pool().await?.clone();
+    let mut purged = Vec::with_capacity(due.len());
+    for deletion in due {
+        match purge_chatter(&mut conn, pool, &deletion.chatter_id).await {
+            Ok(chatter) => {
+                tracing::info!(?chatter, "purged chatter");
+                purged.push(chatter);
+            }
+            Err(e) => tracing::error!(
+                error = ?e,
+                chatter_id = %deletion.chatter_id,
+                "failed to purge chatter"
+            ),
+        }
+    }
+
+    Ok(purged)
+}
+
+pub fn spawn_chatter_purge(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge_due(pool).await {
+                Ok(purged) if purged.is_empty() => (),
+                Ok(purged) => tracing::info!(count = purged.len(), "purged deleted chatters"),
+                Err(e) => tracing::error!(error = ?e, "failed to purge deleted chatters"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn confirmation_tokens_are_stored_hashed() {
+        let (token, hash) = confirmation_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, hash);
+        assert_eq!(hash_token(&token), hash);
+        assert_ne!(confirmation_token().0, token);
+    }
+}
@@ -0,0 +1,155 @@
+//! Compiles each channel's daily digest - its total, top chatters and biggest gainer for the day -
+//! once its `digest_hour` has passed in the channel's timezone.
+//!
+//! Every instance checks for due digests every `CHECK_INTERVAL`, but a channel only has one digest
+//! per day, so only the instance that stores it publishes a `DigestCompiled` event. Digests are
+//! kept for the API, and delivered from the event to the channel's `daily_digest` Discord webhook
+//! and (when `digest_in_chat` is set and the channel is live) its chat. Days without any scores
+//! are stored but not delivered.
+//!
+//! Chat and Discord share the digest's placeholders (see `placeholders`), so a webhook's template
+//! can use the same `{placeholder}`s as `CHAT_TEMPLATE`.
+
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::digest::Digest;
+use crate::db::prelude::DigestRepository;
+use crate::irc::events::{LiveEvent, events};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(300);
+/// The number of chatters listed in a digest
+const TOP_CHATTERS: i64 = 5;
+
+/// The digest as it's announced in chat
+pub const CHAT_TEMPLATE: &str = "daily digest: {total} counted here in the last day. top chatters: {top_chatters_inline}. {gainer}";
+
+/// Fills the placeholders shared by chat announcements and Discord posts.
+pub fn placeholders(digest: &Digest) -> Vec<(&'static str, String)> {
+    let top = &digest.top_chatters.0;
+    let top_chatters = top
+        .iter()
+        .enumerate()
+        .map(|(idx, chatter)| format!("{}. {} - {}", idx + 1, chatter.login, chatter.score))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let top_chatters_inline = top
+        .iter()
+        .map(|chatter| format!("{} ({})", chatter.login, chatter.score))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let gainer = match &digest.biggest_gainer {
+        Some(gainer) => format!(
+            "biggest gainer: {} (#{} -> #{})",
+            gainer.login, gainer.from_rank, gainer.to_rank
+        ),
+        None => String::from("nobody climbed the leaderboard"),
+    };
+
+    vec![
+        ("channel", digest.channel_login.clone()),
+        ("date", digest.digest_on.format("%Y-%m-%d").to_string()),
+        ("total", digest.total.to_string()),
+        ("top_chatters", top_chatters),
+        ("top_chatters_inline", top_chatters_inline),
+        ("gainer", gainer),
+    ]
+}
+
+/// Compiles every due digest, publishing those compiled by this instance. Returns the number of
+/// digests compiled.
+#[instrument(skip(pool))]
+pub async fn compile_due(pool: &'static Pool<Postgres>) -> sqlx::Result<usize> {
+    let repo = DigestRepository::new(pool);
+
+    let mut compiled = 0;
+    for due in repo.get_due().await? {
+        let Some(digest) = repo.compile(&due, TOP_CHATTERS).await? else {
+            tracing::debug!(channel = %due.channel_id, "digest already compiled elsewhere");
+            continue;
+        };
+
+        tracing::info!(
+            channel = digest.channel_login,
+            digest_on = %digest.digest_on,
+            total = digest.total,
+            "compiled daily digest"
+        );
+        compiled += 1;
+
+        if digest.total > 0 {
+            events().publish(LiveEvent::DigestCompiled(digest));
+        }
+    }
+
+    Ok(compiled)
+}
+
+pub fn spawn_daily_digests(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match compile_due(pool).await {
+                Ok(0) => (),
+                Ok(compiled) => tracing::debug!(compiled, "compiled daily digests"),
+                Err(e) => tracing::error!(error = ?e, "failed to compile daily digests"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::db::models::channel::ChannelId;
+    use crate::db::models::chatter::ChatterId;
+    use crate::db::models::digest::{DigestChatter, DigestGainer};
+    use crate::util::template::render;
+
+    #[test]
+    fn digests_render_in_chat() {
+        let day = NaiveDate::from_ymd_opt(2026, 10, 18).unwrap();
+        let chatter = |id: &str, login: &str, score| DigestChatter {
+            chatter_id: ChatterId(id.to_string()),
+            login: login.to_string(),
+            score,
+        };
+        let mut digest = Digest {
+            id: 1,
+            channel_id: ChannelId(String::from("1")),
+            channel_login: String::from("chan"),
+            digest_on: day,
+            period_start: day.and_hms_opt(0, 0, 0).unwrap(),
+            period_end: day.and_hms_opt(23, 0, 0).unwrap(),
+            total: 15,
+            top_chatters: Json(vec![chatter("2", "a", 12), chatter("3", "b", 3)]),
+            biggest_gainer: Some(Json(DigestGainer {
+                chatter_id: ChatterId(String::from("3")),
+                login: String::from("b"),
+                score: 3,
+                from_rank: 9,
+                to_rank: 2,
+            })),
+            created_at: day.and_hms_opt(23, 0, 0).unwrap(),
+        };
+
+        assert_eq!(
+            render(CHAT_TEMPLATE, &placeholders(&digest)),
+            "daily digest: 15 counted here in the last day. top chatters: a (12), b (3). biggest gainer: b (#9 -> #2)"
+        );
+
+        digest.biggest_gainer = None;
+        assert_eq!(
+            render("{top_chatters}\n{gainer}", &placeholders(&digest)),
+            "1. a - 12\n2. b - 3\nnobody climbed the leaderboard"
+        );
+    }
+}
@@ -20,6 +20,7 @@ pub async fn get_var(var: Var) -> EnvResult<&'static str> {
     let vars = ENV_VARS.get_or_try_init(|| async { Env::new() }).await?;
     Ok(match var {
         Var::ClientId => &vars.client_id,
+        Var::ClientSecret => &vars.client_secret,
         Var::UserLogin => &vars.user_login,
         Var::UserToken => &vars.user_token,
         Var::AppToken => &vars.app_token,
@@ -29,10 +30,62 @@ pub async fn get_var(var: Var) -> EnvResult<&'static str> {
         Var::DatabaseUrl => &vars.database_url,
         Var::RedisUrl => &vars.redis_url,
         Var::CorsAllowOrigins => &vars.cors_allow_origins,
+        Var::OverlayCorsAllowOrigins => &vars.overlay_cors_allow_origins,
         Var::ServerApiPort => &vars.server_api_port,
         Var::OtelExporterEndpoint => &vars.otel_exporter_otlp_endpoint,
         Var::ApiServiceName => &vars.api_service_name,
         Var::ApiTracerName => &vars.api_tracer_name,
+        Var::LogFilter => &vars.log_filter,
+        Var::DatabaseReplicaUrls => &vars.database_replica_urls,
+        Var::ReplicaMaxLagSecs => &vars.replica_max_lag_secs,
+        Var::ScoreDualWrite => &vars.score_dual_write,
+        Var::RaidThanks => &vars.raid_thanks,
+        Var::ScoreModerationPolicy => &vars.score_moderation_policy,
+        Var::ScoreModerationWindowSecs => &vars.score_moderation_window_secs,
+        Var::IrcJoinRate => &vars.irc_join_rate,
+        Var::IrcLeaseTtlSecs => &vars.irc_lease_ttl_secs,
+        Var::InstanceId => &vars.instance_id,
+        Var::ShardCount => &vars.shard_count,
+        Var::ShardIndex => &vars.shard_index,
+        Var::EventSubSecretKey => &vars.eventsub_secret_key,
+        Var::EventSubMaxTotalCost => &vars.eventsub_max_total_cost,
+        Var::EventSubStreamMetrics => &vars.eventsub_stream_metrics,
+        Var::ScoreRateLimitPerMinute => &vars.score_rate_limit_per_minute,
+        Var::ScoreOnePerMessage => &vars.score_one_per_message,
+        Var::KeywordMatchMode => &vars.keyword_match_mode,
+        Var::LeaderboardSnapshotIntervalSecs => &vars.leaderboard_snapshot_interval_secs,
+        Var::ChatLogDir => &vars.chat_log_dir,
+        Var::ChatLogMaxFileMb => &vars.chat_log_max_file_mb,
+        Var::ChatLogRotateSecs => &vars.chat_log_rotate_secs,
+        Var::ChatLogRetainFiles => &vars.chat_log_retain_files,
+        Var::StorageBackend => &vars.storage_backend,
+        Var::StorageDir => &vars.storage_dir,
+        Var::StorageS3Bucket => &vars.storage_s3_bucket,
+        Var::StorageS3Region => &vars.storage_s3_region,
+        Var::StorageS3Endpoint => &vars.storage_s3_endpoint,
+        Var::StorageS3AccessKeyId => &vars.storage_s3_access_key_id,
+        Var::StorageS3SecretAccessKey => &vars.storage_s3_secret_access_key,
+        Var::ScoreArchival => &vars.score_archival,
+        Var::ScoreArchiveRetentionDays => &vars.score_archive_retention_days,
+        Var::ApiTokenKey => &vars.api_token_key,
+        Var::RateLimitIpPerMinute => &vars.rate_limit_ip_per_minute,
+        Var::RateLimitTokenPerMinute => &vars.rate_limit_token_per_minute,
+        Var::RateLimitStore => &vars.rate_limit_store,
+        Var::RateLimitTrustProxy => &vars.rate_limit_trust_proxy,
+        Var::GrpcPort => &vars.grpc_port,
+        Var::ChatterPurgeDelayHours => &vars.chatter_purge_delay_hours,
+        Var::ChatterRefreshIntervalSecs => &vars.chatter_refresh_interval_secs,
+        Var::ChatterRefreshStaleDays => &vars.chatter_refresh_stale_days,
+        Var::ChatterRefreshBatches => &vars.chatter_refresh_batches,
+        Var::ShutdownDrainTimeoutSecs => &vars.shutdown_drain_timeout_secs,
+        Var::TlsCertPath => &vars.tls_cert_path,
+        Var::TlsKeyPath => &vars.tls_key_path,
+        Var::TlsRedirectPort => &vars.tls_redirect_port,
+        Var::HttpClientTimeoutSecs => &vars.http_client_timeout_secs,
+        Var::HttpClientConnectTimeoutSecs => &vars.http_client_connect_timeout_secs,
+        Var::HttpClientProxy => &vars.http_client_proxy,
+        Var::OAuthRedirectUri => &vars.oauth_redirect_uri,
+        Var::OAuthSuccessRedirect => &vars.oauth_success_redirect,
     })
 }
 
@@ -49,10 +102,340 @@ pub struct Env {
     pub database_url: String,
     pub redis_url: String,
     pub cors_allow_origins: String,
+    /// Origins allowed to fetch overlays (`*`, or a suffix such as `.example.com`). Leave unset to
+    /// allow every origin.
+    #[serde(default = "default_overlay_cors_allow_origins")]
+    pub overlay_cors_allow_origins: String,
     pub server_api_port: String,
     pub otel_exporter_otlp_endpoint: String,
     pub api_service_name: String,
     pub api_tracer_name: String,
+    /// Tracing filter in `EnvFilter` syntax (e.g. `info,pea_fan::irc=debug`), which can be
+    /// changed at runtime through `PUT /admin/log-level`. Leave unset for the default filter.
+    #[serde(default)]
+    pub log_filter: String,
+
+    /// Comma-separated list of read replica URLs; leave unset to read from the primary only.
+    #[serde(default)]
+    pub database_replica_urls: String,
+    #[serde(default = "default_replica_max_lag_secs")]
+    pub replica_max_lag_secs: String,
+
+    /// Set to `true` to mirror score increments into the legacy Redis keys while the Redis ->
+    /// Postgres migration is in progress. Only used until the `score_dual_write` feature flag is
+    /// set (see `util::flags`).
+    #[serde(default)]
+    pub score_dual_write: String,
+
+    /// Set to `true` to thank raiders in channels that have replies enabled.
+    #[serde(default)]
+    pub raid_thanks: String,
+
+    /// What to do with score events for messages removed by moderators: `flag` or `rollback`.
+    /// Leave unset to keep them as-is.
+    #[serde(default)]
+    pub score_moderation_policy: String,
+    /// Only events earned this many seconds before the removal are affected.
+    #[serde(default = "default_score_moderation_window_secs")]
+    pub score_moderation_window_secs: String,
+
+    /// Channels joined per 10 seconds when (re)joining; Twitch allows 20 for regular accounts.
+    #[serde(default = "default_irc_join_rate")]
+    pub irc_join_rate: String,
+    /// How long an instance's claim on a channel lasts without being renewed, after which another
+    /// instance can take the channel over. `0` disables coordination, and every channel is joined.
+    #[serde(default = "default_irc_lease_ttl_secs")]
+    pub irc_lease_ttl_secs: String,
+    /// Identifies this instance when sharing channels with others. Defaults to a random id for each
+    /// process, so set it to let a restarted instance pick its leases straight back up.
+    #[serde(default)]
+    pub instance_id: String,
+    /// Number of shards the tracked channels are split between; `1` tracks every channel.
+    #[serde(default = "default_shard_count")]
+    pub shard_count: String,
+    /// This instance's shard, from `0` to `SHARD_COUNT - 1`.
+    #[serde(default = "default_shard_index")]
+    pub shard_index: String,
+
+    /// Hex-encoded 256-bit key that webhook subscription secrets are encrypted with in the
+    /// database. Required outside of debug builds, which store them unencrypted if it's unset.
+    #[serde(default)]
+    pub eventsub_secret_key: String,
+    /// The client's EventSub subscription cost limit; new subscriptions are refused once the
+    /// stored subscriptions' costs reach it.
+    #[serde(default = "default_eventsub_max_total_cost")]
+    pub eventsub_max_total_cost: String,
+    /// Set to `true` to also subscribe tracked channels to subscriptions and cheers. Twitch only
+    /// allows these for broadcasters that have authorized the client.
+    #[serde(default)]
+    pub eventsub_stream_metrics: String,
+
+    /// Maximum score increments per chatter per channel in any rolling minute; extra keyword
+    /// matches are recorded as suppressed. Leave unset for no limit.
+    #[serde(default)]
+    pub score_rate_limit_per_minute: String,
+    /// Set to `true` to count at most one increment per message, overriding a channel's count mode.
+    #[serde(default)]
+    pub score_one_per_message: String,
+    /// How text keywords are matched: `substring` (the default), `word` or `regex`.
+    #[serde(default)]
+    pub keyword_match_mode: String,
+
+    /// How often the day/week/month leaderboards are snapshotted; `0` disables snapshots.
+    #[serde(default = "default_leaderboard_snapshot_interval_secs")]
+    pub leaderboard_snapshot_interval_secs: String,
+
+    /// Directory that chat is logged to as JSONL, one subdirectory per channel. Leave unset to
+    /// disable chat logging.
+    #[serde(default)]
+    pub chat_log_dir: String,
+    /// A channel's log file is rotated once it grows past this many megabytes...
+    #[serde(default = "default_chat_log_max_file_mb")]
+    pub chat_log_max_file_mb: String,
+    /// ...or once it has been open for this many seconds.
+    #[serde(default = "default_chat_log_rotate_secs")]
+    pub chat_log_rotate_secs: String,
+    /// Rotated files kept per channel; older files are deleted. `0` keeps every file.
+    #[serde(default = "default_chat_log_retain_files")]
+    pub chat_log_retain_files: String,
+
+    /// Where exports, snapshots and score event archives are stored: `local` (the default) or
+    /// `s3`, for S3 or a service with an S3-compatible API.
+    #[serde(default)]
+    pub storage_backend: String,
+    /// Directory that the server stores files under with `local` storage.
+    #[serde(default = "default_storage_dir")]
+    pub storage_dir: String,
+    /// Bucket that files are stored in with `s3` storage.
+    #[serde(default)]
+    pub storage_s3_bucket: String,
+    /// The bucket's region. Leave unset to use `AWS_REGION`, or `us-east-1`.
+    #[serde(default)]
+    pub storage_s3_region: String,
+    /// Endpoint of an S3-compatible service (e.g. `http://localhost:9000`). Leave unset for AWS.
+    #[serde(default)]
+    pub storage_s3_endpoint: String,
+    /// Credentials for the bucket. Leave unset to use the usual AWS credentials (`AWS_PROFILE`,
+    /// `AWS_ACCESS_KEY_ID`, the instance's role, ...).
+    #[serde(default)]
+    pub storage_s3_access_key_id: String,
+    #[serde(default)]
+    pub storage_s3_secret_access_key: String,
+
+    /// Set to `true` to move score events to storage once they're older than the retention window.
+    /// Otherwise every score event is kept in the database.
+    #[serde(default)]
+    pub score_archival: String,
+    /// Days score events are kept in the database before they're archived; at least `731`.
+    #[serde(default = "default_score_archive_retention_days")]
+    pub score_archive_retention_days: String,
+
+    /// Hex-encoded key (at least 256 bits) that API tokens are signed with. Leave unset to disable
+    /// API tokens; admin routes then only accept sessions.
+    #[serde(default)]
+    pub api_token_key: String,
+
+    /// Requests per minute each client IP can make to the public API. `0` disables the limit.
+    #[serde(default = "default_rate_limit_ip_per_minute")]
+    pub rate_limit_ip_per_minute: String,
+    /// Requests per minute each API token can make to the public API, in place of the per-IP
+    /// limit. `0` disables the limit.
+    #[serde(default = "default_rate_limit_token_per_minute")]
+    pub rate_limit_token_per_minute: String,
+    /// Where rate limit buckets are kept: `memory` (per instance) or `redis` (shared between
+    /// instances).
+    #[serde(default = "default_rate_limit_store")]
+    pub rate_limit_store: String,
+    /// Set to `true` to take client IPs from `cf-connecting-ip` whichever peer sends it, when
+    /// we're only reachable through a proxy that sets it. Otherwise it's only taken from
+    /// Cloudflare's own addresses.
+    #[serde(default)]
+    pub rate_limit_trust_proxy: String,
+
+    /// Port the gRPC server listens on, when built with the `grpc` feature.
+    #[serde(default = "default_grpc_port")]
+    pub grpc_port: String,
+
+    /// Hours between a chatter's deletion being confirmed and their data being purged; until then
+    /// they're only hidden.
+    #[serde(default = "default_chatter_purge_delay_hours")]
+    pub chatter_purge_delay_hours: String,
+
+    /// How often chatters' names, colors and avatars are refreshed from Helix; `0` disables
+    /// refreshes.
+    #[serde(default = "default_chatter_refresh_interval_secs")]
+    pub chatter_refresh_interval_secs: String,
+    /// Chatters are refreshed once their data is this many days old...
+    #[serde(default = "default_chatter_refresh_stale_days")]
+    pub chatter_refresh_stale_days: String,
+    /// ...in up to this many batches (of 100 chatters) per refresh.
+    #[serde(default = "default_chatter_refresh_batches")]
+    pub chatter_refresh_batches: String,
+
+    /// How long in-flight requests are given to complete once the server starts shutting down.
+    #[serde(default = "default_shutdown_drain_timeout_secs")]
+    pub shutdown_drain_timeout_secs: String,
+
+    /// PEM certificate chain to serve HTTPS with, when built with the `tls` feature. Leave unset
+    /// (along with `TLS_KEY_PATH`) to serve plain HTTP.
+    #[serde(default)]
+    pub tls_cert_path: String,
+    /// PEM private key for `TLS_CERT_PATH`.
+    #[serde(default)]
+    pub tls_key_path: String,
+    /// Port that plain HTTP requests are redirected to HTTPS from while serving over TLS. Leave
+    /// unset to not listen for HTTP at all.
+    #[serde(default)]
+    pub tls_redirect_port: String,
+
+    /// How long outbound requests (e.g. to Helix) are given to complete, in seconds.
+    #[serde(default = "default_http_client_timeout_secs")]
+    pub http_client_timeout_secs: String,
+    /// How long connecting for an outbound request is given, in seconds.
+    #[serde(default = "default_http_client_connect_timeout_secs")]
+    pub http_client_connect_timeout_secs: String,
+    /// Proxy URL that outbound requests are sent through (e.g. `http://proxy:3128`). Leave unset
+    /// to connect directly.
+    #[serde(default)]
+    pub http_client_proxy: String,
+
+    /// The client's secret, for exchanging broadcasters' authorization codes during self-serve
+    /// onboarding. Leave unset to disable onboarding.
+    #[serde(default)]
+    pub client_secret: String,
+    /// Where Twitch sends broadcasters back to after they authorize the client; must match a
+    /// redirect URL registered for the client, and route to `/api/v1/auth/twitch/callback`.
+    #[serde(default)]
+    pub oauth_redirect_uri: String,
+    /// Where broadcasters are sent once their channel is onboarded, with `?login=` appended.
+    /// Leave unset to respond with JSON instead.
+    #[serde(default)]
+    pub oauth_success_redirect: String,
+}
+
+#[inline]
+fn default_overlay_cors_allow_origins() -> String {
+    String::from("*")
+}
+
+#[inline]
+fn default_replica_max_lag_secs() -> String {
+    String::from("10")
+}
+
+#[inline]
+fn default_score_moderation_window_secs() -> String {
+    String::from("300")
+}
+
+#[inline]
+fn default_irc_join_rate() -> String {
+    String::from("20")
+}
+
+#[inline]
+fn default_irc_lease_ttl_secs() -> String {
+    String::from("30")
+}
+
+#[inline]
+fn default_shard_count() -> String {
+    String::from("1")
+}
+
+#[inline]
+fn default_shard_index() -> String {
+    String::from("0")
+}
+
+#[inline]
+fn default_eventsub_max_total_cost() -> String {
+    String::from("10000")
+}
+
+#[inline]
+fn default_leaderboard_snapshot_interval_secs() -> String {
+    String::from("300")
+}
+
+#[inline]
+fn default_chat_log_max_file_mb() -> String {
+    String::from("64")
+}
+
+#[inline]
+fn default_chat_log_rotate_secs() -> String {
+    String::from("86400")
+}
+
+#[inline]
+fn default_chat_log_retain_files() -> String {
+    String::from("30")
+}
+
+#[inline]
+fn default_storage_dir() -> String {
+    String::from("storage")
+}
+
+#[inline]
+fn default_score_archive_retention_days() -> String {
+    String::from("731")
+}
+
+#[inline]
+fn default_rate_limit_ip_per_minute() -> String {
+    String::from("120")
+}
+
+#[inline]
+fn default_rate_limit_token_per_minute() -> String {
+    String::from("600")
+}
+
+#[inline]
+fn default_rate_limit_store() -> String {
+    String::from("memory")
+}
+
+#[inline]
+fn default_grpc_port() -> String {
+    String::from("50051")
+}
+
+#[inline]
+fn default_chatter_purge_delay_hours() -> String {
+    String::from("72")
+}
+
+#[inline]
+fn default_chatter_refresh_interval_secs() -> String {
+    String::from("3600")
+}
+
+#[inline]
+fn default_chatter_refresh_stale_days() -> String {
+    String::from("7")
+}
+
+#[inline]
+fn default_chatter_refresh_batches() -> String {
+    String::from("10")
+}
+
+#[inline]
+fn default_shutdown_drain_timeout_secs() -> String {
+    String::from("30")
+}
+
+#[inline]
+fn default_http_client_timeout_secs() -> String {
+    String::from("30")
+}
+
+#[inline]
+fn default_http_client_connect_timeout_secs() -> String {
+    String::from("10")
 }
 
 impl Env {
@@ -64,7 +447,7 @@ impl Env {
 #[derive(Debug)]
 pub enum Var {
     ClientId,
-    // ClientSecret,
+    ClientSecret,
     UserLogin,
     UserToken,
     CallbackUrl,
@@ -74,10 +457,62 @@ pub enum Var {
     DatabaseUrl,
     RedisUrl,
     CorsAllowOrigins,
+    OverlayCorsAllowOrigins,
     ServerApiPort,
     OtelExporterEndpoint,
     ApiServiceName,
     ApiTracerName,
+    LogFilter,
+    DatabaseReplicaUrls,
+    ReplicaMaxLagSecs,
+    ScoreDualWrite,
+    RaidThanks,
+    ScoreModerationPolicy,
+    ScoreModerationWindowSecs,
+    IrcJoinRate,
+    IrcLeaseTtlSecs,
+    InstanceId,
+    ShardCount,
+    ShardIndex,
+    EventSubSecretKey,
+    EventSubMaxTotalCost,
+    EventSubStreamMetrics,
+    ScoreRateLimitPerMinute,
+    ScoreOnePerMessage,
+    KeywordMatchMode,
+    LeaderboardSnapshotIntervalSecs,
+    ChatLogDir,
+    ChatLogMaxFileMb,
+    ChatLogRotateSecs,
+    ChatLogRetainFiles,
+    StorageBackend,
+    StorageDir,
+    StorageS3Bucket,
+    StorageS3Region,
+    StorageS3Endpoint,
+    StorageS3AccessKeyId,
+    StorageS3SecretAccessKey,
+    ScoreArchival,
+    ScoreArchiveRetentionDays,
+    ApiTokenKey,
+    RateLimitIpPerMinute,
+    RateLimitTokenPerMinute,
+    RateLimitStore,
+    RateLimitTrustProxy,
+    GrpcPort,
+    ChatterPurgeDelayHours,
+    ChatterRefreshIntervalSecs,
+    ChatterRefreshStaleDays,
+    ChatterRefreshBatches,
+    ShutdownDrainTimeoutSecs,
+    TlsCertPath,
+    TlsKeyPath,
+    TlsRedirectPort,
+    HttpClientTimeoutSecs,
+    HttpClientConnectTimeoutSecs,
+    HttpClientProxy,
+    OAuthRedirectUri,
+    OAuthSuccessRedirect,
 }
 
 #[macro_export]
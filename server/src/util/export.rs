@@ -0,0 +1,282 @@
+//! Bulk export of channel leaderboards as CSV or JSON.
+//!
+//! Leaderboards are read through a cursor (see `ExportRepository`) and encoded batch-by-batch, so
+//! an export is never held in memory in full - whether it's streamed as a chunked HTTP response or
+//! written to a file by the `export` subcommand:
+//!
+//! ```sh
+//! piss-fan-server export <CHANNEL_LOGIN> [--format csv|json] [--output <PATH>]
+//! ```
+//!
+//! With `STORAGE_BACKEND=s3`, `<PATH>` is a key in the bucket, and the file is streamed there as a
+//! multipart upload.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use futures::{Stream, StreamExt, TryStreamExt, stream};
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::instrument;
+
+use crate::db::models::export::LeaderboardExportRow;
+use crate::db::prelude::Repository;
+use crate::db::prelude::{ChannelId, ChannelRepository, ChatterRepository, ExportRepository};
+use crate::util::storage::{FileLocation, StorageError};
+
+const CSV_HEADER: &str = "ranking,chatter_id,login,name,score,first_scored_at,last_scored_at\n";
+const USAGE: &str = "usage: export <CHANNEL_LOGIN> [--format csv|json] [--output <PATH>]";
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("no channel with login '{0}'")]
+    UnknownChannel(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(ExportError::Usage(format!("unknown export format '{s}'"))),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Self::Csv => CSV_HEADER,
+            Self::Json => "[",
+        }
+    }
+
+    fn footer(self) -> &'static str {
+        match self {
+            Self::Csv => "",
+            Self::Json => "]\n",
+        }
+    }
+
+    /// Encodes a batch of rows; JSON batches after the first are prefixed with a separator to
+    /// continue the array.
+    fn encode(self, rows: &[LeaderboardExportRow], first_batch: bool) -> String {
+        match self {
+            Self::Csv => rows.iter().map(csv_record).collect(),
+            Self::Json => {
+                let objects = rows
+                    .iter()
+                    .filter_map(|row| serde_json::to_string(row).ok())
+                    .collect::<Vec<_>>()
+                    .join(",\n");
+
+                if first_batch {
+                    format!("\n{objects}")
+                } else {
+                    format!(",\n{objects}")
+                }
+            }
+        }
+    }
+}
+
+/// Streams a channel's full leaderboard, encoded in `format`.
+pub fn channel_leaderboard(
+    pool: &'static Pool<Postgres>,
+    channel_id: ChannelId,
+    format: ExportFormat,
+) -> impl Stream<Item = sqlx::Result<String>> + Send + use<> {
+    let batches = ExportRepository::new(pool)
+        .channel_leaderboard(channel_id)
+        .enumerate()
+        .map(move |(i, batch)| batch.map(|rows| format.encode(&rows, i == 0)));
+
+    stream::once(async move { Ok(format.header().to_string()) })
+        .chain(batches)
+        .chain(stream::once(async move { Ok(format.footer().to_string()) }))
+}
+
+/// Arguments for the `export` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportArgs {
+    pub login: String,
+    pub format: ExportFormat,
+    pub output: PathBuf,
+}
+
+impl ExportArgs {
+    /// Returns `None` unless `export` is the first argument.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Option<Result<Self, ExportError>> {
+        // skip the binary name
+        _ = args.next();
+        if args.next().as_deref() != Some("export") {
+            return None;
+        }
+
+        Some(Self::parse(args))
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, ExportError> {
+        let mut login = None;
+        let mut format = ExportFormat::default();
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => format = args.next().ok_or(usage())?.parse()?,
+                "--output" => output = Some(PathBuf::from(args.next().ok_or(usage())?)),
+                _ if login.is_none() && !arg.starts_with("--") => login = Some(arg.to_lowercase()),
+                _ => return Err(usage()),
+            }
+        }
+
+        let login = login.ok_or(usage())?;
+        let output =
+            output.unwrap_or_else(|| PathBuf::from(format!("{login}.{}", format.extension())));
+
+        Ok(Self {
+            login,
+            format,
+            output,
+        })
+    }
+}
+
+/// Writes a channel's leaderboard to `args.output`.
+#[instrument(skip(pool))]
+pub async fn run(pool: &'static Pool<Postgres>, args: &ExportArgs) -> Result<(), ExportError> {
+    let chatter = match ChatterRepository::new(pool).get_by_login(&args.login).await {
+        Ok(chatter) => chatter,
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(ExportError::UnknownChannel(args.login.clone()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let channel_id = ChannelId::from(chatter.id);
+    if ChannelRepository::new(pool)
+        .get_by_id(&channel_id)
+        .await?
+        .is_none()
+    {
+        return Err(ExportError::UnknownChannel(args.login.clone()));
+    }
+
+    let output = FileLocation::resolve(&args.output).await?;
+    let mut writer = output.writer().await?;
+    let mut chunks = std::pin::pin!(channel_leaderboard(pool, channel_id, args.format));
+    let written = async {
+        while let Some(chunk) = chunks.try_next().await? {
+            writer.write_all(chunk.as_bytes()).await?;
+        }
+
+        Ok::<_, ExportError>(())
+    }
+    .await;
+
+    if let Err(e) = written {
+        writer.abort().await?;
+        return Err(e);
+    }
+    writer.complete().await?;
+
+    tracing::info!(login = args.login, %output, "exported channel leaderboard");
+    Ok(())
+}
+
+fn usage() -> ExportError {
+    ExportError::Usage(USAGE.to_string())
+}
+
+fn csv_record(row: &LeaderboardExportRow) -> String {
+    format!(
+        "{},{},{},{},{},{},{}\n",
+        row.ranking,
+        csv_field(&row.chatter_id.0),
+        csv_field(&row.login),
+        csv_field(&row.name),
+        row.score,
+        // matches the JSON (serde) representation
+        row.first_scored_at.format("%Y-%m-%dT%H:%M:%S%.f"),
+        row.last_scored_at.format("%Y-%m-%dT%H:%M:%S%.f"),
+    )
+}
+
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &[&str]) -> Option<Result<ExportArgs, ExportError>> {
+        ExportArgs::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn export_args_from_args() {
+        assert!(args(&["server", "--migrate"]).is_none());
+        assert!(matches!(args(&["server", "export"]), Some(Err(_))));
+        assert!(matches!(
+            args(&["server", "export", "a", "--format", "xml"]),
+            Some(Err(_))
+        ));
+
+        let parsed = args(&["server", "export", "PlsUwu", "--format", "json"])
+            .unwrap()
+            .unwrap();
+        assert_eq!(parsed.login, "plsuwu");
+        assert_eq!(parsed.format, ExportFormat::Json);
+        assert_eq!(parsed.output, PathBuf::from("plsuwu.json"));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plsuwu"), "plsuwu");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
@@ -0,0 +1,381 @@
+//! Runtime feature flags, for rolling out risky behaviour gradually and switching it back off
+//! without a restart.
+//!
+//! Each flag is stored in the `feature_flag` table; flags without a row use their built-in
+//! default. An enabled flag is on for its allowlisted channels, and for `rollout_percent` of the
+//! rest - channels are picked by hashing the flag's name with the channel's id, so a channel stays
+//! picked as the percentage grows, and every instance picks the same channels.
+//!
+//! Flags are checked on hot paths (e.g. for every chat message), so they're read from an in-memory
+//! copy. Postgres announces every change on the `feature_flag` notification channel, and the
+//! listener reloads every flag when one changes, or once it reconnects (as notifications can be
+//! missed while it's disconnected). If a reload fails, the last known states are kept.
+//!
+//! Flags are set through `PUT /_admin/flags/{name}`, and reset to their defaults with
+//! `DELETE /_admin/flags/{name}`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use ring::digest::{SHA256, digest};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::flag::FeatureFlag;
+use crate::db::prelude::{ChannelId, FeatureFlagRepository};
+use crate::util::env::Var;
+use crate::var;
+
+const NOTIFY_CHANNEL: &str = "feature_flag";
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+static FLAGS: LazyLock<FlagCache> = LazyLock::new(FlagCache::default);
+
+/// Retrieves a reference to the global `FlagCache`.
+pub fn flags() -> &'static FlagCache {
+    &FLAGS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// Score increments are mirrored into the legacy Redis keys (see `db::redis::sync`)
+    ScoreDualWrite,
+    /// Chat is received from `channel.chat.message` EventSub notifications rather than IRC
+    #[serde(rename = "eventsub_chat")]
+    EventSubChat,
+    /// Replies are held back in channels whose chat modes would drop them (see `irc::room_state`)
+    ReplySuppression,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [
+        Self::ScoreDualWrite,
+        Self::EventSubChat,
+        Self::ReplySuppression,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ScoreDualWrite => "score_dual_write",
+            Self::EventSubChat => "eventsub_chat",
+            Self::ReplySuppression => "reply_suppression",
+        }
+    }
+
+    /// Whether the flag is on everywhere while it isn't stored. `score_dual_write` keeps following
+    /// `SCORE_DUAL_WRITE` until it's set.
+    async fn default_enabled(self) -> bool {
+        match self {
+            Self::ScoreDualWrite => match var!(Var::ScoreDualWrite).await {
+                Ok(val) => matches!(val.trim().to_lowercase().as_str(), "true" | "1"),
+                Err(_) => false,
+            },
+            Self::EventSubChat => false,
+            Self::ReplySuppression => true,
+        }
+    }
+
+    /// `default_enabled`, before the flags have been loaded for the first time.
+    fn fallback(self) -> bool {
+        matches!(self, Self::ReplySuppression)
+    }
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Flag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|flag| flag.as_str() == s)
+            .ok_or_else(|| format!("unknown feature flag '{s}'"))
+    }
+}
+
+/// A flag's current state, whether stored or its default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlagState {
+    pub flag: Flag,
+    pub enabled: bool,
+    pub rollout_percent: i16,
+    pub channels: Vec<ChannelId>,
+    /// `None` while the flag is on its default
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl FlagState {
+    fn default_for(flag: Flag, enabled: bool) -> Self {
+        Self {
+            flag,
+            enabled,
+            rollout_percent: 100,
+            channels: Vec::new(),
+            updated_at: None,
+        }
+    }
+
+    /// Whether the flag is on for any channel at all.
+    pub fn is_on(&self) -> bool {
+        self.enabled && (self.rollout_percent > 0 || !self.channels.is_empty())
+    }
+
+    /// Whether the flag is on for every channel.
+    pub fn is_fully_on(&self) -> bool {
+        self.enabled && self.rollout_percent >= 100
+    }
+
+    pub fn is_on_for(&self, channel_id: &ChannelId) -> bool {
+        self.enabled
+            && (self.channels.contains(channel_id)
+                || rollout_bucket(self.flag, channel_id) < self.rollout_percent)
+    }
+}
+
+impl TryFrom<FeatureFlag> for FlagState {
+    type Error = String;
+
+    fn try_from(stored: FeatureFlag) -> Result<Self, Self::Error> {
+        Ok(Self {
+            flag: stored.name.parse()?,
+            enabled: stored.enabled,
+            rollout_percent: stored.rollout_percent,
+            channels: stored.channels,
+            updated_at: Some(stored.updated_at),
+        })
+    }
+}
+
+/// Where a channel falls in a flag's rollout, from 0 to 99; the flag is on for the channel while
+/// its rollout percentage is above this.
+fn rollout_bucket(flag: Flag, channel_id: &ChannelId) -> i16 {
+    let hash = digest(
+        &SHA256,
+        format!("{}:{}", flag.as_str(), channel_id.0).as_bytes(),
+    );
+    let mut key = [0; 8];
+    key.copy_from_slice(&hash.as_ref()[..8]);
+
+    (u64::from_be_bytes(key) % 100) as i16
+}
+
+#[derive(Debug, Default)]
+pub struct FlagCache {
+    states: RwLock<HashMap<Flag, FlagState>>,
+}
+
+impl FlagCache {
+    /// Whether the flag is on for any channel; for behaviour that isn't tied to a channel.
+    pub fn is_on(&self, flag: Flag) -> bool {
+        self.with(flag, FlagState::is_on)
+    }
+
+    /// Whether the flag is on for every channel.
+    pub fn is_fully_on(&self, flag: Flag) -> bool {
+        self.with(flag, FlagState::is_fully_on)
+    }
+
+    pub fn is_on_for(&self, flag: Flag, channel_id: &ChannelId) -> bool {
+        self.with(flag, |state| state.is_on_for(channel_id))
+    }
+
+    pub fn get(&self, flag: Flag) -> FlagState {
+        self.with(flag, FlagState::clone)
+    }
+
+    /// Every flag's current state.
+    pub fn all(&self) -> Vec<FlagState> {
+        Flag::ALL.into_iter().map(|flag| self.get(flag)).collect()
+    }
+
+    /// Reloads every flag. If the stored flags can't be read, the last known states are kept (or
+    /// the defaults, if they've never been loaded).
+    #[instrument(skip(self, pool))]
+    pub async fn reload(&self, pool: &'static Pool<Postgres>) -> sqlx::Result<()> {
+        let stored = FeatureFlagRepository::new(pool).get_all().await;
+
+        let mut states = HashMap::new();
+        for flag in Flag::ALL {
+            states.insert(
+                flag,
+                FlagState::default_for(flag, flag.default_enabled().await),
+            );
+        }
+
+        let stored = match stored {
+            Ok(stored) => stored,
+            Err(e) => {
+                let mut current = self.states.write().unwrap_or_else(|e| e.into_inner());
+                if current.is_empty() {
+                    *current = states;
+                }
+
+                return Err(e);
+            }
+        };
+
+        for flag in stored {
+            match FlagState::try_from(flag) {
+                Ok(state) => _ = states.insert(state.flag, state),
+                // e.g. a flag that's since been removed
+                Err(e) => tracing::debug!(error = e, "ignoring stored feature flag"),
+            }
+        }
+
+        tracing::debug!(?states, "feature flags loaded");
+        *self.states.write().unwrap_or_else(|e| e.into_inner()) = states;
+        Ok(())
+    }
+
+    fn with<T>(&self, flag: Flag, f: impl FnOnce(&FlagState) -> T) -> T {
+        let states = self.states.read().unwrap_or_else(|e| e.into_inner());
+        match states.get(&flag) {
+            Some(state) => f(state),
+            None => f(&FlagState::default_for(flag, flag.fallback())),
+        }
+    }
+}
+
+/// Loads the flags, then listens for changes to them for as long as the server runs. The flags
+/// are loaded before this returns, so that anything started afterwards sees their stored states.
+pub async fn spawn_flag_listener(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    if let Err(e) = flags().reload(pool).await {
+        tracing::error!(error = ?e, "failed to load feature flags - using defaults");
+    }
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(pool).await {
+                tracing::error!(error = ?e, "feature flag listener failure");
+            }
+
+            tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+
+            // anything that changed while we weren't listening has to be reloaded
+            if let Err(e) = flags().reload(pool).await {
+                tracing::error!(error = ?e, "failed to reload feature flags");
+            }
+        }
+    })
+}
+
+async fn listen(pool: &'static Pool<Postgres>) -> sqlx::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(NOTIFY_CHANNEL).await?;
+    tracing::info!(
+        channel = NOTIFY_CHANNEL,
+        "listening for feature flag changes"
+    );
+
+    loop {
+        match listener.try_recv().await? {
+            Some(notification) => {
+                tracing::info!(flag = notification.payload(), "feature flag changed");
+            }
+            None => tracing::warn!("feature flag listener reconnected"),
+        }
+
+        flags().reload(pool).await?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn channel(id: usize) -> ChannelId {
+        ChannelId((100_000_000 + id).to_string())
+    }
+
+    fn state(enabled: bool, rollout_percent: i16, channels: Vec<ChannelId>) -> FlagState {
+        FlagState {
+            flag: Flag::EventSubChat,
+            enabled,
+            rollout_percent,
+            channels,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn flags_parse_from_their_names() {
+        for flag in Flag::ALL {
+            assert_eq!(flag.as_str().parse::<Flag>(), Ok(flag));
+            assert_eq!(
+                serde_json::to_value(flag).unwrap(),
+                serde_json::json!(flag.as_str())
+            );
+        }
+
+        assert!("reply_suppresion".parse::<Flag>().is_err());
+    }
+
+    #[test]
+    fn rollouts_grow_without_dropping_channels() {
+        let channels: Vec<_> = (0..1_000).map(channel).collect();
+        let on = |percent| {
+            channels
+                .iter()
+                .filter(|id| state(true, percent, Vec::new()).is_on_for(id))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        assert!(on(0).is_empty());
+        assert_eq!(on(100).len(), channels.len());
+
+        let (quarter, half) = (on(25), on(50));
+        assert!(quarter.iter().all(|id| half.contains(id)));
+        // roughly the requested share, with some leeway for the hash
+        assert!((150..=350).contains(&quarter.len()), "{}", quarter.len());
+
+        // different flags pick different channels
+        let dual_write = FlagState {
+            flag: Flag::ScoreDualWrite,
+            ..state(true, 25, Vec::new())
+        };
+        assert!(
+            channels
+                .iter()
+                .filter(|id| dual_write.is_on_for(id))
+                .any(|id| !quarter.contains(id))
+        );
+    }
+
+    #[test]
+    fn allowlisted_channels_are_on_while_enabled() {
+        let (a, b) = (channel(1), channel(2));
+
+        let allowlist = state(true, 0, vec![a.clone()]);
+        assert!(allowlist.is_on_for(&a));
+        assert!(!allowlist.is_on_for(&b));
+        assert!(allowlist.is_on());
+        assert!(!allowlist.is_fully_on());
+
+        let disabled = state(false, 100, vec![a.clone()]);
+        assert!(!disabled.is_on_for(&a));
+        assert!(!disabled.is_on());
+    }
+
+    #[test]
+    fn unloaded_flags_use_their_fallbacks() {
+        let cache = FlagCache::default();
+        assert!(cache.is_on(Flag::ReplySuppression));
+        assert!(!cache.is_on(Flag::EventSubChat));
+        assert!(!cache.is_on_for(Flag::ScoreDualWrite, &channel(1)));
+        assert_eq!(cache.all().len(), Flag::ALL.len());
+    }
+}
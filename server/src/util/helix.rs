@@ -1,11 +1,13 @@
 // #![allow(dead_code)]
 
 use core::fmt;
-use std::sync::LazyLock;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures::stream::FuturesUnordered;
-use futures::{StreamExt, stream};
+use futures::{Stream, StreamExt, TryStreamExt, stream};
 use http::header::{AUTHORIZATION, InvalidHeaderValue};
 use http::{HeaderMap, HeaderValue, StatusCode};
 use reqwest::Response;
@@ -16,11 +18,14 @@ use thiserror::Error;
 use tokio::sync::OnceCell;
 use tracing::{Instrument, error, instrument, warn};
 
-use crate::api::middleware::{MiddlewareErr, verify_external};
-use crate::api::webhook::{HelixDataGeneric, SubscriptionGenericData};
-use crate::api::webhook::{StreamGenericRequest, StreamGenericRequestType};
+use crate::api::middleware::MiddlewareErr;
+use crate::api::webhook::subscriber::SubscriptionRequest;
+use crate::api::webhook::{HelixDataGeneric, HelixPagination, SubscriptionGenericData};
 use crate::db::prelude::{ChannelId, ChatterId};
+use crate::util::availability::{Service, availability};
+use crate::util::breaker::helix_breaker;
 use crate::util::env::{EnvErr, Var};
+use crate::util::http_client;
 use crate::var;
 
 #[async_trait]
@@ -112,15 +117,13 @@ impl Helix {
     /// Makes a GET request
     #[instrument]
     async fn send(uri: &str) -> HelixResult<reqwest::Response> {
-        let client = reqwest::Client::new();
+        Self::ensure_available()?;
+        let client = http_client::client().await;
         let headers = auth_headers().await?.bearer.clone();
 
-        client
-            .get(uri)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(HelixErr::ReqwestError)
+        let res = http_client::send(HELIX_SERVICE, client.get(uri).headers(headers)).await;
+
+        Self::observe(res)
     }
 
     /// Retrieve the state of a stream (online/offline) plus some extra metadata:
@@ -133,9 +136,16 @@ impl Helix {
         let params = build_query_params(HelixParamType::UserId, ids);
 
         for param in params {
-            let uri = format!("{}{}&type=live", String::from(HelixUri::Streams), param);
-            match Self::fetch_users::<RawHelixStream>(uri).await {
-                Ok(data) => all_streams.extend(data.data.into_iter()),
+            let uri = format!(
+                "{}{}&type=live&first={HELIX_PAGE_SIZE}",
+                String::from(HelixUri::Streams),
+                param
+            );
+            match Self::paginate::<HelixStream>(uri)
+                .try_collect::<Vec<_>>()
+                .await
+            {
+                Ok(streams) => all_streams.extend(streams),
                 Err(e) => {
                     tracing::error!(error = ?e, "failed to get stream data");
                     return Err(e);
@@ -150,15 +160,13 @@ impl Helix {
     /// Makes a DELETE request
     #[instrument]
     async fn delete(uri: String) -> HelixResult<reqwest::Response> {
-        let client = reqwest::Client::new();
+        Self::ensure_available()?;
+        let client = http_client::client().await;
         let headers = auth_headers().await?.bearer.clone();
 
-        client
-            .delete(uri)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(HelixErr::ReqwestError)
+        let res = http_client::send(HELIX_SERVICE, client.delete(uri).headers(headers)).await;
+
+        Self::observe(res)
     }
 
     /// Makes a POST request
@@ -167,16 +175,49 @@ impl Helix {
     where
         T: Serialize + fmt::Debug + ?Sized,
     {
-        let client = reqwest::Client::new();
+        Self::ensure_available()?;
+        let client = http_client::client().await;
         let headers = auth_headers().await?.bearer.clone();
 
-        client
-            .post(uri)
-            .json(body)
-            .headers(headers)
-            .send()
-            .await
-            .map_err(HelixErr::ReqwestError)
+        let request = client.post(uri).json(body).headers(headers);
+        let res = http_client::send(HELIX_SERVICE, request).await;
+
+        Self::observe(res)
+    }
+
+    /// Fails fast while the Helix circuit breaker is open, rather than adding to the pile of
+    /// requests timing out during an outage; once it has cooled down, a single request is let
+    /// through to check whether Helix has recovered.
+    fn ensure_available() -> HelixResult<()> {
+        if !helix_breaker().allow() {
+            return Err(HelixErr::Unavailable);
+        }
+
+        Ok(())
+    }
+
+    /// Reports the outcome of a request to the circuit breaker and the availability monitor, and
+    /// records the rate limit it reported.
+    fn observe(res: reqwest::Result<Response>) -> HelixResult<Response> {
+        match &res {
+            Ok(r) if !r.status().is_server_error() => {
+                helix_breaker().record_success();
+                availability().record_success(Service::Helix);
+            }
+            _ => {
+                helix_breaker().record_failure();
+                availability().record_failure(Service::Helix);
+            }
+        }
+
+        if let Ok(r) = &res
+            && let Some(rate_limit) = RateLimit::from_headers(r.headers())
+        {
+            tracing::trace!(?rate_limit, "rate-limit bucket");
+            *RATE_LIMIT.lock().unwrap() = Some(rate_limit);
+        }
+
+        res.map_err(HelixErr::ReqwestError)
     }
 
     #[instrument(skip(res))]
@@ -225,22 +266,6 @@ impl Helix {
             return Err(Self::parse_errored_response(res).await);
         }
 
-        // TODO:
-        //  rate limit "handling"
-        let rl_remaining = res.headers().get("ratelimit-remaining");
-        let rl_total = res.headers().get("ratelimit-limit");
-
-        if let Some(remaining) = rl_remaining
-            && let Some(total) = rl_total
-        {
-            tracing::info!(
-                ratelimit_available = ?remaining,
-                ratelimit_total = ?total,
-                "rate-limit bucket"
-            );
-            // ... implement some kind of backoff if we start to saturate this limit
-        }
-
         res.json::<T>().await.map_err(HelixErr::ReqwestError)
     }
 
@@ -367,60 +392,73 @@ impl Helix {
 
     #[instrument]
     pub async fn get_active_subscription_ids() -> HelixResult<Vec<String>> {
-        let body =
-            Self::get_active_subscriptions_raw(&String::from(HelixUri::WebhookSubscriptions))
-                .await?;
-
-        let result = body.data.into_iter().map(|sub| sub.id).collect();
-
-        return Ok(result);
+        Self::paginate::<SubscriptionGenericData>(String::from(HelixUri::WebhookSubscriptions))
+            .map_ok(|sub| sub.id)
+            .try_collect()
+            .await
     }
 
     #[instrument]
     pub async fn get_active_subscriptions() -> HelixResult<Vec<SubscriptionGenericData>> {
-        let mut out = Vec::new();
-        let mut curr_page = 1;
-
-        let mut page_result =
-            Self::get_active_subscriptions_raw(&String::from(HelixUri::WebhookSubscriptions))
-                .await?;
-
-        tracing::debug!(curr_page, ?page_result, "fetched raw hook subscriptions");
+        let subscriptions: Vec<_> = Self::paginate(String::from(HelixUri::WebhookSubscriptions))
+            .try_collect()
+            .await?;
 
-        out.extend(page_result.data);
+        tracing::debug!(count = subscriptions.len(), "fetched hook subscriptions");
+        Ok(subscriptions)
+    }
 
-        while let Some(cursor) = &page_result.pagination.cursor {
-            let request_page = PaginatedHelixRequest {
-                cursor: cursor.clone(),
-                params: HelixParamType::After,
+    /// Streams every item from a cursor-paginated list endpoint, following each page's
+    /// `pagination.cursor` until the last page. `uri` is the first page's URI, with any query
+    /// params the endpoint needs.
+    ///
+    /// Pages are only requested as the stream is polled. Before requesting each page after the
+    /// first, waits for the rate limit bucket to refill if fewer than `PAGINATION_RESERVE` points
+    /// are left in it, so that a long listing doesn't starve other requests.
+    pub fn paginate<T>(uri: String) -> impl Stream<Item = HelixResult<T>> + Send
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        stream::try_unfold(Some((uri, None)), |state| async move {
+            let Some((uri, cursor)) = state else {
+                return Ok(None);
             };
 
-            let uri = Self::build_subscription_uri(request_page);
-            page_result = Self::get_active_subscriptions_raw(&uri).await?;
+            let page_uri = match &cursor {
+                Some(cursor) => {
+                    if let Some(wait) = RateLimit::current()
+                        .and_then(|r| r.wait(PAGINATION_RESERVE, Utc::now().timestamp()))
+                    {
+                        tracing::debug!(?wait, "waiting for the helix rate limit to refill");
+                        tokio::time::sleep(wait).await;
+                    }
 
-            curr_page += 1;
-            tracing::debug!(curr_page, ?page_result, "fetched raw hook subscriptions");
+                    let separator = if uri.contains('?') { '&' } else { '?' };
+                    format!(
+                        "{uri}{separator}{}{cursor}",
+                        String::from(HelixParamType::After)
+                    )
+                }
+                None => uri.clone(),
+            };
 
-            out.extend(page_result.data);
-        }
+            let res = Self::send(&page_uri).await?;
+            if !res.status().is_success() {
+                return Err(Self::parse_errored_response(res).await);
+            }
 
-        Ok(out)
-    }
+            let page: HelixPage<T> = res.json().await?;
+            tracing::trace!(items = page.data.len(), ?cursor, "fetched helix page");
 
-    #[instrument]
-    pub fn build_subscription_uri(request_page: PaginatedHelixRequest) -> String {
-        // let params = build_query_params(request_page.params, &[request_page.cursor.to_string()]);
-        //
-        // `build_query_params` calls `to_lowercase()` on the param value?? why would i do
-        // that?????? anyway,
+            let next = page
+                .pagination
+                .cursor
+                .filter(|cursor| !cursor.is_empty())
+                .map(|cursor| (uri, Some(cursor)));
 
-        // let query = format!("?{}{}", String::from(request_page.params), request_page.cursor);
-        format!(
-            "{}?{}{}",
-            String::from(HelixUri::WebhookSubscriptions),
-            String::from(request_page.params),
-            request_page.cursor,
-        )
+            Ok(Some((stream::iter(page.data.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
     }
 
     #[instrument]
@@ -442,20 +480,14 @@ impl Helix {
         Ok(body)
     }
 
-    #[instrument]
+    /// See `api::webhook::subscriber` for building the request.
+    // the transport holds the secret and callback path, so the request isn't logged
+    #[instrument(skip(request), fields(subscription_type = request.r#type))]
     pub async fn create_subscription(
-        id: ChannelId,
-        notif_type: StreamGenericRequestType,
+        request: &SubscriptionRequest,
     ) -> HelixResult<SubscriptionGenericData> {
-        let callback_url = var!(Var::CallbackUrl).await?;
-
-        tracing::info!(callback_url, "using callback uri");
-
-        let key = verify_external::get_hmac_key().await?;
-        let body = StreamGenericRequest::new(&id.to_string(), callback_url, &key, notif_type);
-
         let uri = String::from(HelixUri::WebhookSubscriptions);
-        let response = Self::post(uri, &body).await?;
+        let response = Self::post(uri, request).await?;
 
         tracing::trace!(?response, "received raw response");
 
@@ -474,16 +506,15 @@ impl Helix {
             return Err(HelixErr::FetchErr(deserialized_body.to_string()));
         }
 
-        if let Some(data_body) = &deserialized_body["data"].as_array() {
-            let element = &data_body[0];
-
-            let subscription_type = element["type"].as_str().unwrap();
-            let broadcaster_id = element["condition"]["broadcaster_user_id"]
-                .as_str()
-                .unwrap();
+        if let Some(element) = deserialized_body["data"].get(0) {
+            let subscription: SubscriptionGenericData = serde_json::from_value(element.clone())?;
+            tracing::info!(
+                subscription_type = subscription.r#type,
+                broadcaster_id = subscription.condition.broadcaster_user_id,
+                "created subscription"
+            );
 
-            tracing::info!(subscription_type, broadcaster_id, "created subscription");
-            return Ok(serde_json::from_value(element.clone())?);
+            return Ok(subscription);
         }
 
         tracing::error!(body = ?deserialized_body, "failed to parse sub creation response");
@@ -513,27 +544,82 @@ impl Helix {
 
         Ok(())
     }
+
+    /// Whispers `message` to a user from the bot's account (`USER_LOGIN`).
+    ///
+    /// Unlike the other requests, this is made with `USER_TOKEN`, which needs to have been issued
+    /// to `CLIENT_ID` with the `user:manage:whispers` scope.
+    #[instrument(skip(message))]
+    pub async fn send_whisper(to_user_id: &ChatterId, message: &str) -> HelixResult<()> {
+        let from_user_id = Self::bot_user_id().await?;
+        let uri = format!(
+            "{}?from_user_id={from_user_id}&to_user_id={to_user_id}",
+            String::from(HelixUri::Whispers)
+        );
+
+        Self::ensure_available()?;
+        let client = http_client::client().await;
+        let headers = auth_headers().await?.user.clone();
+
+        let body = serde_json::json!({ "message": message });
+        let request = client.post(uri).json(&body).headers(headers);
+        let res = http_client::send(HELIX_SERVICE, request).await;
+        let response = Self::observe(res)?;
+
+        // twitch responds with `204 No Content` once the whisper is sent
+        if response.status() != StatusCode::NO_CONTENT {
+            let status = response.status();
+            let body = response.text().await?;
+            tracing::error!(%status, body, "whisper was rejected");
+
+            return Err(HelixErr::FetchErr(body));
+        }
+
+        Ok(())
+    }
+
+    /// The bot account's user id, looked up from `USER_LOGIN` on first use.
+    async fn bot_user_id() -> HelixResult<&'static str> {
+        BOT_USER_ID
+            .get_or_try_init(|| async {
+                let login = var!(Var::UserLogin).await?.to_lowercase();
+                Self::fetch_users_by_login(vec![login])
+                    .await?
+                    .into_iter()
+                    .next()
+                    .map(|user| user.id)
+                    .ok_or(HelixErr::EmptyDataField)
+            })
+            .await
+            .map(String::as_str)
+    }
 }
 
+static BOT_USER_ID: LazyLock<OnceCell<String>> = LazyLock::new(OnceCell::new);
+
+/// The largest page list endpoints return (as `first=`)
+const HELIX_PAGE_SIZE: usize = 100;
+/// Rate limit points left for other requests while paginating
+const PAGINATION_RESERVE: u32 = 20;
+
+/// Labels Helix requests' metrics; see `util::http_client`
+const HELIX_SERVICE: &str = "helix";
+
 pub const HELIX_URI_BASE: &str = "https://api.twitch.tv/helix";
 pub const HELIX_URN_USERS: &str = "users";
 pub const HELIX_URN_STREAMS: &str = "streams";
 pub const HELIX_URN_COLORS: &str = "chat/color";
 pub const HELIX_WEBHOOK_SUBS: &str = "eventsub/subscriptions";
+pub const HELIX_URN_WHISPERS: &str = "whispers";
 const NUM_WORKER_THREADS: usize = 25;
 
-// TODO pull this from .env instead
-// pub const CALLBACK_ROUTE: &str = "https://api.piss.fan/callback";
-
-// pub const CALLBACK_ROUTE: &str = "https://api.rat.moe/callback";
-// pub const CALLBACK_ROUTE: &str = "http://api.rat.moe/example-callback";
-
 #[derive(Debug)]
 pub enum HelixUri {
     Users,
     Streams,
     Colors,
     WebhookSubscriptions,
+    Whispers,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -571,6 +657,7 @@ impl From<HelixUri> for String {
                 HelixUri::Streams => HELIX_URN_STREAMS,
                 HelixUri::Colors => HELIX_URN_COLORS,
                 HelixUri::WebhookSubscriptions => HELIX_WEBHOOK_SUBS,
+                HelixUri::Whispers => HELIX_URN_WHISPERS,
             }
         )
     }
@@ -611,15 +698,43 @@ pub fn build_query_params(param_type: HelixParamType, items: &[String]) -> Vec<S
     queries
 }
 
-#[derive(Debug, Clone)]
-pub struct PaginatedHelixRequest {
-    pub cursor: String,
+/// The rate limit bucket as of the most recent Helix response.
+static RATE_LIMIT: Mutex<Option<RateLimit>> = Mutex::new(None);
 
-    /// Only valid if set to either `Before` or `After`.
-    ///
-    /// > This whole `util::helix` module needs to be reworked, so this is
-    /// > a bit of a hacky-feeling implementation until then...
-    pub params: HelixParamType,
+/// Helix's rate limit bucket, from the `ratelimit-*` response headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Unix timestamp (in seconds) that the bucket is refilled at
+    pub reset: i64,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+
+        Some(Self {
+            limit: header("ratelimit-limit")?.parse().ok()?,
+            remaining: header("ratelimit-remaining")?.parse().ok()?,
+            reset: header("ratelimit-reset")?.parse().ok()?,
+        })
+    }
+
+    /// The most recently reported bucket, if any request has reported one.
+    pub fn current() -> Option<Self> {
+        *RATE_LIMIT.lock().unwrap()
+    }
+
+    /// How long to wait for the bucket to refill before spending any more of it, so that at least
+    /// `reserve` points are left for other requests. `None` if there's no need to wait.
+    pub fn wait(&self, reserve: u32, now: i64) -> Option<Duration> {
+        if self.remaining > reserve || self.reset <= now {
+            return None;
+        }
+
+        Some(Duration::from_secs((self.reset - now) as u64))
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -627,6 +742,15 @@ pub struct HelixDataResponse<T> {
     data: Vec<T>,
 }
 
+/// A single page from a cursor-paginated list endpoint; see `Helix::paginate`.
+#[derive(Debug, Clone, Deserialize)]
+struct HelixPage<T> {
+    data: Vec<T>,
+    /// Empty (or missing) on the last page
+    #[serde(default)]
+    pagination: HelixPagination,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct HelixUser {
     pub id: String,
@@ -669,11 +793,6 @@ impl PartialEq<ChannelId> for HelixUser {
     }
 }
 
-#[derive(Debug, Clone, Deserialize)]
-pub struct RawHelixStream {
-    data: Vec<HelixStream>,
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct HelixStream {
     pub title: String,
@@ -686,6 +805,8 @@ pub struct HelixStream {
     pub thumbnail: String,
     #[serde(rename = "game_name")]
     pub game: String,
+    pub viewer_count: i64,
+    pub started_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -745,6 +866,8 @@ fn get_default_color() -> String {
 pub struct AuthHeaders {
     bearer: HeaderMap,
     oauth: HeaderMap,
+    /// The bot's own user token, for requests made on its behalf
+    user: HeaderMap,
 }
 
 impl AuthHeaders {
@@ -755,18 +878,27 @@ impl AuthHeaders {
         let oauth_value = HeaderValue::from_str(&format!("OAuth {}", var!(Var::UserToken).await?))?;
         let bearer_value =
             HeaderValue::from_str(&format!("Bearer {}", var!(Var::AppToken).await?))?;
+        let user_value = HeaderValue::from_str(&format!("Bearer {}", var!(Var::UserToken).await?))?;
 
         let mut bearer = HeaderMap::new();
         bearer.insert(AUTHORIZATION, bearer_value);
-        bearer.insert("Client-Id", client_id);
+        bearer.insert("Client-Id", client_id.clone());
 
         let mut oauth = HeaderMap::new();
         oauth.insert(AUTHORIZATION, oauth_value);
         oauth.insert("Client-Id", browser_id);
 
+        let mut user = HeaderMap::new();
+        user.insert(AUTHORIZATION, user_value);
+        user.insert("Client-Id", client_id);
+
         tracing::debug!("built AUTHORIZATION headers for OAuth + Bearer tokens");
 
-        Ok(Self { bearer, oauth })
+        Ok(Self {
+            bearer,
+            oauth,
+            user,
+        })
     }
 }
 
@@ -794,6 +926,9 @@ pub enum HelixErr {
     #[error("attempted to request user data with an invalid user login")]
     InvalidUsername,
 
+    #[error("helix is currently unreachable")]
+    Unavailable,
+
     #[error("error during helix fetch: {0}")]
     FetchErr(String),
 
@@ -812,6 +947,7 @@ impl HelixErr {
         match self {
             Self::InvalidUsername | Self::EmptyDataField => StatusCode::BAD_REQUEST,
             Self::FetchErrWithBody { .. } => StatusCode::BAD_GATEWAY,
+            Self::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -821,6 +957,7 @@ impl HelixErr {
             Self::InvalidUsername => "invalid username".into(),
             Self::EmptyDataField => "user not found".into(),
             Self::FetchErrWithBody { body } => format!("upstream error: {body}"),
+            Self::Unavailable => "twitch is currently unreachable".into(),
             _ => "twitch API error".into(),
         }
     }
@@ -850,4 +987,39 @@ mod test {
 
         provider.shutdown();
     }
+
+    #[test]
+    fn rate_limit_waits_for_the_reserve() {
+        let mut headers = HeaderMap::new();
+        headers.insert("ratelimit-limit", HeaderValue::from_static("800"));
+        headers.insert("ratelimit-remaining", HeaderValue::from_static("40"));
+        headers.insert("ratelimit-reset", HeaderValue::from_static("1700000060"));
+
+        let rate_limit = RateLimit::from_headers(&headers).unwrap();
+        assert_eq!(rate_limit.remaining, 40);
+
+        assert_eq!(rate_limit.wait(20, 1700000000), None);
+        assert_eq!(
+            rate_limit.wait(50, 1700000000),
+            Some(Duration::from_secs(60))
+        );
+        // the bucket has already been refilled
+        assert_eq!(rate_limit.wait(50, 1700000060), None);
+
+        headers.remove("ratelimit-reset");
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+    #[test]
+    fn the_last_page_has_no_cursor() {
+        let page: HelixPage<u32> =
+            serde_json::from_str(r#"{"data":[1,2],"pagination":{"cursor":"abc"}}"#).unwrap();
+        assert_eq!(page.pagination.cursor.as_deref(), Some("abc"));
+
+        // streams send an empty object on the last page; some endpoints leave it out entirely
+        let page: HelixPage<u32> = serde_json::from_str(r#"{"data":[3],"pagination":{}}"#).unwrap();
+        assert_eq!(page.pagination.cursor, None);
+
+        let page: HelixPage<u32> = serde_json::from_str(r#"{"data":[]}"#).unwrap();
+        assert!(page.data.is_empty() && page.pagination.cursor.is_none());
+    }
 }
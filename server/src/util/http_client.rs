@@ -0,0 +1,168 @@
+//! The shared outbound HTTP client.
+//!
+//! Requests to Twitch (and the other services we call out to) are made with a single
+//! `reqwest::Client`, so connections are pooled between callers rather than being set up for every
+//! request. `send` adds what every Helix request wants on top: retries for transient failures, a
+//! tracing span, and request metrics. Auth headers are still attached by the caller (see
+//! `helix::auth_headers`), as different requests are made with different tokens.
+//!
+//! Timeouts and an optional proxy are set with `HTTP_CLIENT_TIMEOUT_SECS`,
+//! `HTTP_CLIENT_CONNECT_TIMEOUT_SECS` and `HTTP_CLIENT_PROXY`.
+
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use http::{Method, StatusCode};
+use reqwest::{Client, Request, RequestBuilder, Response};
+use tokio::sync::OnceCell;
+use tracing::instrument;
+
+use crate::util::env::Var;
+use crate::var;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// Retries after the first attempt; each waits twice as long as the last, from `BASE_BACKOFF`.
+const MAX_RETRIES: u32 = 2;
+const BASE_BACKOFF: Duration = Duration::from_millis(250);
+
+static CLIENT: LazyLock<OnceCell<Client>> = LazyLock::new(OnceCell::new);
+
+/// Retrieves the shared client, building it on first use.
+pub async fn client() -> &'static Client {
+    CLIENT.get_or_init(build).await
+}
+
+async fn build() -> Client {
+    let timeout = secs_var(Var::HttpClientTimeoutSecs, DEFAULT_TIMEOUT).await;
+    let connect_timeout =
+        secs_var(Var::HttpClientConnectTimeoutSecs, DEFAULT_CONNECT_TIMEOUT).await;
+
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+
+    if let Ok(proxy) = var!(Var::HttpClientProxy).await
+        && !proxy.trim().is_empty()
+    {
+        match reqwest::Proxy::all(proxy.trim()) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => tracing::error!(error = ?e, "invalid http client proxy - ignoring"),
+        }
+    }
+
+    tracing::info!(?timeout, ?connect_timeout, "built shared http client");
+    builder.build().unwrap_or_else(|e| {
+        tracing::error!(error = ?e, "failed to build http client - using defaults");
+        Client::new()
+    })
+}
+
+async fn secs_var(var: Var, default: Duration) -> Duration {
+    match var!(var).await {
+        Ok(val) => match val.trim().parse::<u64>() {
+            Ok(secs) if secs > 0 => Duration::from_secs(secs),
+            _ => default,
+        },
+        Err(_) => default,
+    }
+}
+
+/// Sends a request, retrying failures that are likely to be transient.
+///
+/// Requests that never reached the server (i.e. couldn't connect) are always retried. Timeouts,
+/// server errors and `429 Too Many Requests` are only retried for idempotent methods, as a `POST`
+/// may have taken effect despite the error. `service` labels the request's span and metrics.
+pub async fn send(service: &'static str, request: RequestBuilder) -> reqwest::Result<Response> {
+    let (client, request) = request.build_split();
+    execute(service, &client, request?).await
+}
+
+#[instrument(
+    skip_all,
+    fields(service = service, method = %request.method(), path = request.url().path(), attempts)
+)]
+async fn execute(
+    service: &'static str,
+    client: &Client,
+    request: Request,
+) -> reqwest::Result<Response> {
+    let idempotent = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::PUT | Method::DELETE
+    );
+
+    let mut attempt = 0;
+    loop {
+        let started = Instant::now();
+        // requests with streaming bodies can't be cloned, so only get the one attempt
+        let Some(next) = request.try_clone() else {
+            let result = client.execute(request).await;
+            return observe(service, result, started);
+        };
+
+        let result = observe(service, client.execute(next).await, started);
+        tracing::Span::current().record("attempts", attempt + 1);
+
+        if attempt >= MAX_RETRIES || !is_retryable(&result, idempotent) {
+            return result;
+        }
+
+        let backoff = BASE_BACKOFF * 2u32.pow(attempt);
+        tracing::warn!(attempt, ?backoff, "retrying http request");
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+    }
+}
+
+fn is_retryable(result: &reqwest::Result<Response>, idempotent: bool) -> bool {
+    match result {
+        Err(e) if e.is_connect() => true,
+        Err(e) => idempotent && e.is_timeout(),
+        Ok(res) => {
+            idempotent
+                && (res.status().is_server_error() || res.status() == StatusCode::TOO_MANY_REQUESTS)
+        }
+    }
+}
+
+/// Records an attempt's outcome and duration.
+fn observe(
+    service: &'static str,
+    result: reqwest::Result<Response>,
+    started: Instant,
+) -> reqwest::Result<Response> {
+    let status = match &result {
+        Ok(res) => res.status().as_str().to_string(),
+        Err(e) if e.is_timeout() => String::from("timeout"),
+        Err(_) => String::from("error"),
+    };
+
+    metrics::counter!(
+        "http_client_requests_total",
+        "service" => service,
+        "status" => status
+    )
+    .increment(1);
+    metrics::histogram!("http_client_request_duration_seconds", "service" => service)
+        .record(started.elapsed().as_secs_f64());
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn unreachable_hosts_are_retried() {
+        let started = Instant::now();
+        // nothing listens on the discard port, so every attempt fails to connect
+        let request = Client::new().post("http://127.0.0.1:9/");
+
+        assert!(send("test", request).await.is_err());
+        assert!(started.elapsed() >= BASE_BACKOFF * 3);
+    }
+}
@@ -0,0 +1,73 @@
+//! Periodically records which tracked channels are live, along with their viewer count and game,
+//! so that API responses can be annotated without a Helix request per lookup.
+//!
+//! A `StreamOnline` event is published for each channel that went live since the last refresh.
+
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::PgResult;
+use crate::db::models::stream::{LiveStream, StreamOnline};
+use crate::db::prelude::{ChannelId, ChannelRepository, Repository, StreamStatusRepository};
+use crate::irc::events::{LiveEvent, events};
+use crate::util::helix::{Helix, HelixStream};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(120);
+
+impl From<HelixStream> for LiveStream {
+    fn from(stream: HelixStream) -> Self {
+        Self {
+            channel_id: ChannelId(stream.user_id),
+            login: stream.login,
+            viewer_count: stream.viewer_count,
+            game: stream.game,
+            title: stream.title,
+            started_at: stream.started_at.naive_utc(),
+        }
+    }
+}
+
+/// Fetches the live state of every tracked channel and stores it.
+///
+/// Returns the number of live channels.
+#[instrument(skip(pool))]
+pub async fn refresh_stream_status(pool: &'static Pool<Postgres>) -> PgResult<usize> {
+    let ids = ChannelRepository::new(pool).get_all_channel_ids().await?;
+    let live: Vec<LiveStream> = if ids.is_empty() {
+        Vec::new()
+    } else {
+        Helix::get_streams(&ids)
+            .await?
+            .into_iter()
+            .map(LiveStream::from)
+            .collect()
+    };
+
+    let tracked: Vec<ChannelId> = ids.into_iter().map(ChannelId).collect();
+    let went_live = StreamStatusRepository::new(pool)
+        .refresh(&tracked, &live)
+        .await?;
+
+    for stream in live.iter().filter(|s| went_live.contains(&s.channel_id)) {
+        tracing::info!(channel = stream.login, "channel went live");
+        events().publish(LiveEvent::StreamOnline(StreamOnline::from(stream)));
+    }
+
+    Ok(live.len())
+}
+
+pub fn spawn_stream_status_refresh(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            interval.tick().await;
+            match refresh_stream_status(pool).await {
+                Ok(live) => tracing::debug!(live, "refreshed stream status"),
+                Err(e) => tracing::error!(error = ?e, "failed to refresh stream status"),
+            }
+        }
+    })
+}
@@ -0,0 +1,111 @@
+//! Caches the milestones that apply to each channel, so that `increment_score` can check them for
+//! every matched message without a query each time.
+//!
+//! The admin milestone handlers drop the cached copies when milestones are created or deleted.
+//! Other instances don't hear about those edits, so cached entries also expire after
+//! `MILESTONE_TTL`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{Duration, Instant};
+
+use sqlx::{Pool, Postgres};
+use tracing::instrument;
+
+use crate::db::models::milestone::Milestone;
+use crate::db::prelude::{ChannelId, MilestoneRepository};
+
+const MILESTONE_TTL: Duration = Duration::from_secs(60);
+
+/// A channel's milestones, and when they were fetched
+type Entry = (Instant, Arc<Vec<Milestone>>);
+
+static MILESTONES: LazyLock<MilestoneCache> = LazyLock::new(MilestoneCache::default);
+
+/// Retrieves a reference to the global `MilestoneCache`.
+pub fn milestones() -> &'static MilestoneCache {
+    &MILESTONES
+}
+
+#[derive(Debug, Default)]
+pub struct MilestoneCache {
+    channels: RwLock<HashMap<ChannelId, Entry>>,
+    /// Bumped on every invalidation, so that a fetch that raced with one isn't cached
+    generation: AtomicU64,
+}
+
+impl MilestoneCache {
+    /// Retrieves the milestones that apply to a channel (including global milestones), from the
+    /// cache if possible.
+    #[instrument(skip(self, pool))]
+    pub async fn get(
+        &self,
+        pool: &'static Pool<Postgres>,
+        channel_id: &ChannelId,
+    ) -> sqlx::Result<Arc<Vec<Milestone>>> {
+        if let Some((fetched_at, milestones)) = self.read().get(channel_id)
+            && fetched_at.elapsed() < MILESTONE_TTL
+        {
+            return Ok(Arc::clone(milestones));
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let milestones = Arc::new(
+            MilestoneRepository::new(pool)
+                .get_for_channel(channel_id)
+                .await?,
+        );
+
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::Acquire) == generation {
+            channels.insert(
+                channel_id.clone(),
+                (Instant::now(), Arc::clone(&milestones)),
+            );
+        }
+
+        Ok(milestones)
+    }
+
+    /// Drops the cached milestones affected by an edit to a milestone of `channel_id`. Global
+    /// milestones (`None`) apply to every channel, so they drop the whole cache.
+    pub fn invalidate(&self, channel_id: Option<&ChannelId>) {
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        match channel_id {
+            Some(channel_id) => {
+                channels.remove(channel_id);
+            }
+            None => channels.clear(),
+        }
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<ChannelId, Entry>> {
+        self.channels.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn global_edits_drop_every_channel() {
+        let cache = MilestoneCache::default();
+        let (a, b) = (ChannelId(String::from("1")), ChannelId(String::from("2")));
+        {
+            let mut channels = cache.channels.write().unwrap();
+            channels.insert(a.clone(), (Instant::now(), Arc::default()));
+            channels.insert(b.clone(), (Instant::now(), Arc::default()));
+        }
+
+        cache.invalidate(Some(&a));
+        assert!(cache.read().get(&a).is_none());
+        assert!(cache.read().get(&b).is_some());
+
+        cache.invalidate(None);
+        assert!(cache.read().is_empty());
+        assert_eq!(cache.generation.load(Ordering::Acquire), 2);
+    }
+}
@@ -1,54 +1,58 @@
+pub mod alias;
+pub mod archive;
+pub mod availability;
+pub mod avatar;
+pub mod breaker;
 pub mod channel;
+pub mod deletion;
+pub mod digest;
 pub mod env;
+pub mod export;
+pub mod flags;
 pub mod helix;
+pub mod http_client;
+pub mod live;
+pub mod milestones;
+pub mod overlay;
+pub mod period;
+#[cfg(feature = "profiling")]
+pub mod profiling;
+pub mod reconcile;
+pub mod refresh;
+pub mod settings;
+pub mod shard;
+pub mod shutdown;
+pub mod storage;
 pub mod telemetry;
+pub mod template;
 pub mod totp;
-
-use std::arch::asm;
+pub mod trace_buffer;
 
 use chrono::NaiveDateTime;
 use tracing::instrument;
 
 /// Performs `&str` comparisons in constant time in an attempt to close any and all side-channels
-/// that might leak information about our key
+/// that might leak information about our key. Only the lengths are compared in variable time, so
+/// this is for comparing secrets of a known length (signatures, hashes and codes).
 #[instrument(skip(a, b))]
 pub fn constant_time_cmp(a: &str, b: &str) -> bool {
+    constant_time_eq(a.as_bytes(), b.as_bytes())
+}
+
+/// `constant_time_cmp` for bytes. Every byte is compared, whether or not an earlier one differed:
+/// the differences are accumulated with bitwise ops rather than branched on, and passed through
+/// `black_box` so that the compiler can't turn the loop back into an early-exit comparison.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
         return false;
     }
 
-    let a = a.as_bytes();
-    let b = b.as_bytes();
-    let mut res = 0u8;
-
-    // convoluted attempt to avoid optimizations that might do some
-    // bullshit to this iterator
-    //
-    // TODO:
-    //  using pointers is probably not amongst god's most efficient methods
-    //  for equality testing but i am not so smart and i don't think perfect
-    //  efficiency is of utmost importance for this function at present.
-    //
-    //  ... plus we should check out the decompilation for this function anyway
-    for i in 0..a.len() {
-        let left = std::hint::black_box(&a[i]) as *const u8;
-        let right = std::hint::black_box(&b[i]) as *const u8;
-
-        unsafe {
-            asm!(
-                "mov {tmp}, [{a_ptr}]",
-                "xor {tmp}, [{b_ptr}]",
-                "or {res}, {tmp}",
-                a_ptr = in(reg) left,
-                b_ptr = in(reg) right,
-                tmp = out(reg_byte) _,
-                res = inout(reg_byte) res,
-                options(nostack)
-            );
-        }
-    }
+    let diff = a
+        .iter()
+        .zip(b)
+        .fold(0u8, |diff, (a, b)| diff | std::hint::black_box(a ^ b));
 
-    res == 0
+    std::hint::black_box(diff) == 0
 }
 
 /// Returns true if a `&str` meets the criteria for a Twitch user id, otherwise returns false
@@ -72,6 +76,8 @@ pub fn create_timestamp(offset_days: i64) -> NaiveDateTime {
 
 #[cfg(test)]
 mod test {
+    use tinyrand::{Rand, RandRange, Seeded, StdRand};
+
     use super::*;
 
     #[test]
@@ -91,4 +97,28 @@ mod test {
         assert!(!constant_time_cmp(expects, short));
         assert!(!constant_time_cmp(expects, long));
     }
+
+    #[test]
+    fn const_time_eq_agrees_with_eq() {
+        let mut rand = StdRand::seed(0x5eed);
+
+        for _ in 0..1_000 {
+            let len = rand.next_range(0..64usize);
+            let a: Vec<u8> = (0..len).map(|_| rand.next_u32() as u8).collect();
+            assert!(constant_time_eq(&a, &a.clone()));
+
+            // any single differing bit is caught, wherever it is
+            if !a.is_empty() {
+                let mut b = a.clone();
+                let i = rand.next_range(0..len);
+                b[i] ^= 1 << rand.next_range(0..8u32);
+                assert!(!constant_time_eq(&a, &b));
+            }
+
+            let b: Vec<u8> = (0..rand.next_range(0..64usize))
+                .map(|_| rand.next_u32() as u8)
+                .collect();
+            assert_eq!(constant_time_eq(&a, &b), a == b);
+        }
+    }
 }
@@ -0,0 +1,222 @@
+//! Overlay snapshots, shared between every client showing a channel's overlay.
+//!
+//! Overlays are browser sources that stay open for as long as a stream runs, so a channel can have
+//! a great many of them polling at once. Rather than each of them querying the database, a
+//! channel's overlay is reread at most once per `SNAPSHOT_TTL` - by whichever request finds it
+//! stale first, while the others wait for that read - and every client is handed the snapshot.
+//!
+//! Clients waiting on changes (long-polling or streaming) watch the channel's snapshot instead.
+//! `spawn_overlay_updates` rereads a watched overlay at most once per `PUSH_INTERVAL` while scores
+//! are being counted in the channel by this instance, and every `RESYNC_INTERVAL` regardless to
+//! pick up scores counted by other instances and corrections. Channels that nobody has asked for
+//! in `IDLE_TTL` are forgotten.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use sqlx::{Pool, Postgres};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+use crate::db::models::overlay::Overlay;
+use crate::db::prelude::StatsRepository;
+use crate::irc::events::increments;
+
+/// How long a snapshot is served for before it's reread. Responses can be cached for as long.
+pub const SNAPSHOT_TTL: Duration = Duration::from_secs(2);
+const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+const RESYNC_INTERVAL: Duration = Duration::from_secs(15);
+const IDLE_TTL: Duration = Duration::from_secs(300);
+/// The number of chatters listed on an overlay
+const TOP_CHATTERS: i64 = 3;
+
+static OVERLAYS: LazyLock<OverlayCache> = LazyLock::new(OverlayCache::default);
+
+/// Retrieves a reference to the global `OverlayCache`.
+pub fn overlays() -> &'static OverlayCache {
+    &OVERLAYS
+}
+
+/// A channel's overlay as last read; `None` until it's first read.
+pub type Snapshot = Option<Arc<Overlay>>;
+
+#[derive(Debug)]
+struct Entry {
+    tx: watch::Sender<Snapshot>,
+    read_at: Option<Instant>,
+    requested_at: Instant,
+    /// Held while the overlay is being reread
+    reading: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Entry {
+    fn new() -> Self {
+        Self {
+            tx: watch::Sender::new(None),
+            read_at: None,
+            requested_at: Instant::now(),
+            reading: Arc::default(),
+        }
+    }
+
+    fn fresh(&self, max_age: Duration) -> Snapshot {
+        self.read_at
+            .filter(|at| at.elapsed() < max_age)
+            .and_then(|_| self.tx.borrow().clone())
+    }
+}
+
+/// The overlay of each channel asked for, keyed by the channel's login.
+#[derive(Debug, Default)]
+pub struct OverlayCache {
+    channels: Mutex<HashMap<String, Entry>>,
+}
+
+impl OverlayCache {
+    /// The channel's overlay, reread if the snapshot is older than `SNAPSHOT_TTL`. Returns `None`
+    /// for channels that aren't known.
+    pub async fn get(
+        &self,
+        pool: &'static Pool<Postgres>,
+        login: &str,
+    ) -> sqlx::Result<Option<Arc<Overlay>>> {
+        if let Some(entry) = self.lock().get_mut(login) {
+            entry.requested_at = Instant::now();
+        }
+
+        self.read(pool, login, SNAPSHOT_TTL).await
+    }
+
+    /// Watches the channel's overlay, which is marked as changed for the first time straight away.
+    /// Returns `None` for channels that aren't known.
+    pub async fn watch(
+        &self,
+        pool: &'static Pool<Postgres>,
+        login: &str,
+    ) -> sqlx::Result<Option<watch::Receiver<Snapshot>>> {
+        if self.get(pool, login).await?.is_none() {
+            return Ok(None);
+        }
+
+        Ok(self.lock().get(login).map(|entry| {
+            let mut rx = entry.tx.subscribe();
+            rx.mark_changed();
+            rx
+        }))
+    }
+
+    async fn read(
+        &self,
+        pool: &'static Pool<Postgres>,
+        login: &str,
+        max_age: Duration,
+    ) -> sqlx::Result<Option<Arc<Overlay>>> {
+        let reading = {
+            let mut channels = self.lock();
+            let entry = channels.entry(login.to_string()).or_insert_with(Entry::new);
+            if let Some(overlay) = entry.fresh(max_age) {
+                return Ok(Some(overlay));
+            }
+            Arc::clone(&entry.reading)
+        };
+
+        // whoever gets here first rereads the overlay, and anyone waiting behind them uses that
+        let started = Instant::now();
+        let _reading = reading.lock().await;
+        if let Some(overlay) = self
+            .lock()
+            .get(login)
+            .and_then(|entry| entry.fresh(started.elapsed()))
+        {
+            return Ok(Some(overlay));
+        }
+
+        let overlay = StatsRepository::new(pool)
+            .get_overlay(login, TOP_CHATTERS)
+            .await?;
+
+        let mut channels = self.lock();
+        let Some(overlay) = overlay else {
+            // not kept, so that requests for made-up channels can't grow the cache
+            channels.remove(login);
+            return Ok(None);
+        };
+
+        let overlay = Arc::new(overlay);
+        if let Some(entry) = channels.get_mut(login) {
+            entry.read_at = Some(Instant::now());
+            entry.tx.send_if_modified(|current| {
+                let modified = current.as_deref() != Some(&overlay);
+                *current = Some(Arc::clone(&overlay));
+                modified
+            });
+        }
+
+        Ok(Some(overlay))
+    }
+
+    /// Forgets idle channels, returning the logins of those being watched.
+    fn sweep(&self) -> Vec<String> {
+        let mut channels = self.lock();
+        channels.retain(|_, entry| {
+            entry.tx.receiver_count() > 0 || entry.requested_at.elapsed() < IDLE_TTL
+        });
+
+        channels
+            .iter()
+            .filter(|(_, entry)| entry.tx.receiver_count() > 0)
+            .map(|(login, _)| login.clone())
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Spawns a task pushing changes to watched overlays as scores are counted.
+pub fn spawn_overlay_updates(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    let mut rx = increments().subscribe();
+    tokio::spawn(async move {
+        let mut push = tokio::time::interval(PUSH_INTERVAL);
+        push.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        let mut counted = HashSet::new();
+        let mut resync_at = Instant::now() + RESYNC_INTERVAL;
+        loop {
+            tokio::select! {
+                score = rx.recv() => match score {
+                    Ok(score) => {
+                        counted.insert(score.channel_login.clone());
+                    }
+                    // we can't know which channels were missed, so reread every watched overlay
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::debug!(skipped, "overlay updates fell behind, resyncing");
+                        resync_at = Instant::now();
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = push.tick() => {
+                    let resync = Instant::now() >= resync_at;
+                    if resync {
+                        resync_at = Instant::now() + RESYNC_INTERVAL;
+                    }
+
+                    for login in overlays().sweep() {
+                        if !resync && !counted.contains(&login) {
+                            continue;
+                        }
+
+                        if let Err(e) = overlays().read(pool, &login, Duration::ZERO).await {
+                            tracing::warn!(error = ?e, channel = login, "failed to update overlay");
+                        }
+                    }
+                    counted.clear();
+                }
+            }
+        }
+    })
+}
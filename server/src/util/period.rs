@@ -0,0 +1,64 @@
+//! Periodically captures the day/week/month leaderboards into `leaderboard_snapshot`, which
+//! `?period=` leaderboard queries are served from.
+//!
+//! Captures run every `LEADERBOARD_SNAPSHOT_INTERVAL_SECS` (`0` disables them, in which case
+//! period leaderboards are rolled up from `score_event` on each request). Once a past period's
+//! snapshot is final, a `SnapshotFinalised` event is published.
+
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::leaderboard::{Period, SnapshotFinalised};
+use crate::db::prelude::PeriodRepository;
+use crate::irc::events::{LiveEvent, events};
+use crate::util::env::Var;
+use crate::var;
+
+/// Captures every snapshotted period, returning the number of rows written.
+#[instrument(skip(pool))]
+pub async fn capture_all(pool: &'static Pool<Postgres>) -> sqlx::Result<u64> {
+    let repo = PeriodRepository::new(pool);
+
+    let mut written = 0;
+    for period in Period::SNAPSHOTTED {
+        let capture = repo.capture(period).await?;
+        if let Some(period_start) = capture.finalised {
+            tracing::info!(?period, %period_start, "finalised leaderboard snapshot");
+            events().publish(LiveEvent::SnapshotFinalised(SnapshotFinalised {
+                period,
+                period_start,
+            }));
+        }
+
+        written += capture.written;
+    }
+
+    Ok(written)
+}
+
+pub async fn spawn_leaderboard_snapshots(pool: &'static Pool<Postgres>) -> Option<JoinHandle<()>> {
+    let secs = var!(Var::LeaderboardSnapshotIntervalSecs)
+        .await
+        .ok()
+        .and_then(|secs| secs.trim().parse::<u64>().ok())
+        .unwrap_or_default();
+
+    if secs == 0 {
+        tracing::info!("leaderboard snapshots disabled");
+        return None;
+    }
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(secs));
+        loop {
+            interval.tick().await;
+            match capture_all(pool).await {
+                Ok(rows) => tracing::debug!(rows, "captured leaderboard snapshots"),
+                Err(e) => tracing::error!(error = ?e, "failed to capture leaderboard snapshots"),
+            }
+        }
+    }))
+}
@@ -0,0 +1,141 @@
+//! On-demand diagnostics for a running server (enabled via the `profiling` feature).
+//!
+//! Artifacts are written to `$TMPDIR/piss-fan-profiles` and their paths returned to the caller,
+//! so they can be copied off the host and inspected with the usual tooling (e.g. `go tool pprof`
+//! for `.pb` profiles, or any browser for flamegraph `.svg`s).
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use pprof::protos::Message;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::instrument;
+
+const PROFILE_DIR: &str = "piss-fan-profiles";
+const MAX_PROFILE_SECS: u64 = 60;
+const MAX_FREQUENCY: i32 = 1000;
+
+/// Only one CPU profile can be captured at a time, as the profiler installs a process-wide signal
+/// handler
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Serialize)]
+pub struct ProfileArtifacts {
+    pub flamegraph: PathBuf,
+    pub pprof: PathBuf,
+    pub duration_secs: u64,
+    pub frequency: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuntimeSnapshot {
+    pub path: PathBuf,
+    pub workers: usize,
+    pub alive_tasks: usize,
+    pub global_queue_depth: usize,
+    pub worker_busy_ms: Vec<u128>,
+}
+
+fn output_dir() -> ProfilingResult<PathBuf> {
+    let dir = std::env::temp_dir().join(PROFILE_DIR);
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir)
+}
+
+fn timestamp() -> String {
+    chrono::Utc::now().format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Samples CPU usage across the whole process for the given duration.
+///
+/// The capture blocks a thread from the blocking pool for its full duration rather than a runtime
+/// worker, as the profiler guard can't be held across an await point.
+#[instrument]
+pub async fn capture_cpu_profile(secs: u64, frequency: i32) -> ProfilingResult<ProfileArtifacts> {
+    let duration_secs = secs.clamp(1, MAX_PROFILE_SECS);
+    let frequency = frequency.clamp(1, MAX_FREQUENCY);
+
+    if PROFILING.swap(true, Ordering::AcqRel) {
+        return Err(ProfilingError::InProgress);
+    }
+
+    tracing::warn!(duration_secs, frequency, "capturing cpu profile");
+    let result = tokio::task::spawn_blocking(move || -> ProfilingResult<ProfileArtifacts> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+
+        std::thread::sleep(Duration::from_secs(duration_secs));
+        let report = guard.report().build()?;
+
+        let dir = output_dir()?;
+        let name = format!("cpu-{}", timestamp());
+        let flamegraph = dir.join(format!("{name}.svg"));
+        let pprof = dir.join(format!("{name}.pb"));
+
+        report.flamegraph(std::fs::File::create(&flamegraph)?)?;
+
+        std::fs::write(&pprof, report.pprof()?.encode_to_vec())?;
+
+        Ok(ProfileArtifacts {
+            flamegraph,
+            pprof,
+            duration_secs,
+            frequency,
+        })
+    })
+    .await;
+
+    PROFILING.store(false, Ordering::Release);
+    let artifacts = result??;
+
+    tracing::info!(?artifacts, "cpu profile captured");
+    Ok(artifacts)
+}
+
+/// Writes a snapshot of the tokio runtime's task and worker metrics.
+#[instrument]
+pub async fn capture_runtime_snapshot() -> ProfilingResult<RuntimeSnapshot> {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    let workers = metrics.num_workers();
+
+    let path = output_dir()?.join(format!("runtime-{}.json", timestamp()));
+    let snapshot = RuntimeSnapshot {
+        path,
+        workers,
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: metrics.global_queue_depth(),
+        worker_busy_ms: (0..workers)
+            .map(|worker| metrics.worker_total_busy_duration(worker).as_millis())
+            .collect(),
+    };
+
+    std::fs::write(&snapshot.path, serde_json::to_vec_pretty(&snapshot)?)?;
+
+    tracing::info!(?snapshot, "runtime snapshot captured");
+    Ok(snapshot)
+}
+
+pub type ProfilingResult<T> = core::result::Result<T, ProfilingError>;
+
+#[derive(Debug, Error)]
+pub enum ProfilingError {
+    #[error("a cpu profile is already being captured")]
+    InProgress,
+
+    #[error(transparent)]
+    Pprof(#[from] pprof::Error),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    SerdeJson(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+}
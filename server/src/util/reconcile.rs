@@ -0,0 +1,239 @@
+//! Periodically reconciles the channels this instance is tracking with the channels stored in
+//! Postgres.
+//!
+//! Channels are added to the IRC connection and `AppState` as they're added through the API, but
+//! nothing else puts them back in step if the two drift apart - e.g. when a channel is added on
+//! another instance, or an addition fails partway through. Each cycle computes the difference
+//! between the stored channels and the tracked ones, then joins, parts and corrects the channel
+//! lists to match. Every correction is counted in the `channel_reconcile_drift` gauge (labelled
+//! by `kind`) and raised on the `alert` tracing target.
+//!
+//! EventSub subscriptions are reconciled separately, by `api::webhook::reconcile`.
+
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::instrument;
+
+use crate::api::server::AppState;
+use crate::api::webhook::revocation::RevocationReason;
+use crate::db::prelude::SubscriptionRepository;
+use crate::db::prelude::{ChannelRepository, ChatterId, ChatterRepository, Repository};
+use crate::irc::membership;
+use crate::util::channel::ChannelResult;
+use crate::util::shard::sharding;
+use crate::util::shutdown::shutdown;
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+/// What has to change for the tracked channels to match the desired ones.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ChannelDiff {
+    add: Vec<String>,
+    remove: Vec<String>,
+}
+
+impl ChannelDiff {
+    fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// Compares two sets of channels, sorting the result so each cycle applies it in the same order.
+fn diff<'a>(
+    desired: impl IntoIterator<Item = &'a String>,
+    actual: impl IntoIterator<Item = &'a String>,
+) -> ChannelDiff {
+    let desired: BTreeSet<&String> = desired.into_iter().collect();
+    let actual: BTreeSet<&String> = actual.into_iter().collect();
+
+    ChannelDiff {
+        add: desired.difference(&actual).map(|&ch| ch.clone()).collect(),
+        remove: actual.difference(&desired).map(|&ch| ch.clone()).collect(),
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ChannelReconcileReport {
+    /// Channels this instance should have joined
+    pub desired: usize,
+    /// Channels added to the IRC connection
+    pub joined: usize,
+    /// Channels removed from the IRC connection
+    pub parted: usize,
+    /// Tracked channels the connection hadn't joined, which were queued again
+    pub requeued: usize,
+    /// Ids and logins added to or removed from `AppState`'s channel lists
+    pub state: usize,
+    /// `{action}:{channel}` for each channel that couldn't be joined or parted
+    pub errors: Vec<String>,
+}
+
+impl ChannelReconcileReport {
+    pub fn drift(&self) -> usize {
+        self.joined + self.parted + self.requeued + self.state
+    }
+}
+
+/// Reconciles the IRC connection and `AppState`'s channel lists with the stored channels.
+///
+/// The connection is read before the database, so a channel added in the meantime is stored by
+/// the time it's compared, rather than being parted as soon as it's joined.
+#[instrument(skip(state))]
+pub async fn reconcile(state: &AppState) -> ChannelResult<ChannelReconcileReport> {
+    let irc = &state.irc_connection;
+    let tracked = irc.tracked_channels().await.map_err(Box::new)?;
+    let joined = irc.joined_channels().await.map_err(Box::new)?;
+
+    let ids = ChannelRepository::new(state.database_pool)
+        .get_all_channel_ids()
+        .await?;
+    let chatters = ChatterRepository::new(state.database_pool)
+        .get_many_by_id(&ids.iter().cloned().map(ChatterId).collect::<Vec<_>>())
+        .await?;
+
+    // broadcasters who revoked authorization were parted on purpose, but are still stored
+    let revoked: HashSet<String> = SubscriptionRepository::new(state.database_pool)
+        .get_all()
+        .await?
+        .into_iter()
+        .filter(|sub| RevocationReason::from_status(&sub.status).should_part())
+        .map(|sub| sub.channel_id.0)
+        .collect();
+
+    let sharding = sharding();
+    let desired: HashSet<String> = chatters
+        .iter()
+        .filter(|chatter| sharding.owns(&chatter.id.0) && !revoked.contains(&chatter.id.0))
+        .map(|chatter| membership::normalize(&chatter.login))
+        .collect();
+
+    let mut report = ChannelReconcileReport {
+        desired: desired.len(),
+        ..Default::default()
+    };
+
+    // channels can be joined without being tracked if a removal raced a join
+    let changes = diff(&desired, tracked.iter().chain(joined.iter()));
+    if !changes.is_empty() {
+        tracing::warn!(?changes, "irc channels don't match stored channels");
+    }
+
+    for channel in changes.add {
+        match irc.insert_channel(channel.clone()).await {
+            Ok(_) => report.joined += 1,
+            Err(e) => {
+                tracing::error!(error = ?e, channel, "failed to join channel");
+                report.errors.push(format!("join:{channel}"));
+            }
+        }
+    }
+
+    for channel in changes.remove {
+        match irc.remove_channel(channel.clone()).await {
+            Ok(_) => report.parted += 1,
+            Err(e) => {
+                tracing::error!(error = ?e, channel, "failed to part channel");
+                report.errors.push(format!("part:{channel}"));
+            }
+        }
+    }
+
+    // the channel manager retries joins on its own, but backs off while they keep failing
+    let unjoined = diff(&desired, &joined).add;
+    if !unjoined.is_empty() {
+        tracing::info!(?unjoined, "tracked channels haven't been joined");
+        report.requeued = irc.resync().await.map_err(Box::new)?;
+    }
+
+    let logins: Vec<String> = chatters.into_iter().map(|chatter| chatter.login).collect();
+    report.state += replace_if_changed(&state.channel_ids, ids).await;
+    report.state += replace_if_changed(&state.channels, logins).await;
+
+    Ok(report)
+}
+
+/// Replaces one of `AppState`'s channel lists, returning how many entries were wrong.
+async fn replace_if_changed(
+    list: &tokio::sync::RwLock<Vec<String>>,
+    desired: Vec<String>,
+) -> usize {
+    let changes = diff(&desired, list.read().await.iter());
+    if changes.is_empty() {
+        return 0;
+    }
+
+    tracing::warn!(?changes, "channel list doesn't match stored channels");
+    *list.write().await = desired;
+    changes.add.len() + changes.remove.len()
+}
+
+fn report_metrics(report: &ChannelReconcileReport) {
+    for (kind, count) in [
+        ("joined", report.joined),
+        ("parted", report.parted),
+        ("requeued", report.requeued),
+        ("state", report.state),
+    ] {
+        metrics::gauge!("channel_reconcile_drift", "kind" => kind).set(count as f64);
+    }
+
+    metrics::gauge!("channel_reconcile_desired").set(report.desired as f64);
+    metrics::counter!("channel_reconcile_corrections_total").increment(report.drift() as u64);
+}
+
+pub fn spawn_channel_reconciliation(state: Arc<AppState>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        // the channels were just loaded from the database, so there's nothing to reconcile yet
+        let mut interval =
+            tokio::time::interval_at(Instant::now() + RECONCILE_INTERVAL, RECONCILE_INTERVAL);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = shutdown().draining() => break,
+            }
+
+            match reconcile(&state).await {
+                Ok(report) => {
+                    report_metrics(&report);
+                    if report.drift() > 0 || !report.errors.is_empty() {
+                        tracing::warn!(
+                            target: "alert",
+                            ?report,
+                            "corrected channel drift"
+                        );
+                    } else {
+                        tracing::debug!(desired = report.desired, "channels match stored channels");
+                    }
+                }
+                Err(e) => tracing::error!(error = ?e, "failed to reconcile channels"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn channel_diffs_are_sorted_and_ignore_duplicates() {
+        let desired = ["#b", "#a", "#c"].map(String::from);
+        let actual = ["#c", "#d", "#c"].map(String::from);
+
+        assert_eq!(
+            diff(&desired, &actual),
+            ChannelDiff {
+                add: vec![String::from("#a"), String::from("#b")],
+                remove: vec![String::from("#d")],
+            }
+        );
+        assert!(diff(&actual, &actual).is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+//! Periodically re-fetches chatters' names, colors and avatars from Helix, which otherwise only
+//! change when the chatter is next seen in chat.
+//!
+//! Every `CHATTER_REFRESH_INTERVAL_SECS` (`0` disables refreshes), up to
+//! `CHATTER_REFRESH_BATCHES` batches of chatters whose data is older than
+//! `CHATTER_REFRESH_STALE_DAYS` are refreshed. Chatters on a visible leaderboard are refreshed
+//! first. Helix doesn't return deleted or suspended accounts; they're marked as refreshed anyway
+//! (via `chatter.refreshed_at`) so that they don't hold up the rest of the queue.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::prelude::{Chatter, ChatterRepository, Repository};
+use crate::util::alias;
+use crate::util::env::Var;
+use crate::util::helix::{Helix, HelixErr, RateLimit};
+use crate::var;
+
+/// Chatters ranked this high on the global leaderboard or any channel's leaderboard are refreshed
+/// before anyone else.
+const VISIBLE_RANK: i64 = 100;
+
+/// Helix accepts up to 100 ids per request.
+const BATCH_SIZE: i64 = 100;
+
+/// Rate limit points left for chat hydration and the API while a refresh is running.
+const RATE_LIMIT_RESERVE: u32 = 200;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RefreshReport {
+    pub checked: usize,
+    pub updated: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RefreshError {
+    #[error(transparent)]
+    Helix(#[from] HelixErr),
+
+    #[error(transparent)]
+    Sqlx(#[from] sqlx::Error),
+}
+
+async fn parse_var(var: Var, default: i64) -> i64 {
+    var!(var)
+        .await
+        .ok()
+        .and_then(|val| val.trim().parse::<i64>().ok())
+        .unwrap_or(default)
+}
+
+/// Refreshes up to `batches` batches of stale chatters.
+#[instrument(skip(pool))]
+pub async fn refresh_stale(
+    pool: &'static Pool<Postgres>,
+    stale_days: i64,
+    batches: i64,
+) -> Result<RefreshReport, RefreshError> {
+    let repo = ChatterRepository::new(pool);
+    let stale_before = Utc::now().naive_utc() - chrono::Duration::days(stale_days.max(0));
+
+    let mut report = RefreshReport::default();
+    for _ in 0..batches {
+        let batch = repo
+            .get_stale(stale_before, VISIBLE_RANK, BATCH_SIZE)
+            .await?;
+        if batch.is_empty() {
+            break;
+        }
+
+        if let Some(wait) =
+            RateLimit::current().and_then(|r| r.wait(RATE_LIMIT_RESERVE, Utc::now().timestamp()))
+        {
+            tracing::debug!(?wait, "waiting for the helix rate limit to refill");
+            tokio::time::sleep(wait).await;
+        }
+
+        let mut ids: Vec<String> = batch.iter().map(|id| id.0.clone()).collect();
+        let chatters: Vec<Chatter> = Helix::fetch_users_by_id(&mut ids)
+            .await?
+            .into_iter()
+            .map(Chatter::from)
+            .collect();
+
+        if !chatters.is_empty() {
+            repo.insert_many(&chatters).await?;
+            alias::spawn_merge_for(pool, chatters.iter().map(|ch| ch.id.clone()).collect());
+        }
+
+        repo.mark_refreshed(&batch).await?;
+        report.checked += batch.len();
+        report.updated += chatters.len();
+    }
+
+    Ok(report)
+}
+
+/// Spawns the background refresh job, unless `CHATTER_REFRESH_INTERVAL_SECS` is `0`.
+pub async fn spawn_chatter_refresh(pool: &'static Pool<Postgres>) -> Option<JoinHandle<()>> {
+    let secs = parse_var(Var::ChatterRefreshIntervalSecs, 0).await;
+    if secs <= 0 {
+        tracing::info!("chatter refreshes disabled");
+        return None;
+    }
+
+    let stale_days = parse_var(Var::ChatterRefreshStaleDays, 7).await;
+    let batches = parse_var(Var::ChatterRefreshBatches, 10).await;
+
+    Some(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(secs as u64));
+        loop {
+            interval.tick().await;
+            match refresh_stale(pool, stale_days, batches).await {
+                Ok(report) if report.checked == 0 => (),
+                Ok(report) => tracing::info!(
+                    checked = report.checked,
+                    updated = report.updated,
+                    "refreshed stale chatters"
+                ),
+                Err(e) => tracing::error!(error = ?e, "failed to refresh stale chatters"),
+            }
+        }
+    }))
+}
@@ -0,0 +1,146 @@
+//! Caches each channel's `ChannelSettings`, so that the IRC handlers can check them for every
+//! message without a query each time.
+//!
+//! Postgres announces every change to a channel's settings (or reply mode) on the
+//! `channel_settings` notification channel, and the listener drops the changed channel's cached
+//! copy, so that every instance picks up new settings on the next message. If the listener's
+//! connection drops, notifications can be missed, so the whole cache is dropped once it reconnects.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, RwLock};
+use std::time::Duration;
+
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::db::models::settings::ChannelSettings;
+use crate::db::prelude::{ChannelId, SettingsRepository};
+
+const NOTIFY_CHANNEL: &str = "channel_settings";
+const LISTEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+static SETTINGS: LazyLock<SettingsCache> = LazyLock::new(SettingsCache::default);
+
+/// Retrieves a reference to the global `SettingsCache`.
+pub fn settings() -> &'static SettingsCache {
+    &SETTINGS
+}
+
+#[derive(Debug, Default)]
+pub struct SettingsCache {
+    channels: RwLock<HashMap<ChannelId, ChannelSettings>>,
+    /// Bumped on every invalidation, so that a fetch that raced with one isn't cached
+    generation: AtomicU64,
+}
+
+impl SettingsCache {
+    /// Retrieves a channel's settings, from the cache if possible. Untracked channels get the
+    /// defaults.
+    #[instrument(skip(self, pool))]
+    pub async fn get(
+        &self,
+        pool: &'static Pool<Postgres>,
+        channel_id: &ChannelId,
+    ) -> sqlx::Result<ChannelSettings> {
+        if let Some(settings) = self.read().get(channel_id) {
+            return Ok(settings.clone());
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let settings = SettingsRepository::new(pool)
+            .get(channel_id)
+            .await?
+            .unwrap_or_default();
+
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::Acquire) == generation {
+            channels.insert(channel_id.clone(), settings.clone());
+        }
+
+        Ok(settings)
+    }
+
+    /// Drops a channel's cached settings.
+    pub fn invalidate(&self, channel_id: &ChannelId) {
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        channels.remove(channel_id);
+    }
+
+    /// Drops every channel's cached settings.
+    pub fn clear(&self) {
+        let mut channels = self.channels.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        channels.clear();
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<ChannelId, ChannelSettings>> {
+        self.channels.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Listens for settings changes for as long as the server runs, dropping changed channels from
+/// the cache.
+pub fn spawn_settings_listener(pool: &'static Pool<Postgres>) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(pool).await {
+                tracing::error!(error = ?e, "channel settings listener failure");
+            }
+
+            // anything that changed while we weren't listening has to be refetched
+            settings().clear();
+            tokio::time::sleep(LISTEN_RETRY_DELAY).await;
+        }
+    })
+}
+
+async fn listen(pool: &'static Pool<Postgres>) -> sqlx::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(NOTIFY_CHANNEL).await?;
+    tracing::info!(
+        channel = NOTIFY_CHANNEL,
+        "listening for channel settings changes"
+    );
+
+    loop {
+        match listener.try_recv().await? {
+            Some(notification) => {
+                let channel_id = ChannelId(notification.payload().to_string());
+                tracing::debug!(channel = %channel_id, "channel settings changed");
+                settings().invalidate(&channel_id);
+            }
+            None => {
+                tracing::warn!("channel settings listener reconnected");
+                settings().clear();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn invalidation_drops_only_the_changed_channel() {
+        let cache = SettingsCache::default();
+        let (a, b) = (ChannelId(String::from("1")), ChannelId(String::from("2")));
+        {
+            let mut channels = cache.channels.write().unwrap();
+            channels.insert(a.clone(), ChannelSettings::default());
+            channels.insert(b.clone(), ChannelSettings::default());
+        }
+
+        cache.invalidate(&a);
+        assert!(cache.read().get(&a).is_none());
+        assert!(cache.read().get(&b).is_some());
+        assert_eq!(cache.generation.load(Ordering::Acquire), 1);
+
+        cache.clear();
+        assert!(cache.read().is_empty());
+    }
+}
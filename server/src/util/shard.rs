@@ -0,0 +1,206 @@
+//! Static partitioning of the tracked channels between instances, for when one instance can't keep
+//! up with every channel.
+//!
+//! With `SHARD_COUNT=n`, each instance is given a `SHARD_INDEX` in `0..n` and only tracks the
+//! channels hashed to its shard: it only joins their IRC channels, and only creates (or deletes)
+//! their EventSub subscriptions, which are sent to its own `CALLBACK_URL`. Channels are assigned by
+//! jump consistent hashing of their IDs, so changing the shard count only moves the channels that
+//! have to move. Instances given the same shard share its channels with IRC leases (see
+//! `irc::coordination`).
+//!
+//! The assignment is read once on startup, so changing either variable needs a restart.
+
+use std::sync::OnceLock;
+
+use ring::digest::{SHA256, digest};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::util::env::{EnvErr, Var};
+use crate::var;
+
+static SHARDING: OnceLock<Sharding> = OnceLock::new();
+
+pub type ShardResult<T> = core::result::Result<T, ShardError>;
+
+#[derive(Debug, Error)]
+pub enum ShardError {
+    #[error(transparent)]
+    EnvErr(#[from] EnvErr),
+
+    #[error("invalid SHARD_COUNT '{0}'")]
+    InvalidCount(String),
+
+    #[error("invalid SHARD_INDEX '{index}' for {count} shards")]
+    InvalidIndex { index: String, count: u32 },
+}
+
+/// This instance's shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Sharding {
+    pub count: u32,
+    pub index: u32,
+}
+
+impl Default for Sharding {
+    fn default() -> Self {
+        Self { count: 1, index: 0 }
+    }
+}
+
+/// Reads `SHARD_COUNT` and `SHARD_INDEX`; called on startup, before the channel list is loaded.
+///
+/// An invalid assignment is an error rather than falling back to every channel, which would
+/// double-count whichever shard's channels the instance was meant to leave alone.
+pub async fn init() -> ShardResult<Sharding> {
+    let count = var!(Var::ShardCount).await?.trim();
+    let count = match count.parse::<u32>() {
+        Ok(count) if count > 0 => count,
+        _ => return Err(ShardError::InvalidCount(count.to_string())),
+    };
+
+    let index = var!(Var::ShardIndex).await?.trim();
+    let index = match index.parse::<u32>() {
+        Ok(index) if index < count => index,
+        _ => {
+            return Err(ShardError::InvalidIndex {
+                index: index.to_string(),
+                count,
+            });
+        }
+    };
+
+    let sharding = *SHARDING.get_or_init(|| Sharding { count, index });
+    if sharding.is_sharded() {
+        tracing::info!(
+            shard = sharding.index,
+            count = sharding.count,
+            "sharding channels"
+        );
+    }
+
+    Ok(sharding)
+}
+
+/// This instance's shard, or a single shard holding every channel before `init`.
+pub fn sharding() -> Sharding {
+    SHARDING.get().copied().unwrap_or_default()
+}
+
+impl Sharding {
+    pub fn is_sharded(&self) -> bool {
+        self.count > 1
+    }
+
+    pub fn shard_of(&self, channel_id: &str) -> u32 {
+        let hash = digest(&SHA256, channel_id.as_bytes());
+        let mut key = [0; 8];
+        key.copy_from_slice(&hash.as_ref()[..8]);
+
+        jump_hash(u64::from_be_bytes(key), self.count)
+    }
+
+    /// Whether this instance tracks a channel.
+    pub fn owns(&self, channel_id: &str) -> bool {
+        self.shard_of(channel_id) == self.index
+    }
+
+    /// Groups channels by shard, reporting how evenly they're spread.
+    pub fn report(&self, channel_ids: Vec<String>) -> ShardReport {
+        let mut shards: Vec<ShardAssignment> = (0..self.count)
+            .map(|index| ShardAssignment {
+                index,
+                channels: Vec::new(),
+            })
+            .collect();
+
+        for channel_id in channel_ids {
+            shards[self.shard_of(&channel_id) as usize]
+                .channels
+                .push(channel_id);
+        }
+
+        for shard in shards.iter_mut() {
+            shard.channels.sort();
+        }
+
+        let total: usize = shards.iter().map(|shard| shard.channels.len()).sum();
+        let largest = shards.iter().map(|shard| shard.channels.len()).max();
+        let imbalance = match (largest, total) {
+            (Some(largest), 1..) => largest as f64 * self.count as f64 / total as f64,
+            _ => 1.0,
+        };
+
+        ShardReport {
+            count: self.count,
+            index: self.index,
+            imbalance,
+            shards,
+        }
+    }
+}
+
+/// Every tracked channel's shard.
+#[derive(Debug, Serialize)]
+pub struct ShardReport {
+    pub count: u32,
+    /// This instance's shard
+    pub index: u32,
+    /// The largest shard's size over the mean size; `1.0` is perfectly balanced
+    pub imbalance: f64,
+    pub shards: Vec<ShardAssignment>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShardAssignment {
+    pub index: u32,
+    /// Channel IDs, sorted
+    pub channels: Vec<String>,
+}
+
+/// Lamping and Veach's jump consistent hash: growing from `n` to `n + 1` buckets only moves keys
+/// into the new bucket.
+fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    let (mut b, mut j) = (-1i64, 0i64);
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+
+    b as u32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adding_a_shard_only_moves_channels_into_it() {
+        let ids: Vec<String> = (0..1000).map(|id| (100_000 + id).to_string()).collect();
+        let three = Sharding { count: 3, index: 0 };
+        let four = Sharding { count: 4, index: 0 };
+
+        for id in &ids {
+            let after = four.shard_of(id);
+            assert!(after == three.shard_of(id) || after == 3);
+        }
+
+        let report = four.report(ids);
+        assert_eq!(report.shards.len(), 4);
+        assert_eq!(
+            report
+                .shards
+                .iter()
+                .map(|s| s.channels.len())
+                .sum::<usize>(),
+            1000
+        );
+        assert!(report.imbalance >= 1.0 && report.imbalance < 1.2);
+
+        let single = Sharding::default();
+        assert!(!single.is_sharded());
+        assert!(single.owns("103033809"));
+        assert_eq!(single.report(Vec::new()).imbalance, 1.0);
+    }
+}
@@ -0,0 +1,131 @@
+//! Coordinates a graceful shutdown of the server.
+//!
+//! On SIGINT or SIGTERM the server starts draining: it stops accepting connections, requests that
+//! still arrive on open connections are answered with a 503, and requests already in flight are
+//! given up to `SHUTDOWN_DRAIN_TIMEOUT_SECS` to complete before the remaining connections are
+//! dropped. Background jobs that make changes on Twitch's side (e.g. EventSub reconciliation)
+//! check `is_draining` so that they don't start anything they may not get to finish.
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use crate::util::env::Var;
+use crate::var;
+
+static SHUTDOWN: LazyLock<Shutdown> = LazyLock::new(Shutdown::default);
+
+/// Retrieves a reference to the global `Shutdown`.
+pub fn shutdown() -> &'static Shutdown {
+    &SHUTDOWN
+}
+
+#[derive(Debug)]
+pub struct Shutdown {
+    draining: watch::Sender<bool>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            draining: watch::Sender::new(false),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Starts draining. Returns false if a drain was already in progress.
+    pub fn begin_drain(&self) -> bool {
+        self.draining
+            .send_if_modified(|draining| !std::mem::replace(draining, true))
+    }
+
+    /// Resolves once a drain has started.
+    pub async fn draining(&self) {
+        let mut rx = self.draining.subscribe();
+        // the sender is never dropped, as it's owned by `self`
+        _ = rx.wait_for(|draining| *draining).await;
+    }
+
+    /// Resolves `timeout` after a drain has started.
+    pub async fn drain_deadline(&self, timeout: Duration) {
+        self.draining().await;
+        tokio::time::sleep(timeout).await;
+    }
+}
+
+/// Reads `SHUTDOWN_DRAIN_TIMEOUT_SECS`.
+pub async fn drain_timeout() -> Duration {
+    let secs = var!(Var::ShutdownDrainTimeoutSecs)
+        .await
+        .ok()
+        .and_then(|val| val.trim().parse::<u64>().ok())
+        .unwrap_or(30);
+
+    Duration::from_secs(secs)
+}
+
+/// Waits for SIGINT or SIGTERM, then starts draining.
+pub async fn listen_for_signals() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = ?e, "failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => _ = signal.recv().await,
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("received SIGINT"),
+        _ = terminate => tracing::info!("received SIGTERM"),
+    }
+
+    if shutdown().begin_drain() {
+        tracing::info!("draining the server");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn drains_are_started_once() {
+        let shutdown = Arc::new(Shutdown::default());
+        assert!(!shutdown.is_draining());
+
+        let waiting = tokio::spawn({
+            let shutdown = Arc::clone(&shutdown);
+            async move {
+                shutdown.draining().await;
+                let start = tokio::time::Instant::now();
+                shutdown.drain_deadline(Duration::from_secs(5)).await;
+                start.elapsed()
+            }
+        });
+
+        tokio::task::yield_now().await;
+        assert!(shutdown.begin_drain());
+        assert!(!shutdown.begin_drain());
+        assert!(shutdown.is_draining());
+
+        // `draining` resolves straight away once a drain has started, so only the timeout is left
+        assert_eq!(waiting.await.unwrap(), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,504 @@
+//! Where files written by exports, snapshots and score event archives are kept.
+//!
+//! Files are handed to an `ObjectStore` rather than written directly, so that it doesn't matter
+//! whether they end up on local disk or in an S3-compatible bucket, per `STORAGE_BACKEND`. Objects
+//! are addressed by `/`-separated keys, e.g. `score_event/2026-01-05.csv.gz`.
+//!
+//! Large files (exports and snapshots) are streamed through an `ObjectWriter`, which only holds a
+//! part of the file in memory at a time - in S3, anything larger than a part is sent as a
+//! multipart upload.
+
+use std::fmt::Debug;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::buffered::BufWriter;
+use object_store::path::Path as ObjectPath;
+use thiserror::Error;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::OnceCell;
+
+use crate::util::env::Var;
+use crate::var;
+
+/// Bytes buffered before a streamed object is sent to S3 as a multipart upload, and the size of
+/// each part after that
+const PART_SIZE: usize = 8 * 1024 * 1024;
+
+static STORAGE: OnceCell<Arc<dyn ObjectStore>> = OnceCell::const_new();
+
+pub type StorageResult<T> = core::result::Result<T, StorageError>;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("invalid object key '{0}'")]
+    InvalidKey(String),
+
+    #[error("invalid storage config: {0}")]
+    Config(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Remote(#[from] object_store::Error),
+}
+
+#[async_trait]
+pub trait ObjectStore: Send + Sync + Debug {
+    /// Writes an object, replacing anything already stored under `key`.
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> StorageResult<()>;
+
+    /// Reads an object, or `None` if nothing is stored under `key`.
+    async fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>>;
+
+    /// Opens a writer that streams an object to `key`; see `ObjectWriter`.
+    async fn writer(&self, key: &str) -> StorageResult<ObjectWriter>;
+
+    /// Streams an object into a local file. Returns false if nothing is stored under `key`.
+    async fn download(&self, key: &str, path: &Path) -> StorageResult<bool>;
+}
+
+/// The store configured with `STORAGE_BACKEND`: `local` (the default) keeps objects under
+/// `STORAGE_DIR`, while `s3` keeps them in `STORAGE_S3_BUCKET`.
+pub async fn storage() -> StorageResult<Arc<dyn ObjectStore>> {
+    STORAGE
+        .get_or_try_init(|| async {
+            let backend = var!(Var::StorageBackend)
+                .await
+                .map(|backend| backend.trim().to_lowercase())
+                .unwrap_or_default();
+
+            let store: Arc<dyn ObjectStore> = match backend.as_str() {
+                "" | "local" => {
+                    let dir = var!(Var::StorageDir)
+                        .await
+                        .map_err(|e| StorageError::Config(e.to_string()))?;
+                    Arc::new(LocalStore::new(dir.trim()))
+                }
+                "s3" => Arc::new(S3Store::from_env().await?),
+                backend => {
+                    return Err(StorageError::Config(format!(
+                        "unknown storage backend '{backend}'"
+                    )));
+                }
+            };
+
+            tracing::debug!(?store, "opened storage");
+            Ok(store)
+        })
+        .await
+        .cloned()
+}
+
+/// A file read or written by a subcommand (e.g. `export --output <PATH>`).
+#[derive(Debug)]
+pub enum FileLocation {
+    /// A path on local disk, used as it is
+    Local(PathBuf),
+    /// A key in the configured bucket
+    Remote {
+        store: Arc<dyn ObjectStore>,
+        key: String,
+    },
+}
+
+impl FileLocation {
+    /// Subcommands' paths are local unless `STORAGE_BACKEND=s3`, in which case they're keys in
+    /// the bucket - `STORAGE_DIR` only applies to objects written by the server itself.
+    pub async fn resolve(path: &Path) -> StorageResult<Self> {
+        let remote = var!(Var::StorageBackend)
+            .await
+            .is_ok_and(|backend| backend.trim().eq_ignore_ascii_case("s3"));
+        if !remote {
+            return Ok(Self::Local(path.to_path_buf()));
+        }
+
+        let key = path
+            .to_str()
+            .map(|key| key.trim_start_matches('/').to_string())
+            .ok_or_else(|| StorageError::InvalidKey(path.display().to_string()))?;
+
+        Ok(Self::Remote {
+            store: storage().await?,
+            key,
+        })
+    }
+
+    /// Opens a writer that streams the file to its location.
+    pub async fn writer(&self) -> StorageResult<ObjectWriter> {
+        match self {
+            Self::Local(path) => {
+                let dir = path
+                    .parent()
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or(Path::new("."));
+                let file = path
+                    .file_name()
+                    .and_then(|file| file.to_str())
+                    .ok_or_else(|| StorageError::InvalidKey(path.display().to_string()))?;
+
+                LocalStore::new(dir).writer(file).await
+            }
+            Self::Remote { store, key } => store.writer(key).await,
+        }
+    }
+}
+
+impl std::fmt::Display for FileLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local(path) => write!(f, "{}", path.display()),
+            Self::Remote { key, .. } => write!(f, "s3:{key}"),
+        }
+    }
+}
+
+/// Streams an object to its store. The object only replaces whatever was stored under its key
+/// once the writer is completed; a writer that's aborted (or dropped) leaves the key as it was.
+pub struct ObjectWriter {
+    inner: Writer,
+}
+
+enum Writer {
+    /// Written alongside the object and renamed into place, so a reader never sees half an object
+    Local {
+        file: tokio::io::BufWriter<tokio::fs::File>,
+        partial: PathBuf,
+        path: PathBuf,
+        completed: bool,
+    },
+    /// Buffered until it's larger than a part, then sent as a multipart upload
+    S3(BufWriter),
+}
+
+impl ObjectWriter {
+    /// Finishes writing the object, making it visible under its key.
+    pub async fn complete(mut self) -> StorageResult<()> {
+        match &mut self.inner {
+            Writer::Local {
+                file,
+                partial,
+                path,
+                completed,
+            } => {
+                file.flush().await?;
+                file.get_ref().sync_all().await?;
+                tokio::fs::rename(&partial, &path).await?;
+                *completed = true;
+            }
+            Writer::S3(writer) => writer.shutdown().await?,
+        }
+
+        Ok(())
+    }
+
+    /// Discards what's been written, including any parts already uploaded.
+    pub async fn abort(mut self) -> StorageResult<()> {
+        match &mut self.inner {
+            // the partial file is removed on drop
+            Writer::Local { .. } => Ok(()),
+            Writer::S3(writer) => Ok(writer.abort().await?),
+        }
+    }
+}
+
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        if let Writer::Local {
+            partial,
+            completed: false,
+            ..
+        } = &self.inner
+        {
+            _ = std::fs::remove_file(partial);
+        }
+    }
+}
+
+impl Debug for ObjectWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.inner {
+            Writer::Local { path, .. } => f.debug_tuple("ObjectWriter").field(path).finish(),
+            Writer::S3(_) => f.debug_tuple("ObjectWriter").field(&"s3").finish(),
+        }
+    }
+}
+
+impl AsyncWrite for ObjectWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match &mut self.get_mut().inner {
+            Writer::Local { file, .. } => Pin::new(file).poll_write(cx, buf),
+            Writer::S3(writer) => Pin::new(writer).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match &mut self.get_mut().inner {
+            Writer::Local { file, .. } => Pin::new(file).poll_flush(cx),
+            Writer::S3(writer) => Pin::new(writer).poll_flush(cx),
+        }
+    }
+
+    /// Only flushes; objects are finished with `complete`.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Keys can't be absolute or step outside of the store.
+fn validate_key(key: &str) -> StorageResult<()> {
+    let valid = !key.is_empty()
+        && !key.contains('\\')
+        && key
+            .split('/')
+            .all(|segment| !segment.is_empty() && segment != "." && segment != "..");
+    if !valid {
+        return Err(StorageError::InvalidKey(key.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Stores objects as files under a directory, with each key's segments as subdirectories.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> StorageResult<PathBuf> {
+        validate_key(key)?;
+        Ok(self.root.join(key))
+    }
+
+    /// Creates the object's directory, returning its path and the path it's written to first.
+    async fn prepare(&self, key: &str) -> StorageResult<(PathBuf, PathBuf)> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let mut partial = path.clone().into_os_string();
+        partial.push(".partial");
+
+        Ok((path, PathBuf::from(partial)))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalStore {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> StorageResult<()> {
+        let (path, partial) = self.prepare(key).await?;
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn writer(&self, key: &str) -> StorageResult<ObjectWriter> {
+        let (path, partial) = self.prepare(key).await?;
+        let file = tokio::fs::File::create(&partial).await?;
+
+        Ok(ObjectWriter {
+            inner: Writer::Local {
+                file: tokio::io::BufWriter::new(file),
+                partial,
+                path,
+                completed: false,
+            },
+        })
+    }
+
+    async fn download(&self, key: &str, path: &Path) -> StorageResult<bool> {
+        match tokio::fs::copy(self.path(key)?, path).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Stores objects in an S3 bucket, or a bucket on any service with an S3-compatible API.
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    bucket: String,
+    inner: Arc<dyn object_store::ObjectStore>,
+}
+
+impl S3Store {
+    /// Connects to `STORAGE_S3_BUCKET`. Credentials not set with `STORAGE_S3_ACCESS_KEY_ID` and
+    /// `STORAGE_S3_SECRET_ACCESS_KEY` are picked up as usual for AWS (e.g. `AWS_PROFILE`, or the
+    /// instance's role).
+    pub async fn from_env() -> StorageResult<Self> {
+        let config = |var: Var| async move {
+            var!(var)
+                .await
+                .map(|value| value.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        let bucket = config(Var::StorageS3Bucket).await;
+        if bucket.is_empty() {
+            return Err(StorageError::Config(
+                "STORAGE_S3_BUCKET is required for S3 storage".into(),
+            ));
+        }
+
+        let mut builder = AmazonS3Builder::from_env().with_bucket_name(&bucket);
+        let region = config(Var::StorageS3Region).await;
+        if !region.is_empty() {
+            builder = builder.with_region(region);
+        }
+
+        let endpoint = config(Var::StorageS3Endpoint).await;
+        if !endpoint.is_empty() {
+            builder = builder
+                .with_allow_http(endpoint.starts_with("http://"))
+                .with_endpoint(endpoint);
+        }
+
+        let access_key_id = config(Var::StorageS3AccessKeyId).await;
+        let secret_access_key = config(Var::StorageS3SecretAccessKey).await;
+        if !access_key_id.is_empty() && !secret_access_key.is_empty() {
+            builder = builder
+                .with_access_key_id(access_key_id)
+                .with_secret_access_key(secret_access_key);
+        }
+
+        Ok(Self {
+            bucket,
+            inner: Arc::new(builder.build()?),
+        })
+    }
+
+    fn path(&self, key: &str) -> StorageResult<ObjectPath> {
+        validate_key(key)?;
+        ObjectPath::parse(key).map_err(|_| StorageError::InvalidKey(key.to_string()))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3Store {
+    async fn put(&self, key: &str, bytes: Vec<u8>) -> StorageResult<()> {
+        self.inner.put(&self.path(key)?, bytes.into()).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> StorageResult<Option<Vec<u8>>> {
+        match self.inner.get(&self.path(key)?).await {
+            Ok(object) => Ok(Some(object.bytes().await?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn writer(&self, key: &str) -> StorageResult<ObjectWriter> {
+        let writer = BufWriter::with_capacity(self.inner.clone(), self.path(key)?, PART_SIZE);
+        Ok(ObjectWriter {
+            inner: Writer::S3(writer),
+        })
+    }
+
+    async fn download(&self, key: &str, path: &Path) -> StorageResult<bool> {
+        let mut chunks = match self.inner.get(&self.path(key)?).await {
+            Ok(object) => object.into_stream(),
+            Err(object_store::Error::NotFound { .. }) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut file = tokio::io::BufWriter::new(tokio::fs::File::create(path).await?);
+        while let Some(chunk) = chunks.next().await {
+            file.write_all(&chunk?).await?;
+        }
+        file.flush().await?;
+
+        tracing::debug!(bucket = self.bucket, key, ?path, "downloaded object");
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pea-fan-storage-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn local_store_round_trips_objects() {
+        let root = temp_root("objects");
+        let store = LocalStore::new(&root);
+
+        assert_eq!(store.get("a/b.txt").await.unwrap(), None);
+        store.put("a/b.txt", b"one".to_vec()).await.unwrap();
+        store.put("a/b.txt", b"two".to_vec()).await.unwrap();
+        assert_eq!(store.get("a/b.txt").await.unwrap(), Some(b"two".to_vec()));
+
+        for key in ["", "/etc/passwd", "a/../../b", "a//b", "./a", "a\\b"] {
+            assert!(matches!(
+                store.put(key, Vec::new()).await,
+                Err(StorageError::InvalidKey(_))
+            ));
+        }
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn local_writers_only_replace_objects_once_completed() {
+        let root = temp_root("writers");
+        let store = LocalStore::new(&root);
+        store.put("a.csv", b"old".to_vec()).await.unwrap();
+
+        let mut writer = store.writer("a.csv").await.unwrap();
+        writer.write_all(b"new").await.unwrap();
+        writer.abort().await.unwrap();
+        assert_eq!(store.get("a.csv").await.unwrap(), Some(b"old".to_vec()));
+
+        let mut writer = store.writer("a.csv").await.unwrap();
+        writer.write_all(b"new").await.unwrap();
+        assert_eq!(store.get("a.csv").await.unwrap(), Some(b"old".to_vec()));
+        writer.complete().await.unwrap();
+        assert_eq!(store.get("a.csv").await.unwrap(), Some(b"new".to_vec()));
+
+        let copy = root.join("copy.csv");
+        assert!(store.download("a.csv", &copy).await.unwrap());
+        assert!(!store.download("b.csv", &copy).await.unwrap());
+        assert_eq!(tokio::fs::read(&copy).await.unwrap(), b"new");
+
+        // nothing's left behind by either writer
+        let mut entries = tokio::fs::read_dir(&root).await.unwrap();
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            files.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        files.sort();
+        assert_eq!(files, ["a.csv", "copy.csv"]);
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+}
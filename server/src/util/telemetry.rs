@@ -1,3 +1,4 @@
+use std::sync::OnceLock;
 use std::time::Duration;
 
 use opentelemetry::{KeyValue, global};
@@ -7,13 +8,88 @@ use opentelemetry_sdk::Resource;
 use opentelemetry_sdk::logs::SdkLoggerProvider;
 use opentelemetry_sdk::metrics::SdkMeterProvider;
 use opentelemetry_sdk::trace::{self, RandomIdGenerator, Sampler, SdkTracerProvider};
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
+use serde::Serialize;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::util::env::Var;
+use crate::util::trace_buffer::TraceBufferLayer;
 use crate::var;
 
 pub type Result<T> = core::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Used when `LOG_FILTER` is unset or invalid.
+pub const DEFAULT_LOG_FILTER: &str =
+    "piss_fan_server=debug,tower_http=info,axum=info,sqlx=info,info";
+
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// Swaps the filter applied to every tracing layer while the server is running.
+#[derive(Debug)]
+struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// The filter the server started with, which is restored when no filter is given
+    initial: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LogFilterError {
+    #[error("invalid log filter: {0}")]
+    Invalid(#[from] ParseError),
+
+    #[error("tracing hasn't been initialized")]
+    Uninitialized,
+
+    #[error(transparent)]
+    Reload(#[from] reload::Error),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogFilterChange {
+    pub previous: String,
+    pub current: String,
+}
+
+/// The tracing filter currently in effect, if tracing has been initialized.
+pub fn log_filter() -> Option<String> {
+    LOG_FILTER
+        .get()?
+        .handle
+        .with_current(|filter| filter.to_string())
+        .ok()
+}
+
+/// Replaces the tracing filter until it's next changed or the server restarts; `None` restores
+/// the filter the server started with.
+pub fn set_log_filter(
+    directives: Option<&str>,
+) -> core::result::Result<LogFilterChange, LogFilterError> {
+    let log_filter = LOG_FILTER.get().ok_or(LogFilterError::Uninitialized)?;
+    let filter = EnvFilter::try_new(directives.unwrap_or(log_filter.initial.as_str()))?;
+    let current = filter.to_string();
+
+    let mut previous = String::new();
+    log_filter
+        .handle
+        .modify(|active| previous = std::mem::replace(active, filter).to_string())?;
+
+    Ok(LogFilterChange { previous, current })
+}
+
+/// Parses `LOG_FILTER`, falling back to `DEFAULT_LOG_FILTER`. This runs before tracing is set
+/// up, so an invalid filter is reported on stderr.
+fn initial_filter(directives: &str) -> EnvFilter {
+    if directives.trim().is_empty() {
+        return EnvFilter::new(DEFAULT_LOG_FILTER);
+    }
+
+    EnvFilter::try_new(directives).unwrap_or_else(|e| {
+        eprintln!("invalid LOG_FILTER ({e}) - using the default filter");
+        EnvFilter::new(DEFAULT_LOG_FILTER)
+    })
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Telemetry {
@@ -21,6 +97,7 @@ pub struct Telemetry {
     pub base_resource: Resource,
     pub collector_url: &'static str,
 
+    log_filter: &'static str,
     logger_provider: SdkLoggerProvider,
     tracer_provider: SdkTracerProvider,
     meter_provider: SdkMeterProvider,
@@ -32,6 +109,7 @@ impl Telemetry {
         let tracer_name = var!(Var::ApiTracerName).await?;
         let service_name = var!(Var::ApiServiceName).await?;
         let service_version = env!("CARGO_PKG_VERSION");
+        let log_filter = var!(Var::LogFilter).await.unwrap_or_default();
 
         let base_resource = base_attrs(service_name, service_version);
 
@@ -43,6 +121,7 @@ impl Telemetry {
             base_resource,
             tracer_name,
             collector_url,
+            log_filter,
             logger_provider,
             tracer_provider,
             meter_provider,
@@ -57,19 +136,24 @@ impl Telemetry {
         let log_layer = OpenTelemetryTracingBridge::new(&self.logger_provider);
         let meter_layer = tracing_opentelemetry::MetricsLayer::new(self.meter_provider.clone());
 
+        // the filter is the innermost layer so its reload handle only depends on the registry
+        let filter = initial_filter(self.log_filter);
+        let initial = filter.to_string();
+        let (filter, handle) = reload::Layer::new(filter);
+        _ = LOG_FILTER.set(LogFilter { handle, initial });
+
         tracing_subscriber::registry()
+            .with(filter)
             .with(trace_layer)
             .with(log_layer)
             .with(meter_layer)
-            .with(EnvFilter::new(
-                "piss_fan_server=debug,tower_http=info,axum=info,sqlx=info,info",
-            ))
             .with(
                 tracing_subscriber::fmt::layer()
                     .with_target(true)
                     .with_thread_ids(true)
                     .with_line_number(true),
             )
+            .with(TraceBufferLayer)
             .init();
 
         self
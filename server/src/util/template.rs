@@ -0,0 +1,43 @@
+//! Renders the `{placeholder}` templates used by chat replies, announcements and Discord posts.
+
+/// Replaces each `{name}` in `template` with its value, in a single pass so that values from
+/// twitch (e.g. stream titles) are never themselves rendered. Unknown placeholders are left as-is.
+pub fn render(template: &str, placeholders: &[(&str, String)]) -> String {
+    let mut content = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        content.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        let value = rest.find('}').and_then(|close| {
+            placeholders
+                .iter()
+                .find(|(name, _)| *name == &rest[1..close])
+                .map(|(_, value)| (close, value))
+        });
+
+        match value {
+            Some((close, value)) => {
+                content.push_str(value);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                content.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    content.push_str(rest);
+
+    content
+}
+
+/// Truncates `content` to at most `max_chars` characters.
+pub fn truncate_chars(mut content: String, max_chars: usize) -> String {
+    if let Some((idx, _)) = content.char_indices().nth(max_chars) {
+        content.truncate(idx);
+    }
+
+    content
+}
@@ -0,0 +1,307 @@
+//! In-memory ring buffer of recent tracing events.
+//!
+//! The `TraceBufferLayer` keeps the most recent `CAPACITY` events at DEBUG or above, so that very
+//! recent behavior can be inspected via the admin API without needing access to the collector.
+//! Fields recorded on parent spans are attached to each event, so e.g. an event logged inside an
+//! instrumented handler can still be matched by the channel that handler was called for.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{LazyLock, Mutex};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Record};
+use tracing::{Event, Id, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+const CAPACITY: usize = 4096;
+const DEFAULT_LIMIT: usize = 200;
+
+static TRACE_BUFFER: LazyLock<TraceBuffer> = LazyLock::new(|| TraceBuffer::new(CAPACITY));
+
+/// Retrieves a reference to the global `TraceBuffer`.
+pub fn trace_buffer() -> &'static TraceBuffer {
+    &TRACE_BUFFER
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceEvent {
+    pub timestamp: NaiveDateTime,
+    pub level: String,
+    pub target: String,
+    pub message: Option<String>,
+    /// The event's own fields, followed by those of its parent spans (innermost first)
+    pub fields: BTreeMap<String, String>,
+    pub spans: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct TraceBuffer {
+    capacity: usize,
+    events: Mutex<VecDeque<TraceEvent>>,
+}
+
+impl TraceBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            events: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    fn push(&self, event: TraceEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+
+        events.push_back(event);
+    }
+
+    /// Returns the most recent events matching the filter, newest first.
+    pub fn query(&self, filter: &TraceFilter, limit: Option<usize>) -> Vec<TraceEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|event| filter.matches(event))
+            .take(limit.unwrap_or(DEFAULT_LIMIT))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TraceQuery {
+    pub filter: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Parsed from a comma-separated list of directives:
+///
+/// - `module=<path>` (or a bare `<path>`) matches events whose target starts with the path, with
+///   or without the crate prefix (e.g. `irc::worker`)
+/// - `channel=<login or id>` matches events with a `channel*` field containing the value
+/// - `level=<level>` matches events at or above the level
+#[derive(Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    modules: Vec<String>,
+    channels: Vec<String>,
+    level: Option<Level>,
+}
+
+impl TraceFilter {
+    pub fn parse(filter: &str) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        for directive in filter.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                None => parsed.modules.push(directive.to_string()),
+                Some(("module", module)) => parsed.modules.push(module.to_string()),
+                Some(("channel", channel)) => {
+                    let channel = channel.trim_start_matches('#').to_lowercase();
+                    parsed.channels.push(channel);
+                }
+                Some(("level", level)) => {
+                    let level = level
+                        .parse::<Level>()
+                        .map_err(|_| format!("invalid level '{level}'"))?;
+                    parsed.level = Some(level);
+                }
+                _ => return Err(format!("invalid filter directive '{directive}'")),
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn matches(&self, event: &TraceEvent) -> bool {
+        let module_match = self.modules.is_empty()
+            || self.modules.iter().any(|module| {
+                event.target.starts_with(module.as_str())
+                    || event
+                        .target
+                        .split_once("::")
+                        .is_some_and(|(_, path)| path.starts_with(module.as_str()))
+            });
+
+        let channel_match = self.channels.is_empty()
+            || event
+                .fields
+                .iter()
+                .filter(|(key, _)| key.starts_with("channel"))
+                .any(|(_, value)| {
+                    let value = value.to_lowercase();
+                    self.channels
+                        .iter()
+                        .any(|channel| value.contains(channel.as_str()))
+                });
+
+        // `Level`'s ordering treats more verbose levels as greater
+        let level_match = self
+            .level
+            .is_none_or(|level| event.level.parse::<Level>().is_ok_and(|l| l <= level));
+
+        module_match && channel_match && level_match
+    }
+}
+
+/// Span fields, stored in the span's extensions when it is created.
+struct SpanFields(BTreeMap<String, String>);
+
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: BTreeMap<String, String>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.fields
+                .insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        } else {
+            self.fields
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+}
+
+/// Tracing layer that records events into the global `TraceBuffer`.
+pub struct TraceBufferLayer;
+
+impl<S> Layer<S> for TraceBufferLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            fields.extend(visitor.fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > Level::DEBUG {
+            return;
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let mut fields = visitor.fields;
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope {
+                spans.push(span.name().to_string());
+                if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                    for (key, value) in span_fields {
+                        fields
+                            .entry(key.to_owned())
+                            .or_insert_with(|| value.to_owned());
+                    }
+                }
+            }
+        }
+
+        trace_buffer().push(TraceEvent {
+            timestamp: chrono::Utc::now().naive_utc(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: visitor.message,
+            fields,
+            spans,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(level: &str, target: &str, fields: &[(&str, &str)]) -> TraceEvent {
+        TraceEvent {
+            timestamp: NaiveDateTime::default(),
+            level: level.to_string(),
+            target: target.to_string(),
+            message: None,
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            spans: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn filter_matches_module_channel_and_level() {
+        let filter = TraceFilter::parse("irc::worker, channel=#Pea, level=info").unwrap();
+        let matching = event(
+            "INFO",
+            "piss_fan_server::irc::worker",
+            &[("channel", "1234.#pea")],
+        );
+
+        assert!(filter.matches(&matching));
+        assert!(!filter.matches(&event(
+            "DEBUG",
+            "piss_fan_server::irc::worker",
+            &[("channel", "1234.#pea")]
+        )));
+        assert!(!filter.matches(&event(
+            "INFO",
+            "piss_fan_server::api::server",
+            &[("channel", "1234.#pea")]
+        )));
+        assert!(!filter.matches(&event(
+            "INFO",
+            "piss_fan_server::irc::worker",
+            &[("chatter", "pea")]
+        )));
+
+        assert!(TraceFilter::parse("level=loud").is_err());
+        assert!(TraceFilter::parse("colour=red").is_err());
+    }
+
+    #[test]
+    fn buffer_drops_oldest_events() {
+        let buffer = TraceBuffer::new(2);
+        for target in ["a", "b", "c"] {
+            buffer.push(event("INFO", target, &[]));
+        }
+
+        let events = buffer.query(&TraceFilter::default(), None);
+        let targets: Vec<_> = events.iter().map(|e| e.target.as_str()).collect();
+        assert_eq!(targets, ["c", "b"]);
+    }
+}